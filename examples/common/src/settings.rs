@@ -75,6 +75,7 @@ impl Conditioner {
             incoming_latency: Duration::from_millis(self.latency_ms as u64),
             incoming_jitter: Duration::from_millis(self.jitter_ms as u64),
             incoming_loss: self.packet_loss,
+            ..default()
         }
     }
 }
@@ -148,6 +149,7 @@ pub(crate) fn build_server_netcode_config(
             incoming_latency: Duration::from_millis(c.latency_ms as u64),
             incoming_jitter: Duration::from_millis(c.jitter_ms as u64),
             incoming_loss: c.packet_loss,
+            ..default()
         })
     });
     let netcode_config = server::NetcodeConfig::default()
@@ -157,6 +159,7 @@ pub(crate) fn build_server_netcode_config(
         transport: transport_config,
         conditioner,
         compression: shared.compression,
+        ..default()
     };
     server::NetConfig::Netcode {
         config: netcode_config,
@@ -197,15 +200,16 @@ pub(crate) fn get_server_net_configs(settings: &Settings) -> Vec<server::NetConf
                     })
                     .pop()
                     .unwrap();
-                let digest = certificate.certificate_chain().as_slice()[0].hash();
+                let transport = server::ServerTransport::WebTransportServer {
+                    server_addr: SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), *local_port),
+                    certificate,
+                };
+                let digest = transport.certificate_digest().unwrap();
                 println!("Generated self-signed certificate with digest: {}", digest);
                 build_server_netcode_config(
                     settings.server.conditioner.as_ref(),
                     &settings.shared,
-                    server::ServerTransport::WebTransportServer {
-                        server_addr: SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), *local_port),
-                        certificate,
-                    },
+                    transport,
                 )
             }
             ServerTransports::WebSocket { local_port } => build_server_netcode_config(
@@ -262,6 +266,7 @@ pub(crate) fn build_client_netcode_config(
         transport: transport_config,
         conditioner,
         compression: shared.compression,
+        ..default()
     };
     client::NetConfig::Netcode {
         auth,
@@ -66,6 +66,7 @@ impl Conditioner {
             incoming_latency: Duration::from_millis(self.latency_ms as u64),
             incoming_jitter: Duration::from_millis(self.jitter_ms as u64),
             incoming_loss: self.packet_loss,
+            ..Default::default()
         }
     }
 }
@@ -148,6 +149,7 @@ pub fn build_server_netcode_config(
             incoming_latency: Duration::from_millis(c.latency_ms as u64),
             incoming_jitter: Duration::from_millis(c.jitter_ms as u64),
             incoming_loss: c.packet_loss,
+            ..Default::default()
         })
     });
     let netcode_config = server::NetcodeConfig::default()
@@ -157,6 +159,7 @@ pub fn build_server_netcode_config(
         transport: transport_config,
         conditioner,
         compression: shared.compression,
+        ..Default::default()
     };
     server::NetConfig::Netcode {
         config: netcode_config,
@@ -334,6 +337,7 @@ pub fn build_client_netcode_config(
         transport: transport_config,
         conditioner,
         compression: shared.compression,
+        ..Default::default()
     };
     client::NetConfig::Netcode {
         auth,
@@ -0,0 +1,110 @@
+//! Authentication of the messages sent on a channel.
+//!
+//! Channels with [`ChannelSettings::authenticated`](crate::channel::builder::ChannelSettings::authenticated)
+//! append a message authentication code (a truncated keyed blake3 hash) to each message they send,
+//! and drop the received messages whose code is invalid.
+//!
+//! Each peer uses one key to sign the messages it sends and another key to verify the messages it receives.
+//! They are derived from the session keys exchanged during the connection handshake and from the channel id,
+//! so a message cannot be replayed on another channel or in the other direction.
+//! The code does not protect against a message being replayed on the same channel.
+use bytes::{Bytes, BytesMut};
+
+use crate::connection::netcode::Key;
+use crate::protocol::channel::ChannelId;
+
+/// Number of bytes of the authentication code appended to each message
+pub(crate) const MAC_BYTES: usize = 16;
+
+/// Context string used to derive the keys of the channels from the session keys
+const KEY_DERIVATION_CONTEXT: &str = "lightyear 0.15 channel message authentication";
+
+/// Signs the messages sent on a channel and verifies the messages received on it
+#[derive(Clone)]
+pub(crate) struct ChannelAuthenticator {
+    send_key: [u8; blake3::KEY_LEN],
+    receive_key: [u8; blake3::KEY_LEN],
+}
+
+impl std::fmt::Debug for ChannelAuthenticator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // do not leak the keys in the logs
+        f.debug_struct("ChannelAuthenticator")
+            .finish_non_exhaustive()
+    }
+}
+
+impl ChannelAuthenticator {
+    /// Derive the keys of a channel from the session keys of the connection
+    pub(crate) fn new(send_key: &Key, receive_key: &Key, channel_id: ChannelId) -> Self {
+        Self {
+            send_key: derive_key(send_key, channel_id),
+            receive_key: derive_key(receive_key, channel_id),
+        }
+    }
+
+    /// Returns the message followed by its authentication code
+    pub(crate) fn sign(&self, message: Bytes) -> Bytes {
+        let hash = blake3::keyed_hash(&self.send_key, &message);
+        let mut signed = BytesMut::with_capacity(message.len() + MAC_BYTES);
+        signed.extend_from_slice(&message);
+        signed.extend_from_slice(&hash.as_bytes()[..MAC_BYTES]);
+        signed.freeze()
+    }
+
+    /// Checks the authentication code at the end of the message, and returns the message without it.
+    ///
+    /// Returns None if the code is missing or invalid.
+    pub(crate) fn verify(&self, mut message: Bytes) -> Option<Bytes> {
+        if message.len() < MAC_BYTES {
+            return None;
+        }
+        let mac = message.split_off(message.len() - MAC_BYTES);
+        let hash = blake3::keyed_hash(&self.receive_key, &message);
+        // compare in constant time to avoid leaking how many bytes of the code are valid
+        let diff = hash.as_bytes()[..MAC_BYTES]
+            .iter()
+            .zip(mac.iter())
+            .fold(0, |acc, (a, b)| acc | (a ^ b));
+        (diff == 0).then_some(message)
+    }
+}
+
+fn derive_key(session_key: &Key, channel_id: ChannelId) -> [u8; blake3::KEY_LEN] {
+    let mut hasher = blake3::Hasher::new_derive_key(KEY_DERIVATION_CONTEXT);
+    hasher.update(session_key);
+    hasher.update(&channel_id.to_le_bytes());
+    *hasher.finalize().as_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_verify() {
+        let client_to_server_key = [1; 32];
+        let server_to_client_key = [2; 32];
+        let client = ChannelAuthenticator::new(&client_to_server_key, &server_to_client_key, 3);
+        let server = ChannelAuthenticator::new(&server_to_client_key, &client_to_server_key, 3);
+
+        let message = Bytes::from_static(b"kick player 7");
+        let signed = client.sign(message.clone());
+        assert_eq!(signed.len(), message.len() + MAC_BYTES);
+        assert_eq!(server.verify(signed.clone()), Some(message.clone()));
+
+        // a message signed by the server cannot be sent back to the server
+        assert_eq!(server.verify(server.sign(message.clone())), None);
+
+        // the keys depend on the channel
+        let other_channel =
+            ChannelAuthenticator::new(&server_to_client_key, &client_to_server_key, 4);
+        assert_eq!(other_channel.verify(signed.clone()), None);
+
+        // tampered messages are dropped
+        let mut tampered = BytesMut::from(signed.as_ref());
+        tampered[0] ^= 1;
+        assert_eq!(server.verify(tampered.freeze()), None);
+        assert_eq!(server.verify(Bytes::from_static(b"short")), None);
+    }
+}
@@ -1,32 +1,45 @@
 //! This module contains the [`Channel`] trait
 use bevy::utils::Duration;
+use bytes::Bytes;
+use tracing::error;
 
 use lightyear_macros::ChannelInternal;
 
+use crate::channel::authentication::ChannelAuthenticator;
+use crate::channel::compression::MessageCompression;
+#[cfg(feature = "zstd")]
+use crate::channel::compression::MessageCompressor;
 use crate::channel::receivers::ordered_reliable::OrderedReliableReceiver;
 use crate::channel::receivers::sequenced_reliable::SequencedReliableReceiver;
 use crate::channel::receivers::sequenced_unreliable::SequencedUnreliableReceiver;
+use crate::channel::receivers::tick_buffered::TickBufferedReceiver;
 use crate::channel::receivers::unordered_reliable::UnorderedReliableReceiver;
 use crate::channel::receivers::unordered_unreliable::UnorderedUnreliableReceiver;
-use crate::channel::receivers::ChannelReceiver;
+use crate::channel::receivers::{ChannelReceive, ChannelReceiver};
+use crate::channel::senders::error::ChannelSendError;
 use crate::channel::senders::reliable::ReliableSender;
 use crate::channel::senders::sequenced_unreliable::SequencedUnreliableSender;
 use crate::channel::senders::unordered_unreliable::UnorderedUnreliableSender;
 use crate::channel::senders::unordered_unreliable_with_acks::UnorderedUnreliableWithAcksSender;
 use crate::channel::senders::ChannelSender;
-#[cfg(feature = "trace")]
-use crate::channel::stats::send::ChannelSendStats;
-use crate::prelude::ChannelKind;
+use crate::channel::stats::ChannelStats;
+use crate::prelude::{ChannelKind, Tick};
 
 /// A ChannelContainer is a struct that implements the [`Channel`] trait
 pub struct ChannelContainer {
     pub setting: ChannelSettings,
     pub(crate) receiver: ChannelReceiver,
     pub(crate) sender: ChannelSender,
-    // we will put this behind the trace feature for now, as this is pretty niche
-    // and might be performance heavy
-    #[cfg(feature = "trace")]
-    pub(crate) sender_stats: ChannelSendStats,
+    pub(crate) stats: ChannelStats,
+    /// If true, the messages buffered on this channel are not sent yet.
+    ///
+    /// This is used for channels registered at runtime, until the remote peer has registered them too.
+    pub(crate) paused: bool,
+    /// Signs and verifies the messages of the channel, if the channel is authenticated
+    pub(crate) authenticator: Option<ChannelAuthenticator>,
+    /// Compresses and decompresses the messages of the channel, if the channel uses compression
+    #[cfg(feature = "zstd")]
+    pub(crate) compressor: Option<MessageCompressor>,
 }
 
 /// A `Channel` is an abstraction for a way to send messages over the network
@@ -45,6 +58,7 @@ pub struct ChannelContainer {
 ///     mode: ChannelMode::UnorderedUnreliable,
 ///     direction: ChannelDirection::Bidirectional,
 ///     priority: 1.0,
+///     bandwidth_weight: 1.0,
 /// });
 /// ```
 pub trait Channel: 'static {
@@ -92,6 +106,10 @@ impl ChannelContainer {
                 receiver = SequencedUnreliableReceiver::new().into();
                 sender = SequencedUnreliableSender::new(settings.send_frequency).into();
             }
+            ChannelMode::TickBuffered => {
+                receiver = TickBufferedReceiver::new().into();
+                sender = UnorderedUnreliableSender::new(settings.send_frequency).into();
+            }
             ChannelMode::UnorderedReliable(reliable_settings) => {
                 receiver = UnorderedReliableReceiver::new().into();
                 sender = ReliableSender::new(reliable_settings, settings.send_frequency).into();
@@ -101,16 +119,73 @@ impl ChannelContainer {
                 sender = ReliableSender::new(reliable_settings, settings.send_frequency).into();
             }
             ChannelMode::OrderedReliable(reliable_settings) => {
-                receiver = OrderedReliableReceiver::new().into();
+                receiver =
+                    OrderedReliableReceiver::new(reliable_settings.receive_buffer_capacity).into();
                 sender = ReliableSender::new(reliable_settings, settings.send_frequency).into();
             }
         }
         Self {
-            setting: settings_clone,
             receiver,
             sender,
-            #[cfg(feature = "trace")]
-            sender_stats: ChannelSendStats::default(),
+            stats: ChannelStats::default(),
+            paused: false,
+            authenticator: None,
+            #[cfg(feature = "zstd")]
+            compressor: MessageCompressor::new(&settings_clone.compression),
+            setting: settings_clone,
+        }
+    }
+
+    /// Compress the message and append the authentication code, depending on the channel settings
+    pub(crate) fn encode_message(&mut self, message: Bytes) -> Result<Bytes, ChannelSendError> {
+        #[cfg(feature = "zstd")]
+        let message = match self.compressor.as_mut() {
+            Some(compressor) => compressor.compress(message),
+            None => message,
+        };
+        if !self.setting.authenticated {
+            return Ok(message);
+        }
+        self.authenticator
+            .as_ref()
+            .map(|authenticator| authenticator.sign(message))
+            .ok_or(ChannelSendError::MissingSessionKeys)
+    }
+
+    /// Read the next message received on the channel.
+    ///
+    /// If the channel is authenticated, the messages with an invalid authentication code are dropped.
+    /// If the channel uses compression, the messages that cannot be decompressed are dropped.
+    pub(crate) fn read_message(&mut self) -> Option<(Tick, Bytes)> {
+        loop {
+            let (tick, message) = self.receiver.read_message()?;
+            let message = if self.setting.authenticated {
+                match self
+                    .authenticator
+                    .as_ref()
+                    .and_then(|authenticator| authenticator.verify(message))
+                {
+                    Some(message) => message,
+                    None => {
+                        error!("Dropping a message with an invalid authentication code");
+                        continue;
+                    }
+                }
+            } else {
+                message
+            };
+            #[cfg(feature = "zstd")]
+            let message = match self.compressor.as_mut() {
+                Some(compressor) => match compressor.decompress(message) {
+                    Some(message) => message,
+                    None => {
+                        error!("Dropping a message that could not be decompressed");
+                        continue;
+                    }
+                },
+                None => message,
+            };
+            return Some((tick, message));
         }
     }
 }
@@ -125,6 +200,25 @@ pub struct ChannelSettings {
     pub direction: ChannelDirection,
     /// Sets the priority of the channel. The final priority of a message will be `MessagePriority * ChannelPriority`
     pub priority: f32,
+    /// Share of the bandwidth quota allocated to this channel, relative to the other channels.
+    ///
+    /// Only used if the bandwidth cap is enabled. When the quota is exhausted, each channel gets a part of
+    /// the bytes sent proportional to its weight, so that a channel with a lot of data cannot starve the others.
+    pub bandwidth_weight: f32,
+    /// If true, a message authentication code is appended to every message sent on this channel,
+    /// and the messages received with an invalid code are dropped.
+    ///
+    /// The keys are derived from the session keys exchanged during the connection handshake, so this
+    /// is only available for connections that perform one (i.e. netcode). A client or server that uses
+    /// another connection (Steam, Local, etc.) refuses to connect/start if any channel is authenticated.
+    /// This is useful for sensitive channels (admin commands, etc.) when the transport does not
+    /// authenticate the packets.
+    pub authenticated: bool,
+    /// How the messages sent on this channel are compressed.
+    ///
+    /// This is useful for channels that send many similar messages, which can be compressed individually
+    /// with a dictionary shared by both peers.
+    pub compression: MessageCompression,
 }
 
 impl Default for ChannelSettings {
@@ -134,6 +228,9 @@ impl Default for ChannelSettings {
             send_frequency: Duration::default(),
             direction: ChannelDirection::Bidirectional,
             priority: 1.0,
+            bandwidth_weight: 1.0,
+            authenticated: false,
+            compression: MessageCompression::None,
         }
     }
 }
@@ -154,9 +251,16 @@ pub enum ChannelMode {
     /// will arrive
     UnorderedReliable(ReliableSettings),
     /// Same as unordered reliable, but the messages are sequenced (only the newest message is accepted)
+    ///
+    /// If several messages arrive at once, only the newest one is read; the older ones are still acked.
     SequencedReliable(ReliableSettings),
     /// Messages will arrive in the correct order at the destination
     OrderedReliable(ReliableSettings),
+    /// Same as unordered unreliable, but the messages are only read once the local simulation reaches
+    /// the tick at which they were sent, and duplicate messages for the same tick are only read once.
+    ///
+    /// This is useful for messages that target a specific tick, such as inputs or tick-scheduled commands.
+    TickBuffered,
 }
 
 impl ChannelMode {
@@ -165,6 +269,7 @@ impl ChannelMode {
             ChannelMode::UnorderedUnreliableWithAcks => false,
             ChannelMode::UnorderedUnreliable => false,
             ChannelMode::SequencedUnreliable => false,
+            ChannelMode::TickBuffered => false,
             ChannelMode::UnorderedReliable(_) => true,
             ChannelMode::SequencedReliable(_) => true,
             ChannelMode::OrderedReliable(_) => true,
@@ -177,6 +282,7 @@ impl ChannelMode {
             ChannelMode::UnorderedUnreliableWithAcks => true,
             ChannelMode::UnorderedUnreliable => false,
             ChannelMode::SequencedUnreliable => false,
+            ChannelMode::TickBuffered => false,
             ChannelMode::UnorderedReliable(_) => true,
             ChannelMode::SequencedReliable(_) => true,
             ChannelMode::OrderedReliable(_) => true,
@@ -198,6 +304,28 @@ pub struct ReliableSettings {
     pub rtt_resend_factor: f32,
     /// Minimum duration to wait before resending a packet if it has not been acked
     pub rtt_resend_min_delay: Duration,
+    /// Multiple of the jitter that is added to the resend delay, so that we don't resend
+    /// messages that are only late because of the variance of the latency
+    pub jitter_resend_factor: f32,
+    /// The resend delay is multiplied by this factor every time a message is resent without being acked.
+    ///
+    /// Set to 1.0 to disable the exponential backoff.
+    pub resend_backoff_factor: f32,
+    /// Maximum duration to wait before resending a packet, once the backoff is applied.
+    ///
+    /// The resend delay is never lower than the delay computed from the rtt and jitter, even if it is above this value.
+    pub max_resend_delay: Duration,
+    /// Maximum number of messages that can be waiting for an ack on this channel.
+    ///
+    /// When the limit is reached, [`try_send_message`](crate::client::connection::ConnectionManager::try_send_message)
+    /// returns [`ChannelSendError::ChannelFull`](crate::prelude::ChannelSendError::ChannelFull).
+    /// Messages sent with the other methods are always buffered. There is no limit if `None`.
+    pub max_in_flight_messages: Option<usize>,
+    /// Number of messages that an ordered receiver can hold while it waits for a missing message.
+    ///
+    /// The buffer is allocated once and rounded up to a power of two. It only grows if a message arrives
+    /// further ahead of the missing message than this capacity.
+    pub receive_buffer_capacity: usize,
 }
 
 impl Default for ReliableSettings {
@@ -205,14 +333,36 @@ impl Default for ReliableSettings {
         Self {
             rtt_resend_factor: 1.5,
             rtt_resend_min_delay: Duration::default(),
+            jitter_resend_factor: 4.0,
+            resend_backoff_factor: 2.0,
+            max_resend_delay: Duration::from_secs(2),
+            max_in_flight_messages: None,
+            receive_buffer_capacity: 256,
         }
     }
 }
 
+/// The backoff stops growing after this many resends, to avoid overflowing the delay
+const MAX_BACKOFF_EXPONENT: u32 = 10;
+
 impl ReliableSettings {
-    pub(crate) fn resend_delay(&self, rtt: Duration) -> Duration {
-        let delay = rtt.mul_f32(self.rtt_resend_factor);
-        std::cmp::max(delay, self.rtt_resend_min_delay)
+    /// Delay before resending a message that has already been resent `num_resends` times
+    pub(crate) fn resend_delay(
+        &self,
+        rtt: Duration,
+        jitter: Duration,
+        num_resends: u32,
+    ) -> Duration {
+        let delay = rtt.mul_f32(self.rtt_resend_factor) + jitter.mul_f32(self.jitter_resend_factor);
+        let delay = std::cmp::max(delay, self.rtt_resend_min_delay);
+        let backoff = self
+            .resend_backoff_factor
+            .max(1.0)
+            .powi(num_resends.min(MAX_BACKOFF_EXPONENT) as i32);
+        std::cmp::min(
+            delay.mul_f32(backoff),
+            std::cmp::max(delay, self.max_resend_delay),
+        )
     }
 }
 
@@ -240,3 +390,23 @@ pub struct PongChannel;
 #[derive(ChannelInternal)]
 /// Default channel to send inputs from client to server. This is a Sequenced Unreliable channel.
 pub struct InputChannel;
+
+/// Default channel to agree on the ids of the channels registered at runtime. This is an Ordered Reliable channel.
+#[derive(ChannelInternal)]
+pub struct ChannelRegistrationChannel;
+
+/// Default channel to notify a client that it gained or lost the authority over an entity. This is an Ordered Reliable channel.
+#[derive(ChannelInternal)]
+pub struct AuthorityChannel;
+
+/// Default channel to notify a client that it received the initial replication messages. This is an Ordered Reliable channel.
+#[derive(ChannelInternal)]
+pub struct InitialSyncChannel;
+
+/// Default channel to stream the content of the world chunks to the clients. This is an Ordered Reliable channel.
+#[derive(ChannelInternal)]
+pub struct ChunkChannel;
+
+/// Default channel to send the checksums of the predicted entities to the clients. This is an Unordered Unreliable channel.
+#[derive(ChannelInternal)]
+pub struct ChecksumChannel;
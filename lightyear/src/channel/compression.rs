@@ -0,0 +1,163 @@
+//! Compression of the messages sent on a channel, with a dictionary shared by both peers.
+//!
+//! Packet-level compression works poorly on small messages, because there is not enough data to find
+//! repetitions in. When many messages are near-identical, a dictionary trained on sample messages
+//! (see [`train_dictionary`]) lets each message be compressed on its own, since the repetitions are
+//! found in the dictionary instead.
+//!
+//! Channels with [`ChannelSettings::compression`](crate::channel::builder::ChannelSettings::compression)
+//! compress each message before it is signed and fragmented. The dictionary is not exchanged over the
+//! network: both peers must use the same dictionary, for example by compiling it into both builds with
+//! `include_bytes!`.
+//!
+//! Every message is prefixed with a varint that is 0 if the message is not compressed
+//! (because compression would not make it smaller), or the length of the decompressed message plus 1.
+
+/// How the messages of a channel are compressed
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum MessageCompression {
+    #[default]
+    None,
+    /// Compress each message with zstd, using a dictionary shared by both peers
+    #[cfg(feature = "zstd")]
+    ZstdDictionary {
+        /// The dictionary, which must be identical on both peers
+        dictionary: bytes::Bytes,
+        /// The zstd compression level
+        level: i32,
+    },
+}
+
+/// Train a zstd dictionary from sample messages, to use with [`MessageCompression::ZstdDictionary`].
+///
+/// The samples should be representative of the serialized messages sent on the channel.
+#[cfg(feature = "zstd")]
+pub fn train_dictionary(samples: &[impl AsRef<[u8]>], max_size: usize) -> std::io::Result<Vec<u8>> {
+    zstd::dict::from_samples(samples, max_size)
+}
+
+#[cfg(feature = "zstd")]
+pub(crate) use zstd_dictionary::MessageCompressor;
+
+#[cfg(feature = "zstd")]
+mod zstd_dictionary {
+    use std::io::Write;
+
+    use bytes::Bytes;
+    use tracing::error;
+    use zstd::bulk::{Compressor, Decompressor};
+
+    use super::MessageCompression;
+    use crate::packet::packet::MAX_MESSAGE_SIZE;
+    use crate::serialize::reader::Reader;
+    use crate::serialize::varint::{varint_len, VarIntReadExt, VarIntWriteExt};
+    use crate::serialize::writer::Writer;
+
+    /// Compresses the messages sent on a channel and decompresses the messages received on it
+    pub(crate) struct MessageCompressor {
+        compressor: Compressor<'static>,
+        decompressor: Decompressor<'static>,
+    }
+
+    impl std::fmt::Debug for MessageCompressor {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("MessageCompressor").finish_non_exhaustive()
+        }
+    }
+
+    impl MessageCompressor {
+        /// Returns None if the messages are not compressed
+        ///
+        /// # Panics
+        ///
+        /// Panics if zstd cannot load the dictionary.
+        pub(crate) fn new(compression: &MessageCompression) -> Option<Self> {
+            match compression {
+                MessageCompression::None => None,
+                MessageCompression::ZstdDictionary { dictionary, level } => Some(Self {
+                    compressor: Compressor::with_dictionary(*level, dictionary)
+                        .expect("could not load the zstd dictionary"),
+                    decompressor: Decompressor::with_dictionary(dictionary)
+                        .expect("could not load the zstd dictionary"),
+                }),
+            }
+        }
+
+        /// Returns the message prefixed with the compression header, compressed if that makes it smaller
+        pub(crate) fn compress(&mut self, message: Bytes) -> Bytes {
+            let compressed = self
+                .compressor
+                .compress(&message)
+                .inspect_err(|e| error!("Could not compress message: {:?}", e))
+                .ok()
+                .filter(|compressed| {
+                    compressed.len() + varint_len(message.len() as u64 + 1) < message.len() + 1
+                });
+            let (header, data) = match &compressed {
+                Some(compressed) => (message.len() as u64 + 1, compressed.as_slice()),
+                None => (0, message.as_ref()),
+            };
+            let mut writer = Writer::with_capacity(varint_len(header) + data.len());
+            // writing to a Writer cannot fail
+            writer.write_varint(header).unwrap();
+            writer.write_all(data).unwrap();
+            writer.to_bytes()
+        }
+
+        /// Strips the compression header and decompresses the message if needed.
+        ///
+        /// Returns None if the message is invalid.
+        pub(crate) fn decompress(&mut self, message: Bytes) -> Option<Bytes> {
+            let mut reader = Reader::from(message);
+            let header = reader.read_varint().ok()?;
+            let data = reader.split_len(reader.remaining());
+            if header == 0 {
+                return Some(data);
+            }
+            let len = usize::try_from(header - 1).ok()?;
+            if len > MAX_MESSAGE_SIZE {
+                return None;
+            }
+            let decompressed = self.decompressor.decompress(&data, len).ok()?;
+            (decompressed.len() == len).then(|| decompressed.into())
+        }
+    }
+}
+
+#[cfg(all(test, feature = "zstd"))]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn test_dictionary_compression() {
+        // any content can be used as a dictionary, without training
+        let compression = MessageCompression::ZstdDictionary {
+            dictionary: Bytes::from_static(
+                b"{\"player\": 0, \"action\": \"move\", \"direction\": \"north\"}",
+            ),
+            level: 3,
+        };
+        let mut sender = MessageCompressor::new(&compression).unwrap();
+        let mut receiver = MessageCompressor::new(&compression).unwrap();
+
+        let message =
+            Bytes::from("{\"player\": 4242, \"action\": \"move\", \"direction\": \"north\"}");
+        let compressed = sender.compress(message.clone());
+        assert!(compressed.len() < message.len());
+        assert_eq!(receiver.decompress(compressed), Some(message));
+
+        // messages that don't compress are sent as is
+        let message = Bytes::from_static(&[7]);
+        let compressed = sender.compress(message.clone());
+        assert_eq!(compressed.len(), message.len() + 1);
+        assert_eq!(receiver.decompress(compressed), Some(message));
+
+        // invalid messages are rejected
+        assert_eq!(
+            receiver.decompress(Bytes::from_static(&[10, 1, 2, 3])),
+            None
+        );
+    }
+}
@@ -1,8 +1,8 @@
 /*! Channels are used to add reliability/ordering on top of the transport layer
 */
+pub(crate) mod authentication;
 pub mod builder;
+pub mod compression;
 pub(crate) mod receivers;
 pub(crate) mod senders;
-
-#[cfg(feature = "trace")]
 pub mod stats;
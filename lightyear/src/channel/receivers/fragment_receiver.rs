@@ -1,10 +1,9 @@
 use std::collections::HashMap;
 
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use tracing::trace;
 
 use crate::packet::message::{FragmentData, MessageId};
-use crate::packet::packet::FRAGMENT_SIZE;
 use crate::prelude::Tick;
 use crate::shared::time_manager::WrappedTime;
 
@@ -32,6 +31,11 @@ impl FragmentReceiver {
         })
     }
 
+    /// Returns true if we have received some of the fragments of this message, but not all of them
+    pub fn is_pending(&self, message_id: &MessageId) -> bool {
+        self.fragment_messages.contains_key(message_id)
+    }
+
     /// Receive a fragment of a FragmentData message.
     ///
     /// When we complete the final message by aggregating all fragments, we will return the
@@ -52,7 +56,7 @@ impl FragmentReceiver {
         // completed the fragmented message!
         if let Some(payload) = fragment_message.receive_fragment(
             fragment.fragment_id as usize,
-            fragment.bytes.as_ref(),
+            fragment.bytes,
             current_time,
         ) {
             self.fragment_messages.remove(&fragment.message_id);
@@ -65,12 +69,13 @@ impl FragmentReceiver {
 
 #[derive(Debug, Clone)]
 /// Data structure to reconstruct a single fragmented message from individual fragments
+///
+/// The fragments are kept as slices of the packets they were received in, and are only copied
+/// once, when all of them have been received.
 pub struct FragmentConstructor {
     num_fragments: usize,
     num_received_fragments: usize,
-    received: Vec<bool>,
-    // bytes: Bytes,
-    bytes: Vec<u8>,
+    fragments: Vec<Option<Bytes>>,
 
     tick: Tick,
     last_received: Option<WrappedTime>,
@@ -81,8 +86,7 @@ impl FragmentConstructor {
         Self {
             num_fragments,
             num_received_fragments: 0,
-            received: vec![false; num_fragments],
-            bytes: vec![0; num_fragments * FRAGMENT_SIZE],
+            fragments: vec![None; num_fragments],
             tick,
             last_received: None,
         }
@@ -91,33 +95,27 @@ impl FragmentConstructor {
     pub fn receive_fragment(
         &mut self,
         fragment_index: usize,
-        bytes: &[u8],
+        bytes: Bytes,
         received_time: Option<WrappedTime>,
     ) -> Option<(Tick, Bytes)> {
         self.last_received = received_time;
 
-        let is_last_fragment = fragment_index == self.num_fragments - 1;
-
         // TODO: check sizes?
-
-        if !self.received[fragment_index] {
-            self.received[fragment_index] = true;
+        let fragment = self.fragments.get_mut(fragment_index)?;
+        if fragment.is_none() {
+            *fragment = Some(bytes);
             self.num_received_fragments += 1;
-
-            if is_last_fragment {
-                let len = (self.num_fragments - 1) * FRAGMENT_SIZE + bytes.len();
-                self.bytes.resize(len, 0);
-            }
-
-            let start = fragment_index * FRAGMENT_SIZE;
-            let end = start + bytes.len();
-            self.bytes[start..end].copy_from_slice(bytes);
         }
 
         if self.num_received_fragments == self.num_fragments {
             trace!("Received all fragments!");
-            let payload = std::mem::take(&mut self.bytes);
-            return Some((self.tick, payload.into()));
+            let fragments = std::mem::take(&mut self.fragments);
+            let len = fragments.iter().flatten().map(Bytes::len).sum();
+            let mut payload = BytesMut::with_capacity(len);
+            for fragment in fragments.into_iter().flatten() {
+                payload.extend_from_slice(&fragment);
+            }
+            return Some((self.tick, payload.freeze()));
         }
 
         None
@@ -127,6 +125,7 @@ impl FragmentConstructor {
 #[cfg(test)]
 mod tests {
     use crate::channel::senders::fragment_sender::FragmentSender;
+    use crate::packet::packet::FRAGMENT_SIZE;
 
     use super::*;
 
@@ -148,4 +147,33 @@ mod tests {
             Some((Tick(0), message_bytes.clone()))
         );
     }
+
+    #[test]
+    fn test_receiver_out_of_order_and_duplicates() {
+        let mut receiver = FragmentReceiver::new();
+        let num_bytes = (FRAGMENT_SIZE as f32 * 2.5) as usize;
+        let message_bytes = Bytes::from((0..num_bytes).map(|i| i as u8).collect::<Vec<_>>());
+        let fragments = FragmentSender::new()
+            .build_fragments(MessageId(0), None, message_bytes.clone())
+            .unwrap();
+        assert_eq!(fragments.len(), 3);
+
+        assert_eq!(
+            receiver.receive_fragment(fragments[2].clone(), Tick(0), None),
+            None
+        );
+        assert_eq!(
+            receiver.receive_fragment(fragments[0].clone(), Tick(1), None),
+            None
+        );
+        // duplicate fragments are ignored
+        assert_eq!(
+            receiver.receive_fragment(fragments[0].clone(), Tick(1), None),
+            None
+        );
+        assert_eq!(
+            receiver.receive_fragment(fragments[1].clone(), Tick(2), None),
+            Some((Tick(0), message_bytes.clone()))
+        );
+    }
 }
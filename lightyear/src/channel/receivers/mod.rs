@@ -19,6 +19,9 @@ pub(crate) mod sequenced_reliable;
 /// Receive messages in an Sequenced Unreliable manner
 pub(crate) mod sequenced_unreliable;
 
+/// Receive messages in a Tick Buffered manner
+pub(crate) mod tick_buffered;
+
 /// Receive messages in an Unordered Reliable manner
 pub(crate) mod unordered_reliable;
 
@@ -47,4 +50,5 @@ pub enum ChannelReceiver {
     OrderedReliable(ordered_reliable::OrderedReliableReceiver),
     SequencedReliable(sequenced_reliable::SequencedReliableReceiver),
     UnorderedReliable(unordered_reliable::UnorderedReliableReceiver),
+    TickBuffered(tick_buffered::TickBufferedReceiver),
 }
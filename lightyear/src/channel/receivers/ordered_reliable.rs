@@ -1,6 +1,5 @@
-use std::collections::{btree_map, BTreeMap};
-
 use bytes::Bytes;
+use tracing::debug;
 
 use super::error::{ChannelReceiveError, Result};
 use crate::channel::receivers::fragment_receiver::FragmentReceiver;
@@ -9,6 +8,7 @@ use crate::packet::message::{MessageData, MessageId, ReceiveMessage};
 use crate::prelude::Tick;
 pub use crate::shared::tick_manager::TickManager;
 pub use crate::shared::time_manager::TimeManager;
+use crate::utils::sequence_buffer::SequenceBuffer;
 
 /// Ordered Reliable receiver: make sure that all messages are received,
 /// and return them in order
@@ -16,17 +16,16 @@ pub struct OrderedReliableReceiver {
     /// Next message id that we are waiting to receive
     /// The channel is reliable so we should see all message ids sequentially.
     pending_recv_message_id: MessageId,
-    // TODO: optimize via ring buffer?
-    /// Buffer of the messages that we received, but haven't processed yet
-    recv_message_buffer: BTreeMap<MessageId, (Tick, Bytes)>,
+    /// Buffer of the messages that we received, but haven't processed yet, indexed by message id
+    recv_message_buffer: SequenceBuffer<MessageId, (Tick, Bytes)>,
     fragment_receiver: FragmentReceiver,
 }
 
 impl OrderedReliableReceiver {
-    pub fn new() -> Self {
+    pub fn new(capacity: usize) -> Self {
         Self {
             pending_recv_message_id: MessageId(0),
-            recv_message_buffer: BTreeMap::new(),
+            recv_message_buffer: SequenceBuffer::new(capacity),
             fragment_receiver: FragmentReceiver::new(),
         }
     }
@@ -47,20 +46,33 @@ impl ChannelReceive for OrderedReliableReceiver {
             return Ok(());
         }
 
+        if self.recv_message_buffer.contains_key(&message_id) {
+            return Ok(());
+        }
+        // the message was acked, so it will not be sent again: make room for it instead of dropping it
+        let distance = (message_id - self.pending_recv_message_id) as usize;
+        if distance >= self.recv_message_buffer.capacity() {
+            debug!(
+                ?message_id,
+                pending = ?self.pending_recv_message_id,
+                "Growing the receive buffer of an ordered channel"
+            );
+            self.recv_message_buffer.grow(distance + 1);
+        }
+
         // add the message to the buffer
-        if let btree_map::Entry::Vacant(entry) = self.recv_message_buffer.entry(message_id) {
-            match message.data {
-                MessageData::Single(single) => {
-                    entry.insert((message.remote_sent_tick, single.bytes));
-                }
-                MessageData::Fragment(fragment) => {
-                    if let Some(res) = self.fragment_receiver.receive_fragment(
-                        fragment,
-                        message.remote_sent_tick,
-                        None,
-                    ) {
-                        entry.insert(res);
-                    }
+        match message.data {
+            MessageData::Single(single) => {
+                self.recv_message_buffer
+                    .push(&message_id, (message.remote_sent_tick, single.bytes));
+            }
+            MessageData::Fragment(fragment) => {
+                if let Some(res) = self.fragment_receiver.receive_fragment(
+                    fragment,
+                    message.remote_sent_tick,
+                    None,
+                ) {
+                    self.recv_message_buffer.push(&message_id, res);
                 }
             }
         }
@@ -95,7 +107,7 @@ mod tests {
 
     #[test]
     fn test_ordered_reliable_receiver_internals() -> Result<(), PacketError> {
-        let mut receiver = OrderedReliableReceiver::new();
+        let mut receiver = OrderedReliableReceiver::new(4);
 
         let mut single1 = SingleData::new(None, Bytes::from("hello"));
         let mut single2 = SingleData::new(None, Bytes::from("world"));
@@ -142,4 +154,32 @@ mod tests {
         );
         Ok(())
     }
+    #[test]
+    fn test_ordered_reliable_receiver_grow() -> Result<(), PacketError> {
+        let mut receiver = OrderedReliableReceiver::new(4);
+        let mut single = SingleData::new(None, Bytes::from("hello"));
+
+        // a message further ahead than the capacity is still buffered
+        single.id = Some(MessageId(10));
+        receiver.buffer_recv(ReceiveMessage {
+            data: single.clone().into(),
+            remote_sent_tick: Tick(1),
+        })?;
+        assert_eq!(receiver.recv_message_buffer.capacity(), 16);
+        assert!(receiver.recv_message_buffer.contains_key(&MessageId(10)));
+
+        for id in 0..10 {
+            single.id = Some(MessageId(id));
+            receiver.buffer_recv(ReceiveMessage {
+                data: single.clone().into(),
+                remote_sent_tick: Tick(1),
+            })?;
+        }
+        for _ in 0..11 {
+            assert!(receiver.read_message().is_some());
+        }
+        assert_eq!(receiver.read_message(), None);
+        assert_eq!(receiver.pending_recv_message_id, MessageId(11));
+        Ok(())
+    }
 }
@@ -1,5 +1,3 @@
-use std::collections::{btree_map, BTreeMap};
-
 use bytes::Bytes;
 
 use super::error::{ChannelReceiveError, Result};
@@ -10,24 +8,27 @@ use crate::packet::message::{MessageData, MessageId, ReceiveMessage};
 use crate::prelude::Tick;
 use crate::shared::tick_manager::TickManager;
 use crate::shared::time_manager::TimeManager;
+use crate::utils::sequence_buffer::SequenceBuffer;
 
 /// Sequenced Reliable receiver: make sure that all messages are received,
-/// do not return them in order, but ignore the messages that are older than the most recent one received
+/// do not return them in order, but ignore the messages that are older than the most recent one received.
+///
+/// If several messages are received before they are read, only the most recent one is returned.
 pub struct SequencedReliableReceiver {
-    // TODO: optimize via ring buffer?
-    // TODO: actually do we even need a buffer? we might just need a buffer of 1
-    /// Buffer of the messages that we received, but haven't processed yet
-    recv_message_buffer: BTreeMap<MessageId, (Tick, Bytes)>,
+    /// Buffer of the messages that we received, but haven't processed yet.
+    ///
+    /// Only the most recent message can be read, so the buffer holds a single message.
+    recv_message_buffer: SequenceBuffer<MessageId, (Tick, Bytes)>,
     /// Highest message id received so far
-    most_recent_message_id: MessageId,
+    most_recent_message_id: Option<MessageId>,
     fragment_receiver: FragmentReceiver,
 }
 
 impl SequencedReliableReceiver {
     pub fn new() -> Self {
         Self {
-            recv_message_buffer: BTreeMap::new(),
-            most_recent_message_id: MessageId(0),
+            recv_message_buffer: SequenceBuffer::new(1),
+            most_recent_message_id: None,
             fragment_receiver: FragmentReceiver::new(),
         }
     }
@@ -43,43 +44,52 @@ impl ChannelReceive for SequencedReliableReceiver {
             .message_id()
             .ok_or(ChannelReceiveError::MissingMessageId)?;
 
-        // if the message is too old, ignore it
-        if message_id < self.most_recent_message_id {
-            return Ok(());
+        match self.most_recent_message_id {
+            // the sender starts at message id 0, anything before that is a stale message
+            None if message_id < MessageId(0) => return Ok(()),
+            // if the message is too old, ignore it
+            Some(most_recent) if message_id < most_recent => return Ok(()),
+            // a resend of the most recent message, which might have already been read
+            // (fragments of the most recent message are still accepted until all of them are received)
+            Some(most_recent)
+                if message_id == most_recent
+                    && (matches!(message.data, MessageData::Single(_))
+                        || !self.fragment_receiver.is_pending(&message_id)) =>
+            {
+                return Ok(())
+            }
+            _ => {}
         }
 
         // update the most recent message id
-        if message_id > self.most_recent_message_id {
-            self.most_recent_message_id = message_id;
-        }
+        self.most_recent_message_id = Some(message_id);
 
-        // add the message to the buffer
-        if let btree_map::Entry::Vacant(entry) = self.recv_message_buffer.entry(message_id) {
-            match message.data {
-                MessageData::Single(single) => {
-                    entry.insert((message.remote_sent_tick, single.bytes));
-                }
-                MessageData::Fragment(fragment) => {
-                    if let Some(res) = self.fragment_receiver.receive_fragment(
-                        fragment,
-                        message.remote_sent_tick,
-                        None,
-                    ) {
-                        entry.insert(res);
-                    }
+        // add the message to the buffer, replacing the older message that wasn't read
+        if self.recv_message_buffer.contains_key(&message_id) {
+            return Ok(());
+        }
+        match message.data {
+            MessageData::Single(single) => {
+                self.recv_message_buffer
+                    .push(&message_id, (message.remote_sent_tick, single.bytes));
+            }
+            MessageData::Fragment(fragment) => {
+                if let Some(res) = self.fragment_receiver.receive_fragment(
+                    fragment,
+                    message.remote_sent_tick,
+                    None,
+                ) {
+                    self.recv_message_buffer.push(&message_id, res);
                 }
             }
         }
         Ok(())
     }
     fn read_message(&mut self) -> Option<(Tick, Bytes)> {
-        // keep popping messages until we get one that is more recent than the last one we processed
-        loop {
-            let (message_id, message) = self.recv_message_buffer.pop_first()?;
-            if message_id >= self.most_recent_message_id {
-                return Some(message);
-            }
-        }
+        // only the most recent message is returned; an older message that is still buffered
+        // (because the most recent one is still missing fragments) is never read
+        self.recv_message_buffer
+            .remove(&self.most_recent_message_id?)
     }
 }
 
@@ -123,7 +133,7 @@ mod tests {
             receiver.read_message(),
             Some((Tick(2), single2.bytes.clone()))
         );
-        assert_eq!(receiver.most_recent_message_id, MessageId(1));
+        assert_eq!(receiver.most_recent_message_id, Some(MessageId(1)));
 
         // receive message 0:
         // we don't care about receiving message 0 anymore, since we already have received a more recent message
@@ -135,6 +145,31 @@ mod tests {
         })?;
         assert_eq!(receiver.recv_message_buffer.len(), 0);
         assert_eq!(receiver.read_message(), None);
+
+        // receive a resend of message 1, that was already read: it is ignored
+        receiver.buffer_recv(ReceiveMessage {
+            data: single2.clone().into(),
+            remote_sent_tick: Tick(2),
+        })?;
+        assert_eq!(receiver.read_message(), None);
+
+        // receive several messages before reading: only the most recent one is returned
+        single1.id = Some(MessageId(2));
+        receiver.buffer_recv(ReceiveMessage {
+            data: single1.clone().into(),
+            remote_sent_tick: Tick(4),
+        })?;
+        single2.id = Some(MessageId(3));
+        receiver.buffer_recv(ReceiveMessage {
+            data: single2.clone().into(),
+            remote_sent_tick: Tick(5),
+        })?;
+        assert_eq!(
+            receiver.read_message(),
+            Some((Tick(5), single2.bytes.clone()))
+        );
+        assert_eq!(receiver.read_message(), None);
+        assert_eq!(receiver.recv_message_buffer.len(), 0);
         Ok(())
     }
 }
@@ -19,7 +19,7 @@ pub struct SequencedUnreliableReceiver {
     /// Buffer of the messages that we received, but haven't processed yet
     recv_message_buffer: VecDeque<(Tick, Bytes)>,
     /// Highest message id received so far
-    most_recent_message_id: MessageId,
+    most_recent_message_id: Option<MessageId>,
     fragment_receiver: FragmentReceiver,
     current_time: WrappedTime,
 }
@@ -28,7 +28,7 @@ impl SequencedUnreliableReceiver {
     pub fn new() -> Self {
         Self {
             recv_message_buffer: VecDeque::new(),
-            most_recent_message_id: MessageId(0),
+            most_recent_message_id: None,
             fragment_receiver: FragmentReceiver::new(),
             // TODO: starting at 0 time could be dangerous, because the first update will bring it to time_manager time ?
             current_time: WrappedTime::default(),
@@ -50,15 +50,22 @@ impl ChannelReceive for SequencedUnreliableReceiver {
             .message_id()
             .ok_or(ChannelReceiveError::MissingMessageId)?;
 
-        // if the message is too old, ignore it
-        if message_id < self.most_recent_message_id {
-            return Ok(());
+        match self.most_recent_message_id {
+            // the sender starts at message id 0, anything before that is a stale message
+            None if message_id < MessageId(0) => return Ok(()),
+            // if the message is too old, ignore it
+            Some(most_recent) if message_id < most_recent => return Ok(()),
+            // a duplicate of the most recent message (fragments of the most recent message are still accepted)
+            Some(most_recent)
+                if message_id == most_recent && matches!(message.data, MessageData::Single(_)) =>
+            {
+                return Ok(())
+            }
+            _ => {}
         }
 
         // update the most recent message id
-        if message_id > self.most_recent_message_id {
-            self.most_recent_message_id = message_id;
-        }
+        self.most_recent_message_id = Some(message_id);
 
         // add the message to the buffer
         match message.data {
@@ -118,7 +125,7 @@ mod tests {
         // the message has been buffered, and we can read it instantly
         // since it's the most recent
         assert_eq!(receiver.recv_message_buffer.len(), 1);
-        assert_eq!(receiver.most_recent_message_id, MessageId(1));
+        assert_eq!(receiver.most_recent_message_id, Some(MessageId(1)));
         assert_eq!(
             receiver.read_message(),
             Some((Tick(2), single2.bytes.clone()))
@@ -143,12 +150,19 @@ mod tests {
         })?;
         // it's the most recent message so we receive it
         assert_eq!(receiver.recv_message_buffer.len(), 1);
-        assert_eq!(receiver.most_recent_message_id, MessageId(2));
+        assert_eq!(receiver.most_recent_message_id, Some(MessageId(2)));
         assert_eq!(
             receiver.read_message(),
             Some((Tick(4), single3.bytes.clone()))
         );
         assert_eq!(receiver.recv_message_buffer.len(), 0);
+
+        // receive a duplicate of the most recent message: it is ignored
+        receiver.buffer_recv(ReceiveMessage {
+            data: single3.clone().into(),
+            remote_sent_tick: Tick(4),
+        })?;
+        assert_eq!(receiver.recv_message_buffer.len(), 0);
         Ok(())
     }
 }
@@ -0,0 +1,155 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use bytes::Bytes;
+
+use super::error::Result;
+
+use crate::channel::receivers::fragment_receiver::FragmentReceiver;
+use crate::channel::receivers::ChannelReceive;
+use crate::packet::message::{MessageData, ReceiveMessage};
+use crate::prelude::Tick;
+use crate::shared::tick_manager::TickManager;
+use crate::shared::time_manager::{TimeManager, WrappedTime};
+
+const DISCARD_AFTER: chrono::Duration = chrono::Duration::milliseconds(3000);
+
+/// Tick Buffered receiver: the messages are buffered until the local simulation reaches the tick
+/// at which they were sent by the remote.
+///
+/// This is useful for messages that target a specific tick, such as inputs: the client runs ahead
+/// of the server, so its inputs for tick 10 are sent during its tick 10 and should only be read
+/// by the server at its own tick 10.
+///
+/// Copies of a message that are received several times for the same tick are only read once, and
+/// messages that arrive after their tick has been released are discarded.
+pub struct TickBufferedReceiver {
+    /// Buffer of the messages that we received, grouped by the tick that they target
+    recv_message_buffer: BTreeMap<Tick, VecDeque<Bytes>>,
+    /// Most recent tick for which messages were released
+    last_released_tick: Option<Tick>,
+    current_tick: Tick,
+    fragment_receiver: FragmentReceiver,
+    current_time: WrappedTime,
+}
+
+impl TickBufferedReceiver {
+    pub fn new() -> Self {
+        Self {
+            recv_message_buffer: BTreeMap::new(),
+            last_released_tick: None,
+            current_tick: Tick(0),
+            fragment_receiver: FragmentReceiver::new(),
+            current_time: WrappedTime::default(),
+        }
+    }
+
+    fn buffer_message(&mut self, tick: Tick, bytes: Bytes) {
+        // the messages for this tick have already been read: the message is late or a duplicate
+        if self
+            .last_released_tick
+            .is_some_and(|released| tick <= released)
+        {
+            return;
+        }
+        let messages = self.recv_message_buffer.entry(tick).or_default();
+        if !messages.contains(&bytes) {
+            messages.push_back(bytes);
+        }
+    }
+}
+
+impl ChannelReceive for TickBufferedReceiver {
+    fn update(&mut self, time_manager: &TimeManager, tick_manager: &TickManager) {
+        self.current_time = time_manager.current_time();
+        self.current_tick = tick_manager.tick();
+        self.fragment_receiver
+            .cleanup(self.current_time - DISCARD_AFTER);
+    }
+
+    /// Queues a received message in an internal buffer, keyed by the tick at which it was sent
+    fn buffer_recv(&mut self, message: ReceiveMessage) -> Result<()> {
+        match message.data {
+            MessageData::Single(single) => {
+                self.buffer_message(message.remote_sent_tick, single.bytes);
+            }
+            MessageData::Fragment(fragment) => {
+                if let Some((tick, bytes)) = self.fragment_receiver.receive_fragment(
+                    fragment,
+                    message.remote_sent_tick,
+                    Some(self.current_time),
+                ) {
+                    self.buffer_message(tick, bytes);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the oldest message whose tick has been reached by the local simulation
+    fn read_message(&mut self) -> Option<(Tick, Bytes)> {
+        let mut entry = self.recv_message_buffer.first_entry()?;
+        let tick = *entry.key();
+        if tick > self.current_tick {
+            return None;
+        }
+        self.last_released_tick = Some(tick);
+        let bytes = entry.get_mut().pop_front();
+        if entry.get().is_empty() {
+            entry.remove();
+        }
+        bytes.map(|bytes| (tick, bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::packet::message::SingleData;
+
+    use super::*;
+
+    #[test]
+    fn test_tick_buffered_receiver_internals() -> Result<()> {
+        let mut receiver = TickBufferedReceiver::new();
+        let single1 = SingleData::new(None, Bytes::from("hello"));
+        let single2 = SingleData::new(None, Bytes::from("world"));
+
+        receiver.current_tick = Tick(5);
+        receiver.buffer_recv(ReceiveMessage {
+            data: single2.clone().into(),
+            remote_sent_tick: Tick(7),
+        })?;
+        receiver.buffer_recv(ReceiveMessage {
+            data: single1.clone().into(),
+            remote_sent_tick: Tick(6),
+        })?;
+        // a redundant copy of the same message
+        receiver.buffer_recv(ReceiveMessage {
+            data: single1.clone().into(),
+            remote_sent_tick: Tick(6),
+        })?;
+
+        // the messages are not released until we reach their tick
+        assert_eq!(receiver.read_message(), None);
+        receiver.current_tick = Tick(6);
+        assert_eq!(
+            receiver.read_message(),
+            Some((Tick(6), single1.bytes.clone()))
+        );
+        assert_eq!(receiver.read_message(), None);
+
+        // a message that arrives after its tick was released is discarded
+        receiver.buffer_recv(ReceiveMessage {
+            data: single2.clone().into(),
+            remote_sent_tick: Tick(6),
+        })?;
+        receiver.current_tick = Tick(8);
+        assert_eq!(
+            receiver.read_message(),
+            Some((Tick(7), single2.bytes.clone()))
+        );
+        assert_eq!(receiver.read_message(), None);
+        Ok(())
+    }
+}
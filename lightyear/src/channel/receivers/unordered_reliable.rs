@@ -53,15 +53,18 @@ impl ChannelReceive for UnorderedReliableReceiver {
             return Ok(());
         }
 
+        // a resend of a message we have already received (or of one of its fragments, since only the
+        // fragments that were not acked are resent)
+        if self.received_message_ids.contains(&message_id) {
+            return Ok(());
+        }
+
         // add the message to the buffer
         if let btree_map::Entry::Vacant(entry) = self.recv_message_buffer.entry(message_id) {
             match message.data {
                 MessageData::Single(single) => {
-                    // receive the message if we haven't received it already
-                    if !self.received_message_ids.contains(&message_id) {
-                        self.received_message_ids.insert(message_id);
-                        entry.insert((message.remote_sent_tick, single.bytes));
-                    }
+                    self.received_message_ids.insert(message_id);
+                    entry.insert((message.remote_sent_tick, single.bytes));
                 }
                 MessageData::Fragment(fragment) => {
                     if let Some(res) = self.fragment_receiver.receive_fragment(
@@ -69,11 +72,8 @@ impl ChannelReceive for UnorderedReliableReceiver {
                         message.remote_sent_tick,
                         None,
                     ) {
-                        // receive the message if we haven't received it already
-                        if !self.received_message_ids.contains(&message_id) {
-                            self.received_message_ids.insert(message_id);
-                            entry.insert(res);
-                        }
+                        self.received_message_ids.insert(message_id);
+                        entry.insert(res);
                     }
                 }
             }
@@ -108,7 +108,9 @@ mod tests {
     use bytes::Bytes;
 
     use crate::channel::receivers::ChannelReceive;
+    use crate::channel::senders::fragment_sender::FragmentSender;
     use crate::packet::message::SingleData;
+    use crate::packet::packet::FRAGMENT_SIZE;
 
     use super::*;
 
@@ -162,4 +164,29 @@ mod tests {
         assert_eq!(receiver.pending_recv_message_id, MessageId(2));
         Ok(())
     }
+
+    #[test]
+    fn test_unordered_reliable_receiver_duplicate_fragment() -> Result<(), ChannelReceiveError> {
+        let mut receiver = UnorderedReliableReceiver::new();
+        let message_bytes = Bytes::from(vec![1u8; FRAGMENT_SIZE + 10]);
+        let fragments = FragmentSender::new()
+            .build_fragments(MessageId(1), None, message_bytes.clone())
+            .unwrap();
+        for fragment in fragments.iter() {
+            receiver.buffer_recv(ReceiveMessage {
+                data: fragment.clone().into(),
+                remote_sent_tick: Tick(0),
+            })?;
+        }
+        assert_eq!(receiver.read_message(), Some((Tick(0), message_bytes)));
+
+        // a fragment whose ack was lost is resent after the message was completed
+        receiver.buffer_recv(ReceiveMessage {
+            data: fragments[0].clone().into(),
+            remote_sent_tick: Tick(0),
+        })?;
+        assert!(!receiver.fragment_receiver.is_pending(&MessageId(1)));
+        assert_eq!(receiver.read_message(), None);
+        Ok(())
+    }
 }
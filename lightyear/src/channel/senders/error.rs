@@ -0,0 +1,15 @@
+//! Errors for sending messages
+
+use crate::serialize::SerializationError;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ChannelSendError {
+    #[error("serialization error: {0}")]
+    Serialization(#[from] SerializationError),
+    #[error("the channel already holds the maximum number of messages")]
+    ChannelFull,
+    #[error("the message is {size} bytes, but the maximum message size is {max_size} bytes")]
+    MessageTooBig { size: usize, max_size: usize },
+    #[error("the channel is authenticated but the connection has no session keys")]
+    MissingSessionKeys,
+}
@@ -1,15 +1,20 @@
 use std::collections::VecDeque;
 
+use bevy::time::{Timer, TimerMode};
+use bevy::utils::Duration;
 use bytes::Bytes;
 use crossbeam_channel::Receiver;
 use enum_dispatch::enum_dispatch;
 
+use crate::channel::builder::ChannelSettings;
+use crate::channel::senders::error::ChannelSendError;
 use crate::packet::message::{MessageAck, MessageId, SendMessage};
 use crate::serialize::SerializationError;
 use crate::shared::ping::manager::PingManager;
 use crate::shared::tick_manager::TickManager;
 use crate::shared::time_manager::TimeManager;
 
+pub(crate) mod error;
 pub(crate) mod fragment_ack_receiver;
 pub(crate) mod fragment_sender;
 pub(crate) mod reliable;
@@ -42,6 +47,22 @@ pub trait ChannelSend {
         priority: f32,
     ) -> Result<Option<MessageId>, SerializationError>;
 
+    /// Queues a message to be transmitted, unless the channel is full.
+    ///
+    /// Returns [`ChannelSendError::ChannelFull`] instead of buffering the message if the channel
+    /// already holds the maximum number of messages. By default channels are never full.
+    fn try_send(
+        &mut self,
+        message: Bytes,
+        priority: f32,
+    ) -> Result<Option<MessageId>, ChannelSendError> {
+        Ok(self.buffer_send(message, priority)?)
+    }
+
+    /// Number of messages held by the channel: the messages (or fragments) waiting to be sent,
+    /// and for reliable channels the messages that have not been acked yet
+    fn queue_depth(&self) -> usize;
+
     /// Reads from the buffer of messages to send to prepare a list of Packets
     /// that can be sent over the network for this channel
     fn send_packet(&mut self) -> (VecDeque<SendMessage>, VecDeque<SendMessage>);
@@ -49,6 +70,18 @@ pub trait ChannelSend {
     /// Called when we receive acknowledgement that a Message has been received
     fn receive_ack(&mut self, message_ack: &MessageAck);
 
+    /// Called when a message returned by [`send_packet`](ChannelSend::send_packet) could not be sent
+    /// because the bandwidth quota was exhausted.
+    ///
+    /// By default the message is dropped.
+    fn message_not_sent(&mut self, _message_ack: &MessageAck) {}
+
+    /// Returns the number of messages that were sent again because they were not acked in time,
+    /// since the last call to this function
+    fn take_num_resends(&mut self) -> usize {
+        0
+    }
+
     /// Create a new receiver that will receive a message id when a sent message is acked
     fn subscribe_acks(&mut self) -> Receiver<MessageId>;
 
@@ -58,6 +91,23 @@ pub trait ChannelSend {
 
     /// Send nacks to the subscribers of nacks
     fn send_nacks(&mut self, nack: MessageId);
+
+    /// Apply new settings to the channel, keeping the messages that are currently buffered.
+    ///
+    /// The mode of the channel must not change.
+    fn update_settings(&mut self, settings: &ChannelSettings);
+
+    /// Set the maximum number of bytes of a message before it is split into fragments
+    fn set_fragment_size(&mut self, fragment_size: usize);
+}
+
+/// Timer used to send messages once every `send_frequency`, or `None` to send messages every frame
+pub(crate) fn send_timer(send_frequency: Duration) -> Option<Timer> {
+    if send_frequency == Duration::default() {
+        None
+    } else {
+        Some(Timer::new(send_frequency, TimerMode::Repeating))
+    }
 }
 
 /// Enum dispatch lets us derive ChannelSend on each enum variant
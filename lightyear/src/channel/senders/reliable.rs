@@ -7,9 +7,10 @@ use bytes::Bytes;
 use crossbeam_channel::{Receiver, Sender};
 use tracing::trace;
 
-use crate::channel::builder::ReliableSettings;
+use crate::channel::builder::{ChannelMode, ChannelSettings, ReliableSettings};
+use crate::channel::senders::error::ChannelSendError;
 use crate::channel::senders::fragment_sender::FragmentSender;
-use crate::channel::senders::ChannelSend;
+use crate::channel::senders::{send_timer, ChannelSend};
 use crate::packet::message::{FragmentData, MessageAck, MessageId, SendMessage, SingleData};
 use crate::serialize::SerializationError;
 use crate::shared::ping::manager::PingManager;
@@ -20,6 +21,8 @@ pub struct FragmentAck {
     data: FragmentData,
     acked: bool,
     last_sent: Option<WrappedTime>,
+    /// Number of times the fragment was resent, used to compute the backoff of the resend delay
+    num_resends: u32,
 }
 
 /// A message that has not been acked yet
@@ -29,6 +32,8 @@ pub enum UnackedMessage {
         /// If None: this packet has never been sent before
         /// else: the last instant when this packet was sent
         last_sent: Option<WrappedTime>,
+        /// Number of times the message was resent, used to compute the backoff of the resend delay
+        num_resends: u32,
     },
     Fragmented(Vec<FragmentAck>),
 }
@@ -66,12 +71,15 @@ pub struct ReliableSender {
     /// List of senders that want to be notified when a message is lost
     nack_senders: Vec<Sender<MessageId>>,
     current_rtt: Duration,
+    current_jitter: Duration,
     current_time: WrappedTime,
     /// Internal timer to determine if the channel is ready to send messages
     timer: Option<Timer>,
     /// Factor that makes sure that the priority accumulates at the same right even the channel
     /// sends messages infrequently
     priority_multiplier: f32,
+    /// Number of messages that were sent again since the last call to `take_num_resends`
+    num_resends: usize,
 }
 
 impl ReliableSender {
@@ -92,9 +100,11 @@ impl ReliableSender {
             ack_senders: vec![],
             nack_senders: vec![],
             current_rtt: Duration::default(),
+            current_jitter: Duration::default(),
             current_time: WrappedTime::default(),
             timer,
             priority_multiplier: 1.0,
+            num_resends: 0,
         }
     }
 }
@@ -103,6 +113,7 @@ impl ChannelSend for ReliableSender {
     fn update(&mut self, time_manager: &TimeManager, ping_manager: &PingManager, _: &TickManager) {
         self.current_time = time_manager.current_time();
         self.current_rtt = ping_manager.rtt();
+        self.current_jitter = ping_manager.jitter();
         if let Some(timer) = &mut self.timer {
             timer.tick(time_manager.delta());
             self.priority_multiplier =
@@ -134,6 +145,7 @@ impl ChannelSend for ReliableSender {
                         data: fragment,
                         acked: false,
                         last_sent: None,
+                        num_resends: 0,
                     })
                     .collect(),
             )
@@ -141,6 +153,7 @@ impl ChannelSend for ReliableSender {
             UnackedMessage::Single {
                 bytes: message,
                 last_sent: None,
+                num_resends: 0,
             }
         };
         let unacked_message_with_priority = UnackedMessageWithPriority {
@@ -167,16 +180,22 @@ impl ChannelSend for ReliableSender {
         // Collect the list of messages that need to be sent
         // Either because they have never been sent, or because they need to be resent
 
-        // resend delay is based on the rtt
-        let resend_delay =
-            chrono::Duration::from_std(self.reliable_settings.resend_delay(self.current_rtt))
-                .unwrap();
-        let should_send = |last_sent: &Option<WrappedTime>| -> bool {
+        // resend delay is based on the rtt and jitter, and increases every time the message is resent
+        let should_send = |last_sent: &Option<WrappedTime>, num_resends: u32| -> bool {
             match last_sent {
                 // send if the message has never been sent
                 None => true,
                 // or if we sent it a while back but didn't get an ack
-                Some(last_sent) => self.current_time - *last_sent > resend_delay,
+                Some(last_sent) => {
+                    let resend_delay =
+                        chrono::Duration::from_std(self.reliable_settings.resend_delay(
+                            self.current_rtt,
+                            self.current_jitter,
+                            num_resends,
+                        ))
+                        .unwrap();
+                    self.current_time - *last_sent > resend_delay
+                }
             }
         };
 
@@ -197,14 +216,19 @@ impl ChannelSend for ReliableSender {
                 UnackedMessage::Single {
                     bytes,
                     ref mut last_sent,
+                    ref mut num_resends,
                 } => {
-                    if should_send(last_sent) {
+                    if should_send(last_sent, *num_resends) {
                         trace!("Should send message {:?}", message_id);
                         let message_info = MessageAck {
                             message_id: *message_id,
                             fragment_id: None,
                         };
                         if !self.message_ids_to_send.contains(&message_info) {
+                            if last_sent.is_some() {
+                                self.num_resends += 1;
+                                *num_resends += 1;
+                            }
                             let message = SingleData::new(Some(*message_id), bytes.clone());
                             self.single_messages_to_send.push_back(SendMessage {
                                 data: message.into(),
@@ -219,13 +243,17 @@ impl ChannelSend for ReliableSender {
                     // only send the fragments that haven't been acked and should be resent
                     fragment_acks
                         .iter_mut()
-                        .filter(|f| !f.acked && should_send(&f.last_sent))
+                        .filter(|f| !f.acked && should_send(&f.last_sent, f.num_resends))
                         .for_each(|f| {
                             let message_info = MessageAck {
                                 message_id: *message_id,
                                 fragment_id: Some(f.data.fragment_id),
                             };
                             if !self.message_ids_to_send.contains(&message_info) {
+                                if f.last_sent.is_some() {
+                                    self.num_resends += 1;
+                                    f.num_resends += 1;
+                                }
                                 let message = f.data.clone();
                                 self.fragmented_messages_to_send.push_back(SendMessage {
                                     data: message.into(),
@@ -308,6 +336,43 @@ impl ChannelSend for ReliableSender {
         }
     }
 
+    /// The message was not sent because of the bandwidth quota, so we queue it again
+    /// to be sent as soon as possible instead of waiting for the resend delay
+    fn message_not_sent(&mut self, message_ack: &MessageAck) {
+        let Some(unacked_message) = self.unacked_messages.get_mut(&message_ack.message_id) else {
+            return;
+        };
+        match (
+            &mut unacked_message.unacked_message,
+            message_ack.fragment_id,
+        ) {
+            (
+                UnackedMessage::Single {
+                    last_sent: last_sent @ Some(_),
+                    num_resends,
+                    ..
+                },
+                None,
+            ) => {
+                // the message will be sent again as if it was never sent, so it was not really resent
+                *last_sent = None;
+                *num_resends = num_resends.saturating_sub(1);
+            }
+            (UnackedMessage::Fragmented(fragment_acks), Some(fragment_id)) => {
+                if let Some(fragment_ack) = fragment_acks.get_mut(fragment_id as usize) {
+                    if fragment_ack.last_sent.take().is_some() {
+                        fragment_ack.num_resends = fragment_ack.num_resends.saturating_sub(1);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn take_num_resends(&mut self) -> usize {
+        std::mem::take(&mut self.num_resends)
+    }
+
     /// Create a new receiver that will receive a message id when a message is acked
     fn subscribe_acks(&mut self) -> Receiver<MessageId> {
         let (sender, receiver) = crossbeam_channel::unbounded();
@@ -329,6 +394,44 @@ impl ChannelSend for ReliableSender {
             sender.send(nack).unwrap();
         }
     }
+
+    fn update_settings(&mut self, settings: &ChannelSettings) {
+        match &settings.mode {
+            ChannelMode::UnorderedReliable(reliable_settings)
+            | ChannelMode::SequencedReliable(reliable_settings)
+            | ChannelMode::OrderedReliable(reliable_settings) => {
+                self.reliable_settings = reliable_settings.clone();
+            }
+            _ => {}
+        }
+        self.timer = send_timer(settings.send_frequency);
+        self.priority_multiplier = 1.0;
+    }
+
+    fn set_fragment_size(&mut self, fragment_size: usize) {
+        self.fragment_sender.fragment_size = fragment_size;
+    }
+
+    /// The message is not buffered if the number of unacked messages reached
+    /// [`ReliableSettings::max_in_flight_messages`]
+    fn try_send(
+        &mut self,
+        message: Bytes,
+        priority: f32,
+    ) -> Result<Option<MessageId>, ChannelSendError> {
+        if self
+            .reliable_settings
+            .max_in_flight_messages
+            .is_some_and(|max| self.unacked_messages.len() >= max)
+        {
+            return Err(ChannelSendError::ChannelFull);
+        }
+        Ok(self.buffer_send(message, priority)?)
+    }
+
+    fn queue_depth(&self) -> usize {
+        self.unacked_messages.len()
+    }
 }
 
 #[cfg(test)]
@@ -337,7 +440,8 @@ mod tests {
     use bytes::Bytes;
 
     use crate::channel::builder::ReliableSettings;
-    use crate::packet::message::SingleData;
+    use crate::packet::message::{MessageData, SingleData};
+    use crate::packet::packet::FRAGMENT_SIZE;
 
     use super::*;
 
@@ -347,6 +451,7 @@ mod tests {
             ReliableSettings {
                 rtt_resend_factor: 1.5,
                 rtt_resend_min_delay: Duration::from_millis(100),
+                ..Default::default()
             },
             Duration::default(),
         );
@@ -371,6 +476,7 @@ mod tests {
         sender.current_time += Duration::from_millis(200);
         let (single, _) = sender.send_packet();
         assert_eq!(single.len(), 1);
+        assert_eq!(sender.take_num_resends(), 1);
         assert_eq!(
             single.front().unwrap(),
             &SendMessage {
@@ -392,4 +498,146 @@ mod tests {
         let (single, _) = sender.send_packet();
         assert_eq!(single.len(), 0);
     }
+
+    #[test]
+    fn test_reliable_sender_message_not_sent() {
+        let mut sender = ReliableSender::new(
+            ReliableSettings {
+                rtt_resend_factor: 1.5,
+                rtt_resend_min_delay: Duration::from_millis(100),
+                ..Default::default()
+            },
+            Duration::default(),
+        );
+        sender.current_rtt = Duration::from_millis(100);
+        sender.current_time = WrappedTime::new(0);
+
+        sender.buffer_send(Bytes::from("hello"), 1.0).unwrap();
+        let (single, _) = sender.send_packet();
+        assert_eq!(single.len(), 1);
+
+        // the message was dropped by the bandwidth limiter: it is sent again on the next send
+        // without waiting for the resend delay
+        sender.message_not_sent(&MessageAck {
+            message_id: MessageId(0),
+            fragment_id: None,
+        });
+        let (single, _) = sender.send_packet();
+        assert_eq!(single.len(), 1);
+    }
+
+    #[test]
+    fn test_reliable_sender_resend_backoff() {
+        let mut sender = ReliableSender::new(
+            ReliableSettings {
+                rtt_resend_factor: 1.5,
+                jitter_resend_factor: 4.0,
+                resend_backoff_factor: 2.0,
+                max_resend_delay: Duration::from_millis(500),
+                ..Default::default()
+            },
+            Duration::default(),
+        );
+        sender.current_rtt = Duration::from_millis(80);
+        sender.current_jitter = Duration::from_millis(10);
+        sender.current_time = WrappedTime::new(0);
+
+        sender.buffer_send(Bytes::from("hello"), 1.0).unwrap();
+        let (single, _) = sender.send_packet();
+        assert_eq!(single.len(), 1);
+
+        // the resend delay is 1.5 * rtt + 4 * jitter = 160ms
+        sender.current_time += Duration::from_millis(150);
+        assert_eq!(sender.send_packet().0.len(), 0);
+        sender.current_time += Duration::from_millis(20);
+        assert_eq!(sender.send_packet().0.len(), 1);
+
+        // the delay doubles after each resend
+        sender.current_time += Duration::from_millis(300);
+        assert_eq!(sender.send_packet().0.len(), 0);
+        sender.current_time += Duration::from_millis(30);
+        assert_eq!(sender.send_packet().0.len(), 1);
+
+        // but is capped by the max resend delay
+        sender.current_time += Duration::from_millis(510);
+        assert_eq!(sender.send_packet().0.len(), 1);
+        assert_eq!(sender.take_num_resends(), 3);
+    }
+
+    #[test]
+    fn test_reliable_sender_resend_missing_fragments() {
+        let mut sender = ReliableSender::new(
+            ReliableSettings {
+                rtt_resend_factor: 1.5,
+                rtt_resend_min_delay: Duration::from_millis(100),
+                ..Default::default()
+            },
+            Duration::default(),
+        );
+        let acks = sender.subscribe_acks();
+        sender.current_rtt = Duration::from_millis(100);
+        sender.current_time = WrappedTime::new(0);
+
+        let message = Bytes::from(vec![0; FRAGMENT_SIZE * 2 + 10]);
+        sender.buffer_send(message, 1.0).unwrap();
+        let (_, fragments) = sender.send_packet();
+        assert_eq!(fragments.len(), 3);
+
+        // the first and last fragments are received
+        for fragment_id in [0, 2] {
+            sender.receive_ack(&MessageAck {
+                message_id: MessageId(0),
+                fragment_id: Some(fragment_id),
+            });
+        }
+        assert!(acks.try_recv().is_err());
+
+        // only the missing fragment is resent
+        sender.current_time += Duration::from_millis(200);
+        let (_, fragments) = sender.send_packet();
+        assert_eq!(fragments.len(), 1);
+        let MessageData::Fragment(fragment) = &fragments[0].data else {
+            panic!("expected a fragment");
+        };
+        assert_eq!(fragment.fragment_id, 1);
+        assert_eq!(sender.take_num_resends(), 1);
+
+        // the message is acked once all fragments are received
+        sender.receive_ack(&MessageAck {
+            message_id: MessageId(0),
+            fragment_id: Some(1),
+        });
+        assert_eq!(acks.try_recv(), Ok(MessageId(0)));
+        assert!(sender.unacked_messages.is_empty());
+    }
+
+    #[test]
+    fn test_reliable_sender_max_in_flight_messages() {
+        let mut sender = ReliableSender::new(
+            ReliableSettings {
+                max_in_flight_messages: Some(2),
+                ..Default::default()
+            },
+            Duration::default(),
+        );
+        sender.try_send(Bytes::from("hello"), 1.0).unwrap();
+        sender.try_send(Bytes::from("world"), 1.0).unwrap();
+        assert_eq!(sender.queue_depth(), 2);
+        assert!(matches!(
+            sender.try_send(Bytes::from("full"), 1.0),
+            Err(ChannelSendError::ChannelFull)
+        ));
+
+        // the channel has room again once a message is acked
+        sender.send_packet();
+        sender.receive_ack(&MessageAck {
+            message_id: MessageId(0),
+            fragment_id: None,
+        });
+        assert_eq!(sender.queue_depth(), 1);
+        assert_eq!(
+            sender.try_send(Bytes::from("again"), 1.0).unwrap(),
+            Some(MessageId(2))
+        );
+    }
 }
@@ -5,8 +5,9 @@ use std::collections::VecDeque;
 use bytes::Bytes;
 use crossbeam_channel::{Receiver, Sender};
 
+use crate::channel::builder::ChannelSettings;
 use crate::channel::senders::fragment_sender::FragmentSender;
-use crate::channel::senders::ChannelSend;
+use crate::channel::senders::{send_timer, ChannelSend};
 use crate::packet::message::{MessageAck, MessageData, MessageId, SendMessage, SingleData};
 use crate::serialize::SerializationError;
 use crate::shared::ping::manager::PingManager;
@@ -121,6 +122,18 @@ impl ChannelSend for SequencedUnreliableSender {
             sender.send(nack).unwrap();
         }
     }
+
+    fn update_settings(&mut self, settings: &ChannelSettings) {
+        self.timer = send_timer(settings.send_frequency);
+    }
+
+    fn set_fragment_size(&mut self, fragment_size: usize) {
+        self.fragment_sender.fragment_size = fragment_size;
+    }
+
+    fn queue_depth(&self) -> usize {
+        self.single_messages_to_send.len() + self.fragmented_messages_to_send.len()
+    }
 }
 
 #[cfg(test)]
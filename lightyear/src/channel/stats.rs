@@ -1,31 +1,45 @@
-pub(crate) mod send {
-    /// TODO: maybe this should be directly on the ChannelSender?
-    #[derive(Default, Copy, Clone, Debug, PartialEq)]
-    pub struct ChannelSendStats {
-        num_single_messages_sent: usize,
-        num_fragment_messages_sent: usize,
-        num_bytes_sent: usize,
-    }
+//! Statistics about the messages sent and received on a channel
+use bevy::utils::Duration;
 
-    impl ChannelSendStats {
-        pub fn new() -> Self {
-            Self::default()
-        }
+/// Statistics about the messages sent and received on a channel, for a given connection.
+///
+/// The fragments of a message that was too big to fit in a single packet are counted as separate messages.
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub struct ChannelStats {
+    /// Number of messages sent
+    pub messages_sent: usize,
+    /// Number of messages received
+    pub messages_received: usize,
+    /// Number of bytes of message payload sent
+    pub bytes_sent: usize,
+    /// Number of bytes of message payload received
+    pub bytes_received: usize,
+    /// Number of messages that had to be sent again because they were not acked in time
+    pub resends: usize,
+    num_acks: usize,
+    total_ack_latency: Duration,
+}
 
-        pub fn add_single_message_sent(&mut self, num: usize) {
-            self.num_single_messages_sent += num;
-        }
+impl ChannelStats {
+    /// Average duration between sending a message and receiving its ack.
+    ///
+    /// Returns `None` if no message was acked yet, or if the channel does not use acks.
+    pub fn average_ack_latency(&self) -> Option<Duration> {
+        (self.num_acks > 0).then(|| self.total_ack_latency / self.num_acks as u32)
+    }
 
-        pub fn add_fragment_message_sent(&mut self, num: usize) {
-            self.num_fragment_messages_sent += num;
-        }
+    pub(crate) fn add_messages_sent(&mut self, num: usize, num_bytes: usize) {
+        self.messages_sent += num;
+        self.bytes_sent = self.bytes_sent.saturating_add(num_bytes);
+    }
 
-        pub fn add_bytes_sent(&mut self, num_bytes: usize) {
-            self.num_bytes_sent = self.num_bytes_sent.saturating_add(num_bytes);
-        }
+    pub(crate) fn add_message_received(&mut self, num_bytes: usize) {
+        self.messages_received += 1;
+        self.bytes_received = self.bytes_received.saturating_add(num_bytes);
+    }
 
-        pub fn messages_sent(&self) -> usize {
-            self.num_single_messages_sent + self.num_fragment_messages_sent
-        }
+    pub(crate) fn add_ack(&mut self, latency: Duration) {
+        self.num_acks += 1;
+        self.total_ack_latency += latency;
     }
 }
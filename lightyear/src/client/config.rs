@@ -10,6 +10,9 @@ use crate::client::interpolation::plugin::InterpolationConfig;
 use crate::client::prediction::plugin::PredictionConfig;
 use crate::client::sync::SyncConfig;
 use crate::connection::client::NetConfig;
+use crate::connection::netcode::MAX_PACKET_SIZE;
+use crate::packet::congestion::CongestionConfig;
+use crate::packet::packet::MAX_MESSAGE_SIZE;
 use crate::shared::config::SharedConfig;
 use crate::shared::ping::manager::PingConfig;
 use crate::shared::replication::plugin::ReplicationConfig;
@@ -18,6 +21,10 @@ use crate::shared::replication::plugin::ReplicationConfig;
 /// Config related to the netcode protocol (abstraction of a connection over raw UDP-like transport)
 pub struct NetcodeConfig {
     pub num_disconnect_packets: usize,
+    /// Interval (in seconds) at which keep-alive packets are sent to the server when no other packet
+    /// was sent, so that the connection is not timed out while the client is idle (for example in menus
+    /// or loading screens).
+    /// The default is 0.1 seconds.
     pub keepalive_packet_send_rate: f64,
     /// Set the duration (in seconds) after which the server disconnects a client if they don't hear from them.
     /// This is valid for tokens generated by the server.
@@ -41,6 +48,19 @@ impl Default for NetcodeConfig {
 }
 
 impl NetcodeConfig {
+    /// Set the interval (in seconds) at which keep-alive packets are sent to the server when the client is idle
+    pub fn with_keepalive_packet_send_rate(mut self, keepalive_packet_send_rate: f64) -> Self {
+        self.keepalive_packet_send_rate = keepalive_packet_send_rate;
+        self
+    }
+
+    /// Set the duration (in seconds) after which an idle connection is timed out,
+    /// for the `ConnectToken`s generated by the client
+    pub fn with_client_timeout_secs(mut self, client_timeout_secs: i32) -> Self {
+        self.client_timeout_secs = client_timeout_secs;
+        self
+    }
+
     pub(crate) fn build(&self) -> crate::connection::netcode::ClientConfig<()> {
         crate::connection::netcode::ClientConfig::default()
             .num_disconnect_packets(self.num_disconnect_packets)
@@ -60,7 +80,25 @@ pub struct PacketConfig {
     /// Number of bytes per second that can be sent to the server
     pub send_bandwidth_cap: Quota,
     /// If false, there is no bandwidth cap and all messages are sent as soon as possible
+    ///
+    /// If true, the messages that don't fit in the bandwidth quota are dropped if they are unreliable,
+    /// and sent again at the next opportunity if they are reliable.
     pub bandwidth_cap_enabled: bool,
+    /// Configuration of the congestion control, which reduces the packet send rate and the
+    /// replication frequency when the RTT or the packet loss spike
+    pub congestion: CongestionConfig,
+    /// Maximum number of bytes of the payload of a packet.
+    ///
+    /// The default is [`MAX_PACKET_SIZE`], which is also the largest value allowed by the netcode protocol.
+    /// Use a smaller value if the packets go through a relay or a network with a small MTU.
+    /// The value is clamped between 128 and [`MAX_PACKET_SIZE`] bytes.
+    pub max_packet_size: usize,
+    /// Maximum number of bytes of a message. Sending a bigger message returns
+    /// [`ChannelSendError::MessageTooBig`](crate::prelude::ChannelSendError::MessageTooBig).
+    ///
+    /// The messages that don't fit in a packet are split into at most 255 fragments, so the
+    /// largest message that can be sent also depends on [`max_packet_size`](Self::max_packet_size).
+    pub max_message_size: usize,
 }
 
 impl Default for PacketConfig {
@@ -70,6 +108,9 @@ impl Default for PacketConfig {
             // 56 KB/s bandwidth cap
             send_bandwidth_cap: Quota::per_second(nonzero!(56000u32)),
             bandwidth_cap_enabled: false,
+            congestion: CongestionConfig::default(),
+            max_packet_size: MAX_PACKET_SIZE,
+            max_message_size: MAX_MESSAGE_SIZE,
         }
     }
 }
@@ -90,6 +131,21 @@ impl PacketConfig {
         self.bandwidth_cap_enabled = true;
         self
     }
+
+    pub fn with_congestion_control(mut self, congestion: CongestionConfig) -> Self {
+        self.congestion = congestion;
+        self
+    }
+
+    pub fn with_max_packet_size(mut self, max_packet_size: usize) -> Self {
+        self.max_packet_size = max_packet_size;
+        self
+    }
+
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
 }
 
 /// The configuration object that lets you create a `ClientPlugin` with the desired settings.
@@ -4,25 +4,30 @@ use bevy::ecs::entity::EntityHashMap;
 use bevy::prelude::{Mut, Resource, World};
 use bevy::utils::{Duration, HashMap};
 use bytes::Bytes;
-use tracing::{debug, trace, trace_span};
+use tracing::{debug, error, trace, trace_span};
 
 use crate::channel::builder::{
-    EntityActionsChannel, EntityUpdatesChannel, PingChannel, PongChannel,
+    AuthorityChannel, ChannelRegistrationChannel, ChannelSettings, EntityActionsChannel,
+    EntityUpdatesChannel, InitialSyncChannel, PingChannel, PongChannel,
 };
 
-use crate::channel::receivers::ChannelReceive;
 use crate::channel::senders::ChannelSend;
+use crate::channel::stats::ChannelStats;
 use crate::client::config::PacketConfig;
 use crate::client::error::ClientError;
+use crate::client::events::InitialSyncComplete;
 use crate::client::message::ClientMessage;
-use crate::client::replication::send::ReplicateCache;
+use crate::client::replication::send::{Replicate, ReplicateCache};
 use crate::client::sync::SyncConfig;
 use crate::connection::netcode::MAX_PACKET_SIZE;
+use crate::packet::congestion::CongestionConfig;
+use crate::packet::error::PacketError;
+use crate::packet::message::MessageId;
 use crate::packet::message_manager::MessageManager;
 use crate::packet::packet_builder::{Payload, RecvPayload};
 use crate::packet::priority_manager::PriorityConfig;
-use crate::prelude::{Channel, ChannelKind, ClientId, Message, ReplicationConfig};
-use crate::protocol::channel::ChannelRegistry;
+use crate::prelude::{Channel, ChannelKind, ClientId, Message, ReplicationConfig, TargetEntity};
+use crate::protocol::channel::{ChannelRegistration, ChannelRegistry};
 use crate::protocol::component::ComponentRegistry;
 use crate::protocol::message::{MessageError, MessageRegistry, MessageType};
 use crate::protocol::registry::NetId;
@@ -33,7 +38,10 @@ use crate::shared::events::connection::ConnectionEvents;
 use crate::shared::message::MessageSend;
 use crate::shared::ping::manager::{PingConfig, PingManager};
 use crate::shared::ping::message::{Ping, Pong};
+use crate::shared::replication::authority::{AuthorityChange, HasAuthority};
 use crate::shared::replication::delta::DeltaManager;
+use crate::shared::replication::entity_map::RemoteEntityMap;
+use crate::shared::replication::initial_sync::InitialSync;
 use crate::shared::replication::network_target::NetworkTarget;
 use crate::shared::replication::receive::ReplicationReceiver;
 use crate::shared::replication::send::ReplicationSender;
@@ -86,6 +94,10 @@ pub struct ConnectionManager {
     /// Used to transfer raw bytes to a system that can convert the bytes to the actual type
     pub(crate) received_messages: HashMap<NetId, Vec<Bytes>>,
     pub(crate) writer: Writer,
+    /// The [`InitialSync`] marker whose actions messages we are waiting for, if any
+    pub(crate) pending_initial_sync: Option<InitialSync>,
+    /// Authority changes received from the server, applied after the replication messages
+    pending_authority_changes: Vec<AuthorityChange>,
     // TODO: maybe don't do any replication until connection is synced?
 }
 
@@ -107,6 +119,7 @@ impl Default for ConnectionManager {
                 &ChannelRegistry::default(),
                 0.0,
                 PriorityConfig::default(),
+                CongestionConfig::default(),
             ),
             delta_manager: DeltaManager::default(),
             replication_sender,
@@ -119,6 +132,8 @@ impl Default for ConnectionManager {
             received_leafwing_input_messages: HashMap::default(),
             received_messages: HashMap::default(),
             writer: Writer::with_capacity(0),
+            pending_initial_sync: None,
+            pending_authority_changes: Vec::new(),
         }
     }
 }
@@ -136,12 +151,19 @@ impl ConnectionManager {
         input_delay_ticks: u16,
     ) -> Self {
         let bandwidth_cap_enabled = packet_config.bandwidth_cap_enabled;
+        let congestion_config = packet_config.congestion.clone();
+        let (max_packet_size, max_message_size) = (
+            packet_config.max_packet_size,
+            packet_config.max_message_size,
+        );
         // create the message manager and the channels
         let mut message_manager = MessageManager::new(
             channel_registry,
             packet_config.nack_rtt_multiple,
             packet_config.into(),
+            congestion_config,
         );
+        message_manager.set_max_sizes(max_packet_size, max_message_size);
         // get notified when a replication-update message gets acked/nacked
         let entity_updates_sender = &mut message_manager
             .channels
@@ -160,7 +182,8 @@ impl ConnectionManager {
             replication_config,
             bandwidth_cap_enabled,
         );
-        let replication_receiver = ReplicationReceiver::new();
+        let mut replication_receiver = ReplicationReceiver::new();
+        replication_receiver.remote_entity_map.record_changes();
         Self {
             component_registry: component_registry.clone(),
             message_registry: message_registry.clone(),
@@ -176,10 +199,29 @@ impl ConnectionManager {
             received_leafwing_input_messages: HashMap::default(),
             received_messages: HashMap::default(),
             writer: Writer::with_capacity(MAX_PACKET_SIZE),
+            pending_initial_sync: None,
+            pending_authority_changes: Vec::new(),
         }
     }
 
+    /// Return the statistics of the messages sent to and received from the server on the channel `C`
+    pub fn channel_stats<C: Channel>(&self) -> Option<&ChannelStats> {
+        self.message_manager.channel_stats::<C>()
+    }
+
+    /// Returns true if the congestion control detected that the connection to the server is congested,
+    /// in which case packets are sent less frequently
+    pub fn is_congested(&self) -> bool {
+        self.message_manager.congestion.is_congested()
+    }
+
     #[doc(hidden)]
+    /// Return the number of messages held by the channel `C`: the messages waiting to be sent,
+    /// and for reliable channels the messages that were not acked yet
+    pub fn channel_queue_depth<C: Channel>(&self) -> Option<usize> {
+        self.message_manager.queue_depth(&ChannelKind::of::<C>())
+    }
+
     /// Returns true if the connection is synced with the server
     pub fn is_synced(&self) -> bool {
         self.sync_manager.is_synced()
@@ -230,11 +272,101 @@ impl ConnectionManager {
         Ok(())
     }
 
+    /// Register a channel that the server has registered at runtime.
+    ///
+    /// The channel can only be used once the server has sent the id to use for it; until then,
+    /// sending a message on the channel returns an error.
+    ///
+    /// Prefer [`ClientCommands::register_channel`](crate::client::networking::ClientCommands::register_channel),
+    /// which also keeps the channel registered if the client reconnects.
+    pub fn register_channel<C: Channel>(
+        &mut self,
+        settings: ChannelSettings,
+    ) -> Result<(), ClientError> {
+        if let Some(channel_kind) = self
+            .message_manager
+            .channel_registry
+            .add_unbound_channel::<C>(settings)
+        {
+            self.add_bound_channel(channel_kind)?;
+        }
+        Ok(())
+    }
+
+    /// Start using a channel once it is registered locally and we know the id chosen by the server,
+    /// and let the server know that it can send messages on that channel
+    fn add_bound_channel(&mut self, channel_kind: ChannelKind) -> Result<(), ClientError> {
+        let channel_registry = self.message_manager.channel_registry.clone();
+        self.message_manager
+            .add_runtime_channel(channel_kind, &channel_registry, false)?;
+        let registration = ChannelRegistration {
+            name: channel_registry
+                .name(&channel_kind)
+                .ok_or(PacketError::ChannelNotFound)?
+                .to_string(),
+            net_id: *channel_registry
+                .get_net_from_kind(&channel_kind)
+                .ok_or(PacketError::ChannelNotFound)?,
+        };
+        let mut writer = Writer::with_capacity(registration.len());
+        registration.to_bytes(&mut writer)?;
+        let message_bytes = writer.to_bytes();
+        self.message_manager.buffer_send(
+            message_bytes,
+            ChannelKind::of::<ChannelRegistrationChannel>(),
+        )?;
+        Ok(())
+    }
+
     /// Send a message to the server
     pub fn send_message<C: Channel, M: Message>(&mut self, message: &M) -> Result<(), ClientError> {
         self.send_message_to_target::<C, M>(message, NetworkTarget::None)
     }
 
+    /// Send a message to the server, on the default channel of the message
+    ///
+    /// See [`MessageRegistration::set_default_channel`](crate::protocol::message::MessageRegistration::set_default_channel)
+    pub fn send_event<M: Message>(&mut self, message: &M) -> Result<(), ClientError> {
+        let channel_kind = self.message_registry.default_channel::<M>()?;
+        self.erased_send_message_to_target(message, channel_kind, NetworkTarget::None)
+    }
+
+    /// Send a message to the server, and return the id of the message on the channel `C`
+    ///
+    /// If the channel keeps track of acks, a [`MessageDelivered`](crate::client::events::MessageDelivered)
+    /// event with this id is emitted once the server has received the message.
+    /// On unreliable channels, a [`MessageLost`](crate::client::events::MessageLost) event is emitted
+    /// instead if the message was lost.
+    pub fn send_message_with_receipt<C: Channel, M: Message>(
+        &mut self,
+        message: &M,
+    ) -> Result<Option<MessageId>, ClientError> {
+        self.message_registry.serialize(message, &mut self.writer)?;
+        let message_bytes = self.writer.split();
+        self.buffer_message(message_bytes, ChannelKind::of::<C>(), NetworkTarget::None)
+    }
+
+    /// Send a message to the server, unless the channel `C` is full.
+    ///
+    /// Returns [`ChannelSendError::ChannelFull`](crate::prelude::ChannelSendError::ChannelFull) if the channel
+    /// already holds [`ReliableSettings::max_in_flight_messages`](crate::prelude::ReliableSettings::max_in_flight_messages)
+    /// messages that were not acked, so that the caller can wait before sending more messages.
+    pub fn try_send_message<C: Channel, M: Message>(
+        &mut self,
+        message: &M,
+    ) -> Result<Option<MessageId>, ClientError> {
+        self.message_registry.serialize(message, &mut self.writer)?;
+        let message = ClientMessage {
+            message: self.writer.split(),
+            target: NetworkTarget::None,
+        };
+        message.to_bytes(&mut self.writer)?;
+        let message_bytes = self.writer.split();
+        Ok(self
+            .message_manager
+            .try_buffer_send(message_bytes, ChannelKind::of::<C>())?)
+    }
+
     /// Send a message to the server, the message should be re-broadcasted according to the `target`
     pub fn send_message_to_target<C: Channel, M: Message>(
         &mut self,
@@ -252,7 +384,8 @@ impl ConnectionManager {
     ) -> Result<(), ClientError> {
         self.message_registry.serialize(message, &mut self.writer)?;
         let message_bytes = self.writer.split();
-        self.buffer_message(message_bytes, channel_kind, target)
+        self.buffer_message(message_bytes, channel_kind, target)?;
+        Ok(())
     }
 
     pub(crate) fn buffer_message(
@@ -260,7 +393,7 @@ impl ConnectionManager {
         message: Bytes,
         channel: ChannelKind,
         target: NetworkTarget,
-    ) -> Result<(), ClientError> {
+    ) -> Result<Option<MessageId>, ClientError> {
         // TODO: i know channel names never change so i should be able to get them as static
         let channel_name = self
             .message_manager
@@ -272,8 +405,7 @@ impl ConnectionManager {
         message.to_bytes(&mut self.writer)?;
         let message_bytes = self.writer.split();
         // message.emit_send_logs(&channel_name);
-        self.message_manager.buffer_send(message_bytes, channel)?;
-        Ok(())
+        Ok(self.message_manager.buffer_send(message_bytes, channel)?)
     }
 
     pub(crate) fn buffer_replication_messages(
@@ -298,12 +430,15 @@ impl ConnectionManager {
             &mut self.writer,
             &mut self.message_manager,
         )?;
-        self.replication_sender.send_updates_messages(
-            tick,
-            bevy_tick,
-            &mut self.writer,
-            &mut self.message_manager,
-        )?;
+        // when the connection is congested, only buffer updates right before sending a packet
+        if self.message_manager.congestion.ready_to_send() {
+            self.replication_sender.send_updates_messages(
+                tick,
+                bevy_tick,
+                &mut self.writer,
+                &mut self.message_manager,
+            )?;
+        }
         Ok(())
     }
 
@@ -319,6 +454,10 @@ impl ConnectionManager {
         // maybe send pings
         // same thing, we want the correct send time for the ping
         // (and not have the delay between when we prepare the ping and when we send the packet)
+        // when the connection is congested, we don't send packets every frame
+        if !self.message_manager.congestion.ready_to_send() {
+            return Ok(vec![]);
+        }
         if let Some(ping) = self.ping_manager.maybe_prepare_ping(time_manager) {
             self.send_ping(ping)?;
         }
@@ -342,6 +481,11 @@ impl ConnectionManager {
         payloads.map_err(Into::into)
     }
 
+    /// Return the payloads once they have been sent, so that their buffers can be reused
+    pub(crate) fn release_payloads(&mut self, payloads: Vec<Payload>) {
+        self.message_manager.release_payloads(payloads);
+    }
+
     pub(crate) fn receive(
         &mut self,
         // TODO: use Commands to avoid blocking the world?
@@ -351,11 +495,13 @@ impl ConnectionManager {
     ) -> Result<(), ClientError> {
         let _span = trace_span!("receive").entered();
         let message_registry = world.resource::<MessageRegistry>();
+        let mut channel_registrations = vec![];
+        let mut authority_changes = vec![];
         self.message_manager
             .channels
             .iter_mut()
             .try_for_each(|(channel_kind, channel)| {
-                while let Some((tick, single_data)) = channel.receiver.read_message() {
+                while let Some((tick, single_data)) = channel.read_message() {
                     // let channel_name = self
                     //     .message_manager
                     //     .channel_registry
@@ -391,6 +537,13 @@ impl ConnectionManager {
                             time = ?pong.pong_sent_time,
                             "Updated server pong generation"
                         )
+                    } else if *channel_kind == ChannelKind::of::<ChannelRegistrationChannel>() {
+                        // the server has registered a channel at runtime
+                        channel_registrations.push(ChannelRegistration::from_bytes(&mut reader)?);
+                    } else if *channel_kind == ChannelKind::of::<AuthorityChannel>() {
+                        authority_changes.push(AuthorityChange::from_bytes(&mut reader)?);
+                    } else if *channel_kind == ChannelKind::of::<InitialSyncChannel>() {
+                        self.pending_initial_sync = Some(InitialSync::from_bytes(&mut reader)?);
                     } else if *channel_kind == ChannelKind::of::<EntityActionsChannel>() {
                         let actions = EntityActionsMessage::from_bytes(&mut reader)?;
                         self.replication_receiver.recv_actions(actions, tick);
@@ -424,6 +577,24 @@ impl ConnectionManager {
                 Ok::<(), SerializationError>(())
             })?;
 
+        for ChannelRegistration { name, net_id } in channel_registrations {
+            debug!(?name, ?net_id, "Server registered channel");
+            if let Some(channel_kind) = self
+                .message_manager
+                .channel_registry
+                .bind_channel(name, net_id)
+            {
+                self.add_bound_channel(channel_kind)?;
+            }
+        }
+
+        self.pending_authority_changes.extend(authority_changes);
+        Ok(())
+    }
+
+    /// Apply the replication messages that were received from the server to the world
+    pub(crate) fn apply_replication(&mut self, world: &mut World, tick_manager: &TickManager) {
+        let _span = trace_span!("apply_replication").entered();
         if self.sync_manager.is_synced() {
             world.resource_scope(|world, component_registry: Mut<ComponentRegistry>| {
                 // Check if we have any replication messages we can apply to the World (and emit events)
@@ -435,8 +606,53 @@ impl ConnectionManager {
                     &mut self.events,
                 );
             });
+            let entity_map_events = self.replication_receiver.remote_entity_map.drain_changes();
+            if !entity_map_events.is_empty() {
+                world.send_event_batch(entity_map_events);
+            }
+        }
+
+        if self
+            .pending_initial_sync
+            .as_ref()
+            .is_some_and(|initial_sync| initial_sync.is_complete(&self.replication_receiver))
+        {
+            debug!("Replicated the entities that existed on connection");
+            self.pending_initial_sync = None;
+            world.send_event(InitialSyncComplete);
+        }
+
+        // apply the authority changes after the replication messages, so that the entity exists
+        for AuthorityChange {
+            entity,
+            gain_authority,
+        } in std::mem::take(&mut self.pending_authority_changes)
+        {
+            let Some(mut entity_mut) = self
+                .replication_receiver
+                .remote_entity_map
+                .get_local(entity)
+                .and_then(|local_entity| world.get_entity_mut(*local_entity))
+            else {
+                error!(
+                    ?entity,
+                    "Received an authority change for an unknown entity"
+                );
+                continue;
+            };
+            debug!(?entity, ?gain_authority, "Received authority change");
+            if gain_authority {
+                // replicate the entity back to the server entity
+                entity_mut.insert((
+                    HasAuthority,
+                    Replicate::default(),
+                    TargetEntity::Preexisting(entity),
+                ));
+            } else {
+                // remove the whole bundle, so that the entity is not despawned on the server
+                entity_mut.remove::<(HasAuthority, Replicate, TargetEntity)>();
+            }
         }
-        Ok(())
     }
 
     pub(crate) fn recv_packet(
@@ -504,6 +720,10 @@ impl ReplicationReceive for ConnectionManager {
         &mut self.events
     }
 
+    fn remote_entity_map(&self, _from: Option<ClientId>) -> Option<&RemoteEntityMap> {
+        Some(&self.replication_receiver.remote_entity_map)
+    }
+
     fn cleanup(&mut self, tick: Tick) {
         self.replication_receiver.cleanup(tick);
     }
@@ -521,9 +741,9 @@ impl ReplicationSend for ConnectionManager {
         vec![]
     }
 
-    fn cleanup(&mut self, tick: Tick) {
+    fn cleanup(&mut self, tick: Tick, component_registry: &ComponentRegistry) {
         debug!("Running replication clean");
         self.replication_sender.cleanup(tick);
-        self.delta_manager.tick_cleanup(tick);
+        self.delta_manager.tick_cleanup(tick, component_registry);
     }
 }
@@ -0,0 +1,128 @@
+//! Access the mapping between the server entities and the local entities of the client
+//!
+//! The server refers to the entities with its own [`Entity`] ids. When an entity is replicated,
+//! the client spawns a local entity for it and keeps track of the mapping between the two.
+//! The [`NetworkEntityMap`] system parameter can be used to resolve a server entity (for example one
+//! that is mentioned in a message) to the local entity, or the other way around.
+//!
+//! ```rust,ignore
+//! use bevy::prelude::*;
+//! use lightyear::prelude::client::*;
+//!
+//! fn handle_kill_feed(mut messages: EventReader<MessageEvent<KillMessage>>, entity_map: NetworkEntityMap) {
+//!     for message in messages.read() {
+//!         if let Some(local) = entity_map.to_local(message.message().victim) {
+//!             // ...
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Changes to the mapping are emitted as [`EntityMapEvent`](crate::client::events::EntityMapEvent)s.
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::{Entity, Res};
+
+use crate::client::connection::ConnectionManager;
+
+/// System parameter that maps the server entities to the local entities of the client
+#[derive(SystemParam)]
+pub struct NetworkEntityMap<'w> {
+    connection: Res<'w, ConnectionManager>,
+}
+
+impl NetworkEntityMap<'_> {
+    /// Return the local entity that replicates the given server entity
+    pub fn to_local(&self, remote: Entity) -> Option<Entity> {
+        self.connection
+            .replication_receiver
+            .remote_entity_map
+            .get_local(remote)
+            .copied()
+    }
+
+    /// Return the server entity that is replicated by the given local entity
+    pub fn to_remote(&self, local: Entity) -> Option<Entity> {
+        self.connection
+            .replication_receiver
+            .remote_entity_map
+            .get_remote(local)
+            .copied()
+    }
+
+    /// Iterate through all the `(server entity, local entity)` pairs
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, Entity)> + '_ {
+        self.connection
+            .replication_receiver
+            .remote_entity_map
+            .to_local()
+            .iter()
+            .map(|(remote, local)| (*remote, *local))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::SystemState;
+    use bevy::prelude::Events;
+
+    use crate::client::events::EntityMapEvent;
+    use crate::prelude::server::Replicate;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    #[test]
+    fn test_network_entity_map() {
+        let mut stepper = BevyStepper::default();
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((Component1(0.0), Replicate::default()))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+
+        let mut state: SystemState<NetworkEntityMap> =
+            SystemState::new(&mut stepper.client_app.world);
+        let entity_map = state.get(&stepper.client_app.world);
+        let client_entity = entity_map
+            .to_local(server_entity)
+            .expect("entity was not replicated to client");
+        assert_eq!(entity_map.to_remote(client_entity), Some(server_entity));
+        assert_eq!(
+            entity_map.iter().collect::<Vec<_>>(),
+            vec![(server_entity, client_entity)]
+        );
+        assert_eq!(
+            stepper
+                .client_app
+                .world
+                .resource_mut::<Events<EntityMapEvent>>()
+                .drain()
+                .collect::<Vec<_>>(),
+            vec![EntityMapEvent::Mapped {
+                remote: server_entity,
+                local: client_entity,
+            }]
+        );
+
+        stepper.server_app.world.despawn(server_entity);
+        stepper.frame_step();
+        stepper.frame_step();
+        let entity_map = state.get(&stepper.client_app.world);
+        assert!(entity_map.to_local(server_entity).is_none());
+        assert_eq!(
+            stepper
+                .client_app
+                .world
+                .resource_mut::<Events<EntityMapEvent>>()
+                .drain()
+                .collect::<Vec<_>>(),
+            vec![EntityMapEvent::Unmapped {
+                remote: server_entity,
+                local: client_entity,
+            }]
+        );
+    }
+}
@@ -14,13 +14,14 @@
 //! ```
 
 use bevy::app::{App, Plugin, PreUpdate};
-use bevy::prelude::{Component, Event, IntoSystemConfigs};
+use bevy::prelude::{Component, Entity, Event, EventWriter, IntoSystemConfigs, ResMut};
 
 use crate::client::connection::ConnectionManager;
 use crate::connection::client::DisconnectReason;
 use crate::prelude::ClientId;
 use crate::shared::events::plugin::EventsPlugin;
 use crate::shared::events::systems::push_component_events;
+use crate::shared::run_conditions::is_connected;
 use crate::shared::sets::{ClientMarker, InternalMainSet};
 
 /// Plugin that handles generating bevy [`Events`](Event) related to networking and replication
@@ -33,11 +34,49 @@ impl Plugin for ClientEventsPlugin {
             // EVENTS
             .add_event::<ConnectEvent>()
             .add_event::<DisconnectEvent>()
+            .add_event::<InitialSyncComplete>()
+            .add_event::<EntityMapEvent>()
+            .add_event::<MessageDelivered>()
+            .add_event::<MessageLost>()
+            // SYSTEMS
+            .add_systems(
+                PreUpdate,
+                (push_message_delivered_events, push_message_lost_events)
+                    .in_set(InternalMainSet::<ClientMarker>::EmitEvents)
+                    .run_if(is_connected),
+            )
             // PLUGIN
             .add_plugins(EventsPlugin::<ConnectionManager>::default());
     }
 }
 
+/// Emit a [`MessageDelivered`] event for every message that was acked by the server
+fn push_message_delivered_events(
+    mut connection_manager: ResMut<ConnectionManager>,
+    mut events: EventWriter<MessageDelivered>,
+) {
+    events.send_batch(
+        connection_manager
+            .message_manager
+            .delivered_messages()
+            .map(|(channel, message_id)| MessageDelivered::new(message_id, channel, ())),
+    );
+}
+
+/// Emit a [`MessageLost`] event for every unreliable message that was lost on the way to the server
+fn push_message_lost_events(
+    mut connection_manager: ResMut<ConnectionManager>,
+    mut events: EventWriter<MessageLost>,
+) {
+    events.send_batch(
+        connection_manager
+            .message_manager
+            .lost_messages()
+            .into_iter()
+            .map(|(channel, message_id)| MessageLost::new(message_id, channel, ())),
+    );
+}
+
 pub(crate) fn emit_replication_events<C: Component>(app: &mut App) {
     app.add_event::<ComponentUpdateEvent<C>>();
     app.add_event::<ComponentInsertEvent<C>>();
@@ -71,6 +110,23 @@ pub struct DisconnectEvent {
     pub reason: Option<DisconnectReason>,
 }
 
+/// Bevy [`Event`] emitted on the client once the entities that the server sent on connection have been replicated
+///
+/// This can be used to know when to hide a loading screen.
+#[derive(Event, Debug, Default)]
+pub struct InitialSyncComplete;
+
+/// Bevy [`Event`] emitted on the client when a server entity gets mapped to a local entity, or when the mapping is removed
+///
+/// See [`NetworkEntityMap`](crate::client::entity_map::NetworkEntityMap) to look up the current mapping.
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub enum EntityMapEvent {
+    /// The server entity `remote` is now replicated as the local entity `local`
+    Mapped { remote: Entity, local: Entity },
+    /// The server entity `remote` is no longer replicated as the local entity `local`
+    Unmapped { remote: Entity, local: Entity },
+}
+
 /// Bevy [`Event`] emitted on the client to indicate the user input for the tick
 pub type InputEvent<I> = crate::shared::events::components::InputEvent<I, ()>;
 /// Bevy [`Event`] emitted on the client when a EntitySpawn replication message is received
@@ -85,3 +141,7 @@ pub type ComponentInsertEvent<C> = crate::shared::events::components::ComponentI
 pub type ComponentRemoveEvent<C> = crate::shared::events::components::ComponentRemoveEvent<C, ()>;
 /// Bevy [`Event`] emitted on the client when a (non-replication) message is received
 pub type MessageEvent<M> = crate::shared::events::components::MessageEvent<M, ()>;
+/// Bevy [`Event`] emitted on the client when a message sent to the server has been received by the server
+pub type MessageDelivered = crate::shared::events::components::MessageDelivered<()>;
+/// Bevy [`Event`] emitted on the client when an unreliable message sent to the server has been lost
+pub type MessageLost = crate::shared::events::components::MessageLost<()>;
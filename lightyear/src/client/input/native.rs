@@ -36,7 +36,9 @@
 //! There are several steps to use the `InputPlugin`:
 //! - (optional) read the inputs from an external signal (mouse click or keyboard press, for instance)
 //! - to buffer inputs for each tick. This is done by calling [`add_input`](InputManager::add_input) in a system.
-//! That system must run in the [`InputSystemSet::BufferInputs`] system set, in the `FixedPreUpdate` stage.
+//!   That system must run in the [`InputSystemSet::BufferInputs`] system set, in the `FixedPreUpdate` stage.
+//!   If [`input_delay_ticks`](crate::client::prediction::plugin::PredictionConfig::input_delay_ticks) is set,
+//!   the input will be applied that many ticks later on both the client and the server.
 //! - handle inputs in your game logic in systems that run in the `FixedUpdate` schedule. These systems
 //! will read the inputs using the [`InputEvent`] event.
 //!
@@ -83,12 +85,15 @@ pub struct InputConfig {
 #[derive(Debug, Resource)]
 pub struct InputManager<A> {
     pub(crate) input_buffer: InputBuffer<A>,
+    /// Number of ticks that the inputs are delayed by
+    pub(crate) input_delay_ticks: u16,
 }
 
 impl<A> Default for InputManager<A> {
     fn default() -> Self {
         Self {
             input_buffer: InputBuffer::default(),
+            input_delay_ticks: 0,
         }
     }
 }
@@ -101,8 +106,11 @@ impl<A: UserAction> InputManager<A> {
     }
 
     /// Buffer a user action for the given tick
+    ///
+    /// If there is some input delay, the action will be applied `input_delay_ticks` after the given tick
     pub fn add_input(&mut self, input: A, tick: Tick) {
-        self.input_buffer.set(tick, Some(input));
+        self.input_buffer
+            .set(tick + self.input_delay_ticks as i16, Some(input));
     }
 }
 
@@ -147,7 +155,14 @@ impl<A: UserAction> Plugin for InputPlugin<A> {
         // REGISTRATION
         app.register_type::<InputConfig>();
         // RESOURCES
-        app.init_resource::<InputManager<A>>();
+        let input_delay_ticks = app
+            .world
+            .get_resource::<ClientConfig>()
+            .map_or(0, |config| config.prediction.input_delay_ticks);
+        app.insert_resource(InputManager::<A> {
+            input_delay_ticks,
+            ..Default::default()
+        });
         // EVENT
         app.add_event::<InputEvent<A>>();
         // SETS
@@ -289,8 +304,10 @@ fn prepare_input_message<A: UserAction>(
     };
 
     let current_tick = tick_manager.tick();
+    // with input delay, the inputs have been buffered up to a later tick
+    let end_tick = current_tick + input_manager.input_delay_ticks as i16;
     // TODO: the number of messages should be in SharedConfig
-    trace!(tick = ?current_tick, "prepare_input_message");
+    trace!(tick = ?current_tick, ?end_tick, "prepare_input_message");
     // TODO: instead of 15, send ticks up to the latest yet ACK-ed input tick
     //  this means we would also want to track packet->message acks for unreliable channels as well, so we can notify
     //  this system what the latest acked input tick is?
@@ -315,7 +332,7 @@ fn prepare_input_message<A: UserAction>(
     // let message_len = 20 as u16;
     let message = input_manager
         .input_buffer
-        .create_message(tick_manager.tick(), message_len);
+        .create_message(end_tick, message_len);
     // all inputs are absent
     if !message.is_empty() {
         // TODO: should we provide variants of each user-facing function, so that it pushes the error
@@ -351,3 +368,98 @@ fn send_input_directly_to_client_events<A: UserAction>(
     let input = input_manager.input_buffer.pop(tick);
     client_input_events.send(InputEvent::new(input, ()));
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::{default, FixedUpdate};
+
+    use crate::client::prediction::plugin::PredictionConfig;
+    use crate::prelude::{server, SharedConfig, TickConfig};
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    /// Buffer an input that contains the tick at which it was buffered
+    fn buffer_tick_input(
+        tick_manager: Res<TickManager>,
+        mut input_manager: ResMut<InputManager<MyInput>>,
+    ) {
+        let tick = tick_manager.tick();
+        input_manager.add_input(MyInput(tick.0 as i16), tick);
+    }
+
+    #[derive(Resource, Default)]
+    struct ReceivedInputs(Vec<(Tick, MyInput)>);
+
+    fn record_client_inputs(
+        tick_manager: Res<TickManager>,
+        mut events: EventReader<InputEvent<MyInput>>,
+        mut received: ResMut<ReceivedInputs>,
+    ) {
+        for event in events.read() {
+            if let Some(input) = event.input() {
+                received.0.push((tick_manager.tick(), input.clone()));
+            }
+        }
+    }
+
+    fn record_server_inputs(
+        tick_manager: Res<TickManager>,
+        mut events: EventReader<server::InputEvent<MyInput>>,
+        mut received: ResMut<ReceivedInputs>,
+    ) {
+        for event in events.read() {
+            if let Some(input) = event.input() {
+                received.0.push((tick_manager.tick(), input.clone()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_input_delay() {
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..default()
+        };
+        let client_config = ClientConfig {
+            prediction: PredictionConfig::default().with_input_delay_ticks(2),
+            ..default()
+        };
+        let mut stepper = BevyStepper::new(shared_config, client_config, tick_duration);
+        stepper.client_app.init_resource::<ReceivedInputs>();
+        stepper.server_app.init_resource::<ReceivedInputs>();
+        stepper.client_app.add_systems(
+            FixedPreUpdate,
+            buffer_tick_input.in_set(InputSystemSet::BufferInputs),
+        );
+        stepper
+            .client_app
+            .add_systems(FixedUpdate, record_client_inputs);
+        stepper
+            .server_app
+            .add_systems(FixedUpdate, record_server_inputs);
+        stepper.init();
+        // the client tick can jump while the client is syncing with the server, which
+        // skips some of the buffered inputs: only check the inputs after the sync
+        for _ in 0..20 {
+            stepper.frame_step();
+        }
+        for app in [&mut stepper.client_app, &mut stepper.server_app] {
+            app.world.resource_mut::<ReceivedInputs>().0.clear();
+        }
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+
+        // the inputs are applied 2 ticks after they were buffered, on both the client and the server
+        for app in [&stepper.client_app, &stepper.server_app] {
+            let received = &app.world.resource::<ReceivedInputs>().0;
+            assert!(!received.is_empty());
+            for (tick, input) in received {
+                assert_eq!(*tick - 2u16, Tick(input.0 as u16));
+            }
+        }
+    }
+}
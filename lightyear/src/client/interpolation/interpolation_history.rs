@@ -11,20 +11,17 @@ use crate::client::interpolation::resource::InterpolationManager;
 use crate::client::interpolation::Interpolated;
 use crate::prelude::{ComponentRegistry, TickManager};
 use crate::shared::tick_manager::Tick;
-use crate::utils::ready_buffer::ReadyBuffer;
+use crate::utils::history_buffer::HistoryBuffer;
 
 /// To know if we need to do rollback, we need to compare the interpolated entity's history with the server's state updates
-#[derive(Component, Debug)]
+#[derive(Component, Debug, PartialEq)]
 pub struct ConfirmedHistory<C: SyncComponent> {
-    // TODO: here we can use a sequence buffer. We won't store more than a couple
-
-    // TODO: add a max size for the buffer
     // We want to avoid using a SequenceBuffer for optimization (we don't want to store a copy of the component for each history tick)
-    // We can afford to use a ReadyBuffer because we will get server updates with monotonically increasing ticks
-    // therefore we can get rid of the old ticks before the server update
+    // We can afford to use a bounded buffer ordered by tick because we will get server updates with (mostly) monotonically
+    // increasing ticks, therefore we can get rid of the old ticks before the server update
 
     // We will only store the history for the ticks where the component got updated
-    pub buffer: ReadyBuffer<Tick, C>,
+    pub(crate) buffer: HistoryBuffer<C>,
 }
 
 impl<C: SyncComponent> Default for ConfirmedHistory<C> {
@@ -33,42 +30,35 @@ impl<C: SyncComponent> Default for ConfirmedHistory<C> {
     }
 }
 
-// mostly used for tests
-impl<C: SyncComponent> PartialEq for ConfirmedHistory<C> {
-    fn eq(&self, other: &Self) -> bool {
-        self.buffer.heap.iter().eq(other.buffer.heap.iter())
-    }
-}
-
 impl<C: SyncComponent> ConfirmedHistory<C> {
     pub fn new() -> Self {
         Self {
-            buffer: ReadyBuffer::new(),
+            buffer: HistoryBuffer::default(),
         }
     }
 
     /// Reset the history for this component
     pub(crate) fn clear(&mut self) {
-        self.buffer = ReadyBuffer::new();
+        self.buffer.clear();
     }
 
     pub(crate) fn peek(&mut self) -> Option<(Tick, &C)> {
-        self.buffer.heap.peek().map(|item| (item.key, &item.item))
+        self.buffer.front().map(|(tick, value)| (*tick, value))
     }
 
     pub(crate) fn pop(&mut self) -> Option<(Tick, C)> {
-        self.buffer.heap.pop().map(|item| (item.key, item.item))
+        self.buffer.pop_front()
     }
 
     /// Get the value of the component at the specified tick.
-    /// Clears the history buffer of all ticks older or equal than the specified tick.
-    /// NOTE: doesn't pop the last value!
-    /// CAREFUL:
-    /// the component history will only contain the ticks where the component got updated, and otherwise
-    /// contains gaps. Therefore, we need to always leave a value in the history buffer so that we can
-    /// get the values for the future ticks
+    /// Clears the history buffer of all ticks older or equal than the specified tick,
+    /// and returns the most recent of these values.
     pub(crate) fn pop_until_tick(&mut self, tick: Tick) -> Option<(Tick, C)> {
-        self.buffer.pop_until(&tick)
+        let mut value = None;
+        while self.buffer.front().is_some_and(|(t, _)| *t <= tick) {
+            value = self.buffer.pop_front();
+        }
+        value
     }
 }
 
@@ -163,7 +153,7 @@ pub(crate) fn apply_confirmed_update_mode_full<C: SyncComponent>(
                     let _ = manager.map_entities(&mut component, component_registry.as_ref());
                     trace!(?kind, tick = ?tick, "adding confirmed update to history");
                     // update the history at the value that the entity currently is
-                    history.buffer.push(tick, component);
+                    history.buffer.insert(tick, component);
 
                     // TODO: here we do not want to update directly the component, that will be done during interpolation
                 }
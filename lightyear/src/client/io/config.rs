@@ -1,27 +1,35 @@
 use crate::client::io::transport::{ClientTransportBuilder, ClientTransportBuilderEnum};
 use crate::client::io::{Io, IoContext};
-use crate::prelude::CompressionConfig;
+#[cfg(not(target_family = "wasm"))]
+use crate::transport::async_transport::AsyncTransportFactory;
 use crate::transport::config::SharedIoConfig;
 use crate::transport::dummy::DummyIo;
 use crate::transport::error::Result;
 use crate::transport::io::{BaseIo, IoStats};
 use crate::transport::local::LocalChannelBuilder;
-#[cfg(feature = "zstd")]
-use crate::transport::middleware::compression::zstd::compression::ZstdCompressor;
-#[cfg(feature = "zstd")]
-use crate::transport::middleware::compression::zstd::decompression::ZstdDecompressor;
 use crate::transport::middleware::conditioner::LinkConditioner;
-use crate::transport::middleware::PacketReceiverWrapper;
+#[cfg(all(feature = "quic", not(target_family = "wasm")))]
+use crate::transport::quic::client::QuicClientSocketBuilder;
+#[cfg(not(target_family = "wasm"))]
+use crate::transport::relay::RelaySocketBuilder;
+#[cfg(all(feature = "tcp", not(target_family = "wasm")))]
+use crate::transport::tcp::client::TcpClientSocketBuilder;
 #[cfg(not(target_family = "wasm"))]
-use crate::transport::udp::UdpSocketBuilder;
+use crate::transport::udp::{BoundUdpSocketBuilder, UdpSocketBuilder, UdpSocketConfig};
+#[cfg(unix)]
+use crate::transport::unix::UnixClientSocketBuilder;
 #[cfg(feature = "websocket")]
 use crate::transport::websocket::client::WebSocketClientSocketBuilder;
 #[cfg(feature = "webtransport")]
 use crate::transport::webtransport::client::WebTransportClientSocketBuilder;
-use crate::transport::{BoxedReceiver, Transport, LOCAL_SOCKET};
+use crate::transport::{Transport, LOCAL_SOCKET};
 use bevy::prelude::TypePath;
 use crossbeam_channel::{Receiver, Sender};
 use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::PathBuf;
+#[cfg(not(target_family = "wasm"))]
+use std::sync::Arc;
 
 /// Use this to configure the [`Transport`] that will be used to establish a connection with the
 /// server.
@@ -30,6 +38,16 @@ pub enum ClientTransport {
     /// Use a [`UdpSocket`](std::net::UdpSocket)
     #[cfg(not(target_family = "wasm"))]
     UdpSocket(SocketAddr),
+    /// Use a [`UdpSocket`](std::net::UdpSocket) with custom low-level socket options
+    #[cfg(not(target_family = "wasm"))]
+    UdpSocketWithConfig {
+        local_addr: SocketAddr,
+        config: UdpSocketConfig,
+    },
+    /// Use a [`UdpSocket`](std::net::UdpSocket) that is already bound, for example the one returned
+    /// by [`punch_hole`](crate::transport::nat::punch_hole) so that the NAT mapping is kept
+    #[cfg(not(target_family = "wasm"))]
+    BoundUdpSocket(Arc<std::net::UdpSocket>),
     /// Use [`WebTransport`](https://wicg.github.io/web-transport/) as a transport layer
     #[cfg(feature = "webtransport")]
     WebTransportClient {
@@ -42,6 +60,46 @@ pub enum ClientTransport {
     /// Use [`WebSocket`](https://developer.mozilla.org/en-US/docs/Web/API/WebSocket) as a transport
     #[cfg(feature = "websocket")]
     WebSocketClient { server_addr: SocketAddr },
+    /// Use raw [QUIC](https://www.rfc-editor.org/rfc/rfc9000) connections as a transport.
+    /// Packets are sent as unreliable datagrams.
+    #[cfg(all(feature = "quic", not(target_family = "wasm")))]
+    QuicClient {
+        client_addr: SocketAddr,
+        server_addr: SocketAddr,
+    },
+    /// Use a TCP stream as a transport, with each packet prefixed by its length.
+    ///
+    /// This can be used on networks that block UDP, but note that reliability is then handled twice
+    /// (by TCP and by lightyear's channels), and a lost packet delays all the packets sent after it.
+    #[cfg(all(feature = "tcp", not(target_family = "wasm")))]
+    TcpClient {
+        client_addr: SocketAddr,
+        server_addr: SocketAddr,
+    },
+    /// Send packets through a [`RelayServer`](crate::transport::relay::RelayServer), which forwards
+    /// them to the server that registered as the host of the session `session_id`.
+    ///
+    /// The address of the server in the connect token should be `relay_addr`.
+    #[cfg(not(target_family = "wasm"))]
+    Relay {
+        local_addr: SocketAddr,
+        relay_addr: SocketAddr,
+        session_id: u64,
+    },
+    /// Use a unix domain datagram socket bound to `local_path`, to talk to a server on the same machine
+    /// bound to `server_path` without going through the loopback network stack.
+    ///
+    /// Packets from the server are reported as coming from `server_addr`, which should be the address
+    /// of the server in the connect token.
+    #[cfg(unix)]
+    UnixSocket {
+        local_path: PathBuf,
+        server_path: PathBuf,
+        server_addr: SocketAddr,
+    },
+    /// Use a user-provided [`AsyncTransport`](crate::transport::async_transport::AsyncTransport)
+    #[cfg(not(target_family = "wasm"))]
+    Async(AsyncTransportFactory),
     /// Use a crossbeam_channel as a transport. This is useful for testing.
     /// This is mostly for clients.
     LocalChannel {
@@ -53,11 +111,43 @@ pub enum ClientTransport {
 }
 
 impl ClientTransport {
+    /// Create a [`ClientTransport::LocalChannel`] that can talk to a server running in the same process,
+    /// without going through a socket.
+    ///
+    /// Also returns the channel endpoints that the server needs to communicate with this client;
+    /// they can be passed directly to [`ServerTransport::Channels`](crate::prelude::server::ServerTransport).
+    /// `client_addr` is the address that the server will use to identify this client.
+    pub fn local_channels(
+        client_addr: SocketAddr,
+    ) -> (Self, (SocketAddr, Receiver<Vec<u8>>, Sender<Vec<u8>>)) {
+        // channels to receive a message from/to server
+        let (from_server_send, from_server_recv) = crossbeam_channel::unbounded();
+        let (to_server_send, to_server_recv) = crossbeam_channel::unbounded();
+        (
+            ClientTransport::LocalChannel {
+                recv: from_server_recv,
+                send: to_server_send,
+            },
+            (client_addr, to_server_recv, from_server_send),
+        )
+    }
+
     pub(super) fn build(self) -> ClientTransportBuilderEnum {
         match self {
             #[cfg(not(target_family = "wasm"))]
             ClientTransport::UdpSocket(addr) => {
-                ClientTransportBuilderEnum::UdpSocket(UdpSocketBuilder { local_addr: addr })
+                ClientTransportBuilderEnum::UdpSocket(UdpSocketBuilder {
+                    local_addr: addr,
+                    config: UdpSocketConfig::default(),
+                })
+            }
+            #[cfg(not(target_family = "wasm"))]
+            ClientTransport::UdpSocketWithConfig { local_addr, config } => {
+                ClientTransportBuilderEnum::UdpSocket(UdpSocketBuilder { local_addr, config })
+            }
+            #[cfg(not(target_family = "wasm"))]
+            ClientTransport::BoundUdpSocket(socket) => {
+                ClientTransportBuilderEnum::BoundUdpSocket(BoundUdpSocketBuilder { socket })
             }
             #[cfg(all(feature = "webtransport", not(target_family = "wasm")))]
             ClientTransport::WebTransportClient {
@@ -83,6 +173,44 @@ impl ClientTransport {
                     server_addr,
                 })
             }
+            #[cfg(all(feature = "quic", not(target_family = "wasm")))]
+            ClientTransport::QuicClient {
+                client_addr,
+                server_addr,
+            } => ClientTransportBuilderEnum::QuicClient(QuicClientSocketBuilder {
+                client_addr,
+                server_addr,
+            }),
+            #[cfg(all(feature = "tcp", not(target_family = "wasm")))]
+            ClientTransport::TcpClient {
+                client_addr,
+                server_addr,
+            } => ClientTransportBuilderEnum::TcpClient(TcpClientSocketBuilder {
+                client_addr,
+                server_addr,
+            }),
+            #[cfg(not(target_family = "wasm"))]
+            ClientTransport::Relay {
+                local_addr,
+                relay_addr,
+                session_id,
+            } => ClientTransportBuilderEnum::Relay(RelaySocketBuilder {
+                local_addr,
+                relay_addr,
+                session_id,
+            }),
+            #[cfg(unix)]
+            ClientTransport::UnixSocket {
+                local_path,
+                server_path,
+                server_addr,
+            } => ClientTransportBuilderEnum::UnixSocket(UnixClientSocketBuilder {
+                local_path,
+                server_path,
+                server_addr,
+            }),
+            #[cfg(not(target_family = "wasm"))]
+            ClientTransport::Async(factory) => ClientTransportBuilderEnum::Async(factory.build()),
             ClientTransport::LocalChannel { recv, send } => {
                 ClientTransportBuilderEnum::LocalChannel(LocalChannelBuilder { recv, send })
             }
@@ -108,36 +236,13 @@ impl SharedIoConfig<ClientTransport> {
     pub fn connect(self) -> Result<Io> {
         let (transport, state, io_rx, network_tx) = self.transport.build().connect()?;
         let local_addr = transport.local_addr();
-        #[allow(unused_mut)]
-        let (mut sender, receiver) = transport.split();
-        #[allow(unused_mut)]
-        let mut receiver: BoxedReceiver = if let Some(conditioner_config) = self.conditioner {
-            let conditioner = LinkConditioner::new(conditioner_config);
-            Box::new(conditioner.wrap(receiver))
-        } else {
-            Box::new(receiver)
-        };
-        match self.compression {
-            CompressionConfig::None => {}
-            #[cfg(feature = "zstd")]
-            CompressionConfig::Zstd { level } => {
-                use crate::transport::middleware::PacketSenderWrapper;
-                let compressor = ZstdCompressor::new(level);
-                sender = Box::new(compressor.wrap(sender));
-                let decompressor = ZstdDecompressor::new();
-                receiver = Box::new(decompressor.wrap(receiver));
-            }
-            #[cfg(feature = "lz4")]
-            CompressionConfig::Lz4 => {
-                use crate::transport::middleware::PacketSenderWrapper;
-                let compressor =
-                    crate::transport::middleware::compression::lz4::Compressor::default();
-                sender = Box::new(compressor.wrap(sender));
-                let decompressor =
-                    crate::transport::middleware::compression::lz4::Decompressor::default();
-                receiver = Box::new(decompressor.wrap(receiver));
-            }
+        let (sender, receiver) = transport.split();
+        // user middlewares are the closest to the transport
+        let mut middlewares = self.middlewares;
+        if let Some(conditioner_config) = self.conditioner {
+            middlewares.push(move || LinkConditioner::<Vec<u8>>::new(conditioner_config.clone()));
         }
+        let (sender, receiver) = middlewares.wrap(sender, receiver);
         Ok(BaseIo {
             local_addr,
             sender,
@@ -1,10 +1,20 @@
 use crate::client::io::{ClientIoEventReceiver, ClientNetworkEventSender};
+#[cfg(not(target_family = "wasm"))]
+use crate::transport::async_transport::{AsyncTransportBuilder, AsyncTransportSocket};
 use crate::transport::dummy::DummyIo;
 use crate::transport::error::Error as TransportError;
 use crate::transport::io::IoState;
 use crate::transport::local::{LocalChannel, LocalChannelBuilder};
+#[cfg(all(feature = "quic", not(target_family = "wasm")))]
+use crate::transport::quic::client::{QuicClientSocket, QuicClientSocketBuilder};
+#[cfg(not(target_family = "wasm"))]
+use crate::transport::relay::{RelaySocket, RelaySocketBuilder};
+#[cfg(all(feature = "tcp", not(target_family = "wasm")))]
+use crate::transport::tcp::client::{TcpClientSocket, TcpClientSocketBuilder};
 #[cfg(not(target_family = "wasm"))]
-use crate::transport::udp::{UdpSocket, UdpSocketBuilder};
+use crate::transport::udp::{BoundUdpSocketBuilder, UdpSocket, UdpSocketBuilder};
+#[cfg(unix)]
+use crate::transport::unix::{UnixClientSocketBuilder, UnixTransport};
 #[cfg(feature = "websocket")]
 use crate::transport::websocket::client::{WebSocketClientSocket, WebSocketClientSocketBuilder};
 #[cfg(feature = "webtransport")]
@@ -37,10 +47,22 @@ pub(crate) trait ClientTransportBuilder: Send + Sync {
 pub(crate) enum ClientTransportBuilderEnum {
     #[cfg(not(target_family = "wasm"))]
     UdpSocket(UdpSocketBuilder),
+    #[cfg(not(target_family = "wasm"))]
+    BoundUdpSocket(BoundUdpSocketBuilder),
     #[cfg(feature = "webtransport")]
     WebTransportClient(WebTransportClientSocketBuilder),
     #[cfg(feature = "websocket")]
     WebSocketClient(WebSocketClientSocketBuilder),
+    #[cfg(all(feature = "quic", not(target_family = "wasm")))]
+    QuicClient(QuicClientSocketBuilder),
+    #[cfg(all(feature = "tcp", not(target_family = "wasm")))]
+    TcpClient(TcpClientSocketBuilder),
+    #[cfg(not(target_family = "wasm"))]
+    Relay(RelaySocketBuilder),
+    #[cfg(unix)]
+    UnixSocket(UnixClientSocketBuilder),
+    #[cfg(not(target_family = "wasm"))]
+    Async(AsyncTransportBuilder),
     LocalChannel(LocalChannelBuilder),
     Dummy(DummyIo),
 }
@@ -54,6 +76,16 @@ pub(crate) enum ClientTransportEnum {
     WebTransportClient(WebTransportClientSocket),
     #[cfg(feature = "websocket")]
     WebSocketClient(WebSocketClientSocket),
+    #[cfg(all(feature = "quic", not(target_family = "wasm")))]
+    QuicClient(QuicClientSocket),
+    #[cfg(all(feature = "tcp", not(target_family = "wasm")))]
+    TcpClient(TcpClientSocket),
+    #[cfg(not(target_family = "wasm"))]
+    Relay(RelaySocket),
+    #[cfg(unix)]
+    UnixSocket(UnixTransport),
+    #[cfg(not(target_family = "wasm"))]
+    Async(AsyncTransportSocket),
     LocalChannel(LocalChannel),
     Dummy(DummyIo),
 }
@@ -21,6 +21,7 @@ pub mod sync;
 
 pub mod diagnostics;
 mod easings;
+pub mod entity_map;
 
 pub(crate) mod io;
 pub(crate) mod message;
@@ -19,9 +19,11 @@ use crate::client::sync::SyncSet;
 use crate::connection::client::{ClientConnection, ConnectionState, DisconnectReason, NetClient};
 use crate::connection::server::IoConfig;
 use crate::prelude::{
-    is_host_server, ChannelRegistry, MainSet, MessageRegistry, TickManager, TimeManager,
+    is_host_server, Channel, ChannelRegistry, ChannelSettings, MainSet, MessageRegistry,
+    TickManager, TimeManager,
 };
 use crate::protocol::component::ComponentRegistry;
+use crate::protocol::hash::ProtocolHash;
 use crate::server::clients::ControlledEntities;
 use crate::shared::config::Mode;
 use crate::shared::replication::components::Replicated;
@@ -145,6 +147,7 @@ pub(crate) fn receive(world: &mut World) {
                                                             if state.get() != &NetworkingState::Connected {
                                                                 debug!("Setting the networking state to connected");
                                                                 next_state.set(NetworkingState::Connected);
+                                                                connection.message_manager.set_session_keys(netclient.session_keys());
                                                             }
 
                                                             // update the connection (message manager, ping manager, etc.)
@@ -194,11 +197,12 @@ pub(crate) fn send(
     let packet_bytes = connection
         .send_packets(time_manager.as_ref(), tick_manager.as_ref())
         .unwrap();
-    for packet_byte in packet_bytes {
+    for packet_byte in packet_bytes.iter() {
         let _ = netcode.send(packet_byte.as_slice()).map_err(|e| {
             error!("Error sending packet: {}", e);
         });
     }
+    connection.release_payloads(packet_bytes);
 
     // no need to clear the connection, because we already std::mem::take it
     // client.connection.clear();
@@ -416,7 +420,10 @@ fn rebuild_client_connection(world: &mut World) {
     // drop the previous client connection to make sure we release any resources before creating the new one
     world.remove_resource::<ClientConnection>();
     // insert the new client connection
-    let client_connection = client_config.net.build_client();
+    let protocol_hash = ProtocolHash::from_world(world);
+    world.insert_resource(protocol_hash);
+    let mut client_connection = client_config.net.build_client();
+    client_connection.set_protocol_hash(protocol_hash.0);
     world.insert_resource(client_connection);
 }
 
@@ -438,6 +445,20 @@ fn connect(world: &mut World) {
     // - this allows us to take into account any changes to the client config (when building a
     // new client connection and connection manager, which want to do because we need to reset
     // the internal time, sync, priority, message numbers, etc.)
+    let config = world.resource::<ClientConfig>();
+    // in HostServer mode the messages are not sent over the network, so no keys are needed
+    if config.shared.mode != Mode::HostServer
+        && !config.net.provides_session_keys()
+        && world
+            .resource::<ChannelRegistry>()
+            .has_authenticated_channels()
+    {
+        error!("Cannot connect: some channels are authenticated but the client connection does not provide session keys. Only netcode connections support authenticated channels.");
+        world
+            .resource_mut::<NextState<NetworkingState>>()
+            .set(NetworkingState::Disconnected);
+        return;
+    }
     rebuild_client_connection(world);
     let _ = world
         .resource_mut::<ClientConnection>()
@@ -467,6 +488,12 @@ pub trait ClientCommands {
 
     /// Disconnect the client
     fn disconnect_client(&mut self);
+
+    /// Register a channel that the server has registered at runtime with
+    /// [`ServerCommands::register_channel`](crate::server::networking::ServerCommands::register_channel).
+    ///
+    /// The channel can be used once the server has sent the id to use for it.
+    fn register_channel<C: Channel>(&mut self, settings: ChannelSettings);
 }
 
 impl ClientCommands for Commands<'_, '_> {
@@ -481,6 +508,20 @@ impl ClientCommands for Commands<'_, '_> {
             NetworkingState::Disconnected,
         )));
     }
+
+    fn register_channel<C: Channel>(&mut self, settings: ChannelSettings) {
+        self.add(move |world: &mut World| {
+            // keep the channel registered if the client reconnects
+            world
+                .resource_mut::<ChannelRegistry>()
+                .add_unbound_channel::<C>(settings.clone());
+            if let Some(mut connection_manager) = world.get_resource_mut::<ConnectionManager>() {
+                if let Err(e) = connection_manager.register_channel::<C>(settings) {
+                    error!("Could not register channel: {:?}", e);
+                }
+            }
+        });
+    }
 }
 
 mod utils {
@@ -511,3 +552,47 @@ mod utils {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Duration;
+    use lightyear_macros::ChannelInternal;
+
+    use crate::client::config::ClientConfig;
+    use crate::connection::client::NetConfig;
+    use crate::prelude::{AppChannelExt, ChannelSettings, SharedConfig, TickConfig};
+    use crate::tests::stepper::BevyStepper;
+
+    use super::*;
+
+    #[derive(ChannelInternal)]
+    struct AuthenticatedChannel;
+
+    /// A client connection that does not provide session keys cannot connect if a channel is authenticated
+    #[test]
+    fn test_authenticated_channel_requires_session_keys() {
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(shared_config, ClientConfig::default(), tick_duration);
+        stepper
+            .client_app
+            .add_channel::<AuthenticatedChannel>(ChannelSettings {
+                authenticated: true,
+                ..default()
+            });
+        stepper.client_app.world.resource_mut::<ClientConfig>().net = NetConfig::Local { id: 1 };
+        stepper.init();
+
+        assert_eq!(
+            stepper
+                .client_app
+                .world
+                .resource::<State<NetworkingState>>()
+                .get(),
+            &NetworkingState::Disconnected
+        );
+    }
+}
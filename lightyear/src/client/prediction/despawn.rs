@@ -3,6 +3,7 @@ use bevy::prelude::{
     Commands, Component, DespawnRecursiveExt, Entity, Query, Reflect, RemovedComponents, Res,
     ResMut, With, Without, World,
 };
+use bevy::utils::Duration;
 use tracing::{debug, error, trace};
 
 use crate::client::components::{ComponentSyncMode, Confirmed, SyncComponent};
@@ -74,14 +75,76 @@ impl Command for PredictionDespawnCommand {
     }
 }
 
+/// Marker component for predicted entities that we predicted to be despawned, but that are only hidden
+/// until the server confirms the despawn.
+///
+/// The entity is not modified: the systems that display the entity or apply game logic to it should
+/// filter out the entities with this component.
+/// - if the server despawns the entity, the predicted entity gets despawned as well
+/// - if the server doesn't despawn the entity before the `deadline` tick, the marker is removed and the
+///   entity is visible again
+#[derive(Component, PartialEq, Debug, Reflect)]
+pub struct PredictionHidden {
+    /// Tick after which the entity is restored if the despawn was not confirmed
+    pub deadline: Tick,
+}
+
+/// This command hides a predicted entity until the server confirms its despawn.
+///
+/// See [`PredictionHidden`]
+pub struct PredictionHideCommand {
+    entity: Entity,
+    timeout: Duration,
+}
+
+impl Command for PredictionHideCommand {
+    fn apply(self, world: &mut World) {
+        let config = world.resource::<ClientConfig>();
+        let is_host_server = config.shared.mode == Mode::HostServer;
+        let tick_duration = config.shared.tick.tick_duration;
+        // if we are in host server mode, the despawn is authoritative so we can despawn the entity immediately
+        if is_host_server {
+            world.despawn(self.entity);
+            return;
+        }
+        let timeout_ticks =
+            (self.timeout.as_secs_f32() / tick_duration.as_secs_f32()).ceil() as i16;
+        let deadline = world.resource::<TickManager>().tick() + timeout_ticks;
+
+        let Some(mut entity) = world.get_entity_mut(self.entity) else {
+            return;
+        };
+        if entity.get::<Predicted>().is_some() || entity.get::<ShouldBePredicted>().is_some() {
+            trace!(
+                ?deadline,
+                "hiding predicted entity until the despawn is confirmed"
+            );
+            entity.insert(PredictionHidden { deadline });
+        } else {
+            error!("This command should only be called for predicted entities!");
+        }
+    }
+}
+
 pub trait PredictionDespawnCommandsExt {
     fn prediction_despawn(&mut self);
+
+    /// Hide the predicted entity instead of despawning it, until the server confirms the despawn.
+    ///
+    /// If the despawn is not confirmed within `timeout`, the entity is restored.
+    fn prediction_hide(&mut self, timeout: Duration);
 }
 impl PredictionDespawnCommandsExt for EntityCommands<'_> {
     fn prediction_despawn(&mut self) {
         let entity = self.id();
         self.commands().add(PredictionDespawnCommand { entity })
     }
+
+    fn prediction_hide(&mut self, timeout: Duration) {
+        let entity = self.id();
+        self.commands()
+            .add(PredictionHideCommand { entity, timeout })
+    }
 }
 
 /// Despawn predicted entities when the confirmed entity gets despawned
@@ -104,6 +167,24 @@ pub(crate) fn despawn_confirmed(
     }
 }
 
+/// Restore the hidden entities whose despawn was not confirmed by the server in time
+pub(crate) fn restore_hidden_entities(
+    mut commands: Commands,
+    tick_manager: Res<TickManager>,
+    query: Query<(Entity, &PredictionHidden)>,
+) {
+    let tick = tick_manager.tick();
+    for (entity, hidden) in query.iter() {
+        if tick > hidden.deadline {
+            debug!(
+                ?entity,
+                "the despawn was not confirmed by the server, restoring the hidden entity"
+            );
+            commands.entity(entity).remove::<PredictionHidden>();
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct RemovedCache<C: Component>(pub Option<C>);
 
@@ -520,3 +601,61 @@ pub(crate) fn remove_despawn_marker(
 //         Ok(())
 //     }
 // }
+
+#[cfg(test)]
+mod hide_tests {
+    use crate::client::prediction::Predicted;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    #[test]
+    fn test_prediction_hide_timeout() {
+        let mut stepper = BevyStepper::default();
+        let predicted = stepper
+            .client_app
+            .world
+            .spawn(Predicted {
+                confirmed_entity: None,
+            })
+            .id();
+        stepper.frame_step();
+
+        let tick_duration = stepper
+            .client_app
+            .world
+            .resource::<ClientConfig>()
+            .shared
+            .tick
+            .tick_duration;
+        PredictionHideCommand {
+            entity: predicted,
+            timeout: tick_duration * 3,
+        }
+        .apply(&mut stepper.client_app.world);
+        let deadline = stepper.client_tick() + 3;
+        assert_eq!(
+            stepper.client_app.world.get::<PredictionHidden>(predicted),
+            Some(&PredictionHidden { deadline })
+        );
+
+        // the entity is still hidden until the deadline
+        stepper.frame_step();
+        assert!(stepper
+            .client_app
+            .world
+            .get::<PredictionHidden>(predicted)
+            .is_some());
+
+        // the server did not confirm the despawn: the entity is restored
+        for _ in 0..5 {
+            stepper.frame_step();
+        }
+        assert!(stepper
+            .client_app
+            .world
+            .get::<PredictionHidden>(predicted)
+            .is_none());
+        assert!(stepper.client_app.world.get_entity(predicted).is_some());
+    }
+}
@@ -0,0 +1,139 @@
+//! Extrapolate the entities controlled by other clients, instead of only interpolating them.
+//!
+//! The entities of the other players are usually interpolated, so they are displayed in the past. For interactions
+//! at close range (melee combat, for example), it can be fairer to show them at the same tick as the local player.
+//! The server can make them predicted by the client with [`SyncTarget::prediction`], but the client
+//! doesn't know the inputs of the other players, so the game systems can't predict them forward.
+//!
+//! Instead, the predicted entities that have the [`Extrapolated`] component are moved forward every tick with
+//! the last value of a driver component that is replicated from the server (for example their velocity, or their
+//! last input). When the server data contradicts the extrapolation, the entity is rolled back like the other
+//! predicted entities, and the ticks that are re-simulated are extrapolated again from the corrected state.
+//!
+//! ```rust,ignore
+//! use bevy::prelude::*;
+//! use lightyear::prelude::*;
+//!
+//! fn extrapolate_position(position: &mut Position, velocity: &Velocity, delta: Duration) {
+//!     position.0 += velocity.0 * delta.as_secs_f32();
+//! }
+//!
+//! // on the client
+//! app.add_plugins(client::ExtrapolationPlugin::new(extrapolate_position));
+//!
+//! // extrapolate the predicted entities of the other players
+//! fn add_extrapolation(mut commands: Commands, query: Query<Entity, (Added<client::Predicted>, Without<Controlled>)>) {
+//!     for entity in query.iter() {
+//!         commands.entity(entity).insert(client::Extrapolated);
+//!     }
+//! }
+//! ```
+//!
+//! [`SyncTarget::prediction`]: crate::server::replication::send::SyncTarget::prediction
+use bevy::prelude::*;
+use bevy::utils::Duration;
+
+use crate::client::config::ClientConfig;
+use crate::client::prediction::Predicted;
+
+/// Marks a predicted entity that is extrapolated by the [`ExtrapolationPlugin`]s
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Reflect)]
+pub struct Extrapolated;
+
+/// Function that moves the component forward by `delta`, using the last value of the driver component
+pub type ExtrapolateFn<C, V> = fn(&mut C, &V, Duration);
+
+/// Plugin that extrapolates the component `C` of the [`Extrapolated`] entities with the driver component `V`.
+///
+/// The extrapolation runs in the `FixedUpdate` schedule, once per tick (including the ticks that are
+/// re-simulated during a rollback). `C` should be registered in the protocol with prediction, so that
+/// the extrapolation is corrected when the server state is received.
+pub struct ExtrapolationPlugin<C, V> {
+    extrapolate_fn: ExtrapolateFn<C, V>,
+}
+
+impl<C, V> ExtrapolationPlugin<C, V> {
+    pub fn new(extrapolate_fn: ExtrapolateFn<C, V>) -> Self {
+        Self { extrapolate_fn }
+    }
+}
+
+impl<C: Component, V: Component> Plugin for ExtrapolationPlugin<C, V> {
+    fn build(&self, app: &mut App) {
+        let extrapolate_fn = self.extrapolate_fn;
+        // move the extrapolated entities forward by one tick
+        app.add_systems(
+            FixedUpdate,
+            move |config: Res<ClientConfig>,
+                  mut query: Query<(&mut C, &V), (With<Predicted>, With<Extrapolated>)>| {
+                let delta = config.shared.tick.tick_duration;
+                for (mut component, driver) in query.iter_mut() {
+                    extrapolate_fn(&mut component, driver, delta);
+                }
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::{SharedConfig, TickConfig};
+    use crate::tests::protocol::{Component1, Component2};
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    fn extrapolate_component(component: &mut Component1, velocity: &Component2, _: Duration) {
+        component.0 += velocity.0;
+    }
+
+    #[test]
+    fn test_extrapolation() {
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(shared_config, ClientConfig::default(), tick_duration);
+        stepper
+            .client_app
+            .add_plugins(ExtrapolationPlugin::new(extrapolate_component));
+        stepper.init();
+        let extrapolated = stepper
+            .client_app
+            .world
+            .spawn((
+                Predicted {
+                    confirmed_entity: None,
+                },
+                Extrapolated,
+                Component1(0.0),
+                Component2(1.0),
+            ))
+            .id();
+        let predicted = stepper
+            .client_app
+            .world
+            .spawn((
+                Predicted {
+                    confirmed_entity: None,
+                },
+                Component1(0.0),
+                Component2(1.0),
+            ))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+
+        // the stepper runs one tick per frame
+        assert_eq!(
+            stepper.client_app.world.get::<Component1>(extrapolated),
+            Some(&Component1(2.0))
+        );
+        // only the entities that opted in are extrapolated
+        assert_eq!(
+            stepper.client_app.world.get::<Component1>(predicted),
+            Some(&Component1(0.0))
+        );
+    }
+}
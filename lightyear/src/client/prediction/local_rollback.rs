@@ -0,0 +1,522 @@
+//! Rollback of the components of predicted entities that are not replicated, and of resources.
+//!
+//! Only the replicated components with [`ComponentSyncMode::Full`](crate::prelude::client::ComponentSyncMode) are
+//! snapped to the server state during a rollback. Components that only exist on the client (cooldown timers, animation
+//! state, etc.) would keep the value they had at the end of the previous frame, and be simulated again from there.
+//!
+//! Registering them with [`AppRollbackExt::add_rollback`] stores their value for every tick in a [`PredictionHistory`],
+//! and restores the value they had at the rollback tick before the rollback runs.
+//!
+//! Resources that are modified by the predicted systems (a deterministic RNG, a table of cooldowns, etc.) can be
+//! registered with [`AppRollbackExt::add_resource_rollback`] in the same way.
+//!
+//! Entities that are spawned by the predicted systems and only exist on the client (muzzle flashes, local
+//! projectiles, etc.) can be marked with [`PredictedSpawn`]: they are despawned when a rollback goes back
+//! before their spawn tick, so that re-simulating the tick spawns them again instead of duplicating them.
+//!
+//! ```rust,ignore
+//! use bevy::prelude::*;
+//! use lightyear::prelude::client::*;
+//!
+//! #[derive(Component, Clone, PartialEq)]
+//! struct DashCooldown(u16);
+//!
+//! app.add_rollback::<DashCooldown>();
+//! app.add_resource_rollback::<GlobalRng>();
+//! ```
+use bevy::prelude::*;
+use tracing::{debug, error};
+
+use crate::client::components::Confirmed;
+use crate::client::prediction::plugin::PredictionSet;
+use crate::client::prediction::predicted_history::{
+    update_prediction_history, ComponentState, PredictionHistory,
+};
+use crate::client::prediction::rollback::{PredictionGroup, Rollback};
+use crate::client::prediction::Predicted;
+use crate::prelude::{PreSpawnedPlayerObject, Tick, TickManager};
+use crate::utils::history_buffer::HistoryBuffer;
+use crate::utils::ready_buffer::ReadyBuffer;
+
+/// Restore the components that are not replicated during rollbacks
+pub trait AppRollbackExt {
+    /// Store the history of the component `C` on the predicted entities, so that it is restored to its value
+    /// at the rollback tick when a rollback happens.
+    ///
+    /// This is meant for components that are not replicated: the replicated components are rolled back
+    /// to the server state with [`ComponentSyncMode::Full`](crate::prelude::client::ComponentSyncMode).
+    fn add_rollback<C: Component + Clone + PartialEq>(&mut self) -> &mut Self;
+
+    /// Store the history of the resource `R`, so that it is restored to its value at the rollback tick
+    /// when a rollback happens.
+    fn add_resource_rollback<R: Resource + Clone>(&mut self) -> &mut Self;
+}
+
+impl AppRollbackExt for App {
+    fn add_rollback<C: Component + Clone + PartialEq>(&mut self) -> &mut Self {
+        self.add_systems(
+            PreUpdate,
+            (
+                add_local_history::<C>.in_set(PredictionSet::SpawnHistory),
+                prune_local_history::<C>.in_set(PredictionSet::CheckRollback),
+                prepare_local_rollback::<C>.in_set(PredictionSet::PrepareRollback),
+            ),
+        );
+        self.add_systems(
+            FixedPostUpdate,
+            (
+                add_local_history::<C>.in_set(PredictionSet::SpawnHistory),
+                update_prediction_history::<C>.in_set(PredictionSet::UpdateHistory),
+            ),
+        );
+        self
+    }
+
+    fn add_resource_rollback<R: Resource + Clone>(&mut self) -> &mut Self {
+        self.insert_resource(ResourceHistory::<R>::default());
+        self.add_systems(
+            PreUpdate,
+            (
+                prune_resource_history::<R>.in_set(PredictionSet::CheckRollback),
+                prepare_resource_rollback::<R>.in_set(PredictionSet::PrepareRollback),
+            ),
+        );
+        self.add_systems(
+            FixedPostUpdate,
+            update_resource_history::<R>.in_set(PredictionSet::UpdateHistory),
+        );
+        self
+    }
+}
+
+/// History of the values of a resource, for the ticks where it changed.
+///
+/// `None` means that the resource was removed at that tick.
+#[derive(Resource, Debug)]
+pub(crate) struct ResourceHistory<R> {
+    buffer: HistoryBuffer<Option<R>>,
+}
+
+impl<R> Default for ResourceHistory<R> {
+    fn default() -> Self {
+        Self {
+            buffer: HistoryBuffer::default(),
+        }
+    }
+}
+
+impl<R: Clone> ResourceHistory<R> {
+    /// Add the value of the resource at the given tick
+    fn add(&mut self, tick: Tick, value: Option<R>) {
+        self.buffer.add(tick, value);
+    }
+
+    /// Clear the history of values strictly older than the specified tick,
+    /// and return the most recent value that is older or equal to the specified tick.
+    /// That value is kept in the history.
+    fn pop_until_tick(&mut self, tick: Tick) -> Option<Option<R>> {
+        self.buffer
+            .pop_until_tick(tick)
+            .map(|(_, value)| value.clone())
+    }
+
+    /// Remove the values that are strictly more recent than the specified tick
+    fn truncate_after(&mut self, tick: Tick) {
+        self.buffer.truncate_after(tick);
+    }
+}
+
+/// Add a [`PredictionHistory`] to the predicted entities that have the component
+#[allow(clippy::type_complexity)]
+fn add_local_history<C: Component + Clone + PartialEq>(
+    mut commands: Commands,
+    tick_manager: Res<TickManager>,
+    rollback: Res<Rollback>,
+    query: Query<
+        (Entity, &C),
+        (
+            Without<PredictionHistory<C>>,
+            Or<(With<Predicted>, With<PreSpawnedPlayerObject>)>,
+        ),
+    >,
+) {
+    let tick = tick_manager.tick_or_rollback_tick(rollback.as_ref());
+    for (entity, component) in query.iter() {
+        let mut history = PredictionHistory::<C>::default();
+        history.add_update(tick, component.clone());
+        commands.entity(entity).insert(history);
+    }
+}
+
+/// Remove the values that are older than the tick of the latest server update, since no rollback can go back
+/// further than that
+fn prune_local_history<C: Component + Clone + PartialEq>(
+    mut query: Query<(&Predicted, &mut PredictionHistory<C>)>,
+    confirmed_query: Query<&Confirmed>,
+) {
+    for (predicted, mut history) in query.iter_mut() {
+        if let Some(confirmed) = predicted
+            .confirmed_entity
+            .and_then(|entity| confirmed_query.get(entity).ok())
+        {
+            history.pop_until_tick(confirmed.tick);
+        }
+    }
+}
+
+/// Restore the component to its value at the rollback tick
+#[allow(clippy::type_complexity)]
+fn prepare_local_rollback<C: Component + Clone + PartialEq>(
+    mut commands: Commands,
+    rollback: Res<Rollback>,
+    mut query: Query<
+        (
+            Entity,
+            Option<&mut C>,
+            &mut PredictionHistory<C>,
+            Option<&PredictionGroup>,
+        ),
+        Or<(With<Predicted>, With<PreSpawnedPlayerObject>)>,
+    >,
+) {
+    let kind = std::any::type_name::<C>();
+    let Some(rollback_tick_plus_one) = rollback.get_rollback_tick() else {
+        error!("prepare_local_rollback should only be called when we are in rollback");
+        return;
+    };
+    // careful, the current_tick is already incremented by 1 in the check_rollback stage...
+    let rollback_tick = rollback_tick_plus_one - 1;
+    for (entity, component, mut history, group) in query.iter_mut() {
+        // the entity is not affected by the rollback
+        if !rollback.is_rollback_group(group) {
+            continue;
+        }
+        let state = history.pop_until_tick(rollback_tick);
+        // the values after the rollback tick will be written again during the rollback
+        history.clear();
+        match state {
+            None | Some(ComponentState::Removed) => {
+                if component.is_some() {
+                    debug!(
+                        ?entity,
+                        ?kind,
+                        "Component didn't exist at the rollback tick, removing it"
+                    );
+                    commands.entity(entity).remove::<C>();
+                }
+            }
+            Some(ComponentState::Updated(value)) => {
+                history.add_update(rollback_tick, value.clone());
+                match component {
+                    Some(mut component) => *component = value,
+                    None => {
+                        debug!(
+                            ?entity,
+                            ?kind,
+                            "Component existed at the rollback tick, inserting it"
+                        );
+                        commands.entity(entity).insert(value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Store the value of the resource in its history whenever it changes
+fn update_resource_history<R: Resource + Clone>(
+    resource: Option<Res<R>>,
+    mut history: ResMut<ResourceHistory<R>>,
+    tick_manager: Res<TickManager>,
+    rollback: Res<Rollback>,
+) {
+    let tick = tick_manager.tick_or_rollback_tick(rollback.as_ref());
+    match resource {
+        Some(resource) if resource.is_changed() => history.add(tick, Some((*resource).clone())),
+        None if history
+            .buffer
+            .back()
+            .is_some_and(|(_, value)| value.is_some()) =>
+        {
+            history.add(tick, None)
+        }
+        _ => {}
+    }
+}
+
+/// Remove the values that are older than the tick of the oldest predicted entity, since no rollback can go back
+/// further than that
+fn prune_resource_history<R: Resource + Clone>(
+    confirmed_query: Query<&Confirmed>,
+    mut history: ResMut<ResourceHistory<R>>,
+) {
+    if let Some(tick) = confirmed_query
+        .iter()
+        .filter(|confirmed| confirmed.predicted.is_some())
+        .map(|confirmed| confirmed.tick)
+        .min()
+    {
+        history.pop_until_tick(tick);
+    }
+}
+
+/// Restore the resource to its value at the rollback tick
+fn prepare_resource_rollback<R: Resource + Clone>(
+    mut commands: Commands,
+    resource: Option<ResMut<R>>,
+    mut history: ResMut<ResourceHistory<R>>,
+    rollback: Res<Rollback>,
+) {
+    let kind = std::any::type_name::<R>();
+    let Some(rollback_tick_plus_one) = rollback.get_rollback_tick() else {
+        error!("prepare_resource_rollback should only be called when we are in rollback");
+        return;
+    };
+    let rollback_tick = rollback_tick_plus_one - 1;
+    let state = history.pop_until_tick(rollback_tick);
+    // the values after the rollback tick will be written again during the rollback
+    history.truncate_after(rollback_tick);
+    match (state, resource) {
+        (Some(Some(value)), Some(mut resource)) => *resource = value,
+        (Some(Some(value)), None) => {
+            debug!(?kind, "Resource existed at the rollback tick, inserting it");
+            commands.insert_resource(value);
+        }
+        (Some(None), Some(_)) => {
+            debug!(
+                ?kind,
+                "Resource didn't exist at the rollback tick, removing it"
+            );
+            commands.remove_resource::<R>();
+        }
+        // no value was recorded before the rollback tick, keep the current value
+        _ => {}
+    }
+}
+
+/// Marks an entity that is spawned by the predicted systems, and that only exists on the client.
+///
+/// The tick at which the entity was spawned is tracked: if a rollback re-simulates that tick, the entity is
+/// despawned before the rollback, and gets spawned again during the re-simulation.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Reflect)]
+pub struct PredictedSpawn;
+
+/// The spawn ticks of the [`PredictedSpawn`] entities
+#[derive(Resource, Debug)]
+pub(crate) struct PredictedSpawnHistory {
+    spawn_ticks: ReadyBuffer<Tick, Entity>,
+}
+
+impl Default for PredictedSpawnHistory {
+    fn default() -> Self {
+        Self {
+            spawn_ticks: ReadyBuffer::new(),
+        }
+    }
+}
+
+/// Store the spawn tick of the new [`PredictedSpawn`] entities
+pub(crate) fn track_predicted_spawns(
+    tick_manager: Res<TickManager>,
+    rollback: Res<Rollback>,
+    mut history: ResMut<PredictedSpawnHistory>,
+    query: Query<Entity, Added<PredictedSpawn>>,
+) {
+    let tick = tick_manager.tick_or_rollback_tick(rollback.as_ref());
+    for entity in query.iter() {
+        history.spawn_ticks.push(tick, entity);
+    }
+}
+
+/// Remove the entities that were spawned before the tick of the oldest predicted entity,
+/// since no rollback can go back further than that
+pub(crate) fn prune_predicted_spawns(
+    confirmed_query: Query<&Confirmed>,
+    mut history: ResMut<PredictedSpawnHistory>,
+) {
+    if let Some(tick) = confirmed_query
+        .iter()
+        .filter(|confirmed| confirmed.predicted.is_some())
+        .map(|confirmed| confirmed.tick)
+        .min()
+    {
+        history.spawn_ticks.drain_until(&tick);
+    }
+}
+
+/// Despawn the entities that were spawned during the ticks that are going to be re-simulated
+pub(crate) fn despawn_predicted_spawns(
+    mut commands: Commands,
+    rollback: Res<Rollback>,
+    mut history: ResMut<PredictedSpawnHistory>,
+    query: Query<Option<&PredictionGroup>, With<PredictedSpawn>>,
+) {
+    let Some(rollback_tick_plus_one) = rollback.get_rollback_tick() else {
+        error!("despawn_predicted_spawns should only be called when we are in rollback");
+        return;
+    };
+    // the first re-simulated tick is `rollback_tick_plus_one`
+    for (tick, entity) in history.spawn_ticks.drain_after(&rollback_tick_plus_one) {
+        let Ok(group) = query.get(entity) else {
+            // the entity was already despawned
+            continue;
+        };
+        if !rollback.is_rollback_group(group) {
+            history.spawn_ticks.push(tick, entity);
+            continue;
+        }
+        debug!(
+            ?entity,
+            ?tick,
+            "despawning entity that was spawned after the rollback tick"
+        );
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    #[derive(Component, Clone, Debug, PartialEq)]
+    struct Cooldown(u16);
+
+    #[test]
+    fn test_local_rollback() {
+        let mut stepper = BevyStepper::default();
+        stepper.client_app.add_rollback::<Cooldown>();
+        let entity = stepper
+            .client_app
+            .world
+            .spawn((
+                Predicted {
+                    confirmed_entity: None,
+                },
+                Cooldown(10),
+            ))
+            .id();
+        stepper.frame_step();
+        assert!(stepper
+            .client_app
+            .world
+            .get::<PredictionHistory<Cooldown>>(entity)
+            .is_some());
+
+        stepper
+            .client_app
+            .world
+            .get_mut::<Cooldown>(entity)
+            .unwrap()
+            .0 = 5;
+        stepper.frame_step();
+        let tick = stepper.client_tick();
+        stepper
+            .client_app
+            .world
+            .get_mut::<Cooldown>(entity)
+            .unwrap()
+            .0 = 2;
+        stepper.frame_step();
+
+        // rollback to the tick: the value of the component at that tick is restored
+        stepper
+            .client_app
+            .world
+            .resource::<Rollback>()
+            .set_rollback_tick(tick + 1);
+        stepper
+            .client_app
+            .world
+            .run_system_once(prepare_local_rollback::<Cooldown>);
+        assert_eq!(
+            stepper.client_app.world.get::<Cooldown>(entity),
+            Some(&Cooldown(5))
+        );
+        // the values after the rollback tick were removed from the history
+        assert_eq!(
+            stepper
+                .client_app
+                .world
+                .get::<PredictionHistory<Cooldown>>(entity)
+                .unwrap()
+                .buffer
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_predicted_spawn_rollback() {
+        let mut stepper = BevyStepper::default();
+        let before = stepper.client_app.world.spawn(PredictedSpawn).id();
+        stepper.frame_step();
+        stepper.frame_step();
+        let after = stepper.client_app.world.spawn(PredictedSpawn).id();
+        stepper.frame_step();
+        let tick = stepper.client_tick();
+
+        // rollback to the tick where the second entity was spawned: it gets despawned,
+        // since it will be spawned again during the rollback
+        stepper
+            .client_app
+            .world
+            .resource::<Rollback>()
+            .set_rollback_tick(tick);
+        stepper
+            .client_app
+            .world
+            .run_system_once(despawn_predicted_spawns);
+        assert!(stepper.client_app.world.get_entity(before).is_some());
+        assert!(stepper.client_app.world.get_entity(after).is_none());
+    }
+
+    #[derive(Resource, Clone, Debug, PartialEq)]
+    struct Counter(u32);
+
+    #[test]
+    fn test_resource_history() {
+        let mut history = ResourceHistory::<Counter>::default();
+        assert_eq!(history.pop_until_tick(Tick(0)), None);
+        history.add(Tick(1), Some(Counter(1)));
+        history.add(Tick(3), Some(Counter(3)));
+        history.add(Tick(4), None);
+        assert_eq!(history.pop_until_tick(Tick(0)), None);
+        assert_eq!(history.pop_until_tick(Tick(2)), Some(Some(Counter(1))));
+        assert_eq!(history.pop_until_tick(Tick(4)), Some(None));
+        assert_eq!(history.buffer.len(), 1);
+        history.add(Tick(6), Some(Counter(6)));
+        history.truncate_after(Tick(5));
+        assert_eq!(history.buffer.len(), 1);
+    }
+
+    #[test]
+    fn test_resource_rollback() {
+        let mut stepper = BevyStepper::default();
+        stepper
+            .client_app
+            .insert_resource(Counter(0))
+            .add_resource_rollback::<Counter>();
+        stepper.frame_step();
+        stepper.client_app.world.resource_mut::<Counter>().0 = 5;
+        stepper.frame_step();
+        let tick = stepper.client_tick();
+        stepper.client_app.world.resource_mut::<Counter>().0 = 7;
+        stepper.frame_step();
+
+        // rollback to the tick: the value of the resource at that tick is restored
+        stepper
+            .client_app
+            .world
+            .resource::<Rollback>()
+            .set_rollback_tick(tick + 1);
+        stepper
+            .client_app
+            .world
+            .run_system_once(prepare_resource_rollback::<Counter>);
+        assert_eq!(stepper.client_app.world.resource::<Counter>(), &Counter(5));
+    }
+}
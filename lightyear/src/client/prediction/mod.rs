@@ -5,12 +5,15 @@ use std::fmt::Debug;
 pub(crate) mod correction;
 pub(crate) mod despawn;
 pub mod diagnostics;
+pub mod extrapolation;
+pub mod local_rollback;
 pub mod plugin;
 mod pre_prediction;
 pub mod predicted_history;
 pub mod prespawn;
 pub(crate) mod resource;
 pub(crate) mod rollback;
+pub mod smoothing;
 pub mod spawn;
 
 /// Marks an entity that is being predicted by the client
@@ -4,6 +4,7 @@ use bevy::prelude::{
 };
 use bevy::reflect::Reflect;
 use bevy::transform::TransformSystem;
+use bevy::utils::Duration;
 
 use crate::client::components::{ComponentSyncMode, Confirmed, SyncComponent};
 use crate::client::prediction::correction::{
@@ -11,7 +12,12 @@ use crate::client::prediction::correction::{
 };
 use crate::client::prediction::despawn::{
     despawn_confirmed, remove_component_for_despawn_predicted, remove_despawn_marker,
-    restore_components_if_despawn_rolled_back, PredictionDespawnMarker,
+    restore_components_if_despawn_rolled_back, restore_hidden_entities, PredictionDespawnMarker,
+    PredictionHidden,
+};
+use crate::client::prediction::local_rollback::{
+    despawn_predicted_spawns, prune_predicted_spawns, track_predicted_spawns, PredictedSpawn,
+    PredictedSpawnHistory,
 };
 use crate::client::prediction::predicted_history::{
     add_prespawned_component_history, update_prediction_history,
@@ -20,6 +26,11 @@ use crate::client::prediction::prespawn::{
     PreSpawnedPlayerObjectPlugin, PreSpawnedPlayerObjectSet,
 };
 use crate::client::prediction::resource::PredictionManager;
+use crate::client::prediction::smoothing::{
+    add_visual_error_offset, apply_visual_error_offset, is_visual_smoothing,
+    remove_visual_error_offset, store_pre_rollback_transform, PreRollbackTransforms,
+    VisualErrorOffset,
+};
 use crate::client::prediction::Predicted;
 use crate::client::sync::client_is_synced;
 use crate::prelude::{is_connected, is_host_server, PreSpawnedPlayerObject};
@@ -29,7 +40,7 @@ use super::pre_prediction::{PrePredictionPlugin, PrePredictionSet};
 use super::predicted_history::{add_component_history, apply_confirmed_update};
 use super::rollback::{
     check_rollback, increment_rollback_tick, prepare_rollback, prepare_rollback_prespawn,
-    run_rollback, Rollback, RollbackState,
+    run_rollback, Rollback, RollbackEndEvent, RollbackSnapEvent, RollbackStartEvent, RollbackState,
 };
 use super::spawn::spawn_predicted_entity;
 
@@ -49,6 +60,18 @@ pub struct PredictionConfig {
     /// (i.e. if the client is 10 ticks head and correction_ticks is 1.0, then the correction will be done over 10 ticks)
     // Number of ticks it will take to visually update the Predicted state to the new Corrected state
     pub correction_ticks_factor: f32,
+    /// If set, the [`Transform`](bevy::prelude::Transform) of the predicted entities does not visually snap
+    /// to the corrected value after a rollback: the error is smoothed out over this duration instead.
+    ///
+    /// See [`VisualErrorOffset`](crate::client::prediction::smoothing::VisualErrorOffset)
+    pub visual_smoothing: Option<Duration>,
+    /// Maximum number of ticks that a rollback can re-simulate.
+    ///
+    /// If a rollback would need more ticks (for example after a long packet stall), the predicted entities
+    /// are snapped to the confirmed state instead, and a
+    /// [`RollbackSnapEvent`](crate::client::prediction::rollback::RollbackSnapEvent) is emitted.
+    /// If `None`, there is no limit.
+    pub max_rollback_ticks: Option<u16>,
 }
 
 impl PredictionConfig {
@@ -63,6 +86,18 @@ impl PredictionConfig {
         self
     }
 
+    /// Update the maximum number of ticks that a rollback can re-simulate
+    pub fn with_max_rollback_ticks(mut self, ticks: u16) -> Self {
+        self.max_rollback_ticks = Some(ticks);
+        self
+    }
+
+    /// Smooth the visual corrections of the predicted transforms after a rollback over the given duration
+    pub fn with_visual_smoothing(mut self, duration: Duration) -> Self {
+        self.visual_smoothing = Some(duration);
+        self
+    }
+
     /// Update the amount of input delay (number of ticks)
     pub fn with_correction_ticks_factor(mut self, factor: f32) -> Self {
         self.correction_ticks_factor = factor;
@@ -200,12 +235,22 @@ impl Plugin for PredictionPlugin {
             .register_type::<Rollback>()
             .register_type::<RollbackState>()
             .register_type::<PredictionDespawnMarker>()
-            .register_type::<PredictionConfig>();
+            .register_type::<PredictionHidden>()
+            .register_type::<PredictedSpawn>()
+            .register_type::<PredictionConfig>()
+            .register_type::<VisualErrorOffset>();
 
         // RESOURCES
         app.init_resource::<PredictionManager>();
+        app.init_resource::<PreRollbackTransforms>();
+        app.init_resource::<PredictedSpawnHistory>();
         app.insert_resource(Rollback::new(RollbackState::Default));
 
+        // EVENTS
+        app.add_event::<RollbackStartEvent>()
+            .add_event::<RollbackEndEvent>()
+            .add_event::<RollbackSnapEvent>();
+
         // PreUpdate systems:
         // 1. Receive confirmed entities, add Confirmed and Predicted components
         // 2. (in prediction_systems) add ComponentHistory
@@ -245,11 +290,29 @@ impl Plugin for PredictionPlugin {
                     // NOTE: we put `despawn_confirmed` here because we only need to run it once per frame,
                     //  not at every fixed-update tick, since it only depends on server messages
                     despawn_confirmed,
+                    // restore the hidden entities whose despawn was not confirmed in time
+                    restore_hidden_entities.after(despawn_confirmed),
                 )
                     .in_set(PredictionSet::SpawnPrediction),
                 run_rollback.in_set(PredictionSet::Rollback),
+                prune_predicted_spawns.in_set(PredictionSet::CheckRollback),
+                despawn_predicted_spawns.in_set(PredictionSet::PrepareRollback),
             ),
         );
+        // smooth the visual snapping of the transforms after a rollback
+        app.add_systems(
+            PreUpdate,
+            (
+                remove_visual_error_offset.in_set(PredictionSet::RestoreVisualCorrection),
+                store_pre_rollback_transform
+                    .after(PredictionSet::CheckRollback)
+                    .before(PredictionSet::PrepareRollback)
+                    .run_if(is_in_rollback),
+                add_visual_error_offset.after(PredictionSet::Rollback),
+            )
+                .in_set(PredictionSet::All)
+                .run_if(is_visual_smoothing),
+        );
 
         // FixedUpdate systems
         // 1. Update client tick (don't run in rollback)
@@ -277,12 +340,19 @@ impl Plugin for PredictionPlugin {
             FixedPostUpdate,
             (
                 remove_despawn_marker.in_set(PredictionSet::EntityDespawn),
+                track_predicted_spawns.in_set(PredictionSet::SpawnHistory),
                 increment_rollback_tick.in_set(PredictionSet::IncrementRollbackTick),
             ),
         );
 
         // PostUpdate systems
         // 1. Visually interpolate the prediction to the corrected state
+        app.add_systems(
+            PostUpdate,
+            apply_visual_error_offset
+                .in_set(PredictionSet::VisualCorrection)
+                .run_if(is_visual_smoothing),
+        );
         app.configure_sets(
             PostUpdate,
             PredictionSet::VisualCorrection
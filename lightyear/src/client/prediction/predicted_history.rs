@@ -10,11 +10,11 @@ use tracing::{debug, trace};
 
 use crate::client::components::{ComponentSyncMode, Confirmed, SyncComponent};
 use crate::client::prediction::resource::PredictionManager;
-use crate::client::prediction::rollback::Rollback;
+use crate::client::prediction::rollback::{PredictionGroup, Rollback};
 use crate::client::prediction::Predicted;
 use crate::prelude::{ComponentRegistry, PreSpawnedPlayerObject, ShouldBePredicted, TickManager};
 use crate::shared::tick_manager::Tick;
-use crate::utils::ready_buffer::ReadyBuffer;
+use crate::utils::history_buffer::HistoryBuffer;
 
 /// Stores a past update for a component
 #[derive(Debug, PartialEq, Clone)]
@@ -26,66 +26,53 @@ pub(crate) enum ComponentState<C> {
 }
 
 /// To know if we need to do rollback, we need to compare the predicted entity's history with the server's state updates
-#[derive(Component, Debug)]
+#[derive(Component, Debug, PartialEq)]
 pub(crate) struct PredictionHistory<C: PartialEq> {
-    // TODO: add a max size for the buffer
     // We want to avoid using a SequenceBuffer for optimization (we don't want to store a copy of the component for each history tick)
-    // We can afford to use a ReadyBuffer because we will get server updates with monotonically increasing ticks
-    // therefore we can get rid of the old ticks before the server update
+    // We can afford to use a bounded buffer ordered by tick because the history is written with increasing ticks
+    // (the ticks re-simulated during a rollback overwrite the more recent values), and we get server updates
+    // with monotonically increasing ticks, therefore we can get rid of the old ticks before the server update
 
     // We will only store the history for the ticks where the component got updated
-    pub buffer: ReadyBuffer<Tick, ComponentState<C>>,
+    pub buffer: HistoryBuffer<ComponentState<C>>,
 }
 
 impl<C: PartialEq> Default for PredictionHistory<C> {
     fn default() -> Self {
         Self {
-            buffer: ReadyBuffer::new(),
+            buffer: HistoryBuffer::default(),
         }
     }
 }
 
-impl<C: SyncComponent> PartialEq for PredictionHistory<C> {
-    fn eq(&self, other: &Self) -> bool {
-        let mut self_history: Vec<_> = self.buffer.heap.iter().collect();
-        let mut other_history: Vec<_> = other.buffer.heap.iter().collect();
-        self_history.sort_by_key(|item| item.key);
-        other_history.sort_by_key(|item| item.key);
-        self_history.eq(&other_history)
-    }
-}
-
-impl<C: SyncComponent> PredictionHistory<C> {
+impl<C: Component + Clone + PartialEq> PredictionHistory<C> {
     /// Reset the history for this component
     pub(crate) fn clear(&mut self) {
-        self.buffer = ReadyBuffer::new();
+        self.buffer.clear();
     }
 
     /// Add to the buffer that we received an update for the component at the given tick
     pub(crate) fn add_update(&mut self, tick: Tick, component: C) {
-        self.buffer.push(tick, ComponentState::Updated(component));
+        self.buffer.add(tick, ComponentState::Updated(component));
     }
 
     /// Add to the buffer that the component got removed at the given tick
     pub(crate) fn add_remove(&mut self, tick: Tick) {
-        self.buffer.push(tick, ComponentState::Removed);
+        self.buffer.add(tick, ComponentState::Removed);
     }
 
-    // TODO: check if this logic is necessary/correct?
     /// Clear the history of values strictly older than the specified tick,
     /// and return the most recent value that is older or equal to the specified tick.
-    /// NOTE: That value is written back into the buffer
+    /// NOTE: That value is kept in the buffer
     ///
     /// CAREFUL:
     /// the component history will only contain the ticks where the component got updated, and otherwise
     /// contains gaps. Therefore, we need to always leave a value in the history buffer so that we can
     /// get the values for the future ticks
     pub(crate) fn pop_until_tick(&mut self, tick: Tick) -> Option<ComponentState<C>> {
-        self.buffer.pop_until(&tick).map(|(tick, state)| {
-            // TODO: this clone is pretty bad and avoidable. Probably switch to a sequence buffer?
-            self.buffer.push(tick, state.clone());
-            state
-        })
+        self.buffer
+            .pop_until_tick(tick)
+            .map(|(_, state)| state.clone())
     }
 }
 
@@ -98,7 +85,7 @@ pub(crate) fn add_component_history<C: SyncComponent>(
     mut commands: Commands,
     tick_manager: Res<TickManager>,
     predicted_entities: Query<
-        (Entity, Option<Ref<C>>),
+        (Entity, Ref<Predicted>, Option<Ref<C>>),
         (
             Without<PredictionHistory<C>>,
             // for all types of predicted entities, we want to add the component history to enable them to be rolled-back
@@ -111,7 +98,9 @@ pub(crate) fn add_component_history<C: SyncComponent>(
     let tick = tick_manager.tick();
     for (confirmed_entity, confirmed, confirmed_component) in confirmed_entities.iter() {
         if let Some(p) = confirmed.predicted {
-            if let Ok((predicted_entity, predicted_component)) = predicted_entities.get(p) {
+            if let Ok((predicted_entity, predicted, predicted_component)) =
+                predicted_entities.get(p)
+            {
                 // if component got added on predicted side, add history
                 add_history::<C>(
                     component_registry.as_ref(),
@@ -121,11 +110,11 @@ pub(crate) fn add_component_history<C: SyncComponent>(
                     &mut commands,
                 );
 
-                // if component got added on confirmed side
+                // if component got added on confirmed side (or the confirmed entity just started being predicted)
                 // - full: sync component and add history
                 // - simple/once: sync component
                 if let Some(confirmed_component) = confirmed_component {
-                    if confirmed_component.is_added() {
+                    if confirmed_component.is_added() || predicted.is_added() {
                         trace!(?kind, "Component added on confirmed side");
                         // safety: we know the entity exists
                         let mut predicted_entity_mut =
@@ -220,26 +209,31 @@ fn add_history<C: SyncComponent>(
 }
 
 /// If ComponentSyncMode::Full, we store every update on the predicted entity in the PredictionHistory
-pub(crate) fn update_prediction_history<T: SyncComponent>(
-    mut query: Query<(Ref<T>, &mut PredictionHistory<T>)>,
+pub(crate) fn update_prediction_history<T: Component + Clone + PartialEq>(
+    mut query: Query<(Ref<T>, &mut PredictionHistory<T>, Option<&PredictionGroup>)>,
     mut removed_component: RemovedComponents<T>,
-    mut removed_entities: Query<&mut PredictionHistory<T>, Without<T>>,
+    mut removed_entities: Query<(&mut PredictionHistory<T>, Option<&PredictionGroup>), Without<T>>,
     tick_manager: Res<TickManager>,
     rollback: Res<Rollback>,
 ) {
     // tick for which we will record the history (either the current client tick or the current rollback tick)
     let tick = tick_manager.tick_or_rollback_tick(rollback.as_ref());
+    // during a partial rollback, the history of the entities that are not rolled back is kept as is
+    let is_rollback = rollback.is_rollback();
+    let skip = |group: Option<&PredictionGroup>| is_rollback && !rollback.is_rollback_group(group);
 
     // update history if the predicted component changed
-    for (component, mut history) in query.iter_mut() {
+    for (component, mut history, group) in query.iter_mut() {
         // change detection works even when running the schedule for rollback
-        if component.is_changed() {
+        if component.is_changed() && !skip(group) {
             history.add_update(tick, component.deref().clone());
         }
     }
     for entity in removed_component.read() {
-        if let Ok(mut history) = removed_entities.get_mut(entity) {
-            history.add_remove(tick);
+        if let Ok((mut history, group)) = removed_entities.get_mut(entity) {
+            if !skip(group) {
+                history.add_remove(tick);
+            }
         }
     }
 }
@@ -294,7 +288,6 @@ mod tests {
     use crate::prelude::client::RollbackState;
     use crate::tests::protocol::*;
     use crate::tests::stepper::{BevyStepper, Step};
-    use crate::utils::ready_buffer::ItemWithReadyKey;
     use bevy::ecs::system::RunSystemOnce;
 
     /// Test adding and removing updates to the component history
@@ -313,8 +306,11 @@ mod tests {
             Some(ComponentState::Updated(Component1(2.0)))
         );
         // check that we cleared older ticks, and that the most recent value still remains
+        assert_eq!(
+            component_history.buffer.front(),
+            Some(&(Tick(2), ComponentState::Updated(Component1(2.0))))
+        );
         assert_eq!(component_history.buffer.len(), 1);
-        assert!(component_history.buffer.has_item(&Tick(2)));
 
         // check when we try to access a value in-between ticks
         component_history.add_update(Tick(4), Component1(4.0));
@@ -324,15 +320,15 @@ mod tests {
             Some(ComponentState::Updated(Component1(2.0)))
         );
         assert_eq!(component_history.buffer.len(), 2);
-        // check that the most recent value got added back to the buffer at the popped tick
+        // check that the most recent value was kept in the buffer at the popped tick
         assert_eq!(
-            component_history.buffer.heap.peek(),
-            Some(&ItemWithReadyKey {
-                key: Tick(2),
-                item: ComponentState::Updated(Component1(2.0))
-            })
+            component_history.buffer.front(),
+            Some(&(Tick(2), ComponentState::Updated(Component1(2.0))))
+        );
+        assert_eq!(
+            component_history.buffer.back().map(|(tick, _)| *tick),
+            Some(Tick(4))
         );
-        assert!(component_history.buffer.has_item(&Tick(4)));
 
         // check that nothing happens when we try to access a value before any ticks
         assert_eq!(component_history.pop_until_tick(Tick(0)), None);
@@ -341,6 +337,14 @@ mod tests {
         component_history.add_remove(Tick(5));
         assert_eq!(component_history.buffer.len(), 3);
 
+        // check that the ticks that are simulated again overwrite the more recent values
+        component_history.add_update(Tick(4), Component1(-4.0));
+        assert_eq!(
+            component_history.buffer.back(),
+            Some(&(Tick(4), ComponentState::Updated(Component1(-4.0))))
+        );
+        assert_eq!(component_history.buffer.len(), 2);
+
         component_history.clear();
         assert_eq!(component_history.buffer.len(), 0);
     }
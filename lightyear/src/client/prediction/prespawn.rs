@@ -112,6 +112,7 @@ impl PreSpawnedPlayerObjectPlugin {
                     let mut pre_spawned_query = world
                 .query_filtered::<(EntityRef, Ref<PreSpawnedPlayerObject>), (Without<Replicated>, Without<Confirmed>)>();
                     // let mut predicted_entities = vec![];
+                    let mut duplicates = vec![];
                     for (entity_ref, prespawn) in pre_spawned_query.iter(world) {
                         // we only care about newly-added PreSpawnedPlayerObject components
                         // TODO shouldn't we use an Added<PreSpawnedPlayerObject> query filter?
@@ -206,7 +207,12 @@ impl PreSpawnedPlayerObjectPlugin {
                         // - client 1 presses input and spawns a prespawned-object
                         // - the pre-spawned object AND the input are replicated to player 2
                         // - player 2 receives BOTH the replicated object and the input, and spawns a duplicate object
-
+                        // The server entity already has a Predicted counterpart, so we despawn the duplicate
+                        if manager.prespawn_server_hash_to_entity.remove(&hash).is_some() {
+                            debug!(?entity, ?hash, "the server entity was already received, despawning the duplicate pre-spawned entity");
+                            duplicates.push(entity);
+                            continue;
+                        }
 
                         // TODO: what to do in multiple entities share the same hash?
                         //  just match a random one of them? or should the user have a more precise hash?
@@ -220,6 +226,11 @@ impl PreSpawnedPlayerObjectPlugin {
                         manager.prespawn_tick_to_hash.push(tick, hash);
                         // predicted_entities.push(entity);
                     }
+                    for entity in duplicates {
+                        if let Some(entity_mut) = world.get_entity_mut(entity) {
+                            entity_mut.despawn_recursive();
+                        }
+                    }
 
                     // NOTE: originally I wanted to remove PreSpawnedPlayerObject here because I wanted to call `compute_hash`
                     // at PostUpdate, which would run twice (at the end of FixedUpdate and at PostUpdate)
@@ -265,6 +276,18 @@ impl PreSpawnedPlayerObjectPlugin {
                 manager.prespawn_hash_to_entities.remove(&server_hash)
             else {
                 debug!(?server_hash, "Received a PreSpawnedPlayerObject entity from the server with a hash that does not match any client entity");
+                // remember the hash in case the client pre-spawns the entity later
+                if let Some(confirmed_tick) = connection
+                    .replication_receiver
+                    .get_confirmed_tick(confirmed_entity)
+                {
+                    manager
+                        .prespawn_server_hash_to_entity
+                        .insert(server_hash, confirmed_entity);
+                    manager
+                        .prespawn_server_tick_to_hash
+                        .push(confirmed_tick, server_hash);
+                }
                 // remove the PreSpawnedPlayerObject so that the entity can be normal-predicted
                 commands
                     .entity(confirmed_entity)
@@ -373,6 +396,10 @@ impl PreSpawnedPlayerObjectPlugin {
                     }
                 });
         }
+        // forget the unmatched server entities that are too old to be pre-spawned by the client
+        for (_, hash) in manager.prespawn_server_tick_to_hash.drain_until(&past_tick) {
+            manager.prespawn_server_hash_to_entity.remove(&hash);
+        }
     }
 }
 
@@ -514,7 +541,7 @@ impl PreSpawnedPlayerObject {
 #[cfg(test)]
 mod tests {
     use crate::client::prediction::predicted_history::{ComponentState, PredictionHistory};
-    use bevy::prelude::Entity;
+    use bevy::prelude::{default, Entity};
     use hashbrown::HashMap;
 
     use crate::client::prediction::resource::PredictionManager;
@@ -566,12 +593,68 @@ mod tests {
                 .get::<PredictionHistory<Component1>>()
                 .unwrap()
                 .buffer
-                .heap
-                .peek(),
-            Some(&ItemWithReadyKey {
-                key: current_tick,
-                item: ComponentState::Updated(Component1(1.0)),
-            })
+                .front(),
+            Some(&(current_tick, ComponentState::Updated(Component1(1.0))))
         );
     }
+
+    /// The server entity is received before the client pre-spawns the entity:
+    /// the client entity is a duplicate of the Predicted entity and should be despawned
+    #[test]
+    fn test_prespawn_after_server_entity() {
+        let mut stepper = BevyStepper::default();
+
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((
+                Component1(1.0),
+                PreSpawnedPlayerObject::new(1),
+                server::Replicate {
+                    sync: server::SyncTarget {
+                        prediction: NetworkTarget::All,
+                        ..default()
+                    },
+                    ..default()
+                },
+            ))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        let confirmed_entity = *stepper
+            .client_app
+            .world
+            .resource::<client::ConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .unwrap();
+        let predicted_entity = stepper
+            .client_app
+            .world
+            .get::<client::Confirmed>(confirmed_entity)
+            .unwrap()
+            .predicted
+            .unwrap();
+
+        // the client pre-spawns the same entity
+        let client_entity = stepper
+            .client_app
+            .world
+            .spawn((Component1(1.0), PreSpawnedPlayerObject::new(1)))
+            .id();
+        stepper.frame_step();
+        assert!(stepper.client_app.world.get_entity(client_entity).is_none());
+        assert!(stepper
+            .client_app
+            .world
+            .get_entity(predicted_entity)
+            .is_some());
+        assert!(stepper
+            .client_app
+            .world
+            .resource::<PredictionManager>()
+            .prespawn_server_hash_to_entity
+            .is_empty());
+    }
 }
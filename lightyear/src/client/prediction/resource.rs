@@ -28,6 +28,13 @@ pub(crate) struct PredictionManager {
     pub(crate) prespawn_hash_to_entities: EntityHashMap<u64, Vec<Entity>>,
     /// Store the spawn tick of the entity, as well as the corresponding hash
     pub(crate) prespawn_tick_to_hash: ReadyBuffer<Tick, u64>,
+    /// Map from the hash of a server PrespawnedPlayerObject that did not match any local entity to the
+    /// corresponding Confirmed entity.
+    /// The client could still pre-spawn the entity later (for example when predicting other players),
+    /// in which case it is a duplicate of the entity already predicted from the server entity.
+    pub(crate) prespawn_server_hash_to_entity: EntityHashMap<u64, Entity>,
+    /// Store the tick at which the unmatched server entities were received, as well as the corresponding hash
+    pub(crate) prespawn_server_tick_to_hash: ReadyBuffer<Tick, u64>,
 }
 
 // SAFETY: We never use UnsafeCell to mutate the predicted_entity_map, so it's safe to send and sync
@@ -40,6 +47,8 @@ impl PredictionManager {
             predicted_entity_map: Default::default(),
             prespawn_hash_to_entities: Default::default(),
             prespawn_tick_to_hash: Default::default(),
+            prespawn_server_hash_to_entity: Default::default(),
+            prespawn_server_tick_to_hash: Default::default(),
         }
     }
 
@@ -5,10 +5,11 @@ use bevy::app::FixedMain;
 use bevy::ecs::entity::EntityHashSet;
 use bevy::ecs::reflect::ReflectResource;
 use bevy::prelude::{
-    Commands, DespawnRecursiveExt, DetectChanges, Entity, Query, Ref, Res, ResMut, Resource, With,
-    Without, World,
+    Commands, Component, DespawnRecursiveExt, DetectChanges, Entity, Event, Query, Ref, Res,
+    ResMut, Resource, With, Without, World,
 };
 use bevy::reflect::Reflect;
+use bevy::utils::HashSet;
 use parking_lot::RwLock;
 use tracing::{debug, error, trace, trace_span};
 
@@ -19,7 +20,9 @@ use crate::client::prediction::correction::Correction;
 use crate::client::prediction::diagnostics::PredictionMetrics;
 use crate::client::prediction::predicted_history::ComponentState;
 use crate::client::prediction::resource::PredictionManager;
+use crate::client::prediction::smoothing::PreRollbackTransforms;
 use crate::prelude::{ComponentRegistry, PreSpawnedPlayerObject, Tick, TickManager};
+use crate::protocol::component::ComponentKind;
 
 use super::predicted_history::PredictionHistory;
 use super::Predicted;
@@ -33,9 +36,97 @@ pub struct Rollback {
     /// We use a RwLock because we want to be able to update this value from multiple systems
     /// in parallel.
     pub state: RwLock<RollbackState>,
-    // pub rollback_groups: EntityHashMap<ReplicationGroupId, RollbackState>,
+    #[reflect(ignore)]
+    /// The prediction groups that are being rolled back.
+    /// `None` means that all the predicted entities are rolled back.
+    groups: RwLock<Option<HashSet<PredictionGroup>>>,
+    #[reflect(ignore)]
+    /// What triggered the current rollback
+    cause: RwLock<Option<RollbackCause>>,
+    /// If true, the [`PredictionGroup`]s are ignored and every rollback rolls back all the predicted entities
+    groups_disabled: bool,
+}
+
+/// What triggered a rollback
+#[derive(Debug, Clone, PartialEq)]
+pub enum RollbackCause {
+    /// The state of a component on the confirmed entity did not match the predicted history
+    Mismatch {
+        /// The confirmed entity that received the server update
+        confirmed_entity: Entity,
+        /// The component that was mispredicted
+        component: ComponentKind,
+    },
+    /// The rollback was requested without a mismatch
+    Other,
+}
+
+/// Event emitted right before a rollback starts.
+///
+/// The ticks from `from_tick` to `to_tick` (included) will be simulated again.
+/// Systems can use it to suppress side effects (sounds, particles, etc.) during the re-simulation,
+/// or to keep track of how often rollbacks happen.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct RollbackStartEvent {
+    /// First tick that is re-simulated
+    pub from_tick: Tick,
+    /// Last tick that is re-simulated (the current tick)
+    pub to_tick: Tick,
+    /// What triggered the rollback
+    pub cause: RollbackCause,
+}
+
+/// Event emitted instead of [`RollbackStartEvent`] when the rollback would re-simulate more than
+/// [`PredictionConfig::max_rollback_ticks`](crate::client::prediction::plugin::PredictionConfig::max_rollback_ticks) ticks.
+///
+/// The predicted entities are snapped to the confirmed state without being re-simulated, so the game
+/// can hide the discontinuity (fade, teleport effect, etc.)
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct RollbackSnapEvent {
+    /// First tick that would have been re-simulated
+    pub from_tick: Tick,
+    /// Last tick that would have been re-simulated (the current tick)
+    pub to_tick: Tick,
+    /// What triggered the rollback
+    pub cause: RollbackCause,
 }
 
+/// Event emitted once a rollback is over and all the ticks have been simulated again
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct RollbackEndEvent {
+    /// First tick that was re-simulated
+    pub from_tick: Tick,
+    /// Last tick that was re-simulated (the current tick)
+    pub to_tick: Tick,
+}
+
+/// Group of predicted entities that are rolled back together.
+///
+/// By default, a mismatch between the predicted and confirmed state of any entity rolls back all the
+/// predicted entities. Entities that are independent from each other can be put in different groups:
+/// a mismatch on an entity of a group will only roll back the entities of that group.
+/// Predicted entities without a [`PredictionGroup`] are always rolled back.
+///
+/// The systems that run during the rollback should skip the entities that are not rolled back, using
+/// [`Rollback::is_rollback_group`]; otherwise these entities would be simulated again for the rollback ticks.
+///
+/// The physics engines step the whole physics world at once, so they cannot skip the entities of the other
+/// groups. When one of the physics integrations (`XpbdReplicationPlugin` or `RapierReplicationPlugin`) is added,
+/// the groups are ignored: every rollback rolls back all the predicted entities.
+///
+/// ```rust,ignore
+/// fn movement(rollback: Res<Rollback>, mut query: Query<(&mut Position, Option<&PredictionGroup>)>) {
+///     for (mut position, group) in query.iter_mut() {
+///         if rollback.is_rollback() && !rollback.is_rollback_group(group) {
+///             continue;
+///         }
+///         // ...
+///     }
+/// }
+/// ```
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub struct PredictionGroup(pub u64);
+
 /// Resource that will track whether we should do rollback or not
 /// (We have this as a resource because if any predicted entity needs to be rolled-back; we should roll back all predicted entities)
 #[derive(Debug, Default, Reflect)]
@@ -56,9 +147,20 @@ impl Rollback {
     pub(crate) fn new(state: RollbackState) -> Self {
         Self {
             state: RwLock::new(state),
+            groups: RwLock::new(None),
+            cause: RwLock::new(None),
+            groups_disabled: false,
         }
     }
 
+    /// Ignore the [`PredictionGroup`]s: every rollback will roll back all the predicted entities.
+    ///
+    /// This is used when the rollback re-simulates systems that cannot be restricted to some of the
+    /// predicted entities, such as a physics step.
+    pub(crate) fn disable_prediction_groups(&mut self) {
+        self.groups_disabled = true;
+    }
+
     /// Returns true if we are currently in a rollback state
     pub fn is_rollback(&self) -> bool {
         match *self.state.read().deref() {
@@ -88,11 +190,61 @@ impl Rollback {
     /// Set the rollback state back to non-rollback
     pub(crate) fn set_non_rollback(&self) {
         *self.state.write().deref_mut() = RollbackState::Default;
+        *self.cause.write().deref_mut() = None;
+    }
+
+    /// Returns what triggered the current rollback
+    pub(crate) fn cause(&self) -> RollbackCause {
+        self.cause.read().clone().unwrap_or(RollbackCause::Other)
     }
 
     /// Set the rollback state to `ShouldRollback` with the given tick
     pub(crate) fn set_rollback_tick(&self, tick: Tick) {
         *self.state.write().deref_mut() = RollbackState::ShouldRollback { current_tick: tick };
+        *self.groups.write().deref_mut() = None;
+        *self.cause.write().deref_mut() = None;
+    }
+
+    /// Roll back the entities of the given prediction group (all entities if `None`), starting from the given tick.
+    ///
+    /// If we are already in rollback, the group is added to the groups that are rolled back.
+    pub(crate) fn add_rollback_group(
+        &self,
+        group: Option<&PredictionGroup>,
+        tick: Tick,
+        cause: RollbackCause,
+    ) {
+        let group = group.filter(|_| !self.groups_disabled);
+        let mut state = self.state.write();
+        let mut groups = self.groups.write();
+        let is_rollback = matches!(*state, RollbackState::ShouldRollback { .. });
+        if !is_rollback {
+            *state = RollbackState::ShouldRollback { current_tick: tick };
+            *groups = group.map(|group| HashSet::from([*group]));
+            *self.cause.write() = Some(cause);
+            return;
+        }
+        match (groups.as_mut(), group) {
+            // we are already rolling back all entities
+            (None, _) => {}
+            (Some(groups), Some(group)) => {
+                groups.insert(*group);
+            }
+            (Some(_), None) => *groups = None,
+        }
+    }
+
+    /// Returns true if the entities of the given prediction group are being rolled back.
+    ///
+    /// Entities without a [`PredictionGroup`] are rolled back in every rollback.
+    pub fn is_rollback_group(&self, group: Option<&PredictionGroup>) -> bool {
+        if !self.is_rollback() {
+            return false;
+        }
+        match (self.groups.read().as_ref(), group) {
+            (Some(groups), Some(group)) => groups.contains(group),
+            _ => true,
+        }
     }
 }
 
@@ -107,7 +259,10 @@ pub(crate) fn check_rollback<C: SyncComponent>(
     tick_manager: Res<TickManager>,
     connection: Res<ConnectionManager>,
     // We also snap the value of the component to the server state if we are in rollback
-    mut predicted_query: Query<&mut PredictionHistory<C>, (With<Predicted>, Without<Confirmed>)>,
+    mut predicted_query: Query<
+        (&mut PredictionHistory<C>, Option<&PredictionGroup>),
+        (With<Predicted>, Without<Confirmed>),
+    >,
     // We use Option<> because the predicted component could have been removed while it still exists in Confirmed
     confirmed_query: Query<(Entity, Option<&C>, Ref<Confirmed>)>,
     rollback: Res<Rollback>,
@@ -147,7 +302,7 @@ pub(crate) fn check_rollback<C: SyncComponent>(
         let Some(p) = confirmed.predicted else {
             continue;
         };
-        let Ok(mut predicted_history) = predicted_query.get_mut(p) else {
+        let Ok((mut predicted_history, group)) = predicted_query.get_mut(p) else {
             debug!(
                 "Predicted entity {:?} was not found when checking rollback for {:?}",
                 confirmed.predicted,
@@ -171,9 +326,9 @@ pub(crate) fn check_rollback<C: SyncComponent>(
             continue;
         }
 
-        // 3.a We are still not sure if we should do rollback. Compare history against confirmed
+        // 3.a We are still not sure if we should do rollback for the entity's group. Compare history against confirmed
         // We rollback if there's no history (newly added predicted entity, or if there is a mismatch)
-        if !rollback.is_rollback() {
+        if !rollback.is_rollback_group(group) {
             let history_value = predicted_history.pop_until_tick(tick);
             let predicted_exist = history_value.is_some();
             let confirmed_exist = confirmed_component.is_some();
@@ -199,7 +354,14 @@ pub(crate) fn check_rollback<C: SyncComponent>(
                    );
                 // we already rolled-back the state for the entity's latest_tick
                 // after this we will start right away with a physics update, so we need to start taking the inputs from the next tick
-                rollback.set_rollback_tick(tick + 1);
+                rollback.add_rollback_group(
+                    group,
+                    tick + 1,
+                    RollbackCause::Mismatch {
+                        confirmed_entity,
+                        component: ComponentKind::of::<C>(),
+                    },
+                );
             }
         } else {
             // 3.b We already know we should do rollback (because of another entity/component), start the rollback
@@ -228,6 +390,7 @@ pub(crate) fn prepare_rollback<C: SyncComponent>(
             Option<&mut C>,
             &mut PredictionHistory<C>,
             Option<&mut Correction<C>>,
+            Option<&PredictionGroup>,
         ),
         (
             With<Predicted>,
@@ -261,8 +424,13 @@ pub(crate) fn prepare_rollback<C: SyncComponent>(
         };
 
         // 1. Get the predicted entity, and it's history
-        let Ok((predicted_entity, predicted_component, mut predicted_history, mut correction)) =
-            predicted_query.get_mut(p)
+        let Ok((
+            predicted_entity,
+            predicted_component,
+            mut predicted_history,
+            mut correction,
+            group,
+        )) = predicted_query.get_mut(p)
         else {
             debug!(
                 "Predicted entity {:?} was not found when preparing rollback for {:?}",
@@ -271,6 +439,10 @@ pub(crate) fn prepare_rollback<C: SyncComponent>(
             );
             continue;
         };
+        // the entity is not affected by the rollback
+        if !rollback.is_rollback_group(group) {
+            continue;
+        }
 
         // 2. we need to clear the history so we can write a new one
         predicted_history.clear();
@@ -283,16 +455,12 @@ pub(crate) fn prepare_rollback<C: SyncComponent>(
         match confirmed_component {
             // confirm does not exist, remove on predicted
             None => {
-                predicted_history
-                    .buffer
-                    .push(rollback_tick, ComponentState::Removed);
+                predicted_history.add_remove(rollback_tick);
                 entity_mut.remove::<C>();
             }
             // confirm exist, update or insert on predicted
             Some(c) => {
-                predicted_history
-                    .buffer
-                    .push(rollback_tick, ComponentState::Updated(c.clone()));
+                predicted_history.add_update(rollback_tick, c.clone());
                 match predicted_component {
                     None => {
                         debug!("Re-adding deleted Full component to predicted");
@@ -372,6 +540,7 @@ pub(crate) fn prepare_rollback_prespawn<C: SyncComponent>(
             Option<&mut C>,
             &mut PredictionHistory<C>,
             Option<&mut Correction<C>>,
+            Option<&PredictionGroup>,
         ),
         (
             With<PreSpawnedPlayerObject>,
@@ -417,10 +586,10 @@ pub(crate) fn prepare_rollback_prespawn<C: SyncComponent>(
         }
     });
 
-    for (prespawned_entity, predicted_component, mut predicted_history, mut correction) in
+    for (prespawned_entity, predicted_component, mut predicted_history, mut correction, group) in
         predicted_query.iter_mut()
     {
-        if entities_to_despawn.contains(&prespawned_entity) {
+        if entities_to_despawn.contains(&prespawned_entity) || !rollback.is_rollback_group(group) {
             continue;
         }
 
@@ -504,10 +673,43 @@ pub(crate) fn run_rollback(world: &mut World) {
     // (we set `current_rollback_tick` to `confirmed + 1` so that on the FixedUpdate rollback run, we fetch the input for
     // `confirmed + 1`
     let num_rollback_ticks = current_tick + 1 - current_rollback_tick;
+    let cause = rollback.cause();
+
+    // if the rollback is too long, we snap to the confirmed state instead of re-simulating all the ticks
+    let max_rollback_ticks = world
+        .resource::<ClientConfig>()
+        .prediction
+        .max_rollback_ticks;
+    // compare in a wider type: a limit above `i16::MAX` would wrap to a negative value
+    if max_rollback_ticks.is_some_and(|max| i32::from(num_rollback_ticks) > i32::from(max)) {
+        debug!(
+            ?num_rollback_ticks,
+            "Rollback between {:?} and {:?} is too long, snapping to the confirmed state",
+            current_rollback_tick,
+            current_tick
+        );
+        // the snap should not be smoothed visually
+        if let Some(mut transforms) = world.get_resource_mut::<PreRollbackTransforms>() {
+            transforms.clear();
+        }
+        world.resource::<Rollback>().set_non_rollback();
+        world.send_event(RollbackSnapEvent {
+            from_tick: current_rollback_tick,
+            to_tick: current_tick,
+            cause,
+        });
+        return;
+    }
+
     debug!(
         "Rollback between {:?} and {:?}",
         current_rollback_tick, current_tick
     );
+    world.send_event(RollbackStartEvent {
+        from_tick: current_rollback_tick,
+        to_tick: current_tick,
+        cause,
+    });
 
     // run the physics fixed update schedule (which should contain ALL predicted/rollback components)
     for i in 0..num_rollback_ticks {
@@ -525,6 +727,10 @@ pub(crate) fn run_rollback(world: &mut World) {
     // revert the state of Rollback for the next frame
     let rollback = world.get_resource_mut::<Rollback>().unwrap();
     rollback.set_non_rollback();
+    world.send_event(RollbackEndEvent {
+        from_tick: current_rollback_tick,
+        to_tick: current_tick,
+    });
 }
 
 pub(crate) fn increment_rollback_tick(rollback: Res<Rollback>) {
@@ -715,6 +921,119 @@ mod unit_tests {
             .resource::<Rollback>()
             .is_rollback());
     }
+
+    #[test]
+    fn test_rollback_groups() {
+        let rollback = Rollback::default();
+        assert!(!rollback.is_rollback_group(None));
+
+        // partial rollback
+        rollback.add_rollback_group(Some(&PredictionGroup(1)), Tick(3), RollbackCause::Other);
+        assert_eq!(rollback.get_rollback_tick(), Some(Tick(3)));
+        assert!(rollback.is_rollback_group(Some(&PredictionGroup(1))));
+        assert!(!rollback.is_rollback_group(Some(&PredictionGroup(2))));
+        // entities without a group are always rolled back
+        assert!(rollback.is_rollback_group(None));
+
+        rollback.add_rollback_group(Some(&PredictionGroup(2)), Tick(3), RollbackCause::Other);
+        assert!(rollback.is_rollback_group(Some(&PredictionGroup(2))));
+        assert!(!rollback.is_rollback_group(Some(&PredictionGroup(3))));
+
+        // a mismatch on an entity without a group rolls back everything
+        rollback.add_rollback_group(None, Tick(3), RollbackCause::Other);
+        assert!(rollback.is_rollback_group(Some(&PredictionGroup(3))));
+
+        rollback.set_non_rollback();
+        assert!(!rollback.is_rollback_group(Some(&PredictionGroup(1))));
+
+        // with the groups disabled, a mismatch in any group rolls back everything
+        let mut rollback = Rollback::default();
+        rollback.disable_prediction_groups();
+        rollback.add_rollback_group(Some(&PredictionGroup(1)), Tick(3), RollbackCause::Other);
+        assert!(rollback.is_rollback_group(Some(&PredictionGroup(2))));
+    }
+
+    /// Check that a mismatch on an entity only rolls back the entities of its prediction group
+    #[test]
+    fn test_partial_rollback() {
+        let mut stepper = BevyStepper::default();
+
+        // add two independent predicted/confirmed entities
+        let mut spawn = |group: u64| {
+            let confirmed = stepper
+                .client_app
+                .world
+                .spawn((Confirmed::default(), Component1(1.0)))
+                .id();
+            let predicted = stepper
+                .client_app
+                .world
+                .spawn((
+                    Predicted {
+                        confirmed_entity: Some(confirmed),
+                    },
+                    PredictionGroup(group),
+                ))
+                .id();
+            stepper
+                .client_app
+                .world
+                .get_mut::<Confirmed>(confirmed)
+                .unwrap()
+                .predicted = Some(predicted);
+            (confirmed, predicted)
+        };
+        let (confirmed_a, predicted_a) = spawn(1);
+        let (confirmed_b, predicted_b) = spawn(2);
+        stepper.frame_step();
+
+        // the confirmed state of A does not match the prediction, but B does
+        let tick = stepper.client_tick();
+        stepper
+            .client_app
+            .world
+            .get_mut::<Component1>(confirmed_a)
+            .unwrap()
+            .0 = 2.0;
+        for (confirmed, predicted) in [(confirmed_a, predicted_a), (confirmed_b, predicted_b)] {
+            stepper
+                .client_app
+                .world
+                .get_mut::<PredictionHistory<Component1>>(predicted)
+                .unwrap()
+                .add_update(tick, Component1(1.0));
+            received_confirmed_update(&mut stepper, confirmed, tick);
+        }
+        stepper
+            .client_app
+            .world
+            .run_system_once(check_rollback::<Component1>);
+        let rollback = stepper.client_app.world.resource::<Rollback>();
+        assert!(rollback.is_rollback_group(Some(&PredictionGroup(1))));
+        assert!(!rollback.is_rollback_group(Some(&PredictionGroup(2))));
+
+        // only A is reset to the confirmed state
+        for predicted in [predicted_a, predicted_b] {
+            stepper
+                .client_app
+                .world
+                .get_mut::<Component1>(predicted)
+                .unwrap()
+                .0 = 5.0;
+        }
+        stepper
+            .client_app
+            .world
+            .run_system_once(prepare_rollback::<Component1>);
+        assert_eq!(
+            stepper.client_app.world.get::<Component1>(predicted_a),
+            Some(&Component1(2.0))
+        );
+        assert_eq!(
+            stepper.client_app.world.get::<Component1>(predicted_b),
+            Some(&Component1(5.0))
+        );
+    }
 }
 
 /// More general integration tests for rollback
@@ -724,7 +1043,9 @@ mod integration_tests {
 
     use bevy::prelude::*;
 
+    use crate::client::prediction::predicted_history::PredictionHistory;
     use crate::prelude::client::*;
+    use crate::protocol::component::ComponentKind;
 
     use crate::tests::protocol::*;
     use crate::tests::stepper::{BevyStepper, Step};
@@ -766,6 +1087,109 @@ mod integration_tests {
         (stepper, confirmed, predicted)
     }
 
+    /// Increment the component, except on the entities that are not rolled back during a rollback
+    fn increment_rollback_group(
+        rollback: Res<Rollback>,
+        mut query: Query<(&mut Component1, Option<&PredictionGroup>), With<Predicted>>,
+    ) {
+        for (mut component, group) in query.iter_mut() {
+            if rollback.is_rollback() && !rollback.is_rollback_group(group) {
+                continue;
+            }
+            component.0 += 1.0;
+        }
+    }
+
+    /// Test that a mismatch in one prediction group re-simulates that group only,
+    /// and leaves the state of the other group untouched
+    #[test]
+    fn test_partial_rollback_other_group_untouched() {
+        let mut stepper = BevyStepper::default();
+        stepper
+            .client_app
+            .add_systems(FixedUpdate, increment_rollback_group);
+        let mut spawn = |group: u64| {
+            let confirmed = stepper
+                .client_app
+                .world
+                .spawn((Confirmed::default(), Component1(0.0)))
+                .id();
+            let predicted = stepper
+                .client_app
+                .world
+                .spawn((
+                    Predicted {
+                        confirmed_entity: Some(confirmed),
+                    },
+                    PredictionGroup(group),
+                ))
+                .id();
+            stepper
+                .client_app
+                .world
+                .get_mut::<Confirmed>(confirmed)
+                .unwrap()
+                .predicted = Some(predicted);
+            (confirmed, predicted)
+        };
+        let (confirmed_a, predicted_a) = spawn(1);
+        let (_, predicted_b) = spawn(2);
+        for _ in 0..4 {
+            stepper.frame_step();
+        }
+        let component_b = stepper
+            .client_app
+            .world
+            .get::<Component1>(predicted_b)
+            .unwrap()
+            .0;
+        let history_b: Vec<_> = stepper
+            .client_app
+            .world
+            .get::<PredictionHistory<Component1>>(predicted_b)
+            .unwrap()
+            .buffer
+            .iter()
+            .cloned()
+            .collect();
+
+        // only the confirmed state of A diverges from the prediction
+        let tick = stepper.client_tick();
+        stepper
+            .client_app
+            .world
+            .get_mut::<Component1>(confirmed_a)
+            .unwrap()
+            .0 = -10.0;
+        received_confirmed_update(&mut stepper, confirmed_a, tick - 3);
+        stepper.frame_step();
+
+        // A rolled back 3 ticks and advanced by 1 tick
+        assert_eq!(
+            stepper.client_app.world.get::<Component1>(predicted_a),
+            Some(&Component1(-6.0))
+        );
+        // B was only simulated for the new tick, and its history was not rewritten
+        assert_eq!(
+            stepper.client_app.world.get::<Component1>(predicted_b),
+            Some(&Component1(component_b + 1.0))
+        );
+        let new_history_b = stepper
+            .client_app
+            .world
+            .get::<PredictionHistory<Component1>>(predicted_b)
+            .unwrap();
+        assert_eq!(
+            new_history_b
+                .buffer
+                .iter()
+                .take(history_b.len())
+                .cloned()
+                .collect::<Vec<_>>(),
+            history_b
+        );
+    }
+
     /// Test that:
     /// - we remove a component from the predicted entity
     /// - rolling back before the remove should re-add it
@@ -904,6 +1328,237 @@ mod integration_tests {
             .is_none());
     }
 
+    /// Check that the rollback start and end events are emitted with the mispredicted component
+    #[test]
+    fn test_rollback_events() {
+        let (mut stepper, confirmed, _) = setup();
+        stepper
+            .client_app
+            .world
+            .entity_mut(confirmed)
+            .insert(Component1(0.0));
+        stepper.frame_step();
+        stepper.frame_step();
+        stepper
+            .client_app
+            .world
+            .resource_mut::<Events<RollbackStartEvent>>()
+            .clear();
+        stepper
+            .client_app
+            .world
+            .resource_mut::<Events<RollbackEndEvent>>()
+            .clear();
+
+        // create a mismatch between the confirmed and predicted component
+        stepper
+            .client_app
+            .world
+            .get_mut::<Component1>(confirmed)
+            .unwrap()
+            .0 = -10.0;
+        let tick = stepper.client_tick();
+        received_confirmed_update(&mut stepper, confirmed, tick - 1);
+        stepper.frame_step();
+
+        let start_events: Vec<RollbackStartEvent> = stepper
+            .client_app
+            .world
+            .resource_mut::<Events<RollbackStartEvent>>()
+            .drain()
+            .collect();
+        assert_eq!(start_events.len(), 1);
+        let start = &start_events[0];
+        assert_eq!(start.from_tick, tick);
+        assert_eq!(
+            start.cause,
+            RollbackCause::Mismatch {
+                confirmed_entity: confirmed,
+                component: ComponentKind::of::<Component1>(),
+            }
+        );
+        let end_events: Vec<RollbackEndEvent> = stepper
+            .client_app
+            .world
+            .resource_mut::<Events<RollbackEndEvent>>()
+            .drain()
+            .collect();
+        assert_eq!(
+            end_events,
+            vec![RollbackEndEvent {
+                from_tick: start.from_tick,
+                to_tick: start.to_tick,
+            }]
+        );
+    }
+
+    /// Test that a rollback longer than `max_rollback_ticks` snaps the predicted entity to the confirmed
+    /// state instead of re-simulating the ticks
+    #[test]
+    fn test_max_rollback_ticks() {
+        let (mut stepper, confirmed, predicted) = setup();
+        stepper
+            .client_app
+            .world
+            .resource_mut::<ClientConfig>()
+            .prediction
+            .max_rollback_ticks = Some(1);
+        stepper
+            .client_app
+            .world
+            .entity_mut(confirmed)
+            .insert(Component1(0.0));
+        stepper.frame_step();
+        stepper.frame_step();
+        stepper.frame_step();
+
+        // create a mismatch a few ticks in the past
+        stepper
+            .client_app
+            .world
+            .get_mut::<Component1>(confirmed)
+            .unwrap()
+            .0 = -10.0;
+        let tick = stepper.client_tick();
+        received_confirmed_update(&mut stepper, confirmed, tick - 2u16);
+        stepper.frame_step();
+
+        let snap_events: Vec<RollbackSnapEvent> = stepper
+            .client_app
+            .world
+            .resource_mut::<Events<RollbackSnapEvent>>()
+            .drain()
+            .collect();
+        assert_eq!(snap_events.len(), 1);
+        assert_eq!(snap_events[0].from_tick, tick - 1u16);
+        assert!(stepper
+            .client_app
+            .world
+            .resource_mut::<Events<RollbackStartEvent>>()
+            .drain()
+            .next()
+            .is_none());
+        // the predicted entity was snapped to the confirmed value, then only the current tick was simulated
+        assert_eq!(
+            stepper.client_app.world.get::<Component1>(predicted),
+            Some(&Component1(-9.0))
+        );
+        assert!(!stepper
+            .client_app
+            .world
+            .resource::<Rollback>()
+            .is_rollback());
+    }
+
+    /// Test that a `max_rollback_ticks` above `i16::MAX` does not snap every rollback
+    #[test]
+    fn test_max_rollback_ticks_large_limit() {
+        let (mut stepper, confirmed, _) = setup();
+        stepper
+            .client_app
+            .world
+            .resource_mut::<ClientConfig>()
+            .prediction
+            .max_rollback_ticks = Some(u16::MAX);
+        stepper
+            .client_app
+            .world
+            .entity_mut(confirmed)
+            .insert(Component1(0.0));
+        stepper.frame_step();
+        stepper.frame_step();
+        stepper.frame_step();
+
+        stepper
+            .client_app
+            .world
+            .get_mut::<Component1>(confirmed)
+            .unwrap()
+            .0 = -10.0;
+        let tick = stepper.client_tick();
+        received_confirmed_update(&mut stepper, confirmed, tick - 2u16);
+        stepper.frame_step();
+
+        assert!(stepper
+            .client_app
+            .world
+            .resource_mut::<Events<RollbackSnapEvent>>()
+            .drain()
+            .next()
+            .is_none());
+        assert_eq!(
+            stepper
+                .client_app
+                .world
+                .resource_mut::<Events<RollbackStartEvent>>()
+                .drain()
+                .count(),
+            1
+        );
+    }
+
+    /// Test that a custom `should_rollback` function can ignore small differences between the predicted
+    /// and confirmed values
+    #[test]
+    fn test_should_rollback_threshold() {
+        let (mut stepper, confirmed, _) = setup();
+        stepper
+            .client_app
+            .world
+            .resource_mut::<crate::prelude::ComponentRegistry>()
+            .set_should_rollback::<Component1>(|this, that| (this.0 - that.0).abs() > 100.0);
+        stepper
+            .client_app
+            .world
+            .entity_mut(confirmed)
+            .insert(Component1(0.0));
+        stepper.frame_step();
+        stepper.frame_step();
+        stepper
+            .client_app
+            .world
+            .resource_mut::<Events<RollbackStartEvent>>()
+            .clear();
+
+        // the mismatch is below the threshold
+        stepper
+            .client_app
+            .world
+            .get_mut::<Component1>(confirmed)
+            .unwrap()
+            .0 = -10.0;
+        let tick = stepper.client_tick();
+        received_confirmed_update(&mut stepper, confirmed, tick - 1);
+        stepper.frame_step();
+        assert!(stepper
+            .client_app
+            .world
+            .resource_mut::<Events<RollbackStartEvent>>()
+            .drain()
+            .next()
+            .is_none());
+
+        // the mismatch is above the threshold
+        stepper
+            .client_app
+            .world
+            .get_mut::<Component1>(confirmed)
+            .unwrap()
+            .0 = -1000.0;
+        let tick = stepper.client_tick();
+        received_confirmed_update(&mut stepper, confirmed, tick - 1);
+        stepper.frame_step();
+        assert_eq!(
+            stepper
+                .client_app
+                .world
+                .resource_mut::<Events<RollbackStartEvent>>()
+                .drain()
+                .count(),
+            1
+        );
+    }
+
     /// Test that:
     /// - a component gets added to the confirmed entity, triggering rollback
     /// - the predicted entity did not have the component, so the rollback adds it
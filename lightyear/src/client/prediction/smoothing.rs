@@ -0,0 +1,256 @@
+//! Smooth the visual snapping of predicted entities after a rollback.
+//!
+//! When a rollback happens, the [`Transform`] of a predicted entity jumps from the mispredicted value to the
+//! corrected value, which looks like jitter. If [`PredictionConfig::visual_smoothing`] is set:
+//! - the difference between the transform before and after the rollback is stored in a [`VisualErrorOffset`]
+//! - the offset is added to the [`Transform`] in `PostUpdate` (before transform propagation) so that the rendered
+//!   entity does not move, and removed again at the start of the next frame, so that the game logic only sees
+//!   the corrected value
+//! - the offset decays to zero over the configured duration
+//!
+//! If a new rollback happens while an offset is still decaying, the remaining offset is carried over.
+//!
+//! [`PredictionConfig::visual_smoothing`]: crate::client::prediction::plugin::PredictionConfig::visual_smoothing
+use bevy::ecs::entity::EntityHashMap;
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use tracing::trace;
+
+use crate::client::config::ClientConfig;
+use crate::client::prediction::rollback::{PredictionGroup, Rollback};
+use crate::client::prediction::Predicted;
+
+/// Visual offset between the rendered [`Transform`] of a predicted entity and its corrected [`Transform`],
+/// that is decaying after a rollback
+#[derive(Component, Debug, Clone, PartialEq, Reflect)]
+pub struct VisualErrorOffset {
+    /// Offset added to the translation
+    pub translation: Vec3,
+    /// Offset applied to the rotation
+    pub rotation: Quat,
+    /// Time left before the offset reaches zero
+    pub remaining: Duration,
+    /// True if the offset is currently applied to the [`Transform`]
+    applied: bool,
+}
+
+impl VisualErrorOffset {
+    /// Decay the offset linearly, so that it reaches zero once `remaining` has elapsed.
+    ///
+    /// Returns false if the offset is over.
+    fn decay(&mut self, delta: Duration) -> bool {
+        if self.remaining <= delta {
+            return false;
+        }
+        let factor = (self.remaining - delta).as_secs_f32() / self.remaining.as_secs_f32();
+        self.translation *= factor;
+        self.rotation = Quat::IDENTITY.slerp(self.rotation, factor);
+        self.remaining -= delta;
+        true
+    }
+}
+
+/// The [`Transform`] of the predicted entities right before the rollback
+#[derive(Resource, Default, Debug)]
+pub(crate) struct PreRollbackTransforms(EntityHashMap<Transform>);
+
+impl PreRollbackTransforms {
+    pub(crate) fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// Run condition that returns true if the visual smoothing is enabled
+pub(crate) fn is_visual_smoothing(config: Res<ClientConfig>) -> bool {
+    config.prediction.visual_smoothing.is_some()
+}
+
+/// At the start of the frame, remove the visual offset so that the game logic uses the corrected [`Transform`]
+pub(crate) fn remove_visual_error_offset(
+    mut query: Query<(&mut Transform, &mut VisualErrorOffset)>,
+) {
+    for (mut transform, mut offset) in query.iter_mut() {
+        if offset.applied {
+            transform.translation -= offset.translation;
+            transform.rotation = offset.rotation.inverse() * transform.rotation;
+            offset.applied = false;
+        }
+    }
+}
+
+/// Store the [`Transform`] of the predicted entities before they get rolled back
+pub(crate) fn store_pre_rollback_transform(
+    rollback: Res<Rollback>,
+    mut pre_rollback: ResMut<PreRollbackTransforms>,
+    query: Query<(Entity, &Transform, Option<&PredictionGroup>), With<Predicted>>,
+) {
+    pre_rollback.0.clear();
+    pre_rollback.0.extend(
+        query
+            .iter()
+            .filter(|(_, _, group)| rollback.is_rollback_group(*group))
+            .map(|(entity, transform, _)| (entity, *transform)),
+    );
+}
+
+/// Compute the visual offset between the [`Transform`] before and after the rollback,
+/// so that the rendered entity doesn't snap to the corrected value
+pub(crate) fn add_visual_error_offset(
+    mut commands: Commands,
+    config: Res<ClientConfig>,
+    mut pre_rollback: ResMut<PreRollbackTransforms>,
+    query: Query<(&Transform, Option<&VisualErrorOffset>), With<Predicted>>,
+) {
+    let Some(duration) = config.prediction.visual_smoothing else {
+        return;
+    };
+    for (entity, pre) in pre_rollback.0.drain() {
+        let Ok((post, previous)) = query.get(entity) else {
+            continue;
+        };
+        // the entity was rendered at `pre + previous offset`: we want to keep rendering it there
+        let (previous_translation, previous_rotation) = previous
+            .map_or((Vec3::ZERO, Quat::IDENTITY), |offset| {
+                (offset.translation, offset.rotation)
+            });
+        let offset = VisualErrorOffset {
+            translation: pre.translation + previous_translation - post.translation,
+            rotation: previous_rotation * pre.rotation * post.rotation.inverse(),
+            remaining: duration,
+            applied: false,
+        };
+        trace!(
+            ?entity,
+            ?offset,
+            "adding visual error offset after rollback"
+        );
+        commands.entity(entity).insert(offset);
+    }
+}
+
+/// Decay the visual offset and apply it to the [`Transform`] before it gets propagated
+pub(crate) fn apply_visual_error_offset(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Transform, &mut VisualErrorOffset)>,
+) {
+    for (entity, mut transform, mut offset) in query.iter_mut() {
+        if !offset.decay(time.delta()) {
+            trace!(?entity, "visual error offset is over");
+            commands.entity(entity).remove::<VisualErrorOffset>();
+            continue;
+        }
+        transform.translation += offset.translation;
+        transform.rotation = offset.rotation * transform.rotation;
+        offset.applied = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::prediction::plugin::is_in_rollback;
+    use crate::client::prediction::plugin::PredictionConfig;
+    use crate::client::prediction::rollback::RollbackState;
+    use crate::prelude::{client, SharedConfig, TickConfig};
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    #[test]
+    fn test_decay() {
+        let mut offset = VisualErrorOffset {
+            translation: Vec3::new(4.0, 0.0, 0.0),
+            rotation: Quat::IDENTITY,
+            remaining: Duration::from_millis(100),
+            applied: false,
+        };
+        assert!(offset.decay(Duration::from_millis(50)));
+        assert_eq!(offset.translation, Vec3::new(2.0, 0.0, 0.0));
+        assert_eq!(offset.remaining, Duration::from_millis(50));
+        assert!(!offset.decay(Duration::from_millis(50)));
+    }
+
+    /// Move the predicted entities to a new position, as if the rollback had corrected them
+    fn correct_transform(mut query: Query<&mut Transform, With<Predicted>>) {
+        for mut transform in query.iter_mut() {
+            transform.translation = Vec3::new(10.0, 0.0, 0.0);
+        }
+    }
+
+    #[test]
+    fn test_visual_smoothing() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let client_config = client::ClientConfig {
+            prediction: PredictionConfig::default()
+                .with_visual_smoothing(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(shared_config, client_config, frame_duration);
+        stepper.init();
+        // only correct the transform during the rollback
+        stepper
+            .client_app
+            .add_systems(FixedUpdate, correct_transform.run_if(is_in_rollback));
+        let predicted = stepper
+            .client_app
+            .world
+            .spawn((
+                Predicted {
+                    confirmed_entity: None,
+                },
+                Transform::default(),
+            ))
+            .id();
+        stepper.frame_step();
+
+        // trigger a rollback
+        let tick = stepper.client_tick();
+        stepper
+            .client_app
+            .world
+            .insert_resource(Rollback::new(RollbackState::ShouldRollback {
+                current_tick: tick - 1u16,
+            }));
+        stepper.frame_step();
+
+        // the game logic sees the corrected transform, but the rendered transform is close to the original one
+        let offset = stepper
+            .client_app
+            .world
+            .get::<VisualErrorOffset>(predicted)
+            .expect("the offset should be added after a rollback")
+            .clone();
+        assert!(offset.applied);
+        let rendered = stepper
+            .client_app
+            .world
+            .get::<Transform>(predicted)
+            .unwrap()
+            .translation;
+        assert_eq!(rendered, Vec3::new(10.0, 0.0, 0.0) + offset.translation);
+        assert!(rendered.x < 2.0);
+
+        // the offset decays to zero
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        assert!(stepper
+            .client_app
+            .world
+            .get::<VisualErrorOffset>(predicted)
+            .is_none());
+        let transform = stepper
+            .client_app
+            .world
+            .get::<Transform>(predicted)
+            .unwrap();
+        assert!(transform
+            .translation
+            .abs_diff_eq(Vec3::new(10.0, 0.0, 0.0), 1e-4));
+    }
+}
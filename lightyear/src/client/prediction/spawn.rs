@@ -1,12 +1,13 @@
 //! Logic to handle spawning Predicted entities
-use bevy::prelude::{Added, Commands, Entity, Query, Res, ResMut};
-use tracing::debug;
+use bevy::ecs::system::{Command, EntityCommands};
+use bevy::prelude::{Added, Commands, DespawnRecursiveExt, Entity, Query, Res, ResMut, World};
+use tracing::{debug, error};
 
 use crate::client::components::Confirmed;
 use crate::client::connection::ConnectionManager;
 use crate::client::prediction::resource::PredictionManager;
 use crate::client::prediction::Predicted;
-use crate::prelude::ShouldBePredicted;
+use crate::prelude::{Replicated, ShouldBePredicted};
 
 /// Spawn a predicted entity for each confirmed entity that has the `ShouldBePredicted` component added
 /// The `Confirmed` entity could already exist because we share the Confirmed component for prediction and interpolation.
@@ -76,3 +77,177 @@ pub(crate) fn spawn_predicted_entity(
         }
     }
 }
+
+/// Start predicting a confirmed entity that was replicated from the server.
+///
+/// A predicted entity is spawned, and the components of the confirmed entity are synced to it
+/// (with their history) like for the entities that are predicted as soon as they are replicated.
+pub struct StartPredictionCommand {
+    confirmed_entity: Entity,
+}
+
+impl Command for StartPredictionCommand {
+    fn apply(self, world: &mut World) {
+        let Some(mut entity_mut) = world.get_entity_mut(self.confirmed_entity) else {
+            return;
+        };
+        if !entity_mut.contains::<Replicated>() {
+            error!(
+                entity = ?self.confirmed_entity,
+                "Only the entities replicated from the server can be predicted"
+            );
+            return;
+        }
+        if entity_mut
+            .get::<Confirmed>()
+            .is_some_and(|confirmed| confirmed.predicted.is_some())
+        {
+            debug!(entity = ?self.confirmed_entity, "Entity is already predicted");
+            return;
+        }
+        // the predicted entity is spawned by `spawn_predicted_entity`
+        entity_mut.insert(ShouldBePredicted);
+    }
+}
+
+/// Stop predicting a confirmed entity: its predicted entity (and the prediction history) is despawned.
+///
+/// The confirmed entity keeps being replicated from the server.
+pub struct StopPredictionCommand {
+    confirmed_entity: Entity,
+}
+
+impl Command for StopPredictionCommand {
+    fn apply(self, world: &mut World) {
+        let Some(predicted_entity) = world
+            .get_mut::<Confirmed>(self.confirmed_entity)
+            .and_then(|mut confirmed| confirmed.predicted.take())
+        else {
+            return;
+        };
+        debug!(
+            "Stop predicting confirmed entity {:?}, despawning predicted entity {:?}",
+            self.confirmed_entity, predicted_entity
+        );
+        world
+            .resource_mut::<PredictionManager>()
+            .predicted_entity_map
+            .get_mut()
+            .confirmed_to_predicted
+            .remove(&self.confirmed_entity);
+        if let Some(entity_mut) = world.get_entity_mut(predicted_entity) {
+            entity_mut.despawn_recursive();
+        }
+    }
+}
+
+/// Toggle the prediction of a confirmed entity at runtime
+/// (for example to only predict the vehicle that the player is currently driving)
+pub trait PredictionCommandsExt {
+    /// Start predicting the confirmed entity. See [`StartPredictionCommand`]
+    fn start_prediction(&mut self);
+
+    /// Stop predicting the confirmed entity. See [`StopPredictionCommand`]
+    fn stop_prediction(&mut self);
+}
+
+impl PredictionCommandsExt for EntityCommands<'_> {
+    fn start_prediction(&mut self) {
+        let confirmed_entity = self.id();
+        self.commands()
+            .add(StartPredictionCommand { confirmed_entity })
+    }
+
+    fn stop_prediction(&mut self) {
+        let confirmed_entity = self.id();
+        self.commands()
+            .add(StopPredictionCommand { confirmed_entity })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::prediction::predicted_history::PredictionHistory;
+    use crate::prelude::server;
+    use crate::tests::protocol::Component1;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    #[test]
+    fn test_toggle_prediction() {
+        let mut stepper = BevyStepper::default();
+        // the entity is not predicted by default
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((Component1(1.0), server::Replicate::default()))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        let confirmed_entity = *stepper
+            .client_app
+            .world
+            .resource::<ConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .expect("entity was not replicated to client");
+
+        // start predicting the entity
+        StartPredictionCommand { confirmed_entity }.apply(&mut stepper.client_app.world);
+        stepper.frame_step();
+        let predicted_entity = stepper
+            .client_app
+            .world
+            .get::<Confirmed>(confirmed_entity)
+            .and_then(|confirmed| confirmed.predicted)
+            .expect("a predicted entity should have been spawned");
+        assert_eq!(
+            stepper.client_app.world.get::<Component1>(predicted_entity),
+            Some(&Component1(1.0))
+        );
+        assert!(stepper
+            .client_app
+            .world
+            .get::<PredictionHistory<Component1>>(predicted_entity)
+            .is_some());
+
+        // starting the prediction again does nothing
+        StartPredictionCommand { confirmed_entity }.apply(&mut stepper.client_app.world);
+        stepper.frame_step();
+        assert_eq!(
+            stepper
+                .client_app
+                .world
+                .get::<Confirmed>(confirmed_entity)
+                .unwrap()
+                .predicted,
+            Some(predicted_entity)
+        );
+
+        // stop predicting the entity
+        StopPredictionCommand { confirmed_entity }.apply(&mut stepper.client_app.world);
+        stepper.frame_step();
+        assert!(stepper
+            .client_app
+            .world
+            .get_entity(predicted_entity)
+            .is_none());
+        assert!(stepper
+            .client_app
+            .world
+            .get::<Confirmed>(confirmed_entity)
+            .unwrap()
+            .predicted
+            .is_none());
+        assert!(stepper
+            .client_app
+            .world
+            .resource_mut::<PredictionManager>()
+            .predicted_entity_map
+            .get_mut()
+            .confirmed_to_predicted
+            .is_empty());
+    }
+}
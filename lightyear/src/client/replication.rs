@@ -6,11 +6,26 @@ use crate::client::connection::ConnectionManager;
 use crate::client::sync::client_is_synced;
 use crate::shared::replication::plugin::receive::ReplicationReceivePlugin;
 use crate::shared::replication::plugin::send::ReplicationSendPlugin;
-use crate::shared::sets::{ClientMarker, InternalReplicationSet};
+use crate::shared::sets::{ClientMarker, InternalMainSet, InternalReplicationSet};
+
+/// SystemSets in `PreUpdate` around the systems that write the replication messages received from the server into the world
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub enum ClientReplicationSet {
+    /// Runs after the packets are received, right before the replication messages are applied.
+    ///
+    /// The replicated entities still have the values of the previous updates, for example to store them
+    /// before they are overwritten.
+    PreApply,
+    /// Applies the replication messages (entity spawns/despawns, component inserts/updates/removals) to the world
+    Apply,
+    /// Runs right after the replication messages are applied, before the replication events are emitted
+    PostApply,
+}
 
 pub(crate) mod receive {
     use super::*;
-    use crate::prelude::{is_connected, is_host_server};
+    use crate::client::networking;
+    use crate::prelude::{is_connected, is_host_server, TickManager};
     #[derive(Default)]
     pub struct ClientReplicationReceivePlugin {
         pub tick_interval: Duration,
@@ -23,6 +38,22 @@ pub(crate) mod receive {
                 self.tick_interval,
             ));
 
+            app.configure_sets(
+                PreUpdate,
+                (
+                    ClientReplicationSet::PreApply,
+                    ClientReplicationSet::Apply,
+                    ClientReplicationSet::PostApply,
+                )
+                    .chain()
+                    .after(networking::receive)
+                    .in_set(InternalMainSet::<ClientMarker>::Receive),
+            );
+            app.add_systems(
+                PreUpdate,
+                apply_replication.in_set(ClientReplicationSet::Apply),
+            );
+
             // TODO: currently we only support pre-spawned entities spawned during the FixedUpdate schedule
             // // SYSTEM SETS
             // .configure_sets(
@@ -46,6 +77,110 @@ pub(crate) mod receive {
             );
         }
     }
+
+    /// Write the replication messages received from the server into the world
+    pub(crate) fn apply_replication(world: &mut World) {
+        world.resource_scope(|world, mut connection: Mut<ConnectionManager>| {
+            world.resource_scope(|world, tick_manager: Mut<TickManager>| {
+                connection.apply_replication(world, tick_manager.as_ref());
+            });
+        });
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::prelude::{server, ComponentRegistry, Replicated};
+        use crate::tests::protocol::{Component1, Component2};
+        use crate::tests::stepper::{BevyStepper, Step};
+
+        use super::*;
+
+        #[derive(Resource, Default)]
+        struct AppliedValues {
+            before: Option<f32>,
+            changes: Vec<(Option<f32>, Option<f32>)>,
+        }
+
+        fn record_before(
+            query: Query<&Component1, With<Replicated>>,
+            mut values: ResMut<AppliedValues>,
+        ) {
+            values.before = query.iter().next().map(|c| c.0);
+        }
+
+        fn record_after(
+            query: Query<&Component1, With<Replicated>>,
+            mut values: ResMut<AppliedValues>,
+        ) {
+            let after = query.iter().next().map(|c| c.0);
+            if after != values.before {
+                let before = values.before;
+                values.changes.push((before, after));
+            }
+        }
+
+        fn store_previous(entity: &mut EntityWorldMut, previous: Option<Component1>) {
+            entity.insert(Component2(previous.map_or(-1.0, |c| c.0)));
+        }
+
+        #[test]
+        fn test_apply_sets_and_hooks() {
+            let mut stepper = BevyStepper::default();
+            stepper
+                .client_app
+                .init_resource::<AppliedValues>()
+                .add_systems(
+                    PreUpdate,
+                    (
+                        record_before.in_set(ClientReplicationSet::PreApply),
+                        record_after.in_set(ClientReplicationSet::PostApply),
+                    ),
+                );
+            stepper
+                .client_app
+                .world
+                .resource_mut::<ComponentRegistry>()
+                .set_apply_hook::<Component1>(store_previous);
+
+            let server_entity = stepper
+                .server_app
+                .world
+                .spawn((Component1(1.0), server::Replicate::default()))
+                .id();
+            stepper.frame_step();
+            stepper.frame_step();
+            let client_entity = *stepper
+                .client_app
+                .world
+                .resource::<ConnectionManager>()
+                .replication_receiver
+                .remote_entity_map
+                .get_local(server_entity)
+                .expect("entity was not replicated to client");
+            assert_eq!(
+                stepper.client_app.world.get::<Component2>(client_entity),
+                Some(&Component2(-1.0))
+            );
+
+            stepper
+                .server_app
+                .world
+                .entity_mut(server_entity)
+                .insert(Component1(2.0));
+            stepper.frame_step();
+            stepper.frame_step();
+            // the hook received the previous value
+            assert_eq!(
+                stepper.client_app.world.get::<Component2>(client_entity),
+                Some(&Component2(1.0))
+            );
+            // the PreApply and PostApply sets ran right before and after the updates were applied
+            assert_eq!(
+                stepper.client_app.world.resource::<AppliedValues>().changes,
+                vec![(None, Some(1.0)), (Some(1.0), Some(2.0))]
+            );
+        }
+    }
 }
 
 pub(crate) mod send {
@@ -66,6 +201,7 @@ pub(crate) mod send {
 
     use crate::shared::replication::archetypes::{get_erased_component, ReplicatedArchetypes};
     use crate::shared::replication::error::ReplicationError;
+    use crate::shared::replication::DespawnReason;
     use bevy::ecs::entity::Entities;
     use bevy::ecs::system::SystemChangeTick;
     use bevy::ptr::Ptr;
@@ -425,9 +561,11 @@ pub(crate) mod send {
                 ?entity,
                 "send entity despawn because ReplicationToServerTarget was removed"
             );
-            sender
-                .replication_sender
-                .prepare_entity_despawn(entity, group.group_id(Some(entity)));
+            sender.replication_sender.prepare_entity_despawn(
+                entity,
+                group.group_id(Some(entity)),
+                DespawnReason::TargetChanged,
+            );
         });
 
         // Despawn entities when the entity gets despawned on local world
@@ -441,6 +579,7 @@ pub(crate) mod send {
                 sender.replication_sender.prepare_entity_despawn(
                     entity,
                     replicate_cache.replication_group.group_id(Some(entity)),
+                    DespawnReason::Despawned,
                 );
             }
         }
@@ -613,7 +752,8 @@ pub(crate) mod send {
         use crate::client::replication::send::ReplicateToServer;
         use crate::prelude::client::Replicate;
         use crate::prelude::{
-            server, ClientId, DisabledComponent, ReplicateOnceComponent, Replicated, TargetEntity,
+            server, AuthorityPeer, ClientId, DisabledComponent, ReplicateOnceComponent, Replicated,
+            TargetEntity,
         };
         use crate::tests::protocol::Component1;
         use crate::tests::stepper::{BevyStepper, Step, TEST_CLIENT_ID};
@@ -677,7 +817,11 @@ pub(crate) mod send {
         fn test_entity_spawn_preexisting_target() {
             let mut stepper = BevyStepper::default();
 
-            let server_entity = stepper.server_app.world.spawn_empty().id();
+            let server_entity = stepper
+                .server_app
+                .world
+                .spawn(AuthorityPeer::Client(ClientId::Netcode(TEST_CLIENT_ID)))
+                .id();
             stepper.frame_step();
             let client_entity = stepper
                 .client_app
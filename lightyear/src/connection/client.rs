@@ -64,6 +64,14 @@ pub trait NetClient: Send + Sync {
 
     /// Get mutable access to the inner io
     fn io_mut(&mut self) -> Option<&mut Io>;
+
+    /// Keys exchanged with the server during the connection handshake (send key, receive key).
+    ///
+    /// They are used to authenticate the messages of the authenticated channels.
+    /// Returns None if the connection does not perform a key exchange.
+    fn session_keys(&self) -> Option<(Key, Key)> {
+        None
+    }
 }
 
 #[enum_dispatch(NetClient)]
@@ -129,6 +137,13 @@ impl Default for NetConfig {
 }
 
 impl NetConfig {
+    /// Returns true if the connection exchanges session keys with the server during the handshake.
+    ///
+    /// The session keys are required to use the authenticated channels.
+    pub(crate) fn provides_session_keys(&self) -> bool {
+        matches!(self, NetConfig::Netcode { .. })
+    }
+
     pub fn build_client(self) -> ClientConnection {
         match self {
             NetConfig::Netcode {
@@ -145,6 +160,8 @@ impl NetConfig {
                         .expect("could not create netcode client");
                 let client = super::netcode::Client {
                     client: netcode,
+                    compressor: io_config.compressor(),
+                    server_compression: 0,
                     io_config,
                     io: None,
                 };
@@ -182,6 +199,17 @@ impl NetConfig {
     }
 }
 
+impl ClientConnection {
+    /// Set the [`ProtocolHash`](crate::prelude::ProtocolHash) sent to the server during the connection handshake.
+    ///
+    /// Only the netcode connection performs the check; it is ignored by the other connections.
+    pub(crate) fn set_protocol_hash(&mut self, protocol_hash: u64) {
+        if let NetClientDispatch::Netcode(client) = &mut self.client {
+            client.client.set_protocol_hash(protocol_hash);
+        }
+    }
+}
+
 impl NetClient for ClientConnection {
     fn connect(&mut self) -> Result<(), ConnectionError> {
         self.client.connect()
@@ -222,6 +250,10 @@ impl NetClient for ClientConnection {
     fn io_mut(&mut self) -> Option<&mut Io> {
         self.client.io_mut()
     }
+
+    fn session_keys(&self) -> Option<(Key, Key)> {
+        self.client.session_keys()
+    }
 }
 
 #[derive(Resource, Default, Clone)]
@@ -66,6 +66,22 @@ impl ClientId {
     pub fn is_local(&self) -> bool {
         matches!(self, ClientId::Local(_))
     }
+
+    /// Returns the [`SteamId`](steamworks::SteamId) of the client, if this is a steam client
+    #[cfg(all(feature = "steam", not(target_family = "wasm")))]
+    pub fn steam_id(&self) -> Option<steamworks::SteamId> {
+        match self {
+            ClientId::Steam(id) => Some(steamworks::SteamId::from_raw(*id)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(all(feature = "steam", not(target_family = "wasm")))]
+impl From<steamworks::SteamId> for ClientId {
+    fn from(steam_id: steamworks::SteamId) -> Self {
+        ClientId::Steam(steam_id.raw())
+    }
 }
 
 impl core::fmt::Display for ClientId {
@@ -1,4 +1,14 @@
 /*!  A connection is an abstraction over an unreliable transport of a connection between a client and server
+
+# Encryption
+
+The [`netcode`] connection already encrypts and signs every packet with ChaCha20-Poly1305,
+using per-connection keys that are distributed to the client through the [`ConnectToken`](netcode::ConnectToken)
+during the handshake. Since this happens above the [`Transport`](crate::transport::Transport) layer,
+any transport (UDP, WebTransport, WebSocket, etc.) used with netcode gets confidentiality and integrity for free,
+and there is no need to add a separate encryption middleware on the transport.
+
+Other connection types (for example steam) rely on the encryption provided by their own networking layer.
 */
 pub mod client;
 pub mod netcode;
@@ -8,8 +8,10 @@ use crate::connection::client::{
     ConnectionError, ConnectionState, DisconnectReason, IoConfig, NetClient,
 };
 use crate::connection::id;
-use crate::packet::packet_builder::RecvPayload;
+use crate::connection::server::DeniedReason;
+use crate::packet::packet_builder::{RecvBuffer, RecvPayload};
 use crate::transport::io::IoState;
+use crate::transport::middleware::compression::{PacketCompressor, COMPRESSION_HEADER_BYTES};
 use crate::transport::{PacketReceiver, PacketSender, LOCAL_SOCKET};
 use crate::utils::pool::Pool;
 
@@ -21,7 +23,8 @@ use super::{
     },
     replay::ReplayProtection,
     token::{ChallengeToken, ConnectToken},
-    utils, ClientId, MAX_PACKET_SIZE, MAX_PKT_BUF_SIZE, PACKET_SEND_RATE_SEC,
+    utils, ClientId, Key, MAX_PACKET_SIZE, MAX_PKT_BUF_SIZE, MIGRATION_TIMEOUT_SEC,
+    PACKET_SEND_RATE_SEC,
 };
 
 type Callback<Ctx> = Box<dyn FnMut(ClientState, ClientState, &mut Ctx) + Send + Sync + 'static>;
@@ -30,6 +33,7 @@ type Callback<Ctx> = Box<dyn FnMut(ClientState, ClientState, &mut Ctx) + Send +
 ///
 /// * `num_disconnect_packets` - The number of redundant disconnect packets that will be sent to a server when the clients wants to disconnect.
 /// * `packet_send_rate` - The rate at which periodic packets will be sent to the server.
+/// * `migration_timeout` - The duration without hearing from the server after which the client assumes its address might have changed.
 /// * `on_state_change` - A callback that will be called when the client changes states.
 ///
 /// # Example
@@ -56,6 +60,8 @@ type Callback<Ctx> = Box<dyn FnMut(ClientState, ClientState, &mut Ctx) + Send +
 pub struct ClientConfig<Ctx> {
     num_disconnect_packets: usize,
     packet_send_rate: f64,
+    migration_timeout: f64,
+    protocol_hash: u64,
     context: Ctx,
     on_state_change: Option<Callback<Ctx>>,
 }
@@ -65,6 +71,8 @@ impl Default for ClientConfig<()> {
         Self {
             num_disconnect_packets: 10,
             packet_send_rate: PACKET_SEND_RATE_SEC,
+            migration_timeout: MIGRATION_TIMEOUT_SEC,
+            protocol_hash: 0,
             context: (),
             on_state_change: None,
         }
@@ -81,6 +89,8 @@ impl<Ctx> ClientConfig<Ctx> {
         Self {
             num_disconnect_packets: 10,
             packet_send_rate: PACKET_SEND_RATE_SEC,
+            migration_timeout: MIGRATION_TIMEOUT_SEC,
+            protocol_hash: 0,
             context: ctx,
             on_state_change: None,
         }
@@ -97,6 +107,21 @@ impl<Ctx> ClientConfig<Ctx> {
         self.packet_send_rate = rate_seconds;
         self
     }
+    /// Set the duration (in seconds) without receiving any packet from the server after which the client
+    /// assumes that its address might have changed (for example when switching from Wi-Fi to cellular).
+    /// The client then sends migration packets so that the server can rebind the connection to the new address.
+    /// The default is 1 second.
+    pub fn migration_timeout(mut self, timeout_seconds: f64) -> Self {
+        self.migration_timeout = timeout_seconds;
+        self
+    }
+    /// Set the hash of the client's protocol, which is sent to the server in the connection request.
+    /// The server denies the connection if its own protocol hash is different.
+    /// The default is 0.
+    pub fn protocol_hash(mut self, protocol_hash: u64) -> Self {
+        self.protocol_hash = protocol_hash;
+        self
+    }
     /// Set a callback that will be called when the client changes states.
     pub fn on_state_change<F>(mut self, cb: F) -> Self
     where
@@ -125,6 +150,8 @@ impl<Ctx> ClientConfig<Ctx> {
 ///  - The client application may send payload packets to the server.
 ///  - In the absence of payload packets sent by the client application, the client generates and sends connection keep-alive packets
 ///    to the server at some rate (default is 10HZ, can be overridden in [`ClientConfig`]).
+///  - If no packets are received from the server for a short while (default is 1 second, can be overridden in [`ClientConfig`]),
+///    the client sends migration packets instead, so that the server can follow it if its address changed.
 ///  - If no payload or keep-alive packets are received from the server within the timeout period specified in the connect token,
 ///    the client transitions to `ConnectionTimedOut`.
 ///  - While `Connected`, if the client receives a disconnect packet from the server, it transitions to `Disconnected`.
@@ -144,6 +171,9 @@ pub enum ClientState {
     ChallengeResponseTimedOut,
     /// The server has denied the client's connection request, most likely due to the server being full.
     ConnectionDenied,
+    /// The server has denied the client's connection request because their protocols are different
+    /// (the messages, components or channels were not registered identically).
+    ProtocolMismatch,
     /// The client is disconnected from the server.
     Disconnected,
     /// The client is waiting for a response from the server after sending a connection request packet.
@@ -184,6 +214,7 @@ pub struct NetcodeClient<Ctx = ()> {
     start_time: f64,
     last_send_time: f64,
     last_receive_time: f64,
+    last_migration_send_time: f64,
     server_addr_idx: usize,
     sequence: u64,
     challenge_token_sequence: u64,
@@ -193,6 +224,7 @@ pub struct NetcodeClient<Ctx = ()> {
     should_disconnect: bool,
     should_disconnect_state: ClientState,
     packet_queue: VecDeque<RecvPayload>,
+    recv_buffer: RecvBuffer,
     buffer_pool: Pool<Vec<u8>>,
     cfg: ClientConfig<Ctx>,
 }
@@ -219,6 +251,7 @@ impl<Ctx> NetcodeClient<Ctx> {
             start_time: 0.0,
             last_send_time: f64::NEG_INFINITY,
             last_receive_time: f64::NEG_INFINITY,
+            last_migration_send_time: f64::NEG_INFINITY,
             server_addr_idx: 0,
             sequence: 0,
             challenge_token_sequence: 0,
@@ -228,6 +261,7 @@ impl<Ctx> NetcodeClient<Ctx> {
             should_disconnect: false,
             should_disconnect_state: ClientState::Disconnected,
             packet_queue: VecDeque::new(),
+            recv_buffer: RecvBuffer::default(),
             buffer_pool: Pool::new(10, || vec![0u8; MAX_PKT_BUF_SIZE]),
             cfg,
         })
@@ -286,6 +320,14 @@ impl<Ctx> NetcodeClient<Ctx> {
     }
 }
 
+impl<Ctx> NetcodeClient<Ctx> {
+    /// Set the hash of the client's protocol, which is sent in the next connection requests.
+    /// See [`ClientConfig::protocol_hash`].
+    pub(crate) fn set_protocol_hash(&mut self, protocol_hash: u64) {
+        self.cfg.protocol_hash = protocol_hash;
+    }
+}
+
 impl<Ctx> NetcodeClient<Ctx> {
     const ALLOWED_PACKETS: u8 = 1 << Packet::DENIED
         | 1 << Packet::CHALLENGE
@@ -303,6 +345,7 @@ impl<Ctx> NetcodeClient<Ctx> {
         self.start_time = self.time;
         self.last_send_time = self.time - 1.0; // force a packet to be sent immediately
         self.last_receive_time = self.time;
+        self.last_migration_send_time = f64::NEG_INFINITY;
         self.should_disconnect = false;
         self.should_disconnect_state = ClientState::Disconnected;
         self.challenge_token_sequence = 0;
@@ -317,6 +360,11 @@ impl<Ctx> NetcodeClient<Ctx> {
         debug!("client disconnected");
     }
     fn send_packets(&mut self, io: &mut Io) -> Result<()> {
+        if self.state == ClientState::Connected
+            && self.last_receive_time + self.cfg.migration_timeout < self.time
+        {
+            return self.send_migration_packet(io);
+        }
         if self.last_send_time + self.cfg.packet_send_rate >= self.time {
             return Ok(());
         }
@@ -328,6 +376,7 @@ impl<Ctx> NetcodeClient<Ctx> {
                     self.token.expire_timestamp,
                     self.token.nonce,
                     self.token.private_data,
+                    self.cfg.protocol_hash,
                 )
             }
             ClientState::SendingChallengeResponse => {
@@ -365,6 +414,30 @@ impl<Ctx> NetcodeClient<Ctx> {
         Ok(())
     }
 
+    /// If we haven't heard from the server for a while, our address might have changed, in which case
+    /// the server does not recognize our packets anymore.
+    /// Send keep-alive packets that carry our client id in clear so that the server can authenticate them
+    /// and rebind our connection to the new address.
+    fn send_migration_packet(&mut self, io: &mut Io) -> Result<()> {
+        if self.last_migration_send_time + self.cfg.packet_send_rate >= self.time {
+            return Ok(());
+        }
+        trace!("client sending migration packet to server");
+        let mut buf = [0u8; MAX_PKT_BUF_SIZE];
+        let size = Packet::write_migration(
+            &mut buf,
+            self.id,
+            self.sequence,
+            &self.token.client_to_server_key,
+            self.token.protocol_id,
+        )?;
+        io.send(&buf[..size], &self.server_addr())?;
+        self.last_migration_send_time = self.time;
+        self.last_send_time = self.time;
+        self.sequence += 1;
+        Ok(())
+    }
+
     pub fn server_addr(&self) -> SocketAddr {
         self.token.server_addresses[self.server_addr_idx]
     }
@@ -378,12 +451,17 @@ impl<Ctx> NetcodeClient<Ctx> {
                 Packet::Denied(pkt),
                 ClientState::SendingConnectionRequest | ClientState::SendingChallengeResponse,
             ) => {
-                error!(
-                    "client connection denied by server. Reason: {:?}",
-                    pkt.reason
-                );
                 self.should_disconnect = true;
-                self.should_disconnect_state = ClientState::ConnectionDenied;
+                if pkt.reason == DeniedReason::ProtocolMismatch {
+                    error!("client connection denied by server: the client and server protocols are different. Make sure that they register the same messages, components and channels, in the same order");
+                    self.should_disconnect_state = ClientState::ProtocolMismatch;
+                } else {
+                    error!(
+                        "client connection denied by server. Reason: {:?}",
+                        pkt.reason
+                    );
+                    self.should_disconnect_state = ClientState::ConnectionDenied;
+                }
             }
             (Packet::Challenge(pkt), ClientState::SendingConnectionRequest) => {
                 debug!("client received connection challenge packet from server");
@@ -416,10 +494,8 @@ impl<Ctx> NetcodeClient<Ctx> {
                 // // return the buffer to the pool
                 // self.buffer_pool.attach(reader);
 
-                // let buf = self.buffer_pool.pull(|| vec![0u8; pkt.buf.len()]);
-                // TODO: COPY THE PAYLOAD INTO A BUFFER FROM THE POOL! and we allocate buffers to the pool
-                //  outside of the hotpath? we could have a static pool of buffers?
-                let buf = bytes::Bytes::copy_from_slice(pkt.buf);
+                // the payload was decrypted in-place in the io buffer, copy it into our receive buffer
+                let buf = self.recv_buffer.copy_from_slice(pkt.buf);
                 // TODO: control the size/memory of the packet queue?
                 self.packet_queue.push_back(buf);
             }
@@ -518,6 +594,14 @@ impl<Ctx> NetcodeClient<Ctx> {
         self.id
     }
 
+    /// Returns the keys of the connect token (client-to-server key, server-to-client key)
+    pub(crate) fn session_keys(&self) -> (Key, Key) {
+        (
+            self.token.client_to_server_key,
+            self.token.server_to_client_key,
+        )
+    }
+
     /// Prepares the client to connect to the server.
     ///
     /// This function does not perform any IO, it only readies the client to send/receive packets on the next call to [`update`](NetcodeClient::update). <br>
@@ -598,14 +682,17 @@ impl<Ctx> NetcodeClient<Ctx> {
 
     /// Sends a packet to the server.
     ///
-    /// The provided buffer must be smaller than [`MAX_PACKET_SIZE`].
+    /// The provided buffer must be smaller than [`MAX_PACKET_SIZE`] (plus the compression header).
     pub fn send(&mut self, buf: &[u8], io: &mut Io) -> Result<()> {
         if self.state != ClientState::Connected {
             trace!("tried to send but not connected");
             return Ok(());
         }
-        if buf.len() > MAX_PACKET_SIZE {
-            return Err(Error::SizeMismatch(MAX_PACKET_SIZE, buf.len()));
+        if buf.len() > MAX_PACKET_SIZE + COMPRESSION_HEADER_BYTES {
+            return Err(Error::SizeMismatch(
+                MAX_PACKET_SIZE + COMPRESSION_HEADER_BYTES,
+                buf.len(),
+            ));
         }
         self.send_packet(PayloadPacket::create(buf), io)?;
         Ok(())
@@ -660,6 +747,9 @@ pub(crate) mod connection {
         pub client: NetcodeClient<Ctx>,
         pub io_config: IoConfig,
         pub io: Option<Io>,
+        pub(crate) compressor: PacketCompressor,
+        /// Compression algorithms supported by the server, learned from its packets
+        pub(crate) server_compression: u8,
     }
 
     impl<Ctx: Send + Sync> NetClient for Client<Ctx> {
@@ -667,6 +757,8 @@ pub(crate) mod connection {
             let io_config = self.io_config.clone();
             let io = io_config.connect()?;
             self.io = Some(io);
+            self.compressor = self.io_config.compressor();
+            self.server_compression = 0;
             self.client.connect();
             Ok(())
         }
@@ -705,12 +797,29 @@ pub(crate) mod connection {
         }
 
         fn recv(&mut self) -> Option<RecvPayload> {
-            self.client.recv()
+            while let Some(packet) = self.client.recv() {
+                let compressed_len = packet.len();
+                match self.compressor.decompress(packet) {
+                    Ok((payload, server_compression)) => {
+                        self.server_compression = server_compression;
+                        if let Some(io) = self.io.as_mut() {
+                            io.stats.compressed_bytes_received += compressed_len;
+                            io.stats.uncompressed_bytes_received += payload.len();
+                        }
+                        return Some(payload);
+                    }
+                    Err(e) => error!("could not decompress packet from server: {:?}", e),
+                }
+            }
+            None
         }
 
         fn send(&mut self, buf: &[u8]) -> Result<(), ConnectionError> {
             let io = self.io.as_mut().ok_or(ConnectionError::IoNotInitialized)?;
-            self.client.send(buf, io)?;
+            let payload = self.compressor.compress(buf, self.server_compression)?;
+            io.stats.uncompressed_bytes_sent += buf.len();
+            io.stats.compressed_bytes_sent += payload.len();
+            self.client.send(payload, io)?;
             Ok(())
         }
 
@@ -729,5 +838,9 @@ pub(crate) mod connection {
         fn io_mut(&mut self) -> Option<&mut Io> {
             self.io.as_mut()
         }
+
+        fn session_keys(&self) -> Option<(Key, Key)> {
+            Some(self.client.session_keys())
+        }
     }
 }
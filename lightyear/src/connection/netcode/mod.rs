@@ -141,6 +141,7 @@ pub(crate) const MAC_BYTES: usize = 16;
 pub(crate) const MAX_PKT_BUF_SIZE: usize = 1300;
 pub(crate) const CONNECTION_TIMEOUT_SEC: i32 = 15;
 pub(crate) const PACKET_SEND_RATE_SEC: f64 = 1.0 / 10.0;
+pub(crate) const MIGRATION_TIMEOUT_SEC: f64 = 1.0;
 
 /// The size of a private key in bytes.
 pub const PRIVATE_KEY_BYTES: usize = 32;
@@ -151,4 +152,7 @@ pub const CONNECT_TOKEN_BYTES: usize = 2048;
 /// The maximum size of a packet in bytes.
 pub const MAX_PACKET_SIZE: usize = 1200;
 /// The version of the netcode protocol implemented by this crate.
-pub const NETCODE_VERSION: &[u8; 13] = b"NETCODE 1.02\0";
+///
+/// This is not the standard `NETCODE 1.02`: the connection request also carries the
+/// [`ProtocolHash`](crate::prelude::ProtocolHash), and the denied packet carries a reason.
+pub const NETCODE_VERSION: &[u8; 13] = b"NETCODE 1.03\0";
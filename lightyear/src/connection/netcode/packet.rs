@@ -81,6 +81,8 @@ pub struct RequestPacket {
     pub expire_timestamp: u64,
     pub token_nonce: XNonce,
     pub token_data: Box<[u8; ConnectTokenPrivate::SIZE]>,
+    /// Hash of the client's protocol, so that the server can deny clients with an incompatible protocol
+    pub protocol_hash: u64,
 }
 
 impl RequestPacket {
@@ -89,6 +91,7 @@ impl RequestPacket {
         expire_timestamp: u64,
         token_nonce: XNonce,
         token_data: [u8; ConnectTokenPrivate::SIZE],
+        protocol_hash: u64,
     ) -> Packet<'static> {
         Packet::Request(RequestPacket {
             version_info: *NETCODE_VERSION,
@@ -96,6 +99,7 @@ impl RequestPacket {
             expire_timestamp,
             token_nonce,
             token_data: Box::new(token_data),
+            protocol_hash,
         })
     }
     pub fn validate(&self, protocol_id: u64, current_timestamp: u64) -> Result<(), Error> {
@@ -136,6 +140,7 @@ impl Bytes for RequestPacket {
         writer.write_u64::<LittleEndian>(self.expire_timestamp)?;
         writer.write_all(&self.token_nonce)?;
         writer.write_all(&self.token_data[..])?;
+        writer.write_u64::<LittleEndian>(self.protocol_hash)?;
         Ok(())
     }
 
@@ -149,12 +154,14 @@ impl Bytes for RequestPacket {
         let token_nonce = XNonce::from_slice(&nonce).to_owned();
         let mut token_data = [0; ConnectTokenPrivate::SIZE];
         reader.read_exact(&mut token_data)?;
+        let protocol_hash = reader.read_u64::<LittleEndian>()?;
         Ok(Self {
             version_info,
             protocol_id,
             expire_timestamp,
             token_nonce,
             token_data: Box::new(token_data),
+            protocol_hash,
         })
     }
 }
@@ -210,6 +217,9 @@ impl Bytes for DeniedReason {
                     ));
                 }
             }
+            DeniedReason::ProtocolMismatch => {
+                writer.write_u8(7)?;
+            }
         }
         Ok(())
     }
@@ -235,6 +245,8 @@ impl Bytes for DeniedReason {
             let reason_str = String::from_utf8(string_buf)
                 .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid denied reason"))?;
             Ok(DeniedReason::Custom(reason_str))
+        } else if variant == 7 {
+            Ok(DeniedReason::ProtocolMismatch)
         } else {
             Err(io::Error::new(
                 io::ErrorKind::InvalidData,
@@ -403,6 +415,15 @@ impl<'p> Packet<'p> {
     pub const KEEP_ALIVE: PacketKind = 4;
     pub const PAYLOAD: PacketKind = 5;
     pub const DISCONNECT: PacketKind = 6;
+    /// First byte of a migration packet.
+    ///
+    /// A migration packet is a keep-alive packet prefixed with the client id in clear, so that
+    /// the server can authenticate a client that is sending from a new address.
+    /// It cannot be confused with a regular packet, because the prefix byte of every packet other
+    /// than a connection request has a non-zero sequence length in its high bits.
+    pub const MIGRATION: u8 = 7;
+    /// Size of the header (marker byte + client id) that precedes the keep-alive packet in a migration packet
+    pub const MIGRATION_HEADER_BYTES: usize = size_of::<u8>() + size_of::<ClientId>();
     fn kind(&self) -> PacketKind {
         match self {
             Packet::Request(_) => Packet::REQUEST,
@@ -551,6 +572,44 @@ impl<'p> Packet<'p> {
     }
 }
 
+impl Packet<'static> {
+    /// Write a migration packet for `client_id`, encrypted with the client-to-server key.
+    pub fn write_migration(
+        out: &mut [u8],
+        client_id: ClientId,
+        sequence: u64,
+        packet_key: &Key,
+        protocol_id: u64,
+    ) -> Result<usize, NetcodeError> {
+        if out.len() < Packet::MIGRATION_HEADER_BYTES {
+            return Err(Error::TooSmall.into());
+        }
+        out[0] = Packet::MIGRATION;
+        out[1..Packet::MIGRATION_HEADER_BYTES].copy_from_slice(&client_id.to_le_bytes());
+        let size = KeepAlivePacket::create(client_id).write(
+            &mut out[Packet::MIGRATION_HEADER_BYTES..],
+            sequence,
+            packet_key,
+            protocol_id,
+        )?;
+        Ok(Packet::MIGRATION_HEADER_BYTES + size)
+    }
+
+    /// Split a migration packet into the client id it claims to come from and the
+    /// encrypted keep-alive packet, which should be read with the receive key of that client.
+    pub fn read_migration_header(buf: &mut [u8]) -> Option<(ClientId, &mut [u8])> {
+        if buf.len() <= Packet::MIGRATION_HEADER_BYTES || buf[0] != Packet::MIGRATION {
+            return None;
+        }
+        let client_id = ClientId::from_le_bytes(
+            buf[1..Packet::MIGRATION_HEADER_BYTES]
+                .try_into()
+                .expect("slice has the size of a client id"),
+        );
+        Some((client_id, &mut buf[Packet::MIGRATION_HEADER_BYTES..]))
+    }
+}
+
 pub fn sequence_len(sequence: u64) -> u8 {
     std::cmp::max(8 - sequence.leading_zeros() as u8 / 8, 1)
 }
@@ -618,6 +677,7 @@ mod tests {
             expire_timestamp,
             token_nonce: nonce,
             token_data: Box::new(token_data),
+            protocol_hash: 0xdead_beef,
         });
 
         let mut buf = [0u8; MAX_PACKET_SIZE];
@@ -643,6 +703,7 @@ mod tests {
         assert_eq!(req_pkt.protocol_id, protocol_id);
         assert_eq!(req_pkt.expire_timestamp, expire_timestamp);
         assert_eq!(req_pkt.token_nonce, nonce);
+        assert_eq!(req_pkt.protocol_hash, 0xdead_beef);
 
         let mut reader = std::io::Cursor::new(&req_pkt.token_data[..]);
         let connect_token_private = ConnectTokenPrivate::read_from(&mut reader).unwrap();
@@ -787,6 +848,44 @@ mod tests {
         assert_eq!(keep_alive_pkt.client_id, client_id);
     }
 
+    #[test]
+    pub fn migration_packet() {
+        let packet_key = generate_key();
+        let protocol_id = 0x1234_5678_9abc_def0;
+        let sequence = 0u64;
+        let client_id = 0x1234;
+        let mut replay_protection = ReplayProtection::new();
+
+        let mut buf = [0u8; MAX_PKT_BUF_SIZE];
+        let size = Packet::write_migration(&mut buf, client_id, sequence, &packet_key, protocol_id)
+            .unwrap();
+
+        let (read_client_id, keep_alive_buf) =
+            Packet::read_migration_header(&mut buf[..size]).unwrap();
+        assert_eq!(read_client_id, client_id);
+
+        let packet = Packet::read(
+            keep_alive_buf,
+            protocol_id,
+            0,
+            packet_key,
+            Some(&mut replay_protection),
+            0xff,
+        )
+        .unwrap();
+
+        let Packet::KeepAlive(keep_alive_pkt) = packet else {
+            panic!("wrong packet type");
+        };
+        assert_eq!(keep_alive_pkt.client_id, client_id);
+
+        // regular packets are not mistaken for migration packets
+        let size = KeepAlivePacket::create(client_id)
+            .write(&mut buf, sequence, &packet_key, protocol_id)
+            .unwrap();
+        assert!(Packet::read_migration_header(&mut buf[..size]).is_none());
+    }
+
     #[test]
     pub fn disconnect_packet() {
         let packet_key = generate_key();
@@ -4,7 +4,7 @@ use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use bevy::prelude::Resource;
-use tracing::{debug, error, trace};
+use tracing::{debug, error, info, trace};
 
 #[cfg(feature = "trace")]
 use tracing::{instrument, Level};
@@ -14,9 +14,10 @@ use crate::connection::netcode::token::TOKEN_EXPIRE_SEC;
 use crate::connection::server::{
     ConnectionRequestHandler, DefaultConnectionRequestHandler, DeniedReason, IoConfig, NetServer,
 };
-use crate::packet::packet_builder::RecvPayload;
+use crate::packet::packet_builder::{RecvBuffer, RecvPayload};
 use crate::server::config::NetcodeConfig;
 use crate::server::io::{Io, ServerIoEvent, ServerNetworkEventSender};
+use crate::transport::middleware::compression::{PacketCompressor, COMPRESSION_HEADER_BYTES};
 use crate::transport::{PacketReceiver, PacketSender};
 
 use super::{
@@ -126,6 +127,8 @@ struct ConnectionCache {
 
     // packet queue for all clients
     packet_queue: VecDeque<(RecvPayload, ClientId)>,
+    // buffer that the payloads of all clients are copied into
+    recv_buffer: RecvBuffer,
 
     // corresponds to the server time
     time: f64,
@@ -138,6 +141,7 @@ impl ConnectionCache {
             client_id_map: HashMap::with_capacity(MAX_CLIENTS),
             replay_protection: HashMap::with_capacity(MAX_CLIENTS),
             packet_queue: VecDeque::with_capacity(MAX_CLIENTS * 2),
+            recv_buffer: RecvBuffer::default(),
             time: server_time,
         }
     }
@@ -188,6 +192,26 @@ impl ConnectionCache {
         self.clients.remove(&client_id);
     }
 
+    /// Rebind the connection of `client_id` to a new address.
+    ///
+    /// Returns false if the address is already used by another client.
+    fn migrate(&mut self, client_id: ClientId, addr: SocketAddr) -> bool {
+        if self
+            .client_id_map
+            .get(&addr)
+            .is_some_and(|id| *id != client_id)
+        {
+            return false;
+        }
+        let Some(conn) = self.clients.get_mut(&client_id) else {
+            return false;
+        };
+        self.client_id_map.remove(&conn.addr);
+        conn.addr = addr;
+        self.client_id_map.insert(addr, client_id);
+        true
+    }
+
     fn ids(&self) -> Vec<ClientId> {
         self.clients.keys().cloned().collect()
     }
@@ -246,6 +270,7 @@ pub struct ServerConfig<Ctx> {
     client_timeout_secs: i32,
     connection_request_handler: Arc<dyn ConnectionRequestHandler>,
     server_addr: SocketAddr,
+    protocol_hash: u64,
     context: Ctx,
     on_connect: Option<Callback<Ctx>>,
     on_disconnect: Option<Callback<Ctx>>,
@@ -260,6 +285,7 @@ impl Default for ServerConfig<()> {
             client_timeout_secs: CLIENT_TIMEOUT_SECS,
             connection_request_handler: Arc::new(DefaultConnectionRequestHandler),
             server_addr: SocketAddr::from(([0, 0, 0, 0], 0)),
+            protocol_hash: 0,
             context: (),
             on_connect: None,
             on_disconnect: None,
@@ -281,6 +307,7 @@ impl<Ctx> ServerConfig<Ctx> {
             client_timeout_secs: CLIENT_TIMEOUT_SECS,
             connection_request_handler: Arc::new(DefaultConnectionRequestHandler),
             server_addr: SocketAddr::from(([0, 0, 0, 0], 0)),
+            protocol_hash: 0,
             context: ctx,
             on_connect: None,
             on_disconnect: None,
@@ -304,6 +331,13 @@ impl<Ctx> ServerConfig<Ctx> {
         self.client_timeout_secs = client_timeout_secs;
         self
     }
+    /// Set the hash of the server's protocol. Clients with a different hash are denied with
+    /// [`DeniedReason::ProtocolMismatch`].
+    /// The default is 0.
+    pub fn protocol_hash(mut self, protocol_hash: u64) -> Self {
+        self.protocol_hash = protocol_hash;
+        self
+    }
     /// Set the duration (in seconds) after which ConnectTokens generated by the server will expire
     /// The default is 30 seconds.
     pub fn token_expire_secs(mut self, expire_secs: i32) -> Self {
@@ -442,6 +476,14 @@ impl<Ctx> NetcodeServer<Ctx> {
     }
 }
 
+impl<Ctx> NetcodeServer<Ctx> {
+    /// Set the hash of the server's protocol, which is compared with the hash of the next connection requests.
+    /// See [`ServerConfig::protocol_hash`].
+    pub(crate) fn set_protocol_hash(&mut self, protocol_hash: u64) {
+        self.cfg.protocol_hash = protocol_hash;
+    }
+}
+
 impl<Ctx> NetcodeServer<Ctx> {
     const ALLOWED_PACKETS: u8 = 1 << Packet::REQUEST
         | 1 << Packet::RESPONSE
@@ -500,8 +542,8 @@ impl<Ctx> NetcodeServer<Ctx> {
                     // return the buffer to the pool
                     // self.conn_cache.buffer_pool.attach(reader);
 
-                    // TODO: use a pool of buffers to avoid re-allocation
-                    let buf = bytes::Bytes::copy_from_slice(packet.buf);
+                    // the payload was decrypted in-place in the io buffer, copy it into our receive buffer
+                    let buf = self.conn_cache.recv_buffer.copy_from_slice(packet.buf);
                     self.conn_cache.packet_queue.push_back((buf, idx));
                 }
                 Ok(())
@@ -616,6 +658,20 @@ impl<Ctx> NetcodeServer<Ctx> {
             )?;
             return Ok(());
         };
+        if packet.protocol_hash != self.cfg.protocol_hash {
+            debug!(
+                client_hash = packet.protocol_hash,
+                server_hash = self.cfg.protocol_hash,
+                "server denied connection request. the client's protocol is different"
+            );
+            self.send_to_addr(
+                DeniedPacket::create(DeniedReason::ProtocolMismatch),
+                from_addr,
+                token.server_to_client_key,
+                sender,
+            )?;
+            return Ok(());
+        }
         if let Some(denied_reason) = self
             .cfg
             .connection_request_handler
@@ -707,6 +763,55 @@ impl<Ctx> NetcodeServer<Ctx> {
         self.on_connect(id, from_addr);
         Ok(())
     }
+    /// Process a migration packet, sent by a connected client that stopped hearing from the server
+    /// because its address might have changed (for example when switching from Wi-Fi to cellular).
+    ///
+    /// If the wrapped keep-alive packet is authenticated by the receive key of the client and is not a replay,
+    /// the existing connection is rebound to the new address instead of treating it as a new client.
+    /// Everything above netcode is keyed by client id, so the replication state of the client is preserved.
+    fn process_migration(&mut self, buf: &mut [u8], now: u64, addr: SocketAddr) -> Result<()> {
+        let Some((client_id, buf)) = Packet::read_migration_header(buf) else {
+            debug!("server ignored invalid migration packet from {addr}");
+            return Ok(());
+        };
+        let Some(conn) = self
+            .conn_cache
+            .find_by_id(client_id)
+            .filter(|conn| conn.is_connected())
+        else {
+            debug!("server ignored migration packet for unknown client {client_id} from {addr}");
+            return Ok(());
+        };
+        match Packet::read(
+            buf,
+            self.protocol_id,
+            now,
+            conn.receive_key,
+            self.conn_cache.replay_protection.get_mut(&client_id),
+            1 << Packet::KEEP_ALIVE,
+        ) {
+            Ok(Packet::KeepAlive(packet)) if packet.client_id == client_id => {}
+            Ok(_) => {
+                debug!("server ignored migration packet with invalid content from {addr}");
+                return Ok(());
+            }
+            Err(e) => {
+                debug!(error = ?e, "server ignored migration packet from {addr}");
+                return Ok(());
+            }
+        }
+        if conn.addr != addr {
+            if !self.conn_cache.migrate(client_id, addr) {
+                debug!("server ignored migration of client {client_id} to {addr}: address already in use");
+                return Ok(());
+            }
+            info!(
+                "server migrated client {client_id} from {} to {addr}",
+                conn.addr
+            );
+        }
+        self.touch_client(Some(client_id))
+    }
     fn check_for_timeouts(&mut self) {
         for id in self.conn_cache.ids() {
             let Some(client) = self.conn_cache.clients.get_mut(&id) else {
@@ -757,6 +862,9 @@ impl<Ctx> NetcodeServer<Ctx> {
             // Too small to be a packet
             return Ok(());
         }
+        if buf[0] == Packet::MIGRATION {
+            return self.process_migration(buf, now, addr);
+        }
         let (key, replay_protection) = match self.conn_cache.find_by_addr(&addr) {
             // Regardless of whether an entry in the connection cache exists for the client or not,
             // if the packet is a connection request we need to use the server's private key to decrypt it.
@@ -869,11 +977,14 @@ impl<Ctx> NetcodeServer<Ctx> {
     }
     /// Sends a packet to a client.
     ///
-    /// The provided buffer must be smaller than [`MAX_PACKET_SIZE`].
+    /// The provided buffer must be smaller than [`MAX_PACKET_SIZE`] (plus the compression header).
     #[cfg_attr(feature = "trace", instrument(level = Level::INFO, skip_all))]
     pub fn send(&mut self, buf: &[u8], client_id: ClientId, io: &mut Io) -> Result<()> {
-        if buf.len() > MAX_PACKET_SIZE {
-            return Err(Error::SizeMismatch(MAX_PACKET_SIZE, buf.len()));
+        if buf.len() > MAX_PACKET_SIZE + COMPRESSION_HEADER_BYTES {
+            return Err(Error::SizeMismatch(
+                MAX_PACKET_SIZE + COMPRESSION_HEADER_BYTES,
+                buf.len(),
+            ));
         }
         let Some(conn) = self.conn_cache.clients.get_mut(&client_id) else {
             return Err(Error::ClientNotFound);
@@ -1016,6 +1127,14 @@ impl<Ctx> NetcodeServer<Ctx> {
         self.conn_cache.clients.get(&client_id).map(|c| c.addr)
     }
 
+    /// Gets the keys used to communicate with a client (send key, receive key).
+    pub(crate) fn session_keys(&self, client_id: ClientId) -> Option<(Key, Key)> {
+        self.conn_cache
+            .clients
+            .get(&client_id)
+            .map(|c| (c.send_key, c.receive_key))
+    }
+
     /// Gets the address of the server
     pub fn local_addr(&self) -> SocketAddr {
         self.cfg.server_addr
@@ -1038,12 +1157,17 @@ pub(crate) mod connection {
         pub(crate) server: NetcodeServer<NetcodeServerContext>,
         io_config: IoConfig,
         io: Option<Io>,
+        compressor: PacketCompressor,
+        /// Compression algorithms supported by each client, learned from their packets
+        client_compression: HashMap<ClientId, u8>,
     }
 
     impl NetServer for Server {
         fn start(&mut self) -> Result<(), ConnectionError> {
             let io_config = self.io_config.clone();
             let io = io_config.start()?;
+            self.compressor = self.io_config.compressor();
+            self.client_compression.clear();
             self.server
                 .cfg
                 .context
@@ -1098,13 +1222,30 @@ pub(crate) mod connection {
             self.server.cfg.context.disconnections.clear();
 
             self.server.try_update(delta_ms, io)?;
+            for client_id in &self.server.cfg.context.disconnections {
+                if let id::ClientId::Netcode(id) = client_id {
+                    self.client_compression.remove(id);
+                }
+            }
             Ok(())
         }
 
         fn recv(&mut self) -> Option<(RecvPayload, id::ClientId)> {
-            self.server
-                .recv()
-                .map(|(packet, id)| (packet, id::ClientId::Netcode(id)))
+            while let Some((packet, id)) = self.server.recv() {
+                let compressed_len = packet.len();
+                match self.compressor.decompress(packet) {
+                    Ok((payload, client_compression)) => {
+                        self.client_compression.insert(id, client_compression);
+                        if let Some(io) = self.io.as_mut() {
+                            io.stats.compressed_bytes_received += compressed_len;
+                            io.stats.uncompressed_bytes_received += payload.len();
+                        }
+                        return Some((payload, id::ClientId::Netcode(id)));
+                    }
+                    Err(e) => error!("could not decompress packet from client {id}: {:?}", e),
+                }
+            }
+            None
         }
 
         fn send(&mut self, buf: &[u8], client_id: id::ClientId) -> Result<(), ConnectionError> {
@@ -1112,7 +1253,15 @@ pub(crate) mod connection {
             let id::ClientId::Netcode(client_id) = client_id else {
                 return Err(ConnectionError::InvalidConnectionType);
             };
-            self.server.send(buf, client_id, io)?;
+            let client_compression = self
+                .client_compression
+                .get(&client_id)
+                .copied()
+                .unwrap_or_default();
+            let payload = self.compressor.compress(buf, client_compression)?;
+            io.stats.uncompressed_bytes_sent += buf.len();
+            io.stats.compressed_bytes_sent += payload.len();
+            self.server.send(payload, client_id, io)?;
             Ok(())
         }
 
@@ -1130,6 +1279,13 @@ pub(crate) mod connection {
         fn io_mut(&mut self) -> Option<&mut Io> {
             self.io.as_mut()
         }
+
+        fn session_keys(&self, client_id: id::ClientId) -> Option<(Key, Key)> {
+            match client_id {
+                id::ClientId::Netcode(id) => self.server.session_keys(id),
+                _ => None,
+            }
+        }
     }
 
     impl Server {
@@ -1161,6 +1317,8 @@ pub(crate) mod connection {
 
             Self {
                 server,
+                compressor: io_config.compressor(),
+                client_compression: HashMap::new(),
                 io_config,
                 io: None,
             }
@@ -1179,3 +1337,85 @@ pub(crate) mod connection {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::utils;
+    use super::*;
+
+    /// Sender that drops the packets, the migration does not send anything back
+    struct NoopSender;
+
+    impl PacketSender for NoopSender {
+        fn send(&mut self, _: &[u8], _: &SocketAddr) -> crate::transport::error::Result<()> {
+            Ok(())
+        }
+    }
+
+    const PROTOCOL_ID: u64 = 0x1122334455667788;
+
+    fn connect_client(server: &mut NetcodeServer, client_id: ClientId, addr: SocketAddr) -> Key {
+        let receive_key = crypto::generate_key();
+        server
+            .conn_cache
+            .add(client_id, addr, 5, crypto::generate_key(), receive_key);
+        server
+            .conn_cache
+            .clients
+            .get_mut(&client_id)
+            .unwrap()
+            .connect();
+        receive_key
+    }
+
+    fn recv_migration(
+        server: &mut NetcodeServer,
+        client_id: ClientId,
+        sequence: u64,
+        key: &Key,
+        addr: SocketAddr,
+    ) {
+        let mut buf = [0u8; MAX_PKT_BUF_SIZE];
+        let size =
+            Packet::write_migration(&mut buf, client_id, sequence, key, PROTOCOL_ID).unwrap();
+        server
+            .recv_packet(&mut buf[..size], utils::now(), addr, &mut NoopSender)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_migration() {
+        let mut server = NetcodeServer::with_config(
+            PROTOCOL_ID,
+            crypto::generate_key(),
+            ServerConfig::default(),
+        )
+        .unwrap();
+        let old_addr: SocketAddr = "127.0.0.1:1000".parse().unwrap();
+        let new_addr: SocketAddr = "127.0.0.1:2000".parse().unwrap();
+        let key = connect_client(&mut server, 1, old_addr);
+
+        // the packets of the client now arrive from a new address
+        recv_migration(&mut server, 1, 0, &key, new_addr);
+
+        // the session is kept, and the old address is released
+        assert_eq!(server.connected_client_ids().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(server.client_addr(1), Some(new_addr));
+        assert_eq!(
+            server.conn_cache.find_by_addr(&new_addr).map(|(id, _)| id),
+            Some(1)
+        );
+        assert!(server.conn_cache.find_by_addr(&old_addr).is_none());
+
+        // the client can't take over the address of another client
+        let other_addr: SocketAddr = "127.0.0.1:3000".parse().unwrap();
+        connect_client(&mut server, 2, other_addr);
+        recv_migration(&mut server, 1, 1, &key, other_addr);
+        assert_eq!(server.client_addr(1), Some(new_addr));
+        assert_eq!(server.client_addr(2), Some(other_addr));
+
+        // a migration packet that is not authenticated by the key of the client is ignored
+        recv_migration(&mut server, 1, 2, &crypto::generate_key(), old_addr);
+        assert_eq!(server.client_addr(1), Some(new_addr));
+    }
+}
@@ -8,6 +8,7 @@ use std::fmt::Debug;
 use std::sync::Arc;
 
 use crate::connection::id::ClientId;
+use crate::connection::netcode::Key;
 #[cfg(all(feature = "steam", not(target_family = "wasm")))]
 use crate::connection::steam::{server::SteamConfig, steamworks_client::SteamworksClient};
 use crate::packet::packet_builder::RecvPayload;
@@ -28,6 +29,8 @@ pub enum DeniedReason {
     TokenAlreadyUsed,
     InvalidToken,
     Custom(String),
+    /// The client's protocol (messages, components, channels) is different from the server's
+    ProtocolMismatch,
 }
 
 /// Trait for handling connection requests from clients.
@@ -83,6 +86,14 @@ pub trait NetServer: Send + Sync {
     fn io(&self) -> Option<&Io>;
 
     fn io_mut(&mut self) -> Option<&mut Io>;
+
+    /// Keys exchanged with a client during the connection handshake (send key, receive key).
+    ///
+    /// They are used to authenticate the messages of the authenticated channels.
+    /// Returns None if the connection does not perform a key exchange.
+    fn session_keys(&self, _client_id: ClientId) -> Option<(Key, Key)> {
+        None
+    }
 }
 
 #[enum_dispatch(NetServer)]
@@ -137,6 +148,13 @@ impl Default for NetConfig {
 }
 
 impl NetConfig {
+    /// Returns true if the connection exchanges session keys with the clients during the handshake.
+    ///
+    /// The session keys are required to use the authenticated channels.
+    pub(crate) fn provides_session_keys(&self) -> bool {
+        matches!(self, NetConfig::Netcode { .. })
+    }
+
     pub fn build_server(self) -> ServerConnection {
         match self {
             NetConfig::Netcode { config, io } => {
@@ -195,6 +213,18 @@ impl ServerConnections {
         }
     }
 
+    /// Set the [`ProtocolHash`](crate::prelude::ProtocolHash) used to deny the clients with a different protocol.
+    ///
+    /// Only the netcode servers perform the check; it is ignored by the other servers.
+    pub(crate) fn set_protocol_hash(&mut self, protocol_hash: u64) {
+        for server in &mut self.servers {
+            #[allow(irrefutable_let_patterns)]
+            if let ServerConnection::Netcode(server) = server {
+                server.server.set_protocol_hash(protocol_hash);
+            }
+        }
+    }
+
     /// Start listening for client connections on all internal servers
     pub fn start(&mut self) -> Result<(), ConnectionError> {
         for server in &mut self.servers {
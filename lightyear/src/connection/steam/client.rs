@@ -213,14 +213,13 @@ impl NetClient for Client {
     }
 
     fn id(&self) -> ClientId {
-        ClientId::Steam(
+        ClientId::from(
             self.steamworks_client
                 .try_read()
                 .expect("could not get steamworks client")
                 .get_client()
                 .user()
-                .steam_id()
-                .raw(),
+                .steam_id(),
         )
     }
 
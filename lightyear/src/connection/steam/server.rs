@@ -204,7 +204,7 @@ impl NetServer for Server {
             match event {
                 ListenSocketEvent::Connected(event) => {
                     if let Some(steam_id) = event.remote().steam_id() {
-                        let client_id = ClientId::Steam(steam_id.raw());
+                        let client_id = ClientId::from(steam_id);
                         info!("Client with id: {:?} connected!", client_id);
                         self.new_connections.push(client_id);
                         self.connections.insert(client_id, event.take_connection());
@@ -214,7 +214,7 @@ impl NetServer for Server {
                 }
                 ListenSocketEvent::Disconnected(event) => {
                     if let Some(steam_id) = event.remote().steam_id() {
-                        let client_id = ClientId::Steam(steam_id.raw());
+                        let client_id = ClientId::from(steam_id);
                         info!(
                             "Client with id: {:?} disconnected! Reason: {:?}",
                             client_id,
@@ -241,9 +241,12 @@ impl NetServer for Server {
                     if let Some(denied_reason) = self
                         .config
                         .connection_request_handler
-                        .handle_request(ClientId::Steam(steam_id.raw()))
+                        .handle_request(ClientId::from(steam_id))
                     {
-                        event.reject(NetConnectionEnd::AppGeneric, Some("{denied_reason:?}"));
+                        event.reject(
+                            NetConnectionEnd::AppGeneric,
+                            Some(&format!("{denied_reason:?}")),
+                        );
                         continue;
                     } else {
                         if let Err(e) = event.accept() {
@@ -185,45 +185,70 @@ pub mod prelude {
 
     pub use crate::channel::builder::{
         Channel, ChannelBuilder, ChannelContainer, ChannelDirection, ChannelMode, ChannelSettings,
-        InputChannel, ReliableSettings,
+        ChecksumChannel, ChunkChannel, InputChannel, ReliableSettings,
     };
+    pub use crate::channel::compression::MessageCompression;
+    pub use crate::channel::senders::error::ChannelSendError;
+    pub use crate::channel::stats::ChannelStats;
     pub use crate::client::prediction::prespawn::PreSpawnedPlayerObject;
     pub use crate::connection::id::ClientId;
     pub use crate::connection::netcode::{generate_key, ConnectToken, Key};
     #[cfg(feature = "leafwing")]
     pub use crate::inputs::leafwing::{input_message::InputMessage, LeafwingUserAction};
     pub use crate::inputs::native::UserAction;
+    pub use crate::packet::congestion::CongestionConfig;
     pub use crate::packet::error::PacketError;
     pub use crate::packet::message::Message;
     pub use crate::protocol::channel::{AppChannelExt, ChannelKind, ChannelRegistry};
-    pub use crate::protocol::component::{AppComponentExt, ComponentRegistry, Linear};
+    pub use crate::protocol::component::{
+        AppComponentExt, ApplyHookFn, ComponentRegistry, Linear, ShouldRollbackFn, ValidateFn,
+    };
+    pub use crate::protocol::hash::ProtocolHash;
     pub use crate::protocol::message::{AppMessageExt, MessageRegistry};
-    pub use crate::protocol::serialize::AppSerializeExt;
+    pub use crate::protocol::serialize::{
+        AppSerializeExt, Bincode, BincodeLegacy, DeserializeFn, Extensible, SerializeFn,
+        SerializerBackend,
+    };
+    pub use crate::shared::checksum::{AppChecksumExt, DesyncDetectionPlugin};
+    pub use crate::shared::chunk::{ChunkId, ChunkStreamingPlugin};
     pub use crate::shared::config::{Mode, SharedConfig};
     #[cfg(feature = "leafwing")]
     pub use crate::shared::input::leafwing::LeafwingInputPlugin;
     pub use crate::shared::input::native::InputPlugin;
     pub use crate::shared::ping::manager::PingConfig;
     pub use crate::shared::plugin::{NetworkIdentity, SharedPlugin};
+    pub use crate::shared::projectile::{Projectile, ProjectileCommandsExt, ProjectilePlugin};
+    pub use crate::shared::replication::authority::{AuthorityPeer, HasAuthority};
     pub use crate::shared::replication::components::{
         DeltaCompression, DisabledComponent, NetworkRelevanceMode, OverrideTargetComponent,
-        PrePredicted, ReplicateHierarchy, ReplicateOnceComponent, Replicated, Replicating,
-        ReplicationGroup, ReplicationTarget, ShouldBePredicted, TargetEntity,
+        PauseReplication, PrePredicted, ReplicateHierarchy, ReplicateOnce, ReplicateOnceComponent,
+        Replicated, Replicating, ReplicationGroup, ReplicationRate, ReplicationTarget,
+        ShouldBePredicted, TargetEntity,
     };
     pub use crate::shared::replication::entity_map::RemoteEntityMap;
     pub use crate::shared::replication::hierarchy::ParentSync;
     pub use crate::shared::replication::network_target::NetworkTarget;
     pub use crate::shared::replication::plugin::ReplicationConfig;
+    pub use crate::shared::replication::prefab::{Prefab, PrefabPlugin, PrefabRegistry};
+    pub use crate::shared::replication::reflect::{
+        AppReflectReplicationExt, ReflectReplicationPlugin, ReflectedComponents,
+    };
     pub use crate::shared::replication::resources::{
         ReplicateResourceExt, ReplicateResourceMetadata, StopReplicateResourceExt,
     };
+    pub use crate::shared::replication::DespawnReason;
     pub use crate::shared::run_conditions::*;
     pub use crate::shared::sets::{FixedUpdateSet, MainSet};
     pub use crate::shared::tick_manager::TickManager;
     pub use crate::shared::tick_manager::{Tick, TickConfig};
     pub use crate::shared::time_manager::TimeManager;
     pub use crate::transport::middleware::compression::CompressionConfig;
-    pub use crate::transport::middleware::conditioner::LinkConditionerConfig;
+    pub use crate::transport::middleware::conditioner::{
+        GilbertElliottConfig, JitterDistribution, LinkConditionerConfig,
+    };
+    pub use crate::transport::middleware::PacketMiddleware;
+    #[cfg(not(target_family = "wasm"))]
+    pub use crate::transport::udp::{UdpPollStrategy, UdpSocketConfig};
 
     pub mod client {
         pub use crate::client::components::{
@@ -231,10 +256,12 @@ pub mod prelude {
         };
         pub use crate::client::config::{ClientConfig, NetcodeConfig, PacketConfig};
         pub use crate::client::connection::ConnectionManager;
+        pub use crate::client::entity_map::NetworkEntityMap;
         pub use crate::client::error::ClientError;
         pub use crate::client::events::{
             ComponentInsertEvent, ComponentRemoveEvent, ComponentUpdateEvent, ConnectEvent,
-            DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent, InputEvent, MessageEvent,
+            DisconnectEvent, EntityDespawnEvent, EntityMapEvent, EntitySpawnEvent,
+            InitialSyncComplete, InputEvent, MessageDelivered, MessageEvent, MessageLost,
         };
         #[cfg(feature = "leafwing")]
         pub use crate::client::input::leafwing::{LeafwingInputConfig, ToggleActions};
@@ -251,22 +278,38 @@ pub mod prelude {
         pub use crate::client::networking::{ClientCommands, NetworkingState};
         pub use crate::client::plugin::ClientPlugins;
         pub use crate::client::prediction::correction::Correction;
-        pub use crate::client::prediction::despawn::PredictionDespawnCommandsExt;
+        pub use crate::client::prediction::despawn::{
+            PredictionDespawnCommandsExt, PredictionHidden,
+        };
+        pub use crate::client::prediction::extrapolation::{Extrapolated, ExtrapolationPlugin};
+        pub use crate::client::prediction::local_rollback::{AppRollbackExt, PredictedSpawn};
         pub use crate::client::prediction::plugin::is_in_rollback;
         pub use crate::client::prediction::plugin::{PredictionConfig, PredictionSet};
-        pub use crate::client::prediction::rollback::{Rollback, RollbackState};
+        pub use crate::client::prediction::rollback::{
+            PredictionGroup, Rollback, RollbackCause, RollbackEndEvent, RollbackSnapEvent,
+            RollbackStartEvent, RollbackState,
+        };
+        pub use crate::client::prediction::smoothing::VisualErrorOffset;
+        pub use crate::client::prediction::spawn::PredictionCommandsExt;
         pub use crate::client::prediction::Predicted;
         pub use crate::client::replication::commands::DespawnReplicationCommandExt;
         pub use crate::client::replication::send::Replicate;
+        pub use crate::client::replication::ClientReplicationSet;
         pub use crate::client::sync::SyncConfig;
         pub use crate::connection::client::{
             Authentication, ClientConnection, IoConfig, NetClient, NetConfig,
         };
         #[cfg(all(feature = "steam", not(target_family = "wasm")))]
         pub use crate::connection::steam::client::{SocketConfig, SteamConfig};
+        pub use crate::shared::checksum::DesyncDetected;
+        pub use crate::shared::chunk::{ChunkLoadEvent, ChunkUnloadEvent, LoadedChunks};
+        pub use crate::shared::projectile::{ProjectileCancelled, ProjectileConfirmed};
     }
     pub mod server {
-        #[cfg(all(feature = "webtransport", not(target_family = "wasm")))]
+        #[cfg(all(
+            any(feature = "webtransport", feature = "quic"),
+            not(target_family = "wasm")
+        ))]
         pub use wtransport::tls::Identity;
 
         pub use crate::connection::server::{
@@ -280,19 +323,30 @@ pub mod prelude {
         pub use crate::server::error::ServerError;
         pub use crate::server::events::{
             ComponentInsertEvent, ComponentRemoveEvent, ComponentUpdateEvent, ConnectEvent,
-            DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent, InputEvent, MessageEvent,
+            DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent, InputEvent, MessageDelivered,
+            MessageEvent, MessageLost,
         };
         pub use crate::server::io::config::ServerTransport;
         pub use crate::server::io::Io;
         pub use crate::server::networking::{NetworkingState, ServerCommands};
         pub use crate::server::plugin::ServerPlugins;
         pub use crate::server::relevance::immediate::RelevanceManager;
-        pub use crate::server::relevance::room::{RoomId, RoomManager};
-        pub use crate::server::replication::commands::DespawnReplicationCommandExt;
+        pub use crate::server::relevance::predicate::AppRelevancePredicateExt;
+        pub use crate::server::relevance::room::{
+            RoomId, RoomManager, RoomReplicationTarget, RoomTarget,
+        };
+        pub use crate::server::relevance::spatial::{
+            ClientView, SpatialGrid, SpatialPosition, SpatialRelevancePlugin,
+        };
+        pub use crate::server::replication::commands::{
+            DespawnReplicationCommandExt, PauseReplicationCommandExt,
+        };
         pub use crate::server::replication::{
             send::{ControlledBy, Replicate, ServerFilter, SyncTarget},
             ReplicationSet, ServerReplicationSet,
         };
+        pub use crate::shared::chunk::ChunkManager;
+        pub use crate::shared::replication::authority::AuthorityCommandExt;
     }
 
     #[cfg(all(feature = "steam", not(target_family = "wasm")))]
@@ -316,6 +370,8 @@ pub mod server;
 
 pub mod shared;
 
+/// Harnesses to test the networking stack with your own protocol
+pub mod testing;
 #[cfg(test)]
 pub(crate) mod tests;
 
@@ -0,0 +1,228 @@
+//! Congestion control: reduce the rate at which packets are sent when the network is congested.
+//!
+//! The connection can be in two modes:
+//! - in good mode, packets are sent every frame
+//! - in congested mode, packets are sent at most once every [`CongestionConfig::congested_send_interval`],
+//!   and replication updates are only buffered when a packet is about to be sent
+//!
+//! We switch to congested mode as soon as the RTT or the packet loss go above their threshold.
+//! We switch back to good mode once the network conditions have been good for a recovery duration.
+//! To avoid flip-flopping between the two modes, the recovery duration is doubled every time the
+//! connection becomes congested shortly after recovering, and halved every time the connection
+//! stays in good mode for a while.
+use bevy::reflect::Reflect;
+use bevy::utils::Duration;
+use tracing::debug;
+
+/// The recovery duration cannot grow larger than this
+const MAX_RECOVERY_DURATION: Duration = Duration::from_secs(60);
+/// Duration after which the connection is considered to be stable in good mode
+const STABLE_DURATION: Duration = Duration::from_secs(10);
+/// Weight of each new sample in the exponential moving average of the packet loss
+const PACKET_LOSS_SMOOTHING: f32 = 0.1;
+
+#[derive(Clone, Debug, Reflect)]
+pub struct CongestionConfig {
+    /// If false, packets are sent every frame regardless of the network conditions
+    pub enabled: bool,
+    /// The connection is considered congested when the RTT goes above this threshold
+    pub rtt_threshold: Duration,
+    /// The connection is considered congested when the ratio of lost packets goes above this threshold
+    pub packet_loss_threshold: f32,
+    /// Minimum duration between two sends while the connection is congested
+    pub congested_send_interval: Duration,
+    /// How long the network conditions need to be good before we leave congested mode.
+    ///
+    /// This is the minimum value; it grows if the connection keeps getting congested.
+    pub recovery_duration: Duration,
+}
+
+impl Default for CongestionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            rtt_threshold: Duration::from_millis(250),
+            packet_loss_threshold: 0.1,
+            congested_send_interval: Duration::from_millis(100),
+            recovery_duration: Duration::from_secs(1),
+        }
+    }
+}
+
+impl CongestionConfig {
+    pub fn with_rtt_threshold(mut self, rtt_threshold: Duration) -> Self {
+        self.rtt_threshold = rtt_threshold;
+        self
+    }
+
+    pub fn with_packet_loss_threshold(mut self, packet_loss_threshold: f32) -> Self {
+        self.packet_loss_threshold = packet_loss_threshold;
+        self
+    }
+
+    pub fn with_congested_send_interval(mut self, congested_send_interval: Duration) -> Self {
+        self.congested_send_interval = congested_send_interval;
+        self
+    }
+
+    pub fn enable(mut self) -> Self {
+        self.enabled = true;
+        self
+    }
+}
+
+/// Tracks the network conditions of a connection to decide if we can send packets
+#[derive(Debug)]
+pub(crate) struct CongestionController {
+    config: CongestionConfig,
+    congested: bool,
+    /// Exponential moving average of the ratio of lost packets
+    packet_loss: f32,
+    /// Current duration that the network conditions need to be good before we leave congested mode
+    recovery_duration: Duration,
+    /// In congested mode, how long the network conditions have been good.
+    /// In good mode, how long we have been in good mode.
+    good_duration: Duration,
+    /// Time elapsed since we last sent packets
+    time_since_send: Duration,
+}
+
+impl CongestionController {
+    pub(crate) fn new(config: CongestionConfig) -> Self {
+        Self {
+            recovery_duration: config.recovery_duration,
+            config,
+            congested: false,
+            packet_loss: 0.0,
+            good_duration: Duration::default(),
+            time_since_send: Duration::default(),
+        }
+    }
+
+    /// Returns true if the connection is currently considered congested
+    pub(crate) fn is_congested(&self) -> bool {
+        self.congested
+    }
+
+    /// Returns true if we can send packets this frame
+    pub(crate) fn ready_to_send(&self) -> bool {
+        !self.congested || self.time_since_send >= self.config.congested_send_interval
+    }
+
+    /// Record that packets were sent this frame
+    pub(crate) fn on_send(&mut self) {
+        self.time_since_send = Duration::default();
+    }
+
+    /// Record that one of our packets was acked by the remote peer
+    pub(crate) fn on_packet_acked(&mut self) {
+        self.packet_loss += (0.0 - self.packet_loss) * PACKET_LOSS_SMOOTHING;
+    }
+
+    /// Record that one of our packets was lost
+    pub(crate) fn on_packet_lost(&mut self) {
+        self.packet_loss += (1.0 - self.packet_loss) * PACKET_LOSS_SMOOTHING;
+    }
+
+    /// Update the congestion mode using the latest network conditions
+    pub(crate) fn update(&mut self, delta: Duration, rtt: Duration) {
+        if !self.config.enabled {
+            return;
+        }
+        self.time_since_send += delta;
+        let bad_conditions =
+            rtt > self.config.rtt_threshold || self.packet_loss > self.config.packet_loss_threshold;
+        if self.congested {
+            if bad_conditions {
+                self.good_duration = Duration::default();
+                return;
+            }
+            self.good_duration += delta;
+            if self.good_duration >= self.recovery_duration {
+                debug!(?rtt, packet_loss = ?self.packet_loss, "Connection is no longer congested");
+                self.congested = false;
+                self.good_duration = Duration::default();
+            }
+        } else if bad_conditions {
+            debug!(?rtt, packet_loss = ?self.packet_loss, "Connection is congested");
+            // we got congested shortly after recovering, wait longer before recovering next time
+            if self.good_duration < STABLE_DURATION {
+                self.recovery_duration = (self.recovery_duration * 2).min(MAX_RECOVERY_DURATION);
+            }
+            self.congested = true;
+            self.good_duration = Duration::default();
+        } else {
+            self.good_duration += delta;
+            if self.good_duration >= STABLE_DURATION {
+                self.recovery_duration =
+                    (self.recovery_duration / 2).max(self.config.recovery_duration);
+                self.good_duration -= STABLE_DURATION;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn test_congestion_disabled() {
+        let mut controller = CongestionController::new(CongestionConfig::default());
+        for _ in 0..100 {
+            controller.on_packet_lost();
+        }
+        controller.update(FRAME, Duration::from_secs(1));
+        assert!(!controller.is_congested());
+        assert!(controller.ready_to_send());
+    }
+
+    #[test]
+    fn test_congestion_rtt() {
+        let config = CongestionConfig::default().enable();
+        let mut controller = CongestionController::new(config.clone());
+        controller.update(FRAME, Duration::from_millis(50));
+        assert!(!controller.is_congested());
+        assert!(controller.ready_to_send());
+
+        // high rtt: we only send once every congested_send_interval
+        controller.update(FRAME, Duration::from_millis(300));
+        assert!(controller.is_congested());
+        controller.on_send();
+        controller.update(FRAME, Duration::from_millis(300));
+        assert!(!controller.ready_to_send());
+        for _ in 0..10 {
+            controller.update(FRAME, Duration::from_millis(300));
+        }
+        assert!(controller.ready_to_send());
+
+        // we got congested right away, so the recovery duration doubled
+        assert_eq!(controller.recovery_duration, config.recovery_duration * 2);
+        controller.update(config.recovery_duration, Duration::from_millis(50));
+        assert!(controller.is_congested());
+        controller.update(config.recovery_duration, Duration::from_millis(50));
+        assert!(!controller.is_congested());
+
+        // staying in good mode decreases the recovery duration
+        controller.update(STABLE_DURATION, Duration::from_millis(50));
+        assert_eq!(controller.recovery_duration, config.recovery_duration);
+    }
+
+    #[test]
+    fn test_congestion_packet_loss() {
+        let mut controller = CongestionController::new(CongestionConfig::default().enable());
+        controller.on_packet_lost();
+        controller.on_packet_lost();
+        controller.update(FRAME, Duration::default());
+        assert!(controller.is_congested());
+
+        // the packet loss goes back down once packets are acked again
+        for _ in 0..20 {
+            controller.on_packet_acked();
+        }
+        controller.update(Duration::from_secs(2), Duration::default());
+        assert!(!controller.is_congested());
+    }
+}
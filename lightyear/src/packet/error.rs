@@ -1,6 +1,7 @@
 //! Errors for building packets
 
 use crate::channel::receivers::error::ChannelReceiveError;
+use crate::channel::senders::error::ChannelSendError;
 use crate::serialize::SerializationError;
 
 pub type Result<T> = core::result::Result<T, PacketError>;
@@ -10,6 +11,10 @@ pub enum PacketError {
     Serialization(#[from] SerializationError),
     #[error("channel was not found")]
     ChannelNotFound,
+    #[error("the mode, direction and authentication of a channel cannot be changed")]
+    InvalidChannelSettings,
     #[error("receiver channel error: {0}")]
     ChannelReceiveError(#[from] ChannelReceiveError),
+    #[error("sender channel error: {0}")]
+    ChannelSendError(#[from] ChannelSendError),
 }
@@ -8,7 +8,7 @@ use serde::Serialize;
 
 use crate::protocol::EventContext;
 use crate::serialize::reader::Reader;
-use crate::serialize::varint::varint_len;
+use crate::serialize::varint::{varint_len, VarIntReadExt, VarIntWriteExt};
 use crate::serialize::{SerializationError, ToBytes};
 use crate::shared::tick_manager::Tick;
 use crate::utils::wrapping_id::wrapping_id;
@@ -117,43 +117,34 @@ impl From<SingleData> for MessageData {
 /// The message/component does not need to implement Clone anymore!
 /// Also we know the size of the message early, which is useful for fragmentation.
 pub struct SingleData {
-    // TODO: MessageId is from 1 to 65535, so that we can use 0 to represent None?
     pub id: Option<MessageId>,
     pub bytes: Bytes,
 }
 
+/// Tag written in front of a [`SingleData`] that doesn't have a [`MessageId`]
+const NO_ID_TAG: u64 = 0;
+/// Tag written in front of a [`SingleData`] whose [`MessageId`] is written in full
+const FULL_ID_TAG: u64 = 1;
+/// Tags above this value encode the [`MessageId`] as a zigzag-encoded delta from the previous id
+const DELTA_ID_TAG: u64 = 2;
+/// Largest tag that fits in a 2-byte varint; larger deltas are cheaper to write as a full id
+const MAX_DELTA_TAG: u64 = (1 << 14) - 1;
+
 impl ToBytes for SingleData {
-    // TODO: how to avoid the option taking 1 byte?
+    /// Upper bound of the serialized size: the id is counted as if it was written in full
     fn len(&self) -> usize {
         varint_len(self.bytes.len() as u64) + self.bytes.len() + self.id.map_or(1, |_| 3)
     }
 
     fn to_bytes<T: WriteBytesExt>(&self, buffer: &mut T) -> Result<(), SerializationError> {
-        if let Some(id) = self.id {
-            buffer.write_u8(1)?;
-            buffer.write_u16::<NetworkEndian>(id.0)?;
-        } else {
-            buffer.write_u8(0)?;
-        }
-        self.bytes.to_bytes(buffer)?;
-        // buffer.write_varint(self.bytes.len() as u64)?;
-        // buffer.write_all(self.bytes.as_ref())?;
-        Ok(())
+        self.to_bytes_with_previous(buffer, None)
     }
 
     fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
     where
         Self: Sized,
     {
-        let id = if buffer.read_u8()? == 1 {
-            Some(MessageId(buffer.read_u16::<NetworkEndian>()?))
-        } else {
-            None
-        };
-        let bytes = Bytes::from_bytes(buffer)?;
-        // let len = buffer.read_varint()? as usize;
-        // let bytes = buffer.split_len(len);
-        Ok(Self { id, bytes })
+        Self::from_bytes_with_previous(buffer, None)
     }
 }
 
@@ -161,6 +152,59 @@ impl SingleData {
     pub fn new(id: Option<MessageId>, bytes: Bytes) -> Self {
         Self { id, bytes }
     }
+
+    /// Serialize the message, writing its id as a delta from `previous` (the id of the previous message
+    /// written in the same channel section of the packet) if that is shorter than the full id.
+    ///
+    /// The written size is at most [`ToBytes::len`].
+    pub(crate) fn to_bytes_with_previous<T: WriteBytesExt>(
+        &self,
+        buffer: &mut T,
+        previous: Option<MessageId>,
+    ) -> Result<(), SerializationError> {
+        match (self.id, previous) {
+            (None, _) => buffer.write_varint(NO_ID_TAG)?,
+            (Some(id), previous) => {
+                let delta_tag = previous
+                    .map(|previous| zigzag(id - previous) + DELTA_ID_TAG)
+                    .filter(|tag| *tag <= MAX_DELTA_TAG);
+                if let Some(tag) = delta_tag {
+                    buffer.write_varint(tag)?;
+                } else {
+                    buffer.write_varint(FULL_ID_TAG)?;
+                    buffer.write_u16::<NetworkEndian>(id.0)?;
+                }
+            }
+        }
+        self.bytes.to_bytes(buffer)?;
+        Ok(())
+    }
+
+    /// Deserialize a message written with [`SingleData::to_bytes_with_previous`]
+    pub(crate) fn from_bytes_with_previous(
+        buffer: &mut Reader,
+        previous: Option<MessageId>,
+    ) -> Result<Self, SerializationError> {
+        let id = match buffer.read_varint()? {
+            NO_ID_TAG => None,
+            FULL_ID_TAG => Some(MessageId(buffer.read_u16::<NetworkEndian>()?)),
+            tag => {
+                let previous = previous.ok_or(SerializationError::InvalidValue)?;
+                Some(previous + unzigzag(tag - DELTA_ID_TAG))
+            }
+        };
+        let bytes = Bytes::from_bytes(buffer)?;
+        Ok(Self { id, bytes })
+    }
+}
+
+fn zigzag(value: i16) -> u64 {
+    ((value << 1) ^ (value >> 15)) as u16 as u64
+}
+
+fn unzigzag(value: u64) -> i16 {
+    let value = value as u16;
+    ((value >> 1) as i16) ^ -((value & 1) as i16)
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -264,6 +308,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_to_bytes_single_data_with_previous() {
+        let previous = Some(MessageId(u16::MAX - 1));
+        for (id, expected_len) in [
+            // the id is written as a 1-byte delta, even across the wrap-around
+            (MessageId(1), 1),
+            (MessageId(u16::MAX - 20), 1),
+            // the delta needs a 2-byte varint
+            (MessageId(1000), 2),
+            // the delta is too big, the id is written in full
+            (MessageId(20000), 3),
+        ] {
+            let data = SingleData::new(Some(id), vec![7u8; 10].into());
+            let mut writer = vec![];
+            data.to_bytes_with_previous(&mut writer, previous).unwrap();
+            assert_eq!(writer.len(), expected_len + 11);
+            assert!(writer.len() <= data.len());
+
+            let mut reader = writer.into();
+            let decoded = SingleData::from_bytes_with_previous(&mut reader, previous).unwrap();
+            assert_eq!(decoded, data);
+        }
+    }
+
     #[test]
     fn test_to_bytes_fragment_data() {
         let bytes = Bytes::from(vec![0; 10]);
@@ -6,17 +6,20 @@ use tracing::trace;
 #[cfg(feature = "trace")]
 use tracing::{instrument, Level};
 
-use crate::channel::builder::ChannelContainer;
+use crate::channel::authentication::ChannelAuthenticator;
+use crate::channel::builder::{ChannelContainer, ChannelSettings};
 use crate::channel::receivers::ChannelReceive;
+use crate::channel::senders::error::ChannelSendError;
 use crate::channel::senders::ChannelSend;
-#[cfg(feature = "trace")]
-use crate::channel::stats::send::ChannelSendStats;
+use crate::channel::stats::ChannelStats;
+use crate::connection::netcode::Key;
+use crate::packet::congestion::{CongestionConfig, CongestionController};
 use crate::packet::error::PacketError;
 use crate::packet::header::PacketHeader;
 use crate::packet::message::{
     FragmentData, MessageAck, MessageId, ReceiveMessage, SendMessage, SingleData,
 };
-use crate::packet::packet::PacketId;
+use crate::packet::packet::{fragment_size, PacketId, FRAGMENT_SIZE, MAX_MESSAGE_SIZE};
 use crate::packet::packet_builder::{PacketBuilder, Payload, RecvPayload};
 use crate::packet::packet_type::PacketType;
 use crate::packet::priority_manager::{PriorityConfig, PriorityManager};
@@ -28,7 +31,7 @@ use crate::serialize::ToBytes;
 use crate::shared::ping::manager::PingManager;
 use crate::shared::tick_manager::Tick;
 use crate::shared::tick_manager::TickManager;
-use crate::shared::time_manager::TimeManager;
+use crate::shared::time_manager::{TimeManager, WrappedTime};
 #[cfg(test)]
 use crate::utils::captures::Captures;
 
@@ -43,13 +46,30 @@ pub struct MessageManager {
     /// Handles sending/receiving packets (including acks)
     packet_manager: PacketBuilder,
     priority_manager: PriorityManager,
+    pub(crate) congestion: CongestionController,
     pub(crate) channels: HashMap<ChannelKind, ChannelContainer>,
     pub(crate) channel_registry: ChannelRegistry,
     // TODO: can use Vec<ChannelKind, Vec<MessageId>> to be more efficient?
     /// Map to keep track of which messages have been sent in which packets, so that
     /// reliable senders can stop trying to send a message that has already been received
     packet_to_message_ack_map: HashMap<PacketId, Vec<(ChannelKind, MessageAck)>>,
+    /// Time at which the packets in `packet_to_message_ack_map` were sent, to compute the ack latency
+    packet_send_times: HashMap<PacketId, WrappedTime>,
     nack_senders: Vec<Sender<MessageId>>,
+    /// Receivers that get notified when a message sent on a channel that tracks acks has been
+    /// received by the remote peer
+    delivery_receivers: Vec<(ChannelKind, Receiver<MessageId>)>,
+    /// Receivers that get notified when a message sent on an unreliable channel that tracks acks
+    /// has been lost
+    loss_receivers: Vec<(ChannelKind, Receiver<MessageId>)>,
+    current_time: WrappedTime,
+    /// Maximum number of bytes of a message before it is split into fragments
+    fragment_size: usize,
+    /// Maximum number of bytes of a message
+    max_message_size: usize,
+    /// Keys exchanged during the connection handshake (send key, receive key), used to authenticate
+    /// the messages of the authenticated channels
+    session_keys: Option<(Key, Key)>,
 }
 
 impl MessageManager {
@@ -57,15 +77,201 @@ impl MessageManager {
         channel_registry: &ChannelRegistry,
         nack_rtt_multiple: f32,
         priority_config: PriorityConfig,
+        congestion_config: CongestionConfig,
     ) -> Self {
-        Self {
+        let mut message_manager = Self {
             packet_manager: PacketBuilder::new(nack_rtt_multiple),
             priority_manager: PriorityManager::new(priority_config),
-            channels: channel_registry.channels(),
+            congestion: CongestionController::new(congestion_config),
+            channels: HashMap::new(),
             channel_registry: channel_registry.clone(),
             packet_to_message_ack_map: HashMap::new(),
+            packet_send_times: HashMap::new(),
             nack_senders: vec![],
+            delivery_receivers: vec![],
+            loss_receivers: vec![],
+            current_time: WrappedTime::default(),
+            fragment_size: FRAGMENT_SIZE,
+            max_message_size: MAX_MESSAGE_SIZE,
+            session_keys: None,
+        };
+        for (channel_kind, channel) in channel_registry.channels() {
+            message_manager.insert_channel(channel_kind, channel);
+        }
+        message_manager
+    }
+
+    /// Add a channel, and get notified when the messages sent on it are acked or lost
+    fn insert_channel(&mut self, channel_kind: ChannelKind, mut channel: ChannelContainer) {
+        let watching_acks = channel.setting.mode.is_watching_acks();
+        let reliable = channel.setting.mode.is_reliable();
+        if watching_acks {
+            self.delivery_receivers
+                .push((channel_kind, channel.sender.subscribe_acks()));
+            if !reliable {
+                self.loss_receivers
+                    .push((channel_kind, channel.sender.subscribe_nacks()));
+            }
+        }
+        channel.authenticator = self.authenticator(&channel_kind, &channel.setting);
+        channel.sender.set_fragment_size(self.fragment_size);
+        self.channels.insert(channel_kind, channel);
+    }
+
+    /// Set the maximum number of bytes of the packets and of the messages sent on this connection.
+    ///
+    /// The packet size is clamped between 128 bytes and [`MAX_PACKET_SIZE`](crate::connection::netcode::MAX_PACKET_SIZE).
+    /// Messages that don't fit in a packet are split into fragments, and buffering a message bigger than
+    /// `max_message_size` returns [`ChannelSendError::MessageTooBig`].
+    pub(crate) fn set_max_sizes(&mut self, max_packet_size: usize, max_message_size: usize) {
+        self.packet_manager.set_max_packet_size(max_packet_size);
+        self.fragment_size = fragment_size(self.packet_manager.max_packet_size());
+        self.max_message_size = max_message_size;
+        for channel in self.channels.values_mut() {
+            channel.sender.set_fragment_size(self.fragment_size);
+        }
+    }
+
+    fn check_message_size(&self, message: &Bytes) -> Result<(), ChannelSendError> {
+        if message.len() > self.max_message_size {
+            return Err(ChannelSendError::MessageTooBig {
+                size: message.len(),
+                max_size: self.max_message_size,
+            });
+        }
+        Ok(())
+    }
+
+    fn authenticator(
+        &self,
+        channel_kind: &ChannelKind,
+        settings: &ChannelSettings,
+    ) -> Option<ChannelAuthenticator> {
+        if !settings.authenticated {
+            return None;
         }
+        let (send_key, receive_key) = self.session_keys.as_ref()?;
+        let channel_id = self.channel_registry.get_net_from_kind(channel_kind)?;
+        Some(ChannelAuthenticator::new(
+            send_key,
+            receive_key,
+            *channel_id,
+        ))
+    }
+
+    /// Set the keys exchanged during the connection handshake, which are used to sign and verify
+    /// the messages of the authenticated channels.
+    ///
+    /// Without session keys, messages cannot be sent on authenticated channels, and the messages
+    /// received on them are dropped.
+    pub(crate) fn set_session_keys(&mut self, session_keys: Option<(Key, Key)>) {
+        self.session_keys = session_keys;
+        let authenticators: Vec<_> = self
+            .channels
+            .iter()
+            .map(|(channel_kind, channel)| {
+                (
+                    *channel_kind,
+                    self.authenticator(channel_kind, &channel.setting),
+                )
+            })
+            .collect();
+        for (channel_kind, authenticator) in authenticators {
+            if let Some(channel) = self.channels.get_mut(&channel_kind) {
+                channel.authenticator = authenticator;
+            }
+        }
+    }
+
+    /// Add a channel that was registered at runtime in `channel_registry`.
+    ///
+    /// If `paused` is true, the messages buffered on the channel are not sent until
+    /// [`resume_channel`](Self::resume_channel) is called.
+    pub(crate) fn add_runtime_channel(
+        &mut self,
+        channel_kind: ChannelKind,
+        channel_registry: &ChannelRegistry,
+        paused: bool,
+    ) -> Result<(), PacketError> {
+        let mut channel = channel_registry
+            .get_builder_from_kind(&channel_kind)
+            .ok_or(PacketError::ChannelNotFound)?
+            .build();
+        channel.paused = paused;
+        self.channel_registry = channel_registry.clone();
+        self.insert_channel(channel_kind, channel);
+        Ok(())
+    }
+
+    /// Change the settings of a channel for this connection only.
+    ///
+    /// The send frequency, priority, bandwidth weight and the resend timing of reliable channels can be changed,
+    /// but not the mode, the direction or the authentication of the channel since the remote peer must use the same ones.
+    pub(crate) fn update_channel_settings(
+        &mut self,
+        channel_kind: &ChannelKind,
+        settings: ChannelSettings,
+    ) -> Result<(), PacketError> {
+        let channel = self
+            .channels
+            .get_mut(channel_kind)
+            .ok_or(PacketError::ChannelNotFound)?;
+        if std::mem::discriminant(&channel.setting.mode) != std::mem::discriminant(&settings.mode)
+            || channel.setting.direction != settings.direction
+            || channel.setting.authenticated != settings.authenticated
+        {
+            return Err(PacketError::InvalidChannelSettings);
+        }
+        channel.sender.update_settings(&settings);
+        channel.setting = settings.clone();
+        self.channel_registry
+            .update_settings(channel_kind, settings);
+        Ok(())
+    }
+
+    /// Stop sending the messages buffered on a channel; they stay buffered until the channel is resumed
+    pub(crate) fn pause_channel(&mut self, channel_kind: &ChannelKind) {
+        if let Some(channel) = self.channels.get_mut(channel_kind) {
+            channel.paused = true;
+        }
+    }
+
+    /// Start sending the messages buffered on a paused channel
+    pub(crate) fn resume_channel(&mut self, channel_kind: &ChannelKind) {
+        if let Some(channel) = self.channels.get_mut(channel_kind) {
+            channel.paused = false;
+        }
+    }
+
+    /// Returns the messages that have been acknowledged by the remote peer since the last call,
+    /// on the channels that keep track of acks
+    pub(crate) fn delivered_messages(
+        &mut self,
+    ) -> impl Iterator<Item = (ChannelKind, MessageId)> + '_ {
+        self.delivery_receivers
+            .iter()
+            .flat_map(|(channel_kind, receiver)| {
+                receiver
+                    .try_iter()
+                    .map(move |message_id| (*channel_kind, message_id))
+            })
+    }
+
+    /// Returns the messages sent on unreliable channels that track acks, and that have been
+    /// deemed lost since the last call.
+    ///
+    /// A fragmented message is only returned once even if several of its fragments were lost.
+    pub(crate) fn lost_messages(&mut self) -> Vec<(ChannelKind, MessageId)> {
+        let mut lost_messages = Vec::new();
+        for (channel_kind, receiver) in &self.loss_receivers {
+            lost_messages.extend(
+                receiver
+                    .try_iter()
+                    .map(|message_id| (*channel_kind, message_id)),
+            );
+        }
+        lost_messages.dedup();
+        lost_messages
     }
 
     pub(crate) fn get_replication_update_send_receiver(&mut self) -> Receiver<MessageId> {
@@ -81,6 +287,7 @@ impl MessageManager {
         ping_manager: &PingManager,
         tick_manager: &TickManager,
     ) {
+        self.current_time = time_manager.current_time();
         // on the sender side, gather the list of packets that haven't been received by the remote peer
         let lost_packets = self
             .packet_manager
@@ -88,6 +295,8 @@ impl MessageManager {
             .update(time_manager, ping_manager);
         // notify that some messages have been lost
         for lost_packet in lost_packets {
+            self.congestion.on_packet_lost();
+            self.packet_send_times.remove(&lost_packet);
             if let Some(message_map) = self.packet_to_message_ack_map.remove(&lost_packet) {
                 for (channel_kind, message_ack) in message_map {
                     let channel = self
@@ -105,6 +314,8 @@ impl MessageManager {
                 }
             }
         }
+        self.congestion
+            .update(time_manager.delta(), ping_manager.rtt());
         for channel in self.channels.values_mut() {
             channel
                 .sender
@@ -141,30 +352,62 @@ impl MessageManager {
         channel_kind: ChannelKind,
         priority: f32,
     ) -> Result<Option<MessageId>, PacketError> {
+        self.check_message_size(&message)?;
         let channel = self
             .channels
             .get_mut(&channel_kind)
             .ok_or(PacketError::ChannelNotFound)?;
+        let message = channel.encode_message(message)?;
         Ok(channel.sender.buffer_send(message, priority)?)
     }
 
+    /// Buffer a message to be sent on this connection, unless the channel is full.
+    ///
+    /// Returns [`ChannelSendError::ChannelFull`](crate::prelude::ChannelSendError::ChannelFull)
+    /// if the channel already holds the maximum number of messages.
+    pub fn try_buffer_send(
+        &mut self,
+        message: Bytes,
+        channel_kind: ChannelKind,
+    ) -> Result<Option<MessageId>, PacketError> {
+        self.check_message_size(&message)?;
+        let channel = self
+            .channels
+            .get_mut(&channel_kind)
+            .ok_or(PacketError::ChannelNotFound)?;
+        let message = channel.encode_message(message)?;
+        Ok(channel.sender.try_send(message, DEFAULT_MESSAGE_PRIORITY)?)
+    }
+
+    /// Number of messages held by a channel (waiting to be sent, or waiting for an ack)
+    pub(crate) fn queue_depth(&self, channel_kind: &ChannelKind) -> Option<usize> {
+        self.channels
+            .get(channel_kind)
+            .map(|channel| channel.sender.queue_depth())
+    }
+
     /// Prepare buckets from the internal send buffers, and return the bytes to send
     // TODO: maybe pass TickManager instead of Tick? Find a more elegant way to pass extra data that might not be used?
     //  (ticks are not purely necessary without client prediction)
     //  maybe be generic over a Context ?
     #[cfg_attr(feature = "trace", instrument(level = Level::INFO, skip_all))]
     pub fn send_packets(&mut self, current_tick: Tick) -> Result<Vec<Payload>, PacketError> {
+        self.congestion.on_send();
         // Step 1. Get the list of packets to send from all channels
         // for each channel, prepare packets using the buffered messages that are ready to be sent
         // TODO: iterate through the channels in order of channel priority? (with accumulation)
         let mut data_to_send: Vec<(NetId, (VecDeque<SendMessage>, VecDeque<SendMessage>))> = vec![];
         let mut has_data_to_send = false;
         for (channel_kind, channel) in self.channels.iter_mut() {
+            if channel.paused {
+                continue;
+            }
             let channel_id = self
                 .channel_registry
                 .get_net_from_kind(channel_kind)
                 .ok_or(PacketError::ChannelNotFound)?;
             let (single_data, fragment_data) = channel.sender.send_packet();
+            channel.stats.resends += channel.sender.take_num_resends();
 
             if !single_data.is_empty() || !fragment_data.is_empty() {
                 trace!(?channel_id, "send message with channel_id");
@@ -178,41 +421,35 @@ impl MessageManager {
         }
 
         // priority manager: get the list of messages we can send according to the rate limiter
-        //  (the other messages are stored in an internal buffer)
-        let (single_data, fragment_data, num_bytes_added_to_limiter) = self
+        let (single_data, fragment_data, num_bytes_added_to_limiter, messages_not_sent) = self
             .priority_manager
             .priority_filter(data_to_send, &self.channel_registry, current_tick);
+        // the messages that could not be sent are dropped if unreliable, or queued again if reliable
+        for (channel_id, message_ack) in messages_not_sent {
+            let channel_kind = self
+                .channel_registry
+                .get_kind_from_net_id(channel_id)
+                .ok_or(PacketError::ChannelNotFound)?;
+            self.channels
+                .get_mut(channel_kind)
+                .ok_or(PacketError::ChannelNotFound)?
+                .sender
+                .message_not_sent(&message_ack);
+        }
 
-        #[cfg(feature = "trace")]
-        {
-            // NOTE: we don't know the actual exact amount of bytes sent (because we don't take into account the ids, etc.),
-            // but we could during build_packet?
-            for (channel_id, data) in &single_data {
-                let channel_stats = &mut self
-                    .channels
-                    .get_mut(
-                        self.channel_registry
-                            .get_kind_from_net_id(*channel_id)
-                            .ok_or(PacketError::ChannelNotFound)?,
-                    )
-                    .ok_or(PacketError::ChannelNotFound)?
-                    .sender_stats;
-                channel_stats.add_bytes_sent(data.iter().fold(0, |acc, d| acc + d.bytes.len()));
-                channel_stats.add_single_message_sent(data.len());
-            }
-            for (channel_id, data) in &fragment_data {
-                let channel_stats = &mut self
-                    .channels
-                    .get_mut(
-                        self.channel_registry
-                            .get_kind_from_net_id(*channel_id)
-                            .ok_or(PacketError::ChannelNotFound)?,
-                    )
-                    .ok_or(PacketError::ChannelNotFound)?
-                    .sender_stats;
-                channel_stats.add_bytes_sent(data.iter().fold(0, |acc, d| acc + d.bytes.len()));
-                channel_stats.add_fragment_message_sent(data.len());
-            }
+        // NOTE: we don't know the actual exact amount of bytes sent (because we don't take into account the ids, etc.),
+        // but we could during build_packet?
+        for (channel_id, data) in &single_data {
+            self.get_channel_mut(*channel_id)?.stats.add_messages_sent(
+                data.len(),
+                data.iter().fold(0, |acc, d| acc + d.bytes.len()),
+            );
+        }
+        for (channel_id, data) in &fragment_data {
+            self.get_channel_mut(*channel_id)?.stats.add_messages_sent(
+                data.len(),
+                data.iter().fold(0, |acc, d| acc + d.bytes.len()),
+            );
         }
 
         let packets =
@@ -246,6 +483,8 @@ impl MessageManager {
                             .entry(packet.packet_id)
                             .or_default()
                             .push((*channel_kind, message_ack));
+                        self.packet_send_times
+                            .insert(packet.packet_id, self.current_time);
                     }
                     Ok::<(), PacketError>(())
                 })?;
@@ -270,6 +509,14 @@ impl MessageManager {
         Ok(bytes)
     }
 
+    /// Return the payloads produced by [`send_packets`](Self::send_packets) once they have been sent,
+    /// so that their buffers can be reused for the next packets
+    pub(crate) fn release_payloads(&mut self, payloads: Vec<Payload>) {
+        for payload in payloads {
+            self.packet_manager.release_buffer(payload);
+        }
+    }
+
     /// Process packet received over the network as raw bytes
     /// Update the acks, and put the messages from the packets in internal buffers
     /// Returns the tick of the packet
@@ -298,6 +545,11 @@ impl MessageManager {
         // Step 3. Update the list of messages that have been acked
         for acked_packet in acked_packets {
             trace!("Acked packet {:?}", acked_packet);
+            self.congestion.on_packet_acked();
+            let ack_latency = self
+                .packet_send_times
+                .remove(&acked_packet)
+                .and_then(|send_time| (self.current_time - send_time).to_std().ok());
             if let Some(message_acks) = self.packet_to_message_ack_map.remove(&acked_packet) {
                 for (channel_kind, message_ack) in message_acks {
                     let channel_name = self
@@ -314,6 +566,9 @@ impl MessageManager {
                         .get_mut(&channel_kind)
                         .ok_or(PacketError::ChannelNotFound)?;
                     channel.sender.receive_ack(&message_ack);
+                    if let Some(ack_latency) = ack_latency {
+                        channel.stats.add_ack(ack_latency);
+                    }
                 }
             }
         }
@@ -325,25 +580,30 @@ impl MessageManager {
             // read the fragment data
             let channel_id = ChannelId::from_bytes(&mut cursor)?;
             let fragment_data = FragmentData::from_bytes(&mut cursor)?;
-            self.get_channel_mut(channel_id)?
-                .receiver
-                .buffer_recv(ReceiveMessage {
-                    data: fragment_data.into(),
-                    remote_sent_tick: tick,
-                })?;
+            let channel = self.get_channel_mut(channel_id)?;
+            channel
+                .stats
+                .add_message_received(fragment_data.bytes.len());
+            channel.receiver.buffer_recv(ReceiveMessage {
+                data: fragment_data.into(),
+                remote_sent_tick: tick,
+            })?;
         }
         // read single message data
         while cursor.has_remaining() {
             let channel_id = ChannelId::from_bytes(&mut cursor)?;
             let num_messages = cursor.read_varint()?;
+            // message ids are delta-encoded from the previous message id of the same section
+            let mut previous_id = None;
             for i in 0..num_messages {
-                let single_data = SingleData::from_bytes(&mut cursor)?;
-                self.get_channel_mut(channel_id)?
-                    .receiver
-                    .buffer_recv(ReceiveMessage {
-                        data: single_data.into(),
-                        remote_sent_tick: tick,
-                    })?;
+                let single_data = SingleData::from_bytes_with_previous(&mut cursor, previous_id)?;
+                previous_id = single_data.id.or(previous_id);
+                let channel = self.get_channel_mut(channel_id)?;
+                channel.stats.add_message_received(single_data.bytes.len());
+                channel.receiver.buffer_recv(ReceiveMessage {
+                    data: single_data.into(),
+                    remote_sent_tick: tick,
+                })?;
             }
         }
         // trace!(
@@ -378,7 +638,7 @@ impl MessageManager {
             .iter_mut()
             .flat_map(move |(channel_kind, channel)| {
                 // TODO: this is broken, we need to call a read_message in a while loop !
-                channel.receiver.read_message().map(move |(tick, bytes)| {
+                channel.read_message().map(move |(tick, bytes)| {
                     trace!(?channel_kind, "reading message: {:?}", bytes);
                     // SAFETY: when we receive the message, we set the tick of the message to the header tick
                     // so every message has a tick
@@ -413,12 +673,11 @@ impl MessageManager {
             .ok_or(PacketError::ChannelNotFound)
     }
 
-    /// Get the ChannelSendStats of a given channel
-    #[cfg(feature = "trace")]
-    pub fn channel_send_stats<C: crate::prelude::Channel>(&self) -> Option<&ChannelSendStats> {
+    /// Get the [`ChannelStats`] of a given channel
+    pub fn channel_stats<C: crate::prelude::Channel>(&self) -> Option<&ChannelStats> {
         self.channels
             .get(&ChannelKind::of::<C>())
-            .map(|channel| &channel.sender_stats)
+            .map(|channel| &channel.stats)
     }
 }
 
@@ -429,6 +688,7 @@ mod tests {
     use std::collections::HashMap;
 
     use bevy::prelude::default;
+    use bevy::utils::Duration;
 
     use crate::packet::message::MessageId;
     use crate::packet::packet::FRAGMENT_SIZE;
@@ -451,10 +711,18 @@ mod tests {
         });
 
         // Create message managers
-        let client_message_manager =
-            MessageManager::new(&channel_registry, 1.5, PriorityConfig::default());
-        let server_message_manager =
-            MessageManager::new(&channel_registry, 1.5, PriorityConfig::default());
+        let client_message_manager = MessageManager::new(
+            &channel_registry,
+            1.5,
+            PriorityConfig::default(),
+            CongestionConfig::default(),
+        );
+        let server_message_manager = MessageManager::new(
+            &channel_registry,
+            1.5,
+            PriorityConfig::default(),
+            CongestionConfig::default(),
+        );
         (client_message_manager, server_message_manager)
     }
 
@@ -504,6 +772,14 @@ mod tests {
             &vec![(Tick(0), message.clone())]
         );
 
+        // check the channel stats
+        let client_stats = client_message_manager.channel_stats::<Channel1>().unwrap();
+        assert_eq!(client_stats.messages_sent, 1);
+        assert_eq!(client_stats.bytes_sent, message.len());
+        let server_stats = server_message_manager.channel_stats::<Channel1>().unwrap();
+        assert_eq!(server_stats.messages_received, 1);
+        assert_eq!(server_stats.bytes_received, message.len());
+
         // Confirm what happens if we try to receive but there is nothing on the io
         let it = server_message_manager.read_messages();
         let data = MessageManager::collect_messages(it);
@@ -534,6 +810,17 @@ mod tests {
 
         // Check that reliability works correctly
         assert_eq!(client_message_manager.packet_to_message_ack_map.len(), 0);
+        assert_eq!(
+            client_message_manager
+                .delivered_messages()
+                .collect::<Vec<_>>(),
+            vec![(channel_kind_2, MessageId(0))]
+        );
+        assert!(client_message_manager
+            .channel_stats::<Channel2>()
+            .unwrap()
+            .average_ack_latency()
+            .is_some());
         // TODO: check that client_channel_1's sender's unacked messages is empty
         // let client_channel_1 = client_connection.channels.get(&channel_kind_1).unwrap();
         // assert_eq!(client_channel_1.sender.)
@@ -681,4 +968,102 @@ mod tests {
         assert_eq!(update_acks_tracker.try_recv().unwrap(), message_id);
         Ok(())
     }
+
+    #[test]
+    fn test_notify_loss() -> Result<(), PacketError> {
+        let (mut client_message_manager, _) = setup();
+        let mut time_manager = TimeManager::default();
+        let ping_manager = PingManager::new(PingConfig::default());
+        let tick_manager = TickManager::from_config(TickConfig::new(Duration::from_millis(16)));
+
+        // send a message that is never received by the server
+        let message_id = client_message_manager
+            .buffer_send(vec![0].into(), Channel2::kind())?
+            .unwrap();
+        client_message_manager.send_packets(Tick(0))?;
+        client_message_manager.update(&time_manager, &ping_manager, &tick_manager);
+        assert!(client_message_manager.lost_messages().is_empty());
+
+        // the packet is considered lost after a while
+        time_manager.update(Duration::from_secs(5));
+        client_message_manager.update(&time_manager, &ping_manager, &tick_manager);
+        assert_eq!(
+            client_message_manager.lost_messages(),
+            vec![(Channel2::kind(), message_id)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_channel_settings() -> Result<(), PacketError> {
+        let (mut client_message_manager, _) = setup();
+
+        // the mode of the channel cannot change
+        assert!(matches!(
+            client_message_manager.update_channel_settings(
+                &Channel1::kind(),
+                ChannelSettings {
+                    mode: ChannelMode::SequencedUnreliable,
+                    ..default()
+                }
+            ),
+            Err(PacketError::InvalidChannelSettings)
+        ));
+
+        // messages are only sent once the timer of the new send frequency is finished
+        client_message_manager.update_channel_settings(
+            &Channel1::kind(),
+            ChannelSettings {
+                mode: ChannelMode::UnorderedUnreliable,
+                send_frequency: Duration::from_millis(100),
+                priority: 2.0,
+                ..default()
+            },
+        )?;
+        assert_eq!(
+            client_message_manager
+                .channel_registry
+                .get_builder_from_kind(&Channel1::kind())
+                .unwrap()
+                .settings
+                .priority,
+            2.0
+        );
+        client_message_manager.buffer_send(vec![0].into(), Channel1::kind())?;
+        assert!(client_message_manager.send_packets(Tick(0))?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_sizes() -> Result<(), PacketError> {
+        let (mut client_message_manager, mut server_message_manager) = setup();
+        client_message_manager.set_max_sizes(300, 1000);
+        let channel_kind = ChannelKind::of::<Channel1>();
+
+        // messages bigger than the maximum message size are rejected
+        let message = Bytes::from(vec![1; 1001]);
+        assert!(matches!(
+            client_message_manager.buffer_send(message, channel_kind),
+            Err(PacketError::ChannelSendError(
+                ChannelSendError::MessageTooBig {
+                    size: 1001,
+                    max_size: 1000
+                }
+            ))
+        ));
+
+        // the other messages are split into fragments that fit in the smaller packets
+        let message = Bytes::from(vec![1; 1000]);
+        client_message_manager.buffer_send(message.clone(), channel_kind)?;
+        let payloads = client_message_manager.send_packets(Tick(0))?;
+        assert_eq!(payloads.len(), 4);
+        assert!(payloads.iter().all(|payload| payload.len() <= 300));
+
+        for payload in payloads {
+            server_message_manager.recv_packet(payload.into())?;
+        }
+        let data = MessageManager::collect_messages(server_message_manager.read_messages());
+        assert_eq!(data.get(&channel_kind).unwrap(), &vec![(Tick(0), message)]);
+        Ok(())
+    }
 }
@@ -19,6 +19,9 @@ Packets that are over the maximum packet size will be fragmented into multiple [
 [`FragmentData`]: message::FragmentData
 */
 
+/// Reduces the packet send rate when the network is congested
+pub mod congestion;
+
 /// Manages the [`PacketHeader`](header::PacketHeader) which includes important packet information
 pub mod header;
 
@@ -1,6 +1,6 @@
 /// Defines the [`Packet`] struct
 use crate::connection::netcode::MAX_PACKET_SIZE;
-use crate::packet::message::MessageAck;
+use crate::packet::message::{FragmentIndex, MessageAck};
 use crate::packet::packet_builder::Payload;
 use crate::protocol::channel::ChannelId;
 use crate::serialize::ToBytes;
@@ -24,13 +24,29 @@ wrapping_id!(PacketId);
 /// Number of bytes to write the header
 const HEADER_BYTES: usize = 11;
 
-/// The maximum number of bytes for a message before it is fragmented
-/// MAX_PACKET_SIZE - HEADER_BYTES - 1 (channel_net_id) - 6 (message_id/fragment_id/num_fragments) - 2 (num bytes in fragment)
+/// Number of bytes written in a packet for a fragment, in addition to its data:
+/// 1 (channel_net_id) + 6 (message_id/fragment_id/num_fragments) + 2 (num bytes in fragment)
 #[cfg(feature = "big_messages")]
-pub(crate) const FRAGMENT_SIZE: usize = MAX_PACKET_SIZE - HEADER_BYTES - 9;
+const FRAGMENT_OVERHEAD: usize = 9;
 
 #[cfg(not(feature = "big_messages"))]
-pub(crate) const FRAGMENT_SIZE: usize = MAX_PACKET_SIZE - HEADER_BYTES - 7;
+const FRAGMENT_OVERHEAD: usize = 7;
+
+/// The maximum number of bytes for a message before it is fragmented, with the default packet size
+pub(crate) const FRAGMENT_SIZE: usize = fragment_size(MAX_PACKET_SIZE);
+
+/// The smallest maximum packet size that can be configured
+pub(crate) const MIN_PACKET_SIZE: usize = 128;
+
+/// The maximum number of bytes of a message with the default packet size, since a message can be
+/// split into at most [`FragmentIndex::MAX`] fragments
+pub(crate) const MAX_MESSAGE_SIZE: usize = FRAGMENT_SIZE * FragmentIndex::MAX as usize;
+
+/// The maximum number of bytes for a message before it is fragmented, in packets of at most
+/// `max_packet_size` bytes
+pub(crate) const fn fragment_size(max_packet_size: usize) -> usize {
+    max_packet_size - HEADER_BYTES - FRAGMENT_OVERHEAD
+}
 
 /// Data structure that will help us write the packet
 #[derive(Debug)]
@@ -41,12 +57,14 @@ pub(crate) struct Packet {
     pub(crate) packet_id: PacketId,
     // How many bytes we know we are going to have to write in the packet, but haven't written yet
     pub(crate) prewritten_size: usize,
+    /// Maximum number of bytes of the packet
+    pub(crate) max_size: usize,
 }
 
 impl Packet {
     /// Check that we can still fit some data in the buffer
     pub(crate) fn can_fit(&self, size: usize) -> bool {
-        self.payload.len() + size + self.prewritten_size <= MAX_PACKET_SIZE
+        self.payload.len() + size + self.prewritten_size <= self.max_size
     }
 
     /// Check if we can write a channel_id + the number of messages in the packet.
@@ -92,8 +110,10 @@ impl Packet {
         while cursor.has_remaining() {
             let channel_id = ChannelId::from_bytes(&mut cursor)?;
             let num_messages = cursor.read_varint()?;
+            let mut previous_id = None;
             for i in 0..num_messages {
-                let single_data = SingleData::from_bytes(&mut cursor)?;
+                let single_data = SingleData::from_bytes_with_previous(&mut cursor, previous_id)?;
+                previous_id = single_data.id.or(previous_id);
                 res.entry(channel_id).or_default().push(single_data.bytes);
             }
         }
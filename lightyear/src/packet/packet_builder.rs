@@ -1,23 +1,26 @@
 //! Module to take a buffer of messages to send and build packets
 use crate::connection::netcode::MAX_PACKET_SIZE;
-use byteorder::WriteBytesExt;
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use std::collections::VecDeque;
 #[cfg(feature = "trace")]
 use tracing::{instrument, Level};
 
 use crate::packet::header::PacketHeaderManager;
 use crate::packet::message::{FragmentData, MessageAck, SingleData};
-use crate::packet::packet::{Packet, FRAGMENT_SIZE};
+use crate::packet::packet::{Packet, FRAGMENT_SIZE, MIN_PACKET_SIZE};
 use crate::packet::packet_type::PacketType;
 use crate::prelude::Tick;
 use crate::protocol::channel::ChannelId;
 use crate::protocol::registry::NetId;
-use crate::serialize::varint::varint_len;
+use crate::serialize::varint::{varint_len, VarIntWriteExt};
 use crate::serialize::{SerializationError, ToBytes};
 
 pub type Payload = Vec<u8>;
 
+/// Maximum number of messages in a channel section of a packet, so that the number of messages
+/// fits in a 1-byte varint
+const MAX_MESSAGES_PER_SECTION: usize = 63;
+
 /// We use `Bytes` on the receive side because we want to be able to refer to sub-slices of the original
 /// packet without allocating.
 ///
@@ -25,11 +28,41 @@ pub type Payload = Vec<u8>;
 /// store subslices in receiver channels without allocating.
 pub type RecvPayload = Bytes;
 
+/// Maximum number of payload buffers kept by a [`PacketBuilder`] for reuse
+const MAX_POOLED_BUFFERS: usize = 64;
+
+/// Number of bytes allocated at once by [`RecvBuffer`], enough to hold many packets
+const RECV_BUFFER_CAPACITY: usize = 64 * MAX_PACKET_SIZE;
+
+/// Buffer that the received packets are copied into, so that we don't allocate for every packet.
+///
+/// Each packet is split off into a [`RecvPayload`] that shares the allocation of the buffer, and the
+/// messages read from the packet are sub-slices of it. The allocation is reused once all the payloads
+/// and messages that refer to it have been dropped.
+#[derive(Debug, Default)]
+pub(crate) struct RecvBuffer(BytesMut);
+
+impl RecvBuffer {
+    /// Copy the packet into the buffer, and return it as a ref-counted [`RecvPayload`]
+    pub(crate) fn copy_from_slice(&mut self, packet: &[u8]) -> RecvPayload {
+        if self.0.capacity() < packet.len() {
+            self.0.reserve(RECV_BUFFER_CAPACITY.max(packet.len()));
+        }
+        self.0.extend_from_slice(packet);
+        self.0.split().freeze()
+    }
+}
+
 /// `PacketBuilder` handles the process of creating a packet (writing the header and packing the
 /// messages into packets)
 pub(crate) struct PacketBuilder {
     pub(crate) header_manager: PacketHeaderManager,
     current_packet: Option<Packet>,
+    /// Maximum number of bytes of the packets
+    max_packet_size: usize,
+    /// Buffers of the packets that were already sent, which are reused for the next packets
+    /// so that we don't allocate a buffer for every packet
+    buffer_pool: Vec<Payload>,
     // Pre-allocated buffer to encode/decode without allocation.
     // TODO: should this be associated with Packet?
     // cursor: Vec<u8>,
@@ -44,6 +77,8 @@ impl PacketBuilder {
         Self {
             header_manager: PacketHeaderManager::new(nack_rtt_multiple),
             current_packet: None,
+            max_packet_size: MAX_PACKET_SIZE,
+            buffer_pool: Vec::new(),
             // cursor: Vec::with_capacity(PACKET_BUFFER_CAPACITY),
             // acks: Vec::new(),
 
@@ -54,9 +89,29 @@ impl PacketBuilder {
         }
     }
 
-    // TODO: get the vec from a pool of preallocated buffers
-    fn get_new_buffer(&self) -> Payload {
-        Vec::with_capacity(MAX_PACKET_SIZE)
+    /// Set the maximum number of bytes of the packets, which is clamped between [`MIN_PACKET_SIZE`]
+    /// and [`MAX_PACKET_SIZE`]
+    pub(crate) fn set_max_packet_size(&mut self, max_packet_size: usize) {
+        self.max_packet_size = max_packet_size.clamp(MIN_PACKET_SIZE, MAX_PACKET_SIZE);
+    }
+
+    pub(crate) fn max_packet_size(&self) -> usize {
+        self.max_packet_size
+    }
+
+    /// Get an empty buffer from the pool, or allocate a new one if the pool is empty
+    fn get_new_buffer(&mut self) -> Payload {
+        self.buffer_pool
+            .pop()
+            .unwrap_or_else(|| Vec::with_capacity(self.max_packet_size))
+    }
+
+    /// Return the buffer of a packet that was sent, so that it can be reused for the next packets
+    pub(crate) fn release_buffer(&mut self, mut buffer: Payload) {
+        if self.buffer_pool.len() < MAX_POOLED_BUFFERS {
+            buffer.clear();
+            self.buffer_pool.push(buffer);
+        }
     }
 
     /// Start building new packet, we start with an empty packet
@@ -79,6 +134,7 @@ impl PacketBuilder {
             message_acks: vec![],
             packet_id: header.packet_id,
             prewritten_size: 0,
+            max_size: self.max_packet_size,
         });
         Ok(())
     }
@@ -111,6 +167,7 @@ impl PacketBuilder {
             )],
             packet_id: header.packet_id,
             prewritten_size: 0,
+            max_size: self.max_packet_size,
         });
         Ok(())
 
@@ -137,10 +194,8 @@ impl PacketBuilder {
     }
 
     pub fn finish_packet(&mut self) -> Packet {
-        let mut packet = self.current_packet.take().unwrap();
-        packet.payload.shrink_to_fit();
-        // TODO: should we use bytes so this clone is cheap?
-        packet
+        // we don't shrink the payload, so that its allocation can be reused once the packet is sent
+        self.current_packet.take().unwrap()
     }
 
     /// Pack messages into packets
@@ -149,6 +204,8 @@ impl PacketBuilder {
     /// - sort the single data messages from smallest to largest
     /// - write the fragment data first. Big fragments take the entire packet. Small fragments have
     ///   some room to spare for small messages
+    /// - fill each packet with small messages from all the channels, so that the messages of many
+    ///   channels share a packet instead of each channel getting its own packet
     #[cfg_attr(feature = "trace", instrument(level = Level::INFO, skip_all))]
     pub fn build_packets(
         &mut self,
@@ -158,9 +215,6 @@ impl PacketBuilder {
     ) -> Result<Vec<Packet>, SerializationError> {
         let mut packets: Vec<Packet> = vec![];
 
-        // indices in the main vec
-        let mut single_data_idx = 0;
-
         for (_, single_messages) in single_data.iter_mut() {
             // sort from smallest to largest each array of small messages
             single_messages
@@ -177,49 +231,9 @@ impl PacketBuilder {
                     // big fragment, write packet immediately
                     packets.push(self.finish_packet());
                 } else {
-                    let mut packet = self.current_packet.take().unwrap();
                     // it's a smaller fragment, fill it with small messages
-                    'out: while single_data_idx < single_data.len() {
-                        // if we don't even have space for a new channel, return the packet immediately
-                        if !packet.can_fit_channel(channel_id) {
-                            break;
-                        }
-
-                        let (channel_id, single_messages) = &mut single_data[single_data_idx];
-                        // number of messages for this channel that we will write
-                        // (we wait until we know the full number, because we want to write that)
-                        let mut num_messages = 0;
-                        // fill with messages from the current channel
-                        loop {
-                            // no more messages to send in this channel, try to fill with messages from the next channels
-                            if num_messages == single_messages.len() {
-                                Self::write_single_messages(
-                                    &mut packet,
-                                    single_messages,
-                                    &mut num_messages,
-                                    *channel_id,
-                                )?;
-                                single_data_idx += 1;
-                                break;
-                            }
-
-                            if packet.can_fit(single_messages[num_messages].len()) {
-                                packet.prewritten_size += single_messages[num_messages].len();
-                                num_messages += 1;
-                            } else {
-                                // can't add any more messages (since we sorted messages from smallest to largest)
-                                // finish packet and go back to trying to write fragment messages
-                                Self::write_single_messages(
-                                    &mut packet,
-                                    single_messages,
-                                    &mut num_messages,
-                                    *channel_id,
-                                )?;
-                                break 'out;
-                            }
-                        }
-                    }
-                    // no more single messages to send, finish the fragment packet
+                    let mut packet = self.current_packet.take().unwrap();
+                    Self::fill_with_single_messages(&mut packet, &mut single_data)?;
                     self.current_packet = Some(packet);
                     packets.push(self.finish_packet());
                 }
@@ -229,65 +243,65 @@ impl PacketBuilder {
         debug_assert!(self.current_packet.is_none());
 
         // all fragment messages have been written, now write small messages
-        'out: while single_data_idx < single_data.len() {
-            let (channel_id, single_messages) = &mut single_data[single_data_idx];
-            // start a new packet if we aren't already writing one
-            if self.current_packet.is_none() {
-                self.build_new_single_packet(current_tick)?;
-            }
-
+        while single_data
+            .iter()
+            .any(|(_, single_messages)| !single_messages.is_empty())
+        {
+            self.build_new_single_packet(current_tick)?;
             let mut packet = self.current_packet.take().unwrap();
-            // we need to call this to preassign the channel_id
-            if !packet.can_fit_channel(*channel_id) {
-                // can't add any more messages (since we sorted messages from smallest to largest)
-                // finish packet and go back to trying to write fragment messages
-                self.current_packet = Some(packet);
-                packets.push(self.finish_packet());
-                continue 'out;
+            let num_messages = Self::fill_with_single_messages(&mut packet, &mut single_data)?;
+            self.current_packet = Some(packet);
+            packets.push(self.finish_packet());
+            // the remaining messages cannot fit in an empty packet, which should not happen
+            // since big messages are fragmented
+            if num_messages == 0 {
+                break;
             }
-            // number of messages for this channel that we will write
-            // (we wait until we know the full number, because we want to write that)
-            let mut num_messages = 0;
-            // fill with messages from the current channel
-            loop {
-                // no more messages to send in this channel, try to fill with messages from the next channels
-                if num_messages == single_messages.len() {
-                    Self::write_single_messages(
-                        &mut packet,
-                        single_messages,
-                        &mut num_messages,
-                        *channel_id,
-                    )?;
-                    // we make sure we keep writing the current packet
-                    self.current_packet = Some(packet);
-                    single_data_idx += 1;
-                    break;
-                }
+        }
+        Ok(packets)
+    }
 
-                if packet.can_fit(single_messages[num_messages].len()) {
+    /// Fill the packet with single data messages from all the channels, and return the number of
+    /// messages written.
+    ///
+    /// Each channel writes as many of its messages as can fit (the smallest messages first).
+    /// A channel whose next message does not fit does not finish the packet: the following channels
+    /// can still use the remaining space, so that small messages from many channels share the same packet.
+    fn fill_with_single_messages(
+        packet: &mut Packet,
+        single_data: &mut [(ChannelId, VecDeque<SingleData>)],
+    ) -> Result<usize, SerializationError> {
+        let mut total_messages = 0;
+        for (channel_id, single_messages) in single_data.iter_mut() {
+            // a channel can have multiple sections in the same packet, since the number of messages
+            // in a section is limited
+            while !single_messages.is_empty() && packet.can_fit_channel(*channel_id) {
+                let max_messages = single_messages.len().min(MAX_MESSAGES_PER_SECTION);
+                // number of messages for this channel that we will write
+                // (we wait until we know the full number, because we want to write that)
+                let mut num_messages = 0;
+                while num_messages < max_messages
+                    && packet.can_fit(single_messages[num_messages].len())
+                {
                     packet.prewritten_size += single_messages[num_messages].len();
                     num_messages += 1;
-                } else {
-                    // can't add any more messages (since we sorted messages from smallest to largest)
-                    // finish packet and go back to trying to write fragment messages
-                    Self::write_single_messages(
-                        &mut packet,
-                        single_messages,
-                        &mut num_messages,
-                        *channel_id,
-                    )?;
-                    self.current_packet = Some(packet);
-                    packets.push(self.finish_packet());
-                    continue 'out;
+                }
+                total_messages += num_messages;
+                let section_full = num_messages == MAX_MESSAGES_PER_SECTION;
+                Self::write_single_messages(
+                    packet,
+                    single_messages,
+                    &mut num_messages,
+                    *channel_id,
+                )?;
+                // the next message doesn't fit (and neither do the bigger ones after it),
+                // try the next channel
+                if !section_full {
+                    break;
                 }
             }
         }
-
-        // if we had a packet we were working on, push it
-        if self.current_packet.is_some() {
-            packets.push(self.finish_packet());
-        }
-        Ok(packets)
+        Ok(total_messages)
     }
 
     /// Helper function to fill the current packet with single data message from the current channel
@@ -304,12 +318,16 @@ impl PacketBuilder {
         if *num_messages > 0 {
             channel_id.to_bytes(&mut packet.payload)?;
             // write the number of messages for the current channel
-            packet.payload.write_u8(*num_messages as u8).unwrap();
-            // write the messages
+            packet.payload.write_varint(*num_messages as u64)?;
+            // write the messages, with their ids delta-encoded from the previous message id
+            let mut previous_id = None;
             for _ in 0..*num_messages {
                 // TODO: deal with error
                 let message = messages.pop_front().unwrap();
-                message.to_bytes(&mut packet.payload).unwrap();
+                message
+                    .to_bytes_with_previous(&mut packet.payload, previous_id)
+                    .unwrap();
+                previous_id = message.id.or(previous_id);
                 packet.prewritten_size = packet
                     .prewritten_size
                     .checked_sub(message.len())
@@ -416,7 +434,7 @@ impl PacketBuilder {
 
 #[cfg(test)]
 mod tests {
-    use std::collections::VecDeque;
+    use std::collections::{HashMap, VecDeque};
 
     use bevy::prelude::{default, TypePath};
     use bytes::Bytes;
@@ -563,7 +581,65 @@ mod tests {
         ];
         let fragment_data = vec![];
         let packets = manager.build_packets(Tick(0), single_data, fragment_data)?;
+        // 600 messages of 12 bytes don't fit in 6 packets; every packet but the last one is filled
+        // with messages from all the channels
         assert_eq!(packets.len(), 7);
+        assert!(packets[..6]
+            .iter()
+            .all(|packet| !packet.can_fit(small_message.len())));
+
+        // channels with more messages than fit in a single section are split in multiple sections
+        let mut num_messages = HashMap::new();
+        for packet in packets {
+            for (channel_id, messages) in packet.parse_packet_payload()? {
+                *num_messages.entry(channel_id).or_insert(0) += messages.len();
+            }
+        }
+        assert_eq!(num_messages.get(channel_id1), Some(&200));
+        assert_eq!(num_messages.get(channel_id2), Some(&200));
+        assert_eq!(num_messages.get(channel_id3), Some(&200));
+        Ok(())
+    }
+
+    /// A channel whose message does not fit in the packet does not prevent the following
+    /// channels from using the remaining space
+    ///
+    /// Channel 1: 500 bytes
+    /// Channel 2: 800 bytes
+    /// Channel 3: 600 bytes
+    ///
+    /// We should get 2 packets: 1 with the messages from channels 1 and 3, and 1 with the message from channel 2
+    #[test]
+    fn test_pack_fill_with_next_channels() -> Result<(), PacketError> {
+        let channel_registry = get_channel_registry();
+        let mut manager = PacketBuilder::new(1.5);
+        let channel_id1 = *channel_registry
+            .get_net_from_kind(&ChannelKind::of::<Channel1>())
+            .unwrap();
+        let channel_id2 = *channel_registry
+            .get_net_from_kind(&ChannelKind::of::<Channel2>())
+            .unwrap();
+        let channel_id3 = *channel_registry
+            .get_net_from_kind(&ChannelKind::of::<Channel3>())
+            .unwrap();
+
+        let message = |size: usize| SingleData::new(None, Bytes::from(vec![7u8; size]));
+        let single_data = vec![
+            (channel_id1, VecDeque::from(vec![message(500)])),
+            (channel_id2, VecDeque::from(vec![message(800)])),
+            (channel_id3, VecDeque::from(vec![message(600)])),
+        ];
+        let packets = manager.build_packets(Tick(0), single_data, vec![])?;
+        assert_eq!(packets.len(), 2);
+
+        let mut packets_queue: VecDeque<_> = packets.into();
+        let contents = packets_queue.pop_front().unwrap().parse_packet_payload()?;
+        assert_eq!(contents.len(), 2);
+        assert!(contents.contains_key(&channel_id1));
+        assert!(contents.contains_key(&channel_id3));
+        let contents = packets_queue.pop_front().unwrap().parse_packet_payload()?;
+        assert_eq!(contents.len(), 1);
+        assert!(contents.contains_key(&channel_id2));
         Ok(())
     }
 
@@ -685,5 +761,32 @@ mod tests {
         Ok(())
     }
 
+    /// The buffers of the packets that were sent are reused for the next packets
+    #[test]
+    fn test_reuse_released_buffers() -> Result<(), PacketError> {
+        let channel_registry = get_channel_registry();
+        let mut manager = PacketBuilder::new(1.5);
+        let channel_id = *channel_registry
+            .get_net_from_kind(&ChannelKind::of::<Channel1>())
+            .unwrap();
+        let small_message = SingleData::new(None, Bytes::from(vec![7u8; 10]));
+
+        let single_data = vec![(channel_id, VecDeque::from(vec![small_message.clone()]))];
+        let mut packets = manager.build_packets(Tick(0), single_data, vec![])?;
+        let payload = packets.pop().unwrap().payload;
+        let ptr = payload.as_ptr();
+        manager.release_buffer(payload);
+
+        let single_data = vec![(channel_id, VecDeque::from(vec![small_message.clone()]))];
+        let mut packets = manager.build_packets(Tick(1), single_data, vec![])?;
+        let packet = packets.pop().unwrap();
+        assert_eq!(packet.payload.as_ptr(), ptr);
+        assert_eq!(
+            packet.parse_packet_payload()?.get(&channel_id).unwrap(),
+            &vec![small_message.bytes]
+        );
+        Ok(())
+    }
+
     // TODO: ADD MORE TESTS
 }
@@ -9,12 +9,27 @@ use tracing::{debug, error, trace};
 #[cfg(feature = "trace")]
 use tracing::{instrument, Level};
 
-use crate::packet::message::{FragmentData, MessageData, MessageId, SendMessage, SingleData};
+use crate::connection::netcode::MAX_PACKET_SIZE;
+use crate::packet::message::{
+    FragmentData, MessageAck, MessageData, MessageId, SendMessage, SingleData,
+};
 use crate::prelude::{ChannelRegistry, Tick};
 use crate::protocol::channel::ChannelId;
 use crate::protocol::registry::NetId;
 
 const BYPASS_QUOTA_PRIORITY: f32 = 100000.0;
+/// Number of bytes shared between the channels in each round of the bandwidth scheduler
+const BANDWIDTH_ROUND_BYTES: f32 = MAX_PACKET_SIZE as f32;
+
+/// Messages of a channel that are waiting for some bandwidth
+struct ChannelQueue {
+    channel_priority: f32,
+    weight: f32,
+    /// Number of bytes that the channel can still send in the current round
+    deficit: f32,
+    /// Messages sorted from lowest to highest priority
+    messages: Vec<BufferedMessage>,
+}
 
 #[derive(Debug)]
 pub struct BufferedMessage {
@@ -95,7 +110,7 @@ impl PriorityManager {
     // TODO: maybe accumulate the used_bytes in the priority_manager instead of returning here?
     /// Filter the messages by priority and bandwidth quota
     /// Returns the list of messages that we can send, along with the amount of bytes we used
-    /// in the rate limiter.
+    /// in the rate limiter, and the list of messages with an id that could not be sent.
     #[cfg_attr(feature = "trace", instrument(level = Level::INFO, skip_all))]
     pub(crate) fn priority_filter(
         &mut self,
@@ -106,6 +121,7 @@ impl PriorityManager {
         Vec<(ChannelId, VecDeque<SingleData>)>,
         Vec<(ChannelId, VecDeque<FragmentData>)>,
         u32,
+        Vec<(ChannelId, MessageAck)>,
     ) {
         // if the bandwidth quota is disabled, just pass all messages through
         // As an optimization: no need to send the tick of the message, it is the same as the header tick
@@ -138,110 +154,124 @@ impl PriorityManager {
                         .collect(),
                 ));
             }
-            return (single_data, fragment_data, 0);
+            return (single_data, fragment_data, 0, vec![]);
         }
 
-        // compute the priority of each new message
-        let mut all_messages = data
+        // group the messages by channel, and compute the priority of each new message
+        let mut queues = data
             .into_iter()
-            .flat_map(|(net_id, (single, fragment))| {
-                let channel_priority = channel_registry
+            .map(|(net_id, (single, fragment))| {
+                let settings = &channel_registry
                     .get_builder_from_net_id(net_id)
                     .unwrap()
-                    .settings
-                    .priority;
+                    .settings;
+                let channel_priority = settings.priority;
                 trace!(?channel_priority, num_single=?single.len(), "channel priority");
-                single
+                // TODO (IMPORTANT): we should split fragments AFTER priority filtering
+                //  because if we don't send one fragment, it's over..
+                let mut messages = single
                     .into_iter()
-                    .map(move |single| BufferedMessage {
-                        priority: single.priority * channel_priority,
+                    .chain(fragment)
+                    .map(|message| BufferedMessage {
+                        priority: message.priority * channel_priority,
                         channel_net_id: net_id,
-                        data: single.data,
+                        data: message.data,
                     })
-                    .chain(fragment.into_iter().map(move |fragment| {
-                        // TODO (IMPORTANT): we should split fragments AFTER priority filtering
-                        //  because if we don't send one fragment, it's over..
-                        BufferedMessage {
-                            priority: fragment.priority * channel_priority,
-                            channel_net_id: net_id,
-                            data: fragment.data,
-                        }
-                    }))
+                    .collect::<Vec<_>>();
+                // sort from lower priority to highest, so that we can pop the highest priority message
+                messages.sort_by(|a, b| a.priority.partial_cmp(&b.priority).unwrap());
+                ChannelQueue {
+                    channel_priority,
+                    weight: settings.bandwidth_weight.max(0.0),
+                    deficit: 0.0,
+                    messages,
+                }
             })
             .collect::<Vec<_>>();
+        // the channels with the highest priority get to send first in each round
+        queues.sort_by(|a, b| b.channel_priority.partial_cmp(&a.channel_priority).unwrap());
 
-        // sort from highest priority to lower
-        all_messages.sort_by(|a, b| a.priority.partial_cmp(&b.priority).unwrap());
-        debug!(
-            "all messages to send, sorted by priority: {:?}",
-            all_messages
-        );
-
-        // select the top messages with the rate limiter
         let mut single_data: HashMap<ChannelId, VecDeque<SingleData>> = HashMap::new();
         let mut fragment_data: HashMap<ChannelId, VecDeque<FragmentData>> = HashMap::new();
         let mut bytes_used = 0;
-        while let Some(buffered_message) = all_messages.pop() {
-            // we don't use the exact size of the message, but the size of the bytes
-            // we will adjust for this later
-            let message_bytes = buffered_message.data.len() as u32;
-            let nonzero_message_bytes = NonZeroU32::try_from(message_bytes).unwrap();
-            let Ok(result) = self.limiter.check_n(nonzero_message_bytes) else {
-                error!("the bandwidth does not have enough capacity for a message of this size!");
-                break;
-            };
 
-            // above BYPASS_QUOTA_PRIORITY, we still send the message
-            if buffered_message.priority < BYPASS_QUOTA_PRIORITY {
-                let Ok(()) = result else {
-                    debug!("Bandwidth quota reached, no more messages can be sent this tick");
-                    break;
-                };
+        // above BYPASS_QUOTA_PRIORITY, we still send the message
+        for queue in queues.iter_mut() {
+            while queue
+                .messages
+                .last()
+                .is_some_and(|message| message.priority >= BYPASS_QUOTA_PRIORITY)
+            {
+                let buffered_message = queue.messages.pop().unwrap();
+                let message_bytes = buffered_message.data.len() as u32;
+                let _ = self
+                    .limiter
+                    .check_n(NonZeroU32::try_from(message_bytes).unwrap());
+                bytes_used += message_bytes;
+                self.add_message(
+                    buffered_message,
+                    channel_registry,
+                    &mut single_data,
+                    &mut fragment_data,
+                );
             }
-            trace!(channel=?buffered_message.channel_net_id, "Sending message with priority {:?}", buffered_message.priority);
+        }
 
-            // keep track of the bytes we added to the rate limiter
-            bytes_used += message_bytes;
+        // select the top messages of each channel with the rate limiter, using deficit round-robin:
+        // every round, each channel gets a number of bytes proportional to its weight
+        let total_weight = queues.iter().map(|queue| queue.weight).sum::<f32>();
+        let num_queues = queues.len() as f32;
+        'rounds: while queues.iter().any(|queue| !queue.messages.is_empty()) {
+            for queue in queues.iter_mut().filter(|queue| !queue.messages.is_empty()) {
+                let share = if total_weight > 0.0 {
+                    queue.weight / total_weight
+                } else {
+                    1.0 / num_queues
+                };
+                queue.deficit += (share * BANDWIDTH_ROUND_BYTES).max(1.0);
+                while let Some(buffered_message) = queue.messages.last() {
+                    // we don't use the exact size of the message, but the size of the bytes
+                    // we will adjust for this later
+                    let message_bytes = buffered_message.data.len() as u32;
+                    if message_bytes as f32 > queue.deficit {
+                        break;
+                    }
+                    let nonzero_message_bytes = NonZeroU32::try_from(message_bytes).unwrap();
+                    let Ok(result) = self.limiter.check_n(nonzero_message_bytes) else {
+                        error!("the bandwidth does not have enough capacity for a message of this size!");
+                        break 'rounds;
+                    };
+                    let Ok(()) = result else {
+                        debug!("Bandwidth quota reached, no more messages can be sent this tick");
+                        break 'rounds;
+                    };
+                    queue.deficit -= message_bytes as f32;
+                    let buffered_message = queue.messages.pop().unwrap();
+                    trace!(channel=?buffered_message.channel_net_id, "Sending message with priority {:?}", buffered_message.priority);
 
-            // notify the replication sender that the message was actually sent
-            if channel_registry.is_replication_update_channel(buffered_message.channel_net_id) {
-                // SAFETY: we are guaranteed in this situation to have a message id (because we use the unreliable with acks sender)
-                let message_id = buffered_message.data.message_id().unwrap();
-                for sender in self.replication_update_senders.iter() {
-                    trace!(
-                        ?message_id,
-                        "notifying replication sender that a message was actually sent."
+                    // keep track of the bytes we added to the rate limiter
+                    bytes_used += message_bytes;
+                    self.add_message(
+                        buffered_message,
+                        channel_registry,
+                        &mut single_data,
+                        &mut fragment_data,
                     );
-                    let _ = sender.send(message_id).map_err(|e| {
-                        error!("error notifying replication sender that a message was actually sent: {:?}", e)
-                    });
-                }
-            }
-
-            // the message is allowed, add it to the list of messages to send
-            match buffered_message.data {
-                MessageData::Single(single) => {
-                    single_data
-                        .entry(buffered_message.channel_net_id)
-                        .or_default()
-                        .push_back(single);
-                }
-                MessageData::Fragment(fragment) => {
-                    fragment_data
-                        .entry(buffered_message.channel_net_id)
-                        .or_default()
-                        .push_back(fragment);
                 }
             }
         }
 
         // all the other messages that don't make the cut, we just drop
         // - unreliable messages: they are unreliable so it's ok
-        // - reliable messages: they will be retried later, maybe with higher priority?
+        // - reliable messages: we return them so that the channel can queue them again for the next send
         // - unreliable entity updates: the replication sender keeps track for each entity of when we were able to send an update
         //   - PROBLEM: we could have the entity action not get sent (bandwidth), and then the priority still drops because the entity update
         //     was sent right after...
         // - reliable entity actions:
+        let all_messages = queues
+            .into_iter()
+            .flat_map(|queue| queue.messages)
+            .collect::<Vec<_>>();
         let num_messages_sent = single_data.values().map(|data| data.len()).sum::<usize>()
             + fragment_data.values().map(|data| data.len()).sum::<usize>();
         debug!(
@@ -250,10 +280,131 @@ impl PriorityManager {
             num_messages_discarded = ?all_messages.len(),
             "priority filter done.");
 
+        let messages_not_sent = all_messages
+            .into_iter()
+            .filter_map(|buffered_message| {
+                let message_ack = match buffered_message.data {
+                    MessageData::Single(single) => MessageAck {
+                        message_id: single.id?,
+                        fragment_id: None,
+                    },
+                    MessageData::Fragment(fragment) => MessageAck {
+                        message_id: fragment.message_id,
+                        fragment_id: Some(fragment.fragment_id),
+                    },
+                };
+                Some((buffered_message.channel_net_id, message_ack))
+            })
+            .collect();
+
         (
             single_data.into_iter().collect(),
             fragment_data.into_iter().collect(),
             bytes_used,
+            messages_not_sent,
         )
     }
+
+    /// Add a message that passed the bandwidth quota to the list of messages to send
+    fn add_message(
+        &self,
+        buffered_message: BufferedMessage,
+        channel_registry: &ChannelRegistry,
+        single_data: &mut HashMap<ChannelId, VecDeque<SingleData>>,
+        fragment_data: &mut HashMap<ChannelId, VecDeque<FragmentData>>,
+    ) {
+        // notify the replication sender that the message was actually sent
+        if channel_registry.is_replication_update_channel(buffered_message.channel_net_id) {
+            // SAFETY: we are guaranteed in this situation to have a message id (because we use the unreliable with acks sender)
+            let message_id = buffered_message.data.message_id().unwrap();
+            for sender in self.replication_update_senders.iter() {
+                trace!(
+                    ?message_id,
+                    "notifying replication sender that a message was actually sent."
+                );
+                let _ = sender.send(message_id).map_err(|e| {
+                    error!(
+                        "error notifying replication sender that a message was actually sent: {:?}",
+                        e
+                    )
+                });
+            }
+        }
+
+        match buffered_message.data {
+            MessageData::Single(single) => {
+                single_data
+                    .entry(buffered_message.channel_net_id)
+                    .or_default()
+                    .push_back(single);
+            }
+            MessageData::Fragment(fragment) => {
+                fragment_data
+                    .entry(buffered_message.channel_net_id)
+                    .or_default()
+                    .push_back(fragment);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::default;
+    use bytes::Bytes;
+
+    use crate::prelude::*;
+    use crate::tests::protocol::*;
+
+    use super::*;
+
+    #[test]
+    fn test_bandwidth_weight() {
+        let mut channel_registry = ChannelRegistry::default();
+        channel_registry.add_channel::<Channel1>(ChannelSettings {
+            mode: ChannelMode::UnorderedUnreliable,
+            bandwidth_weight: 1.0,
+            ..default()
+        });
+        channel_registry.add_channel::<Channel2>(ChannelSettings {
+            mode: ChannelMode::UnorderedUnreliable,
+            bandwidth_weight: 3.0,
+            ..default()
+        });
+        let channel_1 = *channel_registry
+            .get_net_from_kind(&ChannelKind::of::<Channel1>())
+            .unwrap();
+        let channel_2 = *channel_registry
+            .get_net_from_kind(&ChannelKind::of::<Channel2>())
+            .unwrap();
+        let mut manager = PriorityManager::new(PriorityConfig {
+            bandwidth_quota: Quota::per_second(nonzero!(1000u32)),
+            enabled: true,
+        });
+
+        let messages = || {
+            (0..20)
+                .map(|_| SendMessage {
+                    data: SingleData::new(None, Bytes::from(vec![0; 100])).into(),
+                    priority: 1.0,
+                })
+                .collect::<VecDeque<_>>()
+        };
+        let data = vec![
+            (channel_1, (messages(), VecDeque::new())),
+            (channel_2, (messages(), VecDeque::new())),
+        ];
+        let (single_data, _, bytes_used, _) =
+            manager.priority_filter(data, &channel_registry, Tick(0));
+        let num_sent = |channel: ChannelId| {
+            single_data
+                .iter()
+                .find(|(net_id, _)| *net_id == channel)
+                .map_or(0, |(_, data)| data.len())
+        };
+        assert!(bytes_used <= 1000);
+        // the bandwidth is shared proportionally to the weight of each channel
+        assert!(num_sent(channel_1) > 0);
+        assert!(num_sent(channel_2) >= 3 * num_sent(channel_1));
+    }
 }
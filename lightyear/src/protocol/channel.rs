@@ -4,12 +4,20 @@ use bevy::utils::Duration;
 use std::any::TypeId;
 use std::collections::HashMap;
 
-use crate::channel::builder::{Channel, ChannelBuilder, ChannelSettings, PongChannel};
+use byteorder::WriteBytesExt;
+use tracing::error;
+
 use crate::channel::builder::{
-    ChannelContainer, EntityActionsChannel, EntityUpdatesChannel, InputChannel, PingChannel,
+    AuthorityChannel, ChannelContainer, ChannelRegistrationChannel, ChecksumChannel, ChunkChannel,
+    EntityActionsChannel, EntityUpdatesChannel, InitialSyncChannel, InputChannel, PingChannel,
 };
+use crate::channel::builder::{Channel, ChannelBuilder, ChannelSettings, PongChannel};
+use crate::channel::compression::MessageCompression;
 use crate::prelude::{ChannelDirection, ChannelMode, ReliableSettings};
 use crate::protocol::registry::{NetId, TypeKind, TypeMapper};
+use crate::serialize::reader::Reader;
+use crate::serialize::varint::{varint_len, VarIntReadExt, VarIntWriteExt};
+use crate::serialize::{SerializationError, ToBytes};
 
 // TODO: derive Reflect once we reach bevy 0.14
 /// ChannelKind - internal wrapper around the type of the channel
@@ -63,6 +71,14 @@ pub struct ChannelRegistry {
     pub(in crate::protocol) builder_map: HashMap<ChannelKind, ChannelBuilder>,
     pub(in crate::protocol) kind_map: TypeMapper<ChannelKind>,
     pub(in crate::protocol) name_map: HashMap<ChannelKind, String>,
+    /// Channels that were registered at runtime on the server, in registration order.
+    /// Their ids are sent to the clients when they connect
+    runtime_channels: Vec<ChannelKind>,
+    /// Channels that were registered at runtime on the client, and for which we haven't received an id
+    /// from the server yet
+    unbound_channels: HashMap<String, (ChannelKind, ChannelBuilder)>,
+    /// Ids received from the server for runtime channels that the client hasn't registered yet
+    remote_net_ids: HashMap<String, ChannelId>,
     built: bool,
 }
 
@@ -72,6 +88,9 @@ impl ChannelRegistry {
             builder_map: HashMap::new(),
             kind_map: TypeMapper::new(),
             name_map: HashMap::new(),
+            runtime_channels: Vec::new(),
+            unbound_channels: HashMap::new(),
+            remote_net_ids: HashMap::new(),
             built: false,
         };
         registry.add_channel::<EntityUpdatesChannel>(ChannelSettings {
@@ -83,6 +102,9 @@ impl ChannelRegistry {
             send_frequency: Duration::default(),
             direction: ChannelDirection::Bidirectional,
             priority: 1.0,
+            bandwidth_weight: 1.0,
+            authenticated: false,
+            compression: MessageCompression::None,
         });
         registry.add_channel::<EntityActionsChannel>(ChannelSettings {
             mode: ChannelMode::UnorderedReliable(ReliableSettings::default()),
@@ -94,6 +116,9 @@ impl ChannelRegistry {
             send_frequency: Duration::default(),
             // we want to send the entity actions as soon as possible
             priority: 10.0,
+            bandwidth_weight: 1.0,
+            authenticated: false,
+            compression: MessageCompression::None,
         });
         registry.add_channel::<PingChannel>(ChannelSettings {
             mode: ChannelMode::SequencedUnreliable,
@@ -101,6 +126,9 @@ impl ChannelRegistry {
             send_frequency: Duration::default(),
             // we always want to include the ping in the packet
             priority: 1000.0,
+            bandwidth_weight: 1.0,
+            authenticated: false,
+            compression: MessageCompression::None,
         });
         registry.add_channel::<PongChannel>(ChannelSettings {
             mode: ChannelMode::SequencedUnreliable,
@@ -108,12 +136,65 @@ impl ChannelRegistry {
             send_frequency: Duration::default(),
             // we always want to include the ping in the packet
             priority: 1000.0,
+            bandwidth_weight: 1.0,
+            authenticated: false,
+            compression: MessageCompression::None,
         });
         registry.add_channel::<InputChannel>(ChannelSettings {
             mode: ChannelMode::UnorderedUnreliable,
             direction: ChannelDirection::ClientToServer,
             send_frequency: input_send_interval,
             priority: 3.0,
+            bandwidth_weight: 1.0,
+            authenticated: false,
+            compression: MessageCompression::None,
+        });
+        registry.add_channel::<ChannelRegistrationChannel>(ChannelSettings {
+            mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+            direction: ChannelDirection::Bidirectional,
+            send_frequency: Duration::default(),
+            // the remote needs the channel ids before we can send messages on those channels
+            priority: 10.0,
+            bandwidth_weight: 1.0,
+            authenticated: false,
+            compression: MessageCompression::None,
+        });
+        registry.add_channel::<AuthorityChannel>(ChannelSettings {
+            mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+            direction: ChannelDirection::ServerToClient,
+            send_frequency: Duration::default(),
+            priority: 10.0,
+            bandwidth_weight: 1.0,
+            authenticated: false,
+            compression: MessageCompression::None,
+        });
+        registry.add_channel::<InitialSyncChannel>(ChannelSettings {
+            mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+            direction: ChannelDirection::ServerToClient,
+            send_frequency: Duration::default(),
+            priority: 10.0,
+            bandwidth_weight: 1.0,
+            authenticated: false,
+            compression: MessageCompression::None,
+        });
+        registry.add_channel::<ChunkChannel>(ChannelSettings {
+            mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+            direction: ChannelDirection::ServerToClient,
+            send_frequency: Duration::default(),
+            // chunks can be large, they should not delay the other messages
+            priority: 0.5,
+            bandwidth_weight: 1.0,
+            authenticated: false,
+            compression: MessageCompression::None,
+        });
+        registry.add_channel::<ChecksumChannel>(ChannelSettings {
+            mode: ChannelMode::UnorderedUnreliable,
+            direction: ChannelDirection::ServerToClient,
+            send_frequency: Duration::default(),
+            priority: 1.0,
+            bandwidth_weight: 1.0,
+            authenticated: false,
+            compression: MessageCompression::None,
         });
         registry
     }
@@ -154,6 +235,76 @@ impl ChannelRegistry {
         self.name_map.insert(kind, name.to_string());
     }
 
+    /// Register a channel on the server after the protocol has been built.
+    ///
+    /// The channel gets the next available id, which is sent to the clients so that
+    /// they can register the channel with the same id.
+    pub(crate) fn add_runtime_channel<C: Channel>(&mut self, settings: ChannelSettings) {
+        if self.kind_map.net_id(&ChannelKind::of::<C>()).is_some() {
+            return;
+        }
+        self.add_channel::<C>(settings);
+        self.runtime_channels.push(ChannelKind::of::<C>());
+    }
+
+    /// Channels that were registered at runtime on the server, in registration order
+    pub(crate) fn runtime_channels(&self) -> &[ChannelKind] {
+        &self.runtime_channels
+    }
+
+    /// Register a channel on the client after the protocol has been built.
+    ///
+    /// The channel can only be used once the server has sent the id to use for it.
+    /// Returns the kind of the channel if the id was already received.
+    pub(crate) fn add_unbound_channel<C: Channel>(
+        &mut self,
+        settings: ChannelSettings,
+    ) -> Option<ChannelKind> {
+        let kind = ChannelKind::of::<C>();
+        let name = C::name().to_string();
+        self.unbound_channels
+            .insert(name.clone(), (kind, C::get_builder(settings)));
+        let net_id = self.remote_net_ids.remove(&name)?;
+        self.bind_channel(name, net_id)
+    }
+
+    /// Assign the id chosen by the server to a channel that was registered at runtime.
+    ///
+    /// Returns the kind of the channel if it was registered locally, otherwise the id is stored
+    /// until the channel gets registered.
+    pub(crate) fn bind_channel(&mut self, name: String, net_id: ChannelId) -> Option<ChannelKind> {
+        let Some((kind, builder)) = self.unbound_channels.remove(&name) else {
+            self.remote_net_ids.insert(name, net_id);
+            return None;
+        };
+        if !self.kind_map.insert(kind, net_id) {
+            error!(
+                ?name,
+                ?net_id,
+                "Could not bind channel: the id is already used"
+            );
+            return None;
+        }
+        self.builder_map.insert(kind, builder);
+        self.name_map.insert(kind, name);
+        Some(kind)
+    }
+
+    /// Returns true if any of the registered channels is authenticated
+    pub(crate) fn has_authenticated_channels(&self) -> bool {
+        self.builder_map
+            .values()
+            .chain(self.unbound_channels.values().map(|(_, builder)| builder))
+            .any(|builder| builder.settings.authenticated)
+    }
+
+    /// Replace the settings of a registered channel
+    pub(crate) fn update_settings(&mut self, kind: &ChannelKind, settings: ChannelSettings) {
+        if let Some(builder) = self.builder_map.get_mut(kind) {
+            builder.settings = settings;
+        }
+    }
+
     /// get the registered object for a given type
     pub fn get_builder_from_kind(&self, channel_kind: &ChannelKind) -> Option<&ChannelBuilder> {
         self.builder_map.get(channel_kind)
@@ -182,6 +333,41 @@ impl ChannelRegistry {
     }
 }
 
+/// Message sent by the server to tell a client which [`ChannelId`] to use for a channel registered at runtime.
+///
+/// The client sends the same message back once it has registered the channel locally.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ChannelRegistration {
+    pub(crate) name: String,
+    pub(crate) net_id: ChannelId,
+}
+
+impl ToBytes for ChannelRegistration {
+    fn len(&self) -> usize {
+        varint_len(self.name.len() as u64) + self.name.len() + self.net_id.len()
+    }
+
+    fn to_bytes<T: WriteBytesExt>(&self, buffer: &mut T) -> Result<(), SerializationError> {
+        buffer.write_varint(self.name.len() as u64)?;
+        buffer.write_all(self.name.as_bytes())?;
+        self.net_id.to_bytes(buffer)
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
+    where
+        Self: Sized,
+    {
+        let name_len = buffer.read_varint()? as usize;
+        if name_len > buffer.remaining() {
+            return Err(SerializationError::InvalidValue);
+        }
+        let name = String::from_utf8(buffer.split_len(name_len).to_vec())
+            .map_err(|_| SerializationError::InvalidValue)?;
+        let net_id = ChannelId::from_bytes(buffer)?;
+        Ok(Self { name, net_id })
+    }
+}
+
 /// Add a message to the list of messages that can be sent
 pub trait AppChannelExt {
     fn add_channel<C: Channel>(&mut self, settings: ChannelSettings);
@@ -224,4 +410,42 @@ mod tests {
             ChannelMode::UnorderedUnreliable
         );
     }
+
+    #[test]
+    fn test_runtime_channel() {
+        let settings = ChannelSettings {
+            mode: ChannelMode::UnorderedUnreliable,
+            ..default()
+        };
+        let mut server_registry = ChannelRegistry::new(Duration::default());
+        server_registry.add_runtime_channel::<MyChannel>(settings.clone());
+        let kind = ChannelKind::of::<MyChannel>();
+        assert_eq!(server_registry.runtime_channels(), &[kind]);
+        let registration = ChannelRegistration {
+            name: server_registry.name(&kind).unwrap().to_string(),
+            net_id: *server_registry.get_net_from_kind(&kind).unwrap(),
+        };
+
+        // the registration can be serialized
+        let mut writer = crate::serialize::writer::Writer::with_capacity(registration.len());
+        registration.to_bytes(&mut writer).unwrap();
+        let mut reader = Reader::from(writer.to_bytes());
+        let received = ChannelRegistration::from_bytes(&mut reader).unwrap();
+        assert_eq!(received, registration);
+
+        // the client receives the id before registering the channel
+        let mut client_registry = ChannelRegistry::new(Duration::default());
+        assert_eq!(
+            client_registry.bind_channel(received.name, received.net_id),
+            None
+        );
+        assert_eq!(
+            client_registry.add_unbound_channel::<MyChannel>(settings),
+            Some(kind)
+        );
+        assert_eq!(
+            client_registry.get_net_from_kind(&kind),
+            Some(&registration.net_id)
+        );
+    }
 }
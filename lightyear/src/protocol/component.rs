@@ -5,8 +5,9 @@ use std::fmt::Debug;
 use std::hash::Hash;
 use std::ops::{Add, Mul};
 
-use bevy::prelude::{App, Component, EntityWorldMut, Mut, Resource, TypePath, World};
+use bevy::prelude::{App, Component, EntityRef, EntityWorldMut, Mut, Resource, TypePath, World};
 use bevy::ptr::Ptr;
+use bevy::reflect::GetTypeRegistration;
 use bevy::utils::HashMap;
 
 use tracing::{debug, error, trace};
@@ -20,7 +21,9 @@ use crate::prelude::server::ServerConfig;
 use crate::prelude::{ChannelDirection, Message, Tick};
 use crate::protocol::delta::ErasedDeltaFns;
 use crate::protocol::registry::{NetId, TypeKind, TypeMapper};
-use crate::protocol::serialize::ErasedSerializeFns;
+use crate::protocol::serialize::{
+    DeserializeFn, ErasedSerializeFns, SerializeFn, SerializerBackend,
+};
 use crate::serialize::reader::Reader;
 use crate::serialize::SerializationError;
 use crate::shared::events::connection::ConnectionEvents;
@@ -78,6 +81,28 @@ pub enum ComponentError {
 ///
 /// Provided that your type implements [`MapEntities`], you can extend the protocol to support this behaviour, by
 /// calling the [`add_map_entities`](ComponentRegistration::add_map_entities) method.
+/// The entities are then mapped every time the component is received, including when it is delta-compressed.
+///
+/// ```rust
+/// use bevy::ecs::entity::{EntityMapper, MapEntities};
+/// use bevy::prelude::*;
+/// use serde::{Deserialize, Serialize};
+/// use lightyear::prelude::*;
+///
+/// #[derive(Component, PartialEq, Serialize, Deserialize)]
+/// struct Target(Entity);
+///
+/// impl MapEntities for Target {
+///     fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+///         self.0 = entity_mapper.map_entity(self.0);
+///     }
+/// }
+///
+/// fn add_components(app: &mut App) {
+///   app.register_component::<Target>(ChannelDirection::ServerToClient)
+///       .add_map_entities();
+/// }
+/// ```
 ///
 /// #### Prediction
 /// When client-prediction is enabled, we create two distinct entities on the client when the server replicates an entity: a Confirmed entity and a Predicted entity.
@@ -139,10 +164,12 @@ pub struct ComponentRegistry {
     prediction_map: HashMap<ComponentKind, PredictionMetadata>,
     serialize_fns_map: HashMap<ComponentKind, ErasedSerializeFns>,
     delta_fns_map: HashMap<ComponentKind, ErasedDeltaFns>,
+    /// Names of the components whose net id doesn't depend on the order of registration
+    stable_names: HashMap<ComponentKind, String>,
     pub(crate) kind_map: TypeMapper<ComponentKind>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct ReplicationMetadata {
     pub component_id: ComponentId,
     pub delta_compression_id: ComponentId,
@@ -151,6 +178,24 @@ pub struct ReplicationMetadata {
     pub disabled_id: ComponentId,
     pub write: RawWriteFn,
     pub remove: Option<RawRemoveFn>,
+    /// Optional [`ValidateFn`] for the component
+    pub validate: Option<unsafe fn()>,
+    /// Optional [`ApplyHookFn`] for the component
+    pub apply_hook: Option<unsafe fn()>,
+    /// If true, the component is only sent when the entity is spawned and is never updated
+    pub immutable: bool,
+}
+
+/// The function pointers are not compared, since their addresses are not guaranteed to be unique
+impl PartialEq for ReplicationMetadata {
+    fn eq(&self, other: &Self) -> bool {
+        self.component_id == other.component_id
+            && self.delta_compression_id == other.delta_compression_id
+            && self.replicate_once_id == other.replicate_once_id
+            && self.override_target_id == other.override_target_id
+            && self.disabled_id == other.disabled_id
+            && self.immutable == other.immutable
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -201,7 +246,15 @@ pub type LerpFn<C> = fn(start: &C, other: &C, t: f32) -> C;
 
 /// Function that returns true if a rollback is needed, by comparing the server's value with the client's predicted value.
 /// Defaults to PartialEq::ne
-type ShouldRollbackFn<C> = fn(this: &C, that: &C) -> bool;
+pub type ShouldRollbackFn<C> = fn(this: &C, that: &C) -> bool;
+
+/// Function called on the receiving side before a replicated component value is applied to the entity.
+/// The value is discarded if it returns false.
+pub type ValidateFn<C> = fn(entity: &EntityRef, component: &C) -> bool;
+
+/// Function called on the receiving side right after a replicated component value is written to the entity.
+/// It receives the previous value of the component, or `None` if the component was just inserted.
+pub type ApplyHookFn<C> = fn(entity: &mut EntityWorldMut, previous: Option<C>);
 
 pub trait Linear {
     fn lerp(start: &Self, other: &Self, t: f32) -> Self;
@@ -263,6 +316,17 @@ impl ComponentRegistry {
         self.serialize_fns_map
             .insert(component_kind, ErasedSerializeFns::new::<C>());
     }
+
+    /// Identify the component by its type path instead of its order of registration
+    pub(crate) fn set_stable_name<C: TypePath>(&mut self) {
+        self.stable_names
+            .insert(ComponentKind::of::<C>(), C::type_path().to_string());
+    }
+
+    /// Assign the net ids of the components that have a stable name, once all of them are registered
+    pub(crate) fn assign_stable_net_ids(&mut self) {
+        self.kind_map.sort_stable(&self.stable_names);
+    }
 }
 
 mod serialize {
@@ -290,6 +354,46 @@ mod serialize {
             erased_fns.add_map_entities::<C>();
         }
 
+        pub(crate) fn set_serializer<C: Message, B: SerializerBackend>(&mut self) {
+            let kind = ComponentKind::of::<C>();
+            let erased_fns = self.serialize_fns_map.get_mut(&kind).unwrap_or_else(|| {
+                panic!(
+                    "Component {} is not part of the protocol",
+                    std::any::type_name::<C>()
+                )
+            });
+            erased_fns.set_backend::<C, B>();
+        }
+
+        pub(crate) fn set_serialize_fns<C: 'static>(
+            &mut self,
+            serialize: SerializeFn<C>,
+            deserialize: DeserializeFn<C>,
+        ) {
+            let kind = ComponentKind::of::<C>();
+            let erased_fns = self.serialize_fns_map.get_mut(&kind).unwrap_or_else(|| {
+                panic!(
+                    "Component {} is not part of the protocol",
+                    std::any::type_name::<C>()
+                )
+            });
+            erased_fns.set_custom_fns::<C>(serialize, deserialize);
+        }
+
+        pub(crate) fn set_network_representation<C: From<R> + 'static, R>(&mut self)
+        where
+            R: Message + for<'a> From<&'a C>,
+        {
+            let kind = ComponentKind::of::<C>();
+            let erased_fns = self.serialize_fns_map.get_mut(&kind).unwrap_or_else(|| {
+                panic!(
+                    "Component {} is not part of the protocol",
+                    std::any::type_name::<C>()
+                )
+            });
+            erased_fns.set_network_representation::<C, R>();
+        }
+
         pub(crate) fn serialize<C: 'static>(
             &self,
             component: &C,
@@ -325,7 +429,7 @@ mod serialize {
             net_id.to_bytes(writer)?;
             // SAFETY: the ErasedSerializeFns corresponds to type C
             unsafe {
-                (erased_fns.serialize)(component, writer)?;
+                (erased_fns.serialize)(erased_fns, component, writer)?;
             }
             Ok(())
         }
@@ -518,6 +622,7 @@ mod replication {
     };
     use crate::serialize::reader::Reader;
     use crate::serialize::ToBytes;
+    use crate::shared::replication::delta::DeltaComponentHistory;
 
     impl ComponentRegistry {
         pub(crate) fn set_replication_fns<C: Component + PartialEq>(&mut self, world: &mut World) {
@@ -534,10 +639,100 @@ mod replication {
                     disabled_id: world.init_component::<DisabledComponent<C>>(),
                     write,
                     remove: Some(remove),
+                    validate: None,
+                    apply_hook: None,
+                    immutable: false,
                 },
             );
         }
 
+        pub(crate) fn set_immutable<C: Component>(&mut self) {
+            let kind = ComponentKind::of::<C>();
+            self.replication_map
+                .get_mut(&kind)
+                .expect("the component is not part of the protocol")
+                .immutable = true;
+        }
+
+        /// Returns true if the component was registered with [`ComponentRegistration::immutable`]
+        pub(crate) fn is_immutable(&self, kind: &ComponentKind) -> bool {
+            self.replication_map
+                .get(kind)
+                .is_some_and(|metadata| metadata.immutable)
+        }
+
+        pub(crate) fn set_validate_fn<C: Component>(&mut self, validate_fn: ValidateFn<C>) {
+            let kind = ComponentKind::of::<C>();
+            self.replication_map
+                .get_mut(&kind)
+                .expect("the component is not part of the protocol")
+                .validate = Some(unsafe {
+                std::mem::transmute::<
+                    for<'a, 'b, 'c> fn(&'a EntityRef<'b>, &'c C) -> bool,
+                    unsafe fn(),
+                >(validate_fn)
+            });
+        }
+
+        pub(crate) fn set_apply_hook<C: Component>(&mut self, apply_hook: ApplyHookFn<C>) {
+            let kind = ComponentKind::of::<C>();
+            self.replication_map
+                .get_mut(&kind)
+                .expect("the component is not part of the protocol")
+                .apply_hook = Some(unsafe {
+                std::mem::transmute::<
+                    for<'a, 'b, 'c> fn(&'a mut EntityWorldMut<'b>, Option<C>),
+                    unsafe fn(),
+                >(apply_hook)
+            });
+        }
+
+        /// Run the [`ApplyHookFn`] of the component, if any, after a value received from the remote was written
+        pub(crate) fn run_apply_hook<C: Component>(
+            &self,
+            entity_world_mut: &mut EntityWorldMut,
+            previous: Option<C>,
+        ) {
+            let kind = ComponentKind::of::<C>();
+            let Some(apply_hook) = self
+                .replication_map
+                .get(&kind)
+                .and_then(|metadata| metadata.apply_hook)
+            else {
+                return;
+            };
+            // SAFETY: the function was registered for the component type C
+            let apply_hook: ApplyHookFn<C> = unsafe { std::mem::transmute(apply_hook) };
+            apply_hook(entity_world_mut, previous);
+        }
+
+        /// Returns false if the [`ValidateFn`] of the component rejects the value received from the remote
+        pub(crate) fn validate<C: Component>(
+            &self,
+            entity_world_mut: &EntityWorldMut,
+            component: &C,
+        ) -> bool {
+            let kind = ComponentKind::of::<C>();
+            let Some(validate) = self
+                .replication_map
+                .get(&kind)
+                .and_then(|metadata| metadata.validate)
+            else {
+                return true;
+            };
+            // SAFETY: the function was registered for the component type C
+            let validate_fn: ValidateFn<C> = unsafe { std::mem::transmute(validate) };
+            let valid = validate_fn(&EntityRef::from(entity_world_mut), component);
+            if !valid {
+                debug!(
+                    entity = ?entity_world_mut.id(),
+                    "Rejected the value of {} received from the remote",
+                    std::any::type_name::<C>()
+                );
+            }
+            valid
+        }
+
         /// SAFETY: the ReadWordBuffer must contain bytes corresponding to the correct component type
         pub(crate) fn raw_write(
             &self,
@@ -548,6 +743,19 @@ mod replication {
             events: &mut ConnectionEvents,
         ) -> Result<(), ComponentError> {
             let net_id = ComponentNetId::from_bytes(reader).map_err(SerializationError::from)?;
+            self.raw_write_with_net_id(reader, net_id, entity_world_mut, tick, entity_map, events)
+        }
+
+        /// Same as [`raw_write`](Self::raw_write), for a component whose net id is not part of the `reader`
+        pub(crate) fn raw_write_with_net_id(
+            &self,
+            reader: &mut Reader,
+            net_id: ComponentNetId,
+            entity_world_mut: &mut EntityWorldMut,
+            tick: Tick,
+            entity_map: &mut EntityMap,
+            events: &mut ConnectionEvents,
+        ) -> Result<(), ComponentError> {
             let kind = self
                 .kind_map
                 .kind(net_id)
@@ -578,17 +786,26 @@ mod replication {
         ) -> Result<(), ComponentError> {
             trace!("Writing component {} to entity", std::any::type_name::<C>());
             let component = self.raw_deserialize::<C>(reader, net_id, entity_map)?;
+            if !self.validate(entity_world_mut, &component) {
+                return Ok(());
+            }
             let entity = entity_world_mut.id();
             // TODO: should we send the event based on on the message type (Insert/Update) or based on whether the component was actually inserted?
             if let Some(mut c) = entity_world_mut.get_mut::<C>() {
+                // immutable components keep the value they were spawned with
+                if self.is_immutable(&ComponentKind::of::<C>()) {
+                    return Ok(());
+                }
                 // only apply the update if the component is different, to not trigger change detection
                 if c.as_ref() != &component {
                     events.push_update_component(entity, net_id, tick);
-                    *c = component;
+                    let previous = std::mem::replace(c.as_mut(), component);
+                    self.run_apply_hook(entity_world_mut, Some(previous));
                 }
             } else {
                 events.push_insert_component(entity, net_id, tick);
                 entity_world_mut.insert(component);
+                self.run_apply_hook::<C>(entity_world_mut, None);
             }
             Ok(())
         }
@@ -611,6 +828,9 @@ mod replication {
 
         pub(crate) fn remove<C: Component>(&self, entity_world_mut: &mut EntityWorldMut) {
             entity_world_mut.remove::<C>();
+            // the history of a delta-compressed component is not needed anymore; if the component is
+            // inserted again, the first value will be a diff from the base value
+            entity_world_mut.remove::<DeltaComponentHistory<C>>();
         }
     }
 }
@@ -630,6 +850,10 @@ mod delta {
             let delta_kind = ComponentKind::of::<DeltaMessage<C::Delta>>();
             // add the delta as a message
             self.register_component::<DeltaMessage<C::Delta>>();
+            if let Some(name) = self.stable_names.get(&kind) {
+                let name = format!("DeltaMessage<{name}>");
+                self.stable_names.insert(delta_kind, name);
+            }
             // add delta-related type-erased functions
             self.delta_fns_map.insert(kind, ErasedDeltaFns::new::<C>());
             // add write/remove functions associated with the delta component's net_id
@@ -647,6 +871,9 @@ mod delta {
                     disabled_id: ComponentId::new(0),
                     write,
                     remove: None,
+                    validate: None,
+                    apply_hook: None,
+                    immutable: false,
                 },
             );
         }
@@ -761,28 +988,41 @@ mod delta {
                     history.buffer = history.buffer.split_off(&previous_tick);
                     // store the new value in the history
                     history.buffer.insert(tick, new_value.clone());
+                    // the history keeps the remote entities, since the diffs are computed by the remote peer
+                    // from its own values; only the value inserted in the world is mapped
+                    self.map_entities(&mut new_value, entity_map)?;
+                    // the history is still updated, since the next diffs are computed from this value
+                    if !self.validate(entity_world_mut, &new_value) {
+                        return Ok(());
+                    }
                     let Some(mut c) = entity_world_mut.get_mut::<C>() else {
                         return Err(ComponentError::DeltaCompressionError(
                             format!("Entity {entity:?} does not have a {} component, but we received a diff for delta-compression",
                             std::any::type_name::<C>())
                         ));
                     };
-                    *c = new_value;
+                    let previous = std::mem::replace(c.as_mut(), new_value);
                     events.push_update_component(entity, net_id, tick);
+                    self.run_apply_hook(entity_world_mut, Some(previous));
                 }
                 DeltaType::FromBase => {
                     let mut new_value = C::base_value();
                     new_value.apply_diff(&delta.delta);
                     let value = new_value.clone();
-                    if let Some(mut c) = entity_world_mut.get_mut::<C>() {
+                    self.map_entities(&mut new_value, entity_map)?;
+                    if !self.validate(entity_world_mut, &new_value) {
+                        // do not apply the value, but still store it in the history below
+                    } else if let Some(mut c) = entity_world_mut.get_mut::<C>() {
                         // only apply the update if the component is different, to not trigger change detection
                         if c.as_ref() != &new_value {
-                            *c = new_value;
+                            let previous = std::mem::replace(c.as_mut(), new_value);
                             events.push_update_component(entity, net_id, tick);
+                            self.run_apply_hook(entity_world_mut, Some(previous));
                         }
                     } else {
                         entity_world_mut.insert(new_value);
                         events.push_insert_component(entity, net_id, tick);
+                        self.run_apply_hook::<C>(entity_world_mut, None);
                     }
                     // store the component value in the delta component history, so that we can compute
                     // diffs from it
@@ -847,6 +1087,18 @@ pub trait AppComponentExt {
         direction: ChannelDirection,
     ) -> ComponentRegistration<'_, C>;
 
+    /// Registers the component in the Registry, and in the bevy type registry.
+    ///
+    /// Unlike [`register_component`](AppComponentExt::register_component), the network id of the
+    /// component is derived from its [`TypePath`] instead of the order in which it was registered,
+    /// so third-party plugins can register their components in any order.
+    fn register_reflect_component<
+        C: Component + Message + PartialEq + TypePath + GetTypeRegistration,
+    >(
+        &mut self,
+        direction: ChannelDirection,
+    ) -> ComponentRegistration<'_, C>;
+
     /// Enable prediction systems for this component.
     /// You can specify the prediction [`ComponentSyncMode`]
     fn add_prediction<C: SyncComponent>(&mut self, prediction_mode: ComponentSyncMode);
@@ -901,6 +1153,87 @@ impl<C> ComponentRegistration<'_, C> {
         self
     }
 
+    /// Serialize the component with the [`SerializerBackend`] `B` instead of the default [`Bincode`](crate::prelude::Bincode) backend
+    pub fn set_serializer<B: SerializerBackend>(self) -> Self
+    where
+        C: Message,
+    {
+        let mut registry = self.app.world.resource_mut::<ComponentRegistry>();
+        registry.set_serializer::<C, B>();
+        self
+    }
+
+    /// Serialize the component with custom functions instead of its `Serialize` and `Deserialize`
+    /// implementations, for example to leave out the fields that can be recomputed by the receiver.
+    pub fn set_serialize_fns(self, serialize: SerializeFn<C>, deserialize: DeserializeFn<C>) -> Self
+    where
+        C: 'static,
+    {
+        let mut registry = self.app.world.resource_mut::<ComponentRegistry>();
+        registry.set_serialize_fns::<C>(serialize, deserialize);
+        self
+    }
+
+    /// Send the component over the network as its network representation `R`, so that the wire format
+    /// and the layout of the component can differ.
+    ///
+    /// The component is converted to `R` before being serialized with [`Bincode`](crate::prelude::Bincode),
+    /// and converted back from `R` after being deserialized.
+    pub fn set_network_representation<R>(self) -> Self
+    where
+        C: From<R> + 'static,
+        R: Message + for<'a> From<&'a C>,
+    {
+        let mut registry = self.app.world.resource_mut::<ComponentRegistry>();
+        registry.set_network_representation::<C, R>();
+        self
+    }
+
+    /// Validate the values of the component received from the remote before applying them.
+    ///
+    /// This is useful for components that are replicated from the clients to the server: the server
+    /// can discard values that a client should not be able to set. The [`Replicated`](crate::prelude::Replicated)
+    /// component of the entity identifies the client that replicated it.
+    pub fn add_validation(self, validate_fn: ValidateFn<C>) -> Self
+    where
+        C: Component,
+    {
+        let mut registry = self.app.world.resource_mut::<ComponentRegistry>();
+        registry.set_validate_fn::<C>(validate_fn);
+        self
+    }
+
+    /// Replicate the component only once, when it is inserted on the entity.
+    ///
+    /// This is the same as adding [`ReplicateOnceComponent<C>`](crate::prelude::ReplicateOnceComponent)
+    /// on every replicated entity: the later changes of the component are never sent to the remote,
+    /// and the remote ignores any update it receives for it.
+    /// Useful for components that never change after the entity is spawned (the class of a player, the
+    /// kind of a projectile, etc.)
+    pub fn immutable(self) -> Self
+    where
+        C: Component,
+    {
+        let mut registry = self.app.world.resource_mut::<ComponentRegistry>();
+        registry.set_immutable::<C>();
+        self
+    }
+
+    /// Run a function right after a value of the component received from the remote is written to the entity.
+    ///
+    /// The function receives the previous value of the component (`None` if it was just inserted), and can be used
+    /// to react to the changes made by the remote, for example to trigger visual effects.
+    /// To run systems before or after all the replication updates are applied, use the
+    /// [`ClientReplicationSet`](crate::prelude::client::ClientReplicationSet) SystemSets.
+    pub fn add_apply_hook(self, apply_hook: ApplyHookFn<C>) -> Self
+    where
+        C: Component,
+    {
+        let mut registry = self.app.world.resource_mut::<ComponentRegistry>();
+        registry.set_apply_hook::<C>(apply_hook);
+        self
+    }
+
     /// Enable prediction systems for this component.
     /// You can specify the prediction [`ComponentSyncMode`]
     pub fn add_prediction(self, prediction_mode: ComponentSyncMode) -> Self
@@ -1009,6 +1342,25 @@ impl AppComponentExt for App {
         }
     }
 
+    fn register_reflect_component<
+        C: Component + Message + PartialEq + TypePath + GetTypeRegistration,
+    >(
+        &mut self,
+        direction: ChannelDirection,
+    ) -> ComponentRegistration<'_, C> {
+        self.register_type::<C>();
+        if !self
+            .world
+            .resource::<ComponentRegistry>()
+            .is_registered::<C>()
+        {
+            let mut registry = self.world.resource_mut::<ComponentRegistry>();
+            registry.register_component::<C>();
+            registry.set_stable_name::<C>();
+        }
+        self.register_component::<C>(direction)
+    }
+
     fn add_prediction<C: SyncComponent>(&mut self, prediction_mode: ComponentSyncMode) {
         let mut registry = self.world.resource_mut::<ComponentRegistry>();
         registry.set_prediction_mode::<C>(prediction_mode);
@@ -1096,3 +1448,263 @@ impl From<TypeId> for ComponentKind {
         Self(type_id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::entity::EntityMapper;
+    use bevy::prelude::Entity;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::serialize::writer::Writer;
+    use crate::serialize::ToBytes;
+    use crate::shared::replication::delta::{DeltaComponentHistory, DeltaType};
+
+    /// Delta-compressed component that references entities
+    #[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct Targets(Vec<Entity>);
+
+    impl MapEntities for Targets {
+        fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+            for entity in &mut self.0 {
+                *entity = entity_mapper.map_entity(*entity);
+            }
+        }
+    }
+
+    impl Diffable for Targets {
+        type Delta = Vec<Entity>;
+
+        fn base_value() -> Self {
+            Self(vec![])
+        }
+
+        fn diff(&self, new: &Self) -> Self::Delta {
+            new.0[self.0.len()..].to_vec()
+        }
+
+        fn apply_diff(&mut self, delta: &Self::Delta) {
+            self.0.extend(delta);
+        }
+    }
+
+    fn write_delta(
+        registry: &ComponentRegistry,
+        world: &mut World,
+        entity: Entity,
+        tick: Tick,
+        delta: DeltaMessage<Vec<Entity>>,
+        entity_map: &mut EntityMap,
+    ) {
+        let mut writer = Writer::default();
+        registry.serialize(&delta, &mut writer).unwrap();
+        let mut reader = Reader::from(writer.to_bytes());
+        let net_id = NetId::from_bytes(&mut reader).unwrap();
+        registry
+            .write_delta::<Targets>(
+                &mut reader,
+                net_id,
+                tick,
+                &mut world.entity_mut(entity),
+                entity_map,
+                &mut ConnectionEvents::default(),
+            )
+            .unwrap();
+    }
+
+    /// Component with a field that can be recomputed by the receiver
+    #[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct Health {
+        current: u32,
+        ratio: f32,
+    }
+
+    impl Health {
+        fn new(current: u32) -> Self {
+            Self {
+                current,
+                ratio: current as f32 / 100.0,
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct NetworkHealth(u32);
+
+    impl From<&Health> for NetworkHealth {
+        fn from(health: &Health) -> Self {
+            Self(health.current)
+        }
+    }
+
+    impl From<NetworkHealth> for Health {
+        fn from(health: NetworkHealth) -> Self {
+            Self::new(health.0)
+        }
+    }
+
+    fn roundtrip(registry: &ComponentRegistry, health: &Health) -> (usize, Health) {
+        let mut writer = Writer::default();
+        registry.serialize(health, &mut writer).unwrap();
+        let bytes = writer.to_bytes();
+        let len = bytes.len();
+        let decoded = registry
+            .deserialize(&mut Reader::from(bytes), &mut EntityMap::default())
+            .unwrap();
+        (len, decoded)
+    }
+
+    #[test]
+    fn test_custom_serialize_fns() {
+        use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+
+        let health = Health::new(40);
+        let mut registry = ComponentRegistry::default();
+        registry.register_component::<Health>();
+        registry.set_serialize_fns::<Health>(
+            |health, writer| Ok(writer.write_u8(health.current as u8)?),
+            |reader| Ok(Health::new(reader.read_u8()? as u32)),
+        );
+        // net id + 1 byte
+        assert_eq!(roundtrip(&registry, &health), (2, health.clone()));
+
+        let mut registry = ComponentRegistry::default();
+        registry.register_component::<Health>();
+        registry.set_network_representation::<Health, NetworkHealth>();
+        // net id + varint
+        assert_eq!(roundtrip(&registry, &health), (2, health.clone()));
+
+        // the functions can write any format
+        let mut registry = ComponentRegistry::default();
+        registry.register_component::<Health>();
+        registry.set_serialize_fns::<Health>(
+            |health, writer| Ok(writer.write_u32::<NetworkEndian>(health.current)?),
+            |reader| Ok(Health::new(reader.read_u32::<NetworkEndian>()?)),
+        );
+        assert_eq!(roundtrip(&registry, &health), (5, health));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut world = World::new();
+        let mut registry = ComponentRegistry::default();
+        registry.register_component::<Health>();
+        registry.set_replication_fns::<Health>(&mut world);
+        // the health can only decrease
+        registry.set_validate_fn::<Health>(|entity, health| {
+            entity
+                .get::<Health>()
+                .map_or(true, |current| health.current <= current.current)
+        });
+        let entity = world.spawn(Health::new(40)).id();
+
+        let mut write = |health: Health| {
+            let mut writer = Writer::default();
+            registry.serialize(&health, &mut writer).unwrap();
+            let mut reader = Reader::from(writer.to_bytes());
+            let net_id = NetId::from_bytes(&mut reader).unwrap();
+            registry
+                .write::<Health>(
+                    &mut reader,
+                    net_id,
+                    Tick(0),
+                    &mut world.entity_mut(entity),
+                    &mut EntityMap::default(),
+                    &mut ConnectionEvents::default(),
+                )
+                .unwrap();
+            world.get::<Health>(entity).unwrap().current
+        };
+        assert_eq!(write(Health::new(100)), 40);
+        assert_eq!(write(Health::new(30)), 30);
+    }
+
+    #[test]
+    fn test_stable_net_ids() {
+        use crate::tests::protocol::{Component1, Component2, Component3};
+
+        fn registry(stable: &[fn(&mut ComponentRegistry)]) -> ComponentRegistry {
+            let mut registry = ComponentRegistry::default();
+            registry.register_component::<Component1>();
+            for register in stable {
+                register(&mut registry);
+            }
+            registry.assign_stable_net_ids();
+            registry
+        }
+        fn register_2(registry: &mut ComponentRegistry) {
+            registry.register_component::<Component2>();
+            registry.set_stable_name::<Component2>();
+        }
+        fn register_3(registry: &mut ComponentRegistry) {
+            registry.register_component::<Component3>();
+            registry.set_stable_name::<Component3>();
+        }
+
+        // the components with a stable name get the same net ids whatever the order of registration
+        let registry_a = registry(&[register_2, register_3]);
+        let registry_b = registry(&[register_3, register_2]);
+        assert_eq!(registry_a.kind_map, registry_b.kind_map);
+        assert_eq!(registry_a.net_id::<Component1>(), 0);
+        assert_eq!(registry_a.net_id::<Component2>(), 1);
+        assert_eq!(registry_a.net_id::<Component3>(), 2);
+    }
+
+    #[test]
+    fn test_map_entities_delta_compression() {
+        let mut registry = ComponentRegistry::default();
+        registry.register_component::<Targets>();
+        registry.set_delta_compression::<Targets>();
+        registry.add_map_entities::<Targets>();
+
+        let remote_1 = Entity::from_raw(10);
+        let remote_2 = Entity::from_raw(11);
+        let local_1 = Entity::from_raw(20);
+        let local_2 = Entity::from_raw(21);
+        let mut entity_map = EntityMap::default();
+        entity_map.insert(remote_1, local_1);
+        entity_map.insert(remote_2, local_2);
+
+        let mut world = World::new();
+        let entity = world.spawn_empty().id();
+        write_delta(
+            &registry,
+            &mut world,
+            entity,
+            Tick(1),
+            DeltaMessage {
+                delta_type: DeltaType::FromBase,
+                delta: vec![remote_1],
+            },
+            &mut entity_map,
+        );
+        assert_eq!(world.get::<Targets>(entity), Some(&Targets(vec![local_1])));
+
+        write_delta(
+            &registry,
+            &mut world,
+            entity,
+            Tick(2),
+            DeltaMessage {
+                delta_type: DeltaType::Normal {
+                    previous_tick: Tick(1),
+                },
+                delta: vec![remote_2],
+            },
+            &mut entity_map,
+        );
+        assert_eq!(
+            world.get::<Targets>(entity),
+            Some(&Targets(vec![local_1, local_2]))
+        );
+        // the history keeps the remote entities, to apply the next diffs
+        assert_eq!(
+            world
+                .get::<DeltaComponentHistory<Targets>>(entity)
+                .unwrap()
+                .buffer
+                .get(&Tick(2)),
+            Some(&Targets(vec![remote_1, remote_2]))
+        );
+    }
+}
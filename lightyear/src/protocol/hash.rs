@@ -0,0 +1,220 @@
+//! Hash of the protocol, used to detect peers that were built with a different protocol.
+//!
+//! The messages, components and channels are identified on the network by the order in which they
+//! were registered. If the client and the server don't register them identically, they would
+//! silently deserialize the data as the wrong type; instead the client sends the hash of its protocol
+//! in the connection request, and the server denies the connection with
+//! [`DeniedReason::ProtocolMismatch`](crate::connection::server::DeniedReason::ProtocolMismatch)
+//! if it doesn't match its own.
+use std::hash::Hasher;
+
+use bevy::prelude::{FromWorld, Resource, World};
+
+use crate::channel::builder::{ChannelDirection, ChannelMode, ChannelSettings};
+use crate::channel::compression::MessageCompression;
+use crate::protocol::channel::ChannelRegistry;
+use crate::protocol::component::ComponentRegistry;
+use crate::protocol::message::MessageRegistry;
+
+/// Hash of the messages, components and channels registered in the protocol.
+///
+/// It is computed from the type names of the messages and components in the order of their ids,
+/// and from the names and settings of the channels. Only the settings that must be identical on
+/// both peers are included (not the priority or the send frequency, for example).
+///
+/// The hash is inserted as a resource when the client connects or the server starts.
+/// Only the netcode transport ([`NetConfig::Netcode`](crate::prelude::client::NetConfig::Netcode)) sends and checks
+/// the hash during its connection handshake: the Steam and local connections don't have a handshake that lightyear
+/// controls, so a client with a different protocol can still connect through them.
+/// The channels registered at runtime are not included, since the server sends their ids to the
+/// clients after they connect.
+/// The type names are only stable for a given compiler and version of the crates, so the client and
+/// the server should be built with the same toolchain.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProtocolHash(pub u64);
+
+impl ProtocolHash {
+    pub fn new(
+        component_registry: &ComponentRegistry,
+        message_registry: &MessageRegistry,
+        channel_registry: &ChannelRegistry,
+    ) -> Self {
+        let mut hasher = seahash::SeaHasher::new();
+        // write the lengths as fixed-size integers, so that the hash is the same on every platform
+        hasher.write_u16(component_registry.kind_map.next_net_id);
+        for net_id in 0..component_registry.kind_map.next_net_id {
+            if let Some(kind) = component_registry.kind_map.kind(net_id) {
+                hash_name(&mut hasher, component_registry.name(*kind));
+            }
+        }
+        hasher.write_u16(message_registry.kind_map.next_net_id);
+        for net_id in 0..message_registry.kind_map.next_net_id {
+            if let Some(kind) = message_registry.kind_map.kind(net_id) {
+                hash_name(&mut hasher, message_registry.name(*kind));
+            }
+        }
+        for net_id in 0..channel_registry.kind_map.next_net_id {
+            let Some(kind) = channel_registry.kind_map.kind(net_id) else {
+                continue;
+            };
+            if channel_registry.runtime_channels().contains(kind) {
+                continue;
+            }
+            if let (Some(name), Some(builder)) = (
+                channel_registry.name(kind),
+                channel_registry.get_builder_from_kind(kind),
+            ) {
+                hash_name(&mut hasher, name);
+                hash_channel_settings(&mut hasher, &builder.settings);
+            }
+        }
+        Self(hasher.finish())
+    }
+}
+
+impl FromWorld for ProtocolHash {
+    fn from_world(world: &mut World) -> Self {
+        ProtocolHash::new(
+            world.resource::<ComponentRegistry>(),
+            world.resource::<MessageRegistry>(),
+            world.resource::<ChannelRegistry>(),
+        )
+    }
+}
+
+fn hash_name(hasher: &mut impl Hasher, name: &str) {
+    hasher.write_u64(name.len() as u64);
+    hasher.write(name.as_bytes());
+}
+
+fn hash_channel_settings(hasher: &mut impl Hasher, settings: &ChannelSettings) {
+    hasher.write_u8(match settings.mode {
+        ChannelMode::UnorderedUnreliableWithAcks => 0,
+        ChannelMode::UnorderedUnreliable => 1,
+        ChannelMode::SequencedUnreliable => 2,
+        ChannelMode::UnorderedReliable(_) => 3,
+        ChannelMode::SequencedReliable(_) => 4,
+        ChannelMode::OrderedReliable(_) => 5,
+        ChannelMode::TickBuffered => 6,
+    });
+    hasher.write_u8(match settings.direction {
+        ChannelDirection::ClientToServer => 0,
+        ChannelDirection::ServerToClient => 1,
+        ChannelDirection::Bidirectional => 2,
+    });
+    hasher.write_u8(settings.authenticated as u8);
+    match &settings.compression {
+        MessageCompression::None => hasher.write_u8(0),
+        #[cfg(feature = "zstd")]
+        MessageCompression::ZstdDictionary { dictionary, .. } => {
+            hasher.write_u8(1);
+            hasher.write_u64(dictionary.len() as u64);
+            hasher.write(dictionary);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::ecs::event::{Events, ManualEventReader};
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::prelude::Commands;
+    use lightyear_macros::ChannelInternal;
+
+    use super::*;
+    use crate::client::config::ClientConfig;
+    use crate::connection::client::DisconnectReason;
+    use crate::connection::netcode::ClientState;
+    use crate::prelude::client::{ClientCommands, DisconnectEvent};
+    use crate::prelude::server::ServerCommands;
+    use crate::prelude::{AppChannelExt, SharedConfig, TickConfig};
+    use crate::protocol::message::MessageType;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    #[derive(ChannelInternal)]
+    struct ClientOnlyChannel;
+
+    fn registries() -> (ComponentRegistry, MessageRegistry, ChannelRegistry) {
+        let mut component_registry = ComponentRegistry::default();
+        component_registry.register_component::<Component1>();
+        component_registry.register_component::<Component2>();
+        let mut message_registry = MessageRegistry::default();
+        message_registry.add_message::<Message1>(MessageType::Normal);
+        let channel_registry = ChannelRegistry::new(Duration::default());
+        (component_registry, message_registry, channel_registry)
+    }
+
+    #[test]
+    fn test_protocol_hash() {
+        let (components, messages, channels) = registries();
+        let hash = ProtocolHash::new(&components, &messages, &channels);
+        let (components, messages, channels) = registries();
+        assert_eq!(hash, ProtocolHash::new(&components, &messages, &channels));
+
+        // registering the components in a different order changes the hash
+        let mut components = ComponentRegistry::default();
+        components.register_component::<Component2>();
+        components.register_component::<Component1>();
+        assert_ne!(hash, ProtocolHash::new(&components, &messages, &channels));
+
+        // registering an additional message changes the hash
+        let (components, mut messages, channels) = registries();
+        messages.add_message::<Message2>(MessageType::Normal);
+        assert_ne!(hash, ProtocolHash::new(&components, &messages, &channels));
+
+        // channels registered at runtime are not included
+        let (components, messages, mut channels) = registries();
+        channels.add_runtime_channel::<Channel1>(ChannelSettings::default());
+        assert_eq!(hash, ProtocolHash::new(&components, &messages, &channels));
+    }
+    /// A client whose protocol is different from the server's is denied during the connection handshake
+    #[test]
+    fn test_protocol_mismatch_denied() {
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(shared_config, ClientConfig::default(), tick_duration);
+        // the client registers an additional channel, so its protocol hash is different
+        stepper
+            .client_app
+            .add_channel::<ClientOnlyChannel>(ChannelSettings::default());
+
+        stepper.server_app.finish();
+        stepper
+            .server_app
+            .world
+            .run_system_once(|mut commands: Commands| commands.start_server());
+        stepper.client_app.finish();
+        stepper
+            .client_app
+            .world
+            .run_system_once(|mut commands: Commands| commands.connect_client());
+
+        let mut disconnect_reader = ManualEventReader::<DisconnectEvent>::default();
+        let mut denied = false;
+        for _ in 0..50 {
+            stepper.frame_step();
+            let events = stepper
+                .client_app
+                .world
+                .resource::<Events<DisconnectEvent>>();
+            denied |= disconnect_reader.read(events).any(|event| {
+                matches!(
+                    event.reason,
+                    Some(DisconnectReason::Netcode(ClientState::ProtocolMismatch))
+                )
+            });
+        }
+        assert!(denied);
+        assert!(!stepper
+            .client_app
+            .world
+            .resource::<crate::client::connection::ConnectionManager>()
+            .is_synced());
+    }
+}
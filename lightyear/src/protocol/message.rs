@@ -6,14 +6,16 @@ use crate::client::config::ClientConfig;
 use crate::client::message::add_server_to_client_message;
 use crate::prelude::{client, server};
 use bevy::prelude::{App, Resource, TypePath};
+use bevy::reflect::GetTypeRegistration;
 use bevy::utils::HashMap;
 use tracing::{debug, error};
 
+use crate::channel::builder::Channel;
 use crate::packet::message::Message;
 use crate::prelude::server::ServerConfig;
-use crate::prelude::ChannelDirection;
+use crate::prelude::{ChannelDirection, ChannelKind};
 use crate::protocol::registry::{NetId, TypeKind, TypeMapper};
-use crate::protocol::serialize::ErasedSerializeFns;
+use crate::protocol::serialize::{ErasedSerializeFns, SerializerBackend};
 use crate::serialize::reader::Reader;
 use crate::serialize::writer::Writer;
 use crate::serialize::ToBytes;
@@ -27,6 +29,8 @@ pub enum MessageError {
     NotRegistered,
     #[error("missing serialization functions for message")]
     MissingSerializationFns,
+    #[error("no default channel was set for the message")]
+    NoDefaultChannel,
     #[error(transparent)]
     Serialization(#[from] crate::serialize::SerializationError),
 }
@@ -93,10 +97,31 @@ pub(crate) enum MessageType {
 ///       .add_map_entities();
 /// }
 /// ```
+///
+/// ### Default channel
+///
+/// The delivery guarantees of a message are the ones of the channel it is sent on. If a message should
+/// always be sent on the same channel, you can set it in the protocol with
+/// [`set_default_channel`](MessageRegistration::set_default_channel) and then send the message without
+/// specifying the channel, with `send_event`. The message is received as a [`MessageEvent`](crate::shared::events::components::MessageEvent)
+/// like any other message.
+///
+/// ```rust,ignore
+/// app.add_message::<GameOver>(ChannelDirection::ServerToClient)
+///     .set_default_channel::<ReliableChannel>();
+///
+/// fn game_over(mut connection: ResMut<server::ConnectionManager>) {
+///     connection.send_event(&GameOver, NetworkTarget::All).unwrap();
+/// }
+/// ```
 #[derive(Debug, Default, Clone, Resource, PartialEq, TypePath)]
 pub struct MessageRegistry {
     typed_map: HashMap<MessageKind, MessageType>,
     serialize_fns_map: HashMap<MessageKind, ErasedSerializeFns>,
+    /// Names of the messages whose net id doesn't depend on the order of registration
+    stable_names: HashMap<MessageKind, String>,
+    /// Channel used to send the message if no channel is specified
+    default_channels: HashMap<MessageKind, ChannelKind>,
     pub(crate) kind_map: TypeMapper<MessageKind>,
 }
 
@@ -196,6 +221,26 @@ impl<M> MessageRegistration<'_, M> {
         registry.add_map_entities::<M>();
         self
     }
+
+    /// Set the channel used to send the message with `send_event`, when the channel is not specified
+    pub fn set_default_channel<C: Channel>(self) -> Self
+    where
+        M: Message,
+    {
+        let mut registry = self.app.world.resource_mut::<MessageRegistry>();
+        registry.set_default_channel::<M>(ChannelKind::of::<C>());
+        self
+    }
+
+    /// Serialize the message with the [`SerializerBackend`] `B` instead of the default [`Bincode`](crate::prelude::Bincode) backend
+    pub fn set_serializer<B: SerializerBackend>(self) -> Self
+    where
+        M: Message,
+    {
+        let mut registry = self.app.world.resource_mut::<MessageRegistry>();
+        registry.set_serializer::<M, B>();
+        self
+    }
 }
 
 pub(crate) trait AppMessageInternalExt {
@@ -235,6 +280,16 @@ pub trait AppMessageExt {
         direction: ChannelDirection,
     ) -> MessageRegistration<'_, M>;
 
+    /// Registers the message in the Registry, and in the bevy type registry.
+    ///
+    /// Unlike [`add_message`](AppMessageExt::add_message), the network id of the message is derived
+    /// from its [`TypePath`] instead of the order in which it was registered, so third-party plugins
+    /// can register their messages in any order.
+    fn add_reflect_message<M: Message + TypePath + GetTypeRegistration>(
+        &mut self,
+        direction: ChannelDirection,
+    ) -> MessageRegistration<'_, M>;
+
     /// Registers the resource in the Registry
     /// This resource can now be sent over the network.
     fn register_resource<R: Resource + Message>(&mut self, direction: ChannelDirection);
@@ -248,6 +303,19 @@ impl AppMessageExt for App {
         self.add_message_internal(direction, MessageType::Normal)
     }
 
+    fn add_reflect_message<M: Message + TypePath + GetTypeRegistration>(
+        &mut self,
+        direction: ChannelDirection,
+    ) -> MessageRegistration<'_, M> {
+        self.register_type::<M>();
+        let mut registry = self.world.resource_mut::<MessageRegistry>();
+        if !registry.is_registered::<M>() {
+            registry.add_message::<M>(MessageType::Normal);
+            registry.set_stable_name::<M>();
+        }
+        self.add_message_internal(direction, MessageType::Normal)
+    }
+
     /// Register a resource to be automatically replicated over the network
     fn register_resource<R: Resource + Message>(&mut self, direction: ChannelDirection) {
         self.add_message::<R>(direction);
@@ -268,6 +336,11 @@ impl MessageRegistry {
         self.kind_map.net_id(&MessageKind::of::<M>()).is_some()
     }
 
+    /// Return the name of the message from the [`MessageKind`]
+    pub(crate) fn name(&self, kind: MessageKind) -> &'static str {
+        self.serialize_fns_map.get(&kind).unwrap().type_name
+    }
+
     pub(crate) fn add_message<M: Message>(&mut self, message_type: MessageType) {
         let message_kind = self.kind_map.add::<M>();
         self.serialize_fns_map
@@ -275,6 +348,30 @@ impl MessageRegistry {
         self.typed_map.insert(message_kind, message_type);
     }
 
+    /// Identify the message by its type path instead of its order of registration
+    pub(crate) fn set_stable_name<M: TypePath>(&mut self) {
+        self.stable_names
+            .insert(MessageKind::of::<M>(), M::type_path().to_string());
+    }
+
+    /// Assign the net ids of the messages that have a stable name, once all of them are registered
+    pub(crate) fn assign_stable_net_ids(&mut self) {
+        self.kind_map.sort_stable(&self.stable_names);
+    }
+
+    pub(crate) fn set_default_channel<M: Message>(&mut self, channel: ChannelKind) {
+        self.default_channels
+            .insert(MessageKind::of::<M>(), channel);
+    }
+
+    /// Return the channel that is used to send the message if no channel is specified
+    pub(crate) fn default_channel<M: Message>(&self) -> Result<ChannelKind, MessageError> {
+        self.default_channels
+            .get(&MessageKind::of::<M>())
+            .copied()
+            .ok_or(MessageError::NoDefaultChannel)
+    }
+
     pub(crate) fn try_add_map_entities<M: MapEntities + 'static>(&mut self) {
         let kind = MessageKind::of::<M>();
         if let Some(erased_fns) = self.serialize_fns_map.get_mut(&kind) {
@@ -291,6 +388,15 @@ impl MessageRegistry {
         erased_fns.add_map_entities::<M>();
     }
 
+    pub(crate) fn set_serializer<M: Message, B: SerializerBackend>(&mut self) {
+        let kind = MessageKind::of::<M>();
+        let erased_fns = self
+            .serialize_fns_map
+            .get_mut(&kind)
+            .expect("the message is not part of the protocol");
+        erased_fns.set_backend::<M, B>();
+    }
+
     pub(crate) fn serialize<M: Message>(
         &self,
         message: &M,
@@ -350,7 +456,24 @@ impl From<TypeId> for MessageKind {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::tests::protocol::Resource1;
+    use crate::protocol::serialize::BincodeLegacy;
+    use crate::tests::protocol::{Channel1, Message2, Resource1};
+
+    #[test]
+    fn test_default_channel() {
+        let mut registry = MessageRegistry::default();
+        registry.add_message::<Message2>(MessageType::Normal);
+        assert!(matches!(
+            registry.default_channel::<Message2>(),
+            Err(MessageError::NoDefaultChannel)
+        ));
+
+        registry.set_default_channel::<Message2>(ChannelKind::of::<Channel1>());
+        assert_eq!(
+            registry.default_channel::<Message2>().unwrap(),
+            ChannelKind::of::<Channel1>()
+        );
+    }
 
     #[test]
     fn test_serde() {
@@ -368,4 +491,24 @@ mod tests {
             .unwrap();
         assert_eq!(message, read);
     }
+
+    #[test]
+    fn test_serde_custom_backend() {
+        let mut registry = MessageRegistry::default();
+        registry.add_message::<Message2>(MessageType::Normal);
+        registry.set_serializer::<Message2, BincodeLegacy>();
+
+        let message = Message2(1);
+        let mut writer = Writer::default();
+        registry.serialize(&message, &mut writer).unwrap();
+        let data = writer.to_bytes();
+        // the net_id, followed by the u32 written with a fixed length
+        assert_eq!(data.len(), 1 + 4);
+
+        let mut reader = Reader::from(data);
+        let read = registry
+            .deserialize(&mut reader, &mut EntityMap::default())
+            .unwrap();
+        assert_eq!(message, read);
+    }
 }
@@ -20,6 +20,8 @@ pub(crate) mod component;
 pub(crate) mod message;
 
 pub(crate) mod delta;
+/// Hash of the protocol, exchanged during the connection handshake
+pub(crate) mod hash;
 /// Provides a mapping from a type to a unique identifier that can be serialized
 pub(crate) mod registry;
 pub(crate) mod serialize;
@@ -65,6 +65,47 @@ impl<K: TypeKind> TypeMapper<K> {
         kind
     }
 
+    /// Map `kind` to a `net_id` chosen by the remote peer.
+    ///
+    /// Returns false if the kind or the net_id are already used.
+    pub(crate) fn insert(&mut self, kind: K, net_id: NetId) -> bool {
+        if self.kind_map.contains_key(&kind) || self.id_map.contains_key(&net_id) {
+            return false;
+        }
+        self.kind_map.insert(kind, net_id);
+        self.id_map.insert(net_id, kind);
+        self.next_net_id = self.next_net_id.max(net_id.saturating_add(1));
+        true
+    }
+
+    /// Reassign the net ids so that they don't depend on the order in which the kinds of `stable_names`
+    /// were registered.
+    ///
+    /// The other kinds keep their relative order and get the first net ids, then the kinds of
+    /// `stable_names` get the next net ids, sorted by name.
+    pub(crate) fn sort_stable(&mut self, stable_names: &HashMap<K, String>) {
+        let mut kinds: Vec<K> = (0..self.next_net_id)
+            .filter_map(|net_id| self.id_map.get(&net_id).copied())
+            .filter(|kind| !stable_names.contains_key(kind))
+            .collect();
+        let mut stable: Vec<(&String, K)> = stable_names
+            .iter()
+            .filter(|(kind, _)| self.kind_map.contains_key(*kind))
+            .map(|(kind, name)| (name, *kind))
+            .collect();
+        stable.sort_by(|a, b| a.0.cmp(b.0));
+        kinds.extend(stable.into_iter().map(|(_, kind)| kind));
+
+        self.kind_map.clear();
+        self.id_map.clear();
+        self.next_net_id = 0;
+        for kind in kinds {
+            self.kind_map.insert(kind, self.next_net_id);
+            self.id_map.insert(self.next_net_id, kind);
+            self.next_net_id += 1;
+        }
+    }
+
     pub fn kind(&self, net_id: NetId) -> Option<&K> {
         self.id_map.get(&net_id)
     }
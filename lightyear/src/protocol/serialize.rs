@@ -1,46 +1,197 @@
 use crate::prelude::{ComponentRegistry, Message, MessageRegistry};
-use crate::serialize::{reader::Reader, writer::Writer, SerializationError};
+use crate::serialize::canonical::Canonical;
+use crate::serialize::{extensible, reader::Reader, writer::Writer, SerializationError};
 use crate::shared::replication::entity_map::EntityMap;
 use bevy::app::App;
 use bevy::ecs::entity::MapEntities;
 use bevy::ptr::{Ptr, PtrMut};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::any::TypeId;
 
+/// Format used to write the messages and components of the protocol to the network.
+///
+/// Every message and component is serialized with [`Bincode`] by default. You can select another
+/// backend for a given type when registering it, for example to interoperate with a service that
+/// already speaks a given format:
+///
+/// ```rust
+/// use bevy::prelude::*;
+/// use serde::{Deserialize, Serialize};
+/// use lightyear::prelude::*;
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct MyMessage(u32);
+///
+/// fn add_messages(app: &mut App) {
+///   app.add_message::<MyMessage>(ChannelDirection::Bidirectional)
+///       .set_serializer::<BincodeLegacy>();
+/// }
+/// ```
+///
+/// The built-in backends write the same bytes on every platform: integers have a fixed byte order,
+/// `usize` and `isize` are written as 64-bit integers, and NaNs are replaced with [`f32::NAN`] or
+/// [`f64::NAN`]. You can check that the wire format of your protocol doesn't change with
+/// [`testing::wire`](crate::testing::wire).
+///
+/// You can implement this trait to use your own format.
+pub trait SerializerBackend: 'static {
+    fn serialize<M: Serialize>(data: &M, writer: &mut Writer) -> Result<(), SerializationError>;
+
+    fn deserialize<M: DeserializeOwned>(reader: &mut Reader) -> Result<M, SerializationError>;
+}
+
+/// Bincode with its standard configuration (little endian, variable-length integers).
+///
+/// This is the default backend.
+pub struct Bincode;
+
+impl SerializerBackend for Bincode {
+    fn serialize<M: Serialize>(data: &M, writer: &mut Writer) -> Result<(), SerializationError> {
+        bincode::serde::encode_into_std_write(
+            Canonical(data),
+            writer,
+            bincode::config::standard(),
+        )?;
+        Ok(())
+    }
+
+    fn deserialize<M: DeserializeOwned>(reader: &mut Reader) -> Result<M, SerializationError> {
+        Ok(bincode::serde::decode_from_std_read(
+            reader,
+            bincode::config::standard(),
+        )?)
+    }
+}
+
+/// Bincode with the configuration used by default in bincode 1.x (little endian, fixed-length integers),
+/// for compatibility with services that use bincode 1.x
+pub struct BincodeLegacy;
+
+impl SerializerBackend for BincodeLegacy {
+    fn serialize<M: Serialize>(data: &M, writer: &mut Writer) -> Result<(), SerializationError> {
+        bincode::serde::encode_into_std_write(Canonical(data), writer, bincode::config::legacy())?;
+        Ok(())
+    }
+
+    fn deserialize<M: DeserializeOwned>(reader: &mut Reader) -> Result<M, SerializationError> {
+        Ok(bincode::serde::decode_from_std_read(
+            reader,
+            bincode::config::legacy(),
+        )?)
+    }
+}
+
+/// Self-describing format that lets peers with different versions of a type talk to each other.
+///
+/// The struct fields are sent with their index, so a peer can add fields to the end of a struct
+/// without breaking the peers that still use the previous version:
+/// - the fields that a peer doesn't know are skipped
+/// - the fields that are missing are set to `None` for `Option` fields, or to their default value
+///   for fields marked with `#[serde(default)]`
+///
+/// This lets you update the server without updating all the clients at the same time, as long as
+/// the changes to the messages and components are additive. Fields must not be removed or reordered,
+/// and enum variants can only be added once all the peers know them.
+///
+/// The messages are bigger than with [`Bincode`], since the kind of every value is written as well.
+pub struct Extensible;
+
+impl SerializerBackend for Extensible {
+    fn serialize<M: Serialize>(data: &M, writer: &mut Writer) -> Result<(), SerializationError> {
+        let value = extensible::to_value(data)?;
+        bincode::serde::encode_into_std_write(value, writer, bincode::config::standard())?;
+        Ok(())
+    }
+
+    fn deserialize<M: DeserializeOwned>(reader: &mut Reader) -> Result<M, SerializationError> {
+        let value: extensible::Value =
+            bincode::serde::decode_from_std_read(reader, bincode::config::standard())?;
+        Ok(extensible::from_value(value)?)
+    }
+}
+
 // TODO: maybe instead of MessageFns, use an erased trait objects? like dyn ErasedSerialize + ErasedDeserialize ?
 //  but how do we deal with implementing behaviour for types that don't have those traits?
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub struct ErasedSerializeFns {
     pub(crate) type_id: TypeId,
     pub(crate) type_name: &'static str,
     // TODO: maybe use `Vec<MaybeUninit<u8>>` instead of unsafe fn(), like bevy?
     pub serialize: ErasedSerializeFn,
     pub deserialize: unsafe fn(),
+    /// Function provided by the user to serialize the type, used instead of its `Serialize` implementation
+    custom_serialize: Option<unsafe fn()>,
     pub map_entities: Option<ErasedMapEntitiesFn>,
 }
 
+/// The function pointers (including `custom_serialize`) are not compared, since their addresses are not
+/// guaranteed to be unique
+impl PartialEq for ErasedSerializeFns {
+    fn eq(&self, other: &Self) -> bool {
+        self.type_id == other.type_id && self.type_name == other.type_name
+    }
+}
+
 pub struct SerializeFns<M> {
     pub deserialize: DeserializeFn<M>,
 }
 
-type ErasedSerializeFn =
-    unsafe fn(message: Ptr, writer: &mut Writer) -> Result<(), SerializationError>;
-type DeserializeFn<M> = fn(reader: &mut Reader) -> Result<M, SerializationError>;
+type ErasedSerializeFn = unsafe fn(
+    fns: &ErasedSerializeFns,
+    message: Ptr,
+    writer: &mut Writer,
+) -> Result<(), SerializationError>;
+
+/// Function used to write a value to the network, instead of its `Serialize` implementation
+pub type SerializeFn<M> = fn(message: &M, writer: &mut Writer) -> Result<(), SerializationError>;
+
+/// Function used to read a value from the network, instead of its `Deserialize` implementation
+pub type DeserializeFn<M> = fn(reader: &mut Reader) -> Result<M, SerializationError>;
 
 pub(crate) type ErasedMapEntitiesFn = unsafe fn(message: PtrMut, entity_map: &mut EntityMap);
 
 /// SAFETY: the Ptr must be a valid pointer to a value of type M
-unsafe fn erased_serialize<M: Message>(
+unsafe fn erased_serialize<M: Message, B: SerializerBackend>(
+    _: &ErasedSerializeFns,
     message: Ptr,
     buffer: &mut Writer,
 ) -> Result<(), SerializationError> {
     let data = message.deref::<M>();
-    let _ = bincode::serde::encode_into_std_write(data, buffer, bincode::config::standard())?;
-    Ok(())
+    B::serialize(data, buffer)
+}
+
+/// SAFETY: the Ptr must be a valid pointer to a value of type M, and the `custom_serialize`
+/// function of `fns` must be a [`SerializeFn<M>`]
+unsafe fn erased_custom_serialize<M: 'static>(
+    fns: &ErasedSerializeFns,
+    message: Ptr,
+    buffer: &mut Writer,
+) -> Result<(), SerializationError> {
+    let serialize = std::mem::transmute::<unsafe fn(), SerializeFn<M>>(
+        fns.custom_serialize
+            .expect("the custom serialize function is missing"),
+    );
+    serialize(message.deref::<M>(), buffer)
+}
+
+/// Serialize `M` by converting it to its network representation `R`
+fn serialize_as<M, R: Message + for<'a> From<&'a M>>(
+    message: &M,
+    writer: &mut Writer,
+) -> Result<(), SerializationError> {
+    Bincode::serialize(&R::from(message), writer)
+}
+
+/// Deserialize `M` from its network representation `R`
+fn deserialize_as<M: From<R>, R: Message>(reader: &mut Reader) -> Result<M, SerializationError> {
+    Ok(M::from(Bincode::deserialize::<R>(reader)?))
 }
 
-fn erased_deserialize<M: Message>(buffer: &mut Reader) -> Result<M, SerializationError> {
-    let data = bincode::serde::decode_from_std_read(buffer, bincode::config::standard())?;
-    Ok(data)
+fn erased_deserialize<M: Message, B: SerializerBackend>(
+    buffer: &mut Reader,
+) -> Result<M, SerializationError> {
+    B::deserialize(buffer)
 }
 
 /// SAFETY: the PtrMut must be a valid pointer to a value of type M
@@ -54,20 +205,70 @@ unsafe fn erased_map_entities<M: MapEntities + 'static>(
 
 impl ErasedSerializeFns {
     pub(crate) fn new<M: Message>() -> Self {
-        let erased_deserialize: DeserializeFn<M> = erased_deserialize::<M>;
+        Self::with_backend::<M, Bincode>()
+    }
+
+    pub(crate) fn with_backend<M: Message, B: SerializerBackend>() -> Self {
+        let erased_deserialize: DeserializeFn<M> = erased_deserialize::<M, B>;
         Self {
             type_id: TypeId::of::<M>(),
             type_name: std::any::type_name::<M>(),
-            serialize: erased_serialize::<M>,
+            serialize: erased_serialize::<M, B>,
             deserialize: unsafe {
                 std::mem::transmute::<
                     for<'a> fn(&'a mut Reader) -> std::result::Result<M, SerializationError>,
                     unsafe fn(),
                 >(erased_deserialize)
             },
+            custom_serialize: None,
             map_entities: None,
         }
     }
+
+    /// Use the backend `B` to serialize the type, keeping the entity mapping function
+    pub(crate) fn set_backend<M: Message, B: SerializerBackend>(&mut self) {
+        debug_assert_eq!(
+            self.type_id,
+            TypeId::of::<M>(),
+            "The erased message fns were created for type {}, but we are trying to set the backend for type {}",
+            self.type_name,
+            std::any::type_name::<M>(),
+        );
+        let map_entities = self.map_entities;
+        *self = Self::with_backend::<M, B>();
+        self.map_entities = map_entities;
+    }
+    /// Use custom functions to serialize the type, keeping the entity mapping function
+    pub(crate) fn set_custom_fns<M: 'static>(
+        &mut self,
+        serialize: SerializeFn<M>,
+        deserialize: DeserializeFn<M>,
+    ) {
+        debug_assert_eq!(
+            self.type_id,
+            TypeId::of::<M>(),
+            "The erased message fns were created for type {}, but we are trying to set the serialization functions for type {}",
+            self.type_name,
+            std::any::type_name::<M>(),
+        );
+        self.serialize = erased_custom_serialize::<M>;
+        // SAFETY: the functions are transmuted back to the same types in `erased_custom_serialize` and `typed`
+        unsafe {
+            self.custom_serialize = Some(std::mem::transmute::<SerializeFn<M>, unsafe fn()>(
+                serialize,
+            ));
+            self.deserialize = std::mem::transmute::<DeserializeFn<M>, unsafe fn()>(deserialize);
+        }
+    }
+
+    /// Serialize the type by converting it to its network representation `R`, with the [`Bincode`] backend
+    pub(crate) fn set_network_representation<M: From<R> + 'static, R>(&mut self)
+    where
+        R: Message + for<'a> From<&'a M>,
+    {
+        self.set_custom_fns::<M>(serialize_as::<M, R>, deserialize_as::<M, R>);
+    }
+
     pub(crate) unsafe fn typed<M: 'static>(&self) -> SerializeFns<M> {
         debug_assert_eq!(
             self.type_id,
@@ -105,7 +306,7 @@ impl ErasedSerializeFns {
         writer: &mut Writer,
     ) -> Result<(), SerializationError> {
         let ptr = Ptr::from(message);
-        (self.serialize)(ptr, writer)
+        (self.serialize)(self, ptr, writer)
     }
 
     /// Deserialize the message value from the reader
@@ -0,0 +1,646 @@
+//! Self-describing encoding of serde types, used by the [`Extensible`](crate::prelude::Extensible) backend.
+//!
+//! The value is first converted into a [`Value`] tree, which records the kind of every value
+//! (integer, string, sequence, struct, etc.), and the tree is then encoded with bincode.
+//! Struct fields are identified by their index in the struct declaration instead of their name.
+//!
+//! Because the encoding describes itself, a peer can read a struct that has more or fewer fields
+//! than its own definition of the type:
+//! - fields that it doesn't know are skipped
+//! - fields that are missing are set to `None` for `Option` fields, or to their default value for
+//!   fields marked with `#[serde(default)]`
+use serde::de::value::{Error, MapDeserializer, SeqDeserializer};
+use serde::de::{DeserializeSeed, EnumAccess, IntoDeserializer, VariantAccess, Visitor};
+use serde::ser::{
+    SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+    SerializeTupleStruct, SerializeTupleVariant,
+};
+use serde::{forward_to_deserialize_any, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::serialize::canonical::{canonical_f32, canonical_f64};
+
+/// Serde data model of a value, with the struct fields identified by index
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Bool(bool),
+    I64(i64),
+    U64(u64),
+    I128(i128),
+    U128(u128),
+    F32(f32),
+    F64(f64),
+    Char(char),
+    String(String),
+    Bytes(Vec<u8>),
+    None,
+    Some(Box<Value>),
+    Unit,
+    Newtype(Box<Value>),
+    /// Sequences, tuples and tuple structs
+    Seq(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    /// Fields of a struct, with the index of the field in the struct declaration
+    Struct(Vec<(u32, Value)>),
+    /// Enum variant index, and the content of the variant
+    Variant(u32, Box<Value>),
+}
+
+/// Convert a value into its [`Value`] representation
+pub(crate) fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, Error> {
+    value.serialize(ValueSerializer)
+}
+
+/// Convert a [`Value`] into a typed value
+pub(crate) fn from_value<T: serde::de::DeserializeOwned>(value: Value) -> Result<T, Error> {
+    T::deserialize(value)
+}
+
+struct ValueSerializer;
+
+impl Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = TupleVariantSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = StructSerializer;
+    type SerializeStructVariant = StructVariantSerializer;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        Ok(Value::I64(v as i64))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        Ok(Value::I64(v as i64))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        Ok(Value::I64(v as i64))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        Ok(Value::I64(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value, Error> {
+        Ok(Value::I128(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        Ok(Value::U64(v as u64))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        Ok(Value::U64(v as u64))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        Ok(Value::U64(v as u64))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        Ok(Value::U64(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value, Error> {
+        Ok(Value::U128(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, Error> {
+        Ok(Value::F32(canonical_f32(v)))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, Error> {
+        Ok(Value::F64(canonical_f64(v)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        Ok(Value::Char(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Value, Error> {
+        Ok(Value::None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, Error> {
+        Ok(Value::Some(Box::new(to_value(value)?)))
+    }
+
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Ok(Value::Unit)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+        Ok(Value::Unit)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<Value, Error> {
+        Ok(Value::Variant(variant_index, Box::new(Value::Unit)))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        Ok(Value::Newtype(Box::new(to_value(value)?)))
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        Ok(Value::Variant(variant_index, Box::new(to_value(value)?)))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer(Vec::with_capacity(len.unwrap_or_default())))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer(Vec::with_capacity(len)))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer(Vec::with_capacity(len)))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        len: usize,
+    ) -> Result<TupleVariantSerializer, Error> {
+        Ok(TupleVariantSerializer {
+            variant_index,
+            items: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer {
+            entries: Vec::with_capacity(len.unwrap_or_default()),
+            next_key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<StructSerializer, Error> {
+        Ok(StructSerializer {
+            fields: Vec::with_capacity(len),
+            index: 0,
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        len: usize,
+    ) -> Result<StructVariantSerializer, Error> {
+        Ok(StructVariantSerializer {
+            variant_index,
+            fields: StructSerializer {
+                fields: Vec::with_capacity(len),
+                index: 0,
+            },
+        })
+    }
+}
+
+struct SeqSerializer(Vec<Value>);
+
+impl SerializeSeq for SeqSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.0.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(Value::Seq(self.0))
+    }
+}
+
+impl SerializeTuple for SeqSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl SerializeTupleStruct for SeqSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        SerializeSeq::end(self)
+    }
+}
+
+struct TupleVariantSerializer {
+    variant_index: u32,
+    items: Vec<Value>,
+}
+
+impl SerializeTupleVariant for TupleVariantSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.items.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(Value::Variant(
+            self.variant_index,
+            Box::new(Value::Seq(self.items)),
+        ))
+    }
+}
+
+struct MapSerializer {
+    entries: Vec<(Value, Value)>,
+    next_key: Option<Value>,
+}
+
+impl SerializeMap for MapSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.next_key = Some(to_value(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.next_key.take().ok_or_else(|| {
+            <Error as serde::ser::Error>::custom("map value serialized before its key")
+        })?;
+        self.entries.push((key, to_value(value)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(Value::Map(self.entries))
+    }
+}
+
+struct StructSerializer {
+    fields: Vec<(u32, Value)>,
+    /// Index of the next field in the struct declaration
+    index: u32,
+}
+
+impl SerializeStruct for StructSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.fields.push((self.index, to_value(value)?));
+        self.index += 1;
+        Ok(())
+    }
+
+    fn skip_field(&mut self, _key: &'static str) -> Result<(), Error> {
+        self.index += 1;
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(Value::Struct(self.fields))
+    }
+}
+
+struct StructVariantSerializer {
+    variant_index: u32,
+    fields: StructSerializer,
+}
+
+impl SerializeStructVariant for StructVariantSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        SerializeStruct::serialize_field(&mut self.fields, key, value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), Error> {
+        SerializeStruct::skip_field(&mut self.fields, key)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(Value::Variant(
+            self.variant_index,
+            Box::new(SerializeStruct::end(self.fields)?),
+        ))
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> Deserializer<'de> for Value {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Value::Bool(v) => visitor.visit_bool(v),
+            Value::I64(v) => visitor.visit_i64(v),
+            Value::U64(v) => visitor.visit_u64(v),
+            Value::I128(v) => visitor.visit_i128(v),
+            Value::U128(v) => visitor.visit_u128(v),
+            Value::F32(v) => visitor.visit_f32(v),
+            Value::F64(v) => visitor.visit_f64(v),
+            Value::Char(v) => visitor.visit_char(v),
+            Value::String(v) => visitor.visit_string(v),
+            Value::Bytes(v) => visitor.visit_byte_buf(v),
+            Value::None => visitor.visit_none(),
+            Value::Some(v) => visitor.visit_some(*v),
+            Value::Unit => visitor.visit_unit(),
+            Value::Newtype(v) => visitor.visit_newtype_struct(*v),
+            Value::Seq(v) => {
+                let mut seq = SeqDeserializer::new(v.into_iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Value::Map(v) => {
+                let mut map = MapDeserializer::new(v.into_iter());
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+            Value::Struct(v) => {
+                // the fields are identified by their index, which the derived implementations
+                // of `Deserialize` accept as well as the field names.
+                // Unknown fields are skipped by the visitor with `deserialize_ignored_any`
+                let mut map =
+                    MapDeserializer::new(v.into_iter().map(|(index, value)| (index as u64, value)));
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+            Value::Variant(index, v) => {
+                visitor.visit_enum(VariantDeserializer { index, value: *v })
+            }
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Value::None => visitor.visit_none(),
+            Value::Some(v) => visitor.visit_some(*v),
+            v => visitor.visit_some(v),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self {
+            Value::Newtype(v) => visitor.visit_newtype_struct(*v),
+            v => visitor.visit_newtype_struct(v),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct enum identifier
+    }
+}
+
+struct VariantDeserializer {
+    index: u32,
+    value: Value,
+}
+
+impl<'de> EnumAccess<'de> for VariantDeserializer {
+    type Error = Error;
+    type Variant = Value;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Value), Error> {
+        let variant = seed.deserialize(self.index.into_deserializer())?;
+        Ok((variant, self.value))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Value {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_any(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_any(visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::prelude::{Extensible, SerializerBackend};
+    use crate::serialize::reader::Reader;
+    use crate::serialize::writer::Writer;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct MessageV1 {
+        id: u32,
+        name: String,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct MessageV2 {
+        id: u32,
+        name: String,
+        #[serde(default)]
+        tags: Vec<u16>,
+        score: Option<f32>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Empty,
+        Circle(f32),
+        Rectangle(f32, f32),
+        Polygon { points: Vec<(i32, i32)> },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Complex {
+        shapes: Vec<Shape>,
+        names: BTreeMap<u8, String>,
+        parent: Option<Box<Complex>>,
+        bytes: bytes::Bytes,
+        unit: (),
+        newtype: Wrapper,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Wrapper(i64);
+
+    #[test]
+    fn test_roundtrip() {
+        let value = Complex {
+            shapes: vec![
+                Shape::Empty,
+                Shape::Circle(1.0),
+                Shape::Rectangle(2.0, -3.0),
+                Shape::Polygon {
+                    points: vec![(0, 0), (-1, 5)],
+                },
+            ],
+            names: BTreeMap::from_iter([(1, "a".to_string()), (2, "b".to_string())]),
+            parent: Some(Box::new(Complex {
+                shapes: vec![],
+                names: BTreeMap::default(),
+                parent: None,
+                bytes: bytes::Bytes::new(),
+                unit: (),
+                newtype: Wrapper(0),
+            })),
+            bytes: bytes::Bytes::from_static(&[1, 2, 3]),
+            unit: (),
+            newtype: Wrapper(i64::MIN),
+        };
+        let decoded: Complex = from_value(to_value(&value).unwrap()).unwrap();
+        assert_eq!(decoded, value);
+    }
+
+    #[test]
+    fn test_added_fields() {
+        // an older peer skips the fields that it doesn't know
+        let new = MessageV2 {
+            id: 3,
+            name: "new".to_string(),
+            tags: vec![1, 2],
+            score: Some(0.5),
+        };
+        let old: MessageV1 = from_value(to_value(&new).unwrap()).unwrap();
+        assert_eq!(
+            old,
+            MessageV1 {
+                id: 3,
+                name: "new".to_string()
+            }
+        );
+
+        // a newer peer uses the default values for the fields that are missing
+        let old = MessageV1 {
+            id: 4,
+            name: "old".to_string(),
+        };
+        let new: MessageV2 = from_value(to_value(&old).unwrap()).unwrap();
+        assert_eq!(
+            new,
+            MessageV2 {
+                id: 4,
+                name: "old".to_string(),
+                tags: vec![],
+                score: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_backend() {
+        let new = MessageV2 {
+            id: 3,
+            name: "new".to_string(),
+            tags: vec![1, 2],
+            score: None,
+        };
+        let mut writer = Writer::default();
+        Extensible::serialize(&new, &mut writer).unwrap();
+        Extensible::serialize(&new, &mut writer).unwrap();
+        let mut reader = Reader::from(writer.to_bytes());
+        // the skipped fields are consumed from the reader
+        let old: MessageV1 = Extensible::deserialize(&mut reader).unwrap();
+        assert_eq!(old.id, 3);
+        let new_read: MessageV2 = Extensible::deserialize(&mut reader).unwrap();
+        assert_eq!(new_read, new);
+        assert!(!reader.has_remaining());
+    }
+}
@@ -5,6 +5,9 @@ use crate::serialize::varint::{varint_len, VarIntReadExt, VarIntWriteExt};
 use byteorder::{ReadBytesExt, WriteBytesExt};
 use bytes::Bytes;
 
+pub(crate) mod canonical;
+pub(crate) mod extensible;
+pub mod quantize;
 pub mod reader;
 pub(crate) mod varint;
 pub mod writer;
@@ -26,6 +29,8 @@ pub enum SerializationError {
     BincodeEncode(#[from] bincode::error::EncodeError),
     #[error(transparent)]
     BincodeDecode(#[from] bincode::error::DecodeError),
+    #[error(transparent)]
+    Serde(#[from] serde::de::value::Error),
     #[error("The message is too big ({0} bytes) to be sent. We can split a message only up to 256 fragments.")]
     MessageTooBig(usize),
 }
@@ -134,7 +139,7 @@ impl<M: ToBytes> ToBytes for Vec<M> {
     }
 
     fn to_bytes<T: WriteBytesExt>(&self, buffer: &mut T) -> Result<(), SerializationError> {
-        buffer.write_u64::<byteorder::NetworkEndian>(self.len() as u64)?;
+        buffer.write_varint(self.len() as u64)?;
         self.iter().try_for_each(|item| item.to_bytes(buffer))?;
         Ok(())
     }
@@ -143,7 +148,7 @@ impl<M: ToBytes> ToBytes for Vec<M> {
     where
         Self: Sized,
     {
-        let len = buffer.read_u64::<byteorder::NetworkEndian>()? as usize;
+        let len = buffer.read_varint()? as usize;
         // TODO: if we know the MIN_LEN we can preallocate
         let mut vec = Vec::with_capacity(len);
         for _ in 0..len {
@@ -168,4 +173,19 @@ mod tests {
         let read = Bytes::from_bytes(&mut reader).unwrap();
         assert_eq!(a, read);
     }
+
+    #[test]
+    fn test_serialize_vec() {
+        let a: Vec<Bytes> = vec![vec![1; 3].into(), vec![2; 70].into()];
+        let mut writer = Writer::with_capacity(5);
+        a.to_bytes(&mut writer).unwrap();
+        let bytes = writer.to_bytes();
+        // the number of elements is written as a varint
+        assert_eq!(bytes.len(), 1 + (1 + 3) + (2 + 70));
+        assert_eq!(bytes.len(), ToBytes::len(&a));
+
+        let mut reader = Reader::from(bytes);
+        let read = Vec::<Bytes>::from_bytes(&mut reader).unwrap();
+        assert_eq!(a, read);
+    }
 }
@@ -0,0 +1,398 @@
+//! Quantization of floats, vectors and rotations into a small number of bits.
+//!
+//! Components are serialized with `serde`, so a component can implement [`Serialize`](serde::Serialize)
+//! and [`Deserialize`](serde::Deserialize) by packing its fields with a [`BitWriter`] into a fixed-size
+//! byte array, with the precision that it needs:
+//! - [`quantize_f32`] maps a float in a known range to an integer of `bits` bits
+//! - [`BitWriter::write_quat`] uses the smallest-three encoding: 2 bits for the index of the largest
+//!   component, and `bits` bits for each of the other three
+//! - [`BitWriter::write_unit_vec3`] uses an octahedral encoding of normalized vectors in `2 * bits` bits
+//!
+//! ```rust
+//! use bevy::math::Vec3;
+//! use lightyear::prelude::*;
+//! use lightyear::serialize::quantize::{BitReader, BitWriter};
+//! use serde::de::Error as _;
+//! use serde::ser::Error as _;
+//!
+//! /// Position in a 2km-wide world, with a precision of 3cm
+//! struct Position(Vec3);
+//!
+//! const POSITION_RANGE: std::ops::RangeInclusive<f32> = -1000.0..=1000.0;
+//!
+//! impl Serialize for Position {
+//!     fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+//!         let mut writer = BitWriter::default();
+//!         writer.write_vec3(self.0, POSITION_RANGE, 16);
+//!         writer.into_array::<6>().map_err(S::Error::custom)?.serialize(serializer)
+//!     }
+//! }
+//!
+//! impl<'de> Deserialize<'de> for Position {
+//!     fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+//!         let bytes = <[u8; 6]>::deserialize(deserializer)?;
+//!         let mut reader = BitReader::new(&bytes);
+//!         let position = reader.read_vec3(POSITION_RANGE, 16).map_err(D::Error::custom)?;
+//!         Ok(Position(position))
+//!     }
+//! }
+//! ```
+use std::io::ErrorKind;
+use std::ops::RangeInclusive;
+
+use bevy::math::{Quat, Vec3};
+use byteorder::WriteBytesExt;
+
+use crate::serialize::SerializationError;
+
+/// The components of a normalized quaternion other than the largest one are in this range
+const SMALLEST_THREE_BOUND: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+fn max_quantized(bits: u32) -> f64 {
+    debug_assert!((1..=32).contains(&bits), "bits must be between 1 and 32");
+    ((1u64 << bits) - 1) as f64
+}
+
+/// Map `value` to an integer of `bits` bits, where 0 is the start of `range` and the largest
+/// integer is the end of `range`.
+///
+/// Values outside of the range are clamped. The precision is `(end - start) / (2^bits - 1)`.
+pub fn quantize_f32(value: f32, range: RangeInclusive<f32>, bits: u32) -> u32 {
+    let (start, end) = (*range.start() as f64, *range.end() as f64);
+    let normalized = ((value as f64 - start) / (end - start)).clamp(0.0, 1.0);
+    // NaN is mapped to the start of the range
+    (normalized * max_quantized(bits)).round() as u32
+}
+
+/// Inverse of [`quantize_f32`]
+pub fn dequantize_f32(quantized: u32, range: RangeInclusive<f32>, bits: u32) -> f32 {
+    let (start, end) = (*range.start() as f64, *range.end() as f64);
+    (start + (quantized as f64 / max_quantized(bits)) * (end - start)) as f32
+}
+
+/// Packs values into a buffer bit by bit, starting with the least significant bits
+#[derive(Debug, Default)]
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    scratch: u64,
+    scratch_bits: u32,
+}
+
+impl BitWriter {
+    /// Write the `bits` least significant bits of `value`
+    pub fn write_bits(&mut self, value: u32, bits: u32) {
+        debug_assert!(bits <= 32, "cannot write more than 32 bits at once");
+        let mask = (1u64 << bits) - 1;
+        self.scratch |= (value as u64 & mask) << self.scratch_bits;
+        self.scratch_bits += bits;
+        while self.scratch_bits >= 8 {
+            self.bytes.push(self.scratch as u8);
+            self.scratch >>= 8;
+            self.scratch_bits -= 8;
+        }
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_bits(value as u32, 1);
+    }
+
+    /// Write a float in `range` with `bits` bits, see [`quantize_f32`]
+    pub fn write_f32(&mut self, value: f32, range: RangeInclusive<f32>, bits: u32) {
+        self.write_bits(quantize_f32(value, range, bits), bits);
+    }
+
+    /// Write each component of a vector in `range` with `bits` bits
+    pub fn write_vec3(&mut self, value: Vec3, range: RangeInclusive<f32>, bits: u32) {
+        self.write_f32(value.x, range.clone(), bits);
+        self.write_f32(value.y, range.clone(), bits);
+        self.write_f32(value.z, range, bits);
+    }
+
+    /// Write a normalized vector with `2 * bits` bits, using an octahedral encoding
+    pub fn write_unit_vec3(&mut self, value: Vec3, bits: u32) {
+        // project the vector on the octahedron |x| + |y| + |z| = 1, then unfold the lower half
+        let n = value / (value.x.abs() + value.y.abs() + value.z.abs()).max(f32::EPSILON);
+        let (x, y) = if n.z >= 0.0 {
+            (n.x, n.y)
+        } else {
+            ((1.0 - n.y.abs()) * sign(n.x), (1.0 - n.x.abs()) * sign(n.y))
+        };
+        self.write_f32(x, -1.0..=1.0, bits);
+        self.write_f32(y, -1.0..=1.0, bits);
+    }
+
+    /// Write a rotation with `2 + 3 * bits` bits, using the smallest-three encoding
+    pub fn write_quat(&mut self, value: Quat, bits: u32) {
+        let mut components = value.normalize().to_array();
+        let largest = (0..4)
+            .max_by(|a, b| components[*a].abs().total_cmp(&components[*b].abs()))
+            .unwrap();
+        // q and -q represent the same rotation, so we can make the largest component positive
+        // and omit its sign
+        if components[largest] < 0.0 {
+            components.iter_mut().for_each(|c| *c = -*c);
+        }
+        self.write_bits(largest as u32, 2);
+        for (i, component) in components.into_iter().enumerate() {
+            if i != largest {
+                self.write_f32(
+                    component,
+                    -SMALLEST_THREE_BOUND..=SMALLEST_THREE_BOUND,
+                    bits,
+                );
+            }
+        }
+    }
+
+    /// Number of bits written so far
+    pub fn bits_written(&self) -> usize {
+        self.bytes.len() * 8 + self.scratch_bits as usize
+    }
+
+    /// Return the written bytes. The last byte is padded with zeros.
+    pub fn finish(mut self) -> Vec<u8> {
+        if self.scratch_bits > 0 {
+            self.bytes.push(self.scratch as u8);
+        }
+        self.bytes
+    }
+
+    /// Return the written bytes as an array of `N` bytes, padded with zeros.
+    ///
+    /// Arrays are serialized without a length prefix, which is convenient to implement `Serialize`.
+    /// Returns an error if more than `N` bytes were written.
+    pub fn into_array<const N: usize>(self) -> Result<[u8; N], SerializationError> {
+        let bytes = self.finish();
+        if bytes.len() > N {
+            return Err(SerializationError::InvalidValue);
+        }
+        let mut array = [0; N];
+        array[..bytes.len()].copy_from_slice(&bytes);
+        Ok(array)
+    }
+
+    /// Write the bytes to `buffer`, without a length prefix
+    pub fn write_to<T: WriteBytesExt>(self, buffer: &mut T) -> Result<(), SerializationError> {
+        buffer.write_all(&self.finish())?;
+        Ok(())
+    }
+}
+
+/// Reads values written by a [`BitWriter`]
+#[derive(Debug)]
+pub struct BitReader<'a> {
+    bytes: &'a [u8],
+    scratch: u64,
+    scratch_bits: u32,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            scratch: 0,
+            scratch_bits: 0,
+        }
+    }
+
+    /// Read a value of `bits` bits
+    pub fn read_bits(&mut self, bits: u32) -> Result<u32, SerializationError> {
+        debug_assert!(bits <= 32, "cannot read more than 32 bits at once");
+        while self.scratch_bits < bits {
+            let (byte, rest) = self
+                .bytes
+                .split_first()
+                .ok_or_else(|| std::io::Error::from(ErrorKind::UnexpectedEof))?;
+            self.scratch |= (*byte as u64) << self.scratch_bits;
+            self.scratch_bits += 8;
+            self.bytes = rest;
+        }
+        let value = self.scratch & ((1u64 << bits) - 1);
+        self.scratch >>= bits;
+        self.scratch_bits -= bits;
+        Ok(value as u32)
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, SerializationError> {
+        Ok(self.read_bits(1)? != 0)
+    }
+
+    pub fn read_f32(
+        &mut self,
+        range: RangeInclusive<f32>,
+        bits: u32,
+    ) -> Result<f32, SerializationError> {
+        Ok(dequantize_f32(self.read_bits(bits)?, range, bits))
+    }
+
+    pub fn read_vec3(
+        &mut self,
+        range: RangeInclusive<f32>,
+        bits: u32,
+    ) -> Result<Vec3, SerializationError> {
+        Ok(Vec3::new(
+            self.read_f32(range.clone(), bits)?,
+            self.read_f32(range.clone(), bits)?,
+            self.read_f32(range, bits)?,
+        ))
+    }
+
+    pub fn read_unit_vec3(&mut self, bits: u32) -> Result<Vec3, SerializationError> {
+        let x = self.read_f32(-1.0..=1.0, bits)?;
+        let y = self.read_f32(-1.0..=1.0, bits)?;
+        let z = 1.0 - x.abs() - y.abs();
+        let value = if z >= 0.0 {
+            Vec3::new(x, y, z)
+        } else {
+            Vec3::new((1.0 - y.abs()) * sign(x), (1.0 - x.abs()) * sign(y), z)
+        };
+        Ok(value.normalize())
+    }
+
+    pub fn read_quat(&mut self, bits: u32) -> Result<Quat, SerializationError> {
+        let largest = self.read_bits(2)? as usize;
+        let mut components = [0.0; 4];
+        let mut sum_squares = 0.0;
+        for (i, component) in components.iter_mut().enumerate() {
+            if i != largest {
+                *component = self.read_f32(-SMALLEST_THREE_BOUND..=SMALLEST_THREE_BOUND, bits)?;
+                sum_squares += *component * *component;
+            }
+        }
+        components[largest] = (1.0 - sum_squares).max(0.0).sqrt();
+        Ok(Quat::from_array(components).normalize())
+    }
+}
+
+/// Sign of a float, where 0 is positive
+fn sign(value: f32) -> f32 {
+    if value >= 0.0 {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[test]
+    fn test_quantize_f32() {
+        let range = -100.0..=100.0;
+        assert_eq!(quantize_f32(-100.0, range.clone(), 16), 0);
+        assert_eq!(quantize_f32(100.0, range.clone(), 16), u16::MAX as u32);
+        // values out of range are clamped
+        assert_eq!(quantize_f32(1000.0, range.clone(), 16), u16::MAX as u32);
+
+        let precision = 200.0 / u16::MAX as f32;
+        for value in [-99.9, -1.234, 0.0, 42.42, 99.99] {
+            let quantized = quantize_f32(value, range.clone(), 16);
+            let dequantized = dequantize_f32(quantized, range.clone(), 16);
+            assert!((dequantized - value).abs() <= precision);
+        }
+    }
+
+    #[test]
+    fn test_bit_writer_reader() {
+        let mut writer = BitWriter::default();
+        writer.write_bits(0b101, 3);
+        writer.write_bool(true);
+        writer.write_bits(u32::MAX, 32);
+        writer.write_bits(0x1234, 13);
+        assert_eq!(writer.bits_written(), 49);
+        let bytes = writer.finish();
+        assert_eq!(bytes.len(), 7);
+
+        let mut reader = BitReader::new(&bytes);
+        assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+        assert!(reader.read_bool().unwrap());
+        assert_eq!(reader.read_bits(32).unwrap(), u32::MAX);
+        assert_eq!(reader.read_bits(13).unwrap(), 0x1234 & 0x1FFF);
+        assert!(reader.read_bits(16).is_err());
+    }
+
+    #[test]
+    fn test_unit_vec3() {
+        for value in [
+            Vec3::X,
+            Vec3::NEG_Y,
+            Vec3::new(0.3, -0.5, -0.8).normalize(),
+            Vec3::new(-1.0, 2.0, 3.0).normalize(),
+        ] {
+            let mut writer = BitWriter::default();
+            writer.write_unit_vec3(value, 12);
+            let bytes = writer.finish();
+            assert_eq!(bytes.len(), 3);
+            let read = BitReader::new(&bytes).read_unit_vec3(12).unwrap();
+            assert!(read.angle_between(value) < 0.005, "{read} != {value}");
+        }
+    }
+
+    #[test]
+    fn test_quat() {
+        for value in [
+            Quat::IDENTITY,
+            Quat::from_rotation_y(3.0),
+            Quat::from_euler(bevy::math::EulerRot::XYZ, 0.1, -2.0, 1.3),
+            -Quat::from_rotation_x(0.5),
+        ] {
+            let mut writer = BitWriter::default();
+            writer.write_quat(value, 10);
+            let bytes = writer.finish();
+            assert_eq!(bytes.len(), 4);
+            let read = BitReader::new(&bytes).read_quat(10).unwrap();
+            assert!(read.angle_between(value) < 0.01, "{read} != {value}");
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Transform {
+        translation: Vec3,
+        rotation: Quat,
+    }
+
+    const RANGE: RangeInclusive<f32> = -1000.0..=1000.0;
+
+    impl Serialize for Transform {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            use serde::ser::Error;
+            let mut writer = BitWriter::default();
+            writer.write_vec3(self.translation, RANGE, 16);
+            writer.write_quat(self.rotation, 10);
+            writer
+                .into_array::<10>()
+                .map_err(S::Error::custom)?
+                .serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Transform {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            use serde::de::Error;
+            let bytes = <[u8; 10]>::deserialize(deserializer)?;
+            let mut reader = BitReader::new(&bytes);
+            Ok(Transform {
+                translation: reader.read_vec3(RANGE, 16).map_err(D::Error::custom)?,
+                rotation: reader.read_quat(10).map_err(D::Error::custom)?,
+            })
+        }
+    }
+
+    #[test]
+    fn test_serde_integration() {
+        let transform = Transform {
+            translation: Vec3::new(12.5, -300.25, 999.0),
+            rotation: Quat::from_rotation_z(1.0),
+        };
+        let bytes = bincode::serde::encode_to_vec(&transform, bincode::config::standard()).unwrap();
+        // 6 bytes for the translation, 4 bytes for the rotation
+        assert_eq!(bytes.len(), 10);
+        let (read, _): (Transform, usize) =
+            bincode::serde::decode_from_slice(&bytes, bincode::config::standard()).unwrap();
+        assert!(read.translation.distance(transform.translation) < 0.1);
+        assert!(read.rotation.angle_between(transform.rotation) < 0.01);
+    }
+}
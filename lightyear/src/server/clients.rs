@@ -2,6 +2,7 @@
 //!
 //! This module contains components and systems to manage the metadata on client entities.
 use crate::prelude::ClientId;
+use crate::shared::replication::authority::reset_authority_on_disconnect;
 use crate::shared::sets::{InternalReplicationSet, ServerMarker};
 use bevy::ecs::entity::EntityHashSet;
 use bevy::prelude::*;
@@ -105,6 +106,12 @@ impl Plugin for ClientsMetadataPlugin {
         );
         // we handle this in the `Last` `SystemSet` to let the user handle the disconnect event
         // however they want first, before the client entity gets despawned
-        app.add_systems(Last, systems::handle_client_disconnect);
+        app.add_systems(
+            Last,
+            (
+                systems::handle_client_disconnect,
+                reset_authority_on_disconnect,
+            ),
+        );
     }
 }
@@ -4,10 +4,12 @@ use governor::Quota;
 use nonzero_ext::nonzero;
 use std::sync::Arc;
 
-use crate::connection::netcode::{Key, PRIVATE_KEY_BYTES};
+use crate::connection::netcode::{Key, MAX_PACKET_SIZE, PRIVATE_KEY_BYTES};
 use crate::connection::server::{
     ConnectionRequestHandler, DefaultConnectionRequestHandler, NetConfig,
 };
+use crate::packet::congestion::CongestionConfig;
+use crate::packet::packet::MAX_MESSAGE_SIZE;
 use crate::prelude::ReplicationConfig;
 use crate::shared::config::SharedConfig;
 use crate::shared::ping::manager::PingConfig;
@@ -15,6 +17,9 @@ use crate::shared::ping::manager::PingConfig;
 #[derive(Debug, Clone)]
 pub struct NetcodeConfig {
     pub num_disconnect_packets: usize,
+    /// Interval (in seconds) at which keep-alive packets are sent to a client when no other packet
+    /// was sent to it, so that idle connections are not timed out.
+    /// The default is 0.1 seconds.
     pub keep_alive_send_rate: f64,
     /// Set the duration (in seconds) after which the server disconnects a client if they don't hear from them.
     /// This is valid for tokens generated by the server.
@@ -53,6 +58,12 @@ impl NetcodeConfig {
         self.client_timeout_secs = client_timeout_secs;
         self
     }
+
+    /// Set the interval (in seconds) at which keep-alive packets are sent to idle clients
+    pub fn with_keep_alive_send_rate(mut self, keep_alive_send_rate: f64) -> Self {
+        self.keep_alive_send_rate = keep_alive_send_rate;
+        self
+    }
 }
 
 /// Configuration related to sending packets
@@ -66,7 +77,25 @@ pub struct PacketConfig {
     /// Number of bytes per second that can be sent to each client
     pub per_client_send_bandwidth_cap: Quota,
     /// If false, there is no bandwidth cap and all messages are sent as soon as possible
+    ///
+    /// If true, the messages that don't fit in the bandwidth quota are dropped if they are unreliable,
+    /// and sent again at the next opportunity if they are reliable.
     pub bandwidth_cap_enabled: bool,
+    /// Configuration of the congestion control, which reduces the packet send rate and the
+    /// replication frequency when the RTT or the packet loss spike
+    pub congestion: CongestionConfig,
+    /// Maximum number of bytes of the payload of a packet.
+    ///
+    /// The default is [`MAX_PACKET_SIZE`], which is also the largest value allowed by the netcode protocol.
+    /// Use a smaller value if the packets go through a relay or a network with a small MTU.
+    /// The value is clamped between 128 and [`MAX_PACKET_SIZE`] bytes.
+    pub max_packet_size: usize,
+    /// Maximum number of bytes of a message. Sending a bigger message returns
+    /// [`ChannelSendError::MessageTooBig`](crate::prelude::ChannelSendError::MessageTooBig).
+    ///
+    /// The messages that don't fit in a packet are split into at most 255 fragments, so the
+    /// largest message that can be sent also depends on [`max_packet_size`](Self::max_packet_size).
+    pub max_message_size: usize,
 }
 
 impl Default for PacketConfig {
@@ -76,6 +105,9 @@ impl Default for PacketConfig {
             // 56 KB/s bandwidth cap
             per_client_send_bandwidth_cap: Quota::per_second(nonzero!(56000u32)),
             bandwidth_cap_enabled: false,
+            congestion: CongestionConfig::default(),
+            max_packet_size: MAX_PACKET_SIZE,
+            max_message_size: MAX_MESSAGE_SIZE,
         }
     }
 }
@@ -96,6 +128,21 @@ impl PacketConfig {
         self.bandwidth_cap_enabled = true;
         self
     }
+
+    pub fn with_congestion_control(mut self, congestion: CongestionConfig) -> Self {
+        self.congestion = congestion;
+        self
+    }
+
+    pub fn with_max_packet_size(mut self, max_packet_size: usize) -> Self {
+        self.max_packet_size = max_packet_size;
+        self
+    }
+
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
 }
 
 /// Configuration for the server plugin.
@@ -6,27 +6,30 @@ use bevy::ptr::Ptr;
 use bevy::utils::{Duration, HashMap, HashSet};
 use bytes::Bytes;
 use hashbrown::hash_map::Entry;
-use tracing::{debug, info, info_span, trace, trace_span};
+use tracing::{debug, error, info, info_span, trace, trace_span};
 #[cfg(feature = "trace")]
 use tracing::{instrument, Level};
 
 use crate::channel::builder::{
-    EntityActionsChannel, EntityUpdatesChannel, PingChannel, PongChannel,
+    AuthorityChannel, ChannelRegistrationChannel, EntityActionsChannel, EntityUpdatesChannel,
+    InitialSyncChannel, PingChannel, PongChannel,
 };
 
-use crate::channel::receivers::ChannelReceive;
 use crate::channel::senders::ChannelSend;
+use crate::channel::stats::ChannelStats;
 use crate::client::message::ClientMessage;
 use crate::connection::id::ClientId;
 use crate::connection::netcode::MAX_PACKET_SIZE;
+use crate::packet::error::PacketError;
+use crate::packet::message::MessageId;
 use crate::packet::message_manager::MessageManager;
 use crate::packet::packet_builder::{Payload, RecvPayload};
-use crate::prelude::server::{DisconnectEvent, RoomId, RoomManager};
+use crate::prelude::server::{DisconnectEvent, RoomId, RoomManager, RoomTarget};
 use crate::prelude::{
-    Channel, ChannelKind, Message, PreSpawnedPlayerObject, ReplicationConfig, ReplicationGroup,
-    ShouldBePredicted,
+    Channel, ChannelKind, ChannelSettings, Message, PreSpawnedPlayerObject, ReplicationConfig,
+    ReplicationGroup, ShouldBePredicted,
 };
-use crate::protocol::channel::ChannelRegistry;
+use crate::protocol::channel::{ChannelRegistration, ChannelRegistry};
 use crate::protocol::component::{
     ComponentError, ComponentKind, ComponentNetId, ComponentRegistry,
 };
@@ -44,12 +47,17 @@ use crate::shared::events::connection::ConnectionEvents;
 use crate::shared::message::MessageSend;
 use crate::shared::ping::manager::{PingConfig, PingManager};
 use crate::shared::ping::message::{Ping, Pong};
+use crate::shared::replication::authority::AuthorityChange;
 use crate::shared::replication::components::ReplicationGroupId;
 use crate::shared::replication::delta::DeltaManager;
+use crate::shared::replication::entity_map::RemoteEntityMap;
+use crate::shared::replication::initial_sync::InitialSync;
 use crate::shared::replication::network_target::NetworkTarget;
 use crate::shared::replication::receive::ReplicationReceiver;
 use crate::shared::replication::send::ReplicationSender;
-use crate::shared::replication::{EntityActionsMessage, EntityUpdatesMessage, ReplicationPeer};
+use crate::shared::replication::{
+    DespawnReason, EntityActionsMessage, EntityUpdatesMessage, ReplicationPeer,
+};
 use crate::shared::replication::{ReplicationReceive, ReplicationSend};
 use crate::shared::sets::ServerMarker;
 use crate::shared::tick_manager::Tick;
@@ -74,6 +82,9 @@ pub struct ConnectionManager {
     // list of clients that connected since the last time we sent replication messages
     // (we want to keep track of them because we need to replicate the entire world state to them)
     pub(crate) new_clients: Vec<ClientId>,
+    // list of entities whose replication was resumed since the last time we sent replication messages
+    // (all their components need to be sent again)
+    pub(crate) resumed_entities: Vec<Entity>,
     pub(crate) writer: Writer,
 
     // CONFIG
@@ -111,6 +122,7 @@ impl ConnectionManager {
             delta_manager: DeltaManager::default(),
             replicate_component_cache: EntityHashMap::default(),
             new_clients: vec![],
+            resumed_entities: vec![],
             writer: Writer::with_capacity(MAX_PACKET_SIZE),
             replication_config,
             packet_config,
@@ -128,6 +140,13 @@ impl ConnectionManager {
         self.connections.keys().copied()
     }
 
+    /// Return the clients that receive the initial snapshot of the world during this send interval
+    ///
+    /// The list is only filled while the systems in [`ServerReplicationSet::LateJoin`](crate::prelude::server::ServerReplicationSet::LateJoin) run.
+    pub fn late_joiners(&self) -> &[ClientId] {
+        &self.new_clients
+    }
+
     /// Queues up a message to be sent to all clients matching the specific [`NetworkTarget`]
     pub fn send_message_to_target<C: Channel, M: Message>(
         &mut self,
@@ -137,6 +156,18 @@ impl ConnectionManager {
         self.erased_send_message_to_target(message, ChannelKind::of::<C>(), target)
     }
 
+    /// Send a message to a target, on the default channel of the message
+    ///
+    /// See [`MessageRegistration::set_default_channel`](crate::protocol::message::MessageRegistration::set_default_channel)
+    pub fn send_event<M: Message>(
+        &mut self,
+        message: &M,
+        target: NetworkTarget,
+    ) -> Result<(), ServerError> {
+        let channel_kind = self.message_registry.default_channel::<M>()?;
+        self.erased_send_message_to_target(message, channel_kind, target)
+    }
+
     /// Send a message to all clients in a room
     pub fn send_message_to_room<C: Channel, M: Message>(
         &mut self,
@@ -151,6 +182,16 @@ impl ConnectionManager {
         self.send_message_to_target::<C, M>(message, target)
     }
 
+    /// Send a message to the clients of a [`RoomTarget`], for example all the players of a team
+    pub fn send_message_to_room_target<C: Channel, M: Message>(
+        &mut self,
+        message: &M,
+        target: &RoomTarget,
+        room_manager: &RoomManager,
+    ) -> Result<(), ServerError> {
+        self.send_message_to_target::<C, M>(message, room_manager.resolve(target))
+    }
+
     /// Queues up a message to be sent to a client
     pub fn send_message<C: Channel, M: Message>(
         &mut self,
@@ -160,6 +201,109 @@ impl ConnectionManager {
         self.send_message_to_target::<C, M>(message, NetworkTarget::Only(vec![client_id]))
     }
 
+    /// Register a new channel while the server is running.
+    ///
+    /// The id of the channel is sent to the connected clients (and to the clients that connect later).
+    /// The clients need to register the same channel with
+    /// [`ClientCommands::register_channel`](crate::client::networking::ClientCommands::register_channel);
+    /// messages sent on the channel to a client are buffered until that client has registered it.
+    ///
+    /// Prefer [`ServerCommands::register_channel`](crate::server::networking::ServerCommands::register_channel),
+    /// which also keeps the channel registered if the server is restarted.
+    pub fn register_channel<C: Channel>(
+        &mut self,
+        settings: ChannelSettings,
+    ) -> Result<(), ServerError> {
+        let channel_kind = ChannelKind::of::<C>();
+        if self
+            .channel_registry
+            .get_net_from_kind(&channel_kind)
+            .is_some()
+        {
+            return Ok(());
+        }
+        self.channel_registry.add_runtime_channel::<C>(settings);
+        self.connections.values_mut().try_for_each(|connection| {
+            connection.message_manager.add_runtime_channel(
+                channel_kind,
+                &self.channel_registry,
+                true,
+            )?;
+            connection.send_channel_registration(channel_kind)
+        })
+    }
+
+    /// Send a message to a client, unless the channel `C` is full for that client.
+    ///
+    /// Returns [`ChannelSendError::ChannelFull`](crate::prelude::ChannelSendError::ChannelFull) if the channel
+    /// already holds [`ReliableSettings::max_in_flight_messages`](crate::prelude::ReliableSettings::max_in_flight_messages)
+    /// messages that the client did not ack, so that the caller can wait before sending more messages.
+    pub fn try_send_message<C: Channel, M: Message>(
+        &mut self,
+        client_id: ClientId,
+        message: &M,
+    ) -> Result<Option<MessageId>, ServerError> {
+        self.message_registry.serialize(message, &mut self.writer)?;
+        let message_bytes = self.writer.split();
+        Ok(self
+            .connection_mut(client_id)?
+            .message_manager
+            .try_buffer_send(message_bytes, ChannelKind::of::<C>())?)
+    }
+
+    /// Override the settings of the channel `C` for a single client.
+    ///
+    /// This can be used to change the send frequency, the priority or the resend timing of reliable messages
+    /// for some clients, for example to replicate less often to clients that have a slow connection.
+    /// The mode and the direction of the channel cannot be changed, since the client uses the same ones.
+    ///
+    /// The override lasts until the client disconnects.
+    pub fn override_channel_settings<C: Channel>(
+        &mut self,
+        client_id: ClientId,
+        settings: ChannelSettings,
+    ) -> Result<(), ServerError> {
+        self.connection_mut(client_id)?
+            .message_manager
+            .update_channel_settings(&ChannelKind::of::<C>(), settings)?;
+        Ok(())
+    }
+
+    /// Restore the settings of the channel `C` for a client to the settings from the protocol
+    pub fn reset_channel_settings<C: Channel>(
+        &mut self,
+        client_id: ClientId,
+    ) -> Result<(), ServerError> {
+        let channel_kind = ChannelKind::of::<C>();
+        let settings = self
+            .channel_registry
+            .get_builder_from_kind(&channel_kind)
+            .ok_or(PacketError::ChannelNotFound)?
+            .settings
+            .clone();
+        self.connection_mut(client_id)?
+            .message_manager
+            .update_channel_settings(&channel_kind, settings)?;
+        Ok(())
+    }
+
+    /// Queues up a message to be sent to a client, and return the id of the message on the channel `C`
+    ///
+    /// If the channel keeps track of acks, a [`MessageDelivered`](crate::server::events::MessageDelivered)
+    /// event with this id is emitted once the client has received the message.
+    /// On unreliable channels, a [`MessageLost`](crate::server::events::MessageLost) event is emitted
+    /// instead if the message was lost.
+    pub fn send_message_with_receipt<C: Channel, M: Message>(
+        &mut self,
+        client_id: ClientId,
+        message: &M,
+    ) -> Result<Option<MessageId>, ServerError> {
+        self.message_registry.serialize(message, &mut self.writer)?;
+        let message_bytes = self.writer.split();
+        self.connection_mut(client_id)?
+            .buffer_message(message_bytes, ChannelKind::of::<C>())
+    }
+
     /// Update the priority of a `ReplicationGroup` that is replicated to a given client
     pub fn update_priority(
         &mut self,
@@ -249,7 +393,7 @@ impl ConnectionManager {
             metrics::gauge!("connected_clients").increment(1.0);
 
             info!("New connection from id: {}", client_id);
-            let connection = Connection::new(
+            let mut connection = Connection::new(
                 client_id,
                 client_entity,
                 &self.channel_registry,
@@ -257,6 +401,13 @@ impl ConnectionManager {
                 self.packet_config.clone(),
                 self.ping_config.clone(),
             );
+            // the client needs the ids of the channels registered at runtime before we can use them
+            for channel_kind in self.channel_registry.runtime_channels() {
+                connection.message_manager.pause_channel(channel_kind);
+                if let Err(e) = connection.send_channel_registration(*channel_kind) {
+                    error!("Could not send channel registration: {:?}", e);
+                }
+            }
             self.events.add_connect_event(ConnectEvent {
                 client_id,
                 entity: client_entity,
@@ -294,7 +445,7 @@ impl ConnectionManager {
             .iter_mut()
             .filter(|(id, _)| target.targets(id))
             // NOTE: this clone is O(1), it just increments the reference count
-            .try_for_each(|(_, c)| c.buffer_message(message.clone(), channel))
+            .try_for_each(|(_, c)| c.buffer_message(message.clone(), channel).map(|_| ()))
     }
 
     pub(crate) fn erased_send_message_to_target<M: Message>(
@@ -406,6 +557,8 @@ pub struct Connection {
     pub(crate) received_leafwing_input_messages:
         HashMap<NetId, Vec<(Bytes, NetworkTarget, ChannelKind)>>,
     writer: Writer,
+    /// True once we have sent the [`InitialSync`] marker, after the first replication messages
+    initial_sync_sent: bool,
     // messages that we have received that need to be rebroadcasted to other clients
     pub(crate) messages_to_rebroadcast: Vec<(Bytes, NetworkTarget, ChannelKind)>,
 }
@@ -420,12 +573,19 @@ impl Connection {
         ping_config: PingConfig,
     ) -> Self {
         let bandwidth_cap_enabled = packet_config.bandwidth_cap_enabled;
+        let congestion_config = packet_config.congestion.clone();
+        let (max_packet_size, max_message_size) = (
+            packet_config.max_packet_size,
+            packet_config.max_message_size,
+        );
         // create the message manager and the channels
         let mut message_manager = MessageManager::new(
             channel_registry,
             packet_config.nack_rtt_multiple,
             packet_config.into(),
+            congestion_config,
         );
+        message_manager.set_max_sizes(max_packet_size, max_message_size);
         // get notified about acks/nacks for replication-update messages
         let entity_updates_sender = &mut message_manager
             .channels
@@ -458,10 +618,47 @@ impl Connection {
             #[cfg(feature = "leafwing")]
             received_leafwing_input_messages: HashMap::default(),
             writer: Writer::with_capacity(MAX_PACKET_SIZE),
+            initial_sync_sent: false,
             messages_to_rebroadcast: vec![],
         }
     }
 
+    /// Send to the client the id of a channel that was registered at runtime
+    pub(crate) fn send_channel_registration(
+        &mut self,
+        channel_kind: ChannelKind,
+    ) -> Result<(), ServerError> {
+        let channel_registry = &self.message_manager.channel_registry;
+        let registration = ChannelRegistration {
+            name: channel_registry
+                .name(&channel_kind)
+                .ok_or(PacketError::ChannelNotFound)?
+                .to_string(),
+            net_id: *channel_registry
+                .get_net_from_kind(&channel_kind)
+                .ok_or(PacketError::ChannelNotFound)?,
+        };
+        registration.to_bytes(&mut self.writer)?;
+        let message_bytes = self.writer.split();
+        self.message_manager.buffer_send(
+            message_bytes,
+            ChannelKind::of::<ChannelRegistrationChannel>(),
+        )?;
+        Ok(())
+    }
+
+    /// Notify the client that it gained or lost the authority over an entity
+    pub(crate) fn send_authority_change(
+        &mut self,
+        authority_change: AuthorityChange,
+    ) -> Result<(), ServerError> {
+        authority_change.to_bytes(&mut self.writer)?;
+        let message_bytes = self.writer.split();
+        self.message_manager
+            .buffer_send(message_bytes, ChannelKind::of::<AuthorityChannel>())?;
+        Ok(())
+    }
+
     /// Return the latest estimate of rtt
     pub fn rtt(&self) -> Duration {
         self.ping_manager.rtt()
@@ -472,6 +669,23 @@ impl Connection {
         self.ping_manager.jitter()
     }
 
+    /// Return the statistics of the messages sent to and received from this client on the channel `C`
+    pub fn channel_stats<C: Channel>(&self) -> Option<&ChannelStats> {
+        self.message_manager.channel_stats::<C>()
+    }
+
+    /// Return the number of messages held by the channel `C` for this client: the messages waiting to be sent,
+    /// and for reliable channels the messages that were not acked yet
+    pub fn channel_queue_depth<C: Channel>(&self) -> Option<usize> {
+        self.message_manager.queue_depth(&ChannelKind::of::<C>())
+    }
+
+    /// Returns true if the congestion control detected that the connection to this client is congested,
+    /// in which case packets are sent less frequently
+    pub fn is_congested(&self) -> bool {
+        self.message_manager.congestion.is_congested()
+    }
+
     pub(crate) fn update(
         &mut self,
         world_tick: BevyTick,
@@ -488,7 +702,7 @@ impl Connection {
         &mut self,
         message: Bytes,
         channel: ChannelKind,
-    ) -> Result<(), ServerError> {
+    ) -> Result<Option<MessageId>, ServerError> {
         // TODO: i know channel names never change so i should be able to get them as static
         // TODO: just have a channel registry enum as well?
         let channel_name = self
@@ -497,8 +711,7 @@ impl Connection {
             .name(&channel)
             .ok_or::<ServerError>(MessageError::NotRegistered.into())?;
         // message.emit_send_logs(&channel_name);
-        self.message_manager.buffer_send(message, channel)?;
-        Ok(())
+        Ok(self.message_manager.buffer_send(message, channel)?)
     }
 
     #[cfg_attr(feature = "trace", instrument(level = Level::INFO, skip_all))]
@@ -515,12 +728,41 @@ impl Connection {
             &mut self.writer,
             &mut self.message_manager,
         )?;
-        self.replication_sender.send_updates_messages(
-            tick,
-            bevy_tick,
-            &mut self.writer,
-            &mut self.message_manager,
-        )?;
+        // the first actions messages contain all the entities that were spawned before the client connected
+        if !self.initial_sync_sent {
+            self.send_initial_sync()?;
+        }
+        // when the connection is congested, only buffer updates right before sending a packet
+        if self.message_manager.congestion.ready_to_send() {
+            self.replication_sender.send_updates_messages(
+                tick,
+                bevy_tick,
+                &mut self.writer,
+                &mut self.message_manager,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Send the [`InitialSync`] marker, which only contains the id of the next actions message of each group.
+    ///
+    /// The entities themselves were sent with the regular actions messages.
+    fn send_initial_sync(&mut self) -> Result<(), ServerError> {
+        let initial_sync = InitialSync {
+            groups: self
+                .replication_sender
+                .group_channels
+                .iter()
+                .filter(|(_, channel)| channel.actions_next_send_message_id != MessageId(0))
+                .map(|(group_id, channel)| (*group_id, channel.actions_next_send_message_id))
+                .collect(),
+        };
+        trace!(?initial_sync, "Sending initial sync");
+        initial_sync.to_bytes(&mut self.writer)?;
+        let message_bytes = self.writer.split();
+        self.message_manager
+            .buffer_send(message_bytes, ChannelKind::of::<InitialSyncChannel>())?;
+        self.initial_sync_sent = true;
         Ok(())
     }
 
@@ -556,6 +798,10 @@ impl Connection {
         // maybe send pings
         // same thing, we want the correct send time for the ping
         // (and not have the delay between when we prepare the ping and when we send the packet)
+        // when the connection is congested, we don't send packets every frame
+        if !self.message_manager.congestion.ready_to_send() {
+            return Ok(vec![]);
+        }
         if let Some(ping) = self.ping_manager.maybe_prepare_ping(time_manager) {
             self.send_ping(ping)?;
         }
@@ -578,6 +824,11 @@ impl Connection {
         Ok(payloads)
     }
 
+    /// Return the payloads once they have been sent, so that their buffers can be reused
+    pub(crate) fn release_payloads(&mut self, payloads: Vec<Payload>) {
+        self.message_manager.release_payloads(payloads);
+    }
+
     pub fn receive(
         &mut self,
         world: &mut World,
@@ -587,11 +838,12 @@ impl Connection {
     ) -> Result<ConnectionEvents, ServerError> {
         let _span = trace_span!("receive").entered();
         let message_registry = world.resource::<MessageRegistry>();
+        let mut channel_registrations = vec![];
         self.message_manager
             .channels
             .iter_mut()
             .try_for_each(|(channel_kind, channel)| {
-                while let Some((tick, single_data)) = channel.receiver.read_message() {
+                while let Some((tick, single_data)) = channel.read_message() {
                     // let channel_name = self
                     //     .message_manager
                     //     .channel_registry
@@ -614,6 +866,9 @@ impl Connection {
                         // process the pong
                         self.ping_manager
                             .process_pong(&pong, time_manager.current_time());
+                    } else if channel_kind == &ChannelKind::of::<ChannelRegistrationChannel>() {
+                        // the client has registered a channel that was added at runtime
+                        channel_registrations.push(ChannelRegistration::from_bytes(&mut reader)?);
                     } else if channel_kind == &ChannelKind::of::<EntityActionsChannel>() {
                         let actions = EntityActionsMessage::from_bytes(&mut reader)?;
                         trace!(?tick, ?actions, "received replication actions message");
@@ -663,6 +918,24 @@ impl Connection {
                 Ok::<(), SerializationError>(())
             })?;
 
+        // we can start sending messages on the channels that the client has registered
+        for registration in channel_registrations {
+            let channel_registry = &self.message_manager.channel_registry;
+            let Some(channel_kind) = channel_registry
+                .get_kind_from_net_id(registration.net_id)
+                .copied()
+                .filter(|kind| channel_registry.name(kind) == Some(registration.name.as_str()))
+            else {
+                error!(
+                    ?registration,
+                    "Client registered a channel that does not match the server's channels"
+                );
+                continue;
+            };
+            debug!(?registration, "Client registered channel");
+            self.message_manager.resume_channel(&channel_kind);
+        }
+
         // Check if we have any replication messages we can apply to the World (and emit events)
         self.replication_receiver.apply_world(
             world,
@@ -701,6 +974,7 @@ impl ConnectionManager {
         entity: Entity,
         group_id: ReplicationGroupId,
         target: NetworkTarget,
+        reason: DespawnReason,
     ) -> Result<(), ServerError> {
         self.apply_replication(target).try_for_each(|client_id| {
             // trace!(
@@ -711,7 +985,7 @@ impl ConnectionManager {
             // );
             self.connection_mut(client_id)?
                 .replication_sender
-                .prepare_entity_despawn(entity, group_id);
+                .prepare_entity_despawn(entity, group_id, reason);
             Ok(())
         })
     }
@@ -828,6 +1102,35 @@ impl ConnectionManager {
             })
     }
 
+    /// Return the oldest `send_tick` of the group among the clients of the target.
+    ///
+    /// Components that did not change since then were already sent to all the clients, so they don't need
+    /// to be checked again. Returns None if some clients haven't received any update for the group yet.
+    pub(crate) fn oldest_send_tick(
+        &self,
+        group_id: ReplicationGroupId,
+        target: &NetworkTarget,
+        system_current_tick: BevyTick,
+    ) -> Option<BevyTick> {
+        let mut oldest: Option<BevyTick> = None;
+        for (client_id, connection) in self.connections.iter() {
+            if !target.targets(client_id) {
+                continue;
+            }
+            let send_tick = connection
+                .replication_sender
+                .group_channels
+                .get(&group_id)?
+                .send_tick?;
+            if oldest.map_or(true, |oldest| {
+                oldest.is_newer_than(send_tick, system_current_tick)
+            }) {
+                oldest = Some(send_tick);
+            }
+        }
+        oldest
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn prepare_component_update(
         &mut self,
@@ -938,6 +1241,11 @@ impl ReplicationReceive for ConnectionManager {
         &mut self.events
     }
 
+    fn remote_entity_map(&self, from: Option<ClientId>) -> Option<&RemoteEntityMap> {
+        from.and_then(|client_id| self.connections.get(&client_id))
+            .map(|connection| &connection.replication_receiver.remote_entity_map)
+    }
+
     fn cleanup(&mut self, tick: Tick) {
         debug!("Running replication receive cleanup");
         for connection in self.connections.values_mut() {
@@ -958,10 +1266,11 @@ impl ReplicationSend for ConnectionManager {
         self.new_clients.clone()
     }
 
-    fn cleanup(&mut self, tick: Tick) {
+    fn cleanup(&mut self, tick: Tick, component_registry: &ComponentRegistry) {
         debug!("Running replication send cleanup");
         for connection in self.connections.values_mut() {
             connection.replication_sender.cleanup(tick);
         }
+        self.delta_manager.tick_cleanup(tick, component_registry);
     }
 }
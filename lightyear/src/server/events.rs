@@ -12,6 +12,8 @@ use crate::shared::events::connection::{
 };
 use crate::shared::events::plugin::EventsPlugin;
 use crate::shared::events::systems::push_component_events;
+use crate::shared::replication::DespawnReason;
+use crate::shared::run_conditions::is_started;
 use crate::shared::sets::{InternalMainSet, ServerMarker};
 
 type EntityHashMap<K, V> = hashbrown::HashMap<K, V, EntityHash>;
@@ -26,11 +28,53 @@ impl Plugin for ServerEventsPlugin {
             // EVENTS
             .add_event::<ConnectEvent>()
             .add_event::<DisconnectEvent>()
+            .add_event::<MessageDelivered>()
+            .add_event::<MessageLost>()
+            // SYSTEMS
+            .add_systems(
+                PreUpdate,
+                (push_message_delivered_events, push_message_lost_events)
+                    .in_set(InternalMainSet::<ServerMarker>::EmitEvents)
+                    .run_if(is_started),
+            )
             // PLUGIN
             .add_plugins(EventsPlugin::<ConnectionManager>::default());
     }
 }
 
+/// Emit a [`MessageDelivered`] event for every message that was acked by a client
+fn push_message_delivered_events(
+    mut connection_manager: ResMut<ConnectionManager>,
+    mut events: EventWriter<MessageDelivered>,
+) {
+    for (client_id, connection) in connection_manager.connections.iter_mut() {
+        events.send_batch(
+            connection
+                .message_manager
+                .delivered_messages()
+                .map(|(channel, message_id)| {
+                    MessageDelivered::new(message_id, channel, *client_id)
+                }),
+        );
+    }
+}
+
+/// Emit a [`MessageLost`] event for every unreliable message that was lost on the way to a client
+fn push_message_lost_events(
+    mut connection_manager: ResMut<ConnectionManager>,
+    mut events: EventWriter<MessageLost>,
+) {
+    for (client_id, connection) in connection_manager.connections.iter_mut() {
+        events.send_batch(
+            connection
+                .message_manager
+                .lost_messages()
+                .into_iter()
+                .map(|(channel, message_id)| MessageLost::new(message_id, channel, *client_id)),
+        );
+    }
+}
+
 #[derive(Debug)]
 pub struct ServerEvents {
     pub connections: Vec<ConnectEvent>,
@@ -147,11 +191,14 @@ impl IterEntitySpawnEvent<ClientId> for ServerEvents {
 }
 
 impl IterEntityDespawnEvent<ClientId> for ServerEvents {
-    fn into_iter_entity_despawn(&mut self) -> Box<dyn Iterator<Item = (Entity, ClientId)> + '_> {
+    fn into_iter_entity_despawn(
+        &mut self,
+    ) -> Box<dyn Iterator<Item = (Entity, DespawnReason, ClientId)> + '_> {
         Box::new(self.events.iter_mut().flat_map(|(client_id, events)| {
-            let entities = events.into_iter_entity_despawn().map(|(entity, _)| entity);
-            let client_ids = std::iter::once(*client_id).cycle();
-            entities.zip(client_ids)
+            let client_id = *client_id;
+            events
+                .into_iter_entity_despawn()
+                .map(move |(entity, reason, _)| (entity, reason, client_id))
         }))
     }
 
@@ -239,6 +286,10 @@ pub type ComponentRemoveEvent<C> =
 
 /// Bevy [`Event`] emitted on the server on the frame where a (non-replication) message is received
 pub type MessageEvent<M> = crate::shared::events::components::MessageEvent<M, ClientId>;
+/// Bevy [`Event`] emitted on the server when a message sent to a client has been received by the client
+pub type MessageDelivered = crate::shared::events::components::MessageDelivered<ClientId>;
+/// Bevy [`Event`] emitted on the server when an unreliable message sent to a client has been lost
+pub type MessageLost = crate::shared::events::components::MessageLost<ClientId>;
 
 #[cfg(test)]
 mod tests {
@@ -1,32 +1,48 @@
 use super::*;
-use crate::prelude::CompressionConfig;
 use crate::server::io::transport::{ServerTransportBuilder, ServerTransportBuilderEnum};
+use crate::transport::async_transport::AsyncTransportFactory;
 use crate::transport::channels::Channels;
 use crate::transport::config::SharedIoConfig;
 use crate::transport::dummy::DummyIo;
 use crate::transport::io::IoStats;
-#[cfg(feature = "zstd")]
-use crate::transport::middleware::compression::zstd::compression::ZstdCompressor;
-#[cfg(feature = "zstd")]
-use crate::transport::middleware::compression::zstd::decompression::ZstdDecompressor;
 use crate::transport::middleware::conditioner::LinkConditioner;
-use crate::transport::middleware::PacketReceiverWrapper;
-use crate::transport::udp::UdpSocketBuilder;
+#[cfg(all(feature = "quic", not(target_family = "wasm")))]
+use crate::transport::quic::server::QuicServerSocketBuilder;
+use crate::transport::relay::RelaySocketBuilder;
+#[cfg(all(feature = "tcp", not(target_family = "wasm")))]
+use crate::transport::tcp::server::TcpServerSocketBuilder;
+use crate::transport::udp::{BoundUdpSocketBuilder, UdpSocketBuilder, UdpSocketConfig};
+#[cfg(unix)]
+use crate::transport::unix::UnixServerSocketBuilder;
 #[cfg(all(feature = "websocket", not(target_family = "wasm")))]
 use crate::transport::websocket::server::WebSocketServerSocketBuilder;
 #[cfg(all(feature = "webtransport", not(target_family = "wasm")))]
 use crate::transport::webtransport::server::WebTransportServerSocketBuilder;
-use crate::transport::BoxedReceiver;
 use crate::transport::Transport;
 use bevy::prelude::TypePath;
 use std::net::IpAddr;
-#[cfg(all(feature = "webtransport", not(target_family = "wasm")))]
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::Arc;
+#[cfg(all(
+    any(feature = "webtransport", feature = "quic"),
+    not(target_family = "wasm")
+))]
 use wtransport::Identity;
 
 #[derive(Debug, TypePath)]
 pub enum ServerTransport {
     /// Use a [`UdpSocket`](std::net::UdpSocket)
     UdpSocket(SocketAddr),
+    /// Use a [`UdpSocket`](std::net::UdpSocket) with custom low-level socket options,
+    /// for example to increase the kernel buffers of servers handling many clients
+    UdpSocketWithConfig {
+        local_addr: SocketAddr,
+        config: UdpSocketConfig,
+    },
+    /// Use a [`UdpSocket`](std::net::UdpSocket) that is already bound, for example the one returned
+    /// by [`punch_hole`](crate::transport::nat::punch_hole) so that the NAT mapping is kept
+    BoundUdpSocket(Arc<std::net::UdpSocket>),
     /// Use [`WebTransport`](https://wicg.github.io/web-transport/) as a transport layer
     #[cfg(all(feature = "webtransport", not(target_family = "wasm")))]
     WebTransportServer {
@@ -37,6 +53,35 @@ pub enum ServerTransport {
     /// Use [`WebSocket`](https://developer.mozilla.org/en-US/docs/Web/API/WebSocket) as a transport
     #[cfg(all(feature = "websocket", not(target_family = "wasm")))]
     WebSocketServer { server_addr: SocketAddr },
+    /// Use raw [QUIC](https://www.rfc-editor.org/rfc/rfc9000) connections as a transport.
+    /// Packets are sent as unreliable datagrams.
+    #[cfg(all(feature = "quic", not(target_family = "wasm")))]
+    QuicServer {
+        server_addr: SocketAddr,
+        /// Certificate that will be used for authentication
+        certificate: Identity,
+    },
+    /// Use a TCP stream as a transport, with each packet prefixed by its length.
+    ///
+    /// This can be used on networks that block UDP, but note that reliability is then handled twice
+    /// (by TCP and by lightyear's channels), and a lost packet delays all the packets sent after it.
+    #[cfg(all(feature = "tcp", not(target_family = "wasm")))]
+    TcpServer { server_addr: SocketAddr },
+    /// Receive packets through a [`RelayServer`](crate::transport::relay::RelayServer), by registering
+    /// as the host of the session `session_id`.
+    ///
+    /// This lets clients connect to a server that is behind a NAT, without knowing its address.
+    Relay {
+        local_addr: SocketAddr,
+        relay_addr: SocketAddr,
+        session_id: u64,
+    },
+    /// Use a unix domain datagram socket bound to `path`, for clients running on the same machine
+    /// (for example sidecar bots).
+    #[cfg(unix)]
+    UnixSocket { path: PathBuf },
+    /// Use a user-provided [`AsyncTransport`](crate::transport::async_transport::AsyncTransport)
+    Async(AsyncTransportFactory),
     /// Use a crossbeam_channel as a transport. This is useful for testing.
     /// This is server-only: each tuple corresponds to a different client.
     Channels {
@@ -58,6 +103,16 @@ impl Clone for ServerTransport {
             ServerTransport::UdpSocket(__self_0) => {
                 ServerTransport::UdpSocket(Clone::clone(__self_0))
             }
+            ServerTransport::UdpSocketWithConfig {
+                local_addr: __self_0,
+                config: __self_1,
+            } => ServerTransport::UdpSocketWithConfig {
+                local_addr: Clone::clone(__self_0),
+                config: Clone::clone(__self_1),
+            },
+            ServerTransport::BoundUdpSocket(__self_0) => {
+                ServerTransport::BoundUdpSocket(Clone::clone(__self_0))
+            }
             #[cfg(all(feature = "webtransport", not(target_family = "wasm")))]
             ServerTransport::WebTransportServer {
                 server_addr: __self_0,
@@ -72,6 +127,34 @@ impl Clone for ServerTransport {
             } => ServerTransport::WebSocketServer {
                 server_addr: Clone::clone(__self_0),
             },
+            #[cfg(all(feature = "quic", not(target_family = "wasm")))]
+            ServerTransport::QuicServer {
+                server_addr: __self_0,
+                certificate: __self_1,
+            } => ServerTransport::QuicServer {
+                server_addr: Clone::clone(__self_0),
+                certificate: __self_1.clone_identity(),
+            },
+            #[cfg(all(feature = "tcp", not(target_family = "wasm")))]
+            ServerTransport::TcpServer {
+                server_addr: __self_0,
+            } => ServerTransport::TcpServer {
+                server_addr: Clone::clone(__self_0),
+            },
+            ServerTransport::Relay {
+                local_addr: __self_0,
+                relay_addr: __self_1,
+                session_id: __self_2,
+            } => ServerTransport::Relay {
+                local_addr: Clone::clone(__self_0),
+                relay_addr: Clone::clone(__self_1),
+                session_id: Clone::clone(__self_2),
+            },
+            #[cfg(unix)]
+            ServerTransport::UnixSocket { path: __self_0 } => ServerTransport::UnixSocket {
+                path: Clone::clone(__self_0),
+            },
+            ServerTransport::Async(__self_0) => ServerTransport::Async(Clone::clone(__self_0)),
             ServerTransport::Channels { channels: __self_0 } => ServerTransport::Channels {
                 channels: Clone::clone(__self_0),
             },
@@ -81,10 +164,39 @@ impl Clone for ServerTransport {
 }
 
 impl ServerTransport {
+    /// Returns the SHA-256 digest of the certificate used by the WebTransport server, as a hex string.
+    ///
+    /// Browsers will only accept a self-signed certificate if they are given its digest, so this
+    /// is the value that should be provided to the `certificate_digest` field of
+    /// [`ClientTransport::WebTransportClient`](crate::prelude::client::ClientTransport) on wasm clients.
+    ///
+    /// Returns `None` if the transport is not a WebTransport server.
+    #[cfg(all(feature = "webtransport", not(target_family = "wasm")))]
+    pub fn certificate_digest(&self) -> Option<String> {
+        match self {
+            ServerTransport::WebTransportServer { certificate, .. } => certificate
+                .certificate_chain()
+                .as_slice()
+                .first()
+                .map(|cert| cert.hash().fmt(wtransport::tls::Sha256DigestFmt::DottedHex))
+                .map(|digest| digest.replace(':', "")),
+            _ => None,
+        }
+    }
+
     fn build(self) -> ServerTransportBuilderEnum {
         match self {
             ServerTransport::UdpSocket(addr) => {
-                ServerTransportBuilderEnum::UdpSocket(UdpSocketBuilder { local_addr: addr })
+                ServerTransportBuilderEnum::UdpSocket(UdpSocketBuilder {
+                    local_addr: addr,
+                    config: UdpSocketConfig::default(),
+                })
+            }
+            ServerTransport::UdpSocketWithConfig { local_addr, config } => {
+                ServerTransportBuilderEnum::UdpSocket(UdpSocketBuilder { local_addr, config })
+            }
+            ServerTransport::BoundUdpSocket(socket) => {
+                ServerTransportBuilderEnum::BoundUdpSocket(BoundUdpSocketBuilder { socket })
             }
             #[cfg(all(feature = "webtransport", not(target_family = "wasm")))]
             ServerTransport::WebTransportServer {
@@ -100,6 +212,32 @@ impl ServerTransport {
                     server_addr,
                 })
             }
+            #[cfg(all(feature = "quic", not(target_family = "wasm")))]
+            ServerTransport::QuicServer {
+                server_addr,
+                certificate,
+            } => ServerTransportBuilderEnum::QuicServer(QuicServerSocketBuilder {
+                server_addr,
+                certificate,
+            }),
+            #[cfg(all(feature = "tcp", not(target_family = "wasm")))]
+            ServerTransport::TcpServer { server_addr } => {
+                ServerTransportBuilderEnum::TcpServer(TcpServerSocketBuilder { server_addr })
+            }
+            ServerTransport::Relay {
+                local_addr,
+                relay_addr,
+                session_id,
+            } => ServerTransportBuilderEnum::Relay(RelaySocketBuilder {
+                local_addr,
+                relay_addr,
+                session_id,
+            }),
+            #[cfg(unix)]
+            ServerTransport::UnixSocket { path } => {
+                ServerTransportBuilderEnum::UnixSocket(UnixServerSocketBuilder { path })
+            }
+            ServerTransport::Async(factory) => ServerTransportBuilderEnum::Async(factory.build()),
             ServerTransport::Channels { channels } => {
                 ServerTransportBuilderEnum::Channels(Channels::new(channels))
             }
@@ -118,36 +256,13 @@ impl SharedIoConfig<ServerTransport> {
     pub fn start(self) -> Result<Io> {
         let (transport, state, io_rx, network_tx) = self.transport.build().start()?;
         let local_addr = transport.local_addr();
-        #[allow(unused_mut)]
-        let (mut sender, receiver) = transport.split();
-        #[allow(unused_mut)]
-        let mut receiver: BoxedReceiver = if let Some(conditioner_config) = self.conditioner {
-            let conditioner = LinkConditioner::new(conditioner_config);
-            Box::new(conditioner.wrap(receiver))
-        } else {
-            Box::new(receiver)
-        };
-        match self.compression {
-            CompressionConfig::None => {}
-            #[cfg(feature = "zstd")]
-            CompressionConfig::Zstd { level } => {
-                use crate::transport::middleware::PacketSenderWrapper;
-                let compressor = ZstdCompressor::new(level);
-                sender = Box::new(compressor.wrap(sender));
-                let decompressor = ZstdDecompressor::new();
-                receiver = Box::new(decompressor.wrap(receiver));
-            }
-            #[cfg(feature = "lz4")]
-            CompressionConfig::Lz4 => {
-                use crate::transport::middleware::PacketSenderWrapper;
-                let compressor =
-                    crate::transport::middleware::compression::lz4::Compressor::default();
-                sender = Box::new(compressor.wrap(sender));
-                let decompressor =
-                    crate::transport::middleware::compression::lz4::Decompressor::default();
-                receiver = Box::new(decompressor.wrap(receiver));
-            }
+        let (sender, receiver) = transport.split();
+        // user middlewares are the closest to the transport
+        let mut middlewares = self.middlewares;
+        if let Some(conditioner_config) = self.conditioner {
+            middlewares.push(move || LinkConditioner::<Vec<u8>>::new(conditioner_config.clone()));
         }
+        let (sender, receiver) = middlewares.wrap(sender, receiver);
         Ok(BaseIo {
             local_addr,
             sender,
@@ -1,9 +1,17 @@
 use crate::server::io::{ServerIoEventReceiver, ServerNetworkEventSender};
+use crate::transport::async_transport::{AsyncTransportBuilder, AsyncTransportSocket};
 use crate::transport::channels::Channels;
 use crate::transport::dummy::DummyIo;
 use crate::transport::error::Result;
 use crate::transport::io::IoState;
-use crate::transport::udp::{UdpSocket, UdpSocketBuilder};
+#[cfg(all(feature = "quic", not(target_family = "wasm")))]
+use crate::transport::quic::server::{QuicServerSocket, QuicServerSocketBuilder};
+use crate::transport::relay::{RelaySocket, RelaySocketBuilder};
+#[cfg(all(feature = "tcp", not(target_family = "wasm")))]
+use crate::transport::tcp::server::{TcpServerSocket, TcpServerSocketBuilder};
+use crate::transport::udp::{BoundUdpSocketBuilder, UdpSocket, UdpSocketBuilder};
+#[cfg(unix)]
+use crate::transport::unix::{UnixServerSocketBuilder, UnixTransport};
 #[cfg(all(feature = "websocket", not(target_family = "wasm")))]
 use crate::transport::websocket::server::{WebSocketServerSocket, WebSocketServerSocketBuilder};
 #[cfg(all(feature = "webtransport", not(target_family = "wasm")))]
@@ -28,10 +36,19 @@ pub(crate) trait ServerTransportBuilder: Send + Sync {
 #[enum_dispatch(ServerTransportBuilder)]
 pub(crate) enum ServerTransportBuilderEnum {
     UdpSocket(UdpSocketBuilder),
+    BoundUdpSocket(BoundUdpSocketBuilder),
     #[cfg(all(feature = "webtransport", not(target_family = "wasm")))]
     WebTransportServer(WebTransportServerSocketBuilder),
     #[cfg(all(feature = "websocket", not(target_family = "wasm")))]
     WebSocketServer(WebSocketServerSocketBuilder),
+    #[cfg(all(feature = "quic", not(target_family = "wasm")))]
+    QuicServer(QuicServerSocketBuilder),
+    #[cfg(all(feature = "tcp", not(target_family = "wasm")))]
+    TcpServer(TcpServerSocketBuilder),
+    Relay(RelaySocketBuilder),
+    #[cfg(unix)]
+    UnixSocket(UnixServerSocketBuilder),
+    Async(AsyncTransportBuilder),
     Channels(Channels),
     Dummy(DummyIo),
 }
@@ -44,6 +61,14 @@ pub(crate) enum ServerTransportEnum {
     WebTransportServer(WebTransportServerSocket),
     #[cfg(all(feature = "websocket", not(target_family = "wasm")))]
     WebSocketServer(WebSocketServerSocket),
+    #[cfg(all(feature = "quic", not(target_family = "wasm")))]
+    QuicServer(QuicServerSocket),
+    #[cfg(all(feature = "tcp", not(target_family = "wasm")))]
+    TcpServer(TcpServerSocket),
+    Relay(RelaySocket),
+    #[cfg(unix)]
+    UnixSocket(UnixTransport),
+    Async(AsyncTransportSocket),
     Channels(Channels),
     Dummy(DummyIo),
 }
@@ -1,9 +1,11 @@
 //! Defines the server bevy systems and run conditions
 use crate::connection::server::{IoConfig, NetServer, ServerConnection, ServerConnections};
 use crate::prelude::{
-    is_started, ChannelRegistry, MainSet, MessageRegistry, TickManager, TimeManager,
+    is_started, Channel, ChannelRegistry, ChannelSettings, MainSet, MessageRegistry, TickManager,
+    TimeManager,
 };
 use crate::protocol::component::ComponentRegistry;
+use crate::protocol::hash::ProtocolHash;
 use crate::server::clients::ControlledEntities;
 use crate::server::config::ServerConfig;
 use crate::server::connection::ConnectionManager;
@@ -118,10 +120,22 @@ pub(crate) fn receive(world: &mut World) {
                                                     .try_update(delta.as_secs_f64())
                                                     .map_err(|e| error!("Error updating netcode server: {:?}", e));
                                                 for client_id in netserver.new_connections().iter().copied() {
+                                                    // the ClientId must be unique across all transports, otherwise we would
+                                                    // not know which transport to use to reach the client
+                                                    if let Some(&existing_idx) = netservers.client_server_map.get(&client_id) {
+                                                        if existing_idx != server_idx {
+                                                            error!("Client {client_id:?} is already connected via another transport (server {existing_idx}), rejecting the connection on server {server_idx}");
+                                                            let _ = netserver.disconnect(client_id);
+                                                            continue;
+                                                        }
+                                                    }
                                                     netservers.client_server_map.insert(client_id, server_idx);
                                                     // spawn an entity for the client
                                                     let client_entity = world.spawn((ControlledEntities::default(), Name::new("Client"))).id();
                                                     connection_manager.add(client_id, client_entity);
+                                                    if let Ok(connection) = connection_manager.connection_mut(client_id) {
+                                                        connection.message_manager.set_session_keys(netserver.session_keys(client_id));
+                                                    }
                                                 }
                                                 // handle disconnections
 
@@ -137,14 +151,20 @@ pub(crate) fn receive(world: &mut World) {
                                                 }
                                                 // disconnects because we received a disconnect message
                                                 for client_id in netserver.new_disconnections().iter().copied() {
-                                                    if netservers.client_server_map.remove(&client_id).is_some() {
-                                                        connection_manager.remove(client_id);
-                                                        // NOTE: we don't despawn the entity right away to let the user react to
-                                                        // the disconnect event
-                                                        // TODO: use observers/component_hooks to react automatically on the client despawn?
-                                                        // world.despawn(client_entity);
-                                                    } else {
-                                                        error!("Client disconnected but could not map client_id to the corresponding netserver");
+                                                    match netservers.client_server_map.get(&client_id) {
+                                                        Some(&idx) if idx == server_idx => {
+                                                            netservers.client_server_map.remove(&client_id);
+                                                            connection_manager.remove(client_id);
+                                                            // NOTE: we don't despawn the entity right away to let the user react to
+                                                            // the disconnect event
+                                                            // TODO: use observers/component_hooks to react automatically on the client despawn?
+                                                            // world.despawn(client_entity);
+                                                        }
+                                                        // the connection was rejected because the client was already connected via another transport
+                                                        Some(_) => {}
+                                                        None => {
+                                                            error!("Client disconnected but could not map client_id to the corresponding netserver");
+                                                        }
                                                     }
                                                 };
                                             }
@@ -239,9 +259,11 @@ pub(crate) fn send(
                 .servers
                 .get_mut(netserver_idx)
                 .ok_or(ServerError::ServerConnectionNotFound)?;
-            for packet_byte in connection.send_packets(&time_manager, &tick_manager)? {
+            let payloads = connection.send_packets(&time_manager, &tick_manager)?;
+            for packet_byte in payloads.iter() {
                 netserver.send(packet_byte.as_slice(), *client_id)?;
             }
+            connection.release_payloads(payloads);
             Ok(())
         })
         .unwrap_or_else(|e: ServerError| {
@@ -281,7 +303,10 @@ fn rebuild_server_connections(world: &mut World) {
     world.insert_resource(connection_manager);
 
     // rebuild the server connections and insert them
-    let server_connections = ServerConnections::new(server_config.net);
+    let protocol_hash = ProtocolHash::from_world(world);
+    world.insert_resource(protocol_hash);
+    let mut server_connections = ServerConnections::new(server_config.net);
+    server_connections.set_protocol_hash(protocol_hash.0);
     world.insert_resource(server_connections);
 }
 
@@ -294,6 +319,21 @@ fn on_start(world: &mut World) {
         error!("The server is already started. The server can only be started when it is stopped.");
         return;
     }
+    if world
+        .resource::<ChannelRegistry>()
+        .has_authenticated_channels()
+        && !world
+            .resource::<ServerConfig>()
+            .net
+            .iter()
+            .all(|config| config.provides_session_keys())
+    {
+        error!("Cannot start the server: some channels are authenticated but a server connection does not provide session keys. Only netcode connections support authenticated channels.");
+        world
+            .resource_mut::<NextState<NetworkingState>>()
+            .set(NetworkingState::Stopped);
+        return;
+    }
     rebuild_server_connections(world);
     let _ = world
         .resource_mut::<ServerConnections>()
@@ -312,6 +352,13 @@ pub trait ServerCommands {
     fn start_server(&mut self);
 
     fn stop_server(&mut self);
+
+    /// Register a channel while the app is running.
+    ///
+    /// The id of the channel is sent to the clients, which need to register the same channel
+    /// with [`ClientCommands::register_channel`](crate::client::networking::ClientCommands::register_channel).
+    /// Messages sent on the channel to a client are buffered until that client has registered it.
+    fn register_channel<C: Channel>(&mut self, settings: ChannelSettings);
 }
 
 impl ServerCommands for Commands<'_, '_> {
@@ -322,4 +369,18 @@ impl ServerCommands for Commands<'_, '_> {
     fn stop_server(&mut self) {
         self.insert_resource(NextState::<NetworkingState>(Some(NetworkingState::Stopped)));
     }
+
+    fn register_channel<C: Channel>(&mut self, settings: ChannelSettings) {
+        self.add(move |world: &mut World| {
+            // keep the channel registered if the server gets restarted
+            world
+                .resource_mut::<ChannelRegistry>()
+                .add_runtime_channel::<C>(settings.clone());
+            if let Some(mut connection_manager) = world.get_resource_mut::<ConnectionManager>() {
+                if let Err(e) = connection_manager.register_channel::<C>(settings) {
+                    error!("Could not register channel: {:?}", e);
+                }
+            }
+        });
+    }
 }
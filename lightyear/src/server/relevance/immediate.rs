@@ -25,8 +25,9 @@ fn my_system(
 */
 use crate::prelude::server::ConnectionManager;
 use crate::prelude::{is_started, ClientId};
-use crate::shared::sets::{InternalReplicationSet, ServerMarker};
-use bevy::ecs::entity::EntityHashSet;
+use crate::shared::replication::DespawnReason;
+use crate::shared::sets::{InternalMainSet, InternalReplicationSet, ServerMarker};
+use bevy::ecs::entity::{EntityHashMap, EntityHashSet};
 use bevy::prelude::*;
 use bevy::utils::HashMap;
 use tracing::trace;
@@ -36,8 +37,9 @@ use tracing::trace;
 pub(crate) enum ClientRelevance {
     /// the entity was not replicated to the client, but now is
     Gained,
-    /// the entity was replicated to the client, but not anymore
-    Lost,
+    /// the entity was replicated to the client, but not anymore.
+    /// The reason is sent to the client with the despawn
+    Lost(DespawnReason),
     /// the entity was already replicated to the client, and still is
     Maintained,
 }
@@ -52,7 +54,7 @@ pub(crate) struct CachedNetworkRelevance {
 #[derive(Debug, Default)]
 struct RelevanceEvents {
     gained: HashMap<ClientId, EntityHashSet>,
-    lost: HashMap<ClientId, EntityHashSet>,
+    lost: HashMap<ClientId, EntityHashMap<DespawnReason>>,
 }
 
 /// Resource that manages the network relevance of entities for clients
@@ -80,42 +82,66 @@ impl RelevanceManager {
     }
 
     /// Lost relevance of an entity for a given client
+    ///
+    /// The entity is despawned on the client with [`DespawnReason::LostRelevance`].
     pub fn lose_relevance(&mut self, client: ClientId, entity: Entity) -> &mut Self {
+        self.lose_relevance_with_reason(client, entity, DespawnReason::LostRelevance)
+    }
+
+    /// Lost relevance of an entity for a given client, which despawns the entity on the client with the given `reason`
+    pub(crate) fn lose_relevance_with_reason(
+        &mut self,
+        client: ClientId,
+        entity: Entity,
+        reason: DespawnReason,
+    ) -> &mut Self {
         self.events.gained.entry(client).and_modify(|set| {
             set.remove(&entity);
         });
-        self.events.lost.entry(client).or_default().insert(entity);
+        self.events
+            .lost
+            .entry(client)
+            .or_default()
+            .insert(entity, reason);
         self
     }
 
-    // NOTE: this might not be needed because we drain the event cache every Send update
-    // /// Remove all relevance events for a given client when they disconnect
-    // ///
-    // /// Called to release the memory associated with the client
-    // pub(crate) fn handle_client_disconnection(&mut self, client: ClientId) {
-    //     self.events.gained.remove(&client);
-    //     self.events.lost.remove(&client);
-    // }
+    /// Remove all relevance events for a given client when they disconnect
+    ///
+    /// Called to release the memory associated with the client
+    pub(crate) fn handle_client_disconnection(&mut self, client: ClientId) {
+        self.events.gained.remove(&client);
+        self.events.lost.remove(&client);
+    }
 }
 
 pub(super) mod systems {
     use super::*;
 
     use crate::prelude::NetworkRelevanceMode;
+    use crate::server::events::DisconnectEvent;
 
     use bevy::prelude::DetectChanges;
 
-    // NOTE: this might not be needed because we drain the event cache every Send update
-    // /// Clear the internal room buffers when a client disconnects
-    // pub fn handle_client_disconnect(
-    //     mut manager: ResMut<VisibilityManager>,
-    //     mut disconnect_events: EventReader<DisconnectEvent>,
-    // ) {
-    //     for event in disconnect_events.read() {
-    //         let client_id = event.context();
-    //         manager.handle_client_disconnection(*client_id);
-    //     }
-    // }
+    /// Remove the client from the relevance caches when it disconnects.
+    ///
+    /// Otherwise the entities would still be considered relevant (`Maintained`) if a client
+    /// reconnects with the same [`ClientId`], and they would never be spawned again on that client.
+    pub(super) fn handle_client_disconnect(
+        mut manager: ResMut<RelevanceManager>,
+        mut disconnect_events: EventReader<DisconnectEvent>,
+        mut query: Query<&mut CachedNetworkRelevance>,
+    ) {
+        for event in disconnect_events.read() {
+            let client_id = event.client_id;
+            manager.handle_client_disconnection(client_id);
+            query.iter_mut().for_each(|mut cache| {
+                if cache.clients_cache.contains_key(&client_id) {
+                    cache.clients_cache.remove(&client_id);
+                }
+            });
+        }
+    }
 
     /// If VisibilityMode becomes InterestManagement, add CachedNetworkRelevance to the entity
     /// If VisibilityMode becomes All, remove CachedNetworkRelevance from the entity
@@ -160,13 +186,13 @@ pub(super) mod systems {
         }
         trace!("Relevance events: {:?}", manager.events);
         for (client, mut entities) in manager.events.lost.drain() {
-            entities.drain().for_each(|entity| {
+            entities.drain().for_each(|(entity, reason)| {
                 if let Ok(mut cache) = relevance.get_mut(entity) {
                     // Only lose relevance if the client was visible to the entity
                     // (to avoid multiple despawn messages)
                     if let Some(vis) = cache.clients_cache.get_mut(&client) {
                         trace!("lose relevance for entity {entity:?} and client {client:?}");
-                        *vis = ClientRelevance::Lost;
+                        *vis = ClientRelevance::Lost(reason);
                     }
                 }
             });
@@ -203,7 +229,7 @@ pub(super) mod systems {
                         *relevance = ClientRelevance::Maintained;
                         true
                     }
-                    ClientRelevance::Lost => {
+                    ClientRelevance::Lost(_) => {
                         trace!("remove client {client_id:?} and entity {entity:?} from relevance cache");
                         false
                     }
@@ -234,7 +260,7 @@ pub(super) mod systems {
                                     .clients_cache
                                     .iter()
                                     .filter_map(|(client, relevance)| {
-                                        if *relevance != ClientRelevance::Lost {
+                                        if !matches!(relevance, ClientRelevance::Lost(_)) {
                                             Some(*client)
                                         } else {
                                             None
@@ -299,11 +325,10 @@ impl Plugin for NetworkRelevancePlugin {
             ),
         );
         // SYSTEMS
-        // NOTE: this might not be needed because we drain the event cache every Send update
-        // app.add_systems(
-        //     PreUpdate,
-        //     systems::handle_client_disconnect.after(InternalMainSet::<ServerMarker>::EmitEvents),
-        // );
+        app.add_systems(
+            PreUpdate,
+            systems::handle_client_disconnect.after(InternalMainSet::<ServerMarker>::EmitEvents),
+        );
         app.add_systems(
             PostUpdate,
             (
@@ -321,6 +346,7 @@ impl Plugin for NetworkRelevancePlugin {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::server::events::DisconnectEvent;
     use bevy::ecs::system::RunSystemOnce;
 
     /// Multiple entities gain relevance for a given client
@@ -404,7 +430,7 @@ mod tests {
                 .clients_cache
                 .get(&client)
                 .unwrap(),
-            &ClientRelevance::Lost
+            &ClientRelevance::Lost(DespawnReason::LostRelevance)
         );
         assert_eq!(
             app.world
@@ -435,4 +461,59 @@ mod tests {
             &ClientRelevance::Maintained
         );
     }
+
+    /// A client that disconnects is removed from the relevance caches, so that the entities
+    /// get spawned again if it reconnects with the same id
+    #[test]
+    fn test_relevance_client_disconnect() {
+        let mut app = App::new();
+        app.world.init_resource::<RelevanceManager>();
+        app.add_event::<DisconnectEvent>();
+        let client = ClientId::Netcode(1);
+        let entity = app
+            .world
+            .spawn(CachedNetworkRelevance {
+                clients_cache: HashMap::from_iter([(client, ClientRelevance::Maintained)]),
+            })
+            .id();
+        app.world
+            .resource_mut::<RelevanceManager>()
+            .lose_relevance(client, entity);
+
+        app.world.send_event(DisconnectEvent {
+            client_id: client,
+            entity: Entity::PLACEHOLDER,
+        });
+        app.world.run_system_once(systems::handle_client_disconnect);
+        assert!(app
+            .world
+            .entity(entity)
+            .get::<CachedNetworkRelevance>()
+            .unwrap()
+            .clients_cache
+            .is_empty());
+        assert!(app
+            .world
+            .resource::<RelevanceManager>()
+            .events
+            .lost
+            .is_empty());
+
+        // the client reconnects: the entity is spawned again
+        app.world
+            .resource_mut::<RelevanceManager>()
+            .gain_relevance(client, entity);
+        app.world
+            .run_system_once(systems::update_relevance_from_events);
+        assert_eq!(
+            app.world
+                .entity(entity)
+                .get::<CachedNetworkRelevance>()
+                .unwrap()
+                .clients_cache
+                .get(&client)
+                .unwrap(),
+            &ClientRelevance::Gained
+        );
+    }
 }
@@ -1,4 +1,6 @@
 pub mod immediate;
 
 pub mod error;
+pub mod predicate;
 pub mod room;
+pub mod spatial;
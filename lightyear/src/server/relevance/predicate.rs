@@ -0,0 +1,198 @@
+/*! Network relevance module where the relevance of an entity is decided by game logic
+
+# Relevance predicates
+
+A relevance predicate is a function `(ClientId, EntityRef, &World) -> bool` that returns true if the entity
+should be replicated to the client. It can be used when the relevance depends on the state of the game
+(the team of the players, a stealth ability, the camera frustum of the client, etc.)
+
+- the entities need to use [`NetworkRelevanceMode::InterestManagement`](crate::prelude::NetworkRelevanceMode)
+- the predicates are evaluated for every connected client and every entity that uses interest management,
+  every send interval. An entity is relevant to a client if all the predicates return true.
+- the predicates update the relevance through the [`RelevanceManager`], so they override the relevance that was set by other means
+  (rooms, [`SpatialRelevancePlugin`](super::spatial::SpatialRelevancePlugin), etc.) for the same entities
+
+## Example
+
+```rust,ignore
+use bevy::prelude::*;
+use lightyear::prelude::*;
+use lightyear::prelude::server::*;
+
+#[derive(Component, PartialEq)]
+struct Team(u8);
+
+#[derive(Resource)]
+struct ClientTeams(HashMap<ClientId, Team>);
+
+// the entities are only replicated to the players of the same team
+app.add_relevance_predicate(|client_id, entity, world| {
+    let teams = world.resource::<ClientTeams>();
+    entity.get::<Team>().is_some_and(|team| teams.0.get(&client_id) == Some(team))
+});
+```
+*/
+use bevy::prelude::*;
+
+use crate::connection::id::ClientId;
+use crate::prelude::is_started;
+use crate::server::connection::ConnectionManager;
+use crate::server::relevance::immediate::{
+    CachedNetworkRelevance, ClientRelevance, NetworkRelevanceSet, RelevanceManager,
+};
+use crate::shared::sets::{InternalReplicationSet, ServerMarker};
+
+type Predicate = Box<dyn Fn(ClientId, EntityRef, &World) -> bool + Send + Sync>;
+
+/// Resource that holds the relevance predicates registered with [`AppRelevancePredicateExt::add_relevance_predicate`]
+#[derive(Resource, Default)]
+pub(crate) struct RelevancePredicates {
+    predicates: Vec<Predicate>,
+}
+
+impl RelevancePredicates {
+    fn is_relevant(&self, client_id: ClientId, entity: EntityRef, world: &World) -> bool {
+        self.predicates
+            .iter()
+            .all(|predicate| predicate(client_id, entity, world))
+    }
+}
+
+/// Add a relevance predicate to the server
+pub trait AppRelevancePredicateExt {
+    /// Register a function that decides if an entity is relevant to a client.
+    ///
+    /// Multiple predicates can be registered: the entity is relevant if all of them return true.
+    fn add_relevance_predicate(
+        &mut self,
+        predicate: impl Fn(ClientId, EntityRef, &World) -> bool + Send + Sync + 'static,
+    ) -> &mut Self;
+}
+
+impl AppRelevancePredicateExt for App {
+    fn add_relevance_predicate(
+        &mut self,
+        predicate: impl Fn(ClientId, EntityRef, &World) -> bool + Send + Sync + 'static,
+    ) -> &mut Self {
+        if !self.world.contains_resource::<RelevancePredicates>() {
+            self.init_resource::<RelevancePredicates>();
+            self.add_systems(
+                PostUpdate,
+                update_predicate_relevance
+                    .after(InternalReplicationSet::<ServerMarker>::BeforeBuffer)
+                    .before(NetworkRelevanceSet::UpdateRelevance)
+                    // the relevance can be updated every send_interval
+                    .in_set(InternalReplicationSet::<ServerMarker>::SendMessages)
+                    .run_if(is_started),
+            );
+        }
+        self.world
+            .resource_mut::<RelevancePredicates>()
+            .predicates
+            .push(Box::new(predicate));
+        self
+    }
+}
+
+/// Evaluate the predicates for each client and each entity, and update their relevance
+/// if it does not match the relevance cache
+fn update_predicate_relevance(
+    world: &mut World,
+    query: &mut QueryState<(Entity, &CachedNetworkRelevance)>,
+) {
+    let clients: Vec<ClientId> = world
+        .resource::<ConnectionManager>()
+        .connected_clients()
+        .collect();
+    let mut gained = vec![];
+    let mut lost = vec![];
+    {
+        let world = &*world;
+        let predicates = world.resource::<RelevancePredicates>();
+        for (entity, cache) in query.iter(world) {
+            let entity_ref = world.entity(entity);
+            for client_id in clients.iter() {
+                let is_relevant = cache
+                    .clients_cache
+                    .get(client_id)
+                    .is_some_and(|relevance| !matches!(relevance, ClientRelevance::Lost(_)));
+                match (
+                    is_relevant,
+                    predicates.is_relevant(*client_id, entity_ref, world),
+                ) {
+                    (false, true) => gained.push((*client_id, entity)),
+                    (true, false) => lost.push((*client_id, entity)),
+                    _ => {}
+                }
+            }
+        }
+    }
+    let mut relevance_manager = world.resource_mut::<RelevanceManager>();
+    for (client_id, entity) in gained {
+        relevance_manager.gain_relevance(client_id, entity);
+    }
+    for (client_id, entity) in lost {
+        relevance_manager.lose_relevance(client_id, entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::server::Replicate;
+    use crate::prelude::{client, NetworkRelevanceMode};
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    #[test]
+    fn test_relevance_predicate() {
+        let mut stepper = BevyStepper::default();
+        // only the entities with a positive Component1 are relevant
+        stepper.server_app.add_relevance_predicate(|_, entity, _| {
+            entity.get::<Component1>().is_some_and(|c| c.0 > 0.0)
+        });
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((
+                Component1(-1.0),
+                Replicate {
+                    relevance_mode: NetworkRelevanceMode::InterestManagement,
+                    ..default()
+                },
+            ))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_entity = |stepper: &BevyStepper| {
+            stepper
+                .client_app
+                .world
+                .resource::<client::ConnectionManager>()
+                .replication_receiver
+                .remote_entity_map
+                .get_local(server_entity)
+                .copied()
+        };
+        assert!(client_entity(&stepper).is_none());
+
+        stepper
+            .server_app
+            .world
+            .entity_mut(server_entity)
+            .insert(Component1(1.0));
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_entity = client_entity(&stepper).expect("entity was not replicated to client");
+
+        stepper
+            .server_app
+            .world
+            .entity_mut(server_entity)
+            .insert(Component1(-2.0));
+        stepper.frame_step();
+        stepper.frame_step();
+        assert!(stepper.client_app.world.get_entity(client_entity).is_none());
+    }
+}
@@ -18,6 +18,8 @@ This can be useful for games where you have physical instances of rooms:
 - a server could have multiple lobbies, and each lobby is in its own room
 - a map could be divided into a grid of 2D squares, where each square is its own room
 
+Rooms can be identified by a number, or by a name with [`RoomId::from_name`].
+
 ```rust
 use bevy::prelude::*;
 use lightyear::prelude::*;
@@ -27,6 +29,8 @@ fn room_system(mut manager: ResMut<RoomManager>) {
    // the entity will now be visible to the client
    manager.add_client(ClientId::Netcode(0), RoomId(0));
    manager.add_entity(Entity::PLACEHOLDER, RoomId(0));
+   // rooms can also be named
+   manager.add_client(ClientId::Netcode(0), RoomId::from_name("tavern"));
 }
 ```
 
@@ -40,8 +44,8 @@ it just caches the room metadata to keep track of the relevance of entities.
 use bevy::app::App;
 use bevy::ecs::entity::EntityHash;
 use bevy::prelude::{
-    Entity, IntoSystemConfigs, IntoSystemSetConfigs, Plugin, PostUpdate, PreUpdate,
-    RemovedComponents, ResMut, Resource, SystemSet,
+    Component, DetectChanges, DetectChangesMut, Entity, IntoSystemConfigs, IntoSystemSetConfigs,
+    Plugin, PostUpdate, PreUpdate, Query, Ref, RemovedComponents, Res, ResMut, Resource, SystemSet,
 };
 use bevy::reflect::Reflect;
 use bevy::utils::{HashMap, HashSet};
@@ -53,7 +57,9 @@ use crate::connection::id::ClientId;
 use crate::prelude::is_started;
 
 use crate::server::relevance::immediate::{NetworkRelevanceSet, RelevanceManager};
-use crate::shared::replication::components::DespawnTracker;
+use crate::shared::replication::components::{DespawnTracker, ReplicationTarget};
+use crate::shared::replication::network_target::NetworkTarget;
+use crate::shared::replication::DespawnReason;
 use crate::shared::sets::{InternalMainSet, InternalReplicationSet, ServerMarker};
 
 type EntityHashMap<K, V> = hashbrown::HashMap<K, V, EntityHash>;
@@ -63,6 +69,21 @@ type EntityHashSet<K> = hashbrown::HashSet<K, EntityHash>;
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, Hash, PartialEq, Default, Reflect)]
 pub struct RoomId(pub u64);
 
+impl RoomId {
+    /// Id of the room with the given name.
+    ///
+    /// The id is a hash of the name, so the same name always gives the same room, even across runs.
+    pub fn from_name(name: &str) -> Self {
+        RoomId(seahash::hash(name.as_bytes()))
+    }
+}
+
+impl From<&str> for RoomId {
+    fn from(name: &str) -> Self {
+        RoomId::from_name(name)
+    }
+}
+
 impl From<Entity> for RoomId {
     fn from(value: Entity) -> Self {
         RoomId(value.to_bits())
@@ -120,6 +141,77 @@ pub struct Room {
     pub entities: EntityHashSet<Entity>,
 }
 
+/// Target that is expressed in terms of rooms, and resolved against the clients that are in the rooms.
+///
+/// This can be used to send messages or replicate entities to groups of clients (for example a team) with
+/// [`ConnectionManager::send_message_to_room_target`](crate::prelude::server::ConnectionManager::send_message_to_room_target)
+/// or [`RoomReplicationTarget`].
+///
+/// ```rust
+/// use lightyear::prelude::*;
+/// use lightyear::prelude::server::*;
+///
+/// // the players of the red team and the spectators, except the ones that are muted
+/// let target = RoomTarget::from(RoomId::from_name("red"))
+///     .union(RoomId::from_name("spectators"))
+///     .intersection(RoomTarget::AllExcept(RoomId::from_name("muted")));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoomTarget {
+    /// The clients of the [`NetworkTarget`]
+    Clients(NetworkTarget),
+    /// All the clients that are in the room
+    Room(RoomId),
+    /// All the clients except the ones that are in the room
+    AllExcept(RoomId),
+    /// The clients that are targeted by any of the targets
+    Union(Vec<RoomTarget>),
+    /// The clients that are targeted by all the targets
+    Intersection(Vec<RoomTarget>),
+}
+
+impl From<NetworkTarget> for RoomTarget {
+    fn from(value: NetworkTarget) -> Self {
+        RoomTarget::Clients(value)
+    }
+}
+
+impl From<RoomId> for RoomTarget {
+    fn from(value: RoomId) -> Self {
+        RoomTarget::Room(value)
+    }
+}
+
+impl RoomTarget {
+    /// Target the clients that are targeted by `self` or `other`
+    pub fn union(self, other: impl Into<RoomTarget>) -> Self {
+        match self {
+            RoomTarget::Union(mut targets) => {
+                targets.push(other.into());
+                RoomTarget::Union(targets)
+            }
+            target => RoomTarget::Union(vec![target, other.into()]),
+        }
+    }
+
+    /// Target the clients that are targeted by both `self` and `other`
+    pub fn intersection(self, other: impl Into<RoomTarget>) -> Self {
+        match self {
+            RoomTarget::Intersection(mut targets) => {
+                targets.push(other.into());
+                RoomTarget::Intersection(targets)
+            }
+            target => RoomTarget::Intersection(vec![target, other.into()]),
+        }
+    }
+}
+
+/// Component that sets the [`ReplicationTarget`] of an entity from a [`RoomTarget`].
+///
+/// The replication target is updated when the clients join or leave the rooms.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct RoomReplicationTarget(pub RoomTarget);
+
 /// Manager responsible for handling rooms
 #[derive(Default, Resource)]
 pub struct RoomManager {
@@ -177,6 +269,8 @@ impl Plugin for RoomPlugin {
                 systems::buffer_room_relevance_events
                     .in_set(RoomSystemSets::UpdateReplicationCaches),
                 systems::clean_entity_despawns.in_set(RoomSystemSets::RoomBookkeeping),
+                systems::update_room_replication_target
+                    .in_set(InternalReplicationSet::<ServerMarker>::BeforeBuffer),
             ),
         );
     }
@@ -243,6 +337,40 @@ impl RoomManager {
         self.data.rooms.get(&room_id).unwrap()
     }
 
+    /// Resolve the [`RoomTarget`] into the [`NetworkTarget`] of the clients that are currently in the rooms
+    pub fn resolve(&self, target: &RoomTarget) -> NetworkTarget {
+        let room_clients = |room_id: &RoomId| -> Vec<ClientId> {
+            let mut clients: Vec<ClientId> = self
+                .get_room(*room_id)
+                .map(|room| room.clients.iter().copied().collect())
+                .unwrap_or_default();
+            // keep the order stable so that the resolved targets can be compared
+            clients.sort_by_key(ClientId::to_bits);
+            clients
+        };
+        match target {
+            RoomTarget::Clients(target) => target.clone(),
+            RoomTarget::Room(room_id) => NetworkTarget::from(room_clients(room_id)),
+            RoomTarget::AllExcept(room_id) => NetworkTarget::from_exclude(room_clients(room_id)),
+            RoomTarget::Union(targets) => {
+                targets
+                    .iter()
+                    .fold(NetworkTarget::None, |mut resolved, target| {
+                        resolved.union(&self.resolve(target));
+                        resolved
+                    })
+            }
+            RoomTarget::Intersection(targets) => {
+                targets
+                    .iter()
+                    .fold(NetworkTarget::All, |mut resolved, target| {
+                        resolved.intersection(&self.resolve(target));
+                        resolved
+                    })
+            }
+        }
+    }
+
     fn add_client_internal(&mut self, room_id: RoomId, client_id: ClientId) {
         self.data
             .client_to_rooms
@@ -449,7 +577,11 @@ pub(super) mod systems {
                 let room = room_manager.data.rooms.get(&room_id).unwrap();
                 room.clients.iter().for_each(|client_id| {
                     trace!("entity {entity:?} left room {room:?}. Sending lost relevance to client {client_id:?}");
-                    relevance_manager.lose_relevance(*client_id, entity);
+                    relevance_manager.lose_relevance_with_reason(
+                        *client_id,
+                        entity,
+                        DespawnReason::RoomChanged,
+                    );
                 });
             });
         }
@@ -470,7 +602,11 @@ pub(super) mod systems {
                 let room = room_manager.data.rooms.get(&room_id).unwrap();
                 room.entities.iter().for_each(|entity| {
                     trace!("client {client_id:?} left room {room:?}. Sending lost relevance to entity {entity:?}");
-                    relevance_manager.lose_relevance(client_id, *entity);
+                    relevance_manager.lose_relevance_with_reason(
+                        client_id,
+                        *entity,
+                        DespawnReason::RoomChanged,
+                    );
                 });
             });
         }
@@ -486,6 +622,19 @@ pub(super) mod systems {
         }
     }
 
+    /// Update the [`ReplicationTarget`] of the entities that use a [`RoomReplicationTarget`]
+    pub fn update_room_replication_target(
+        room_manager: Res<RoomManager>,
+        mut query: Query<(Ref<RoomReplicationTarget>, &mut ReplicationTarget)>,
+    ) {
+        for (room_target, mut replication_target) in query.iter_mut() {
+            if room_manager.is_changed() || room_target.is_changed() {
+                let target = room_manager.resolve(&room_target.0);
+                replication_target.set_if_neq(ReplicationTarget { target });
+            }
+        }
+    }
+
     /// Clear out the room metadata for any entity that was ever replicated
     pub fn clean_entity_despawns(
         mut room_manager: ResMut<RoomManager>,
@@ -518,6 +667,86 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_named_room() {
+        let mut manager = RoomManager::default();
+        let client_id = ClientId::Netcode(111);
+        manager.add_client(client_id, RoomId::from_name("tavern"));
+        assert!(manager.has_client_id(client_id, "tavern".into()));
+        assert!(!manager.has_client_id(client_id, "cave".into()));
+    }
+
+    #[test]
+    fn test_resolve_room_target() {
+        let mut manager = RoomManager::default();
+        let (client_1, client_2, client_3) = (
+            ClientId::Netcode(1),
+            ClientId::Netcode(2),
+            ClientId::Netcode(3),
+        );
+        let (red, blue) = (RoomId::from_name("red"), RoomId::from_name("blue"));
+        manager.add_client(client_1, red);
+        manager.add_client(client_2, red);
+        manager.add_client(client_3, blue);
+
+        assert_eq!(
+            manager.resolve(&red.into()),
+            NetworkTarget::Only(vec![client_1, client_2])
+        );
+        assert_eq!(
+            manager.resolve(&RoomTarget::AllExcept(blue)),
+            NetworkTarget::AllExceptSingle(client_3)
+        );
+        let target =
+            manager.resolve(&RoomTarget::from(blue).union(NetworkTarget::Single(client_1)));
+        assert!(target.targets(&client_1));
+        assert!(!target.targets(&client_2));
+        assert!(target.targets(&client_3));
+        let target = manager
+            .resolve(&RoomTarget::from(red).intersection(NetworkTarget::AllExceptSingle(client_1)));
+        assert_eq!(target, NetworkTarget::Single(client_2));
+        // unknown rooms don't contain any client
+        assert_eq!(
+            manager.resolve(&RoomId::from_name("green").into()),
+            NetworkTarget::None
+        );
+    }
+
+    #[test]
+    fn test_room_replication_target() {
+        let mut stepper = BevyStepper::default();
+        let client_id = ClientId::Netcode(111);
+        let room_id = RoomId::from_name("red");
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((Replicate::default(), RoomReplicationTarget(room_id.into())))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_entity = |stepper: &BevyStepper| {
+            stepper
+                .client_app
+                .world
+                .resource::<client::ConnectionManager>()
+                .replication_receiver
+                .remote_entity_map
+                .get_local(server_entity)
+                .copied()
+        };
+        assert!(client_entity(&stepper).is_none());
+
+        // the client joins the room: the entity is replicated to it
+        stepper
+            .server_app
+            .world
+            .resource_mut::<RoomManager>()
+            .add_client(client_id, room_id);
+        stepper.frame_step();
+        stepper.frame_step();
+        assert!(client_entity(&stepper).is_some());
+    }
+
     #[test]
     // client is in a room
     // we add an entity to that room, then we remove it
@@ -668,7 +897,7 @@ mod tests {
                 .get::<CachedNetworkRelevance>()
                 .unwrap()
                 .clients_cache,
-            HashMap::from([(client_id, ClientRelevance::Lost)])
+            HashMap::from([(client_id, ClientRelevance::Lost(DespawnReason::RoomChanged))])
         );
         stepper.frame_step();
         // after bookkeeping, the entity should not have any clients in its replication cache
@@ -691,6 +920,15 @@ mod tests {
                 .len(),
             1
         );
+        // the entity was not despawned on the server, it just stopped being relevant to the client
+        let events = stepper
+            .client_app
+            .world
+            .resource::<Events<EntityDespawnEvent>>();
+        assert_eq!(
+            events.get_reader().read(events).next().unwrap().reason(),
+            DespawnReason::RoomChanged
+        );
         assert!(stepper.client_app.world.get_entity(client_entity).is_none());
     }
 
@@ -837,7 +1075,7 @@ mod tests {
                 .get::<CachedNetworkRelevance>()
                 .unwrap()
                 .clients_cache,
-            HashMap::from([(client_id, ClientRelevance::Lost)])
+            HashMap::from([(client_id, ClientRelevance::Lost(DespawnReason::RoomChanged))])
         );
         stepper.frame_step();
         // after bookkeeping, the entity should not have any clients in its replication cache
@@ -860,6 +1098,15 @@ mod tests {
                 .len(),
             1
         );
+        // the entity was not despawned on the server, it just stopped being relevant to the client
+        let events = stepper
+            .client_app
+            .world
+            .resource::<Events<EntityDespawnEvent>>();
+        assert_eq!(
+            events.get_reader().read(events).next().unwrap().reason(),
+            DespawnReason::RoomChanged
+        );
         assert!(stepper.client_app.world.get_entity(client_entity).is_none());
     }
 
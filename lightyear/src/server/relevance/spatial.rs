@@ -0,0 +1,366 @@
+/*! Distance-based network relevance module, where entities are relevant to the clients that are close to them
+
+# Spatial relevance
+
+The [`SpatialRelevancePlugin`] makes an entity relevant to a client if the entity is within the view radius of the client.
+
+- the entities need to use [`NetworkRelevanceMode::InterestManagement`](crate::prelude::NetworkRelevanceMode) and
+  have a position component that implements [`SpatialPosition`]
+- each client has a viewpoint: an entity with the position component and a [`ClientView`] component (for example the
+  character controlled by the client)
+
+The entities are stored in a grid (the [`SpatialGrid`] resource), so finding the entities that are relevant to a client
+only requires looking at the grid cells around the client instead of going through all the entities.
+The cell size should be of the same order as the view radius of the clients.
+
+## Example
+
+```rust,ignore
+use bevy::prelude::*;
+use lightyear::prelude::*;
+use lightyear::prelude::server::*;
+
+#[derive(Component)]
+struct Position(Vec2);
+
+impl SpatialPosition for Position {
+    fn position(&self) -> Vec2 {
+        self.0
+    }
+}
+
+app.add_plugins(SpatialRelevancePlugin::<Position>::new(100.0));
+
+fn spawn_player(mut commands: Commands, client_id: ClientId) {
+    commands.spawn((
+        Position(Vec2::ZERO),
+        ClientView { client_id, radius: 100.0 },
+        Replicate {
+            relevance_mode: NetworkRelevanceMode::InterestManagement,
+            ..default()
+        },
+    ));
+}
+```
+*/
+use std::marker::PhantomData;
+
+use bevy::app::App;
+use bevy::ecs::entity::{EntityHashMap, EntityHashSet};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use crate::connection::id::ClientId;
+use crate::prelude::is_started;
+use crate::server::connection::ConnectionManager;
+use crate::server::relevance::immediate::{
+    CachedNetworkRelevance, NetworkRelevanceSet, RelevanceManager,
+};
+use crate::shared::replication::components::DespawnTracker;
+use crate::shared::sets::{InternalReplicationSet, ServerMarker};
+
+/// Component that provides the position of an entity in the [`SpatialGrid`]
+///
+/// For 3D games, return the coordinates on the horizontal plane.
+pub trait SpatialPosition: Component {
+    fn position(&self) -> Vec2;
+}
+
+/// Component inserted on the viewpoint of a client.
+///
+/// The entities that are within `radius` of this entity are relevant to the client.
+/// A client can have multiple viewpoints.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Reflect)]
+pub struct ClientView {
+    pub client_id: ClientId,
+    pub radius: f32,
+}
+
+/// Grid that stores the positions of the entities that use interest management
+#[derive(Resource, Debug)]
+pub struct SpatialGrid {
+    cell_size: f32,
+    /// Entities (and their position) in each cell
+    cells: HashMap<IVec2, EntityHashMap<Vec2>>,
+    /// Cell of each entity
+    entity_cells: EntityHashMap<IVec2>,
+    /// Entities that are currently relevant to each client
+    relevant: HashMap<ClientId, EntityHashSet>,
+}
+
+impl SpatialGrid {
+    pub fn new(cell_size: f32) -> Self {
+        assert!(cell_size > 0.0, "the cell size must be positive");
+        Self {
+            cell_size,
+            cells: HashMap::default(),
+            entity_cells: EntityHashMap::default(),
+            relevant: HashMap::default(),
+        }
+    }
+
+    fn cell(&self, position: Vec2) -> IVec2 {
+        (position / self.cell_size).floor().as_ivec2()
+    }
+
+    /// Insert the entity in the grid, or update its position
+    fn update(&mut self, entity: Entity, position: Vec2) {
+        let cell = self.cell(position);
+        if let Some(previous) = self.entity_cells.insert(entity, cell) {
+            if previous != cell {
+                self.remove_from_cell(entity, previous);
+            }
+        }
+        self.cells.entry(cell).or_default().insert(entity, position);
+    }
+
+    /// Remove the entity from the grid
+    fn remove(&mut self, entity: Entity) {
+        if let Some(cell) = self.entity_cells.remove(&entity) {
+            self.remove_from_cell(entity, cell);
+        }
+    }
+
+    fn remove_from_cell(&mut self, entity: Entity, cell: IVec2) {
+        if let Some(entities) = self.cells.get_mut(&cell) {
+            entities.remove(&entity);
+            if entities.is_empty() {
+                self.cells.remove(&cell);
+            }
+        }
+    }
+
+    /// Iterate through the entities of the grid that are within `radius` of `center`
+    pub fn entities_in_radius(
+        &self,
+        center: Vec2,
+        radius: f32,
+    ) -> impl Iterator<Item = Entity> + '_ {
+        let min = self.cell(center - Vec2::splat(radius));
+        let max = self.cell(center + Vec2::splat(radius));
+        (min.x..=max.x)
+            .flat_map(move |x| (min.y..=max.y).map(move |y| IVec2::new(x, y)))
+            .filter_map(move |cell| self.cells.get(&cell))
+            .flat_map(|entities| entities.iter())
+            .filter(move |(_, position)| position.distance_squared(center) <= radius * radius)
+            .map(|(entity, _)| *entity)
+    }
+}
+
+/// Plugin that updates the network relevance of the entities based on their distance to the clients.
+///
+/// `P` is the component that holds the position of the entities.
+pub struct SpatialRelevancePlugin<P> {
+    cell_size: f32,
+    _marker: PhantomData<P>,
+}
+
+impl<P> SpatialRelevancePlugin<P> {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<P: SpatialPosition> Plugin for SpatialRelevancePlugin<P> {
+    fn build(&self, app: &mut App) {
+        // RESOURCES
+        app.insert_resource(SpatialGrid::new(self.cell_size));
+        // SYSTEMS
+        app.add_systems(
+            PostUpdate,
+            (
+                systems::update_grid::<P>,
+                systems::update_spatial_relevance::<P>,
+            )
+                .chain()
+                .after(InternalReplicationSet::<ServerMarker>::BeforeBuffer)
+                .before(NetworkRelevanceSet::UpdateRelevance)
+                // the relevance can be updated every send_interval
+                .in_set(InternalReplicationSet::<ServerMarker>::SendMessages)
+                .run_if(is_started),
+        );
+    }
+}
+
+pub(super) mod systems {
+    use super::*;
+
+    /// Update the position of the entities in the grid
+    pub fn update_grid<P: SpatialPosition>(
+        mut grid: ResMut<SpatialGrid>,
+        query: Query<(Entity, Ref<P>), With<CachedNetworkRelevance>>,
+        mut despawned: RemovedComponents<DespawnTracker>,
+        mut removed: RemovedComponents<CachedNetworkRelevance>,
+        mut removed_position: RemovedComponents<P>,
+    ) {
+        for entity in despawned
+            .read()
+            .chain(removed.read())
+            .chain(removed_position.read())
+        {
+            if !query.contains(entity) {
+                grid.remove(entity);
+            }
+        }
+        for (entity, position) in query.iter() {
+            // only the entities that moved need to be updated
+            if position.is_changed() || !grid.entity_cells.contains_key(&entity) {
+                grid.update(entity, position.position());
+            }
+        }
+    }
+
+    /// Compute the entities that are relevant to each client, and update their relevance
+    /// if they changed since the last update
+    pub fn update_spatial_relevance<P: SpatialPosition>(
+        mut grid: ResMut<SpatialGrid>,
+        mut relevance_manager: ResMut<RelevanceManager>,
+        sender: Res<ConnectionManager>,
+        viewers: Query<(&P, &ClientView)>,
+    ) {
+        let mut relevant: HashMap<ClientId, EntityHashSet> = HashMap::default();
+        for (position, view) in viewers.iter() {
+            if sender.connection(view.client_id).is_err() {
+                continue;
+            }
+            relevant
+                .entry(view.client_id)
+                .or_default()
+                .extend(grid.entities_in_radius(position.position(), view.radius));
+        }
+        for (client_id, previous) in grid.relevant.iter() {
+            let current = relevant.get(client_id);
+            previous
+                .iter()
+                .filter(|entity| !current.is_some_and(|current| current.contains(*entity)))
+                .for_each(|entity| {
+                    relevance_manager.lose_relevance(*client_id, *entity);
+                });
+        }
+        for (client_id, current) in relevant.iter() {
+            let previous = grid.relevant.get(client_id);
+            current
+                .iter()
+                .filter(|entity| !previous.is_some_and(|previous| previous.contains(*entity)))
+                .for_each(|entity| {
+                    relevance_manager.gain_relevance(*client_id, *entity);
+                });
+        }
+        grid.relevant = relevant;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Duration;
+
+    use crate::prelude::server::Replicate;
+    use crate::prelude::{client, NetworkRelevanceMode, SharedConfig, TickConfig};
+    use crate::tests::stepper::{BevyStepper, Step, TEST_CLIENT_ID};
+
+    use super::*;
+
+    #[derive(Component, Debug, PartialEq)]
+    struct Position(Vec2);
+
+    impl SpatialPosition for Position {
+        fn position(&self) -> Vec2 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_entities_in_radius() {
+        let mut grid = SpatialGrid::new(10.0);
+        let close = Entity::from_raw(1);
+        let far = Entity::from_raw(2);
+        grid.update(close, Vec2::new(-3.0, 4.0));
+        grid.update(far, Vec2::new(30.0, 0.0));
+        assert_eq!(
+            grid.entities_in_radius(Vec2::ZERO, 5.0).collect::<Vec<_>>(),
+            vec![close]
+        );
+
+        // move the entity to another cell
+        grid.update(far, Vec2::new(4.0, 0.0));
+        assert_eq!(grid.entities_in_radius(Vec2::ZERO, 5.0).count(), 2);
+        grid.remove(close);
+        assert_eq!(
+            grid.entities_in_radius(Vec2::ZERO, 5.0).collect::<Vec<_>>(),
+            vec![far]
+        );
+        assert!(!grid.cells.contains_key(&IVec2::new(-1, 0)));
+    }
+
+    #[test]
+    fn test_spatial_relevance() {
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            client::ClientConfig::default(),
+            tick_duration,
+        );
+        stepper
+            .server_app
+            .add_plugins(SpatialRelevancePlugin::<Position>::new(10.0));
+        stepper.init();
+        stepper.server_app.world.spawn((
+            Position(Vec2::ZERO),
+            ClientView {
+                client_id: ClientId::Netcode(TEST_CLIENT_ID),
+                radius: 10.0,
+            },
+        ));
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((
+                Position(Vec2::new(50.0, 0.0)),
+                Replicate {
+                    relevance_mode: NetworkRelevanceMode::InterestManagement,
+                    ..default()
+                },
+            ))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_entity = |stepper: &BevyStepper| {
+            stepper
+                .client_app
+                .world
+                .resource::<client::ConnectionManager>()
+                .replication_receiver
+                .remote_entity_map
+                .get_local(server_entity)
+                .copied()
+        };
+        assert!(client_entity(&stepper).is_none());
+
+        // the entity moves within the view radius of the client
+        stepper
+            .server_app
+            .world
+            .entity_mut(server_entity)
+            .insert(Position(Vec2::new(5.0, 5.0)));
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_entity = client_entity(&stepper).expect("entity was not replicated to client");
+
+        // the entity moves out of the view radius of the client
+        stepper
+            .server_app
+            .world
+            .entity_mut(server_entity)
+            .insert(Position(Vec2::new(15.0, 0.0)));
+        stepper.frame_step();
+        stepper.frame_step();
+        assert!(stepper.client_app.world.get_entity(client_entity).is_none());
+    }
+}
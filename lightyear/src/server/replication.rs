@@ -19,6 +19,13 @@ use crate::shared::sets::{InternalMainSet, InternalReplicationSet, ServerMarker}
 pub enum ServerReplicationSet {
     // You can use this SystemSet to add Replicate components to entities received from clients (to rebroadcast them to other clients)
     ClientReplication,
+    /// Runs in `PostUpdate` on the send intervals where some clients receive the initial snapshot of the world.
+    ///
+    /// The entities and replicated resources are synthesized from the current state of the world for these clients,
+    /// instead of replaying the messages that were sent before they connected. Use this SystemSet to send them any
+    /// additional catch-up data (for example the score of the match) to the clients returned by
+    /// [`ConnectionManager::late_joiners`]: the messages are buffered in the same send interval as the snapshot.
+    LateJoin,
 }
 
 pub type ReplicationSet = InternalReplicationSet<ServerMarker>;
@@ -52,22 +59,25 @@ pub(crate) mod receive {
 pub(crate) mod send {
     use super::*;
     use crate::prelude::{
-        is_host_server, ClientId, ComponentRegistry, DisabledComponent, NetworkRelevanceMode,
-        OverrideTargetComponent, ReplicateHierarchy, ReplicationGroup, ShouldBePredicted,
-        TargetEntity, Tick, TickManager, TimeManager,
+        is_host_server, ClientId, ComponentRegistry, DeltaCompression, DisabledComponent,
+        NetworkRelevanceMode, OverrideTargetComponent, ReplicateHierarchy, ReplicationGroup,
+        ShouldBePredicted, TargetEntity, Tick, TickManager, TimeManager,
     };
     use crate::protocol::component::ComponentKind;
     use crate::server::error::ServerError;
     use crate::server::relevance::immediate::{CachedNetworkRelevance, ClientRelevance};
     use crate::shared::replication::archetypes::{get_erased_component, ReplicatedArchetypes};
+    use crate::shared::replication::authority::AuthorityPeer;
     use crate::shared::replication::components::{
-        Controlled, DespawnTracker, Replicating, ReplicationGroupId, ReplicationTarget,
-        ShouldBeInterpolated,
+        Controlled, DespawnTracker, PauseReplication, ReplicateOnce, Replicating,
+        ReplicationGroupId, ReplicationRate, ReplicationTarget, ShouldBeInterpolated,
     };
     use crate::shared::replication::network_target::NetworkTarget;
+    use crate::shared::replication::prefab::{Prefab, PrefabRegistry};
+    use crate::shared::replication::DespawnReason;
     use crate::shared::replication::ReplicationSend;
     use bevy::ecs::component::ComponentTicks;
-    use bevy::ecs::entity::Entities;
+    use bevy::ecs::entity::{Entities, EntityHashMap};
     use bevy::ecs::system::SystemChangeTick;
     use bevy::ptr::Ptr;
 
@@ -104,6 +114,17 @@ pub(crate) mod send {
                     PostUpdate,
                     InternalReplicationSet::<ServerMarker>::All.run_if(is_started),
                 )
+                .configure_sets(
+                    PostUpdate,
+                    ServerReplicationSet::LateJoin
+                        .in_set(InternalReplicationSet::<ServerMarker>::SendMessages)
+                        .in_set(InternalReplicationSet::<ServerMarker>::All)
+                        .after(InternalReplicationSet::<ServerMarker>::BeforeBuffer)
+                        .before(InternalReplicationSet::<ServerMarker>::AfterBuffer)
+                        .run_if(|connection_manager: Res<ConnectionManager>| {
+                            !connection_manager.new_clients.is_empty()
+                        }),
+                )
                 // SYSTEMS
                 .add_systems(
                     PostUpdate,
@@ -125,6 +146,9 @@ pub(crate) mod send {
                     // NOTE: we make sure to update the replicate_cache before we make use of it in `send_entity_despawn`
                     handle_replicating_remove
                         .in_set(InternalReplicationSet::<ServerMarker>::BeforeBuffer),
+                    // NOTE: the removal of PauseReplication is also only visible for 1 frame
+                    handle_replication_resume
+                        .in_set(InternalReplicationSet::<ServerMarker>::BeforeBuffer),
                     // TODO: putting it here means we might miss entities that are spawned and despawned within the send_interval? bug or feature?
                     //  be careful that newly_connected_client is cleared every send_interval, not every frame.
                     replicate
@@ -132,6 +156,8 @@ pub(crate) mod send {
                         .in_set(InternalReplicationSet::<ServerMarker>::BufferComponentUpdates),
                     replicate_entity_local_despawn
                         .in_set(InternalReplicationSet::<ServerMarker>::BufferDespawnsAndRemovals),
+                    update_replication_group_priority
+                        .in_set(InternalReplicationSet::<ServerMarker>::BeforeBuffer),
                     (
                         handle_replicating_add,
                         handle_replication_target_update,
@@ -252,6 +278,7 @@ pub(crate) mod send {
         //  should be sent with the same frequency!
         // clear the list of newly connected clients
         connection_manager.new_clients.clear();
+        connection_manager.resumed_entities.clear();
     }
 
     /// In HostServer mode, we will add the Predicted/Interpolated components to the server entities
@@ -306,6 +333,8 @@ pub(crate) mod send {
         pub(crate) network_relevance_mode: NetworkRelevanceMode,
         /// If mode = Room, the list of clients that could see the entity
         pub(crate) replication_clients_cache: Vec<ClientId>,
+        /// The reason sent to the clients when the entity is despawned
+        pub(crate) despawn_reason: DespawnReason,
     }
 
     /// For every entity that removes their ReplicationTarget component but are not despawned, remove the component
@@ -326,6 +355,20 @@ pub(crate) mod send {
         }
     }
 
+    /// Keep track of the entities whose replication was resumed (their [`PauseReplication`] component was removed),
+    /// so that all their components are sent again on the next send_interval
+    pub(crate) fn handle_replication_resume(
+        mut sender: ResMut<ConnectionManager>,
+        mut query: RemovedComponents<PauseReplication>,
+        replicating: Query<(), (With<Replicating>, Without<PauseReplication>)>,
+    ) {
+        for entity in query.read() {
+            if replicating.contains(entity) {
+                sender.resumed_entities.push(entity);
+            }
+        }
+    }
+
     /// This system does all the additional bookkeeping required after [`Replicating`] has been added:
     /// - adds DespawnTracker to each entity that was ever replicated, so that we can track when they are despawned
     /// (we have a distinction between removing Replicating, which just stops replication; and despawning the entity)
@@ -354,6 +397,7 @@ pub(crate) mod send {
                 replication_group: group.clone(),
                 network_relevance_mode: *visibility_mode,
                 replication_clients_cache: vec![],
+                despawn_reason: DespawnReason::Despawned,
             };
             sender
                 .replicate_component_cache
@@ -373,6 +417,7 @@ pub(crate) mod send {
 
         let mut sender = std::mem::take(&mut *set.p1());
         let world = set.p0();
+        let prefab_registry = world.get_resource::<PrefabRegistry>();
 
         // 2. go through all the archetypes that should be replicated
         for replicated_archetype in replicated_archetypes.archetypes.iter() {
@@ -408,6 +453,8 @@ pub(crate) mod send {
                 let sync_target = entity_ref.get::<SyncTarget>();
                 let target_entity = entity_ref.get::<TargetEntity>();
                 let controlled_by = entity_ref.get::<ControlledBy>();
+                let authority = entity_ref.get::<AuthorityPeer>();
+                let prefab = entity_ref.get::<Prefab>();
                 // SAFETY: we know that the entity has the ReplicationTarget component
                 // because the archetype is in replicated_archetypes
                 let replication_target =
@@ -455,11 +502,54 @@ pub(crate) mod send {
                     &system_ticks,
                 );
 
+                // the components of paused entities are not replicated
+                if entity_ref.contains::<PauseReplication>() {
+                    continue;
+                }
+                let resumed = sender.resumed_entities.contains(&entity.id());
+
                 // If the group is not set to send, skip sending updates for this entity
-                if group.is_some_and(|g| !g.should_send) {
+                if !resumed && group.is_some_and(|g| !g.should_send) {
                     continue;
                 }
 
+                // true if some clients need to receive all the components of the entity
+                let needs_inserts = resumed
+                    || replication_target.is_changed()
+                    || !sender.new_clients.is_empty()
+                    || visibility.is_some_and(|v| {
+                        v.clients_cache
+                            .values()
+                            .any(|r| matches!(r, ClientRelevance::Gained))
+                    });
+
+                // entities that are replicated once are only scanned when they need to be sent to new clients,
+                // or when they were marked as dirty
+                let replicate_once_dirty =
+                    entity_ref.get_change_ticks::<ReplicateOnce>().map(|ticks| {
+                        ticks.is_changed(system_ticks.last_run(), system_ticks.this_run())
+                    });
+                if replicate_once_dirty == Some(false) && !needs_inserts {
+                    continue;
+                }
+                let replicate_once_entity = replicate_once_dirty == Some(false);
+                // entities with a slower replication rate only send their updates when they are sampled
+                let send_updates = entity_ref
+                    .get::<ReplicationRate>()
+                    .map_or(true, |rate| rate.should_send);
+
+                // the components that did not change since the last update that was sent to every client
+                // can be skipped without looking at each client
+                let oldest_send_tick = if needs_inserts {
+                    None
+                } else {
+                    sender.oldest_send_tick(
+                        group_id,
+                        &replication_target.target,
+                        system_ticks.this_run(),
+                    )
+                };
+
                 // d. all components that were added or changed
                 for replicated_component in replicated_archetype.components.iter() {
                     let (data, component_ticks) = unsafe {
@@ -471,6 +561,17 @@ pub(crate) mod send {
                             replicated_component.id,
                         )
                     };
+                    if replicated_component.override_target.is_none()
+                        && !component_ticks
+                            .is_added(system_ticks.last_run(), system_ticks.this_run())
+                        && oldest_send_tick.is_some_and(|send_tick| {
+                            !component_ticks
+                                .last_changed_tick()
+                                .is_newer_than(send_tick, system_ticks.this_run())
+                        })
+                    {
+                        continue;
+                    }
                     let override_target = replicated_component.override_target.and_then(|id| {
                         entity_ref
                             .get_by_id(id)
@@ -478,6 +579,8 @@ pub(crate) mod send {
                             // the OverrideTarget<C> component has the same memory layout as NetworkTarget
                             .map(|ptr| unsafe { ptr.deref::<NetworkTarget>() })
                     });
+                    // the client inserts the components provided by the prefab itself
+                    let prefab = prefab.zip(prefab_registry);
 
                     replicate_component_updates(
                         tick_manager.tick(),
@@ -491,8 +594,12 @@ pub(crate) mod send {
                         group_id,
                         visibility,
                         replicated_component.delta_compression,
-                        replicated_component.replicate_once,
+                        replicated_component.replicate_once || replicate_once_entity,
                         override_target,
+                        authority,
+                        prefab,
+                        send_updates,
+                        resumed,
                         &system_ticks,
                         &mut sender,
                     );
@@ -505,6 +612,31 @@ pub(crate) mod send {
         *set.p1() = sender;
     }
 
+    /// Propagate the changes of the [`ReplicationGroup`] priority to the clients that the group is replicated to.
+    ///
+    /// The priority is already set when the entity gets spawned on a client, so we only handle later changes.
+    pub(crate) fn update_replication_group_priority(
+        mut sender: ResMut<ConnectionManager>,
+        query: Query<(Entity, Ref<ReplicationGroup>), With<ReplicationTarget>>,
+    ) {
+        for (entity, group) in query.iter() {
+            if !group.is_changed() || group.is_added() {
+                continue;
+            }
+            let group_id = group.group_id(Some(entity));
+            let priority = group.priority();
+            for connection in sender.connections.values_mut() {
+                if let Some(channel) = connection
+                    .replication_sender
+                    .group_channels
+                    .get_mut(&group_id)
+                {
+                    channel.base_priority = priority;
+                }
+            }
+        }
+    }
+
     /// Send entity spawn replication messages to clients
     /// Also handles:
     /// - newly_connected_clients should receive the entity spawn message even if the entity was not just spawned
@@ -541,7 +673,7 @@ pub(crate) mod send {
                                     );
                                     return Some(*client_id);
                                 }
-                                ClientRelevance::Lost => {}
+                                ClientRelevance::Lost(_) => {}
                                 ClientRelevance::Maintained => {
                                     // only try to replicate if the replicate component was just added
                                     if replication_target.is_added() {
@@ -688,6 +820,7 @@ pub(crate) mod send {
                         entity,
                         replicate_cache.replication_group.group_id(Some(entity)),
                         network_target,
+                        replicate_cache.despawn_reason,
                     )
                     // TODO: bubble up errors to user via ConnectionEvents?
                     .inspect_err(|e| {
@@ -707,42 +840,50 @@ pub(crate) mod send {
         visibility: Option<&CachedNetworkRelevance>,
         sender: &mut ConnectionManager,
     ) {
-        // 1. send despawn for clients that lost visibility
-        let mut target: NetworkTarget = match visibility {
-            Some(visibility) => {
-                visibility
-                    .clients_cache
-                    .iter()
-                    .filter_map(|(client_id, visibility)| {
-                        if replication_target.target.targets(client_id)
-                            && matches!(visibility, ClientRelevance::Lost) {
-                            debug!(
-                                "sending entity despawn for entity: {:?} because ClientVisibility::Lost",
-                                entity
-                            );
-                            return Some(*client_id);
+        // 1. send despawn for clients that lost visibility, grouped by the reason of the loss
+        let mut lost_relevance: Vec<(DespawnReason, Vec<ClientId>)> = vec![];
+        if let Some(visibility) = visibility {
+            for (client_id, visibility) in visibility.clients_cache.iter() {
+                if let ClientRelevance::Lost(reason) = visibility {
+                    if replication_target.target.targets(client_id) {
+                        debug!(
+                            "sending entity despawn for entity: {:?} because ClientVisibility::Lost",
+                            entity
+                        );
+                        match lost_relevance.iter_mut().find(|(r, _)| r == reason) {
+                            Some((_, clients)) => clients.push(*client_id),
+                            None => lost_relevance.push((*reason, vec![*client_id])),
                         }
-                        None
-                    }).collect()
-            }
-            None => {
-                NetworkTarget::None
+                    }
+                }
             }
-        };
+        }
         // 2. if the replication target changed, find the clients that were removed in the new replication target
+        let mut target_changed = NetworkTarget::None;
         if replication_target.is_changed() && !replication_target.is_added() {
             if let Some(cache) = sender.replicate_component_cache.get_mut(&entity) {
-                let mut new_despawn = cache.replication_target.clone();
-                new_despawn.exclude(&replication_target.target);
-                target.union(&new_despawn);
+                target_changed = cache.replication_target.clone();
+                target_changed.exclude(&replication_target.target);
+                for (_, clients) in &lost_relevance {
+                    target_changed.exclude(&NetworkTarget::Only(clients.clone()));
+                }
             }
         }
-        if !target.is_empty() {
-            let _ = sender
-                .prepare_entity_despawn(entity, group_id, target)
-                .inspect_err(|e| {
-                    error!("error sending entity despawn: {:?}", e);
-                });
+        for (target, reason) in lost_relevance
+            .into_iter()
+            .map(|(reason, clients)| (NetworkTarget::Only(clients), reason))
+            .chain(std::iter::once((
+                target_changed,
+                DespawnReason::TargetChanged,
+            )))
+        {
+            if !target.is_empty() {
+                let _ = sender
+                    .prepare_entity_despawn(entity, group_id, target, reason)
+                    .inspect_err(|e| {
+                        error!("error sending entity despawn: {:?}", e);
+                    });
+            }
         }
     }
 
@@ -772,6 +913,10 @@ pub(crate) mod send {
         delta_compression: bool,
         replicate_once: bool,
         override_target: Option<&NetworkTarget>,
+        authority: Option<&AuthorityPeer>,
+        prefab: Option<(&Prefab, &PrefabRegistry)>,
+        send_updates: bool,
+        resumed: bool,
         system_ticks: &SystemChangeTick,
         sender: &mut ConnectionManager,
     ) {
@@ -780,89 +925,115 @@ pub(crate) mod send {
         let target = override_target.map_or(&replication_target.target, |override_target| {
             override_target
         });
-        let (insert_target, mut update_target): (NetworkTarget, NetworkTarget) = match visibility {
-            Some(visibility) => {
-                let mut insert_clients = vec![];
-                let mut update_clients = vec![];
-                visibility
-                    .clients_cache
-                    .iter()
-                    .for_each(|(client_id, visibility)| {
-                        if target.targets(client_id) {
-                            match visibility {
-                                ClientRelevance::Gained => {
-                                    insert_clients.push(*client_id);
-                                }
-                                ClientRelevance::Lost => {}
-                                ClientRelevance::Maintained => {
-                                    // send a component_insert for components that were newly added
-                                    if component_ticks
-                                        .is_added(system_ticks.last_run(), system_ticks.this_run())
-                                    {
+        let (mut insert_target, mut update_target): (NetworkTarget, NetworkTarget) =
+            match visibility {
+                Some(visibility) => {
+                    let mut insert_clients = vec![];
+                    let mut update_clients = vec![];
+                    visibility
+                        .clients_cache
+                        .iter()
+                        .for_each(|(client_id, visibility)| {
+                            if target.targets(client_id) {
+                                match visibility {
+                                    ClientRelevance::Gained => {
                                         insert_clients.push(*client_id);
-                                    } else {
-                                        // for components that were not newly added, only send as updates
-                                        if replicate_once {
-                                            // we can exit the function immediately because we know we don't want to replicate
-                                            // to any client
-                                            return;
+                                    }
+                                    ClientRelevance::Lost(_) => {}
+                                    ClientRelevance::Maintained => {
+                                        // send a component_insert for components that were newly added,
+                                        // or for all components if the replication of the entity was resumed
+                                        if resumed
+                                            || component_ticks.is_added(
+                                                system_ticks.last_run(),
+                                                system_ticks.this_run(),
+                                            )
+                                        {
+                                            insert_clients.push(*client_id);
+                                        } else {
+                                            // for components that were not newly added, only send as updates
+                                            if replicate_once {
+                                                // we can exit the function immediately because we know we don't want to replicate
+                                                // to any client
+                                                return;
+                                            }
+                                            update_clients.push(*client_id);
                                         }
-                                        update_clients.push(*client_id);
                                     }
                                 }
                             }
-                        }
-                    });
-                (
-                    NetworkTarget::from(insert_clients),
-                    NetworkTarget::from(update_clients),
-                )
-            }
-            None => {
-                let (mut insert_target, mut update_target) =
-                    (NetworkTarget::None, NetworkTarget::None);
-
-                // send a component_insert for components that were newly added
-                // or if replicate was newly added.
-                // TODO: ideally what we should be checking is: is the component newly added
-                //  for the client we are sending to?
-                //  Otherwise another solution would be to also insert the component on ComponentUpdate if it's missing
-                //  Or should we just have ComponentInsert and ComponentUpdate be the same thing? Or we check
-                //  on the receiver's entity world mut to know if we emit a ComponentInsert or a ComponentUpdate?
-                if component_ticks.is_added(system_ticks.last_run(), system_ticks.this_run())
-                    || replication_target.is_added()
-                {
-                    trace!("component is added or replication_target is added");
-                    insert_target.union(target);
-                } else {
-                    // do not send updates for these components, only inserts/removes
-                    if replicate_once {
-                        trace!(?entity,
+                        });
+                    (
+                        NetworkTarget::from(insert_clients),
+                        NetworkTarget::from(update_clients),
+                    )
+                }
+                None => {
+                    let (mut insert_target, mut update_target) =
+                        (NetworkTarget::None, NetworkTarget::None);
+
+                    // send a component_insert for components that were newly added
+                    // or if replicate was newly added.
+                    // TODO: ideally what we should be checking is: is the component newly added
+                    //  for the client we are sending to?
+                    //  Otherwise another solution would be to also insert the component on ComponentUpdate if it's missing
+                    //  Or should we just have ComponentInsert and ComponentUpdate be the same thing? Or we check
+                    //  on the receiver's entity world mut to know if we emit a ComponentInsert or a ComponentUpdate?
+                    if component_ticks.is_added(system_ticks.last_run(), system_ticks.this_run())
+                        || replication_target.is_added()
+                        || resumed
+                    {
+                        trace!("component is added or replication_target is added");
+                        insert_target.union(target);
+                    } else {
+                        // do not send updates for these components, only inserts/removes
+                        if replicate_once {
+                            trace!(?entity,
                                 "not replicating updates for {:?} because it is marked as replicate_once",
                                 "COMPONENT_KIND"
                             );
-                        return;
+                            return;
+                        }
+                        // otherwise send an update for all components that changed since the
+                        // last update we have ack-ed
+                        update_target.union(target);
                     }
-                    // otherwise send an update for all components that changed since the
-                    // last update we have ack-ed
-                    update_target.union(target);
-                }
 
-                let new_connected_clients = sender.new_connected_clients();
-                // replicate all components to newly connected clients
-                if !new_connected_clients.is_empty() {
-                    // replicate to the newly connected clients that match our target
-                    let mut new_connected_target = NetworkTarget::Only(new_connected_clients);
-                    new_connected_target.intersection(target);
-                    debug!(?entity, target = ?new_connected_target, "Replicate to newly connected clients");
-                    insert_target.union(&new_connected_target);
+                    let new_connected_clients = sender.new_connected_clients();
+                    // replicate all components to newly connected clients
+                    if !new_connected_clients.is_empty() {
+                        // replicate to the newly connected clients that match our target
+                        let mut new_connected_target = NetworkTarget::Only(new_connected_clients);
+                        new_connected_target.intersection(target);
+                        debug!(?entity, target = ?new_connected_target, "Replicate to newly connected clients");
+                        insert_target.union(&new_connected_target);
+                    }
+                    (insert_target, update_target)
                 }
-                (insert_target, update_target)
-            }
-        };
+            };
 
         // do not send a component as both update and insert
         update_target.exclude(&insert_target);
+        // the components that still have the value set by the prefab are inserted by the client itself
+        if !insert_target.is_empty()
+            && prefab.is_some_and(|(prefab, registry)| {
+                registry.has_default_value(
+                    prefab,
+                    component_kind,
+                    component_data,
+                    component_registry,
+                )
+            })
+        {
+            insert_target = NetworkTarget::None;
+        }
+        if !send_updates {
+            update_target = NetworkTarget::None;
+        }
+        // the client with authority over the entity is the one sending us the updates
+        if let Some(AuthorityPeer::Client(client_id)) = authority {
+            update_target.exclude(&NetworkTarget::Single(*client_id));
+        }
 
         if !insert_target.is_empty() || !update_target.is_empty() {
             if !insert_target.is_empty() {
@@ -904,7 +1075,6 @@ pub(crate) mod send {
         }
     }
 
-    // TODO: do removals!
     /// This system sends updates for all components that were removed
     pub(crate) fn send_component_removed<C: Component>(
         registry: Res<ComponentRegistry>,
@@ -942,33 +1112,154 @@ pub(crate) mod send {
                             .clients_cache
                             .iter()
                             .filter_map(|(client_id, visibility)| {
-                                if base_target.targets(client_id) {
-                                    // TODO: maybe send no matter the vis?
-                                    if matches!(visibility, ClientRelevance::Maintained) {
-                                        // TODO: USE THE CUSTOM REPLICATE TARGET FOR THIS COMPONENT IF PRESENT!
-                                        return Some(*client_id);
-                                    }
+                                // the entity is not spawned on clients that just gained (or lost) relevance
+                                if base_target.targets(client_id)
+                                    && matches!(visibility, ClientRelevance::Maintained)
+                                {
+                                    return Some(*client_id);
                                 };
                                 None
                             })
                             .collect()
                     }
-                    None => {
-                        trace!("sending component remove!");
-                        // TODO: USE THE CUSTOM REPLICATE TARGET FOR THIS COMPONENT IF PRESENT!
-                        base_target.clone()
-                    }
+                    None => base_target.clone(),
                 };
                 if target.is_empty() {
                     return;
                 }
-                let group_id = group.group_id(Some(entity));
                 debug!(?entity, ?kind, "Sending RemoveComponent");
                 let _ = sender.prepare_component_remove(entity, kind, group, target);
             }
         })
     }
 
+    /// When the [`OverrideTargetComponent<C>`] of an entity is added, changed or removed, the component is inserted
+    /// on the clients that were added to the target, and removed from the clients that are not part of the target anymore.
+    ///
+    /// (otherwise the clients would only get the component the next time it changes, and the clients that were removed
+    /// from the target would keep a stale copy of the component)
+    pub(crate) fn send_override_target_update<C: Component>(
+        registry: Res<ComponentRegistry>,
+        tick_manager: Res<TickManager>,
+        system_ticks: SystemChangeTick,
+        // the previous override target of each entity
+        mut previous_targets: Local<EntityHashMap<NetworkTarget>>,
+        override_query: Query<(Entity, Ref<OverrideTargetComponent<C>>), With<Replicating>>,
+        query: Query<
+            (
+                Ref<C>,
+                Ref<ReplicationTarget>,
+                &ReplicationGroup,
+                Option<&SyncTarget>,
+                Option<&CachedNetworkRelevance>,
+                Has<DeltaCompression<C>>,
+            ),
+            (With<Replicating>, Without<DisabledComponent<C>>),
+        >,
+        mut removed: RemovedComponents<OverrideTargetComponent<C>>,
+        mut sender: ResMut<ConnectionManager>,
+    ) {
+        let mut updates = vec![];
+        for (entity, override_target) in override_query.iter() {
+            if !override_target.is_changed() {
+                continue;
+            }
+            let previous = previous_targets.insert(entity, override_target.target.clone());
+            let previous = match previous {
+                Some(previous) => previous,
+                None => {
+                    // before the override was added, the component was replicated to the entity's replication target
+                    let Ok((component, replication_target, ..)) = query.get(entity) else {
+                        continue;
+                    };
+                    // the component was not replicated yet, so there is nothing to update
+                    if component.is_added() || replication_target.is_added() {
+                        continue;
+                    }
+                    replication_target.target.clone()
+                }
+            };
+            updates.push((entity, previous, override_target.target.clone()));
+        }
+        // if the override is removed, the component is replicated to the entity's replication target again
+        for entity in removed.read() {
+            let Some(previous) = previous_targets.remove(&entity) else {
+                continue;
+            };
+            if let Ok((_, replication_target, ..)) = query.get(entity) {
+                updates.push((entity, previous, replication_target.target.clone()));
+            }
+        }
+
+        for (entity, previous, new) in updates {
+            let Ok((
+                component,
+                replication_target,
+                group,
+                sync_target,
+                relevance,
+                delta_compression,
+            )) = query.get(entity)
+            else {
+                continue;
+            };
+            // only update the clients that the entity is already replicated to
+            let mut replicated_to = replication_target.target.clone();
+            if let Some(relevance) = relevance {
+                replicated_to.intersection(&NetworkTarget::Only(
+                    relevance
+                        .clients_cache
+                        .iter()
+                        .filter(|(_, relevance)| matches!(relevance, ClientRelevance::Maintained))
+                        .map(|(client_id, _)| *client_id)
+                        .collect(),
+                ));
+            }
+            let mut insert_target = new.clone();
+            insert_target.exclude(&previous);
+            insert_target.intersection(&replicated_to);
+            let mut remove_target = previous;
+            remove_target.exclude(&new);
+            remove_target.intersection(&replicated_to);
+
+            if !insert_target.is_empty() {
+                debug!(
+                    ?entity,
+                    ?insert_target,
+                    "Override target changed: sending InsertComponent"
+                );
+                let _ = sender
+                    .prepare_component_insert(
+                        entity,
+                        ComponentKind::of::<C>(),
+                        Ptr::from(component.into_inner()),
+                        registry.as_ref(),
+                        sync_target.map(|sync_target| &sync_target.prediction),
+                        group.group_id(Some(entity)),
+                        insert_target,
+                        delta_compression,
+                        tick_manager.tick(),
+                        system_ticks.this_run(),
+                    )
+                    .inspect_err(|e| {
+                        error!("error sending component insert: {:?}", e);
+                    });
+            }
+            if !remove_target.is_empty() {
+                debug!(
+                    ?entity,
+                    ?remove_target,
+                    "Override target changed: sending RemoveComponent"
+                );
+                let _ = sender
+                    .prepare_component_remove(entity, registry.net_id::<C>(), group, remove_target)
+                    .inspect_err(|e| {
+                        error!("error sending component remove: {:?}", e);
+                    });
+            }
+        }
+    }
+
     /// Update the replication_target in the cache when the ReplicationTarget component changes
     pub(crate) fn handle_replication_target_update(
         mut sender: ResMut<ConnectionManager>,
@@ -982,7 +1273,9 @@ pub(crate) mod send {
         >,
     ) {
         for (entity, replication_target) in target_query.iter() {
-            if replication_target.is_changed() && !replication_target.is_added() {
+            // the target can also change in the frame where it is added (for example when it is resolved
+            // from a RoomReplicationTarget), after the cache was created in PreUpdate
+            if replication_target.is_changed() {
                 if let Some(replicate_cache) = sender.replicate_component_cache.get_mut(&entity) {
                     replicate_cache.replication_target = replication_target.target.clone();
                 }
@@ -997,7 +1290,10 @@ pub(crate) mod send {
                 // NOTE: we need to run `send_component_removed` once per frame (and not once per send_interval)
                 //  because the RemovedComponents Events are present only for 1 frame and we might miss them if we don't run this every frame
                 //  It is ok to run it every frame because it creates at most one message per despawn
-                send_component_removed::<C>
+                (
+                    send_component_removed::<C>,
+                    send_override_target_update::<C>,
+                )
                     .in_set(InternalReplicationSet::<ServerMarker>::BufferDespawnsAndRemovals),
                 // // NOTE: we run this system once every `send_interval` because we don't want to send too many Update messages
                 // //  and use up all the bandwidth
@@ -1011,12 +1307,15 @@ pub(crate) mod send {
     mod tests {
         use super::*;
         use crate::client::events::ComponentUpdateEvent;
+        use crate::prelude::client::ClientConfig;
         use crate::prelude::client::Confirmed;
         use crate::prelude::server::{ControlledBy, NetConfig, RelevanceManager, Replicate};
         use crate::prelude::{
             client, DeltaCompression, LinkConditionerConfig, ReplicateOnceComponent, Replicated,
+            SharedConfig, TickConfig,
         };
         use crate::server::replication::send::SyncTarget;
+        use crate::shared::events::components::MessageEvent;
         use crate::shared::replication::components::{Controlled, ReplicationGroupId};
         use crate::shared::replication::delta::DeltaComponentHistory;
         use crate::tests::multi_stepper::{MultiBevyStepper, TEST_CLIENT_ID_1, TEST_CLIENT_ID_2};
@@ -1106,6 +1405,48 @@ pub(crate) mod send {
             assert_eq!(stepper.client_app.world.entities().len(), 2);
         }
 
+        #[test]
+        fn test_update_replication_group_priority() {
+            let mut stepper = BevyStepper::default();
+
+            let server_entity = stepper
+                .server_app
+                .world
+                .spawn(Replicate {
+                    group: ReplicationGroup::new_from_entity().set_priority(2.0),
+                    ..default()
+                })
+                .id();
+            stepper.frame_step();
+            stepper.frame_step();
+            let group_id = ReplicationGroupId(server_entity.to_bits());
+            let base_priority = |stepper: &BevyStepper| {
+                stepper
+                    .server_app
+                    .world
+                    .resource::<ConnectionManager>()
+                    .connection(ClientId::Netcode(TEST_CLIENT_ID))
+                    .unwrap()
+                    .replication_sender
+                    .group_channels
+                    .get(&group_id)
+                    .unwrap()
+                    .base_priority
+            };
+            assert_eq!(base_priority(&stepper), 2.0);
+
+            // update the priority after the entity was spawned
+            stepper
+                .server_app
+                .world
+                .entity_mut(server_entity)
+                .get_mut::<ReplicationGroup>()
+                .unwrap()
+                .update_priority(5.0);
+            stepper.frame_step();
+            assert_eq!(base_priority(&stepper), 5.0);
+        }
+
         #[test]
         fn test_entity_spawn_visibility() {
             let mut stepper = MultiBevyStepper::default();
@@ -1275,6 +1616,14 @@ pub(crate) mod send {
 
             // check that the entity was despawned
             assert!(stepper.client_app.world.get_entity(client_entity).is_none());
+            let events = stepper
+                .client_app
+                .world
+                .resource::<Events<client::EntityDespawnEvent>>();
+            assert_eq!(
+                events.get_reader().read(events).next().unwrap().reason(),
+                DespawnReason::Despawned
+            );
         }
 
         /// Check that if interest management is used, a client losing visibility of an entity
@@ -1767,6 +2116,49 @@ pub(crate) mod send {
                 .entity(client_entity_2)
                 .get::<Component1>()
                 .is_none());
+
+            // update the override target: the component is inserted on client 2 and removed from client 1
+            // even though the component itself did not change
+            stepper
+                .server_app
+                .world
+                .entity_mut(server_entity)
+                .insert(OverrideTargetComponent::<Component1>::new(
+                    NetworkTarget::Single(ClientId::Netcode(TEST_CLIENT_ID_2)),
+                ));
+            stepper.frame_step();
+            stepper.frame_step();
+            assert!(stepper
+                .client_app_1
+                .world
+                .entity(client_entity_1)
+                .get::<Component1>()
+                .is_none());
+            assert_eq!(
+                stepper
+                    .client_app_2
+                    .world
+                    .entity(client_entity_2)
+                    .get::<Component1>(),
+                Some(&Component1(1.0))
+            );
+
+            // remove the override: the component is replicated to all clients again
+            stepper
+                .server_app
+                .world
+                .entity_mut(server_entity)
+                .remove::<OverrideTargetComponent<Component1>>();
+            stepper.frame_step();
+            stepper.frame_step();
+            assert_eq!(
+                stepper
+                    .client_app_1
+                    .world
+                    .entity(client_entity_1)
+                    .get::<Component1>(),
+                Some(&Component1(1.0))
+            );
         }
 
         /// Check that override target works even if the entity uses interest management
@@ -1940,6 +2332,71 @@ pub(crate) mod send {
             );
         }
 
+        #[test]
+        fn test_component_update_replication_rate() {
+            let mut stepper = BevyStepper::default();
+
+            // spawn an entity on server that is sampled every 4 ticks
+            let server_entity = stepper
+                .server_app
+                .world
+                .spawn((
+                    Replicate::default(),
+                    ReplicationRate::every_ticks(4),
+                    Component1(1.0),
+                ))
+                .id();
+            stepper.frame_step();
+            stepper.frame_step();
+            let client_entity = *stepper
+                .client_app
+                .world
+                .resource::<client::ConnectionManager>()
+                .replication_receiver
+                .remote_entity_map
+                .get_local(server_entity)
+                .expect("entity was not replicated to client");
+
+            // update component
+            stepper
+                .server_app
+                .world
+                .entity_mut(server_entity)
+                .insert(Component1(2.0));
+            stepper.frame_step();
+            stepper.frame_step();
+            // the update was not sampled yet
+            assert_eq!(
+                stepper.client_app.world.get::<Component1>(client_entity),
+                Some(&Component1(1.0))
+            );
+            stepper.frame_step();
+            stepper.frame_step();
+            assert_eq!(
+                stepper.client_app.world.get::<Component1>(client_entity),
+                Some(&Component1(2.0))
+            );
+        }
+
+        #[test]
+        fn test_replication_rate() {
+            let mut rate = ReplicationRate::every_ticks(2);
+            rate.tick(Duration::default(), Tick(0));
+            assert!(rate.should_send);
+            rate.sent(Tick(0));
+            rate.tick(Duration::default(), Tick(1));
+            assert!(!rate.should_send);
+            rate.tick(Duration::default(), Tick(2));
+            assert!(rate.should_send);
+
+            let mut rate = ReplicationRate::from_hz(10.0);
+            rate.sent(Tick(0));
+            rate.tick(Duration::from_millis(60), Tick(1));
+            assert!(!rate.should_send);
+            rate.tick(Duration::from_millis(60), Tick(2));
+            assert!(rate.should_send);
+        }
+
         #[test]
         fn test_component_update_delta() {
             let mut stepper = BevyStepper::default();
@@ -2265,6 +2722,7 @@ pub(crate) mod send {
                     incoming_latency: Duration::from_millis(30),
                     incoming_jitter: Default::default(),
                     incoming_loss: 0.0,
+                    ..default()
                 })
             }
             stepper.start();
@@ -2436,6 +2894,7 @@ pub(crate) mod send {
                     incoming_latency: Duration::from_millis(30),
                     incoming_jitter: Default::default(),
                     incoming_loss: 0.0,
+                    ..default()
                 })
             }
             stepper.start();
@@ -2721,6 +3180,210 @@ pub(crate) mod send {
             );
         }
 
+        #[test]
+        fn test_component_update_immutable() {
+            let mut stepper = BevyStepper::default();
+
+            // spawn an entity on server, Component3 is registered as immutable
+            let server_entity = stepper
+                .server_app
+                .world
+                .spawn((Replicate::default(), Component3(1.0)))
+                .id();
+            stepper.frame_step();
+            stepper.frame_step();
+            let client_entity = *stepper
+                .client_app
+                .world
+                .resource::<client::ConnectionManager>()
+                .replication_receiver
+                .remote_entity_map
+                .get_local(server_entity)
+                .expect("entity was not replicated to client");
+            assert_eq!(
+                stepper
+                    .client_app
+                    .world
+                    .entity(client_entity)
+                    .get::<Component3>()
+                    .expect("component missing"),
+                &Component3(1.0)
+            );
+
+            // update component
+            stepper
+                .server_app
+                .world
+                .entity_mut(server_entity)
+                .insert(Component3(2.0));
+            stepper.frame_step();
+            stepper.frame_step();
+
+            // check that the component was not updated
+            assert_eq!(
+                stepper
+                    .client_app
+                    .world
+                    .entity(client_entity)
+                    .get::<Component3>()
+                    .expect("component missing"),
+                &Component3(1.0)
+            );
+        }
+
+        #[test]
+        fn test_entity_replicate_once() {
+            let mut stepper = BevyStepper::default();
+
+            // spawn an entity on server
+            let server_entity = stepper
+                .server_app
+                .world
+                .spawn((Replicate::default(), Component1(1.0), ReplicateOnce))
+                .id();
+            stepper.frame_step();
+            stepper.frame_step();
+            let client_entity = *stepper
+                .client_app
+                .world
+                .resource::<client::ConnectionManager>()
+                .replication_receiver
+                .remote_entity_map
+                .get_local(server_entity)
+                .expect("entity was not replicated to client");
+            assert_eq!(
+                stepper.client_app.world.get::<Component1>(client_entity),
+                Some(&Component1(1.0))
+            );
+
+            // the updates are not replicated
+            stepper
+                .server_app
+                .world
+                .entity_mut(server_entity)
+                .insert(Component1(2.0));
+            stepper.frame_step();
+            stepper.frame_step();
+            assert_eq!(
+                stepper.client_app.world.get::<Component1>(client_entity),
+                Some(&Component1(1.0))
+            );
+
+            // until the entity is marked as dirty
+            stepper
+                .server_app
+                .world
+                .entity_mut(server_entity)
+                .insert(ReplicateOnce);
+            stepper.frame_step();
+            stepper.frame_step();
+            assert_eq!(
+                stepper.client_app.world.get::<Component1>(client_entity),
+                Some(&Component1(2.0))
+            );
+        }
+
+        #[test]
+        fn test_skip_unchanged_components() {
+            let mut stepper = BevyStepper::default();
+
+            let server_entity = stepper
+                .server_app
+                .world
+                .spawn((Replicate::default(), Component1(1.0), Component2(1.0)))
+                .id();
+            stepper.frame_step();
+            stepper.frame_step();
+            let group_id = ReplicationGroupId(server_entity.to_bits());
+            let this_run = stepper.server_app.world.read_change_tick();
+            let sender = stepper.server_app.world.resource::<ConnectionManager>();
+            assert!(sender
+                .oldest_send_tick(group_id, &NetworkTarget::All, this_run)
+                .is_some());
+            // the group was never sent to these clients
+            assert!(sender
+                .oldest_send_tick(ReplicationGroupId(0), &NetworkTarget::All, this_run)
+                .is_none());
+
+            // only the component that changed is sent
+            stepper
+                .server_app
+                .world
+                .entity_mut(server_entity)
+                .insert(Component1(2.0));
+            stepper.frame_step();
+            stepper.frame_step();
+            let client_entity = *stepper
+                .client_app
+                .world
+                .resource::<client::ConnectionManager>()
+                .replication_receiver
+                .remote_entity_map
+                .get_local(server_entity)
+                .expect("entity was not replicated to client");
+            assert_eq!(
+                stepper.client_app.world.get::<Component1>(client_entity),
+                Some(&Component1(2.0))
+            );
+            assert_eq!(
+                stepper.client_app.world.get::<Component2>(client_entity),
+                Some(&Component2(1.0))
+            );
+        }
+
+        #[derive(Resource, Default)]
+        struct CatchUpCount(usize);
+
+        fn send_catch_up(
+            mut connection_manager: ResMut<ConnectionManager>,
+            mut count: ResMut<CatchUpCount>,
+        ) {
+            let late_joiners = connection_manager.late_joiners().to_vec();
+            connection_manager
+                .send_message_to_target::<Channel1, _>(
+                    &Message1("score".to_string()),
+                    NetworkTarget::Only(late_joiners),
+                )
+                .unwrap();
+            count.0 += 1;
+        }
+
+        fn receive_catch_up(
+            mut events: EventReader<MessageEvent<Message1>>,
+            mut count: ResMut<CatchUpCount>,
+        ) {
+            count.0 += events.read().count();
+        }
+
+        #[test]
+        fn test_late_join() {
+            let tick_duration = Duration::from_millis(10);
+            let shared_config = SharedConfig {
+                tick: TickConfig::new(tick_duration),
+                ..Default::default()
+            };
+            let mut stepper =
+                BevyStepper::new(shared_config, ClientConfig::default(), tick_duration);
+            stepper
+                .server_app
+                .init_resource::<CatchUpCount>()
+                .add_systems(
+                    PostUpdate,
+                    send_catch_up.in_set(ServerReplicationSet::LateJoin),
+                );
+            stepper
+                .client_app
+                .init_resource::<CatchUpCount>()
+                .add_systems(Update, receive_catch_up);
+            stepper.init();
+            for _ in 0..5 {
+                stepper.frame_step();
+            }
+            // the catch-up data is only sent once, when the client connects
+            assert_eq!(stepper.server_app.world.resource::<CatchUpCount>().0, 1);
+            assert_eq!(stepper.client_app.world.resource::<CatchUpCount>().0, 1);
+        }
+
         #[test]
         fn test_component_remove() {
             let mut stepper = BevyStepper::default();
@@ -2769,6 +3432,58 @@ pub(crate) mod send {
                 .is_none());
         }
 
+        /// The delta-compression history is removed along with the component
+        #[test]
+        fn test_component_remove_delta() {
+            let mut stepper = BevyStepper::default();
+
+            let server_entity = stepper
+                .server_app
+                .world
+                .spawn((
+                    Replicate::default(),
+                    Component6(vec![1, 2]),
+                    DeltaCompression::<Component6>::default(),
+                ))
+                .id();
+            stepper.frame_step();
+            stepper.frame_step();
+            let client_entity = *stepper
+                .client_app
+                .world
+                .resource::<client::ConnectionManager>()
+                .replication_receiver
+                .remote_entity_map
+                .get_local(server_entity)
+                .expect("entity was not replicated to client");
+            assert!(stepper
+                .client_app
+                .world
+                .entity(client_entity)
+                .get::<DeltaComponentHistory<Component6>>()
+                .is_some());
+
+            stepper
+                .server_app
+                .world
+                .entity_mut(server_entity)
+                .remove::<Component6>();
+            stepper.frame_step();
+            stepper.frame_step();
+            assert!(stepper
+                .client_app
+                .world
+                .entity(client_entity)
+                .get::<Component6>()
+                .is_none());
+            assert!(stepper
+                .client_app
+                .world
+                .entity(client_entity)
+                .get::<DeltaComponentHistory<Component6>>()
+                .is_none());
+        }
+
         #[test]
         fn test_replicating_add() {
             let mut stepper = BevyStepper::default();
@@ -2797,6 +3512,7 @@ pub(crate) mod send {
                     replication_group: ReplicationGroup::new_from_entity(),
                     network_relevance_mode: NetworkRelevanceMode::All,
                     replication_clients_cache: vec![],
+                    despawn_reason: DespawnReason::Despawned,
                 }
             );
         }
@@ -2930,6 +3646,8 @@ pub(crate) mod send {
 
 pub(crate) mod commands {
     use crate::server::connection::ConnectionManager;
+    use crate::shared::replication::components::PauseReplication;
+    use crate::shared::replication::DespawnReason;
 
     use bevy::ecs::system::EntityCommands;
     use bevy::prelude::{Entity, World};
@@ -2942,20 +3660,55 @@ pub(crate) mod commands {
         world.despawn(entity);
     }
 
+    fn despawn_with_reason(entity: Entity, world: &mut World, reason: u8) {
+        let mut sender = world.resource_mut::<ConnectionManager>();
+        if let Some(cache) = sender.replicate_component_cache.get_mut(&entity) {
+            cache.despawn_reason = DespawnReason::Custom(reason);
+        }
+        world.despawn(entity);
+    }
+
     pub trait DespawnReplicationCommandExt {
         /// Despawn the entity and makes sure that the despawn won't be replicated.
         fn despawn_without_replication(&mut self);
+
+        /// Despawn the entity, and replicate the despawn with [`DespawnReason::Custom(reason)`](DespawnReason::Custom)
+        /// so that the clients can know why the entity was despawned (for example to play a death animation).
+        fn despawn_with_reason(&mut self, reason: u8);
     }
     impl DespawnReplicationCommandExt for EntityCommands<'_> {
         fn despawn_without_replication(&mut self) {
             self.add(despawn_without_replication);
         }
+
+        fn despawn_with_reason(&mut self, reason: u8) {
+            self.add(move |entity, world: &mut World| despawn_with_reason(entity, world, reason));
+        }
+    }
+
+    pub trait PauseReplicationCommandExt {
+        /// Stop replicating the components of the entity, without despawning it on the clients.
+        fn pause_replication(&mut self);
+
+        /// Resume the replication of the entity: the current value of all its components is sent to the clients.
+        fn resume_replication(&mut self);
+    }
+    impl PauseReplicationCommandExt for EntityCommands<'_> {
+        fn pause_replication(&mut self) {
+            self.insert(PauseReplication);
+        }
+
+        fn resume_replication(&mut self) {
+            self.remove::<PauseReplication>();
+        }
     }
 
     #[cfg(test)]
     mod tests {
+        use bevy::prelude::{Events, With};
         use bevy::utils::Duration;
 
+        use crate::prelude::client;
         use crate::prelude::server::Replicate;
         use crate::tests::protocol::*;
         use crate::tests::stepper::{BevyStepper, Step};
@@ -3028,5 +3781,90 @@ pub(crate) mod commands {
                 .get_single(&stepper.client_app.world)
                 .is_ok());
         }
+
+        #[test]
+        fn test_despawn_with_reason() {
+            let mut stepper = BevyStepper::default();
+
+            let entity = stepper
+                .server_app
+                .world
+                .spawn((Component1(1.0), Replicate::default()))
+                .id();
+            stepper.frame_step();
+            stepper.frame_step();
+
+            despawn_with_reason(entity, &mut stepper.server_app.world, 3);
+            stepper.frame_step();
+            stepper.frame_step();
+
+            assert!(stepper
+                .client_app
+                .world
+                .query::<&Component1>()
+                .get_single(&stepper.client_app.world)
+                .is_err());
+            let events = stepper
+                .client_app
+                .world
+                .resource::<Events<client::EntityDespawnEvent>>();
+            assert_eq!(
+                events.get_reader().read(events).next().unwrap().reason(),
+                DespawnReason::Custom(3)
+            );
+        }
+
+        #[test]
+        fn test_pause_replication() {
+            let mut stepper = BevyStepper::default();
+
+            let entity = stepper
+                .server_app
+                .world
+                .spawn((Component1(1.0), Replicate::default()))
+                .id();
+            stepper.frame_step();
+            stepper.frame_step();
+            let client_entity = stepper
+                .client_app
+                .world
+                .query_filtered::<Entity, With<Component1>>()
+                .single(&stepper.client_app.world);
+
+            // the changes made while the replication is paused are not replicated
+            stepper.server_app.world.entity_mut(entity).insert((
+                PauseReplication,
+                Component1(2.0),
+                Component2(1.0),
+            ));
+            stepper.frame_step();
+            stepper.frame_step();
+            assert_eq!(
+                stepper.client_app.world.get::<Component1>(client_entity),
+                Some(&Component1(1.0))
+            );
+            assert!(stepper
+                .client_app
+                .world
+                .get::<Component2>(client_entity)
+                .is_none());
+
+            // all the components are sent when the replication is resumed
+            stepper
+                .server_app
+                .world
+                .entity_mut(entity)
+                .remove::<PauseReplication>();
+            stepper.frame_step();
+            stepper.frame_step();
+            assert_eq!(
+                stepper.client_app.world.get::<Component1>(client_entity),
+                Some(&Component1(2.0))
+            );
+            assert_eq!(
+                stepper.client_app.world.get::<Component2>(client_entity),
+                Some(&Component2(1.0))
+            );
+        }
     }
 }
@@ -0,0 +1,391 @@
+//! Detect non-determinism in the predicted systems by comparing checksums of the predicted state.
+//!
+//! The client and the server both compute, at every tick, a checksum of the components registered
+//! with [`AppChecksumExt::add_checksum`] for each predicted entity:
+//! - the server sends the checksums of the entities that a client predicts to that client, for each tick
+//! - the client stores the checksums of its own predicted entities for the ticks it simulated (the ticks
+//!   that are re-simulated during a rollback overwrite the previous checksums)
+//! - when the checksums of the server for a tick are received, the client compares them with its own and emits
+//!   a [`DesyncDetected`] event if some of them don't match
+//!
+//! The checksums only match if the client predicted the same inputs as the server received: the mispredictions
+//! of the inputs of the other clients are also reported. The mode is most useful to track the
+//! non-determinism of the entities that are only controlled by the local client, or when the input delay covers
+//! the whole latency.
+//!
+//! ```rust,ignore
+//! use bevy::prelude::*;
+//! use lightyear::prelude::*;
+//!
+//! // in the protocol shared by the client and the server
+//! app.add_plugins(DesyncDetectionPlugin);
+//! app.add_checksum::<Position>();
+//!
+//! // on the client
+//! fn log_desync(mut events: EventReader<client::DesyncDetected>) {
+//!     for event in events.read() {
+//!         error!(tick = ?event.tick, entities = ?event.entities, "desync detected");
+//!     }
+//! }
+//! ```
+use std::hash::Hasher;
+
+use bevy::ecs::entity::EntityHashMap;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+pub use receive::DesyncDetected;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::channel::builder::{ChannelDirection, ChecksumChannel};
+use crate::client::config::ClientConfig;
+use crate::prelude::{AppMessageExt, Tick};
+use crate::serialize::canonical::Canonical;
+use crate::server::config::ServerConfig;
+
+/// Message sent by the server with the checksums of the entities predicted by a client for a given tick
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChecksumMessage {
+    tick: Tick,
+    /// Checksum of each predicted server entity
+    checksums: Vec<(Entity, u64)>,
+}
+
+type ChecksumFn = fn(&EntityRef, &mut seahash::SeaHasher);
+
+/// Resource that holds the components that are included in the checksums
+#[derive(Resource, Default)]
+pub(crate) struct ChecksumRegistry {
+    checksum_fns: Vec<ChecksumFn>,
+}
+
+impl ChecksumRegistry {
+    /// Compute the checksum of the registered components of the entity
+    fn checksum(&self, entity: &EntityRef) -> u64 {
+        let mut hasher = seahash::SeaHasher::new();
+        for checksum_fn in &self.checksum_fns {
+            checksum_fn(entity, &mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+fn hash_component<C: Component + Serialize>(entity: &EntityRef, hasher: &mut seahash::SeaHasher) {
+    let Some(component) = entity.get::<C>() else {
+        hasher.write_u8(0);
+        return;
+    };
+    hasher.write_u8(1);
+    match bincode::serde::encode_to_vec(Canonical(component), bincode::config::standard()) {
+        Ok(bytes) => hasher.write(&bytes),
+        Err(e) => error!(
+            "Could not serialize the component {} for the checksum: {e}",
+            std::any::type_name::<C>()
+        ),
+    }
+}
+
+/// Include components in the checksums of the predicted entities
+pub trait AppChecksumExt {
+    /// Include the component `C` in the checksums of the predicted entities.
+    ///
+    /// The components must be added in the same order on the client and the server, like the rest of the protocol.
+    fn add_checksum<C: Component + Serialize>(&mut self) -> &mut Self;
+}
+
+impl AppChecksumExt for App {
+    fn add_checksum<C: Component + Serialize>(&mut self) -> &mut Self {
+        self.world
+            .get_resource_mut::<ChecksumRegistry>()
+            .expect("the DesyncDetectionPlugin must be added first")
+            .checksum_fns
+            .push(hash_component::<C>);
+        self
+    }
+}
+
+/// Plugin that compares the checksums of the predicted entities between the client and the server.
+///
+/// The server sends, every tick, the checksums of the components registered with
+/// [`add_checksum`](AppChecksumExt::add_checksum) for the entities that each client predicts.
+/// The client compares them with the checksums of its predicted entities at the same tick, and emits a
+/// [`DesyncDetected`] event when they differ. The same components must be registered on both sides,
+/// otherwise every tick is reported as a desync.
+pub struct DesyncDetectionPlugin;
+
+impl Plugin for DesyncDetectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChecksumRegistry>()
+            .add_message::<ChecksumMessage>(ChannelDirection::ServerToClient);
+        if app.world.get_resource::<ClientConfig>().is_some() {
+            app.add_plugins(receive::ChecksumReceivePlugin);
+        }
+        if app.world.get_resource::<ServerConfig>().is_some() {
+            app.add_plugins(send::ChecksumSendPlugin);
+        }
+    }
+}
+
+pub(crate) mod send {
+    use super::*;
+    use crate::connection::id::ClientId;
+    use crate::prelude::{is_started, Replicating, TickManager};
+    use crate::server::connection::ConnectionManager;
+    use crate::server::replication::send::SyncTarget;
+    use crate::shared::replication::components::ReplicationTarget;
+
+    pub(crate) struct ChecksumSendPlugin;
+
+    impl Plugin for ChecksumSendPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(FixedPostUpdate, send_checksums.run_if(is_started));
+        }
+    }
+
+    /// Send to each client the checksums of the entities that it predicts, after the tick was simulated
+    ///
+    /// This is an exclusive system because an [`EntityRef`] query can't run alongside the mutable
+    /// access to the [`ConnectionManager`].
+    pub(crate) fn send_checksums(
+        world: &mut World,
+        query: &mut QueryState<(EntityRef, &ReplicationTarget, &SyncTarget), With<Replicating>>,
+    ) {
+        let tick = world.resource::<TickManager>().tick();
+        world.resource_scope(|world, mut sender: Mut<ConnectionManager>| {
+            let registry = world.resource::<ChecksumRegistry>();
+            let mut messages: HashMap<ClientId, ChecksumMessage> = sender
+                .connected_clients()
+                .map(|client_id| {
+                    (
+                        client_id,
+                        ChecksumMessage {
+                            tick,
+                            checksums: vec![],
+                        },
+                    )
+                })
+                .collect();
+            for (entity, replication_target, sync_target) in query.iter(world) {
+                let checksum = registry.checksum(&entity);
+                for (client_id, message) in messages.iter_mut() {
+                    if replication_target.target.targets(client_id)
+                        && sync_target.prediction.targets(client_id)
+                    {
+                        message.checksums.push((entity.id(), checksum));
+                    }
+                }
+            }
+            for (client_id, message) in messages {
+                let _ = sender
+                    .send_message::<ChecksumChannel, _>(client_id, &message)
+                    .inspect_err(|e| error!("could not send the checksums: {:?}", e));
+            }
+        });
+    }
+}
+
+pub(crate) mod receive {
+    use super::*;
+    use crate::client::connection::ConnectionManager;
+    use crate::client::events::MessageEvent;
+    use crate::client::prediction::plugin::PredictionSet;
+    use crate::client::prediction::rollback::Rollback;
+    use crate::client::prediction::Predicted;
+    use crate::prelude::TickManager;
+    use crate::shared::sets::{ClientMarker, InternalMainSet};
+
+    /// Number of ticks after which the checksums computed by the client are discarded,
+    /// if the checksums of the server were not received
+    const MAX_CHECKSUM_HISTORY_TICKS: i16 = 256;
+
+    /// Event emitted on the client when the checksums of some predicted entities don't match
+    /// the checksums computed by the server
+    #[derive(Event, Debug, Clone, PartialEq)]
+    pub struct DesyncDetected {
+        /// Tick at which the predicted state diverged from the server state
+        pub tick: Tick,
+        /// The predicted entities whose state diverged
+        pub entities: Vec<Entity>,
+    }
+
+    /// Checksums of the predicted entities computed by the client, for each tick
+    #[derive(Resource, Default, Debug)]
+    pub(crate) struct ChecksumHistory {
+        /// For each tick, the predicted entity and its checksum, by server entity
+        ticks: HashMap<Tick, EntityHashMap<(Entity, u64)>>,
+    }
+
+    pub(crate) struct ChecksumReceivePlugin;
+
+    impl Plugin for ChecksumReceivePlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<ChecksumHistory>()
+                .add_event::<DesyncDetected>()
+                .add_systems(
+                    PreUpdate,
+                    compare_checksums.after(InternalMainSet::<ClientMarker>::EmitEvents),
+                )
+                // the checksums are computed for every simulated tick, including the ticks re-simulated
+                // during a rollback
+                .add_systems(
+                    FixedPostUpdate,
+                    record_checksums.in_set(PredictionSet::UpdateHistory),
+                );
+        }
+    }
+
+    /// Store the checksums of the predicted entities for the current tick (or the current rollback tick)
+    ///
+    /// This is an exclusive system because an [`EntityRef`] query can't run alongside the mutable
+    /// access to the [`ChecksumHistory`].
+    pub(crate) fn record_checksums(
+        world: &mut World,
+        query: &mut QueryState<(EntityRef, &Predicted)>,
+    ) {
+        world.resource_scope(|world, mut history: Mut<ChecksumHistory>| {
+            let tick = world
+                .resource::<TickManager>()
+                .tick_or_rollback_tick(world.resource::<Rollback>());
+            let registry = world.resource::<ChecksumRegistry>();
+            let remote_entity_map = &world
+                .resource::<ConnectionManager>()
+                .replication_receiver
+                .remote_entity_map;
+            let checksums = query
+                .iter(world)
+                .filter_map(|(entity, predicted)| {
+                    let server_entity =
+                        remote_entity_map.get_remote(predicted.confirmed_entity?)?;
+                    Some((*server_entity, (entity.id(), registry.checksum(&entity))))
+                })
+                .collect();
+            history.ticks.insert(tick, checksums);
+            history
+                .ticks
+                .retain(|history_tick, _| tick - *history_tick < MAX_CHECKSUM_HISTORY_TICKS);
+        });
+    }
+
+    /// Compare the checksums received from the server with the checksums computed by the client
+    pub(crate) fn compare_checksums(
+        mut messages: ResMut<Events<MessageEvent<ChecksumMessage>>>,
+        mut history: ResMut<ChecksumHistory>,
+        mut events: EventWriter<DesyncDetected>,
+    ) {
+        for message in messages.drain() {
+            let ChecksumMessage { tick, checksums } = message.message;
+            let Some(local_checksums) = history.ticks.get(&tick) else {
+                continue;
+            };
+            // the entities that were not predicted yet (or anymore) at this tick are skipped
+            let entities: Vec<Entity> = checksums
+                .into_iter()
+                .filter_map(|(server_entity, checksum)| {
+                    local_checksums
+                        .get(&server_entity)
+                        .filter(|(_, local_checksum)| *local_checksum != checksum)
+                        .map(|(predicted, _)| *predicted)
+                })
+                .collect();
+            if !entities.is_empty() {
+                events.send(DesyncDetected { tick, entities });
+            }
+            // the server ticks are received in order (except for lost messages), we won't need the older ticks
+            history
+                .ticks
+                .retain(|history_tick, _| *history_tick - tick > 0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Duration;
+
+    use crate::prelude::client::{self, ClientConfig};
+    use crate::prelude::server::{Replicate, SyncTarget};
+    use crate::prelude::{NetworkTarget, SharedConfig, TickConfig};
+    use crate::tests::protocol::Component1;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    /// Non-deterministic system that only runs on the client
+    fn increment_component(mut query: Query<&mut Component1, With<client::Predicted>>) {
+        for mut component in query.iter_mut() {
+            component.0 += 1.0;
+        }
+    }
+
+    #[test]
+    fn test_desync_detected() {
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(shared_config, ClientConfig::default(), tick_duration);
+        for app in [&mut stepper.server_app, &mut stepper.client_app] {
+            app.add_plugins(DesyncDetectionPlugin)
+                .add_checksum::<Component1>();
+        }
+        stepper.init();
+
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((
+                Component1(0.0),
+                Replicate {
+                    sync: SyncTarget {
+                        prediction: NetworkTarget::All,
+                        ..default()
+                    },
+                    ..default()
+                },
+            ))
+            .id();
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        let confirmed = *stepper
+            .client_app
+            .world
+            .resource::<client::ConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .expect("entity was not replicated to client");
+        let predicted = stepper
+            .client_app
+            .world
+            .get::<client::Confirmed>(confirmed)
+            .unwrap()
+            .predicted
+            .unwrap();
+        // the client and the server simulate the same thing
+        assert!(stepper
+            .client_app
+            .world
+            .resource_mut::<Events<DesyncDetected>>()
+            .drain()
+            .next()
+            .is_none());
+
+        // the client diverges from the server
+        stepper
+            .client_app
+            .add_systems(FixedUpdate, increment_component);
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        let events: Vec<DesyncDetected> = stepper
+            .client_app
+            .world
+            .resource_mut::<Events<DesyncDetected>>()
+            .drain()
+            .collect();
+        assert!(!events.is_empty());
+        assert!(events.iter().all(|event| event.entities == vec![predicted]));
+    }
+}
@@ -0,0 +1,511 @@
+//! Stream the content of a large world to the clients, chunk by chunk.
+//!
+//! The world is partitioned into square chunks of `chunk_size`. The server stores the content of each chunk
+//! in the [`ChunkManager`] resource, and each client is subscribed to the chunks that are within the radius of
+//! its [`ClientView`] (for example the character controlled by the client):
+//! - when a chunk enters the radius of a client (or its content is updated on the server), it is sent to the client
+//! - when a chunk leaves the radius of a client (or is removed on the server), the client unloads it
+//!
+//! The chunks are sent on the [`ChunkChannel`], which is reliable and fragments large chunks over multiple packets.
+//! (Enable the `big_messages` feature if a chunk can be bigger than 300KB)
+//!
+//! On the client, the loaded chunks are stored in the [`LoadedChunks`] resource, and a [`ChunkLoadEvent`]
+//! or [`ChunkUnloadEvent`] is emitted whenever a chunk is loaded or unloaded.
+//!
+//! ```rust,ignore
+//! use bevy::prelude::*;
+//! use lightyear::prelude::*;
+//!
+//! #[derive(Serialize, Deserialize, Clone)]
+//! struct Terrain(Vec<u8>);
+//!
+//! // in the protocol shared by the client and the server
+//! app.add_plugins(ChunkStreamingPlugin::<Terrain, Position>::new(64.0));
+//!
+//! // on the server
+//! fn generate_terrain(mut chunks: ResMut<server::ChunkManager<Terrain>>) {
+//!     chunks.insert(ChunkId(IVec2::ZERO), Terrain(vec![0; 64 * 64]));
+//! }
+//!
+//! // on the client
+//! fn spawn_terrain(mut events: EventReader<client::ChunkLoadEvent<Terrain>>, chunks: Res<client::LoadedChunks<Terrain>>) {
+//!     for event in events.read() {
+//!         let terrain = chunks.get(event.chunk()).unwrap();
+//!     }
+//! }
+//! ```
+use std::marker::PhantomData;
+
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+pub use receive::{ChunkLoadEvent, ChunkUnloadEvent, LoadedChunks};
+pub use send::ChunkManager;
+use serde::{Deserialize, Serialize};
+
+use crate::channel::builder::{ChannelDirection, ChunkChannel};
+use crate::client::config::ClientConfig;
+use crate::prelude::{AppMessageExt, Message};
+use crate::server::config::ServerConfig;
+use crate::server::relevance::spatial::{ClientView, SpatialPosition};
+
+/// Coordinates of a chunk in the chunk grid
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Reflect)]
+pub struct ChunkId(pub IVec2);
+
+/// Message sent by the server to load or unload a chunk on a client
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ChunkMessage<C> {
+    Load { chunk: ChunkId, data: C },
+    Unload { chunk: ChunkId },
+}
+
+/// Plugin that streams the chunks of type `C` to the clients.
+///
+/// `P` is the component that holds the position of the [`ClientView`]s.
+/// `chunk_size` is only used on the server, to assign positions to chunks: on the server the plugin inserts the
+/// [`ChunkManager`] and subscribes each client to the chunks around its view; on the client it inserts the
+/// [`LoadedChunks`] and emits the load/unload events. The [`ChunkMessage`] must be registered on both sides.
+pub struct ChunkStreamingPlugin<C, P> {
+    chunk_size: f32,
+    _marker: PhantomData<(C, P)>,
+}
+
+impl<C, P> ChunkStreamingPlugin<C, P> {
+    pub fn new(chunk_size: f32) -> Self {
+        Self {
+            chunk_size,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<C: Message + Clone, P: SpatialPosition> Plugin for ChunkStreamingPlugin<C, P> {
+    fn build(&self, app: &mut App) {
+        app.add_message::<ChunkMessage<C>>(ChannelDirection::ServerToClient);
+        let is_client = app.world.get_resource::<ClientConfig>().is_some();
+        let is_server = app.world.get_resource::<ServerConfig>().is_some();
+        if is_client {
+            app.add_plugins(receive::ChunkReceivePlugin::<C>::default());
+        }
+        if is_server {
+            app.add_plugins(send::ChunkSendPlugin::<C, P>::new(self.chunk_size));
+        }
+    }
+}
+
+pub(crate) mod send {
+    use super::*;
+    use crate::connection::id::ClientId;
+    use crate::prelude::is_started;
+    use crate::server::connection::ConnectionManager;
+    use crate::shared::sets::{InternalMainSet, ServerMarker};
+
+    /// Resource that holds the content of the chunks on the server
+    #[derive(Resource, Debug)]
+    pub struct ChunkManager<C> {
+        chunk_size: f32,
+        chunks: HashMap<ChunkId, C>,
+        /// Chunks that were inserted or removed since the last update
+        dirty: HashSet<ChunkId>,
+        /// Chunks that are currently loaded on each client
+        loaded: HashMap<ClientId, HashSet<ChunkId>>,
+    }
+
+    impl<C> ChunkManager<C> {
+        pub fn new(chunk_size: f32) -> Self {
+            assert!(chunk_size > 0.0, "the chunk size must be positive");
+            Self {
+                chunk_size,
+                chunks: HashMap::default(),
+                dirty: HashSet::default(),
+                loaded: HashMap::default(),
+            }
+        }
+
+        /// Returns the chunk that contains the position
+        pub fn chunk_at(&self, position: Vec2) -> ChunkId {
+            ChunkId((position / self.chunk_size).floor().as_ivec2())
+        }
+
+        /// Insert or update the content of a chunk.
+        ///
+        /// The new content is sent to the clients that have the chunk loaded.
+        pub fn insert(&mut self, chunk: ChunkId, data: C) {
+            self.chunks.insert(chunk, data);
+            self.dirty.insert(chunk);
+        }
+
+        /// Remove a chunk. It is unloaded on the clients that have the chunk loaded.
+        pub fn remove(&mut self, chunk: ChunkId) -> Option<C> {
+            self.dirty.insert(chunk);
+            self.chunks.remove(&chunk)
+        }
+
+        pub fn get(&self, chunk: ChunkId) -> Option<&C> {
+            self.chunks.get(&chunk)
+        }
+
+        /// Returns true if the chunk is loaded on the client
+        pub fn is_loaded(&self, client_id: ClientId, chunk: ChunkId) -> bool {
+            self.loaded
+                .get(&client_id)
+                .is_some_and(|chunks| chunks.contains(&chunk))
+        }
+
+        /// Iterate through the chunks that intersect the circle
+        pub(crate) fn chunks_in_radius(
+            &self,
+            center: Vec2,
+            radius: f32,
+        ) -> impl Iterator<Item = ChunkId> + '_ {
+            let min = self.chunk_at(center - Vec2::splat(radius)).0;
+            let max = self.chunk_at(center + Vec2::splat(radius)).0;
+            (min.x..=max.x)
+                .flat_map(move |x| (min.y..=max.y).map(move |y| IVec2::new(x, y)))
+                .filter(move |cell| {
+                    let min_corner = cell.as_vec2() * self.chunk_size;
+                    let closest =
+                        center.clamp(min_corner, min_corner + Vec2::splat(self.chunk_size));
+                    closest.distance_squared(center) <= radius * radius
+                })
+                .map(ChunkId)
+        }
+    }
+
+    pub(crate) struct ChunkSendPlugin<C, P> {
+        chunk_size: f32,
+        _marker: PhantomData<(C, P)>,
+    }
+
+    impl<C, P> ChunkSendPlugin<C, P> {
+        pub(crate) fn new(chunk_size: f32) -> Self {
+            Self {
+                chunk_size,
+                _marker: PhantomData,
+            }
+        }
+    }
+
+    impl<C: Message + Clone, P: SpatialPosition> Plugin for ChunkSendPlugin<C, P> {
+        fn build(&self, app: &mut App) {
+            app.insert_resource(ChunkManager::<C>::new(self.chunk_size));
+            app.add_systems(
+                PostUpdate,
+                stream_chunks::<C, P>
+                    .before(InternalMainSet::<ServerMarker>::Send)
+                    .run_if(is_started),
+            );
+        }
+    }
+
+    /// Send the chunks that entered the radius of each client, and unload the chunks that left it
+    pub(crate) fn stream_chunks<C: Message + Clone, P: SpatialPosition>(
+        mut manager: ResMut<ChunkManager<C>>,
+        mut sender: ResMut<ConnectionManager>,
+        viewers: Query<(&P, &ClientView)>,
+    ) {
+        let manager = &mut *manager;
+        let mut visible: HashMap<ClientId, HashSet<ChunkId>> = HashMap::default();
+        for (position, view) in viewers.iter() {
+            if sender.connection(view.client_id).is_err() {
+                continue;
+            }
+            visible
+                .entry(view.client_id)
+                .or_default()
+                .extend(manager.chunks_in_radius(position.position(), view.radius));
+        }
+
+        let mut loaded: HashMap<ClientId, HashSet<ChunkId>> = HashMap::default();
+        for (client_id, chunks) in visible.iter() {
+            let previous = manager.loaded.remove(client_id).unwrap_or_default();
+            let current = loaded.entry(*client_id).or_default();
+            for chunk in chunks {
+                let was_loaded = previous.contains(chunk);
+                match manager.chunks.get(chunk) {
+                    Some(data) => {
+                        if !was_loaded || manager.dirty.contains(chunk) {
+                            send_chunk_message(
+                                &mut sender,
+                                *client_id,
+                                ChunkMessage::Load {
+                                    chunk: *chunk,
+                                    data: data.clone(),
+                                },
+                            );
+                        }
+                        current.insert(*chunk);
+                    }
+                    None => {
+                        if was_loaded {
+                            send_chunk_message::<C>(
+                                &mut sender,
+                                *client_id,
+                                ChunkMessage::Unload { chunk: *chunk },
+                            );
+                        }
+                    }
+                }
+            }
+            for chunk in previous.difference(chunks) {
+                send_chunk_message::<C>(
+                    &mut sender,
+                    *client_id,
+                    ChunkMessage::Unload { chunk: *chunk },
+                );
+            }
+        }
+        // the clients that don't have a view anymore unload all their chunks
+        for (client_id, previous) in manager.loaded.drain() {
+            if sender.connection(client_id).is_err() {
+                continue;
+            }
+            for chunk in previous {
+                send_chunk_message::<C>(&mut sender, client_id, ChunkMessage::Unload { chunk });
+            }
+        }
+        manager.loaded = loaded;
+        manager.dirty.clear();
+    }
+
+    fn send_chunk_message<C: Message>(
+        sender: &mut ConnectionManager,
+        client_id: ClientId,
+        message: ChunkMessage<C>,
+    ) {
+        let _ = sender
+            .send_message::<ChunkChannel, _>(client_id, &message)
+            .inspect_err(|e| error!("could not send the chunk: {:?}", e));
+    }
+}
+
+pub(crate) mod receive {
+    use super::*;
+    use crate::client::events::DisconnectEvent;
+    use crate::shared::events::components::MessageEvent;
+    use crate::shared::sets::{ClientMarker, InternalMainSet};
+
+    /// Resource that holds the chunks that are loaded on the client
+    #[derive(Resource, Debug)]
+    pub struct LoadedChunks<C> {
+        chunks: HashMap<ChunkId, C>,
+    }
+
+    impl<C> Default for LoadedChunks<C> {
+        fn default() -> Self {
+            Self {
+                chunks: HashMap::default(),
+            }
+        }
+    }
+
+    impl<C> LoadedChunks<C> {
+        pub fn get(&self, chunk: ChunkId) -> Option<&C> {
+            self.chunks.get(&chunk)
+        }
+
+        pub fn iter(&self) -> impl Iterator<Item = (&ChunkId, &C)> {
+            self.chunks.iter()
+        }
+    }
+
+    /// Event emitted on the client when a chunk is loaded, or when its content is updated
+    #[derive(Event, Debug)]
+    pub struct ChunkLoadEvent<C> {
+        chunk: ChunkId,
+        _marker: PhantomData<C>,
+    }
+
+    impl<C> ChunkLoadEvent<C> {
+        pub fn chunk(&self) -> ChunkId {
+            self.chunk
+        }
+    }
+
+    /// Event emitted on the client when a chunk is unloaded
+    #[derive(Event, Debug)]
+    pub struct ChunkUnloadEvent<C> {
+        chunk: ChunkId,
+        _marker: PhantomData<C>,
+    }
+
+    impl<C> ChunkUnloadEvent<C> {
+        pub fn chunk(&self) -> ChunkId {
+            self.chunk
+        }
+    }
+
+    pub(crate) struct ChunkReceivePlugin<C> {
+        _marker: PhantomData<C>,
+    }
+
+    impl<C> Default for ChunkReceivePlugin<C> {
+        fn default() -> Self {
+            Self {
+                _marker: PhantomData,
+            }
+        }
+    }
+
+    impl<C: Message> Plugin for ChunkReceivePlugin<C> {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<LoadedChunks<C>>()
+                .add_event::<ChunkLoadEvent<C>>()
+                .add_event::<ChunkUnloadEvent<C>>()
+                .add_systems(
+                    PreUpdate,
+                    receive_chunks::<C>.after(InternalMainSet::<ClientMarker>::EmitEvents),
+                );
+        }
+    }
+
+    /// Update the loaded chunks from the messages received from the server
+    pub(crate) fn receive_chunks<C: Message>(
+        mut messages: ResMut<Events<MessageEvent<ChunkMessage<C>>>>,
+        mut disconnect_events: EventReader<DisconnectEvent>,
+        mut loaded: ResMut<LoadedChunks<C>>,
+        mut load_events: EventWriter<ChunkLoadEvent<C>>,
+        mut unload_events: EventWriter<ChunkUnloadEvent<C>>,
+    ) {
+        for message in messages.drain() {
+            match message.message {
+                ChunkMessage::Load { chunk, data } => {
+                    loaded.chunks.insert(chunk, data);
+                    load_events.send(ChunkLoadEvent {
+                        chunk,
+                        _marker: PhantomData,
+                    });
+                }
+                ChunkMessage::Unload { chunk } => {
+                    if loaded.chunks.remove(&chunk).is_some() {
+                        unload_events.send(ChunkUnloadEvent {
+                            chunk,
+                            _marker: PhantomData,
+                        });
+                    }
+                }
+            }
+        }
+        // the server will send the chunks again when we reconnect
+        if disconnect_events.read().next().is_some() {
+            for (chunk, _) in loaded.chunks.drain() {
+                unload_events.send(ChunkUnloadEvent {
+                    chunk,
+                    _marker: PhantomData,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Duration;
+
+    use crate::prelude::{ClientId, SharedConfig, TickConfig};
+    use crate::tests::stepper::{BevyStepper, Step, TEST_CLIENT_ID};
+
+    use super::*;
+
+    #[derive(Component, Debug, PartialEq)]
+    struct Position(Vec2);
+
+    impl SpatialPosition for Position {
+        fn position(&self) -> Vec2 {
+            self.0
+        }
+    }
+
+    type Terrain = Vec<u8>;
+
+    #[test]
+    fn test_chunks_in_radius() {
+        let manager = ChunkManager::<Terrain>::new(10.0);
+        assert_eq!(
+            manager.chunk_at(Vec2::new(-1.0, 15.0)),
+            ChunkId(IVec2::new(-1, 1))
+        );
+        let chunks: HashSet<ChunkId> = manager.chunks_in_radius(Vec2::new(5.0, 5.0), 6.0).collect();
+        // the corner chunks are further than 6.0 from the center
+        assert_eq!(chunks.len(), 5);
+        assert!(chunks.contains(&ChunkId(IVec2::new(0, 0))));
+        assert!(!chunks.contains(&ChunkId(IVec2::new(1, 1))));
+    }
+
+    #[test]
+    fn test_chunk_streaming() {
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(shared_config, ClientConfig::default(), tick_duration);
+        stepper
+            .server_app
+            .add_plugins(ChunkStreamingPlugin::<Terrain, Position>::new(10.0));
+        stepper
+            .client_app
+            .add_plugins(ChunkStreamingPlugin::<Terrain, Position>::new(10.0));
+        stepper.init();
+
+        // the chunk is big enough to be fragmented
+        let near = ChunkId(IVec2::new(0, 0));
+        let far = ChunkId(IVec2::new(5, 0));
+        {
+            let mut manager = stepper
+                .server_app
+                .world
+                .resource_mut::<ChunkManager<Terrain>>();
+            manager.insert(near, vec![1; 5000]);
+            manager.insert(far, vec![2; 10]);
+        }
+        let viewer = stepper
+            .server_app
+            .world
+            .spawn((
+                Position(Vec2::new(5.0, 5.0)),
+                ClientView {
+                    client_id: ClientId::Netcode(TEST_CLIENT_ID),
+                    radius: 10.0,
+                },
+            ))
+            .id();
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        let loaded = stepper.client_app.world.resource::<LoadedChunks<Terrain>>();
+        assert_eq!(loaded.get(near), Some(&vec![1; 5000]));
+        assert!(loaded.get(far).is_none());
+
+        // the viewer moves next to the other chunk
+        stepper
+            .server_app
+            .world
+            .entity_mut(viewer)
+            .insert(Position(Vec2::new(55.0, 5.0)));
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        let loaded = stepper.client_app.world.resource::<LoadedChunks<Terrain>>();
+        assert!(loaded.get(near).is_none());
+        assert_eq!(loaded.get(far), Some(&vec![2; 10]));
+
+        // the chunk content is updated on the server
+        stepper
+            .server_app
+            .world
+            .resource_mut::<ChunkManager<Terrain>>()
+            .insert(far, vec![3; 10]);
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper
+                .client_app
+                .world
+                .resource::<LoadedChunks<Terrain>>()
+                .get(far),
+            Some(&vec![3; 10])
+        );
+    }
+}
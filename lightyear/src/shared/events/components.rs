@@ -4,7 +4,9 @@ use std::marker::PhantomData;
 
 use bevy::prelude::{Component, Entity, Event};
 
-use crate::packet::message::Message;
+use crate::packet::message::{Message, MessageId};
+use crate::protocol::channel::ChannelKind;
+use crate::shared::replication::DespawnReason;
 
 /// This event is emitted whenever we receive a message from the remote
 #[derive(Event)]
@@ -27,6 +29,60 @@ impl<M: Message, Ctx> MessageEvent<M, Ctx> {
     }
 }
 
+/// This event is emitted when a message that we sent on a channel that tracks acks
+/// (reliable channels, or [`UnorderedUnreliableWithAcks`](crate::channel::builder::ChannelMode::UnorderedUnreliableWithAcks))
+/// has been received by the remote
+#[derive(Event, Debug)]
+pub struct MessageDelivered<Ctx = ()> {
+    /// Id of the message, as returned when the message was sent
+    pub message_id: MessageId,
+    /// Channel that the message was sent on
+    pub channel: ChannelKind,
+    pub context: Ctx,
+}
+
+impl<Ctx> MessageDelivered<Ctx> {
+    pub fn new(message_id: MessageId, channel: ChannelKind, context: Ctx) -> Self {
+        Self {
+            message_id,
+            channel,
+            context,
+        }
+    }
+
+    pub fn context(&self) -> &Ctx {
+        &self.context
+    }
+}
+
+/// This event is emitted when a message that we sent on an unreliable channel that tracks acks
+/// ([`UnorderedUnreliableWithAcks`](crate::channel::builder::ChannelMode::UnorderedUnreliableWithAcks))
+/// was in a packet that has been deemed lost.
+///
+/// The message is not sent again automatically, so this can be used to selectively re-send important messages.
+#[derive(Event, Debug)]
+pub struct MessageLost<Ctx = ()> {
+    /// Id of the message, as returned when the message was sent
+    pub message_id: MessageId,
+    /// Channel that the message was sent on
+    pub channel: ChannelKind,
+    pub context: Ctx,
+}
+
+impl<Ctx> MessageLost<Ctx> {
+    pub fn new(message_id: MessageId, channel: ChannelKind, context: Ctx) -> Self {
+        Self {
+            message_id,
+            channel,
+            context,
+        }
+    }
+
+    pub fn context(&self) -> &Ctx {
+        &self.context
+    }
+}
+
 #[derive(Event)]
 /// Event emitted on server every time we receive an event
 pub struct InputEvent<I: crate::inputs::native::UserAction, Ctx = ()> {
@@ -73,18 +129,28 @@ impl<Ctx> EntitySpawnEvent<Ctx> {
 #[derive(Event)]
 pub struct EntityDespawnEvent<Ctx = ()> {
     entity: Entity,
+    reason: DespawnReason,
     context: Ctx,
 }
 
 impl<Ctx> EntityDespawnEvent<Ctx> {
-    pub fn new(entity: Entity, context: Ctx) -> Self {
-        Self { entity, context }
+    pub fn new(entity: Entity, reason: DespawnReason, context: Ctx) -> Self {
+        Self {
+            entity,
+            reason,
+            context,
+        }
     }
 
     pub fn entity(&self) -> Entity {
         self.entity
     }
 
+    /// Why the entity was despawned by the remote
+    pub fn reason(&self) -> DespawnReason {
+        self.reason
+    }
+
     pub fn context(&self) -> &Ctx {
         &self.context
     }
@@ -9,13 +9,14 @@ use tracing::trace;
 use crate::prelude::{ComponentRegistry, Tick};
 use crate::protocol::component::ComponentNetId;
 use crate::protocol::EventContext;
+use crate::shared::replication::DespawnReason;
 
 // TODO: don't make fields pub but instead make accessors
 #[derive(Debug, Resource)]
 pub struct ConnectionEvents {
     // replication
     pub spawns: Vec<Entity>,
-    pub despawns: Vec<Entity>,
+    pub despawns: Vec<(Entity, DespawnReason)>,
 
     // TODO: [IMPORTANT]: add ticks as well?
     // - should we just return the latest update for a given component/entity, or all of them?
@@ -88,13 +89,13 @@ impl ConnectionEvents {
         self.empty = false;
     }
 
-    pub(crate) fn push_despawn(&mut self, entity: Entity) {
-        trace!(?entity, "Received entity despawn");
+    pub(crate) fn push_despawn(&mut self, entity: Entity, reason: DespawnReason) {
+        trace!(?entity, ?reason, "Received entity despawn");
         #[cfg(feature = "metrics")]
         {
             metrics::counter!("entity_despawn").increment(1);
         }
-        self.despawns.push(entity);
+        self.despawns.push((entity, reason));
         self.empty = false;
     }
 
@@ -177,14 +178,22 @@ impl IterEntitySpawnEvent for ConnectionEvents {
 }
 
 pub trait IterEntityDespawnEvent<Ctx: EventContext = ()> {
-    fn into_iter_entity_despawn(&mut self) -> Box<dyn Iterator<Item = (Entity, Ctx)> + '_>;
+    fn into_iter_entity_despawn(
+        &mut self,
+    ) -> Box<dyn Iterator<Item = (Entity, DespawnReason, Ctx)> + '_>;
     fn has_entity_despawn(&self) -> bool;
 }
 
 impl IterEntityDespawnEvent for ConnectionEvents {
-    fn into_iter_entity_despawn(&mut self) -> Box<dyn Iterator<Item = (Entity, ())> + '_> {
+    fn into_iter_entity_despawn(
+        &mut self,
+    ) -> Box<dyn Iterator<Item = (Entity, DespawnReason, ())> + '_> {
         let despawns = std::mem::take(&mut self.despawns);
-        Box::new(despawns.into_iter().map(|entity| (entity, ())))
+        Box::new(
+            despawns
+                .into_iter()
+                .map(|(entity, reason)| (entity, reason, ())),
+        )
     }
 
     fn has_entity_despawn(&self) -> bool {
@@ -55,7 +55,7 @@ pub(crate) fn push_entity_events<R: ReplicationReceive>(
         connection_manager
             .events()
             .into_iter_entity_despawn()
-            .map(|(entity, ctx)| EntityDespawnEvent::new(entity, ctx)),
+            .map(|(entity, reason, ctx)| EntityDespawnEvent::new(entity, reason, ctx)),
     );
 }
 
@@ -1,5 +1,9 @@
 //! Shared code between the server and client.
 
+pub mod checksum;
+
+pub mod chunk;
+
 pub mod config;
 
 pub mod events;
@@ -10,6 +14,8 @@ pub mod ping;
 
 pub mod plugin;
 
+pub mod projectile;
+
 pub mod replication;
 
 pub mod sets;
@@ -13,7 +13,7 @@ pub struct PingStore {
     latest_ping_id: PingId,
     /// Buffer storing the latest pings sent along with their associated time
     /// Older pings will get overwritten by newer pings
-    buffer: SequenceBuffer<PingId, WrappedTime, PING_BUFFER_SIZE>,
+    buffer: SequenceBuffer<PingId, WrappedTime>,
 }
 
 impl Default for PingStore {
@@ -26,7 +26,7 @@ impl PingStore {
     pub fn new() -> Self {
         PingStore {
             latest_ping_id: PingId(0),
-            buffer: SequenceBuffer::new(),
+            buffer: SequenceBuffer::new(PING_BUFFER_SIZE),
         }
     }
 
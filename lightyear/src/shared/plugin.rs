@@ -149,4 +149,15 @@ impl Plugin for SharedPlugin {
         // check that the protocol was built correctly
         app.world.resource::<ComponentRegistry>().check();
     }
+
+    fn cleanup(&self, app: &mut App) {
+        // all the plugins are finished, so every message and component is registered: the types registered
+        // with a stable name can get their net ids
+        app.world
+            .resource_mut::<ComponentRegistry>()
+            .assign_stable_net_ids();
+        app.world
+            .resource_mut::<MessageRegistry>()
+            .assign_stable_net_ids();
+    }
 }
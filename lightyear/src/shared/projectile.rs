@@ -0,0 +1,445 @@
+//! Predict the projectiles fired by the players.
+//!
+//! Firing a projectile (or a hitscan) is the most common case of an entity spawned by the client before the
+//! server: the client can't wait for the server to replicate the projectile, but the server has the authority over it.
+//! This module builds on [`PreSpawnedPlayerObject`] so that the game only has to fire the projectile from a system
+//! that runs on both the client and the server (in `FixedUpdate`, when handling the inputs):
+//! - the client spawns the projectile immediately, and predicts its trajectory
+//! - the server spawns the same projectile when it handles the same input, and replicates it to the clients
+//! - when the client receives the server projectile, it is matched with the client projectile, which becomes the
+//!   predicted entity of the server projectile and adopts the tick of the server projectile; its trajectory is
+//!   then corrected by the usual rollbacks. A [`ProjectileConfirmed`] event is emitted
+//! - if the server never spawns the projectile (for example because the shot was not valid), the client projectile
+//!   is despawned and a [`ProjectileCancelled`] event is emitted
+//!
+//! The projectiles are identified by the client that fired them and an index (to fire multiple projectiles on
+//! the same tick), so there is no need to write any matching logic. The server can fire the projectile a few ticks
+//! apart from the client (for example if the input arrived late): the server projectile is matched with the client
+//! projectile whose tick is the closest, within [`MATCH_TICK_WINDOW`] ticks.
+//! If a rollback re-simulates the tick at which the projectile was fired, the projectile is fired again.
+//!
+//! ```rust,ignore
+//! use bevy::prelude::*;
+//! use lightyear::prelude::*;
+//!
+//! // in the protocol shared by the client and the server
+//! app.add_plugins(ProjectilePlugin);
+//!
+//! // in a FixedUpdate system that runs on both the client and the server
+//! fn shoot(mut commands: Commands, players: Query<(&PlayerId, &ActionState<PlayerActions>, &Position)>) {
+//!     for (player, action, position) in players.iter() {
+//!         if action.just_pressed(&PlayerActions::Shoot) {
+//!             commands.fire_projectile(player.0, 0, (*position, Velocity(Vec2::X * 100.0)));
+//!         }
+//!     }
+//! }
+//! ```
+use std::hash::{Hash, Hasher};
+
+use bevy::ecs::system::Command;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+pub use receive::{ProjectileCancelled, ProjectileConfirmed};
+use serde::{Deserialize, Serialize};
+
+use crate::channel::builder::ChannelDirection;
+use crate::client::components::ComponentSyncMode;
+use crate::client::config::ClientConfig;
+use crate::client::prediction::local_rollback::PredictedSpawn;
+use crate::client::prediction::prespawn::PreSpawnedPlayerObject;
+use crate::client::prediction::rollback::Rollback;
+use crate::connection::id::ClientId;
+use crate::prelude::{AppComponentExt, NetworkTarget, Tick, TickManager};
+use crate::server::replication::send::{Replicate, SyncTarget};
+
+/// Maximum number of ticks between the client projectile and the server projectile for them to be matched
+pub const MATCH_TICK_WINDOW: u16 = 10;
+
+/// Component that identifies a projectile fired with [`ProjectileCommandsExt::fire_projectile`]
+#[derive(Component, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+pub struct Projectile {
+    /// Client that fired the projectile
+    pub shooter: ClientId,
+    /// Tick at which the projectile was fired
+    pub tick: Tick,
+    /// Index of the projectile, to distinguish the projectiles fired by the same client on the same tick
+    pub index: u32,
+}
+
+impl Projectile {
+    /// Hash used by [`PreSpawnedPlayerObject`] to match the client projectile with the server projectile.
+    ///
+    /// The hash includes the tick, so the hash of the server projectile is replaced with the hash of the client
+    /// projectile that it matches (see [`receive::match_server_projectiles`]).
+    fn prespawn_hash(&self) -> u64 {
+        let mut hasher = seahash::SeaHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// Marker for the projectiles fired by the local client that were not confirmed by the server yet
+#[derive(Component, Debug)]
+pub(crate) struct LocalProjectile;
+
+/// Command that fires a projectile. See [`ProjectileCommandsExt::fire_projectile`]
+pub struct FireProjectileCommand<B> {
+    shooter: ClientId,
+    index: u32,
+    bundle: B,
+}
+
+impl<B: Bundle> Command for FireProjectileCommand<B> {
+    fn apply(self, world: &mut World) {
+        // in host-server mode, the server entity is also the client entity
+        let is_server = world.contains_resource::<crate::server::connection::ConnectionManager>();
+        let tick_manager = world.resource::<TickManager>();
+        let tick = match world.get_resource::<Rollback>() {
+            Some(rollback) if !is_server => tick_manager.tick_or_rollback_tick(rollback),
+            _ => tick_manager.tick(),
+        };
+        let projectile = Projectile {
+            shooter: self.shooter,
+            tick,
+            index: self.index,
+        };
+        let prespawn = PreSpawnedPlayerObject::new(projectile.prespawn_hash());
+        if is_server {
+            // the shooter predicts the projectile, the other clients interpolate it
+            world.spawn((
+                self.bundle,
+                projectile,
+                prespawn,
+                Replicate {
+                    sync: SyncTarget {
+                        prediction: NetworkTarget::Single(self.shooter),
+                        interpolation: NetworkTarget::AllExceptSingle(self.shooter),
+                    },
+                    ..default()
+                },
+            ));
+        } else {
+            // the projectile is despawned if a rollback re-simulates the tick at which it was fired,
+            // since it will be fired again
+            let entity = world
+                .spawn((
+                    self.bundle,
+                    projectile,
+                    prespawn,
+                    PredictedSpawn,
+                    LocalProjectile,
+                ))
+                .id();
+            world
+                .resource_mut::<receive::PendingProjectiles>()
+                .projectiles
+                .insert(projectile, entity);
+        }
+    }
+}
+
+/// Fire projectiles that are predicted by the client
+pub trait ProjectileCommandsExt {
+    /// Fire a projectile with the components of the `bundle`.
+    ///
+    /// This must be called with the same arguments on the client and the server, at the same tick or
+    /// within [`MATCH_TICK_WINDOW`] ticks.
+    fn fire_projectile<B: Bundle>(&mut self, shooter: ClientId, index: u32, bundle: B);
+}
+
+impl ProjectileCommandsExt for Commands<'_, '_> {
+    fn fire_projectile<B: Bundle>(&mut self, shooter: ClientId, index: u32, bundle: B) {
+        self.add(FireProjectileCommand {
+            shooter,
+            index,
+            bundle,
+        });
+    }
+}
+
+/// Plugin that handles the projectiles fired with [`ProjectileCommandsExt::fire_projectile`].
+///
+/// It registers the [`Projectile`] component, which the server replicates so that the client can match
+/// its own projectiles. On the client, it also tracks the projectiles that were not confirmed yet, and emits
+/// the [`ProjectileConfirmed`] and [`ProjectileCancelled`] events.
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_component::<Projectile>(ChannelDirection::ServerToClient)
+            .add_prediction(ComponentSyncMode::Once)
+            .add_interpolation(ComponentSyncMode::Once);
+        if app.world.get_resource::<ClientConfig>().is_some() {
+            app.add_plugins(receive::ProjectileReceivePlugin);
+        }
+    }
+}
+
+pub(crate) mod receive {
+    use super::*;
+    use crate::client::prediction::plugin::PredictionSet;
+    use crate::client::prediction::prespawn::PreSpawnedPlayerObjectSet;
+    use crate::client::prediction::Predicted;
+
+    /// Event emitted on the client when a projectile fired by the client was matched with the server projectile
+    #[derive(Event, Debug, Clone, PartialEq)]
+    pub struct ProjectileConfirmed {
+        /// The predicted entity of the projectile
+        pub entity: Entity,
+        pub projectile: Projectile,
+    }
+
+    /// Event emitted on the client when a projectile fired by the client was not spawned by the server,
+    /// and was despawned
+    #[derive(Event, Debug, Clone, PartialEq)]
+    pub struct ProjectileCancelled {
+        pub projectile: Projectile,
+    }
+
+    /// Projectiles fired by the client that were not confirmed by the server yet
+    #[derive(Resource, Default, Debug)]
+    pub(crate) struct PendingProjectiles {
+        pub(crate) projectiles: HashMap<Projectile, Entity>,
+    }
+
+    pub(crate) struct ProjectileReceivePlugin;
+
+    impl Plugin for ProjectileReceivePlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<PendingProjectiles>()
+                .add_event::<ProjectileConfirmed>()
+                .add_event::<ProjectileCancelled>()
+                .add_systems(
+                    PreUpdate,
+                    (
+                        match_server_projectiles.before(PreSpawnedPlayerObjectSet::Spawn),
+                        reconcile_projectiles.after(PreSpawnedPlayerObjectSet::Spawn),
+                    )
+                        .in_set(PredictionSet::SpawnPrediction),
+                );
+        }
+    }
+
+    /// Match the projectiles received from the server with the pending client projectiles that have the same
+    /// shooter and index, and the closest tick within [`MATCH_TICK_WINDOW`].
+    ///
+    /// The hash of the server projectile is replaced with the hash of the client projectile, so that the
+    /// [`PreSpawnedPlayerObject`] systems adopt the client projectile as the predicted entity.
+    pub(crate) fn match_server_projectiles(
+        pending: Res<PendingProjectiles>,
+        mut received: Query<
+            (&Projectile, &mut PreSpawnedPlayerObject),
+            (Added<PreSpawnedPlayerObject>, Without<LocalProjectile>),
+        >,
+    ) {
+        for (server_projectile, mut prespawn) in received.iter_mut() {
+            let Some(client_projectile) = pending
+                .projectiles
+                .keys()
+                .filter(|p| {
+                    p.shooter == server_projectile.shooter && p.index == server_projectile.index
+                })
+                .map(|p| (p, (p.tick - server_projectile.tick).unsigned_abs()))
+                .filter(|(_, diff)| *diff <= MATCH_TICK_WINDOW)
+                .min_by_key(|(_, diff)| *diff)
+                .map(|(p, _)| p)
+            else {
+                continue;
+            };
+            prespawn.hash = Some(client_projectile.prespawn_hash());
+        }
+    }
+
+    /// Emit the events for the projectiles that were matched with a server projectile,
+    /// or that were despawned because the server did not spawn them
+    pub(crate) fn reconcile_projectiles(
+        mut commands: Commands,
+        mut pending: ResMut<PendingProjectiles>,
+        mut confirmed_events: EventWriter<ProjectileConfirmed>,
+        mut cancelled_events: EventWriter<ProjectileCancelled>,
+        confirmed: Query<
+            (Entity, &Projectile, &Predicted),
+            (With<LocalProjectile>, Added<Predicted>),
+        >,
+        server_projectiles: Query<&Projectile, Without<LocalProjectile>>,
+        existing: Query<(), With<LocalProjectile>>,
+    ) {
+        for (entity, projectile, predicted) in confirmed.iter() {
+            pending.projectiles.remove(projectile);
+            // adopt the tick at which the server fired the projectile
+            let projectile = predicted
+                .confirmed_entity
+                .and_then(|confirmed| server_projectiles.get(confirmed).ok())
+                .copied()
+                .unwrap_or(*projectile);
+            // the projectile is now a regular predicted entity
+            commands
+                .entity(entity)
+                .insert(projectile)
+                .remove::<(LocalProjectile, PredictedSpawn)>();
+            confirmed_events.send(ProjectileConfirmed { entity, projectile });
+        }
+        // the projectiles that were not matched in time are despawned by the pre-spawn cleanup
+        pending.projectiles.retain(|projectile, entity| {
+            if existing.contains(*entity) {
+                return true;
+            }
+            cancelled_events.send(ProjectileCancelled {
+                projectile: *projectile,
+            });
+            false
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Duration;
+
+    use crate::client::prediction::Predicted;
+    use crate::prelude::client;
+    use crate::prelude::{SharedConfig, TickConfig};
+    use crate::tests::protocol::Component1;
+    use crate::tests::stepper::{BevyStepper, Step, TEST_CLIENT_ID};
+
+    use super::*;
+
+    fn setup() -> BevyStepper {
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            client::ClientConfig::default(),
+            tick_duration,
+        );
+        for app in [&mut stepper.server_app, &mut stepper.client_app] {
+            app.add_plugins(ProjectilePlugin);
+        }
+        stepper.init();
+        stepper
+    }
+
+    fn fire(world: &mut World) {
+        FireProjectileCommand {
+            shooter: ClientId::Netcode(TEST_CLIENT_ID),
+            index: 0,
+            bundle: Component1(1.0),
+        }
+        .apply(world);
+    }
+
+    #[test]
+    fn test_projectile_confirmed() {
+        let mut stepper = setup();
+        fire(&mut stepper.client_app.world);
+        let tick = stepper.client_tick();
+        // the server fires the projectile at the same tick
+        while stepper.server_tick() < tick {
+            stepper.frame_step();
+        }
+        fire(&mut stepper.server_app.world);
+
+        let mut confirmed = vec![];
+        for _ in 0..20 {
+            stepper.frame_step();
+            confirmed.extend(
+                stepper
+                    .client_app
+                    .world
+                    .resource_mut::<Events<ProjectileConfirmed>>()
+                    .drain(),
+            );
+        }
+        assert_eq!(confirmed.len(), 1);
+        assert_eq!(confirmed[0].projectile.tick, tick);
+        let entity = confirmed[0].entity;
+        assert!(stepper
+            .client_app
+            .world
+            .get::<Predicted>(entity)
+            .is_some_and(|predicted| predicted.confirmed_entity.is_some()));
+        assert!(stepper
+            .client_app
+            .world
+            .resource::<receive::PendingProjectiles>()
+            .projectiles
+            .is_empty());
+    }
+
+    /// The server fires the projectile a few ticks after the client: the projectiles are still matched,
+    /// and the client projectile adopts the server tick
+    #[test]
+    fn test_projectile_confirmed_later_tick() {
+        let mut stepper = setup();
+        fire(&mut stepper.client_app.world);
+        let server_fire_tick = stepper.client_tick() + 3;
+        while stepper.server_tick() < server_fire_tick {
+            stepper.frame_step();
+        }
+        fire(&mut stepper.server_app.world);
+
+        let mut confirmed = vec![];
+        for _ in 0..20 {
+            stepper.frame_step();
+            confirmed.extend(
+                stepper
+                    .client_app
+                    .world
+                    .resource_mut::<Events<ProjectileConfirmed>>()
+                    .drain(),
+            );
+        }
+        assert_eq!(confirmed.len(), 1);
+        assert_eq!(confirmed[0].projectile.tick, server_fire_tick);
+        assert_eq!(
+            stepper
+                .client_app
+                .world
+                .get::<Projectile>(confirmed[0].entity),
+            Some(&confirmed[0].projectile)
+        );
+        // the client projectile was adopted, no other projectile was spawned
+        assert_eq!(
+            stepper
+                .client_app
+                .world
+                .query_filtered::<(), (With<Projectile>, With<Predicted>)>()
+                .iter(&stepper.client_app.world)
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_projectile_cancelled() {
+        let mut stepper = setup();
+        fire(&mut stepper.client_app.world);
+        let tick = stepper.client_tick();
+
+        // the server never fires the projectile
+        let mut cancelled = vec![];
+        for _ in 0..100 {
+            stepper.frame_step();
+            cancelled.extend(
+                stepper
+                    .client_app
+                    .world
+                    .resource_mut::<Events<ProjectileCancelled>>()
+                    .drain(),
+            );
+        }
+        assert_eq!(
+            cancelled,
+            vec![ProjectileCancelled {
+                projectile: Projectile {
+                    shooter: ClientId::Netcode(TEST_CLIENT_ID),
+                    tick,
+                    index: 0,
+                }
+            }]
+        );
+    }
+}
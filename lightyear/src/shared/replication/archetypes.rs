@@ -150,9 +150,10 @@ impl<C: Component> ReplicatedArchetypes<C> {
                     let delta_compression = archetype
                         .components()
                         .any(|c| c == replication_metadata.delta_compression_id);
-                    let replicate_once = archetype
-                        .components()
-                        .any(|c| c == replication_metadata.replicate_once_id);
+                    let replicate_once = replication_metadata.immutable
+                        || archetype
+                            .components()
+                            .any(|c| c == replication_metadata.replicate_once_id);
                     let override_target = archetype
                         .components()
                         .any(|c| c == replication_metadata.override_target_id)
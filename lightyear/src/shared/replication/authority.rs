@@ -0,0 +1,359 @@
+//! Transfer the authority over an entity between the server and the clients.
+//!
+//! By default the server simulates the entities that it replicates. The server can hand the authority
+//! over an entity to a client (for example physics props that are close to a player), in which case:
+//! - the client starts replicating the entity back to the server, and its updates are applied to the server entity
+//! - the server relays the state of the entity to the other clients, but stops sending updates to the client with authority
+//! - updates received from any other client for that entity are ignored, so conflicting claims are resolved in favor
+//!   of the peer that the server gave the authority to
+//!
+//! The server can take the authority back at any time with [`AuthorityCommandExt::transfer_authority`].
+//! The authority also goes back to the server when the client with authority disconnects.
+use bevy::ecs::system::EntityCommands;
+use bevy::prelude::{Component, Entity, EntityWorldMut, EventReader, Query, Reflect, World};
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::connection::id::ClientId;
+use crate::serialize::reader::Reader;
+use crate::serialize::{SerializationError, ToBytes};
+use crate::server::connection::ConnectionManager;
+use crate::server::events::DisconnectEvent;
+use crate::shared::replication::components::Replicated;
+
+/// Component inserted on the server entity to indicate which peer has authority over the entity.
+///
+/// If the component is absent, the server has authority, except over the entities that a client spawned
+/// by replicating them to the server: that client keeps the authority over them.
+#[derive(
+    Component, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Reflect,
+)]
+pub enum AuthorityPeer {
+    /// The server simulates the entity
+    #[default]
+    Server,
+    /// The client simulates the entity and replicates it to the server
+    Client(ClientId),
+}
+
+/// Marker component inserted on a client entity while the client has authority over it.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Reflect)]
+pub struct HasAuthority;
+
+/// Message sent by the server to a client when the client gains or loses the authority over an entity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct AuthorityChange {
+    /// The entity in the server's World
+    pub(crate) entity: Entity,
+    pub(crate) gain_authority: bool,
+}
+
+impl ToBytes for AuthorityChange {
+    fn len(&self) -> usize {
+        self.entity.len() + 1
+    }
+
+    fn to_bytes<T: WriteBytesExt>(&self, buffer: &mut T) -> Result<(), SerializationError> {
+        self.entity.to_bytes(buffer)?;
+        buffer.write_u8(self.gain_authority as u8)?;
+        Ok(())
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
+    where
+        Self: Sized,
+    {
+        let entity = Entity::from_bytes(buffer)?;
+        let gain_authority = buffer.read_u8()? != 0;
+        Ok(Self {
+            entity,
+            gain_authority,
+        })
+    }
+}
+
+/// Returns true if the replication messages received from `remote` can be applied to the entity.
+///
+/// On the server (`remote` is a client), only the client with authority can update the entity.
+/// On the client (`remote` is the server), the server updates are ignored while the client has authority.
+pub(crate) fn accepts_updates_from(entity: &EntityWorldMut, remote: Option<ClientId>) -> bool {
+    match remote {
+        Some(client_id) => match entity.get::<AuthorityPeer>() {
+            Some(authority) => *authority == AuthorityPeer::Client(client_id),
+            // the entity was spawned by the client replicating it to the server
+            None => entity
+                .get::<Replicated>()
+                .is_some_and(|replicated| replicated.from == Some(client_id)),
+        },
+        None => !entity.contains::<HasAuthority>(),
+    }
+}
+
+/// Give the authority back to the server when the client with authority disconnects
+pub(crate) fn reset_authority_on_disconnect(
+    mut events: EventReader<DisconnectEvent>,
+    mut query: Query<&mut AuthorityPeer>,
+) {
+    for event in events.read() {
+        for mut authority in query.iter_mut() {
+            if *authority == AuthorityPeer::Client(event.client_id) {
+                *authority = AuthorityPeer::Server;
+            }
+        }
+    }
+}
+
+fn transfer_authority(entity: Entity, authority: AuthorityPeer, world: &mut World) {
+    let Some(mut entity_mut) = world.get_entity_mut(entity) else {
+        error!(
+            ?entity,
+            "cannot transfer the authority of an entity that does not exist"
+        );
+        return;
+    };
+    let previous = entity_mut
+        .get::<AuthorityPeer>()
+        .copied()
+        .unwrap_or_default();
+    if previous == authority {
+        return;
+    }
+    entity_mut.insert(authority);
+    let mut sender = world.resource_mut::<ConnectionManager>();
+    for (peer, gain_authority) in [(previous, false), (authority, true)] {
+        if let AuthorityPeer::Client(client_id) = peer {
+            let _ = sender
+                .connection_mut(client_id)
+                .and_then(|connection| {
+                    connection.send_authority_change(AuthorityChange {
+                        entity,
+                        gain_authority,
+                    })
+                })
+                .inspect_err(|e| error!("could not send the authority change: {:?}", e));
+        }
+    }
+}
+
+pub trait AuthorityCommandExt {
+    /// Give the authority over the entity to `authority`.
+    ///
+    /// The client that loses the authority stops replicating the entity, and the client that gains
+    /// the authority starts replicating it to the server.
+    fn transfer_authority(&mut self, authority: AuthorityPeer);
+}
+
+impl AuthorityCommandExt for EntityCommands<'_> {
+    fn transfer_authority(&mut self, authority: AuthorityPeer) {
+        self.add(move |entity, world: &mut World| transfer_authority(entity, authority, world));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::prelude::{Commands, With};
+
+    use crate::client::replication::send::ReplicateToServer;
+    use crate::prelude::client::ClientCommands;
+    use crate::prelude::server::Replicate;
+    use crate::prelude::{client, ClientId, TargetEntity};
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step, TEST_CLIENT_ID};
+
+    use super::*;
+
+    #[test]
+    fn test_authority_change_serialization() {
+        let change = AuthorityChange {
+            entity: Entity::from_raw(3),
+            gain_authority: true,
+        };
+        let mut writer = crate::serialize::writer::Writer::default();
+        change.to_bytes(&mut writer).unwrap();
+        let mut reader = Reader::from(writer.to_bytes());
+        assert_eq!(AuthorityChange::from_bytes(&mut reader).unwrap(), change);
+    }
+
+    #[test]
+    fn test_transfer_authority() {
+        let mut stepper = BevyStepper::default();
+        let client_id = ClientId::Netcode(TEST_CLIENT_ID);
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((Component1(1.0), Replicate::default()))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_entity = *stepper
+            .client_app
+            .world
+            .resource::<client::ConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .unwrap();
+
+        // give the authority to the client
+        transfer_authority(
+            server_entity,
+            AuthorityPeer::Client(client_id),
+            &mut stepper.server_app.world,
+        );
+        stepper.frame_step();
+        stepper.frame_step();
+        assert!(stepper
+            .client_app
+            .world
+            .get::<HasAuthority>(client_entity)
+            .is_some());
+        assert!(stepper
+            .client_app
+            .world
+            .get::<ReplicateToServer>(client_entity)
+            .is_some());
+
+        // the client updates are applied on the server, and the server updates are not sent to the client
+        stepper
+            .client_app
+            .world
+            .entity_mut(client_entity)
+            .insert(Component1(2.0));
+        // the client's tick is ahead of the server's, and the server applies the replication messages
+        // of a client once its own tick reaches the tick at which they were sent
+        let update_tick = stepper.client_tick() + 1;
+        while stepper.server_tick() < update_tick {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper.server_app.world.get::<Component1>(server_entity),
+            Some(&Component1(2.0))
+        );
+        assert_eq!(
+            stepper
+                .server_app
+                .world
+                .get::<Replicated>(server_entity)
+                .unwrap()
+                .from,
+            Some(client_id)
+        );
+
+        // take the authority back
+        transfer_authority(
+            server_entity,
+            AuthorityPeer::Server,
+            &mut stepper.server_app.world,
+        );
+        stepper.frame_step();
+        stepper.frame_step();
+        assert!(stepper
+            .client_app
+            .world
+            .get::<HasAuthority>(client_entity)
+            .is_none());
+        // the client does not replicate the entity anymore, and its updates are ignored
+        stepper
+            .client_app
+            .world
+            .entity_mut(client_entity)
+            .insert(Component1(3.0));
+        stepper
+            .server_app
+            .world
+            .entity_mut(server_entity)
+            .insert(Component1(4.0));
+        stepper.frame_step();
+        stepper.frame_step();
+        assert_eq!(
+            stepper.server_app.world.get::<Component1>(server_entity),
+            Some(&Component1(4.0))
+        );
+        assert_eq!(
+            stepper.client_app.world.get::<Component1>(client_entity),
+            Some(&Component1(4.0))
+        );
+        assert!(stepper
+            .client_app
+            .world
+            .query_filtered::<Entity, With<ReplicateToServer>>()
+            .iter(&stepper.client_app.world)
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn test_updates_without_authority() {
+        let mut stepper = BevyStepper::default();
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((Component1(1.0), Replicate::default()))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_entity = *stepper
+            .client_app
+            .world
+            .resource::<client::ConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .unwrap();
+
+        // the client cannot replicate to an entity of the server without authority
+        stepper.client_app.world.entity_mut(client_entity).insert((
+            Component1(2.0),
+            client::Replicate::default(),
+            TargetEntity::Preexisting(server_entity),
+        ));
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper.server_app.world.get::<Component1>(server_entity),
+            Some(&Component1(1.0))
+        );
+        assert_eq!(
+            stepper
+                .server_app
+                .world
+                .get::<Replicated>(server_entity)
+                .map(|replicated| replicated.from),
+            None
+        );
+    }
+
+    #[test]
+    fn test_authority_reset_on_disconnect() {
+        let mut stepper = BevyStepper::default();
+        let client_id = ClientId::Netcode(TEST_CLIENT_ID);
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((Component1(1.0), Replicate::default()))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        transfer_authority(
+            server_entity,
+            AuthorityPeer::Client(client_id),
+            &mut stepper.server_app.world,
+        );
+        stepper.frame_step();
+
+        stepper
+            .client_app
+            .world
+            .run_system_once(|mut commands: Commands| commands.disconnect_client());
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper.server_app.world.get::<AuthorityPeer>(server_entity),
+            Some(&AuthorityPeer::Server)
+        );
+    }
+}
@@ -2,13 +2,16 @@
 use bevy::ecs::reflect::ReflectComponent;
 use bevy::prelude::{Component, Entity, Reflect};
 use bevy::time::{Timer, TimerMode};
-use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+use bevy::utils::Duration;
+use byteorder::WriteBytesExt;
 use serde::{Deserialize, Serialize};
 
 use crate::connection::id::ClientId;
 use crate::serialize::reader::Reader;
+use crate::serialize::varint::{varint_len, VarIntReadExt, VarIntWriteExt};
 use crate::serialize::{SerializationError, ToBytes};
 use crate::shared::replication::network_target::NetworkTarget;
+use crate::shared::tick_manager::Tick;
 
 /// Marker component that indicates that the entity was spawned via replication
 /// (it is being replicated from a remote world)
@@ -73,6 +76,9 @@ pub enum TargetEntity {
     Spawn,
     /// Instead of spawning a new entity, we will apply the replication updates
     /// to the existing remote entity
+    ///
+    /// A client can only replicate to an existing server entity if the server gave it the
+    /// [`AuthorityPeer`](crate::prelude::AuthorityPeer) over the entity.
     Preexisting(Entity),
 }
 
@@ -144,11 +150,113 @@ impl<C> Default for ReplicateOnceComponent<C> {
     }
 }
 
+/// Marker component for entities that don't change after they are spawned (level geometry, props, etc.)
+///
+/// The components of the entity are replicated when the entity is spawned on a client (or becomes relevant
+/// to a client), but the server does not scan the entity for changes afterwards.
+/// If the entity does change, mark it as dirty by re-inserting the component (or with
+/// [`set_changed`](bevy::prelude::DetectChangesMut::set_changed)): the changes will be replicated on the next send.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct ReplicateOnce;
+
+/// Component to sample the updates of an entity less often than the send interval of the server.
+///
+/// For example, background or slow-moving entities can use `ReplicationRate::from_hz(5.0)` while the characters
+/// of the players are updated at every send interval.
+/// Only the component updates are throttled: spawns and component insertions are sent immediately, and the
+/// changes that happen between two samples are sent together on the next sample.
+///
+/// The updates are still buffered at the send interval, so a rate that is faster than the send interval has no effect.
+/// To throttle a whole [`ReplicationGroup`], see [`ReplicationGroup::set_send_frequency`].
+#[derive(Component, Clone, Debug, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct ReplicationRate {
+    interval: RateInterval,
+    /// Is true if the updates of the entity should be buffered at the next send interval
+    pub(crate) should_send: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Reflect)]
+enum RateInterval {
+    Timer(Timer),
+    Ticks { ticks: u16, last_send: Option<Tick> },
+}
+
+impl ReplicationRate {
+    /// Sample the updates of the entity `hz` times per second
+    pub fn from_hz(hz: f32) -> Self {
+        assert!(hz > 0.0, "the replication rate must be positive");
+        Self::from_interval(Duration::from_secs_f32(1.0 / hz))
+    }
+
+    /// Sample the updates of the entity once every `interval`
+    pub fn from_interval(interval: Duration) -> Self {
+        Self {
+            interval: RateInterval::Timer(Timer::new(interval, TimerMode::Repeating)),
+            should_send: true,
+        }
+    }
+
+    /// Sample the updates of the entity once every `ticks` server ticks
+    pub fn every_ticks(ticks: u16) -> Self {
+        Self {
+            interval: RateInterval::Ticks {
+                ticks,
+                last_send: None,
+            },
+            should_send: true,
+        }
+    }
+
+    /// Advance the rate by one frame
+    pub(crate) fn tick(&mut self, delta: Duration, tick: Tick) {
+        match &mut self.interval {
+            RateInterval::Timer(timer) => {
+                timer.tick(delta);
+                if timer.finished() {
+                    self.should_send = true;
+                }
+            }
+            RateInterval::Ticks { ticks, last_send } => {
+                if last_send.map_or(true, |last_send| tick - last_send >= *ticks as i16) {
+                    self.should_send = true;
+                }
+            }
+        }
+    }
+
+    /// Mark the updates as sent after a send interval where `should_send` was true
+    pub(crate) fn sent(&mut self, tick: Tick) {
+        if let RateInterval::Ticks { last_send, .. } = &mut self.interval {
+            *last_send = Some(tick);
+        }
+        self.should_send = false;
+    }
+}
+
+/// Marker component that temporarily stops the replication of the components of an entity.
+///
+/// The entity is not despawned on the clients, but its components are not inserted or updated while the
+/// component is present (for example while the entity is scripted through a cutscene on the server).
+/// Component removals and despawns are still replicated.
+///
+/// When the component is removed, the current value of all the replicated components is sent again to the clients.
+/// See also [`PauseReplicationCommandExt`](crate::prelude::server::PauseReplicationCommandExt).
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct PauseReplication;
+
 // TODO: maybe have 3 fields:
 //  - target
 //  - override replication_target: bool (if true, we will completely override the replication target. If false, we do the intersection)
 //  - override visibility: bool (if true, we will completely override the visibility. If false, we do the intersection)
 /// This component lets you override the replication target for a specific component
+///
+/// For example, `OverrideTargetComponent::<Inventory>::new(NetworkTarget::Single(owner))` replicates the
+/// `Inventory` of the entity only to its owner.
+/// The target can be updated at any time: the component is then inserted on the clients that were added
+/// to the target, and removed from the clients that are no longer part of it.
 #[derive(Component, Clone, Debug, PartialEq, Reflect)]
 #[reflect(Component)]
 pub struct OverrideTargetComponent<C> {
@@ -254,6 +362,16 @@ impl ReplicationGroup {
         self
     }
 
+    /// Update the priority of the group after it was spawned.
+    ///
+    /// The new priority is applied to all the clients that the group is replicated to. When the bandwidth cap
+    /// is enabled, this can be used to send updates for nearby or important entities more often than for distant ones.
+    /// To use a different priority for each client, see
+    /// [`ConnectionManager::update_priority`](crate::server::connection::ConnectionManager::update_priority).
+    pub fn update_priority(&mut self, priority: f32) {
+        self.base_priority = priority;
+    }
+
     pub fn set_id(mut self, id: u64) -> Self {
         self.id_builder = ReplicationGroupIdBuilder::Group(id);
         self
@@ -276,13 +394,24 @@ impl ReplicationGroup {
 #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Reflect)]
 pub struct ReplicationGroupId(pub u64);
 
+/// The low 32 bits of the id are written as a varint, with a 2-bit tag for the high 32 bits:
+/// - 0: the high bits are 0 (ids set manually with [`ReplicationGroup::new_id`])
+/// - 1: the high bits are 1 (ids built from an [`Entity`] that was never recycled)
+/// - 2: the high bits are written as a separate varint
 impl ToBytes for ReplicationGroupId {
     fn len(&self) -> usize {
-        8
+        let (low, high) = self.split();
+        let high_len = if high > 1 { varint_len(high) } else { 0 };
+        varint_len(low << 2) + high_len
     }
 
     fn to_bytes<T: WriteBytesExt>(&self, buffer: &mut T) -> Result<(), SerializationError> {
-        buffer.write_u64::<NetworkEndian>(self.0)?;
+        let (low, high) = self.split();
+        let tag = high.min(2);
+        buffer.write_varint(low << 2 | tag)?;
+        if tag == 2 {
+            buffer.write_varint(high)?;
+        }
         Ok(())
     }
 
@@ -290,7 +419,18 @@ impl ToBytes for ReplicationGroupId {
     where
         Self: Sized,
     {
-        Ok(Self(buffer.read_u64::<NetworkEndian>()?))
+        let value = buffer.read_varint()?;
+        let high = match value & 0b11 {
+            2 => buffer.read_varint()?,
+            tag => tag,
+        };
+        Ok(Self(high << 32 | value >> 2))
+    }
+}
+
+impl ReplicationGroupId {
+    fn split(&self) -> (u64, u64) {
+        (self.0 & u32::MAX as u64, self.0 >> 32)
     }
 }
 
@@ -124,14 +124,12 @@ impl DeltaManager {
     ///
     /// We remove every tick that is too old (which means we cannot do delta compression and
     /// we will be sending a full component value)
-    pub(crate) fn tick_cleanup(&mut self, current_tick: Tick) {
+    pub(crate) fn tick_cleanup(&mut self, current_tick: Tick, registry: &ComponentRegistry) {
         let delta = (u16::MAX / 3) as i16;
         self.acks.values_mut().for_each(|group_data| {
-            group_data.retain(|k, _| *k - current_tick > delta);
-        });
-        self.data.data.values_mut().for_each(|group_data| {
-            group_data.retain(|k, _| *k - current_tick > delta);
+            group_data.retain(|k, _| current_tick - *k <= delta);
         });
+        self.data.delete_stale_data(current_tick, delta, registry);
     }
 }
 
@@ -215,6 +213,27 @@ impl DeltaComponentStore {
             *data = recent_data;
         }
     }
+
+    /// Remove (and drop) the data for all ticks that are more than `max_age` ticks older than `current_tick`
+    pub(crate) fn delete_stale_data(
+        &mut self,
+        current_tick: Tick,
+        max_age: i16,
+        registry: &ComponentRegistry,
+    ) {
+        self.data.values_mut().for_each(|group_data| {
+            group_data.retain(|tick, tick_data| {
+                let keep = current_tick - *tick <= max_age;
+                if !keep {
+                    tick_data.iter().for_each(|(kind, _, owned_ptr)| unsafe {
+                        // SAFETY: the ptr corresponds to the kind
+                        registry.erased_drop(*owned_ptr, *kind).unwrap();
+                    });
+                }
+                keep
+            });
+        });
+    }
 }
 
 #[cfg(test)]
@@ -279,4 +298,46 @@ mod tests {
         let retrieved_component = unsafe { retrieved.deref::<Component6>() };
         assert_eq!(retrieved_component, &component);
     }
+
+    #[test]
+    fn test_tick_cleanup() {
+        let mut registry = ComponentRegistry::default();
+        registry.register_component::<Component6>();
+        registry.set_delta_compression::<Component6>();
+        let mut manager = DeltaManager::default();
+        let entity = Entity::from_raw(0);
+        let old_tick = Tick(1);
+        let recent_tick = Tick(30000);
+        let replication_group = ReplicationGroupId(0);
+        let component = Component6(vec![1, 2]);
+        let ptr = Ptr::from(&component);
+        let kind = ComponentKind::of::<Component6>();
+        manager.data.store_component_value(
+            entity,
+            old_tick,
+            kind,
+            ptr,
+            replication_group,
+            &registry,
+        );
+        manager.data.store_component_value(
+            entity,
+            recent_tick,
+            kind,
+            ptr,
+            replication_group,
+            &registry,
+        );
+
+        // the recent value can still be used as a baseline, the old one is dropped
+        manager.tick_cleanup(Tick(30010), &registry);
+        assert!(manager
+            .data
+            .get_component_value(entity, old_tick, kind, replication_group)
+            .is_none());
+        assert!(manager
+            .data
+            .get_component_value(entity, recent_tick, kind, replication_group)
+            .is_some());
+    }
 }
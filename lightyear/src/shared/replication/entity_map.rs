@@ -4,6 +4,8 @@ use bevy::prelude::{Deref, DerefMut, Entity, EntityWorldMut, World};
 use bevy::reflect::Reflect;
 use bevy::utils::hashbrown::hash_map::Entry;
 
+use crate::client::events::EntityMapEvent;
+
 #[derive(Default, Debug, Reflect, Deref, DerefMut)]
 pub struct EntityMap(pub(crate) EntityHashMap<Entity>);
 
@@ -19,6 +21,11 @@ impl EntityMapper for EntityMap {
 pub struct RemoteEntityMap {
     pub(crate) remote_to_local: EntityMap,
     pub(crate) local_to_remote: EntityMap,
+    /// Changes in the mapping since the last call to [`RemoteEntityMap::drain_changes`].
+    ///
+    /// Only recorded if enabled with [`RemoteEntityMap::record_changes`]
+    #[reflect(ignore)]
+    changes: Option<Vec<EntityMapEvent>>,
 }
 
 #[derive(Default, Debug, Reflect)]
@@ -40,6 +47,29 @@ impl RemoteEntityMap {
     pub fn insert(&mut self, remote_entity: Entity, local_entity: Entity) {
         self.remote_to_local.insert(remote_entity, local_entity);
         self.local_to_remote.insert(local_entity, remote_entity);
+        self.record(EntityMapEvent::Mapped {
+            remote: remote_entity,
+            local: local_entity,
+        });
+    }
+
+    /// Start recording the changes in the mapping, so that they can be emitted as events
+    pub(crate) fn record_changes(&mut self) {
+        self.changes.get_or_insert_with(Vec::new);
+    }
+
+    /// Return the changes in the mapping since the last call
+    pub(crate) fn drain_changes(&mut self) -> Vec<EntityMapEvent> {
+        self.changes
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    fn record(&mut self, event: EntityMapEvent) {
+        if let Some(changes) = &mut self.changes {
+            changes.push(event);
+        }
     }
 
     // pub(crate) fn get_to_remote_mapper(&self) -> Box<dyn EntityMapper + '_> {
@@ -84,6 +114,10 @@ impl RemoteEntityMap {
                 entry.insert(local_entity.id());
                 self.local_to_remote
                     .insert(local_entity.id(), remote_entity);
+                self.record(EntityMapEvent::Mapped {
+                    remote: remote_entity,
+                    local: local_entity.id(),
+                });
                 local_entity
             }
         }
@@ -93,6 +127,10 @@ impl RemoteEntityMap {
         let local_entity = self.remote_to_local.remove(&remote_entity);
         if let Some(local_entity) = local_entity {
             self.local_to_remote.remove(&local_entity);
+            self.record(EntityMapEvent::Unmapped {
+                remote: remote_entity,
+                local: local_entity,
+            });
         }
         local_entity
     }
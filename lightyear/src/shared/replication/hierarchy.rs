@@ -1,13 +1,13 @@
 //! This module is responsible for making sure that parent-children hierarchies are replicated correctly.
-use bevy::ecs::entity::MapEntities;
+use bevy::ecs::entity::{EntityHashSet, MapEntities};
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use crate::prelude::server::ControlledBy;
-use crate::prelude::{NetworkRelevanceMode, Replicating, ReplicationGroup};
+use crate::prelude::{NetworkRelevanceMode, Replicated, Replicating, ReplicationGroup};
 use crate::server::replication::send::SyncTarget;
 use crate::shared::replication::components::{ReplicateHierarchy, ReplicationTarget};
-use crate::shared::replication::{ReplicationPeer, ReplicationSend};
+use crate::shared::replication::{ReplicationReceive, ReplicationSend};
 use crate::shared::sets::{InternalMainSet, InternalReplicationSet};
 
 /// This component can be added to an entity to replicate the entity's hierarchy to the remote world.
@@ -50,6 +50,7 @@ impl<R: ReplicationSend> HierarchySendPlugin<R> {
                 Entity,
                 Ref<ReplicateHierarchy>,
                 &ReplicationTarget,
+                Option<&ReplicationGroup>,
                 Option<&SyncTarget>,
                 Option<&ControlledBy>,
                 Option<&NetworkRelevanceMode>,
@@ -62,12 +63,19 @@ impl<R: ReplicationSend> HierarchySendPlugin<R> {
             parent_entity,
             replicate_hierarchy,
             replication_target,
+            group,
             sync_target,
             controlled_by,
             visibility_mode,
         ) in parent_query.iter()
         {
             if replicate_hierarchy.is_changed() && replicate_hierarchy.recursive {
+                // the entire hierarchy is replicated as a single group, so that the updates of the parent
+                // and its children are always applied on the same tick. We keep the parent's group id and settings
+                let group = group
+                    .map_or(ReplicationGroup::new_id(parent_entity.to_bits()), |group| {
+                        group.clone().set_id(group.group_id(Some(parent_entity)).0)
+                    });
                 // iterate through all descendents of the entity
                 for child in children_query.iter_descendants(parent_entity) {
                     trace!("Propagate Replicate through hierarchy: adding Replicate on child: {child:?}");
@@ -76,8 +84,7 @@ impl<R: ReplicationSend> HierarchySendPlugin<R> {
                         // TODO: should we add replicating?
                         Replicating,
                         replication_target.clone(),
-                        // the entire hierarchy is replicated as a single group, that uses the parent's entity as the group id
-                        ReplicationGroup::new_id(parent_entity.to_bits()),
+                        group.clone(),
                         ReplicateHierarchy { recursive: true },
                         ParentSync(None),
                     ));
@@ -159,36 +166,61 @@ impl<R> Default for HierarchyReceivePlugin<R> {
     }
 }
 
-impl<R> HierarchyReceivePlugin<R> {
+impl<R: ReplicationReceive> HierarchyReceivePlugin<R> {
     /// Update parent/children hierarchy if parent_sync changed
     ///
+    /// If the parent has not been replicated yet (for example because it is in a different
+    /// [`ReplicationGroup`]), the entity mapping of `ParentSync` failed and it still contains the remote entity.
+    /// In that case we wait until the parent is spawned to set the parent.
+    ///
     /// This only runs on the receiving side
     fn update_parent(
         mut commands: Commands,
-        hierarchy: Query<
-            (Entity, &ParentSync, Option<&Parent>),
-            (Changed<ParentSync>, Without<ReplicationTarget>),
-        >,
+        receiver: Res<R>,
+        mut pending: Local<EntityHashSet>,
+        changed: Query<Entity, (Changed<ParentSync>, Without<ReplicationTarget>)>,
+        hierarchy: Query<(&ParentSync, Option<&Parent>, &Replicated), Without<ReplicationTarget>>,
     ) {
-        for (entity, parent_sync, parent) in hierarchy.iter() {
+        pending.extend(changed.iter());
+        pending.retain(|&entity| {
+            let Ok((parent_sync, parent, replicated)) = hierarchy.get(entity) else {
+                // the entity was despawned
+                return false;
+            };
             trace!(
                 "update_parent: entity: {:?}, parent_sync: {:?}, parent: {:?}",
                 entity,
                 parent_sync,
                 parent
             );
-            if let Some(new_parent) = parent_sync.0 {
-                if parent.filter(|&parent| **parent == new_parent).is_none() {
-                    commands.entity(entity).set_parent(new_parent);
+            let Some(new_parent) = parent_sync.0 else {
+                if parent.is_some() {
+                    commands.entity(entity).remove_parent();
                 }
-            } else if parent.is_some() {
-                commands.entity(entity).remove_parent();
+                return false;
+            };
+            let Some(entity_map) = receiver.remote_entity_map(replicated.from) else {
+                return false;
+            };
+            let new_parent = if entity_map.get_remote(new_parent).is_some() {
+                // the parent was mapped successfully to a local entity
+                new_parent
+            } else if let Some(local_parent) = entity_map.get_local(new_parent) {
+                // the parent got spawned after we received the ParentSync
+                *local_parent
+            } else {
+                trace!(?entity, "parent {new_parent:?} has not been replicated yet");
+                return true;
+            };
+            if parent.filter(|&parent| **parent == new_parent).is_none() {
+                commands.entity(entity).set_parent(new_parent);
             }
-        }
+            false
+        });
     }
 }
 
-impl<R: ReplicationPeer> Plugin for HierarchyReceivePlugin<R> {
+impl<R: ReplicationReceive> Plugin for HierarchyReceivePlugin<R> {
     fn build(&self, app: &mut App) {
         // REFLECTION
         app.register_type::<ParentSync>();
@@ -210,7 +242,7 @@ mod tests {
     use bevy::prelude::{default, Entity, With};
 
     use crate::prelude::server::Replicate;
-    use crate::prelude::ReplicationGroup;
+    use crate::prelude::{Replicated, ReplicationGroup};
     use crate::shared::replication::components::ReplicateHierarchy;
     use crate::shared::replication::hierarchy::ParentSync;
     use crate::tests::protocol::*;
@@ -314,6 +346,95 @@ mod tests {
             .is_none());
     }
 
+    /// The child is replicated before its parent, in a different replication group
+    #[test]
+    fn test_update_parent_spawned_later() {
+        let (mut stepper, grandparent, parent, _) = setup_hierarchy();
+
+        stepper.server_app.world.entity_mut(parent).insert((
+            Replicate {
+                hierarchy: ReplicateHierarchy { recursive: false },
+                group: ReplicationGroup::new_id(1),
+                ..default()
+            },
+            ParentSync::default(),
+        ));
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_parent = stepper
+            .client_app
+            .world
+            .query_filtered::<Entity, With<Component2>>()
+            .get_single(&stepper.client_app.world)
+            .unwrap();
+        // the grandparent is not replicated yet, so the parent cannot be set
+        assert!(stepper
+            .client_app
+            .world
+            .entity(client_parent)
+            .get::<Parent>()
+            .is_none());
+
+        stepper
+            .server_app
+            .world
+            .entity_mut(grandparent)
+            .insert(Replicate {
+                hierarchy: ReplicateHierarchy { recursive: false },
+                group: ReplicationGroup::new_id(2),
+                ..default()
+            });
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_grandparent = stepper
+            .client_app
+            .world
+            .query_filtered::<Entity, With<Component1>>()
+            .get_single(&stepper.client_app.world)
+            .unwrap();
+        assert_eq!(
+            stepper
+                .client_app
+                .world
+                .entity(client_parent)
+                .get::<Parent>()
+                .map(|parent| parent.get()),
+            Some(client_grandparent)
+        );
+    }
+
+    /// The children are replicated in the parent's group, even if it is not the default group
+    #[test]
+    fn test_propagate_hierarchy_custom_group() {
+        let (mut stepper, grandparent, parent, child) = setup_hierarchy();
+        let group = ReplicationGroup::new_id(7).set_priority(2.0);
+        stepper
+            .server_app
+            .world
+            .entity_mut(grandparent)
+            .insert(Replicate {
+                group: group.clone(),
+                ..default()
+            });
+        stepper.frame_step();
+        stepper.frame_step();
+
+        for entity in [parent, child] {
+            assert_eq!(
+                stepper.server_app.world.get::<ReplicationGroup>(entity),
+                Some(&group)
+            );
+        }
+        // the whole hierarchy is received by the client in a single group
+        let client_entities = stepper
+            .client_app
+            .world
+            .query_filtered::<Entity, With<Replicated>>()
+            .iter(&stepper.client_app.world)
+            .count();
+        assert_eq!(client_entities, 3);
+    }
+
     #[test]
     fn test_propagate_hierarchy() {
         // tracing_subscriber::FmtSubscriber::builder()
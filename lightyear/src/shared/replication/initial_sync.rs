@@ -0,0 +1,123 @@
+//! Emit an [`InitialSyncComplete`](crate::prelude::client::InitialSyncComplete) event on the client
+//! once it has replicated the entities that existed when it connected.
+//!
+//! There is no dedicated snapshot format: the existing entities are sent with the regular replication
+//! messages (reliable entity actions, one message per [`ReplicationGroup`](crate::prelude::ReplicationGroup),
+//! fragmented if needed, without extra compression).
+//! Right after the first of these messages, the server sends an [`InitialSync`] marker that contains the id of
+//! the next actions message of each group, so that the client knows which messages it has to wait for.
+//! The event can be used to hide a loading screen for example.
+use crate::packet::message::MessageId;
+use crate::serialize::reader::Reader;
+use crate::serialize::{SerializationError, ToBytes};
+use crate::shared::replication::components::ReplicationGroupId;
+use crate::shared::replication::receive::ReplicationReceiver;
+use byteorder::WriteBytesExt;
+
+/// Marker sent by the server to a newly connected client after the first replication messages.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct InitialSync {
+    /// For each group, the id of the first actions message sent after the marker
+    pub(crate) groups: Vec<(ReplicationGroupId, MessageId)>,
+}
+
+impl InitialSync {
+    /// Returns true if the receiver has applied all the actions messages sent before the marker
+    pub(crate) fn is_complete(&self, receiver: &ReplicationReceiver) -> bool {
+        self.groups.iter().all(|(group_id, next_message_id)| {
+            receiver
+                .group_channels
+                .get(group_id)
+                .is_some_and(|channel| channel.actions_pending_recv_message_id >= *next_message_id)
+        })
+    }
+}
+
+impl ToBytes for InitialSync {
+    fn len(&self) -> usize {
+        ToBytes::len(&self.groups)
+    }
+
+    fn to_bytes<T: WriteBytesExt>(&self, buffer: &mut T) -> Result<(), SerializationError> {
+        self.groups.to_bytes(buffer)
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            groups: Vec::from_bytes(buffer)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::{EventReader, Query, ResMut, Resource, Update, With};
+    use bevy::utils::Duration;
+
+    use crate::prelude::client::{ClientConfig, InitialSyncComplete};
+    use crate::prelude::server::Replicate;
+    use crate::prelude::{Replicated, SharedConfig, TickConfig};
+    use crate::serialize::writer::Writer;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    #[test]
+    fn test_initial_sync_serialization() {
+        let message = InitialSync {
+            groups: vec![
+                (ReplicationGroupId(1), MessageId(3)),
+                (ReplicationGroupId(u64::MAX), MessageId(0)),
+            ],
+        };
+        let mut writer = Writer::default();
+        message.to_bytes(&mut writer).unwrap();
+        let mut reader = Reader::from(writer.to_bytes());
+        assert_eq!(InitialSync::from_bytes(&mut reader).unwrap(), message);
+    }
+
+    #[derive(Resource, Default)]
+    struct SyncedEntities(Option<usize>);
+
+    fn count_synced_entities(
+        mut events: EventReader<InitialSyncComplete>,
+        query: Query<(), With<Replicated>>,
+        mut synced: ResMut<SyncedEntities>,
+    ) {
+        for _ in events.read() {
+            synced.0 = Some(query.iter().count());
+        }
+    }
+
+    #[test]
+    fn test_initial_sync_complete() {
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(shared_config, ClientConfig::default(), tick_duration);
+        // the world already contains entities when the client connects
+        for i in 0..3 {
+            stepper
+                .server_app
+                .world
+                .spawn((Component1(i as f32), Replicate::default()));
+        }
+        stepper
+            .client_app
+            .init_resource::<SyncedEntities>()
+            .add_systems(Update, count_synced_entities);
+        stepper.init();
+        stepper.frame_step();
+        stepper.frame_step();
+        assert_eq!(
+            stepper.client_app.world.resource::<SyncedEntities>().0,
+            Some(3)
+        );
+    }
+}
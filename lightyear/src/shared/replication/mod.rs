@@ -2,14 +2,15 @@
 use std::fmt::Debug;
 use std::hash::Hash;
 
-use bevy::prelude::{Entity, Resource};
+use bevy::prelude::{Entity, Reflect, Resource};
 use byteorder::{ReadBytesExt, WriteBytesExt};
 use bytes::Bytes;
+use tracing::error;
 
 use crate::connection::id::ClientId;
 use crate::packet::message::MessageId;
 use crate::prelude::Tick;
-use crate::protocol::component::ComponentNetId;
+use crate::protocol::component::{ComponentNetId, ComponentRegistry};
 use crate::protocol::EventContext;
 use crate::serialize::reader::Reader;
 use crate::serialize::varint::{varint_len, VarIntReadExt, VarIntWriteExt};
@@ -20,17 +21,22 @@ use crate::shared::events::connection::{
     IterEntityDespawnEvent, IterEntitySpawnEvent,
 };
 use crate::shared::replication::components::ReplicationGroupId;
+use crate::shared::replication::entity_map::RemoteEntityMap;
 
 pub mod components;
 
 pub(crate) mod archetypes;
+pub mod authority;
 pub mod delta;
 pub mod entity_map;
 pub mod error;
 pub(crate) mod hierarchy;
+pub(crate) mod initial_sync;
 pub mod network_target;
 pub(crate) mod plugin;
+pub mod prefab;
 pub(crate) mod receive;
+pub mod reflect;
 pub(crate) mod resources;
 pub(crate) mod send;
 pub(crate) mod systems;
@@ -38,15 +44,25 @@ pub(crate) mod systems;
 /// Serialize Entity as two varints for the index and generation (because they will probably be low).
 /// Revisit this when relations comes out
 ///
-/// TODO: optimize for the case where generation == 1, which should be most cases
+/// Entities are written as a varint of the index shifted left by one bit, where the low bit is set if
+/// the generation is written as well. Most entities have never been recycled (generation 1), so the
+/// generation is usually skipped.
 impl ToBytes for Entity {
     fn len(&self) -> usize {
-        varint_len(self.index() as u64) + varint_len(self.generation() as u64)
+        let generation_len = if self.generation() == 1 {
+            0
+        } else {
+            varint_len(self.generation() as u64)
+        };
+        varint_len((self.index() as u64) << 1) + generation_len
     }
 
     fn to_bytes<T: WriteBytesExt>(&self, buffer: &mut T) -> Result<(), SerializationError> {
-        buffer.write_varint(self.index() as u64)?;
-        buffer.write_varint(self.generation() as u64)?;
+        let has_generation = self.generation() != 1;
+        buffer.write_varint((self.index() as u64) << 1 | has_generation as u64)?;
+        if has_generation {
+            buffer.write_varint(self.generation() as u64)?;
+        }
         Ok(())
     }
 
@@ -54,8 +70,13 @@ impl ToBytes for Entity {
     where
         Self: Sized,
     {
-        let index = buffer.read_varint()?;
-        let generation = buffer.read_varint()?;
+        let value = buffer.read_varint()?;
+        let index = value >> 1;
+        let generation = if value & 1 == 1 {
+            buffer.read_varint()?
+        } else {
+            1
+        };
         let bits = generation << 32 | index;
         Ok(Entity::from_bits(bits))
     }
@@ -100,7 +121,7 @@ impl ToBytes for EntityActions {
 pub(crate) enum SpawnAction {
     None,
     Spawn,
-    Despawn,
+    Despawn(DespawnReason),
     // the u64 is the entity's bits (we cannot use Entity directly because it doesn't implement Encode/Decode)
     Reuse(Entity),
 }
@@ -110,7 +131,7 @@ impl ToBytes for SpawnAction {
         match &self {
             SpawnAction::None => 1,
             SpawnAction::Spawn => 1,
-            SpawnAction::Despawn => 1,
+            SpawnAction::Despawn(reason) => 1 + reason.len(),
             SpawnAction::Reuse(entity) => 1 + entity.len(),
         }
     }
@@ -119,7 +140,10 @@ impl ToBytes for SpawnAction {
         match &self {
             SpawnAction::None => buffer.write_u8(0)?,
             SpawnAction::Spawn => buffer.write_u8(1)?,
-            SpawnAction::Despawn => buffer.write_u8(2)?,
+            SpawnAction::Despawn(reason) => {
+                buffer.write_u8(2)?;
+                reason.to_bytes(buffer)?;
+            }
             SpawnAction::Reuse(entity) => {
                 buffer.write_u8(3)?;
                 entity.to_bytes(buffer)?;
@@ -135,13 +159,72 @@ impl ToBytes for SpawnAction {
         match buffer.read_u8()? {
             0 => Ok(SpawnAction::None),
             1 => Ok(SpawnAction::Spawn),
-            2 => Ok(SpawnAction::Despawn),
+            2 => Ok(SpawnAction::Despawn(DespawnReason::from_bytes(buffer)?)),
             3 => Ok(SpawnAction::Reuse(Entity::from_bytes(buffer)?)),
             _ => Err(SerializationError::InvalidPacketType),
         }
     }
 }
 
+/// The reason why an entity was despawned on the remote world.
+///
+/// It is included in the [`EntityDespawnEvent`](crate::shared::events::components::EntityDespawnEvent),
+/// so that the receiver can distinguish an entity that was destroyed (to play a death animation, for example)
+/// from an entity that simply stopped being replicated to it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect)]
+pub enum DespawnReason {
+    /// The entity was despawned in the sender's World
+    #[default]
+    Despawned,
+    /// The entity is no longer relevant to the receiver, for example because it left the receiver's
+    /// interest area (see [`RelevanceManager::lose_relevance`](crate::prelude::server::RelevanceManager::lose_relevance))
+    LostRelevance,
+    /// The receiver is no longer part of the [`ReplicationTarget`](crate::prelude::ReplicationTarget) of the entity
+    TargetChanged,
+    /// The entity and the receiver are no longer in the same [`Room`](crate::prelude::server::RoomManager)
+    RoomChanged,
+    /// The entity was despawned in the sender's World with a reason defined by the game,
+    /// for example with [`DespawnReplicationCommandExt::despawn_with_reason`](crate::prelude::server::DespawnReplicationCommandExt::despawn_with_reason)
+    Custom(u8),
+}
+
+impl ToBytes for DespawnReason {
+    fn len(&self) -> usize {
+        match self {
+            DespawnReason::Custom(_) => 2,
+            _ => 1,
+        }
+    }
+
+    fn to_bytes<T: WriteBytesExt>(&self, buffer: &mut T) -> Result<(), SerializationError> {
+        match self {
+            DespawnReason::Despawned => buffer.write_u8(0)?,
+            DespawnReason::LostRelevance => buffer.write_u8(1)?,
+            DespawnReason::TargetChanged => buffer.write_u8(2)?,
+            DespawnReason::RoomChanged => buffer.write_u8(3)?,
+            DespawnReason::Custom(reason) => {
+                buffer.write_u8(4)?;
+                buffer.write_u8(*reason)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
+    where
+        Self: Sized,
+    {
+        match buffer.read_u8()? {
+            0 => Ok(DespawnReason::Despawned),
+            1 => Ok(DespawnReason::LostRelevance),
+            2 => Ok(DespawnReason::TargetChanged),
+            3 => Ok(DespawnReason::RoomChanged),
+            4 => Ok(DespawnReason::Custom(buffer.read_u8()?)),
+            _ => Err(SerializationError::InvalidValue),
+        }
+    }
+}
+
 impl Default for EntityActions {
     fn default() -> Self {
         Self {
@@ -194,16 +277,67 @@ pub struct EntityUpdatesMessage {
     /// We set this to None after a certain amount of time without any new Actions, to signify on the receiver side
     /// that there is no ordering constraint with respect to Actions for this group (i.e. the Update can be applied immediately)
     last_action_tick: Option<Tick>,
-    /// Updates containing the full component data
-    pub(crate) updates: Vec<(Entity, Vec<Bytes>)>,
+    /// Updates containing the full component data, grouped by entity into [`EntityUpdatesRecord`]s
+    pub(crate) updates: Vec<EntityUpdatesRecord>,
     // /// Updates containing diffs with a previous value
     // #[bitcode(with_serde)]
     // diff_updates: Vec<(Entity, Vec<RawData>)>,
 }
 
+impl EntityUpdatesMessage {
+    /// Create the message from the serialized component updates of each entity (prefixed by their net id).
+    ///
+    /// The updates are grouped into [`EntityUpdatesRecord`]s here, once, so that computing the length of the
+    /// message and serializing it don't have to group them again.
+    pub(crate) fn new(
+        group_id: ReplicationGroupId,
+        last_action_tick: Option<Tick>,
+        updates: impl IntoIterator<Item = (Entity, Vec<Bytes>)>,
+    ) -> Self {
+        let mut records = vec![];
+        for (entity, components) in updates {
+            let mut components: Vec<(ComponentNetId, Bytes)> = components
+                .into_iter()
+                .filter_map(|bytes| {
+                    let mut reader = Reader::from(bytes.clone());
+                    let net_id = ComponentNetId::from_bytes(&mut reader)
+                        .inspect_err(|e| error!(?entity, "Invalid component update: {e:?}"))
+                        .ok()?;
+                    Some((net_id, bytes.slice(net_id.len()..)))
+                })
+                .collect();
+            components.sort_by_key(|(net_id, _)| *net_id);
+            let mut record: Option<EntityUpdatesRecord> = None;
+            for (net_id, data) in components {
+                // add the component to the current record if it fits in its bitmask
+                if let Some(current) = record.as_mut().filter(|current| {
+                    net_id - current.base < EntityUpdatesRecord::MASK_BITS
+                        && current.mask & (1 << (net_id - current.base)) == 0
+                }) {
+                    current.mask |= 1 << (net_id - current.base);
+                    current.components.push(data);
+                    continue;
+                }
+                records.extend(record.replace(EntityUpdatesRecord {
+                    entity,
+                    base: net_id,
+                    mask: 1,
+                    components: vec![data],
+                }));
+            }
+            records.extend(record);
+        }
+        Self {
+            group_id,
+            last_action_tick,
+            updates: records,
+        }
+    }
+}
+
 impl ToBytes for EntityUpdatesMessage {
     fn len(&self) -> usize {
-        self.group_id.len() + self.last_action_tick.len() + self.updates.len()
+        self.group_id.len() + self.last_action_tick.len() + ToBytes::len(&self.updates)
     }
 
     fn to_bytes<T: WriteBytesExt>(&self, buffer: &mut T) -> Result<(), SerializationError> {
@@ -220,7 +354,83 @@ impl ToBytes for EntityUpdatesMessage {
         Ok(Self {
             group_id: ReplicationGroupId::from_bytes(buffer)?,
             last_action_tick: Option::<Tick>::from_bytes(buffer)?,
-            updates: Vec::<(Entity, Vec<Bytes>)>::from_bytes(buffer)?,
+            updates: Vec::<EntityUpdatesRecord>::from_bytes(buffer)?,
+        })
+    }
+}
+
+/// Compact record of the component updates of an entity.
+///
+/// Instead of writing the [`ComponentNetId`] before each component, the record contains a bitmask of the
+/// components that are present, relative to the smallest net id of the record. The components are then written
+/// in the order of their net ids.
+///
+/// An entity can have several records if its components don't fit in a single bitmask.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct EntityUpdatesRecord {
+    pub(crate) entity: Entity,
+    base: ComponentNetId,
+    /// Bit `i` is set if the component with the net id `base + i` is present
+    mask: u64,
+    /// The serialized components, without their net id
+    components: Vec<Bytes>,
+}
+
+impl EntityUpdatesRecord {
+    /// Number of bits of the mask, limited by the largest value that can be written as a varint
+    const MASK_BITS: ComponentNetId = 62;
+
+    /// Iterate over the serialized components of the record, with their net id.
+    ///
+    /// The components are not copied: they still point to the buffer the record was read from.
+    pub(crate) fn into_components(self) -> impl Iterator<Item = (ComponentNetId, Bytes)> {
+        let base = self.base;
+        let mask = self.mask;
+        // the net ids cannot overflow: this is checked when the record is deserialized
+        (0..Self::MASK_BITS)
+            .filter(move |i| mask & (1 << i) != 0)
+            .map(move |i| base + i)
+            .zip(self.components)
+    }
+}
+
+impl ToBytes for EntityUpdatesRecord {
+    fn len(&self) -> usize {
+        self.entity.len()
+            + self.base.len()
+            + varint_len(self.mask)
+            + self.components.iter().map(ToBytes::len).sum::<usize>()
+    }
+
+    fn to_bytes<T: WriteBytesExt>(&self, buffer: &mut T) -> Result<(), SerializationError> {
+        self.entity.to_bytes(buffer)?;
+        self.base.to_bytes(buffer)?;
+        buffer.write_varint(self.mask)?;
+        self.components
+            .iter()
+            .try_for_each(|component| component.to_bytes(buffer))
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
+    where
+        Self: Sized,
+    {
+        let entity = Entity::from_bytes(buffer)?;
+        let base = ComponentNetId::from_bytes(buffer)?;
+        let mask = buffer.read_varint()?;
+        // the net id of the last component of the record must be a valid net id
+        let last_bit = (u64::BITS - 1).saturating_sub(mask.leading_zeros()) as ComponentNetId;
+        if mask >> Self::MASK_BITS != 0 || base.checked_add(last_bit).is_none() {
+            return Err(SerializationError::InvalidValue);
+        }
+        let components = (0..mask.count_ones())
+            .map(|_| Bytes::from_bytes(buffer))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            entity,
+            base,
+            mask,
+            components,
         })
     }
 }
@@ -247,6 +457,10 @@ pub(crate) trait ReplicationReceive: Resource + ReplicationPeer {
     /// The received events buffer
     fn events(&mut self) -> &mut Self::Events;
 
+    /// The mapping between remote and local entities for the entities replicated by the peer `from`
+    /// (`None` for the server)
+    fn remote_entity_map(&self, from: Option<ClientId>) -> Option<&RemoteEntityMap>;
+
     /// Do some regular cleanup on the internals of replication
     /// - account for tick wrapping by resetting some internal ticks for each replication group
     fn cleanup(&mut self, tick: Tick);
@@ -268,5 +482,153 @@ pub(crate) trait ReplicationSend: Resource + ReplicationPeer {
 
     /// Do some regular cleanup on the internals of replication
     /// - account for tick wrapping by resetting some internal ticks for each replication group
-    fn cleanup(&mut self, tick: Tick);
+    /// - drop the delta-compression values that are too old to be used as a baseline
+    fn cleanup(&mut self, tick: Tick, component_registry: &ComponentRegistry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_bytes_entity() {
+        for (entity, expected_len) in [
+            // the generation is not written for entities that were never recycled
+            (Entity::from_raw(10), 1),
+            (Entity::from_raw(1000), 2),
+            (Entity::from_bits(3 << 32 | 10), 2),
+        ] {
+            let mut writer = vec![];
+            entity.to_bytes(&mut writer).unwrap();
+            assert_eq!(writer.len(), expected_len);
+            assert_eq!(writer.len(), entity.len());
+
+            let mut reader = writer.into();
+            assert_eq!(Entity::from_bytes(&mut reader).unwrap(), entity);
+        }
+    }
+
+    #[test]
+    fn test_to_bytes_despawn_reason() {
+        for reason in [
+            DespawnReason::Despawned,
+            DespawnReason::LostRelevance,
+            DespawnReason::TargetChanged,
+            DespawnReason::RoomChanged,
+            DespawnReason::Custom(7),
+        ] {
+            let action = SpawnAction::Despawn(reason);
+            let mut writer = vec![];
+            action.to_bytes(&mut writer).unwrap();
+            assert_eq!(writer.len(), action.len());
+
+            let mut reader = writer.into();
+            assert_eq!(SpawnAction::from_bytes(&mut reader).unwrap(), action);
+        }
+    }
+
+    fn component_update(net_id: ComponentNetId, payload: &[u8]) -> Bytes {
+        let mut bytes = vec![];
+        net_id.to_bytes(&mut bytes).unwrap();
+        bytes.extend_from_slice(payload);
+        Bytes::from(bytes)
+    }
+
+    #[test]
+    fn test_to_bytes_entity_updates_message() {
+        let message = EntityUpdatesMessage::new(
+            ReplicationGroupId(1),
+            Some(Tick(3)),
+            vec![
+                (
+                    Entity::from_raw(1),
+                    vec![
+                        component_update(0, &[1, 2]),
+                        component_update(1, &[3]),
+                        component_update(5, &[4, 5, 6]),
+                    ],
+                ),
+                // components that don't fit in a single bitmask
+                (
+                    Entity::from_raw(2),
+                    vec![component_update(2, &[7]), component_update(200, &[8])],
+                ),
+            ],
+        );
+        let mut writer = vec![];
+        message.to_bytes(&mut writer).unwrap();
+        assert_eq!(writer.len(), message.len());
+        // group_id, tick, number of records, then each record: entity, base net id, mask, and
+        // each component with its length
+        assert_eq!(
+            writer.len(),
+            1 + 3 + 1 + (3 + 3 + 2 + 4) + (3 + 2) + (4 + 2)
+        );
+
+        let mut reader = writer.into();
+        let decoded = EntityUpdatesMessage::from_bytes(&mut reader).unwrap();
+        assert_eq!(decoded, message);
+        let components: Vec<_> = decoded
+            .updates
+            .into_iter()
+            .flat_map(|record| {
+                let entity = record.entity;
+                record
+                    .into_components()
+                    .map(move |(net_id, data)| (entity, net_id, data))
+            })
+            .collect();
+        assert_eq!(
+            components,
+            vec![
+                (Entity::from_raw(1), 0, Bytes::from_static(&[1, 2])),
+                (Entity::from_raw(1), 1, Bytes::from_static(&[3])),
+                (Entity::from_raw(1), 5, Bytes::from_static(&[4, 5, 6])),
+                (Entity::from_raw(2), 2, Bytes::from_static(&[7])),
+                (Entity::from_raw(2), 200, Bytes::from_static(&[8])),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_bytes_entity_updates_record_net_id_overflow() {
+        let record = EntityUpdatesRecord {
+            entity: Entity::from_raw(1),
+            base: ComponentNetId::MAX - 1,
+            // the second component would have the net id `ComponentNetId::MAX + 1`
+            mask: 0b101,
+            components: vec![Bytes::from_static(&[1]), Bytes::from_static(&[2])],
+        };
+        let mut writer = vec![];
+        record.to_bytes(&mut writer).unwrap();
+        let mut reader = writer.into();
+        assert!(matches!(
+            EntityUpdatesRecord::from_bytes(&mut reader),
+            Err(SerializationError::InvalidValue)
+        ));
+    }
+
+    #[test]
+    fn test_to_bytes_replication_group_id() {
+        for (group_id, expected_len) in [
+            (ReplicationGroupId(3), 1),
+            (ReplicationGroupId(Entity::from_raw(10).to_bits()), 1),
+            (
+                ReplicationGroupId(Entity::from_bits(3 << 32 | 10).to_bits()),
+                2,
+            ),
+            (ReplicationGroupId(u64::MAX), 16),
+        ] {
+            let mut writer = vec![];
+            group_id.to_bytes(&mut writer).unwrap();
+            assert_eq!(writer.len(), expected_len);
+            assert_eq!(writer.len(), group_id.len());
+
+            let mut reader = writer.into();
+            assert_eq!(
+                ReplicationGroupId::from_bytes(&mut reader).unwrap(),
+                group_id
+            );
+        }
+    }
 }
@@ -78,7 +78,8 @@ pub(crate) mod receive {
 
 pub(crate) mod send {
     use super::*;
-    use crate::prelude::{Replicating, ReplicationGroup, TimeManager};
+    use crate::prelude::{Replicating, ReplicationGroup, TickManager, TimeManager};
+    use crate::shared::replication::components::ReplicationRate;
 
     pub(crate) struct ReplicationSendPlugin<R> {
         send_interval: Duration,
@@ -138,6 +139,29 @@ pub(crate) mod send {
                 }
             }
         }
+
+        /// Tick the [`ReplicationRate`] of all replicated entities.
+        fn tick_replication_rates(
+            time_manager: Res<TimeManager>,
+            tick_manager: Res<TickManager>,
+            mut rates: Query<&mut ReplicationRate, With<Replicating>>,
+        ) {
+            for mut rate in rates.iter_mut() {
+                rate.tick(time_manager.delta(), tick_manager.tick());
+            }
+        }
+
+        /// After we buffer updates, reset the [`ReplicationRate`] of the entities whose updates were sampled
+        fn update_replication_rate_should_send(
+            tick_manager: Res<TickManager>,
+            mut rates: Query<&mut ReplicationRate, With<Replicating>>,
+        ) {
+            for mut rate in rates.iter_mut() {
+                if rate.should_send {
+                    rate.sent(tick_manager.tick());
+                }
+            }
+        }
     }
 
     impl<R: ReplicationSend> Plugin for ReplicationSendPlugin<R> {
@@ -220,9 +244,15 @@ pub(crate) mod send {
             app.add_systems(
                 PostUpdate,
                 (
-                    ReplicationSendPlugin::<R>::tick_replication_group_timers
+                    (
+                        ReplicationSendPlugin::<R>::tick_replication_group_timers,
+                        ReplicationSendPlugin::<R>::tick_replication_rates,
+                    )
                         .in_set(InternalReplicationSet::<R::SetMarker>::BeforeBuffer),
-                    ReplicationSendPlugin::<R>::update_replication_group_should_send
+                    (
+                        ReplicationSendPlugin::<R>::update_replication_group_should_send,
+                        ReplicationSendPlugin::<R>::update_replication_rate_should_send,
+                    )
                         // note that this runs every send_interval
                         .in_set(InternalReplicationSet::<R::SetMarker>::AfterBuffer),
                 ),
@@ -241,9 +271,10 @@ pub(crate) mod shared {
         NetworkRelevanceMode, PrePredicted, RemoteEntityMap, ReplicateHierarchy, Replicated,
         ReplicationConfig, ReplicationGroup, ReplicationTarget, ShouldBePredicted, TargetEntity,
     };
+    use crate::shared::replication::authority::{AuthorityPeer, HasAuthority};
     use crate::shared::replication::components::{
-        Controlled, Replicating, ReplicationGroupId, ReplicationGroupIdBuilder,
-        ShouldBeInterpolated,
+        Controlled, PauseReplication, ReplicateOnce, Replicating, ReplicationGroupId,
+        ReplicationGroupIdBuilder, ReplicationRate, ShouldBeInterpolated,
     };
     use crate::shared::replication::entity_map::{InterpolatedEntityMap, PredictedEntityMap};
     use crate::shared::replication::network_target::NetworkTarget;
@@ -261,6 +292,9 @@ pub(crate) mod shared {
                 .register_type::<ReplicationTarget>()
                 .register_type::<ReplicateToServer>()
                 .register_type::<ReplicateHierarchy>()
+                .register_type::<ReplicateOnce>()
+                .register_type::<ReplicationRate>()
+                .register_type::<PauseReplication>()
                 .register_type::<ReplicationGroupIdBuilder>()
                 .register_type::<ReplicationGroup>()
                 .register_type::<ReplicationConfig>()
@@ -272,7 +306,9 @@ pub(crate) mod shared {
                 .register_type::<ShouldBePredicted>()
                 .register_type::<RemoteEntityMap>()
                 .register_type::<PredictedEntityMap>()
-                .register_type::<InterpolatedEntityMap>();
+                .register_type::<InterpolatedEntityMap>()
+                .register_type::<AuthorityPeer>()
+                .register_type::<HasAuthority>();
         }
     }
 }
@@ -0,0 +1,416 @@
+//! Replicate entities as instances of a prefab, to reduce the bandwidth used to spawn them.
+//!
+//! A prefab is a function that inserts a set of components on an entity. It is registered in the
+//! [`PrefabRegistry`] of both the client and the server, under a [`Prefab`] id.
+//!
+//! When a replicated entity has a [`Prefab`] component:
+//! - the server does not send the components that are inserted by the prefab when the entity is spawned on a client,
+//!   unless their value is different from the one set by the prefab (the serialized values are compared)
+//! - the client runs the spawn function of the prefab when it receives the [`Prefab`] component. The components
+//!   that were replicated with the entity keep their replicated value, the prefab only provides the missing ones
+//!
+//! The other components (for example the `Transform` of the entity) are replicated normally, as well as any later
+//! update of the prefab components.
+//!
+//! ```rust,ignore
+//! use bevy::prelude::*;
+//! use lightyear::prelude::*;
+//!
+//! // in the protocol shared by the client and the server
+//! app.add_plugins(PrefabPlugin);
+//! app.world.resource_mut::<PrefabRegistry>().register(Prefab::from_name("tree"), |entity| {
+//!     entity.insert((Tree, Health(100)));
+//! });
+//!
+//! // on the server, only `Prefab` and `Transform` are sent when the tree is spawned on a client
+//! commands.spawn((Prefab::from_name("tree"), Tree, Health(100), Transform::default(), server::Replicate::default()));
+//! ```
+use bevy::ecs::component::ComponentId;
+use bevy::prelude::*;
+use bevy::ptr::Ptr;
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::channel::builder::ChannelDirection;
+use crate::client::config::ClientConfig;
+use crate::prelude::{AppComponentExt, ComponentRegistry, Replicated};
+use crate::protocol::component::ComponentKind;
+use crate::serialize::writer::Writer;
+use crate::shared::sets::{ClientMarker, InternalMainSet};
+
+/// Component that identifies the prefab that an entity was spawned from
+#[derive(Component, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+pub struct Prefab(pub u64);
+
+impl Prefab {
+    /// Id of the prefab with the given name.
+    pub fn from_name(name: &str) -> Self {
+        Prefab(seahash::hash(name.as_bytes()))
+    }
+}
+
+type SpawnFn = Box<dyn Fn(&mut EntityWorldMut) + Send + Sync>;
+
+struct PrefabData {
+    spawn: SpawnFn,
+    /// World that contains a single entity spawned from the prefab, which holds the default values of the prefab
+    defaults: World,
+    entity: Entity,
+    /// Components inserted by the spawn function, with their id in the `defaults` world
+    components: HashMap<ComponentKind, ComponentId>,
+}
+
+/// Resource that maps the [`Prefab`] ids to their spawn functions
+#[derive(Resource, Default)]
+pub struct PrefabRegistry {
+    prefabs: HashMap<Prefab, PrefabData>,
+}
+
+impl PrefabRegistry {
+    /// Register the spawn function of a prefab.
+    ///
+    /// The spawn function should only insert components: it is run once on an empty [`World`] to find
+    /// the components that the prefab provides and their default values.
+    pub fn register(
+        &mut self,
+        prefab: Prefab,
+        spawn: impl Fn(&mut EntityWorldMut) + Send + Sync + 'static,
+    ) {
+        let mut defaults = World::new();
+        let mut entity = defaults.spawn_empty();
+        spawn(&mut entity);
+        let entity = entity.id();
+        let components = defaults
+            .entity(entity)
+            .archetype()
+            .components()
+            .filter_map(|id| {
+                let type_id = defaults.components().get_info(id)?.type_id()?;
+                Some((ComponentKind::from(type_id), id))
+            })
+            .collect();
+        self.prefabs.insert(
+            prefab,
+            PrefabData {
+                spawn: Box::new(spawn),
+                defaults,
+                entity,
+                components,
+            },
+        );
+    }
+
+    /// Returns true if the component is inserted by the spawn function of the prefab
+    pub(crate) fn provides(&self, prefab: &Prefab, kind: &ComponentKind) -> bool {
+        self.prefabs
+            .get(prefab)
+            .is_some_and(|data| data.components.contains_key(kind))
+    }
+
+    /// Returns true if the component is inserted by the spawn function of the prefab, with the same serialized value
+    /// as `component`.
+    ///
+    /// SAFETY: `component` must point to a component of type `kind`
+    pub(crate) fn has_default_value(
+        &self,
+        prefab: &Prefab,
+        kind: ComponentKind,
+        component: Ptr,
+        component_registry: &ComponentRegistry,
+    ) -> bool {
+        let Some(default) = self.prefabs.get(prefab).and_then(|data| {
+            let id = data.components.get(&kind)?;
+            data.defaults.get_by_id(data.entity, *id)
+        }) else {
+            return false;
+        };
+        let mut writer = Writer::default();
+        if component_registry
+            .erased_serialize(default, &mut writer, kind)
+            .is_err()
+        {
+            return false;
+        }
+        let default = writer.split();
+        if component_registry
+            .erased_serialize(component, &mut writer, kind)
+            .is_err()
+        {
+            return false;
+        }
+        default == writer.split()
+    }
+
+    /// Run the spawn function of the prefab on the entity, but keep the value of the components that the
+    /// entity already has (because they were replicated with the entity)
+    fn spawn(&self, prefab: &Prefab, entity: Entity, world: &mut World) {
+        let Some(data) = self.prefabs.get(prefab) else {
+            error!(?prefab, "Received an entity from an unknown prefab");
+            return;
+        };
+        let kept: Vec<(ComponentId, usize)> = data
+            .components
+            .keys()
+            .filter_map(|kind| {
+                let id = world.components().get_id(kind.0)?;
+                let size = world.components().get_info(id)?.layout().size();
+                world.entity(entity).contains_id(id).then_some((id, size))
+            })
+            .collect();
+        if kept.is_empty() {
+            (data.spawn)(&mut world.entity_mut(entity));
+            return;
+        }
+        // the spawn function overwrites every component of the prefab, so the kept values are moved to a
+        // temporary entity while it runs, and moved back afterwards
+        let mut stash = world.spawn_empty();
+        (data.spawn)(&mut stash);
+        let stash = stash.id();
+        // SAFETY: both entities have the kept components, since the prefab inserts them
+        unsafe { swap_components(world, entity, stash, &kept) };
+        (data.spawn)(&mut world.entity_mut(entity));
+        unsafe { swap_components(world, entity, stash, &kept) };
+        world.despawn(stash);
+    }
+}
+
+/// Swap the values of the components between two entities.
+///
+/// SAFETY: both entities must have all the components, and `size` must be the size of the component
+unsafe fn swap_components(
+    world: &mut World,
+    a: Entity,
+    b: Entity,
+    components: &[(ComponentId, usize)],
+) {
+    for (id, size) in components {
+        let a = world
+            .entity_mut(a)
+            .get_mut_by_id(*id)
+            .unwrap()
+            .into_inner()
+            .as_ptr();
+        let b = world
+            .entity_mut(b)
+            .get_mut_by_id(*id)
+            .unwrap()
+            .into_inner()
+            .as_ptr();
+        std::ptr::swap_nonoverlapping(a, b, *size);
+    }
+}
+
+/// Plugin that replicates the [`Prefab`] component and spawns the prefabs on the client.
+///
+/// It inserts the [`PrefabRegistry`]: the server reads it to skip the components inserted by a prefab when
+/// an entity is spawned on a client, and the client reads it to insert them back. The prefabs must
+/// therefore be registered with the same components on both sides.
+pub struct PrefabPlugin;
+
+impl Plugin for PrefabPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PrefabRegistry>()
+            .register_type::<Prefab>()
+            .register_component::<Prefab>(ChannelDirection::ServerToClient);
+        if app.world.get_resource::<ClientConfig>().is_some() {
+            app.add_systems(
+                PreUpdate,
+                spawn_prefabs.after(InternalMainSet::<ClientMarker>::EmitEvents),
+            );
+        }
+    }
+}
+
+/// Run the spawn function of the prefab on the entities that were just replicated.
+///
+/// The components that were replicated with the entity in the same message keep their replicated value.
+fn spawn_prefabs(
+    world: &mut World,
+    query: &mut QueryState<(Entity, &Prefab), (Added<Prefab>, With<Replicated>)>,
+) {
+    let spawned: Vec<(Entity, Prefab)> = query
+        .iter(world)
+        .map(|(entity, prefab)| (entity, *prefab))
+        .collect();
+    if spawned.is_empty() {
+        return;
+    }
+    world.resource_scope(|world, registry: Mut<PrefabRegistry>| {
+        for (entity, prefab) in spawned {
+            registry.spawn(&prefab, entity, world);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Duration;
+
+    use crate::prelude::client::{self, ClientConfig};
+    use crate::prelude::server::Replicate;
+    use crate::prelude::{NetworkTarget, ReplicationTarget, SharedConfig, TickConfig};
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    fn spawn_tree(entity: &mut EntityWorldMut) {
+        entity.insert(Component2(1.0));
+    }
+
+    #[test]
+    fn test_prefab_components() {
+        let mut registry = PrefabRegistry::default();
+        let tree = Prefab::from_name("tree");
+        registry.register(tree, spawn_tree);
+        assert!(registry.provides(&tree, &ComponentKind::of::<Component2>()));
+        assert!(!registry.provides(&tree, &ComponentKind::of::<Component1>()));
+    }
+
+    fn setup() -> BevyStepper {
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(shared_config, ClientConfig::default(), tick_duration);
+        for app in [&mut stepper.server_app, &mut stepper.client_app] {
+            app.add_plugins(PrefabPlugin);
+            app.world
+                .resource_mut::<PrefabRegistry>()
+                .register(Prefab::from_name("tree"), spawn_tree);
+        }
+        stepper.init();
+        stepper
+    }
+
+    fn client_entity(stepper: &BevyStepper, server_entity: Entity) -> Entity {
+        *stepper
+            .client_app
+            .world
+            .resource::<client::ConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_replicate_prefab() {
+        let mut stepper = setup();
+        let tree = Prefab::from_name("tree");
+
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((tree, Component1(2.0), Replicate::default()))
+            .id();
+        spawn_tree(&mut stepper.server_app.world.entity_mut(server_entity));
+        let modified_entity = stepper
+            .server_app
+            .world
+            .spawn((tree, Replicate::default()))
+            .id();
+        spawn_tree(&mut stepper.server_app.world.entity_mut(modified_entity));
+        stepper.frame_step();
+        stepper.frame_step();
+        // the updates of the prefab components are replicated
+        stepper
+            .server_app
+            .world
+            .entity_mut(modified_entity)
+            .insert(Component2(3.0));
+        stepper.frame_step();
+        stepper.frame_step();
+
+        let entity = client_entity(&stepper, server_entity);
+        // the prefab components are spawned by the client, the others are replicated
+        assert_eq!(
+            stepper.client_app.world.get::<Component2>(entity),
+            Some(&Component2(1.0))
+        );
+        assert_eq!(
+            stepper.client_app.world.get::<Component1>(entity),
+            Some(&Component1(2.0))
+        );
+        let entity = client_entity(&stepper, modified_entity);
+        assert_eq!(
+            stepper.client_app.world.get::<Component2>(entity),
+            Some(&Component2(3.0))
+        );
+    }
+
+    /// A prefab component that is spawned with a different value than the prefab's is replicated
+    #[test]
+    fn test_replicate_prefab_non_default_value() {
+        let mut stepper = setup();
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((
+                Prefab::from_name("tree"),
+                Component2(5.0),
+                Replicate::default(),
+            ))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+
+        let entity = client_entity(&stepper, server_entity);
+        assert_eq!(
+            stepper.client_app.world.get::<Component2>(entity),
+            Some(&Component2(5.0))
+        );
+    }
+
+    /// The prefab component of an entity that was modified before the entity is spawned on the client
+    /// keeps its replicated value
+    #[test]
+    fn test_replicate_prefab_late_join() {
+        let mut stepper = setup();
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((
+                Prefab::from_name("tree"),
+                Component1(2.0),
+                Replicate {
+                    target: ReplicationTarget {
+                        target: NetworkTarget::None,
+                    },
+                    ..default()
+                },
+            ))
+            .id();
+        spawn_tree(&mut stepper.server_app.world.entity_mut(server_entity));
+        stepper.frame_step();
+        stepper
+            .server_app
+            .world
+            .entity_mut(server_entity)
+            .insert(Component2(3.0));
+        stepper.frame_step();
+
+        // the client joins the replication of the entity
+        stepper
+            .server_app
+            .world
+            .entity_mut(server_entity)
+            .insert(ReplicationTarget {
+                target: NetworkTarget::All,
+            });
+        stepper.frame_step();
+        stepper.frame_step();
+
+        let entity = client_entity(&stepper, server_entity);
+        assert_eq!(
+            stepper.client_app.world.get::<Component2>(entity),
+            Some(&Component2(3.0))
+        );
+        assert_eq!(
+            stepper.client_app.world.get::<Component1>(entity),
+            Some(&Component1(2.0))
+        );
+    }
+}
@@ -14,6 +14,7 @@ use crate::prelude::{ClientId, Tick};
 use crate::protocol::component::ComponentRegistry;
 use crate::serialize::reader::Reader;
 use crate::shared::events::connection::ConnectionEvents;
+use crate::shared::replication::authority::{accepts_updates_from, AuthorityPeer};
 use crate::shared::replication::components::{Replicated, ReplicationGroupId};
 #[cfg(test)]
 use crate::utils::captures::Captures;
@@ -279,8 +280,8 @@ impl ReplicationReceiver {
             debug!(remote_entity = ?entity, "Received entity actions");
 
             // despawn
-            if actions.spawn == SpawnAction::Despawn {
-                debug!(remote_entity = ?entity, "Received entity despawn");
+            if let SpawnAction::Despawn(reason) = actions.spawn {
+                debug!(remote_entity = ?entity, ?reason, "Received entity despawn");
                 if let Some(local_entity) = self.remote_entity_map.remove_by_remote(entity) {
                     if let Some(group) = self.group_channels.get_mut(&group_id) {
                         group.remote_entities.remove(&entity);
@@ -289,7 +290,7 @@ impl ReplicationReceiver {
                     if let Some(entity_mut) = world.get_entity_mut(local_entity) {
                         entity_mut.despawn_recursive();
                     }
-                    events.push_despawn(local_entity);
+                    events.push_despawn(local_entity, reason);
                     self.remote_entity_to_group.remove(&entity);
                 } else {
                     error!("Received despawn for an entity that does not exist")
@@ -382,16 +383,18 @@ impl ReplicationReceiver {
         if is_history {
             return;
         }
-        for (entity, components) in message.updates.into_iter() {
-            debug!(?components, remote_entity = ?entity, "Received UpdateComponent");
+        for record in message.updates.into_iter() {
+            let entity = record.entity;
+            debug!(?record, remote_entity = ?entity, "Received UpdateComponent");
             // update the entity only if it exists
             if let Some(mut local_entity_mut) = self.remote_entity_map.get_by_remote(world, entity)
             {
-                for component in components {
+                for (net_id, component) in record.into_components() {
                     let mut reader = Reader::from(component);
                     let _ = component_registry
-                        .raw_write(
+                        .raw_write_with_net_id(
                             &mut reader,
+                            net_id,
                             &mut local_entity_mut,
                             remote_tick,
                             &mut self.remote_entity_map.remote_to_local,
@@ -829,6 +832,15 @@ impl GroupChannel {
                         error!("Received ReuseEntity({local_entity:?}) but the entity does not exist in the world");
                         continue;
                     };
+                    // a client can only replicate to an existing entity if it has the authority over it
+                    if let Some(client_id) = remote {
+                        if entity_mut.get::<AuthorityPeer>()
+                            != Some(&AuthorityPeer::Client(client_id))
+                        {
+                            warn!(?local_entity, ?client_id, "Received ReuseEntity from a client without authority over the entity");
+                            continue;
+                        }
+                    }
                     entity_mut.insert(Replicated { from: remote });
                     // update the entity mapping
                     remote_entity_map.insert(*remote_entity, local_entity);
@@ -841,15 +853,25 @@ impl GroupChannel {
             debug!(remote_entity = ?entity, "Received entity actions");
 
             // despawn
-            if actions.spawn == SpawnAction::Despawn {
-                debug!(remote_entity = ?entity, "Received entity despawn");
+            if let SpawnAction::Despawn(reason) = actions.spawn {
+                debug!(remote_entity = ?entity, ?reason, "Received entity despawn");
+                // a client cannot despawn an entity it doesn't have authority over
+                // (the server can always despawn the entity)
+                if remote.is_some()
+                    && remote_entity_map
+                        .get_by_remote(world, entity)
+                        .is_some_and(|entity_mut| !accepts_updates_from(&entity_mut, remote))
+                {
+                    debug!(remote_entity = ?entity, "Ignoring despawn from a peer without authority");
+                    continue;
+                }
                 if let Some(local_entity) = remote_entity_map.remove_by_remote(entity) {
                     self.remote_entities.remove(&entity);
                     // TODO: we despawn all children as well right now, but that might not be what we want?
                     if let Some(entity_mut) = world.get_entity_mut(local_entity) {
                         entity_mut.despawn_recursive();
                     }
-                    events.push_despawn(local_entity);
+                    events.push_despawn(local_entity, reason);
                     remote_entity_to_group.remove(&entity);
                 } else {
                     error!("Received despawn for an entity that does not exist")
@@ -862,6 +884,10 @@ impl GroupChannel {
                 error!(?entity, "cannot find entity");
                 continue;
             };
+            if !accepts_updates_from(&local_entity_mut, remote) {
+                debug!(remote_entity = ?entity, "Ignoring actions from a peer without authority");
+                continue;
+            }
 
             // NOTE: 2 options
             //  - send the raw data to a separate typed system
@@ -939,15 +965,21 @@ impl GroupChannel {
         if is_history {
             return;
         }
-        for (entity, components) in message.updates.into_iter() {
-            debug!(?components, remote_entity = ?entity, "Received UpdateComponent");
+        for record in message.updates.into_iter() {
+            let entity = record.entity;
+            debug!(?record, remote_entity = ?entity, "Received UpdateComponent");
             // update the entity only if it exists
             if let Some(mut local_entity_mut) = remote_entity_map.get_by_remote(world, entity) {
-                for component in components {
+                if !accepts_updates_from(&local_entity_mut, remote) {
+                    debug!(remote_entity = ?entity, "Ignoring updates from a peer without authority");
+                    continue;
+                }
+                for (net_id, component) in record.into_components() {
                     let mut reader = Reader::from(component);
                     let _ = component_registry
-                        .raw_write(
+                        .raw_write_with_net_id(
                             &mut reader,
+                            net_id,
                             &mut local_entity_mut,
                             remote_tick,
                             &mut remote_entity_map.remote_to_local,
@@ -0,0 +1,322 @@
+//! Replicate components that are not part of the protocol, using reflection.
+//!
+//! This is meant for prototyping: a component can be replicated from the server to the clients without
+//! registering it with [`register_component`](crate::prelude::AppComponentExt::register_component).
+//!
+//! - on the server, the component is opted in with [`AppReflectReplicationExt::replicate_reflect`]
+//! - on the client, the component only needs to be in the bevy type registry, with the
+//!   [`ReflectComponent`] and [`ReflectDeserialize`] type data (`#[reflect(Component, Deserialize)]`)
+//!
+//! The values of these components are sent inside a single protocol component, [`ReflectedComponents`],
+//! where each component is identified by the hash of its [`TypePath`], and encoded in a self-describing
+//! format. This is less efficient than registering the component in the protocol: the whole set of
+//! reflected components of an entity is sent again whenever one of them changes.
+//!
+//! ```rust,ignore
+//! use bevy::prelude::*;
+//! use lightyear::prelude::*;
+//!
+//! #[derive(Component, Serialize, Deserialize, Reflect)]
+//! #[reflect(Component, Deserialize)]
+//! struct Health(u32);
+//!
+//! // in the protocol shared by the client and the server
+//! app.add_plugins(ReflectReplicationPlugin);
+//! app.register_type::<Health>();
+//!
+//! // on the server
+//! app.replicate_reflect::<Health>();
+//! ```
+use bevy::prelude::*;
+use bevy::reflect::{ReflectDeserialize, TypeRegistry};
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use crate::channel::builder::ChannelDirection;
+use crate::client::config::ClientConfig;
+use crate::prelude::{AppComponentExt, Replicated, Replicating};
+use crate::serialize::extensible::{self, Value};
+use crate::server::config::ServerConfig;
+use crate::shared::sets::{ClientMarker, InternalMainSet, InternalReplicationSet, ServerMarker};
+
+/// Protocol component that holds the values of the components replicated with reflection
+#[derive(Component, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ReflectedComponents(Vec<ReflectedComponent>);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct ReflectedComponent {
+    /// Hash of the [`TypePath`] of the component
+    type_hash: u64,
+    value: Value,
+}
+
+/// Hashes of the reflected components that were inserted on a client entity,
+/// to remove the components that are not replicated anymore
+#[derive(Component, Default)]
+struct ReceivedReflectedComponents(Vec<u64>);
+
+type SerializeFn = fn(&EntityRef) -> Option<Value>;
+
+/// Resource that holds the components that are replicated with reflection
+#[derive(Resource, Default)]
+pub(crate) struct ReflectReplicationRegistry {
+    /// Components that are sent, with the function used to serialize them
+    serialize_fns: Vec<(u64, SerializeFn)>,
+    /// Cache of the types of the type registry, by hash of their [`TypePath`]
+    types: HashMap<u64, std::any::TypeId>,
+}
+
+fn type_hash(type_path: &str) -> u64 {
+    seahash::hash(type_path.as_bytes())
+}
+
+fn serialize_component<C: Component + Serialize>(entity: &EntityRef) -> Option<Value> {
+    let component = entity.get::<C>()?;
+    extensible::to_value(component)
+        .inspect_err(|e| {
+            error!(
+                "Could not serialize the reflected component {}: {e}",
+                std::any::type_name::<C>()
+            )
+        })
+        .ok()
+}
+
+impl ReflectReplicationRegistry {
+    /// Find the type with the given [`TypePath`] hash in the type registry
+    fn find_type(&mut self, hash: u64, type_registry: &TypeRegistry) -> Option<std::any::TypeId> {
+        if !self.types.contains_key(&hash) {
+            self.types.extend(type_registry.iter().map(|registration| {
+                (
+                    type_hash(registration.type_info().type_path()),
+                    registration.type_id(),
+                )
+            }));
+        }
+        self.types.get(&hash).copied()
+    }
+}
+
+/// Replicate components that are not registered in the protocol
+pub trait AppReflectReplicationExt {
+    /// Replicate the component `C` from the server to the clients with reflection.
+    ///
+    /// This should be called on the server. The clients only need to have the component in their type registry.
+    fn replicate_reflect<C: Component + Serialize + TypePath>(&mut self) -> &mut Self;
+}
+
+impl AppReflectReplicationExt for App {
+    fn replicate_reflect<C: Component + Serialize + TypePath>(&mut self) -> &mut Self {
+        self.world
+            .get_resource_mut::<ReflectReplicationRegistry>()
+            .expect("the ReflectReplicationPlugin must be added first")
+            .serialize_fns
+            .push((type_hash(C::type_path()), serialize_component::<C>));
+        self
+    }
+}
+
+/// Plugin that replicates the components registered with [`AppReflectReplicationExt::replicate_reflect`].
+///
+/// On the server, it serializes the opted-in components of each replicated entity into its
+/// [`ReflectedComponents`] before the replication messages are buffered. On the client, it inserts the
+/// received values using the bevy type registry, and skips (with a warning) the components whose type is
+/// not registered there.
+pub struct ReflectReplicationPlugin;
+
+impl Plugin for ReflectReplicationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReflectReplicationRegistry>()
+            .register_component::<ReflectedComponents>(ChannelDirection::ServerToClient);
+        if app.world.get_resource::<ServerConfig>().is_some() {
+            app.add_systems(
+                PostUpdate,
+                update_reflected_components
+                    .in_set(InternalReplicationSet::<ServerMarker>::BeforeBuffer),
+            );
+        }
+        if app.world.get_resource::<ClientConfig>().is_some() {
+            app.add_systems(
+                PreUpdate,
+                apply_reflected_components.after(InternalMainSet::<ClientMarker>::EmitEvents),
+            );
+        }
+    }
+}
+
+/// Serialize the reflected components of the replicated entities into their [`ReflectedComponents`]
+fn update_reflected_components(
+    world: &mut World,
+    query: &mut QueryState<(Entity, Option<&ReflectedComponents>), With<Replicating>>,
+) {
+    let registry = world.resource::<ReflectReplicationRegistry>();
+    let mut changed = vec![];
+    for (entity, previous) in query.iter(world) {
+        let entity_ref = world.entity(entity);
+        let components: Vec<ReflectedComponent> = registry
+            .serialize_fns
+            .iter()
+            .filter_map(|(type_hash, serialize)| {
+                serialize(&entity_ref).map(|value| ReflectedComponent {
+                    type_hash: *type_hash,
+                    value,
+                })
+            })
+            .collect();
+        // only update the component if it changed, to not trigger change detection
+        match previous {
+            Some(previous) if previous.0 == components => {}
+            None if components.is_empty() => {}
+            _ => changed.push((entity, ReflectedComponents(components))),
+        }
+    }
+    for (entity, components) in changed {
+        world.entity_mut(entity).insert(components);
+    }
+}
+
+/// Insert or update the reflected components on the entities that were just replicated
+fn apply_reflected_components(
+    world: &mut World,
+    query: &mut QueryState<
+        (Entity, &ReflectedComponents),
+        (Changed<ReflectedComponents>, With<Replicated>),
+    >,
+) {
+    let changed: Vec<(Entity, ReflectedComponents)> = query
+        .iter(world)
+        .map(|(entity, components)| (entity, components.clone()))
+        .collect();
+    if changed.is_empty() {
+        return;
+    }
+    let type_registry = world.resource::<AppTypeRegistry>().clone();
+    let type_registry = type_registry.read();
+    world.resource_scope(|world, mut registry: Mut<ReflectReplicationRegistry>| {
+        for (entity, components) in changed {
+            let mut entity_mut = world.entity_mut(entity);
+            let previous = entity_mut
+                .take::<ReceivedReflectedComponents>()
+                .unwrap_or_default();
+            // remove the components that are not replicated anymore
+            for type_hash in previous.0 {
+                if components.0.iter().all(|c| c.type_hash != type_hash) {
+                    if let Some(reflect_component) = registry
+                        .find_type(type_hash, &type_registry)
+                        .and_then(|type_id| type_registry.get_type_data::<ReflectComponent>(type_id))
+                    {
+                        reflect_component.remove(&mut entity_mut);
+                    }
+                }
+            }
+            let mut received = ReceivedReflectedComponents::default();
+            for component in components.0 {
+                let Some(type_id) = registry.find_type(component.type_hash, &type_registry) else {
+                    warn!(
+                        type_hash = component.type_hash,
+                        "Received a reflected component that is not in the type registry"
+                    );
+                    continue;
+                };
+                let (Some(reflect_component), Some(reflect_deserialize)) = (
+                    type_registry.get_type_data::<ReflectComponent>(type_id),
+                    type_registry.get_type_data::<ReflectDeserialize>(type_id),
+                ) else {
+                    warn!(
+                        ?type_id,
+                        "Received a reflected component without the ReflectComponent and ReflectDeserialize type data"
+                    );
+                    continue;
+                };
+                match reflect_deserialize.deserialize(component.value) {
+                    Ok(value) => {
+                        reflect_component.apply_or_insert(&mut entity_mut, &*value, &type_registry);
+                        received.0.push(component.type_hash);
+                    }
+                    Err(e) => error!("Could not deserialize a reflected component: {e}"),
+                }
+            }
+            entity_mut.insert(received);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Duration;
+
+    use crate::prelude::client::{self, ClientConfig};
+    use crate::prelude::server::Replicate;
+    use crate::prelude::{SharedConfig, TickConfig};
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    #[derive(Component, Serialize, Deserialize, Reflect, Clone, Debug, PartialEq)]
+    #[reflect(Component, Deserialize)]
+    struct Health(u32);
+
+    #[test]
+    fn test_replicate_reflect() {
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(shared_config, ClientConfig::default(), tick_duration);
+        for app in [&mut stepper.server_app, &mut stepper.client_app] {
+            app.add_plugins(ReflectReplicationPlugin)
+                .register_type::<Health>();
+        }
+        stepper.server_app.replicate_reflect::<Health>();
+        stepper.init();
+
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((Health(10), Replicate::default()))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_entity = *stepper
+            .client_app
+            .world
+            .resource::<client::ConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .expect("entity was not replicated to client");
+        assert_eq!(
+            stepper.client_app.world.get::<Health>(client_entity),
+            Some(&Health(10))
+        );
+
+        // update the component
+        stepper
+            .server_app
+            .world
+            .entity_mut(server_entity)
+            .insert(Health(5));
+        stepper.frame_step();
+        stepper.frame_step();
+        assert_eq!(
+            stepper.client_app.world.get::<Health>(client_entity),
+            Some(&Health(5))
+        );
+
+        // remove the component
+        stepper
+            .server_app
+            .world
+            .entity_mut(server_entity)
+            .remove::<Health>();
+        stepper.frame_step();
+        stepper.frame_step();
+        assert!(stepper
+            .client_app
+            .world
+            .get::<Health>(client_entity)
+            .is_none());
+    }
+}
@@ -139,15 +139,20 @@ pub(crate) mod send {
         if !new_clients.is_empty() {
             if let Some(resource) = &resource {
                 if let Some(replication_resource) = replication_resource.as_ref() {
-                    trace!(
-                        "sending resource replication update to new clients: {:?}",
-                        std::any::type_name::<R>()
-                    );
-                    let _ = connection_manager.erased_send_message_to_target(
-                        resource.as_ref(),
-                        replication_resource.channel,
-                        NetworkTarget::Only(new_clients.clone()),
-                    );
+                    // only send the resource to the new clients that are part of the replication target
+                    let mut target = NetworkTarget::Only(new_clients.clone());
+                    target.intersection(&replication_resource.target);
+                    if !target.is_empty() {
+                        trace!(
+                            "sending resource replication update to new clients: {:?}",
+                            std::any::type_name::<R>()
+                        );
+                        let _ = connection_manager.erased_send_message_to_target(
+                            resource.as_ref(),
+                            replication_resource.channel,
+                            target,
+                        );
+                    }
                 }
             }
         }
@@ -241,7 +246,6 @@ pub(crate) mod receive {
     ) {
         for message in update_message.drain() {
             trace!("received resource replication message");
-            // TODO: disable change detection only for bidirectional!
             if let Some(ref mut resource) = resource {
                 **resource = message.message;
             } else {
@@ -263,7 +267,6 @@ pub(crate) mod receive {
     ) {
         for message in update_message.drain() {
             trace!("received resource replication message");
-            // TODO: disable change detection only for bidirectional!
             if let Some(ref mut resource) = resource {
                 // write the received value to the resource
                 // without change detection to avoid an infinite loop
@@ -298,13 +301,20 @@ pub(crate) mod receive {
 
 #[cfg(test)]
 mod tests {
+    use std::marker::PhantomData;
+
+    use crate::client::sync::SyncConfig;
+    use crate::prelude::client::{InterpolationConfig, PredictionConfig};
+    use crate::prelude::{ChannelKind, ClientId, SharedConfig, TickConfig};
     use crate::shared::replication::network_target::NetworkTarget;
     use crate::shared::replication::resources::ReplicateResourceExt;
+    use crate::tests::multi_stepper::{MultiBevyStepper, TEST_CLIENT_ID_1};
     use crate::tests::protocol::{Channel1, Resource1, Resource2};
     use crate::tests::stepper::{BevyStepper, Step};
     use bevy::prelude::Commands;
+    use bevy::utils::Duration;
 
-    use super::StopReplicateResourceExt;
+    use super::{ReplicateResourceMetadata, StopReplicateResourceExt};
 
     #[test]
     fn test_resource_replication_via_commands() {
@@ -571,4 +581,41 @@ mod tests {
         // check that the update was replicated to the server
         assert_eq!(stepper.server_app.world.resource::<Resource2>().0, 3.0);
     }
+
+    /// Clients that connect after the resource started being replicated only receive it
+    /// if they are part of the replication target
+    #[test]
+    fn test_new_client_outside_target() {
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let mut stepper = MultiBevyStepper::new(
+            shared_config,
+            SyncConfig::default().speedup_factor(1.0),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            tick_duration,
+        );
+        stepper.server_app.world.insert_resource(Resource1(1.0));
+        stepper
+            .server_app
+            .world
+            .insert_resource(ReplicateResourceMetadata::<Resource1> {
+                target: NetworkTarget::Single(ClientId::Netcode(TEST_CLIENT_ID_1)),
+                channel: ChannelKind::of::<Channel1>(),
+                _marker: PhantomData,
+            });
+        stepper.init();
+        stepper.frame_step();
+        stepper.frame_step();
+
+        assert_eq!(stepper.client_app_1.world.resource::<Resource1>().0, 1.0);
+        assert!(stepper
+            .client_app_2
+            .world
+            .get_resource::<Resource1>()
+            .is_none());
+    }
 }
@@ -26,7 +26,9 @@ use crate::shared::replication::plugin::ReplicationConfig;
 #[cfg(test)]
 use crate::utils::captures::Captures;
 
-use super::{EntityActions, EntityActionsMessage, EntityUpdatesMessage, SpawnAction};
+use super::{
+    DespawnReason, EntityActions, EntityActionsMessage, EntityUpdatesMessage, SpawnAction,
+};
 
 type EntityHashMap<K, V> = hashbrown::HashMap<K, V, EntityHash>;
 
@@ -330,13 +332,18 @@ impl ReplicationSender {
     }
 
     #[cfg_attr(feature = "trace", instrument(level = Level::INFO, skip_all))]
-    pub(crate) fn prepare_entity_despawn(&mut self, entity: Entity, group_id: ReplicationGroupId) {
+    pub(crate) fn prepare_entity_despawn(
+        &mut self,
+        entity: Entity,
+        group_id: ReplicationGroupId,
+        reason: DespawnReason,
+    ) {
         self.pending_actions
             .entry(group_id)
             .or_default()
             .entry(entity)
             .or_default()
-            .spawn = SpawnAction::Despawn;
+            .spawn = SpawnAction::Despawn(reason);
     }
 
     // we want to send all component inserts that happen together for the same entity in a single message
@@ -406,41 +413,35 @@ impl ReplicationSender {
         tick: Tick,
     ) -> Result<(), ReplicationError> {
         let group_channel = self.group_channels.entry(group_id).or_default();
-        // Get the latest acked tick for this entity/component
-        let raw_data = group_channel
-            .ack_tick
-            .map(|ack_tick| {
-                // we have an ack tick for this replication group, get the corresponding component value
-                // so we can compute a diff
-                let old_data = delta_manager
-                    .data
-                    .get_component_value(entity, ack_tick, kind, group_id)
-                    .ok_or(ReplicationError::DeltaCompressionError(
-                        "could not find old component value to compute delta".to_string(),
-                    ))
-                    .inspect_err(|e| {
-                        error!(
-                            ?entity,
-                            name = ?registry.name(kind),
-                            "Could not find old component value from tick {:?} to compute delta",
-                            ack_tick
-                        );
-                        error!("DeltaManager data: {:?}", delta_manager.data);
-                    })?;
-                // SAFETY: the component_data and erased_data is a pointer to a component that corresponds to kind
-                unsafe {
-                    registry.serialize_diff(ack_tick, old_data, component_data, writer, kind)?;
-                }
-                Ok::<Bytes, ReplicationError>(writer.split())
-            })
-            .unwrap_or_else(|| {
-                // SAFETY: the component_data is a pointer to a component that corresponds to kind
-                unsafe {
-                    // compute a diff from the base value, and serialize that
-                    registry.serialize_diff_from_base_value(component_data, writer, kind)?;
+        // Get the component value at the latest acked tick for this replication group, to use as
+        // the baseline for the diff
+        let baseline = group_channel.ack_tick.and_then(|ack_tick| {
+            let old_data = delta_manager
+                .data
+                .get_component_value(entity, ack_tick, kind, group_id);
+            if old_data.is_none() {
+                // this can happen if the component was inserted after the acked tick, or if the
+                // value was already cleaned up. We fall back to sending the full state.
+                debug!(
+                    ?entity,
+                    name = ?registry.name(kind),
+                    "Could not find old component value from tick {:?} to compute delta, sending a diff from the base value",
+                    ack_tick
+                );
+            }
+            old_data.map(|old_data| (ack_tick, old_data))
+        });
+        // SAFETY: the component_data and old_data are pointers to a component that corresponds to kind
+        unsafe {
+            match baseline {
+                Some((ack_tick, old_data)) => {
+                    registry.serialize_diff(ack_tick, old_data, component_data, writer, kind)?
                 }
-                Ok::<Bytes, ReplicationError>(writer.split())
-            })?;
+                // compute a diff from the base value, and serialize that
+                None => registry.serialize_diff_from_base_value(component_data, writer, kind)?,
+            }
+        }
+        let raw_data = writer.split();
         trace!(?kind, "Inserting pending update!");
         self.prepare_component_update(entity, group_id, raw_data);
         Ok(())
@@ -502,6 +503,11 @@ impl ReplicationSender {
     /// Before sending replication messages, we accumulate the priority for all replication groups.
     ///
     /// (the priority starts at 0.0, and is accumulated for each group based on the base priority of the group)
+    ///
+    /// Only the groups that have something to send accumulate priority: a group whose messages could not
+    /// be sent because of the bandwidth cap will have a higher priority on the next send, but a group that was
+    /// idle for a while does not take precedence over the other groups once it changes.
+    /// If the bandwidth cap is disabled, every message is sent so the priority is just the base priority.
     pub(crate) fn accumulate_priority(&mut self, time_manager: &TimeManager) {
         // let priority_multiplier = if self.replication_config.send_interval == Duration::default() {
         //     1.0
@@ -510,7 +516,15 @@ impl ReplicationSender {
         //         / time_manager.delta().as_nanos() as f32)
         // };
         let priority_multiplier = 1.0;
-        self.group_channels.values_mut().for_each(|channel| {
+        for (group_id, channel) in self.group_channels.iter_mut() {
+            let has_pending_messages = self.pending_actions.contains_key(group_id)
+                || self.pending_updates.contains_key(group_id);
+            if !has_pending_messages || !self.bandwidth_cap_enabled {
+                channel.accumulated_priority = 0.0;
+            }
+            if !has_pending_messages {
+                continue;
+            }
             trace!(
                 "in accumulate priority: accumulated={:?} base={:?} multiplier={:?}, send_interval={:?}, time_manager_delta={:?}",
                 channel.accumulated_priority, channel.base_priority, priority_multiplier,
@@ -518,7 +532,7 @@ impl ReplicationSender {
                 time_manager.delta().as_nanos()
             );
             channel.accumulated_priority += channel.base_priority * priority_multiplier;
-        });
+        }
     }
 
     /// Prepare the [`EntityActionsMessage`] messages to send.
@@ -609,15 +623,14 @@ impl ReplicationSender {
             let channel = self.group_channels.entry(group_id).or_default();
             let priority = channel.accumulated_priority;
             (
-                EntityUpdatesMessage {
+                EntityUpdatesMessage::new(
                     group_id,
                     // TODO: as an optimization, we can use `last_action_tick = tick` to signify
                     //  that there is no constraint!
                     // SAFETY: the last action tick is always set because we send Actions before Updates
-                    last_action_tick: channel.last_action_tick,
-                    // TODO: maybe we can just send the HashMap directly?
-                    updates: Vec::from_iter(updates),
-                },
+                    channel.last_action_tick,
+                    updates,
+                ),
                 priority,
             )
         })
@@ -639,15 +652,14 @@ impl ReplicationSender {
                 trace!(?group_id, "pending updates: {:?}", updates);
                 let channel = self.group_channels.entry(group_id).or_default();
                 let priority = channel.accumulated_priority;
-                let message = EntityUpdatesMessage {
+                let message = EntityUpdatesMessage::new(
                     group_id,
                     // TODO: as an optimization, we can use `last_action_tick = tick` to signify
                     //  that there is no constraint!
                     // SAFETY: the last action tick is always set because we send Actions before Updates
-                    last_action_tick: channel.last_action_tick,
-                    // TODO: maybe we can just send the HashMap directly?
-                    updates: Vec::from_iter(updates),
-                };
+                    channel.last_action_tick,
+                    updates,
+                );
 
                 // message.emit_send_logs("EntityUpdatesChannel");
                 message.to_bytes(writer).map_err(SerializationError::from)?;
@@ -745,7 +757,7 @@ mod tests {
     use crate::prelude::ClientId;
     use crate::server::connection::ConnectionManager;
 
-    use crate::tests::protocol::Component1;
+    use crate::tests::protocol::{Component1, Component6};
     use crate::tests::stepper::{BevyStepper, Step, TEST_CLIENT_ID};
     use bevy::prelude::*;
 
@@ -872,6 +884,106 @@ mod tests {
         assert_eq!(group_channel.ack_tick, Some(server_tick - 1));
     }
 
+    #[test]
+    fn test_accumulate_priority() {
+        let (_, rx_ack) = crossbeam_channel::unbounded();
+        let (_, rx_nack) = crossbeam_channel::unbounded();
+        let (tx_send, rx_send) = crossbeam_channel::unbounded();
+        let mut sender =
+            ReplicationSender::new(rx_ack, rx_nack, rx_send, ReplicationConfig::default(), true);
+        let time_manager = TimeManager::default();
+        let entity = Entity::from_raw(0);
+        let group_1 = ReplicationGroupId(0);
+        let group_2 = ReplicationGroupId(1);
+        sender.update_base_priority(group_1, 2.0);
+        sender.update_base_priority(group_2, 1.0);
+
+        // group 1 has an update to send, but it could not be sent because of the bandwidth cap
+        for _ in 0..3 {
+            sender
+                .pending_updates
+                .entry(group_1)
+                .or_default()
+                .insert(entity, vec![]);
+            sender.accumulate_priority(&time_manager);
+        }
+        assert_eq!(sender.group_channels[&group_1].accumulated_priority, 6.0);
+        // group 2 is idle, so it doesn't accumulate priority
+        assert_eq!(sender.group_channels[&group_2].accumulated_priority, 0.0);
+
+        // once the update is sent, the priority is reset
+        sender.buffer_replication_update_message(group_1, MessageId(0), BevyTick::new(0), Tick(0));
+        tx_send.try_send(MessageId(0)).unwrap();
+        sender.recv_send_notification();
+        assert_eq!(sender.group_channels[&group_1].accumulated_priority, 0.0);
+
+        // group 2 changes after being idle: it starts from its base priority
+        sender.pending_updates.clear();
+        sender
+            .pending_updates
+            .entry(group_2)
+            .or_default()
+            .insert(entity, vec![]);
+        sender.accumulate_priority(&time_manager);
+        assert_eq!(sender.group_channels[&group_2].accumulated_priority, 1.0);
+    }
+
+    /// If the component value at the acked tick is not available (for example because the
+    /// component was inserted after that tick), we fall back to a diff from the base value
+    #[test]
+    fn test_delta_compression_fallback_to_base() {
+        let mut component_registry = ComponentRegistry::default();
+        component_registry.register_component::<Component6>();
+        component_registry.set_delta_compression::<Component6>();
+        let mut delta_manager = DeltaManager::default();
+        let mut writer = Writer::with_capacity(10);
+        let (_, rx_ack) = crossbeam_channel::unbounded();
+        let (_, rx_nack) = crossbeam_channel::unbounded();
+        let (_, rx_send) = crossbeam_channel::unbounded();
+        let mut sender = ReplicationSender::new(
+            rx_ack,
+            rx_nack,
+            rx_send,
+            ReplicationConfig::default(),
+            false,
+        );
+        let group_1 = ReplicationGroupId(0);
+        let entity_1 = Entity::from_raw(0);
+        // the group has an ack tick, but no value was stored for the component at that tick
+        sender.group_channels.insert(
+            group_1,
+            GroupChannel {
+                ack_tick: Some(Tick(1)),
+                ..default()
+            },
+        );
+
+        let kind = ComponentKind::of::<Component6>();
+        let component = Component6(vec![1, 2]);
+        sender
+            .prepare_delta_component_update(
+                entity_1,
+                group_1,
+                kind,
+                Ptr::from(&component),
+                &component_registry,
+                &mut writer,
+                &mut delta_manager,
+                Tick(2),
+            )
+            .unwrap();
+
+        unsafe {
+            component_registry
+                .serialize_diff_from_base_value(Ptr::from(&component), &mut writer, kind)
+                .unwrap();
+        }
+        assert_eq!(
+            sender.pending_updates[&group_1][&entity_1],
+            vec![writer.split()]
+        );
+    }
+
     #[test]
     fn test_send_tick_no_priority() {
         // create fake channels for receiving updates about acks and sends
@@ -1111,11 +1223,7 @@ mod tests {
         assert_eq!(
             u,
             &(
-                EntityUpdatesMessage {
-                    group_id: group_2,
-                    last_action_tick: Some(Tick(3)),
-                    updates: vec![(entity_3, vec![raw_4])],
-                },
+                EntityUpdatesMessage::new(group_2, Some(Tick(3)), vec![(entity_3, vec![raw_4])]),
                 0.0
             )
         );
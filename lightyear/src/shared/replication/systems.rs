@@ -2,7 +2,7 @@
 
 use bevy::prelude::{Res, ResMut};
 
-use crate::prelude::TickManager;
+use crate::prelude::{ComponentRegistry, TickManager};
 use crate::shared::replication::{ReplicationReceive, ReplicationSend};
 
 /// Systems that runs internal clean-up on the ReplicationSender
@@ -10,9 +10,10 @@ use crate::shared::replication::{ReplicationReceive, ReplicationSend};
 pub(crate) fn send_cleanup<R: ReplicationSend>(
     mut sender: ResMut<R>,
     tick_manager: Res<TickManager>,
+    component_registry: Res<ComponentRegistry>,
 ) {
     let tick = tick_manager.tick();
-    sender.cleanup(tick);
+    sender.cleanup(tick, &component_registry);
 }
 
 /// Systems that runs internal clean-up on the ReplicationReceiver
@@ -0,0 +1,4 @@
+/*! Tools to test your protocol outside of a running app
+*/
+pub mod soak;
+pub mod wire;
@@ -0,0 +1,483 @@
+//! Soak test of the channel stack.
+//!
+//! [`run`] connects two [`MessageManager`]s built from your [`ChannelRegistry`] through a pair of link
+//! conditioners, and sends a large number of messages in both directions on every channel, with
+//! randomized loss, duplication and reordering. The simulation runs on a virtual clock with a seeded rng,
+//! so it runs as fast as possible and a failing seed can be replayed.
+//!
+//! Every message that is read on the remote peer is checked against the guarantees of its [`ChannelMode`]:
+//! - every message arrives intact, on the channel it was sent on
+//! - reliable and tick-buffered channels never deliver the same message twice
+//! - sequenced channels only deliver messages that are newer than the previous one
+//! - ordered channels deliver all the messages, in the order they were sent
+//! - unordered reliable channels deliver all the messages
+//! - sequenced reliable channels deliver the last message
+//!
+//! The first violation is returned as a [`SoakError`].
+//!
+//! ```rust,ignore
+//! use lightyear::prelude::*;
+//! use lightyear::testing::soak::{self, SoakConfig};
+//!
+//! #[test]
+//! fn soak_channels() {
+//!     let mut app = App::new();
+//!     app.add_plugins(MyProtocolPlugin);
+//!     let registry = app.world.resource::<ChannelRegistry>();
+//!     let report = soak::run(registry, SoakConfig::default().with_seed(42)).unwrap();
+//!     println!("{report:?}");
+//! }
+//! ```
+use bevy::utils::Duration;
+use bytes::{BufMut, Bytes, BytesMut};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::channel::builder::ChannelMode;
+use crate::channel::senders::error::ChannelSendError;
+use crate::packet::congestion::CongestionConfig;
+use crate::packet::error::PacketError;
+use crate::packet::message_manager::MessageManager;
+use crate::packet::packet::FRAGMENT_SIZE;
+use crate::packet::packet_builder::Payload;
+use crate::packet::priority_manager::PriorityConfig;
+use crate::protocol::channel::{ChannelKind, ChannelRegistry};
+use crate::shared::ping::manager::{PingConfig, PingManager};
+use crate::shared::tick_manager::{TickConfig, TickManager};
+use crate::shared::time_manager::TimeManager;
+use crate::transport::middleware::conditioner::{Instant, LinkConditioner, LinkConditionerConfig};
+
+/// Size of the header written at the start of every message: the index of the channel and the
+/// sequence number of the message
+const MESSAGE_HEADER_BYTES: usize = 9;
+
+/// Configuration of a soak test
+#[derive(Clone, Debug)]
+pub struct SoakConfig {
+    /// Number of messages sent on each channel, in each direction
+    pub messages_per_channel: u64,
+    /// Number of messages buffered by each peer on every frame, across all channels
+    pub messages_per_frame: usize,
+    /// The messages have a random size between 9 bytes and this size.
+    ///
+    /// Use a size larger than a packet to test the fragmentation of the messages.
+    pub max_message_size: usize,
+    /// Network conditions applied to the packets in both directions
+    pub conditioner: LinkConditionerConfig,
+    /// Duration of a simulated frame
+    pub frame_duration: Duration,
+    /// The test fails if the reliable channels did not deliver all their messages after this many frames
+    pub max_frames: u64,
+    /// Seed of the rng used to generate the messages and the network conditions
+    pub seed: u64,
+}
+
+impl Default for SoakConfig {
+    fn default() -> Self {
+        Self {
+            messages_per_channel: 100_000,
+            messages_per_frame: 64,
+            max_message_size: 2 * FRAGMENT_SIZE,
+            conditioner: LinkConditionerConfig {
+                incoming_latency: Duration::from_millis(50),
+                incoming_jitter: Duration::from_millis(20),
+                incoming_loss: 0.1,
+                incoming_duplication: 0.05,
+                incoming_reordering: 0.1,
+                ..Default::default()
+            },
+            frame_duration: Duration::from_millis(16),
+            max_frames: 1_000_000,
+            seed: 0,
+        }
+    }
+}
+
+impl SoakConfig {
+    pub fn with_messages_per_channel(mut self, messages_per_channel: u64) -> Self {
+        self.messages_per_channel = messages_per_channel;
+        self
+    }
+
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    pub fn with_conditioner(mut self, conditioner: LinkConditionerConfig) -> Self {
+        self.conditioner = conditioner;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+/// Summary of a successful soak test
+#[derive(Clone, Debug)]
+pub struct SoakReport {
+    /// Number of frames that were simulated
+    pub frames: u64,
+    pub channels: Vec<ChannelSoakReport>,
+}
+
+/// Number of messages sent and read on a channel, summed over both directions
+#[derive(Clone, Debug)]
+pub struct ChannelSoakReport {
+    pub name: String,
+    pub sent: u64,
+    pub received: u64,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SoakError {
+    #[error("packet error: {0}")]
+    Packet(#[from] PacketError),
+    #[error("channel {channel} delivered a corrupted message")]
+    CorruptedMessage { channel: String },
+    #[error("channel {channel} delivered message {message} more than once")]
+    DuplicateMessage { channel: String, message: u64 },
+    #[error("channel {channel} delivered message {message} after message {previous}")]
+    OutOfOrder {
+        channel: String,
+        message: u64,
+        previous: u64,
+    },
+    #[error("channel {channel} skipped message {message}")]
+    MissingMessage { channel: String, message: u64 },
+    #[error(
+        "channel {channel} only delivered {received} of {sent} messages after {frames} frames"
+    )]
+    Undelivered {
+        channel: String,
+        sent: u64,
+        received: u64,
+        frames: u64,
+    },
+}
+
+/// State of one direction of a channel
+struct ChannelState {
+    kind: ChannelKind,
+    name: String,
+    mode: ChannelMode,
+    /// Number of messages buffered by the sender
+    sent: u64,
+    /// Number of messages read by the receiver
+    received: u64,
+    /// Most recent message read by the receiver
+    last: Option<u64>,
+    /// Messages that were read by the receiver
+    seen: Vec<bool>,
+}
+
+impl ChannelState {
+    fn check(&mut self, message: u64) -> Result<(), SoakError> {
+        let duplicate = self.seen[message as usize];
+        match self.mode {
+            ChannelMode::UnorderedUnreliable | ChannelMode::UnorderedUnreliableWithAcks => {}
+            ChannelMode::TickBuffered | ChannelMode::UnorderedReliable(_) => {
+                if duplicate {
+                    return Err(SoakError::DuplicateMessage {
+                        channel: self.name.clone(),
+                        message,
+                    });
+                }
+            }
+            ChannelMode::SequencedUnreliable | ChannelMode::SequencedReliable(_) => {
+                if let Some(previous) = self.last.filter(|previous| message <= *previous) {
+                    return Err(SoakError::OutOfOrder {
+                        channel: self.name.clone(),
+                        message,
+                        previous,
+                    });
+                }
+            }
+            ChannelMode::OrderedReliable(_) => {
+                let expected = self.last.map_or(0, |last| last + 1);
+                if message < expected {
+                    return Err(SoakError::OutOfOrder {
+                        channel: self.name.clone(),
+                        message,
+                        previous: expected - 1,
+                    });
+                }
+                if message > expected {
+                    return Err(SoakError::MissingMessage {
+                        channel: self.name.clone(),
+                        message: expected,
+                    });
+                }
+            }
+        }
+        self.seen[message as usize] = true;
+        self.last = Some(message);
+        self.received += 1;
+        Ok(())
+    }
+
+    /// Returns true if the receiver got every message that the channel guarantees to deliver
+    fn is_done(&self, total: u64) -> bool {
+        if self.sent < total {
+            return false;
+        }
+        match self.mode {
+            ChannelMode::OrderedReliable(_) | ChannelMode::UnorderedReliable(_) => {
+                self.received == total
+            }
+            ChannelMode::SequencedReliable(_) => total == 0 || self.seen[total as usize - 1],
+            _ => true,
+        }
+    }
+}
+
+/// One side of the simulated connection
+struct Peer {
+    message_manager: MessageManager,
+    time_manager: TimeManager,
+    tick_manager: TickManager,
+    ping_manager: PingManager,
+    /// Conditions the packets sent by this peer
+    conditioner: LinkConditioner<Payload>,
+    /// State of the messages sent by this peer, in the same order as the channel kinds
+    channels: Vec<ChannelState>,
+}
+
+/// Run a soak test on all the channels of the registry.
+///
+/// Returns the first violation of the guarantees of a channel, or an error if the reliable channels
+/// did not deliver all their messages after [`SoakConfig::max_frames`] frames.
+pub fn run(
+    channel_registry: &ChannelRegistry,
+    config: SoakConfig,
+) -> Result<SoakReport, SoakError> {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut kinds: Vec<ChannelKind> = channel_registry.channels().into_keys().collect();
+    kinds.sort_by_key(|kind| channel_registry.get_net_from_kind(kind).copied());
+
+    // both peers share the same session keys, so that the authenticated channels can be tested too
+    let send_key = rng.gen();
+    let receive_key = rng.gen();
+    let mut peers = [
+        new_peer(channel_registry, &kinds, &config),
+        new_peer(channel_registry, &kinds, &config),
+    ];
+    peers[0]
+        .message_manager
+        .set_session_keys(Some((send_key, receive_key)));
+    peers[1]
+        .message_manager
+        .set_session_keys(Some((receive_key, send_key)));
+
+    let start = Instant::now();
+    let mut elapsed = Duration::default();
+    let mut frames = 0;
+    loop {
+        if peers.iter().all(|peer| {
+            peer.channels
+                .iter()
+                .all(|c| c.is_done(config.messages_per_channel))
+        }) {
+            break;
+        }
+        if frames >= config.max_frames {
+            let channel = peers
+                .iter()
+                .flat_map(|peer| peer.channels.iter())
+                .find(|c| !c.is_done(config.messages_per_channel))
+                .unwrap();
+            return Err(SoakError::Undelivered {
+                channel: channel.name.clone(),
+                sent: channel.sent,
+                received: channel.received,
+                frames,
+            });
+        }
+        frames += 1;
+        elapsed += config.frame_duration;
+        let now = start + elapsed;
+
+        for peer in peers.iter_mut() {
+            peer.time_manager.update(config.frame_duration);
+            peer.tick_manager.increment_tick();
+            peer.message_manager
+                .update(&peer.time_manager, &peer.ping_manager, &peer.tick_manager);
+            buffer_messages(peer, &config, &mut rng)?;
+            let tick = peer.tick_manager.tick();
+            for packet in peer.message_manager.send_packets(tick)? {
+                let size = packet.len();
+                peer.conditioner
+                    .condition_packet_at(packet, size, now, &mut rng);
+            }
+        }
+
+        let [a, b] = &mut peers;
+        deliver(a, b, &now)?;
+        deliver(b, a, &now)?;
+    }
+
+    let channels = (0..kinds.len())
+        .map(|i| ChannelSoakReport {
+            name: peers[0].channels[i].name.clone(),
+            sent: peers[0].channels[i].sent + peers[1].channels[i].sent,
+            received: peers[0].channels[i].received + peers[1].channels[i].received,
+        })
+        .collect();
+    Ok(SoakReport { frames, channels })
+}
+
+fn new_peer(
+    channel_registry: &ChannelRegistry,
+    kinds: &[ChannelKind],
+    config: &SoakConfig,
+) -> Peer {
+    let mut ping_manager = PingManager::new(PingConfig::default());
+    // there are no pings in the simulation, so we provide the expected network statistics directly
+    ping_manager.final_stats.rtt = config.conditioner.incoming_latency * 2;
+    ping_manager.final_stats.jitter = config.conditioner.incoming_jitter;
+    let channels = kinds
+        .iter()
+        .map(|kind| ChannelState {
+            kind: *kind,
+            name: channel_registry.name(kind).unwrap_or_default().to_string(),
+            mode: channel_registry
+                .get_builder_from_kind(kind)
+                .unwrap()
+                .settings
+                .mode
+                .clone(),
+            sent: 0,
+            received: 0,
+            last: None,
+            seen: vec![false; config.messages_per_channel as usize],
+        })
+        .collect();
+    Peer {
+        message_manager: MessageManager::new(
+            channel_registry,
+            1.5,
+            PriorityConfig::default(),
+            CongestionConfig::default(),
+        ),
+        time_manager: TimeManager::new(),
+        tick_manager: TickManager::from_config(TickConfig::new(config.frame_duration)),
+        ping_manager,
+        conditioner: LinkConditioner::new(config.conditioner.clone()),
+        channels,
+    }
+}
+
+/// Buffer new messages on random channels that still have messages to send
+fn buffer_messages(
+    peer: &mut Peer,
+    config: &SoakConfig,
+    rng: &mut StdRng,
+) -> Result<(), SoakError> {
+    for _ in 0..config.messages_per_frame {
+        let pending: Vec<usize> = (0..peer.channels.len())
+            .filter(|i| peer.channels[*i].sent < config.messages_per_channel)
+            .collect();
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let index = pending[rng.gen_range(0..pending.len())];
+        let channel = &mut peer.channels[index];
+        let size =
+            rng.gen_range(MESSAGE_HEADER_BYTES..=config.max_message_size.max(MESSAGE_HEADER_BYTES));
+        let message = encode_message(index as u8, channel.sent, size);
+        match peer.message_manager.try_buffer_send(message, channel.kind) {
+            Ok(_) => channel.sent += 1,
+            // the channel has too many messages in flight, try again on the next frame
+            Err(PacketError::ChannelSendError(ChannelSendError::ChannelFull)) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Receive the packets sent by `sender` that are ready at `now`, and check the messages that `receiver` reads
+fn deliver(sender: &mut Peer, receiver: &mut Peer, now: &Instant) -> Result<(), SoakError> {
+    while let Some(packet) = sender.conditioner.pop_packet_at(now) {
+        receiver.message_manager.recv_packet(packet.into())?;
+    }
+    for (index, state) in sender.channels.iter_mut().enumerate() {
+        let channel = receiver
+            .message_manager
+            .channels
+            .get_mut(&state.kind)
+            .ok_or(PacketError::ChannelNotFound)?;
+        while let Some((_, message)) = channel.read_message() {
+            let Some(sequence) = decode_message(&message, index as u8, state.sent) else {
+                return Err(SoakError::CorruptedMessage {
+                    channel: state.name.clone(),
+                });
+            };
+            state.check(sequence)?;
+        }
+    }
+    Ok(())
+}
+
+/// Create a message of `size` bytes whose content is derived from its channel and sequence number
+fn encode_message(channel: u8, sequence: u64, size: usize) -> Bytes {
+    let mut message = BytesMut::with_capacity(size);
+    message.put_u8(channel);
+    message.put_u64_le(sequence);
+    for i in MESSAGE_HEADER_BYTES..size {
+        message.put_u8((sequence as u8).wrapping_add(i as u8));
+    }
+    message.freeze()
+}
+
+/// Return the sequence number of the message, if it was sent on this channel and was not corrupted
+fn decode_message(message: &[u8], channel: u8, sent: u64) -> Option<u64> {
+    if message.len() < MESSAGE_HEADER_BYTES || message[0] != channel {
+        return None;
+    }
+    let sequence = u64::from_le_bytes(message[1..MESSAGE_HEADER_BYTES].try_into().unwrap());
+    if sequence >= sent {
+        return None;
+    }
+    message[MESSAGE_HEADER_BYTES..]
+        .iter()
+        .enumerate()
+        .all(|(i, byte)| *byte == (sequence as u8).wrapping_add((i + MESSAGE_HEADER_BYTES) as u8))
+        .then_some(sequence)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::default;
+
+    use crate::prelude::*;
+    use crate::tests::protocol::*;
+
+    use super::*;
+
+    #[test]
+    fn test_soak() {
+        // the default channels cover the other channel modes
+        let mut channel_registry = ChannelRegistry::new(Duration::default());
+        channel_registry.add_channel::<Channel1>(ChannelSettings {
+            mode: ChannelMode::SequencedReliable(ReliableSettings::default()),
+            ..default()
+        });
+        channel_registry.add_channel::<Channel2>(ChannelSettings {
+            mode: ChannelMode::TickBuffered,
+            authenticated: true,
+            ..default()
+        });
+        let report = run(
+            &channel_registry,
+            SoakConfig::default().with_messages_per_channel(1000),
+        )
+        .unwrap();
+        assert_eq!(report.channels.len(), channel_registry.channels().len());
+        for channel in report.channels {
+            assert_eq!(channel.sent, 2000);
+            assert!(channel.received > 0);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Conformance tests of the wire format of your protocol.
+//!
+//! The bytes written for a message or a component depend on its id in the protocol (the order in which
+//! it was registered), on its type definition and on its [`SerializerBackend`](crate::prelude::SerializerBackend).
+//! The built-in backends write the same bytes on x86_64, aarch64 and wasm32, so the bytes of a
+//! known value can be pinned in a test ("golden bytes"): the test then fails whenever a change to the
+//! protocol would make the new build unable to talk to the previous one.
+//!
+//! [`check_message`] and [`check_component`] serialize the value and compare it with the golden bytes,
+//! then deserialize the golden bytes and compare them with the value. If the golden bytes are not known
+//! yet, they can be printed with [`message_bytes`] or [`component_bytes`].
+//!
+//! ```rust,ignore
+//! use lightyear::prelude::*;
+//! use lightyear::testing::wire;
+//!
+//! #[test]
+//! fn chat_message_wire_format() {
+//!     let mut app = App::new();
+//!     app.add_plugins(MyProtocolPlugin);
+//!     let registry = app.world.resource::<MessageRegistry>();
+//!     let message = ChatMessage("hello".to_string());
+//!     wire::check_message(registry, &message, &[0, 5, 104, 101, 108, 108, 111]).unwrap();
+//! }
+//! ```
+use bevy::prelude::Component;
+use bytes::Bytes;
+
+use crate::packet::message::Message;
+use crate::protocol::component::{ComponentError, ComponentRegistry};
+use crate::protocol::message::{MessageError, MessageRegistry};
+use crate::serialize::reader::Reader;
+use crate::serialize::writer::Writer;
+use crate::shared::replication::entity_map::EntityMap;
+
+#[derive(thiserror::Error, Debug)]
+pub enum WireFormatError {
+    #[error("the value is serialized as {actual:?} instead of {expected:?}")]
+    Mismatch { expected: Vec<u8>, actual: Vec<u8> },
+    #[error("the golden bytes are not deserialized to the same value")]
+    ValueMismatch,
+    #[error("the golden bytes contain {0} bytes that were not read")]
+    TrailingBytes(usize),
+    #[error("message error: {0}")]
+    Message(#[from] MessageError),
+    #[error("component error: {0}")]
+    Component(#[from] ComponentError),
+}
+
+/// Returns the bytes written to the network for the message, including its id
+pub fn message_bytes<M: Message>(
+    registry: &MessageRegistry,
+    message: &M,
+) -> Result<Bytes, MessageError> {
+    let mut writer = Writer::default();
+    registry.serialize(message, &mut writer)?;
+    Ok(writer.to_bytes())
+}
+
+/// Returns the bytes written to the network for the component, including its id
+pub fn component_bytes<C: Component + Message>(
+    registry: &ComponentRegistry,
+    component: &C,
+) -> Result<Bytes, ComponentError> {
+    let mut writer = Writer::default();
+    registry.serialize(component, &mut writer)?;
+    Ok(writer.to_bytes())
+}
+
+/// Checks that the message is serialized to `expected`, and that `expected` is deserialized to the message
+pub fn check_message<M: Message + PartialEq>(
+    registry: &MessageRegistry,
+    message: &M,
+    expected: &[u8],
+) -> Result<(), WireFormatError> {
+    compare(&message_bytes(registry, message)?, expected)?;
+    let mut reader = Reader::from(expected.to_vec());
+    let decoded: M = registry.deserialize(&mut reader, &mut EntityMap::default())?;
+    check_decoded(&decoded == message, &reader)
+}
+
+/// Checks that the component is serialized to `expected`, and that `expected` is deserialized to the component
+pub fn check_component<C: Component + Message + PartialEq>(
+    registry: &ComponentRegistry,
+    component: &C,
+    expected: &[u8],
+) -> Result<(), WireFormatError> {
+    compare(&component_bytes(registry, component)?, expected)?;
+    let mut reader = Reader::from(expected.to_vec());
+    let decoded: C = registry.deserialize(&mut reader, &mut EntityMap::default())?;
+    check_decoded(&decoded == component, &reader)
+}
+
+fn compare(actual: &[u8], expected: &[u8]) -> Result<(), WireFormatError> {
+    if actual != expected {
+        return Err(WireFormatError::Mismatch {
+            expected: expected.to_vec(),
+            actual: actual.to_vec(),
+        });
+    }
+    Ok(())
+}
+
+fn check_decoded(equal: bool, reader: &Reader) -> Result<(), WireFormatError> {
+    if !equal {
+        return Err(WireFormatError::ValueMismatch);
+    }
+    if reader.has_remaining() {
+        return Err(WireFormatError::TrailingBytes(reader.remaining()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::message::MessageType;
+    use crate::tests::protocol::*;
+
+    #[test]
+    fn test_check_message() {
+        let mut registry = MessageRegistry::default();
+        registry.add_message::<Message1>(MessageType::Normal);
+        let message = Message1("a".to_string());
+        // net id, then the length of the string and its bytes
+        let golden = [0, 1, b'a'];
+        assert_eq!(message_bytes(&registry, &message).unwrap().as_ref(), golden);
+        check_message(&registry, &message, &golden).unwrap();
+
+        assert!(matches!(
+            check_message(&registry, &Message1("b".to_string()), &golden),
+            Err(WireFormatError::Mismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_check_component() {
+        let mut registry = ComponentRegistry::default();
+        registry.register_component::<Component1>();
+        let component = Component1(f32::from_bits(0xffc0_0000));
+        let golden = component_bytes(&registry, &component).unwrap();
+        // the NaN is canonicalized
+        assert_eq!(
+            golden,
+            component_bytes(&registry, &Component1(f32::NAN)).unwrap()
+        );
+        check_component(&registry, &Component1(1.0), &[0, 0, 0, 128, 63]).unwrap();
+    }
+}
@@ -1,2 +1,3 @@
 mod multi_transport;
+mod runtime_channel;
 mod tick_wrapping;
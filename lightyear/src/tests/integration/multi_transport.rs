@@ -1,8 +1,9 @@
 //! Tests related to the server using multiple transports at the same time to connect to clients
 use crate::client::sync::SyncConfig;
-use crate::prelude::client::{InterpolationConfig, PredictionConfig};
-use crate::prelude::{SharedConfig, TickConfig};
-use crate::tests::multi_stepper::MultiBevyStepper;
+use crate::connection::server::{NetServer, ServerConnections};
+use crate::prelude::client::{InterpolationConfig, NetworkingState, PredictionConfig};
+use crate::prelude::{ClientId, SharedConfig, TickConfig};
+use crate::tests::multi_stepper::{MultiBevyStepper, TEST_CLIENT_ID_1, TEST_CLIENT_ID_2};
 use crate::tests::stepper::Step;
 use bevy::prelude::*;
 use bevy::utils::Duration;
@@ -26,6 +27,30 @@ fn test_multi_transport() {
 
     stepper.frame_step();
     stepper.frame_step();
+
+    // each client is mapped to the transport it connected with
+    let netservers = stepper.server_app.world.resource::<ServerConnections>();
+    assert_eq!(
+        netservers
+            .client_server_map
+            .get(&ClientId::Netcode(TEST_CLIENT_ID_1)),
+        Some(&0)
+    );
+    assert_eq!(
+        netservers
+            .client_server_map
+            .get(&ClientId::Netcode(TEST_CLIENT_ID_2)),
+        Some(&1)
+    );
+
+    // both clients are connected
+    for client_app in [&stepper.client_app_1, &stepper.client_app_2] {
+        assert_eq!(
+            client_app.world.resource::<State<NetworkingState>>().get(),
+            &NetworkingState::Connected
+        );
+    }
+
     // since the clients are synced, the ClientMetadata entities should be replicated already
     // let client_metadata_1 = stepper
     //     .client_app_1
@@ -45,3 +70,53 @@ fn test_multi_transport() {
     // check that the entity got replicated to both clients
     // (even though they share the same client id)
 }
+
+#[test]
+fn test_multi_transport_duplicate_client_id() {
+    let frame_duration = Duration::from_secs_f32(1.0 / 60.0);
+    let tick_duration = Duration::from_millis(10);
+    let shared_config = SharedConfig {
+        tick: TickConfig::new(tick_duration),
+        ..Default::default()
+    };
+    // the two clients connect with the same client id, via different transports
+    let mut stepper = MultiBevyStepper::new_with_client_ids(
+        shared_config,
+        SyncConfig::default(),
+        PredictionConfig::default(),
+        InterpolationConfig::default(),
+        frame_duration,
+        [TEST_CLIENT_ID_1, TEST_CLIENT_ID_1],
+    );
+    stepper.init();
+
+    stepper.frame_step();
+    stepper.frame_step();
+
+    // the connection via the second transport is rejected
+    let netservers = stepper.server_app.world.resource::<ServerConnections>();
+    assert_eq!(netservers.client_server_map.len(), 1);
+    assert_eq!(
+        netservers
+            .client_server_map
+            .get(&ClientId::Netcode(TEST_CLIENT_ID_1)),
+        Some(&0)
+    );
+    assert!(netservers.servers[1].connected_client_ids().is_empty());
+    assert_eq!(
+        stepper
+            .client_app_1
+            .world
+            .resource::<State<NetworkingState>>()
+            .get(),
+        &NetworkingState::Connected
+    );
+    assert_eq!(
+        stepper
+            .client_app_2
+            .world
+            .resource::<State<NetworkingState>>()
+            .get(),
+        &NetworkingState::Disconnected
+    );
+}
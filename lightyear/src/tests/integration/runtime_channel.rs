@@ -0,0 +1,109 @@
+//! Tests related to the channels registered at runtime
+use bevy::ecs::system::RunSystemOnce;
+use bevy::prelude::*;
+use lightyear_macros::ChannelInternal;
+
+use crate::prelude::client::ClientCommands;
+use crate::prelude::server::ServerCommands;
+use crate::prelude::{client, server, ChannelMode, ChannelSettings, ClientId, ReliableSettings};
+use crate::tests::protocol::Message1;
+use crate::tests::stepper::{BevyStepper, Step, TEST_CLIENT_ID};
+
+#[derive(ChannelInternal, Reflect)]
+struct RuntimeChannel;
+
+fn settings() -> ChannelSettings {
+    ChannelSettings {
+        mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+        ..default()
+    }
+}
+
+/// Step the stepper and return the messages received by the client
+fn step_and_receive(stepper: &mut BevyStepper, frames: usize) -> Vec<Message1> {
+    let mut received = vec![];
+    for _ in 0..frames {
+        stepper.frame_step();
+        received.extend(
+            stepper
+                .client_app
+                .world
+                .resource_mut::<Events<client::MessageEvent<Message1>>>()
+                .drain()
+                .map(|event| event.message),
+        );
+    }
+    received
+}
+
+#[test]
+fn test_runtime_channel_server_to_client() {
+    let mut stepper = BevyStepper::default();
+
+    // the server registers a channel while the client is connected
+    stepper
+        .server_app
+        .world
+        .run_system_once(|mut commands: Commands| {
+            ServerCommands::register_channel::<RuntimeChannel>(&mut commands, settings())
+        });
+    stepper.frame_step();
+
+    // the messages sent before the client registered the channel are buffered
+    stepper
+        .server_app
+        .world
+        .resource_mut::<server::ConnectionManager>()
+        .send_message::<RuntimeChannel, _>(
+            ClientId::Netcode(TEST_CLIENT_ID),
+            &Message1("buffered".to_string()),
+        )
+        .unwrap();
+    assert!(step_and_receive(&mut stepper, 5).is_empty());
+
+    // once the client registers the channel, the server sends the buffered messages
+    stepper
+        .client_app
+        .world
+        .run_system_once(|mut commands: Commands| {
+            ClientCommands::register_channel::<RuntimeChannel>(&mut commands, settings())
+        });
+    assert_eq!(
+        step_and_receive(&mut stepper, 5),
+        vec![Message1("buffered".to_string())]
+    );
+
+    // the channel can be used in both directions
+    stepper
+        .server_app
+        .world
+        .resource_mut::<server::ConnectionManager>()
+        .send_message::<RuntimeChannel, _>(
+            ClientId::Netcode(TEST_CLIENT_ID),
+            &Message1("after".to_string()),
+        )
+        .unwrap();
+    assert_eq!(
+        step_and_receive(&mut stepper, 5),
+        vec![Message1("after".to_string())]
+    );
+    stepper
+        .client_app
+        .world
+        .resource_mut::<client::ConnectionManager>()
+        .send_message::<RuntimeChannel, _>(&Message1("from client".to_string()))
+        .unwrap();
+    let mut received = vec![];
+    for _ in 0..5 {
+        stepper.frame_step();
+        received.extend(
+            stepper
+                .server_app
+                .world
+                .resource_mut::<Events<server::MessageEvent<Message1>>>()
+                .drain()
+                .map(|event| event.message),
+        );
+    }
+    assert_eq!(received, vec![Message1("from client".to_string())]);
+}
@@ -67,10 +67,28 @@ impl MultiBevyStepper {
         prediction_config: PredictionConfig,
         interpolation_config: InterpolationConfig,
         frame_duration: Duration,
+    ) -> Self {
+        Self::new_with_client_ids(
+            shared_config,
+            sync_config,
+            prediction_config,
+            interpolation_config,
+            frame_duration,
+            [TEST_CLIENT_ID_1, TEST_CLIENT_ID_2],
+        )
+    }
+
+    /// Create a stepper where the two clients connect with the provided client ids
+    pub fn new_with_client_ids(
+        shared_config: SharedConfig,
+        sync_config: SyncConfig,
+        prediction_config: PredictionConfig,
+        interpolation_config: InterpolationConfig,
+        frame_duration: Duration,
+        client_ids: [u64; 2],
     ) -> Self {
         let now = bevy::utils::Instant::now();
 
-        // both clients will use the same client id
         let server_addr = LOCAL_SOCKET;
 
         // Shared config
@@ -80,23 +98,18 @@ impl MultiBevyStepper {
             server_addr,
             protocol_id,
             private_key,
-            client_id: TEST_CLIENT_ID_1,
+            client_id: client_ids[0],
         };
         let auth_2 = Authentication::Manual {
             server_addr,
             protocol_id,
             private_key,
-            client_id: TEST_CLIENT_ID_2,
+            client_id: client_ids[1],
         };
 
         // client net config 1: use local channels
-        let (from_server_send, from_server_recv) = crossbeam_channel::unbounded();
-        let (to_server_send, to_server_recv) = crossbeam_channel::unbounded();
-        let client_io = client::IoConfig::from_transport(ClientTransport::LocalChannel {
-            recv: from_server_recv,
-            send: to_server_send,
-        });
-        let client_params = (LOCAL_SOCKET, to_server_recv, from_server_send);
+        let (client_transport, client_params) = ClientTransport::local_channels(LOCAL_SOCKET);
+        let client_io = client::IoConfig::from_transport(client_transport);
         let net_config_1 = NetConfig::Netcode {
             auth: auth_1,
             config: client::NetcodeConfig::default(),
@@ -110,13 +123,8 @@ impl MultiBevyStepper {
         });
 
         // client net config 2: use local channels
-        let (from_server_send, from_server_recv) = crossbeam_channel::unbounded();
-        let (to_server_send, to_server_recv) = crossbeam_channel::unbounded();
-        let client_io = client::IoConfig::from_transport(ClientTransport::LocalChannel {
-            recv: from_server_recv,
-            send: to_server_send,
-        });
-        let client_params = (LOCAL_SOCKET, to_server_recv, from_server_send);
+        let (client_transport, client_params) = ClientTransport::local_channels(LOCAL_SOCKET);
+        let client_io = client::IoConfig::from_transport(client_transport);
         let net_config_2 = NetConfig::Netcode {
             auth: auth_2,
             config: client::NetcodeConfig::default(),
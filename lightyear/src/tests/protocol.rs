@@ -173,7 +173,8 @@ impl Plugin for ProtocolPlugin {
             .add_prediction(ComponentSyncMode::Simple);
 
         app.register_component::<Component3>(ChannelDirection::ServerToClient)
-            .add_prediction(ComponentSyncMode::Once);
+            .add_prediction(ComponentSyncMode::Once)
+            .immutable();
 
         app.register_component::<Component4>(ChannelDirection::ServerToClient)
             .add_prediction(ComponentSyncMode::Simple)
@@ -67,16 +67,11 @@ impl BevyStepper {
 
         // Use local channels instead of UDP for testing
         let addr = LOCAL_SOCKET;
-        // channels to receive a message from/to server
-        let (from_server_send, from_server_recv) = crossbeam_channel::unbounded();
-        let (to_server_send, to_server_recv) = crossbeam_channel::unbounded();
-        let mut client_io = client::IoConfig::from_transport(ClientTransport::LocalChannel {
-            send: to_server_send,
-            recv: from_server_recv,
-        });
+        let (client_transport, client_params) = ClientTransport::local_channels(addr);
+        let mut client_io = client::IoConfig::from_transport(client_transport);
 
         let mut server_io = server::IoConfig::from_transport(ServerTransport::Channels {
-            channels: vec![(addr, to_server_recv, from_server_send)],
+            channels: vec![client_params],
         });
 
         let NetConfig::Netcode { io, .. } = client_config.net else {
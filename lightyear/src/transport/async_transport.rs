@@ -0,0 +1,452 @@
+//! Support for user-provided transports that are inherently async.
+//!
+//! The [`Transport`] trait is synchronous and poll-based, which doesn't fit backends that are built
+//! around futures (QUIC, WebTransport, etc.). Instead, those backends can implement [`AsyncTransport`];
+//! the transport is then driven by tasks running on the [`IoTaskPool`] (inside a tokio context, via `async_compat`)
+//! and packets are bridged to the bevy world via channels, exactly like the built-in async transports.
+//!
+//! Use [`AsyncTransportFactory`] to provide such a transport to
+//! [`ClientTransport::Async`](crate::prelude::client::ClientTransport) or
+//! [`ServerTransport::Async`](crate::prelude::server::ServerTransport).
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_compat::Compat;
+use bevy::tasks::{futures_lite, IoTaskPool};
+use bevy::utils::synccell::SyncCell;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tracing::{debug, error, info, trace};
+
+use crate::client::io::transport::{ClientTransportBuilder, ClientTransportEnum};
+use crate::client::io::{ClientIoEvent, ClientIoEventReceiver, ClientNetworkEventSender};
+use crate::server::io::transport::{ServerTransportBuilder, ServerTransportEnum};
+use crate::server::io::{ServerIoEvent, ServerIoEventReceiver, ServerNetworkEventSender};
+use crate::transport::error::{Error, Result};
+use crate::transport::io::IoState;
+use crate::transport::{BoxedReceiver, BoxedSender, PacketReceiver, PacketSender, Transport, MTU};
+
+/// Send data to a remote address, asynchronously
+pub trait AsyncPacketSender: Send + 'static {
+    /// Send a packet to the remote address
+    fn send(
+        &mut self,
+        payload: Vec<u8>,
+        address: SocketAddr,
+    ) -> impl Future<Output = std::io::Result<()>> + Send;
+}
+
+/// Receive data from a remote address, asynchronously
+pub trait AsyncPacketReceiver: Send + 'static {
+    /// Wait until a packet is received. Returns the data read and the origin.
+    ///
+    /// Returning an error closes the transport.
+    fn recv(&mut self) -> impl Future<Output = std::io::Result<(Vec<u8>, SocketAddr)>> + Send;
+}
+
+/// A transport whose operations are async.
+pub trait AsyncTransport: Send + 'static {
+    type Sender: AsyncPacketSender;
+    type Receiver: AsyncPacketReceiver;
+
+    /// Return the local socket address for this transport
+    fn local_addr(&self) -> SocketAddr;
+
+    /// Establish the connection (for clients) or start listening (for servers),
+    /// then split the transport into a sender and a receiver.
+    fn connect(
+        self,
+    ) -> impl Future<Output = std::io::Result<(Self::Sender, Self::Receiver)>> + Send;
+}
+
+type IoTask = Pin<Box<dyn Future<Output = ()> + Send>>;
+type IoTaskFn = Box<dyn FnOnce(IoChannels) -> IoTask + Send>;
+
+/// Creates a new [`AsyncTransport`] every time the io is started.
+///
+/// A factory is needed (instead of the transport itself) because the io configuration can be cloned
+/// and reused, for example to reconnect after a disconnection.
+#[derive(Clone)]
+pub struct AsyncTransportFactory(Arc<dyn Fn() -> AsyncTransportBuilder + Send + Sync>);
+
+impl AsyncTransportFactory {
+    pub fn new<T: AsyncTransport>(factory: impl Fn() -> T + Send + Sync + 'static) -> Self {
+        Self(Arc::new(move || {
+            let transport = factory();
+            AsyncTransportBuilder {
+                local_addr: transport.local_addr(),
+                task: SyncCell::new(Box::new(move |channels| {
+                    Box::pin(run_io_task(transport, channels))
+                })),
+            }
+        }))
+    }
+
+    pub(crate) fn build(&self) -> AsyncTransportBuilder {
+        (self.0)()
+    }
+}
+
+impl Debug for AsyncTransportFactory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncTransportFactory").finish()
+    }
+}
+
+pub(crate) struct AsyncTransportBuilder {
+    local_addr: SocketAddr,
+    // the builders need to be `Sync`, but the task only has to be `Send` since it is only accessed when consumed
+    task: SyncCell<IoTaskFn>,
+}
+
+impl AsyncTransportBuilder {
+    /// Spawn the io task on the [`IoTaskPool`] and return the synchronous side of the transport
+    fn spawn(self, status: StatusSender, close: CloseReceiver) -> AsyncTransportSocket {
+        let (to_send_tx, to_send_rx) = mpsc::unbounded_channel();
+        let (received_tx, received_rx) = mpsc::unbounded_channel();
+        let channels = IoChannels {
+            to_send_rx,
+            received_tx,
+            status,
+            close,
+        };
+        // need to run this with Compat because most async backends require the tokio reactor
+        IoTaskPool::get()
+            .spawn(Compat::new(SyncCell::to_inner(self.task)(channels)))
+            .detach();
+        AsyncTransportSocket {
+            local_addr: self.local_addr,
+            sender: AsyncTransportSocketSender { to_send_tx },
+            receiver: AsyncTransportSocketReceiver {
+                buffer: [0; MTU],
+                received_rx,
+            },
+        }
+    }
+}
+
+impl ClientTransportBuilder for AsyncTransportBuilder {
+    fn connect(
+        self,
+    ) -> Result<(
+        ClientTransportEnum,
+        IoState,
+        Option<ClientIoEventReceiver>,
+        Option<ClientNetworkEventSender>,
+    )> {
+        // channels used to cancel the task
+        let (close_tx, close_rx) = async_channel::bounded(1);
+        // channels used to check the status of the io task
+        let (status_tx, status_rx) = async_channel::bounded(1);
+        let socket = self.spawn(
+            StatusSender::Client(status_tx),
+            CloseReceiver::Client(close_rx),
+        );
+        Ok((
+            ClientTransportEnum::Async(socket),
+            IoState::Connecting,
+            Some(ClientIoEventReceiver(status_rx)),
+            Some(ClientNetworkEventSender(close_tx)),
+        ))
+    }
+}
+
+impl ServerTransportBuilder for AsyncTransportBuilder {
+    fn start(
+        self,
+    ) -> Result<(
+        ServerTransportEnum,
+        IoState,
+        Option<ServerIoEventReceiver>,
+        Option<ServerNetworkEventSender>,
+    )> {
+        // channels used to cancel the task
+        let (close_tx, close_rx) = async_channel::unbounded();
+        // channels used to check the status of the io task
+        let (status_tx, status_rx) = async_channel::unbounded();
+        let socket = self.spawn(
+            StatusSender::Server(status_tx),
+            CloseReceiver::Server(close_rx),
+        );
+        Ok((
+            ServerTransportEnum::Async(socket),
+            IoState::Connecting,
+            Some(ServerIoEventReceiver(status_rx)),
+            Some(ServerNetworkEventSender(close_tx)),
+        ))
+    }
+}
+
+/// Channels used to communicate between the io task and the synchronous transport
+struct IoChannels {
+    to_send_rx: UnboundedReceiver<(Vec<u8>, SocketAddr)>,
+    received_tx: UnboundedSender<(Vec<u8>, SocketAddr)>,
+    status: StatusSender,
+    close: CloseReceiver,
+}
+
+/// Notifies the main thread about the status of the io task
+enum StatusSender {
+    Client(async_channel::Sender<ClientIoEvent>),
+    Server(async_channel::Sender<ServerIoEvent>),
+}
+
+impl StatusSender {
+    async fn connected(&self) {
+        match self {
+            StatusSender::Client(tx) => {
+                let _ = tx.send(ClientIoEvent::Connected).await;
+            }
+            StatusSender::Server(tx) => {
+                let _ = tx.send(ServerIoEvent::ServerConnected).await;
+            }
+        }
+    }
+
+    async fn disconnected(&self, e: Error) {
+        match self {
+            StatusSender::Client(tx) => {
+                let _ = tx.send(ClientIoEvent::Disconnected(e)).await;
+            }
+            StatusSender::Server(tx) => {
+                let _ = tx.send(ServerIoEvent::ServerDisconnected(e)).await;
+            }
+        }
+    }
+}
+
+/// Receives the signal from the main thread that the io should be closed
+enum CloseReceiver {
+    Client(async_channel::Receiver<ClientIoEvent>),
+    Server(async_channel::Receiver<ServerIoEvent>),
+}
+
+impl CloseReceiver {
+    /// Wait until the main thread requests the io to be closed
+    async fn closed(&self) {
+        loop {
+            match self {
+                CloseReceiver::Client(rx) => match rx.recv().await {
+                    Ok(ClientIoEvent::Disconnected(_)) | Err(_) => return,
+                    Ok(_) => {}
+                },
+                CloseReceiver::Server(rx) => match rx.recv().await {
+                    Ok(ServerIoEvent::ServerDisconnected(_)) | Err(_) => return,
+                    // the async transport is responsible for managing the connections to its clients
+                    Ok(_) => {}
+                },
+            }
+        }
+    }
+}
+
+async fn run_io_task<T: AsyncTransport>(transport: T, channels: IoChannels) {
+    let IoChannels {
+        mut to_send_rx,
+        received_tx,
+        status,
+        close,
+    } = channels;
+    let connect = async { Some(transport.connect().await) };
+    let cancel = async {
+        close.closed().await;
+        None
+    };
+    let (mut sender, mut receiver) = match futures_lite::future::race(connect, cancel).await {
+        Some(Ok(split)) => split,
+        Some(Err(e)) => {
+            error!("Error connecting async transport: {:?}", e);
+            status.disconnected(e.into()).await;
+            return;
+        }
+        None => {
+            info!("Async transport closed before being connected.");
+            return;
+        }
+    };
+    status.connected().await;
+    info!("Async transport connected.");
+
+    // NOTE: we spawn separate tasks for receiving and sending, so that a slow send
+    //  doesn't prevent us from receiving packets
+    let recv_handle = IoTaskPool::get().spawn(Compat::new(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(packet) => {
+                    trace!("received packet from {:?}", packet.1);
+                    if received_tx.send(packet).is_err() {
+                        return None;
+                    }
+                }
+                Err(e) => {
+                    error!("async transport receive error: {:?}", e);
+                    return Some(e);
+                }
+            }
+        }
+    }));
+    let send_handle = IoTaskPool::get().spawn(Compat::new(async move {
+        while let Some((payload, address)) = to_send_rx.recv().await {
+            trace!("sending packet to {:?}", address);
+            if let Err(e) = sender.send(payload, address).await {
+                error!("async transport send error: {:?}", e);
+            }
+        }
+    }));
+    // wait for a close signal, or for the transport to stop receiving packets
+    let cancel = async {
+        close.closed().await;
+        None
+    };
+    let recv_closed = async { Some(recv_handle.await) };
+    if let Some(reason) = futures_lite::future::race(recv_closed, cancel).await {
+        let error = reason.unwrap_or_else(|| std::io::Error::other("async transport closed"));
+        status.disconnected(error.into()).await;
+    }
+    send_handle.cancel().await;
+    debug!("Async transport tasks shut down.");
+}
+
+/// Synchronous side of an [`AsyncTransport`]
+pub struct AsyncTransportSocket {
+    local_addr: SocketAddr,
+    sender: AsyncTransportSocketSender,
+    receiver: AsyncTransportSocketReceiver,
+}
+
+impl Transport for AsyncTransportSocket {
+    fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    fn split(self) -> (BoxedSender, BoxedReceiver) {
+        (Box::new(self.sender), Box::new(self.receiver))
+    }
+}
+
+struct AsyncTransportSocketSender {
+    to_send_tx: UnboundedSender<(Vec<u8>, SocketAddr)>,
+}
+
+impl PacketSender for AsyncTransportSocketSender {
+    fn send(&mut self, payload: &[u8], address: &SocketAddr) -> Result<()> {
+        self.to_send_tx
+            .send((payload.to_vec(), *address))
+            .map_err(|e| std::io::Error::other(format!("async transport send error: {}", e)).into())
+    }
+}
+
+struct AsyncTransportSocketReceiver {
+    buffer: [u8; MTU],
+    received_rx: UnboundedReceiver<(Vec<u8>, SocketAddr)>,
+}
+
+impl PacketReceiver for AsyncTransportSocketReceiver {
+    fn recv(&mut self) -> Result<Option<(&mut [u8], SocketAddr)>> {
+        match self.received_rx.try_recv() {
+            Ok((data, addr)) => {
+                if data.len() > MTU {
+                    return Err(std::io::Error::other(format!(
+                        "received packet of size {} is bigger than the MTU",
+                        data.len()
+                    ))
+                    .into());
+                }
+                self.buffer[..data.len()].copy_from_slice(&data);
+                Ok(Some((&mut self.buffer[..data.len()], addr)))
+            }
+            Err(e) => {
+                if e == TryRecvError::Empty {
+                    Ok(None)
+                } else {
+                    Err(
+                        std::io::Error::other(format!("async transport receive error: {}", e))
+                            .into(),
+                    )
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::tasks::TaskPoolBuilder;
+    use bevy::utils::Duration;
+
+    use super::*;
+
+    /// Async transport that sends back every packet to the address it was sent to
+    struct EchoTransport;
+
+    struct EchoSender(async_channel::Sender<(Vec<u8>, SocketAddr)>);
+    struct EchoReceiver(async_channel::Receiver<(Vec<u8>, SocketAddr)>);
+
+    impl AsyncPacketSender for EchoSender {
+        async fn send(&mut self, payload: Vec<u8>, address: SocketAddr) -> std::io::Result<()> {
+            self.0
+                .send((payload, address))
+                .await
+                .map_err(std::io::Error::other)
+        }
+    }
+
+    impl AsyncPacketReceiver for EchoReceiver {
+        async fn recv(&mut self) -> std::io::Result<(Vec<u8>, SocketAddr)> {
+            self.0.recv().await.map_err(std::io::Error::other)
+        }
+    }
+
+    impl AsyncTransport for EchoTransport {
+        type Sender = EchoSender;
+        type Receiver = EchoReceiver;
+
+        fn local_addr(&self) -> SocketAddr {
+            crate::transport::LOCAL_SOCKET
+        }
+
+        async fn connect(self) -> std::io::Result<(Self::Sender, Self::Receiver)> {
+            let (tx, rx) = async_channel::unbounded();
+            Ok((EchoSender(tx), EchoReceiver(rx)))
+        }
+    }
+
+    #[test]
+    fn test_async_transport() {
+        IoTaskPool::get_or_init(|| TaskPoolBuilder::default().build());
+        let factory = AsyncTransportFactory::new(|| EchoTransport);
+
+        let (socket, _, status, close) = factory.build().connect().unwrap();
+        let (mut sender, mut receiver) = socket.split();
+
+        // wait for the transport to be connected
+        let status = status.unwrap();
+        match futures_lite::future::block_on(status.recv()) {
+            Ok(ClientIoEvent::Connected) => {}
+            _ => panic!("expected the async transport to be connected"),
+        }
+
+        let address = "127.0.0.1:1234".parse().unwrap();
+        let msg = b"hello world";
+        sender.send(msg, &address).unwrap();
+
+        // sleep a little to give time to the message to go through the io task
+        std::thread::sleep(Duration::from_millis(50));
+
+        let Ok(Some((recv_msg, recv_address))) = receiver.recv() else {
+            panic!("expected to receive a packet");
+        };
+        assert_eq!(recv_msg, msg);
+        assert_eq!(recv_address, address);
+
+        // closing the io stops the tasks
+        close
+            .unwrap()
+            .try_send(ClientIoEvent::Disconnected(Error::UserRequest))
+            .unwrap();
+    }
+}
@@ -1,5 +1,8 @@
-use crate::transport::middleware::compression::CompressionConfig;
+use crate::transport::middleware::compression::{
+    CompressionConfig, PacketCompressor, DEFAULT_COMPRESSION_THRESHOLD,
+};
 use crate::transport::middleware::conditioner::LinkConditionerConfig;
+use crate::transport::middleware::{PacketMiddleware, PacketMiddlewares};
 use bevy::prelude::Reflect;
 
 #[derive(Clone, Debug, Default, Reflect)]
@@ -9,6 +12,11 @@ pub struct SharedIoConfig<T> {
     pub transport: T,
     pub conditioner: Option<LinkConditionerConfig>,
     pub compression: CompressionConfig,
+    /// Payloads smaller than this number of bytes are not compressed.
+    /// Defaults to 64 bytes if `None`.
+    pub compression_threshold: Option<usize>,
+    #[reflect(ignore)]
+    pub middlewares: PacketMiddlewares,
 }
 
 impl<T> SharedIoConfig<T> {
@@ -17,6 +25,8 @@ impl<T> SharedIoConfig<T> {
             transport,
             conditioner: None,
             compression: CompressionConfig::default(),
+            compression_threshold: None,
+            middlewares: PacketMiddlewares::default(),
         }
     }
     pub fn with_conditioner(mut self, conditioner_config: LinkConditionerConfig) -> Self {
@@ -28,4 +38,30 @@ impl<T> SharedIoConfig<T> {
         self.compression = compression_config;
         self
     }
+
+    /// Only compress payloads that are at least `threshold` bytes long
+    pub fn with_compression_threshold(mut self, threshold: usize) -> Self {
+        self.compression_threshold = Some(threshold);
+        self
+    }
+
+    pub(crate) fn compressor(&self) -> PacketCompressor {
+        PacketCompressor::new(
+            self.compression,
+            self.compression_threshold
+                .unwrap_or(DEFAULT_COMPRESSION_THRESHOLD),
+        )
+    }
+
+    /// Add a [`PacketMiddleware`] around the transport.
+    ///
+    /// `factory` is called to create a new instance of the middleware for each remote address.
+    /// Middlewares are applied after compression and encryption when sending, and before them when receiving.
+    pub fn with_middleware<M: PacketMiddleware>(
+        mut self,
+        factory: impl Fn() -> M + Send + Sync + 'static,
+    ) -> Self {
+        self.middlewares.push(factory);
+        self
+    }
 }
@@ -9,6 +9,9 @@ pub enum Error {
     #[cfg(all(feature = "webtransport", not(target_family = "wasm")))]
     #[error(transparent)]
     WebTransport(#[from] wtransport::error::ConnectingError),
+    #[cfg(all(feature = "quic", not(target_family = "wasm")))]
+    #[error(transparent)]
+    Quic(#[from] quinn::ConnectionError),
     #[cfg(all(feature = "websocket", not(target_family = "wasm")))]
     #[error(transparent)]
     WebSocket(#[from] tokio_tungstenite::tungstenite::error::Error),
@@ -16,6 +19,8 @@ pub enum Error {
     Channel(String),
     #[error("requested by user")]
     UserRequest,
+    #[error("invalid compression header: {0}")]
+    InvalidCompressionHeader(u8),
     #[cfg(feature = "lz4")]
     #[error("lz4 compression error")]
     CompressError(#[from] lz4_flex::block::CompressError),
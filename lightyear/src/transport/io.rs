@@ -31,6 +31,33 @@ pub struct IoStats {
     pub bytes_received: usize,
     pub packets_sent: usize,
     pub packets_received: usize,
+    /// Size of the payloads sent, before compression
+    pub uncompressed_bytes_sent: usize,
+    /// Size of the payloads sent, after compression (including the compression header)
+    pub compressed_bytes_sent: usize,
+    /// Size of the payloads received, after decompression
+    pub uncompressed_bytes_received: usize,
+    /// Size of the payloads received, before decompression (including the compression header)
+    pub compressed_bytes_received: usize,
+}
+
+impl IoStats {
+    /// Ratio between the size of the sent payloads before and after compression.
+    ///
+    /// Returns None if no payload was sent.
+    pub fn compression_ratio_sent(&self) -> Option<f64> {
+        (self.compressed_bytes_sent > 0)
+            .then(|| self.uncompressed_bytes_sent as f64 / self.compressed_bytes_sent as f64)
+    }
+
+    /// Ratio between the size of the received payloads after and before decompression.
+    ///
+    /// Returns None if no payload was received.
+    pub fn compression_ratio_received(&self) -> Option<f64> {
+        (self.compressed_bytes_received > 0).then(|| {
+            self.uncompressed_bytes_received as f64 / self.compressed_bytes_received as f64
+        })
+    }
 }
 
 impl<T: Send + Sync> BaseIo<T> {
@@ -99,6 +126,13 @@ impl IoDiagnosticsPlugin {
     /// How many bytes do we send per second
     pub const PACKETS_OUT: DiagnosticPath = DiagnosticPath::const_new("packets sent per second");
 
+    /// Compression ratio of the payloads we receive
+    pub const COMPRESSION_RATIO_IN: DiagnosticPath =
+        DiagnosticPath::const_new("compression ratio of received payloads");
+    /// Compression ratio of the payloads we send
+    pub const COMPRESSION_RATIO_OUT: DiagnosticPath =
+        DiagnosticPath::const_new("compression ratio of sent payloads");
+
     /// Max diagnostic history length.
     pub const DIAGNOSTIC_HISTORY_LEN: usize = 60;
 
@@ -123,6 +157,12 @@ impl IoDiagnosticsPlugin {
         diagnostics.add_measurement(&Self::PACKETS_OUT, || {
             stats.packets_sent as f64 / delta_seconds
         });
+        if let Some(ratio) = stats.compression_ratio_received() {
+            diagnostics.add_measurement(&Self::COMPRESSION_RATIO_IN, || ratio);
+        }
+        if let Some(ratio) = stats.compression_ratio_sent() {
+            diagnostics.add_measurement(&Self::COMPRESSION_RATIO_OUT, || ratio);
+        }
         *stats = IoStats::default()
     }
 }
@@ -145,6 +185,14 @@ impl Plugin for IoDiagnosticsPlugin {
             Diagnostic::new(IoDiagnosticsPlugin::PACKETS_OUT)
                 .with_max_history_length(IoDiagnosticsPlugin::DIAGNOSTIC_HISTORY_LEN),
         );
+        app.register_diagnostic(
+            Diagnostic::new(IoDiagnosticsPlugin::COMPRESSION_RATIO_IN)
+                .with_max_history_length(IoDiagnosticsPlugin::DIAGNOSTIC_HISTORY_LEN),
+        );
+        app.register_diagnostic(
+            Diagnostic::new(IoDiagnosticsPlugin::COMPRESSION_RATIO_OUT)
+                .with_max_history_length(IoDiagnosticsPlugin::DIAGNOSTIC_HISTORY_LEN),
+        );
     }
 }
 
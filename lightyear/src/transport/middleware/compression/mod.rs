@@ -1,6 +1,21 @@
+//! Compression of the packets sent through a netcode connection.
+//!
+//! Netcode encrypts every packet before it reaches the transport, and encrypted data does not compress,
+//! so the payloads are compressed right before being handed to netcode.
+//!
+//! Every payload is prefixed with a one-byte header:
+//! - the low 4 bits contain the algorithm that was used to compress this payload (or 0 if it is not compressed)
+//! - the high 4 bits contain the set of algorithms that the sender can decompress
+//!
+//! A peer only compresses its payloads once it has received a header from the other peer that advertises
+//! support for its configured algorithm, so the compression is negotiated during the first exchanged packets,
+//! and peers with different [`CompressionConfig`]s can still talk to each other.
 use bevy::prelude::Reflect;
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 
+use crate::transport::error::{Error, Result};
+
 #[cfg(feature = "zstd")]
 pub(crate) mod zstd;
 
@@ -16,3 +31,194 @@ pub enum CompressionConfig {
     #[cfg(feature = "lz4")]
     Lz4,
 }
+
+/// Payloads smaller than this number of bytes are not compressed by default, since the gain would be negligible
+pub(crate) const DEFAULT_COMPRESSION_THRESHOLD: usize = 64;
+/// Size of the header added in front of every payload
+pub(crate) const COMPRESSION_HEADER_BYTES: usize = 1;
+
+const ALGORITHM_NONE: u8 = 0;
+#[cfg(feature = "zstd")]
+const ALGORITHM_ZSTD: u8 = 1;
+#[cfg(feature = "lz4")]
+const ALGORITHM_LZ4: u8 = 2;
+
+/// Set of algorithms that this build can decompress, advertised to the peer in every header
+const SUPPORTED_ALGORITHMS: u8 = {
+    #[allow(unused_mut)]
+    let mut supported = 0;
+    #[cfg(feature = "zstd")]
+    {
+        supported |= 1 << ALGORITHM_ZSTD;
+    }
+    #[cfg(feature = "lz4")]
+    {
+        supported |= 1 << ALGORITHM_LZ4;
+    }
+    supported
+};
+
+impl CompressionConfig {
+    fn algorithm(&self) -> u8 {
+        match self {
+            CompressionConfig::None => ALGORITHM_NONE,
+            #[cfg(feature = "zstd")]
+            CompressionConfig::Zstd { .. } => ALGORITHM_ZSTD,
+            #[cfg(feature = "lz4")]
+            CompressionConfig::Lz4 => ALGORITHM_LZ4,
+        }
+    }
+}
+
+/// Adds the compression header to the payloads, and compresses them if the peer supports it.
+///
+/// The algorithms supported by each peer are tracked by the caller, since a server has one per client.
+pub(crate) struct PacketCompressor {
+    config: CompressionConfig,
+    threshold: usize,
+    buffer: Vec<u8>,
+    #[cfg(feature = "zstd")]
+    zstd_compressor: Option<zstd::compression::ZstdCompressor>,
+    #[cfg(feature = "zstd")]
+    zstd_decompressor: zstd::decompression::ZstdDecompressor,
+    #[cfg(feature = "lz4")]
+    lz4_compressor: lz4::Compressor,
+    #[cfg(feature = "lz4")]
+    lz4_decompressor: lz4::Decompressor,
+}
+
+impl PacketCompressor {
+    pub(crate) fn new(config: CompressionConfig, threshold: usize) -> Self {
+        Self {
+            config,
+            threshold,
+            buffer: Vec::new(),
+            #[cfg(feature = "zstd")]
+            zstd_compressor: match config {
+                CompressionConfig::Zstd { level } => {
+                    Some(zstd::compression::ZstdCompressor::new(level))
+                }
+                _ => None,
+            },
+            #[cfg(feature = "zstd")]
+            zstd_decompressor: zstd::decompression::ZstdDecompressor::new(),
+            #[cfg(feature = "lz4")]
+            lz4_compressor: lz4::Compressor::default(),
+            #[cfg(feature = "lz4")]
+            lz4_decompressor: lz4::Decompressor::default(),
+        }
+    }
+
+    /// Returns the payload prefixed with the compression header.
+    ///
+    /// The payload is compressed only if it is larger than the threshold, if `peer_algorithms` (the algorithms
+    /// advertised by the peer, or 0 if we didn't hear from the peer yet) contains the configured algorithm,
+    /// and if compression actually makes it smaller.
+    pub(crate) fn compress(&mut self, payload: &[u8], peer_algorithms: u8) -> Result<&[u8]> {
+        let algorithm = self.config.algorithm();
+        self.buffer.clear();
+        if algorithm != ALGORITHM_NONE
+            && payload.len() >= self.threshold
+            && peer_algorithms & (1 << algorithm) != 0
+        {
+            let compressed: &[u8] = match self.config {
+                CompressionConfig::None => payload,
+                #[cfg(feature = "zstd")]
+                CompressionConfig::Zstd { .. } => self
+                    .zstd_compressor
+                    .as_mut()
+                    .expect("zstd compressor should be created for zstd compression")
+                    .compress(payload)?,
+                #[cfg(feature = "lz4")]
+                CompressionConfig::Lz4 => self.lz4_compressor.compress(payload)?,
+            };
+            if compressed.len() < payload.len() {
+                self.buffer.push(SUPPORTED_ALGORITHMS << 4 | algorithm);
+                self.buffer.extend_from_slice(compressed);
+                return Ok(&self.buffer);
+            }
+        }
+        self.buffer.push(SUPPORTED_ALGORITHMS << 4 | ALGORITHM_NONE);
+        self.buffer.extend_from_slice(payload);
+        Ok(&self.buffer)
+    }
+
+    /// Strips the compression header and decompresses the payload if needed.
+    ///
+    /// Returns the payload and the set of algorithms that the peer can decompress.
+    /// Uncompressed payloads are returned as a slice of `packet`, without copying.
+    pub(crate) fn decompress(&mut self, packet: Bytes) -> Result<(Bytes, u8)> {
+        let Some(&header) = packet.first() else {
+            return Err(Error::InvalidCompressionHeader(0));
+        };
+        let peer_algorithms = header >> 4;
+        let payload = match header & 0xF {
+            ALGORITHM_NONE => packet.slice(COMPRESSION_HEADER_BYTES..),
+            #[cfg(feature = "zstd")]
+            ALGORITHM_ZSTD => Bytes::copy_from_slice(
+                self.zstd_decompressor
+                    .decompress(&packet[COMPRESSION_HEADER_BYTES..])?,
+            ),
+            #[cfg(feature = "lz4")]
+            ALGORITHM_LZ4 => Bytes::copy_from_slice(
+                self.lz4_decompressor
+                    .decompress(&packet[COMPRESSION_HEADER_BYTES..])?,
+            ),
+            _ => return Err(Error::InvalidCompressionHeader(header)),
+        };
+        Ok((payload, peer_algorithms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_compression() {
+        let mut compressor = PacketCompressor::new(CompressionConfig::None, 0);
+        let msg = [1; 100];
+        let packet = Bytes::copy_from_slice(compressor.compress(&msg, 0xF).unwrap());
+        assert_eq!(packet.len(), msg.len() + COMPRESSION_HEADER_BYTES);
+        let (payload, peer_algorithms) = compressor.decompress(packet).unwrap();
+        assert_eq!(payload.as_ref(), msg);
+        assert_eq!(peer_algorithms, SUPPORTED_ALGORITHMS);
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_lz4_negotiation() {
+        let mut compressor = PacketCompressor::new(CompressionConfig::Lz4, 10);
+        let msg = [1; 100];
+
+        // the peer did not advertise lz4 yet
+        let packet = compressor.compress(&msg, 0).unwrap().to_vec();
+        assert_eq!(packet.len(), msg.len() + COMPRESSION_HEADER_BYTES);
+
+        // the peer supports lz4
+        let packet =
+            Bytes::copy_from_slice(compressor.compress(&msg, SUPPORTED_ALGORITHMS).unwrap());
+        assert!(packet.len() < msg.len());
+        let (payload, _) = compressor.decompress(packet).unwrap();
+        assert_eq!(payload.as_ref(), msg);
+
+        // payloads below the threshold are not compressed
+        let packet = compressor
+            .compress(&msg[..5], SUPPORTED_ALGORITHMS)
+            .unwrap()
+            .to_vec();
+        assert_eq!(packet.len(), 5 + COMPRESSION_HEADER_BYTES);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd() {
+        let mut compressor = PacketCompressor::new(CompressionConfig::Zstd { level: 0 }, 10);
+        let msg = [1; 100];
+        let packet =
+            Bytes::copy_from_slice(compressor.compress(&msg, SUPPORTED_ALGORITHMS).unwrap());
+        assert!(packet.len() < msg.len());
+        let (payload, _) = compressor.decompress(packet).unwrap();
+        assert_eq!(payload.as_ref(), msg);
+    }
+}
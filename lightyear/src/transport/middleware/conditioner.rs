@@ -3,25 +3,15 @@ use bevy::reflect::Reflect;
 use std::net::SocketAddr;
 
 use bevy::utils::Duration;
-use cfg_if::cfg_if;
 use rand;
 use rand::{thread_rng, Rng};
 
-use crate::transport::error::Result;
-use crate::transport::middleware::PacketReceiverWrapper;
-use crate::transport::PacketReceiver;
+pub(crate) use crate::transport::middleware::Instant;
+use crate::transport::middleware::PacketMiddleware;
 use crate::utils::ready_buffer::ReadyBuffer;
 
-cfg_if! {
-    if #[cfg(test)] {
-        use mock_instant::Instant;
-    } else {
-        use bevy::utils::Instant;
-    }
-}
-
 /// Contains configuration required to initialize a LinkConditioner
-#[derive(Clone, Debug, Reflect)]
+#[derive(Clone, Debug, Default, Reflect)]
 pub struct LinkConditionerConfig {
     /// Delay to receive incoming messages in milliseconds (half the RTT)
     pub incoming_latency: Duration,
@@ -29,96 +19,184 @@ pub struct LinkConditionerConfig {
     /// messages in milliseconds. This may be added OR subtracted from the
     /// latency determined in the `incoming_latency` property above
     pub incoming_jitter: Duration,
+    /// The distribution used to sample the jitter of each packet
+    pub incoming_jitter_distribution: JitterDistribution,
     /// The % chance that an incoming packet will be dropped.
     /// Represented as a value between 0 and 1
     pub incoming_loss: f32,
+    /// Simulate losses that happen in bursts, in addition to `incoming_loss`
+    pub incoming_burst_loss: Option<GilbertElliottConfig>,
+    /// The % chance that an incoming packet will be received twice.
+    /// Represented as a value between 0 and 1
+    pub incoming_duplication: f32,
+    /// The % chance that an incoming packet will be delayed by an additional random duration
+    /// (up to `incoming_latency`), so that it is received after packets that were sent after it.
+    /// Represented as a value between 0 and 1
+    pub incoming_reordering: f32,
+    /// Maximum number of bytes per second that can be received.
+    /// Packets are queued behind each other when the link is saturated.
+    pub incoming_bandwidth: Option<u32>,
 }
 
-pub(crate) type PacketLinkConditioner = LinkConditioner<(SocketAddr, Box<[u8]>)>;
+/// Distribution of the jitter added to the latency of each packet
+#[derive(Clone, Copy, Debug, Default, PartialEq, Reflect)]
+pub enum JitterDistribution {
+    /// The jitter is sampled uniformly between `-incoming_jitter` and `incoming_jitter`
+    #[default]
+    Uniform,
+    /// The jitter follows a normal distribution with a standard deviation of `incoming_jitter`
+    Normal,
+    /// The jitter is always added to the latency, and follows a heavy-tailed Pareto distribution
+    /// with a scale of `incoming_jitter`.
+    ///
+    /// A smaller `shape` makes large delay spikes more likely.
+    Pareto { shape: f32 },
+}
+
+/// Gilbert-Elliott model of burst losses.
+///
+/// The link alternates between a good state and a bad state, with a different loss
+/// probability in each state.
+#[derive(Clone, Debug, Reflect)]
+pub struct GilbertElliottConfig {
+    /// The % chance of going from the good state to the bad state on each packet
+    pub good_to_bad: f32,
+    /// The % chance of going from the bad state to the good state on each packet
+    pub bad_to_good: f32,
+    /// The % chance that a packet is dropped in the good state
+    pub loss_in_good: f32,
+    /// The % chance that a packet is dropped in the bad state
+    pub loss_in_bad: f32,
+}
 
 pub(crate) struct LinkConditioner<P: Eq> {
     config: LinkConditionerConfig,
     pub time_queue: ReadyBuffer<Instant, P>,
-    last_packet: Option<P>,
+    /// True if the burst loss model is in the bad state
+    in_burst: bool,
+    /// Instant at which the simulated link will be done receiving the previous packets
+    link_free_at: Option<Instant>,
 }
 
-impl<P: Eq> LinkConditioner<P> {
+impl<P: Eq + Clone> LinkConditioner<P> {
     pub fn new(config: LinkConditionerConfig) -> Self {
         LinkConditioner {
             config,
             time_queue: ReadyBuffer::new(),
-            last_packet: None,
+            in_burst: false,
+            link_free_at: None,
         }
     }
 
-    /// Add latency/jitter/loss to a packet
-    fn condition_packet(&mut self, packet: P) {
-        let mut rng = thread_rng();
-        if rng.gen_range(0.0..1.0) <= self.config.incoming_loss {
+    /// Add latency/jitter/loss/duplication/reordering to a packet of `size` bytes
+    fn condition_packet(&mut self, packet: P, size: usize) {
+        // TODO: how can i use the virtual time here?
+        self.condition_packet_at(packet, size, Instant::now(), &mut thread_rng());
+    }
+
+    /// Add latency/jitter/loss/duplication/reordering to a packet of `size` bytes that is
+    /// received at `now`, using `rng` as the source of randomness.
+    ///
+    /// This lets simulations drive the conditioner with a virtual clock and a seeded rng.
+    pub(crate) fn condition_packet_at(
+        &mut self,
+        packet: P,
+        size: usize,
+        now: Instant,
+        rng: &mut impl Rng,
+    ) {
+        if rng.gen_range(0.0..1.0) <= self.config.incoming_loss || self.burst_loss(rng) {
             return;
         }
-        let mut latency: i32 = self.config.incoming_latency.as_millis() as i32;
-        // TODO: how can i use the virtual time here?
-        let mut packet_timestamp = Instant::now();
-        if self.config.incoming_jitter > Duration::default() {
-            let jitter: i32 = self.config.incoming_jitter.as_millis() as i32;
-            latency += rng.gen_range(-jitter..jitter);
+        let mut received_at = now;
+        if let Some(bandwidth) = self.config.incoming_bandwidth {
+            if let Some(link_free_at) = self.link_free_at {
+                if link_free_at > received_at {
+                    received_at = link_free_at;
+                }
+            }
+            received_at += Duration::from_secs_f64(size as f64 / bandwidth.max(1) as f64);
+            self.link_free_at = Some(received_at);
+        }
+        if rng.gen_range(0.0..1.0) < self.config.incoming_duplication {
+            let latency = self.sample_latency(rng);
+            self.time_queue.push(received_at + latency, packet.clone());
+        }
+        let latency = self.sample_latency(rng);
+        self.time_queue.push(received_at + latency, packet);
+    }
+
+    /// Update the state of the burst loss model, and return true if the packet should be dropped
+    fn burst_loss(&mut self, rng: &mut impl Rng) -> bool {
+        let Some(burst_loss) = &self.config.incoming_burst_loss else {
+            return false;
+        };
+        let transition = if self.in_burst {
+            burst_loss.bad_to_good
+        } else {
+            burst_loss.good_to_bad
+        };
+        if rng.gen_range(0.0..1.0) < transition {
+            self.in_burst = !self.in_burst;
+        }
+        let loss = if self.in_burst {
+            burst_loss.loss_in_bad
+        } else {
+            burst_loss.loss_in_good
+        };
+        rng.gen_range(0.0..1.0) < loss
+    }
+
+    /// Sample the delay of a packet, including jitter and reordering
+    fn sample_latency(&self, rng: &mut impl Rng) -> Duration {
+        let mut latency = self.config.incoming_latency.as_secs_f32();
+        let jitter = self.config.incoming_jitter.as_secs_f32();
+        if jitter > 0.0 {
+            latency += match self.config.incoming_jitter_distribution {
+                JitterDistribution::Uniform => rng.gen_range(-jitter..jitter),
+                JitterDistribution::Normal => {
+                    // Box-Muller transform
+                    let u1: f32 = 1.0 - rng.gen_range(0.0..1.0);
+                    let u2: f32 = rng.gen_range(0.0..1.0);
+                    jitter * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+                }
+                JitterDistribution::Pareto { shape } => {
+                    // inverse transform sampling, shifted so that the jitter starts at 0
+                    let u: f32 = 1.0 - rng.gen_range(0.0..1.0);
+                    jitter * (u.powf(-1.0 / shape) - 1.0)
+                }
+            };
         }
-        if latency > 0 {
-            packet_timestamp += Duration::from_millis(latency as u64);
+        if rng.gen_range(0.0..1.0) < self.config.incoming_reordering {
+            latency += rng.gen_range(0.0..=self.config.incoming_latency.as_secs_f32());
         }
-        self.time_queue.push(packet_timestamp, packet);
+        Duration::try_from_secs_f32(latency.max(0.0)).unwrap_or(Duration::MAX)
     }
 
     /// Check if a packet is ready to be returned
     fn pop_packet(&mut self) -> Option<P> {
-        self.time_queue
-            .pop_item(&Instant::now())
-            .map(|(_, packet)| packet)
+        self.pop_packet_at(&Instant::now())
     }
-}
 
-impl<T: PacketReceiver> PacketReceiverWrapper<T> for LinkConditioner<(SocketAddr, Box<[u8]>)> {
-    fn wrap(self, receiver: T) -> impl PacketReceiver {
-        ConditionedPacketReceiver {
-            packet_receiver: receiver,
-            conditioner: self,
-        }
+    /// Return a packet that is ready to be received at `now`
+    pub(crate) fn pop_packet_at(&mut self, now: &Instant) -> Option<P> {
+        self.time_queue.pop_item(now).map(|(_, packet)| packet)
     }
 }
 
-/// A wrapper around a packet receiver that simulates network conditions
-/// by adding latency, jitter and packet loss to incoming packets.
-pub struct ConditionedPacketReceiver<T: PacketReceiver, P: Eq> {
-    packet_receiver: T,
-    conditioner: LinkConditioner<P>,
-}
+/// The conditioner is added as the outermost [`PacketMiddleware`] around the transport, so each
+/// remote address gets its own simulated link.
+impl PacketMiddleware for LinkConditioner<Vec<u8>> {
+    fn on_recv(&mut self, packet: &mut Vec<u8>, _: SocketAddr) -> bool {
+        // add conditioning (put the packet in the time queue)
+        let size = packet.len();
+        self.condition_packet(std::mem::take(packet), size);
+        false
+    }
 
-impl<T: PacketReceiver> PacketReceiver for ConditionedPacketReceiver<T, (SocketAddr, Box<[u8]>)> {
-    fn recv(&mut self) -> Result<Option<(&mut [u8], SocketAddr)>> {
-        loop {
-            // keep trying to receive packets from the inner packet receiver
-            let option = self.packet_receiver.recv()?;
-            match option {
-                None => break,
-                // add conditioning (put the packets in the time queue)
-                Some((data, addr)) => self
-                    .conditioner
-                    .condition_packet((addr, data.to_vec().into_boxed_slice())),
-            }
-        }
+    fn poll_recv(&mut self) -> Option<Vec<u8>> {
         // only return a packet if it is ready to be returned
-        match self.conditioner.pop_packet() {
-            Some((addr, data)) => {
-                // we use `last_packet` to get ownership of the data
-                self.conditioner.last_packet = Some((addr, data));
-                Ok(Some((
-                    self.conditioner.last_packet.as_mut().unwrap().1.as_mut(),
-                    addr,
-                )))
-            }
-            None => Ok(None),
-        }
+        self.pop_packet()
     }
 }
 
@@ -129,6 +207,7 @@ impl LinkConditionerConfig {
             incoming_latency,
             incoming_jitter,
             incoming_loss,
+            ..Default::default()
         }
     }
 
@@ -139,6 +218,7 @@ impl LinkConditionerConfig {
             incoming_latency: Duration::from_millis(40),
             incoming_jitter: Duration::from_millis(6),
             incoming_loss: 0.002,
+            ..Default::default()
         }
     }
 
@@ -149,6 +229,7 @@ impl LinkConditionerConfig {
             incoming_latency: Duration::from_millis(170),
             incoming_jitter: Duration::from_millis(45),
             incoming_loss: 0.02,
+            ..Default::default()
         }
     }
 
@@ -159,6 +240,75 @@ impl LinkConditionerConfig {
             incoming_latency: Duration::from_millis(300),
             incoming_jitter: Duration::from_millis(84),
             incoming_loss: 0.04,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mock_instant::MockClock;
+
+    use super::*;
+
+    #[test]
+    fn test_duplication() {
+        let mut conditioner = LinkConditioner::new(LinkConditionerConfig {
+            incoming_duplication: 1.0,
+            ..Default::default()
+        });
+        conditioner.condition_packet(1, 10);
+        assert_eq!(conditioner.pop_packet(), Some(1));
+        assert_eq!(conditioner.pop_packet(), Some(1));
+        assert_eq!(conditioner.pop_packet(), None);
+    }
+
+    #[test]
+    fn test_burst_loss() {
+        let mut conditioner = LinkConditioner::new(LinkConditionerConfig {
+            incoming_burst_loss: Some(GilbertElliottConfig {
+                good_to_bad: 1.0,
+                bad_to_good: 0.0,
+                loss_in_good: 0.0,
+                loss_in_bad: 1.0,
+            }),
+            ..Default::default()
+        });
+        // the link goes to the bad state and never recovers
+        for i in 0..10 {
+            conditioner.condition_packet(i, 10);
+        }
+        assert_eq!(conditioner.pop_packet(), None);
+    }
+
+    #[test]
+    fn test_bandwidth() {
+        let mut conditioner = LinkConditioner::new(LinkConditionerConfig {
+            incoming_bandwidth: Some(1000),
+            ..Default::default()
+        });
+        // each packet takes 100ms to be received
+        conditioner.condition_packet(1, 100);
+        conditioner.condition_packet(2, 100);
+        assert_eq!(conditioner.pop_packet(), None);
+        MockClock::advance(Duration::from_millis(100));
+        assert_eq!(conditioner.pop_packet(), Some(1));
+        assert_eq!(conditioner.pop_packet(), None);
+        MockClock::advance(Duration::from_millis(100));
+        assert_eq!(conditioner.pop_packet(), Some(2));
+    }
+
+    #[test]
+    fn test_pareto_jitter_is_positive() {
+        let conditioner = LinkConditioner::<u32>::new(LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(50),
+            incoming_jitter: Duration::from_millis(10),
+            incoming_jitter_distribution: JitterDistribution::Pareto { shape: 2.0 },
+            ..Default::default()
+        });
+        let mut rng = thread_rng();
+        for _ in 0..100 {
+            assert!(conditioner.sample_latency(&mut rng) >= Duration::from_millis(49));
         }
     }
 }
@@ -1,7 +1,17 @@
 //! Module defining 'wrappers' that modify the behaviour of an existing [`PacketReceiver`] or [`PacketSender`].
 //!
-//! Wrappers are used to add additional functionality to an existing transport, such as encryption, compression, metrics, etc.
-use crate::transport::{PacketReceiver, PacketSender};
+//! Wrappers are used to add additional functionality to an existing transport, such as compression, metrics, etc.
+//! Users can add their own layers by implementing [`PacketMiddleware`].
+//! Encryption is not done here: it is handled by the netcode connection layer, see [`crate::connection`].
+use std::fmt::{Debug, Formatter};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use bevy::utils::{Duration, HashMap};
+use cfg_if::cfg_if;
+
+use crate::transport::error::Result;
+use crate::transport::{BoxedReceiver, BoxedSender, PacketReceiver, PacketSender};
 
 /// A conditioner is used to simulate network conditions such as latency, jitter and packet loss.
 pub(crate) mod conditioner;
@@ -9,10 +19,319 @@ pub(crate) mod conditioner;
 /// Middleware that compresses packets before sending them.
 pub(crate) mod compression;
 
-pub trait PacketReceiverWrapper<T: PacketReceiver> {
-    fn wrap(self, receiver: T) -> impl PacketReceiver;
+cfg_if! {
+    if #[cfg(test)] {
+        pub(crate) use mock_instant::Instant;
+    } else {
+        pub(crate) use bevy::utils::Instant;
+    }
+}
+
+/// The instance of a middleware for a remote address is dropped if no packet was sent to or
+/// received from that address for this long
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// User-defined layer that can inspect, modify or drop packets right before they are sent
+/// on the transport, and right after they are received from it.
+///
+/// This can be used to add logging, custom encryption, replay capture, etc. to any transport.
+/// Middlewares are added with [`SharedIoConfig::with_middleware`](crate::transport::config::SharedIoConfig::with_middleware).
+///
+/// An instance only sees the packets exchanged with a single remote address, in both directions,
+/// so it can keep per-connection state.
+pub trait PacketMiddleware: Send + Sync + 'static {
+    /// Called with a packet that is about to be sent to `address`.
+    ///
+    /// The packet can be modified in place. Return `false` to drop it.
+    fn on_send(&mut self, packet: &mut Vec<u8>, address: SocketAddr) -> bool {
+        let _ = (packet, address);
+        true
+    }
+
+    /// Called with a packet that was just received from `address`.
+    ///
+    /// The packet can be modified in place. Return `false` to drop it.
+    fn on_recv(&mut self, packet: &mut Vec<u8>, address: SocketAddr) -> bool {
+        let _ = (packet, address);
+        true
+    }
+
+    /// Called when all the packets available on the transport have been passed to [`on_recv`](Self::on_recv).
+    ///
+    /// Returns a packet that was held back by `on_recv` and is now ready to be received, so that
+    /// the middleware can delay packets (for example to simulate latency).
+    fn poll_recv(&mut self) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+type MiddlewareFactory = Arc<dyn Fn() -> Box<dyn PacketMiddleware> + Send + Sync>;
+
+/// Stack of [`PacketMiddleware`]s applied around a transport.
+///
+/// Each remote address gets its own instances of the middlewares, created the first time a packet is
+/// sent to or received from that address, so the config can be cloned freely. The instances are dropped
+/// after 30 seconds without any packet sent to or received from their address, so that the clients that
+/// disconnected (or packets with spoofed addresses) don't keep them alive.
+/// The first middleware added is the closest to the transport: it is the last one to see a packet
+/// that is sent, and the first one to see a packet that is received.
+#[derive(Clone, Default)]
+pub struct PacketMiddlewares(Vec<MiddlewareFactory>);
+
+impl Debug for PacketMiddlewares {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("PacketMiddlewares")
+            .field(&self.0.len())
+            .finish()
+    }
 }
 
-pub trait PacketSenderWrapper<T: PacketSender> {
-    fn wrap(self, sender: T) -> impl PacketSender;
+impl PacketMiddlewares {
+    pub(crate) fn push<M: PacketMiddleware>(
+        &mut self,
+        factory: impl Fn() -> M + Send + Sync + 'static,
+    ) {
+        self.0.push(Arc::new(move || {
+            Box::new(factory()) as Box<dyn PacketMiddleware>
+        }));
+    }
+
+    /// Wrap the sender and receiver of a transport with the middlewares
+    pub(crate) fn wrap(
+        &self,
+        mut sender: BoxedSender,
+        mut receiver: BoxedReceiver,
+    ) -> (BoxedSender, BoxedReceiver) {
+        for factory in &self.0 {
+            // the same instances intercept both directions, so that they can share state between them
+            let layer = Arc::new(MiddlewareLayer {
+                factory: factory.clone(),
+                instances: Mutex::new(MiddlewareInstances {
+                    instances: HashMap::default(),
+                    last_eviction: Instant::now(),
+                }),
+            });
+            sender = Box::new(MiddlewareSender {
+                inner: sender,
+                layer: layer.clone(),
+                buffer: Vec::new(),
+            });
+            receiver = Box::new(MiddlewareReceiver {
+                inner: receiver,
+                layer,
+                buffer: Vec::new(),
+            });
+        }
+        (sender, receiver)
+    }
+}
+
+/// The instances of a middleware, one for each remote address
+struct MiddlewareLayer {
+    factory: MiddlewareFactory,
+    instances: Mutex<MiddlewareInstances>,
+}
+
+struct MiddlewareInstances {
+    /// The instance for each remote address, with the last time it was used
+    instances: HashMap<SocketAddr, (Box<dyn PacketMiddleware>, Instant)>,
+    last_eviction: Instant,
+}
+
+impl MiddlewareLayer {
+    /// Get the instance of the middleware for the remote address, creating it if needed
+    fn with_instance<R>(
+        &self,
+        address: SocketAddr,
+        f: impl FnOnce(&mut dyn PacketMiddleware) -> R,
+    ) -> R {
+        let now = Instant::now();
+        let mut instances = self.instances.lock().unwrap();
+        if now - instances.last_eviction >= IDLE_TIMEOUT {
+            instances
+                .instances
+                .retain(|_, (_, last_used)| now - *last_used < IDLE_TIMEOUT);
+            instances.last_eviction = now;
+        }
+        let (middleware, last_used) = instances
+            .instances
+            .entry(address)
+            .or_insert_with(|| ((self.factory)(), now));
+        *last_used = now;
+        f(middleware.as_mut())
+    }
+
+    /// Return a packet that one of the instances held back and that is now ready to be received
+    fn poll_recv(&self) -> Option<(Vec<u8>, SocketAddr)> {
+        let mut instances = self.instances.lock().unwrap();
+        instances
+            .instances
+            .iter_mut()
+            .find_map(|(address, (middleware, _))| {
+                middleware.poll_recv().map(|packet| (packet, *address))
+            })
+    }
+}
+
+struct MiddlewareSender {
+    inner: BoxedSender,
+    layer: Arc<MiddlewareLayer>,
+    buffer: Vec<u8>,
+}
+
+impl PacketSender for MiddlewareSender {
+    fn send(&mut self, payload: &[u8], address: &SocketAddr) -> Result<()> {
+        self.buffer.clear();
+        self.buffer.extend_from_slice(payload);
+        if !self.layer.with_instance(*address, |middleware| {
+            middleware.on_send(&mut self.buffer, *address)
+        }) {
+            return Ok(());
+        }
+        self.inner.send(&self.buffer, address)
+    }
+}
+
+struct MiddlewareReceiver {
+    inner: BoxedReceiver,
+    layer: Arc<MiddlewareLayer>,
+    buffer: Vec<u8>,
+}
+
+impl PacketReceiver for MiddlewareReceiver {
+    fn recv(&mut self) -> Result<Option<(&mut [u8], SocketAddr)>> {
+        while let Some((data, address)) = self.inner.recv()? {
+            self.buffer.clear();
+            self.buffer.extend_from_slice(data);
+            if self.layer.with_instance(address, |middleware| {
+                middleware.on_recv(&mut self.buffer, address)
+            }) {
+                return Ok(Some((self.buffer.as_mut_slice(), address)));
+            }
+        }
+        Ok(self.layer.poll_recv().map(|(packet, address)| {
+            self.buffer = packet;
+            (self.buffer.as_mut_slice(), address)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::io::transport::ClientTransportBuilder;
+    use crate::transport::local::LocalChannelBuilder;
+    use crate::transport::{Transport, LOCAL_SOCKET};
+
+    use super::*;
+
+    /// Xor every byte, and drop the packets that start with 0
+    struct XorMiddleware;
+
+    impl PacketMiddleware for XorMiddleware {
+        fn on_send(&mut self, packet: &mut Vec<u8>, _: SocketAddr) -> bool {
+            packet.iter_mut().for_each(|b| *b ^= 0xff);
+            packet.first() != Some(&0xff)
+        }
+
+        fn on_recv(&mut self, packet: &mut Vec<u8>, _: SocketAddr) -> bool {
+            packet.iter_mut().for_each(|b| *b ^= 0xff);
+            true
+        }
+    }
+
+    #[test]
+    fn test_middleware() {
+        let (send, recv) = crossbeam_channel::unbounded();
+        let (transport, _, _, _) = LocalChannelBuilder { recv, send }.connect().unwrap();
+        let (sender, receiver) = transport.split();
+        let mut middlewares = PacketMiddlewares::default();
+        middlewares.push(|| XorMiddleware);
+        let (mut sender, mut receiver) = middlewares.wrap(sender, receiver);
+
+        sender.send(&[0, 1, 2], &LOCAL_SOCKET).unwrap();
+        sender.send(&[1, 2, 3], &LOCAL_SOCKET).unwrap();
+        // the first packet was dropped, the second one was transformed back by the receiver
+        let (data, _) = receiver.recv().unwrap().unwrap();
+        assert_eq!(data, &[1, 2, 3]);
+        assert!(receiver.recv().unwrap().is_none());
+    }
+
+    /// Prefix each packet with the number of packets sent by this instance
+    #[derive(Default)]
+    struct CounterMiddleware(u8);
+
+    impl PacketMiddleware for CounterMiddleware {
+        fn on_send(&mut self, packet: &mut Vec<u8>, _: SocketAddr) -> bool {
+            packet.insert(0, self.0);
+            self.0 += 1;
+            true
+        }
+    }
+
+    /// Sender that records the packets that are sent
+    #[derive(Default)]
+    struct RecordingSender(Arc<Mutex<Vec<(Vec<u8>, SocketAddr)>>>);
+
+    impl PacketSender for RecordingSender {
+        fn send(&mut self, payload: &[u8], address: &SocketAddr) -> Result<()> {
+            self.0.lock().unwrap().push((payload.to_vec(), *address));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_middleware_instance_per_address() {
+        let (send, recv) = crossbeam_channel::unbounded();
+        let (transport, _, _, _) = LocalChannelBuilder { recv, send }.connect().unwrap();
+        let (_, receiver) = transport.split();
+        let sender = RecordingSender::default();
+        let sent = sender.0.clone();
+        let mut middlewares = PacketMiddlewares::default();
+        middlewares.push(CounterMiddleware::default);
+        let (mut sender, _) = middlewares.wrap(Box::new(sender), receiver);
+
+        // a server sends packets to multiple clients through the same transport
+        let address_1 = SocketAddr::from(([127, 0, 0, 1], 1001));
+        let address_2 = SocketAddr::from(([127, 0, 0, 1], 1002));
+        sender.send(&[10], &address_1).unwrap();
+        sender.send(&[20], &address_2).unwrap();
+        sender.send(&[11], &address_1).unwrap();
+        assert_eq!(
+            *sent.lock().unwrap(),
+            vec![
+                (vec![0, 10], address_1),
+                (vec![0, 20], address_2),
+                (vec![1, 11], address_1),
+            ]
+        );
+    }
+    #[test]
+    fn test_middleware_idle_instance_evicted() {
+        use mock_instant::MockClock;
+
+        let (send, recv) = crossbeam_channel::unbounded();
+        let (transport, _, _, _) = LocalChannelBuilder { recv, send }.connect().unwrap();
+        let (_, receiver) = transport.split();
+        let sender = RecordingSender::default();
+        let sent = sender.0.clone();
+        let mut middlewares = PacketMiddlewares::default();
+        middlewares.push(CounterMiddleware::default);
+        let (mut sender, _) = middlewares.wrap(Box::new(sender), receiver);
+
+        let address_1 = SocketAddr::from(([127, 0, 0, 1], 1001));
+        let address_2 = SocketAddr::from(([127, 0, 0, 1], 1002));
+        sender.send(&[10], &address_1).unwrap();
+        MockClock::advance(IDLE_TIMEOUT);
+        // the instance of address_1 is evicted, so a new one is created
+        sender.send(&[20], &address_2).unwrap();
+        sender.send(&[11], &address_1).unwrap();
+        assert_eq!(
+            *sent.lock().unwrap(),
+            vec![
+                (vec![0, 10], address_1),
+                (vec![0, 20], address_2),
+                (vec![0, 11], address_1),
+            ]
+        );
+    }
 }
@@ -10,10 +10,26 @@ use error::Result;
 // required import for enum dispatch to work
 use crate::client::io::transport::ClientTransportEnum;
 use crate::server::io::transport::ServerTransportEnum;
+#[cfg(not(target_family = "wasm"))]
+use crate::transport::async_transport::{AsyncTransportBuilder, AsyncTransportSocket};
 use crate::transport::channels::Channels;
 use crate::transport::dummy::DummyIo;
 use crate::transport::local::LocalChannel;
+#[cfg(all(feature = "quic", not(target_family = "wasm")))]
+use crate::transport::quic::{
+    client::{QuicClientSocket, QuicClientSocketBuilder},
+    server::{QuicServerSocket, QuicServerSocketBuilder},
+};
+#[cfg(not(target_family = "wasm"))]
+use crate::transport::relay::{RelaySocket, RelaySocketBuilder};
+#[cfg(all(feature = "tcp", not(target_family = "wasm")))]
+use crate::transport::tcp::{
+    client::{TcpClientSocket, TcpClientSocketBuilder},
+    server::{TcpServerSocket, TcpServerSocketBuilder},
+};
 use crate::transport::udp::UdpSocket;
+#[cfg(unix)]
+use crate::transport::unix::{UnixClientSocketBuilder, UnixServerSocketBuilder, UnixTransport};
 #[cfg(feature = "websocket")]
 use crate::transport::websocket::client::{WebSocketClientSocket, WebSocketClientSocketBuilder};
 #[cfg(all(feature = "websocket", not(target_family = "wasm")))]
@@ -44,6 +60,36 @@ pub(crate) mod channels;
 #[cfg(feature = "webtransport")]
 pub(crate) mod webtransport;
 
+/// The transport is using raw QUIC connections
+#[cfg_attr(docsrs, doc(cfg(all(feature = "quic", not(target_family = "wasm")))))]
+#[cfg(all(feature = "quic", not(target_family = "wasm")))]
+pub(crate) mod quic;
+
+/// The transport is a TCP stream, for networks where UDP is not available
+#[cfg_attr(docsrs, doc(cfg(all(feature = "tcp", not(target_family = "wasm")))))]
+#[cfg(all(feature = "tcp", not(target_family = "wasm")))]
+pub(crate) mod tcp;
+
+/// The transport is a unix domain datagram socket
+#[cfg_attr(docsrs, doc(cfg(unix)))]
+#[cfg(unix)]
+pub(crate) mod unix;
+
+/// Support for user-provided async transports
+#[cfg_attr(docsrs, doc(cfg(not(target_family = "wasm"))))]
+#[cfg(not(target_family = "wasm"))]
+pub mod async_transport;
+
+/// NAT traversal helpers for UDP transports
+#[cfg_attr(docsrs, doc(cfg(not(target_family = "wasm"))))]
+#[cfg(not(target_family = "wasm"))]
+pub mod nat;
+
+/// Relay that forwards packets between clients and a server, and the transport to go through it
+#[cfg_attr(docsrs, doc(cfg(not(target_family = "wasm"))))]
+#[cfg(not(target_family = "wasm"))]
+pub mod relay;
+
 pub(crate) mod middleware;
 
 pub mod config;
@@ -0,0 +1,353 @@
+//! NAT traversal via UDP hole punching.
+//!
+//! Player-hosted servers are usually behind a NAT, which drops unsolicited packets coming from clients.
+//! This module provides a small rendezvous protocol to work around this without port forwarding:
+//! 1. Both peers (the host and the client) register to a publicly reachable [`RendezvousServer`]
+//!    with the same session id.
+//! 2. The rendezvous server observes the public endpoint of each peer and sends it to the other one.
+//! 3. Both peers then send packets to each other's public endpoint at the same time
+//!    (simultaneous open), which creates a mapping in both NATs so that the packets can go through.
+//!
+//! [`punch_hole`] performs steps 1 and 3 on a given local address. It returns the socket that was used,
+//! which should be passed to a [`ClientTransport::BoundUdpSocket`](crate::prelude::client::ClientTransport) or
+//! [`ServerTransport::BoundUdpSocket`](crate::prelude::server::ServerTransport) so that the NAT mapping
+//! is kept, and the client can connect to the public endpoint of the host that was returned.
+//!
+//! This does not work with symmetric NATs, which use a different public endpoint for each destination.
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use bevy::utils::HashMap;
+use tracing::{debug, info, trace, warn};
+
+/// Prefix added to every packet of the rendezvous protocol
+const MAGIC: &[u8; 4] = b"LYNP";
+/// Interval between two retries of a register or punch packet
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+/// Number of acknowledged punch packets sent after a successful punch, in case some of them are lost
+const FINAL_PUNCHES: usize = 3;
+/// Duration after which the rendezvous server forgets about a session
+const SESSION_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NatPacket {
+    /// Sent from a peer to the rendezvous server to register to a session
+    Register { session_id: u64 },
+    /// Sent from the rendezvous server to a peer with the public endpoint of the other peer
+    PeerInfo { peer_addr: SocketAddr },
+    /// Sent between peers to open a mapping in their NAT.
+    /// `acked` is true if the sender already received a punch packet from the recipient.
+    Punch { session_id: u64, acked: bool },
+}
+
+impl NatPacket {
+    fn write(&self) -> Vec<u8> {
+        let mut buf = MAGIC.to_vec();
+        match self {
+            NatPacket::Register { session_id } => {
+                buf.push(0);
+                buf.extend_from_slice(&session_id.to_be_bytes());
+            }
+            NatPacket::PeerInfo { peer_addr } => {
+                buf.push(1);
+                match peer_addr.ip() {
+                    IpAddr::V4(ip) => {
+                        buf.push(4);
+                        buf.extend_from_slice(&ip.octets());
+                    }
+                    IpAddr::V6(ip) => {
+                        buf.push(6);
+                        buf.extend_from_slice(&ip.octets());
+                    }
+                }
+                buf.extend_from_slice(&peer_addr.port().to_be_bytes());
+            }
+            NatPacket::Punch { session_id, acked } => {
+                buf.push(2);
+                buf.extend_from_slice(&session_id.to_be_bytes());
+                buf.push(*acked as u8);
+            }
+        }
+        buf
+    }
+
+    fn read(buf: &[u8]) -> Option<Self> {
+        let buf = buf.strip_prefix(MAGIC)?;
+        let (kind, buf) = buf.split_first()?;
+        match kind {
+            0 => Some(NatPacket::Register {
+                session_id: u64::from_be_bytes(buf.try_into().ok()?),
+            }),
+            1 => {
+                let (version, buf) = buf.split_first()?;
+                let (ip, buf): (IpAddr, _) = match version {
+                    4 if buf.len() == 6 => {
+                        let octets: [u8; 4] = buf[..4].try_into().ok()?;
+                        (Ipv4Addr::from(octets).into(), &buf[4..])
+                    }
+                    6 if buf.len() == 18 => {
+                        let octets: [u8; 16] = buf[..16].try_into().ok()?;
+                        (Ipv6Addr::from(octets).into(), &buf[16..])
+                    }
+                    _ => return None,
+                };
+                let port = u16::from_be_bytes(buf.try_into().ok()?);
+                Some(NatPacket::PeerInfo {
+                    peer_addr: SocketAddr::new(ip, port),
+                })
+            }
+            2 if buf.len() == 9 => Some(NatPacket::Punch {
+                session_id: u64::from_be_bytes(buf[..8].try_into().ok()?),
+                acked: buf[8] != 0,
+            }),
+            _ => None,
+        }
+    }
+}
+
+struct Session {
+    peers: Vec<SocketAddr>,
+    last_seen: Instant,
+}
+
+/// Publicly reachable server that lets two peers behind NATs learn each other's public endpoint.
+///
+/// Peers are matched by session id: the first two peers that register with the same session id
+/// will be introduced to each other.
+pub struct RendezvousServer {
+    socket: UdpSocket,
+    sessions: HashMap<u64, Session>,
+    buffer: [u8; 64],
+}
+
+impl RendezvousServer {
+    /// Bind the rendezvous server to the given address
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        info!("Rendezvous server listening on {}", socket.local_addr()?);
+        Ok(Self {
+            socket,
+            sessions: HashMap::default(),
+            buffer: [0; 64],
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Process all the packets that were received since the last update.
+    ///
+    /// This does not block, so it should be called regularly (for example in a bevy system).
+    /// Errors when answering a single peer are logged and do not interrupt the processing of the other packets.
+    pub fn update(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        self.sessions
+            .retain(|_, session| now.duration_since(session.last_seen) < SESSION_TIMEOUT);
+        loop {
+            let (len, addr) = match self.socket.recv_from(&mut self.buffer) {
+                Ok(res) => res,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                // on some platforms, an ICMP port unreachable message from a previous send is reported here
+                Err(e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+                Err(e) => return Err(e),
+            };
+            let Some(NatPacket::Register { session_id }) = NatPacket::read(&self.buffer[..len])
+            else {
+                trace!("Rendezvous server ignored invalid packet from {}", addr);
+                continue;
+            };
+            let session = self.sessions.entry(session_id).or_insert_with(|| Session {
+                peers: Vec::with_capacity(2),
+                last_seen: now,
+            });
+            session.last_seen = now;
+            if !session.peers.contains(&addr) {
+                if session.peers.len() >= 2 {
+                    debug!("Session {} is full, ignoring peer {}", session_id, addr);
+                    continue;
+                }
+                debug!("Peer {} registered to session {}", addr, session_id);
+                session.peers.push(addr);
+            }
+            // answer every register packet, in case a previous answer was lost
+            if let Some(&peer_addr) = session.peers.iter().find(|&&p| p != addr) {
+                if let Err(e) = self
+                    .socket
+                    .send_to(&NatPacket::PeerInfo { peer_addr }.write(), addr)
+                {
+                    warn!("Could not send peer info to {}: {}", addr, e);
+                }
+            }
+        }
+    }
+}
+
+/// Open a path through the NATs between `local_addr` and the other peer of the session.
+///
+/// Both peers must call this function with the same `session_id` and `rendezvous_addr`.
+/// Returns the socket bound to `local_addr` and the public endpoint of the other peer once the punch
+/// succeeded, or a [`TimedOut`](io::ErrorKind::TimedOut) error if it did not succeed within `timeout`.
+///
+/// This function blocks, so it should be run in a separate thread or task.
+/// The returned socket must be used for the UDP transport (see
+/// [`ClientTransport::BoundUdpSocket`](crate::prelude::client::ClientTransport)): closing it could
+/// make the NAT drop the mapping that was just opened.
+pub fn punch_hole(
+    local_addr: SocketAddr,
+    rendezvous_addr: SocketAddr,
+    session_id: u64,
+    timeout: Duration,
+) -> io::Result<(UdpSocket, SocketAddr)> {
+    let socket = UdpSocket::bind(local_addr)?;
+    socket.set_read_timeout(Some(RETRY_INTERVAL))?;
+    let deadline = Instant::now() + timeout;
+    let mut buffer = [0; 64];
+    let mut peer_addr: Option<SocketAddr> = None;
+    let mut received_punch = false;
+    let mut last_sent: Option<Instant> = None;
+
+    while Instant::now() < deadline {
+        if last_sent.map_or(true, |t| t.elapsed() >= RETRY_INTERVAL) {
+            match peer_addr {
+                None => {
+                    socket.send_to(&NatPacket::Register { session_id }.write(), rendezvous_addr)?
+                }
+                Some(addr) => socket.send_to(
+                    &NatPacket::Punch {
+                        session_id,
+                        acked: received_punch,
+                    }
+                    .write(),
+                    addr,
+                )?,
+            };
+            last_sent = Some(Instant::now());
+        }
+        let (len, addr) = match socket.recv_from(&mut buffer) {
+            Ok(res) => res,
+            Err(e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                continue;
+            }
+            // on some platforms, an ICMP port unreachable message from a previous send is reported here
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+            Err(e) => return Err(e),
+        };
+        match NatPacket::read(&buffer[..len]) {
+            Some(NatPacket::PeerInfo { peer_addr: peer }) if peer_addr.is_none() => {
+                info!("Received public endpoint of peer: {}", peer);
+                peer_addr = Some(peer);
+                // start punching right away
+                last_sent = None;
+            }
+            Some(NatPacket::Punch {
+                session_id: id,
+                acked,
+            }) if id == session_id => {
+                // the NAT of the peer might have picked a different port than the one seen by the
+                // rendezvous server, so we trust the address the punch packet came from
+                peer_addr = Some(addr);
+                received_punch = true;
+                if acked {
+                    for _ in 0..FINAL_PUNCHES {
+                        socket.send_to(
+                            &NatPacket::Punch {
+                                session_id,
+                                acked: true,
+                            }
+                            .write(),
+                            addr,
+                        )?;
+                    }
+                    info!("Punched hole to peer {}", addr);
+                    socket.set_read_timeout(None)?;
+                    return Ok((socket, addr));
+                }
+                last_sent = None;
+            }
+            _ => trace!("Ignoring unexpected packet from {}", addr),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "could not punch a hole to the peer",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_packet_serialization() {
+        let packets = [
+            NatPacket::Register { session_id: 42 },
+            NatPacket::PeerInfo {
+                peer_addr: "1.2.3.4:5000".parse().unwrap(),
+            },
+            NatPacket::PeerInfo {
+                peer_addr: "[::1]:5000".parse().unwrap(),
+            },
+            NatPacket::Punch {
+                session_id: 42,
+                acked: true,
+            },
+        ];
+        for packet in packets {
+            assert_eq!(NatPacket::read(&packet.write()), Some(packet));
+        }
+        assert_eq!(NatPacket::read(b"hello world"), None);
+    }
+
+    #[test]
+    fn test_punch_hole() {
+        let mut rendezvous = RendezvousServer::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let rendezvous_addr = rendezvous.local_addr().unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_clone = stop.clone();
+        let rendezvous_handle = std::thread::spawn(move || {
+            while !stop_clone.load(Ordering::Relaxed) {
+                rendezvous.update().unwrap();
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        });
+
+        let local_addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let host = std::thread::spawn(move || {
+            punch_hole(local_addr, rendezvous_addr, 1, Duration::from_secs(5))
+        });
+        let client = std::thread::spawn(move || {
+            punch_hole(local_addr, rendezvous_addr, 1, Duration::from_secs(5))
+        });
+        let (host_socket, host_peer) = host.join().unwrap().unwrap();
+        let (client_socket, client_peer) = client.join().unwrap().unwrap();
+        assert_eq!(host_peer, client_socket.local_addr().unwrap());
+        assert_eq!(client_peer, host_socket.local_addr().unwrap());
+
+        // the returned sockets can still reach each other
+        client_socket.send_to(b"hello", client_peer).unwrap();
+        host_socket
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let mut buffer = [0; 64];
+        // skip the remaining punch packets
+        let (len, addr) = loop {
+            let (len, addr) = host_socket.recv_from(&mut buffer).unwrap();
+            if NatPacket::read(&buffer[..len]).is_none() {
+                break (len, addr);
+            }
+        };
+        assert_eq!(&buffer[..len], b"hello");
+        assert_eq!(addr, host_peer);
+
+        stop.store(true, Ordering::Relaxed);
+        rendezvous_handle.join().unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+//! QUIC client implementation.
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use async_compat::Compat;
+use bevy::tasks::IoTaskPool;
+use bytes::Bytes;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TryRecvError;
+use tracing::{debug, error, info, trace};
+
+use crate::client::io::transport::{ClientTransportBuilder, ClientTransportEnum};
+use crate::client::io::{ClientIoEvent, ClientIoEventReceiver, ClientNetworkEventSender};
+use crate::transport::error::{Error, Result};
+use crate::transport::io::IoState;
+use crate::transport::{BoxedReceiver, BoxedSender, PacketReceiver, PacketSender, Transport, MTU};
+
+use super::ALPN;
+
+pub(crate) struct QuicClientSocketBuilder {
+    pub(crate) client_addr: SocketAddr,
+    pub(crate) server_addr: SocketAddr,
+}
+
+impl QuicClientSocketBuilder {
+    fn client_config() -> quinn::ClientConfig {
+        let mut tls_config = wtransport::tls::client::build_default_tls_config(Arc::new(
+            rustls::RootCertStore::empty(),
+        ));
+        // TODO: add an option to validate the server certificate (for example via its digest)
+        tls_config.dangerous().set_certificate_verifier(Arc::new(
+            wtransport::tls::client::NoServerVerification::new(),
+        ));
+        tls_config.alpn_protocols = vec![ALPN.to_vec()];
+        quinn::ClientConfig::new(Arc::new(tls_config))
+    }
+}
+
+impl ClientTransportBuilder for QuicClientSocketBuilder {
+    fn connect(
+        self,
+    ) -> Result<(
+        ClientTransportEnum,
+        IoState,
+        Option<ClientIoEventReceiver>,
+        Option<ClientNetworkEventSender>,
+    )> {
+        let (to_server_sender, mut to_server_receiver) = mpsc::unbounded_channel::<Bytes>();
+        let (from_server_sender, from_server_receiver) = mpsc::unbounded_channel();
+        // channels used to cancel the task
+        let (close_tx, close_rx) = async_channel::bounded(1);
+        // channels used to check the status of the io task
+        let (event_tx, event_rx) = async_channel::bounded(1);
+
+        // bind the socket right away so that the actual local address is known (for example when binding to port 0)
+        let socket = std::net::UdpSocket::bind(self.client_addr)?;
+        let local_addr = socket.local_addr()?;
+        let server_addr = self.server_addr;
+        IoTaskPool::get()
+            .spawn(Compat::new(async move {
+                let mut endpoint = match quinn::Endpoint::new(
+                    quinn::EndpointConfig::default(),
+                    None,
+                    socket,
+                    Arc::new(quinn::TokioRuntime),
+                ) {
+                    Ok(e) => e,
+                    Err(e) => {
+                        error!("Error creating quic endpoint: {:?}", e);
+                        let _ = event_tx.send(ClientIoEvent::Disconnected(e.into())).await;
+                        return;
+                    }
+                };
+                endpoint.set_default_client_config(Self::client_config());
+                info!("Connecting to server via quic at: {}", server_addr);
+                // the server name is not checked since we don't validate certificates
+                let connecting = match endpoint.connect(server_addr, "localhost") {
+                    Ok(c) => c,
+                    Err(e) => {
+                        error!("Error creating quic connection: {:?}", e);
+                        let _ = event_tx
+                            .send(ClientIoEvent::Disconnected(std::io::Error::other(e).into()))
+                            .await;
+                        return;
+                    }
+                };
+                tokio::select! {
+                    _ = close_rx.recv() => {
+                        info!("Quic connection closed. Reason: client requested disconnection.");
+                        let _ = event_tx.send(ClientIoEvent::Disconnected(std::io::Error::other("received close signal").into())).await;
+                    }
+                    connection = connecting => {
+                        let connection = match connection {
+                            Ok(c) => c,
+                            Err(e) => {
+                                error!("Error creating quic connection: {:?}", e);
+                                let _ = event_tx.send(ClientIoEvent::Disconnected(e.into())).await;
+                                return;
+                            }
+                        };
+                        // signal that the io is connected
+                        event_tx.send(ClientIoEvent::Connected).await.unwrap();
+                        info!("Connected.");
+
+                        // NOTE: we spawn separate tasks for receiving and sending datagrams, see
+                        //  the webtransport client for why we cannot simply use tokio::select!
+                        let connection_recv = connection.clone();
+                        let recv_handle = IoTaskPool::get().spawn(Compat::new(async move {
+                            loop {
+                                match connection_recv.read_datagram().await {
+                                    Ok(data) => {
+                                        trace!("receive datagram from server: {:?}", &data);
+                                        if from_server_sender.send(data).is_err() {
+                                            return;
+                                        }
+                                    }
+                                    Err(e) => {
+                                        // all the ConnectionErrors are related to the connection being close, so we can close the task
+                                        error!("read_datagram connection error: {:?}", e);
+                                        return;
+                                    }
+                                }
+                            }
+                        }));
+                        let connection_send = connection.clone();
+                        let send_handle = IoTaskPool::get().spawn(Compat::new(async move {
+                            while let Some(msg) = to_server_receiver.recv().await {
+                                trace!("send datagram to server: {:?}", &msg);
+                                connection_send.send_datagram(msg).unwrap_or_else(|e| {
+                                    error!("send_datagram error: {:?}", e);
+                                });
+                            }
+                        }));
+                        // Wait for a close signal from the close channel, or for the quic connection to be closed
+                        tokio::select! {
+                            reason = connection.closed() => {
+                                info!("Quic connection closed. Reason: {reason:?}. Shutting down quic tasks.");
+                                let _ = event_tx.send(ClientIoEvent::Disconnected(Error::Quic(reason))).await;
+                            },
+                            _ = close_rx.recv() => {
+                                connection.close(0u32.into(), b"client requested disconnection");
+                                info!("Quic connection closed. Reason: client requested disconnection. Shutting down quic tasks.");
+                            }
+                        }
+                        recv_handle.cancel().await;
+                        send_handle.cancel().await;
+                        debug!("Quic tasks shut down.");
+                    }
+                }
+            }))
+            .detach();
+
+        let sender = QuicClientPacketSender { to_server_sender };
+        let receiver = QuicClientPacketReceiver {
+            server_addr: self.server_addr,
+            from_server_receiver,
+            buffer: [0; MTU],
+        };
+        Ok((
+            ClientTransportEnum::QuicClient(QuicClientSocket {
+                local_addr,
+                sender,
+                receiver,
+            }),
+            IoState::Connecting,
+            Some(ClientIoEventReceiver(event_rx)),
+            Some(ClientNetworkEventSender(close_tx)),
+        ))
+    }
+}
+
+/// QUIC client socket
+pub struct QuicClientSocket {
+    local_addr: SocketAddr,
+    sender: QuicClientPacketSender,
+    receiver: QuicClientPacketReceiver,
+}
+
+impl Transport for QuicClientSocket {
+    fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    fn split(self) -> (BoxedSender, BoxedReceiver) {
+        (Box::new(self.sender), Box::new(self.receiver))
+    }
+}
+
+struct QuicClientPacketSender {
+    to_server_sender: mpsc::UnboundedSender<Bytes>,
+}
+
+impl PacketSender for QuicClientPacketSender {
+    fn send(&mut self, payload: &[u8], address: &SocketAddr) -> Result<()> {
+        self.to_server_sender
+            .send(Bytes::copy_from_slice(payload))
+            .map_err(|e| std::io::Error::other(format!("send_datagram error: {:?}", e)).into())
+    }
+}
+
+struct QuicClientPacketReceiver {
+    server_addr: SocketAddr,
+    from_server_receiver: mpsc::UnboundedReceiver<Bytes>,
+    buffer: [u8; MTU],
+}
+
+impl PacketReceiver for QuicClientPacketReceiver {
+    fn recv(&mut self) -> Result<Option<(&mut [u8], SocketAddr)>> {
+        match self.from_server_receiver.try_recv() {
+            Ok(data) => {
+                self.buffer[..data.len()].copy_from_slice(data.as_ref());
+                Ok(Some((&mut self.buffer[..data.len()], self.server_addr)))
+            }
+            Err(e) => {
+                if e == TryRecvError::Empty {
+                    Ok(None)
+                } else {
+                    Err(std::io::Error::other(format!("read_datagram error: {:?}", e)).into())
+                }
+            }
+        }
+    }
+}
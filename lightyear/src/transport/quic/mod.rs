@@ -0,0 +1,82 @@
+//! Transport using raw QUIC connections, where packets are sent as unreliable datagrams.
+//!
+//! QUIC provides encryption and connection management at the transport layer, while lightyear
+//! still handles reliability and ordering via its own channels on top of the unreliable datagrams.
+pub(crate) mod client;
+pub(crate) mod server;
+
+/// ALPN protocol identifier negotiated between lightyear QUIC clients and servers
+pub(crate) const ALPN: &[u8] = b"lightyear";
+
+#[cfg(test)]
+mod tests {
+    use bevy::tasks::{IoTaskPool, TaskPoolBuilder};
+    use bevy::utils::Duration;
+    use wtransport::Identity;
+
+    use crate::client::io::transport::ClientTransportBuilder;
+    use crate::server::io::transport::ServerTransportBuilder;
+    use crate::transport::{PacketReceiver, PacketSender, Transport};
+
+    use super::client::*;
+    use super::server::*;
+
+    #[tokio::test]
+    async fn test_quic_native() {
+        IoTaskPool::get_or_init(|| TaskPoolBuilder::default().build());
+
+        let certificate = Identity::self_signed(["localhost"]).unwrap();
+        // bind to any available port, so that the tests can run in parallel
+        let (server_socket, _, _, _) = QuicServerSocketBuilder {
+            server_addr: "127.0.0.1:0".parse().unwrap(),
+            certificate,
+        }
+        .start()
+        .unwrap();
+        let server_addr = server_socket.local_addr();
+        let (mut server_send, mut server_recv) = server_socket.split();
+
+        let (client_socket, _, client_status, _) = QuicClientSocketBuilder {
+            client_addr: "127.0.0.1:0".parse().unwrap(),
+            server_addr,
+        }
+        .connect()
+        .unwrap();
+        let client_addr = client_socket.local_addr();
+        let (mut client_send, mut client_recv) = client_socket.split();
+
+        // wait for the connection to be established
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        match client_status.unwrap().try_recv() {
+            Ok(crate::client::io::ClientIoEvent::Connected) => {}
+            Ok(crate::client::io::ClientIoEvent::Disconnected(e)) => panic!("{e:?}"),
+            Err(e) => panic!("{e:?}"),
+        }
+
+        let msg = b"hello world";
+
+        // client to server
+        client_send.send(msg, &server_addr).unwrap();
+
+        // sleep a little to give time to the message to arrive in the socket
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let Ok(Some((recv_msg, address))) = server_recv.recv() else {
+            panic!("server expected to receive a packet from client");
+        };
+        assert_eq!(address, client_addr);
+        assert_eq!(recv_msg, msg);
+
+        // server to client
+        server_send.send(msg, &client_addr).unwrap();
+
+        // sleep a little to give time to the message to arrive in the socket
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let Ok(Some((recv_msg, address))) = client_recv.recv() else {
+            panic!("client expected to receive a packet from server");
+        };
+        assert_eq!(address, server_addr);
+        assert_eq!(recv_msg, msg);
+    }
+}
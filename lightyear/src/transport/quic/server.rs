@@ -0,0 +1,277 @@
+//! QUIC server implementation.
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use async_compat::Compat;
+use bevy::tasks::IoTaskPool;
+use bevy::utils::HashMap;
+use bytes::Bytes;
+use quinn::Connection;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tracing::{debug, error, info, trace};
+use wtransport::Identity;
+
+use crate::server::io::transport::{ServerTransportBuilder, ServerTransportEnum};
+use crate::server::io::{ServerIoEvent, ServerIoEventReceiver, ServerNetworkEventSender};
+use crate::transport::error::Result;
+use crate::transport::io::IoState;
+use crate::transport::{BoxedReceiver, BoxedSender, PacketReceiver, PacketSender, Transport, MTU};
+
+use super::ALPN;
+
+pub(crate) struct QuicServerSocketBuilder {
+    pub(crate) server_addr: SocketAddr,
+    pub(crate) certificate: Identity,
+}
+
+impl QuicServerSocketBuilder {
+    fn server_config(&self) -> quinn::ServerConfig {
+        let mut tls_config = wtransport::tls::server::build_default_tls_config(&self.certificate);
+        tls_config.alpn_protocols = vec![ALPN.to_vec()];
+        quinn::ServerConfig::with_crypto(Arc::new(tls_config))
+    }
+}
+
+impl ServerTransportBuilder for QuicServerSocketBuilder {
+    fn start(
+        self,
+    ) -> Result<(
+        ServerTransportEnum,
+        IoState,
+        Option<ServerIoEventReceiver>,
+        Option<ServerNetworkEventSender>,
+    )> {
+        let (from_client_sender, from_client_receiver) = mpsc::unbounded_channel();
+        // channels used to cancel the task
+        let (close_tx, close_rx) = async_channel::unbounded();
+        // channels used to check the status of the io task
+        let (status_tx, status_rx) = async_channel::unbounded();
+        let to_client_senders = Arc::new(Mutex::new(HashMap::new()));
+        let addr_to_task = Arc::new(Mutex::new(HashMap::new()));
+
+        let sender = QuicServerSocketSender {
+            to_client_senders: to_client_senders.clone(),
+        };
+        let receiver = QuicServerSocketReceiver {
+            buffer: [0; MTU],
+            from_client_receiver,
+        };
+
+        let config = self.server_config();
+        // bind the socket right away so that the actual local address is known (for example when binding to port 0)
+        let socket = std::net::UdpSocket::bind(self.server_addr)?;
+        let local_addr = socket.local_addr()?;
+        // need to run this with Compat because quinn requires the tokio reactor
+        IoTaskPool::get()
+            .spawn(Compat::new(async move {
+                let endpoint = match quinn::Endpoint::new(
+                    quinn::EndpointConfig::default(),
+                    Some(config),
+                    socket,
+                    Arc::new(quinn::TokioRuntime),
+                ) {
+                    Ok(e) => e,
+                    Err(e) => {
+                        status_tx
+                            .send(ServerIoEvent::ServerDisconnected(e.into()))
+                            .await
+                            .unwrap();
+                        return;
+                    }
+                };
+                info!("Starting server quic task");
+                status_tx.send(ServerIoEvent::ServerConnected).await.unwrap();
+                loop {
+                    tokio::select! {
+                        // event from netcode
+                        Ok(event) = close_rx.recv() => {
+                            match event {
+                                ServerIoEvent::ServerDisconnected(e) => {
+                                    debug!("Stopping quic io task. Reason: {:?}", e);
+                                    endpoint.close(0u32.into(), b"server closed");
+                                    drop(addr_to_task);
+                                    return;
+                                }
+                                ServerIoEvent::ClientDisconnected(addr) => {
+                                    debug!("Stopping quic io task associated with address: {:?} because we received a disconnection signal from netcode", addr);
+                                    addr_to_task.lock().unwrap().remove(&addr);
+                                }
+                                _ => {}
+                            }
+                        }
+                        // new client connecting
+                        incoming = endpoint.accept() => {
+                            let Some(connecting) = incoming else {
+                                info!("Quic endpoint closed, stopping quic io task.");
+                                return;
+                            };
+                            // complete the handshake in a separate task, so that a slow client doesn't
+                            // block the other connections
+                            let client_addr = connecting.remote_address();
+                            let from_client_sender = from_client_sender.clone();
+                            let to_client_senders = to_client_senders.clone();
+                            let status_tx = status_tx.clone();
+                            let task = IoTaskPool::get().spawn(Compat::new(async move {
+                                let Ok(connection) = connecting.await.inspect_err(|e| {
+                                    error!("failed to accept new client: {:?}", e);
+                                }) else {
+                                    return;
+                                };
+                                QuicServerSocket::handle_client(
+                                    connection,
+                                    from_client_sender,
+                                    to_client_senders,
+                                    status_tx,
+                                )
+                                .await;
+                            }));
+                            addr_to_task.lock().unwrap().insert(client_addr, task);
+                        }
+                    }
+                }
+            }))
+            .detach();
+
+        Ok((
+            ServerTransportEnum::QuicServer(QuicServerSocket {
+                local_addr,
+                sender,
+                receiver,
+            }),
+            IoState::Connecting,
+            Some(ServerIoEventReceiver(status_rx)),
+            Some(ServerNetworkEventSender(close_tx)),
+        ))
+    }
+}
+
+/// QUIC server socket
+pub struct QuicServerSocket {
+    local_addr: SocketAddr,
+    sender: QuicServerSocketSender,
+    receiver: QuicServerSocketReceiver,
+}
+
+impl QuicServerSocket {
+    pub(crate) async fn handle_client(
+        connection: Connection,
+        from_client_sender: UnboundedSender<(Bytes, SocketAddr)>,
+        to_client_channels: Arc<Mutex<HashMap<SocketAddr, UnboundedSender<Bytes>>>>,
+        status_tx: async_channel::Sender<ServerIoEvent>,
+    ) {
+        let client_addr = connection.remote_address();
+        info!(
+            "Spawning new task to create connection with client: {}",
+            client_addr
+        );
+
+        // add a new pair of channels for this client
+        let (to_client_sender, mut to_client_receiver) = mpsc::unbounded_channel::<Bytes>();
+        to_client_channels
+            .lock()
+            .unwrap()
+            .insert(client_addr, to_client_sender);
+
+        // connection established, waiting for data from client
+        // NOTE: we spawn separate tasks for receiving and sending datagrams, see the webtransport
+        //  client for why we cannot simply use tokio::select! on both futures
+        let connection_recv = connection.clone();
+        let from_client_handle = IoTaskPool::get().spawn(Compat::new(async move {
+            loop {
+                match connection_recv.read_datagram().await {
+                    Ok(data) => {
+                        trace!("received datagram from client!: {:?}", data.as_ref());
+                        if from_client_sender.send((data, client_addr)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        error!("read_datagram connection error: {:?}", e);
+                        break;
+                    }
+                }
+            }
+        }));
+        let connection_send = connection.clone();
+        let to_client_handle = IoTaskPool::get().spawn(Compat::new(async move {
+            while let Some(msg) = to_client_receiver.recv().await {
+                trace!("sending datagram to client!: {:?}", &msg);
+                connection_send.send_datagram(msg).unwrap_or_else(|e| {
+                    error!("send_datagram error: {:?}", e);
+                });
+            }
+        }));
+
+        // await for the quic connection to be closed for any reason
+        let reason = connection.closed().await;
+        info!(
+            "Connection with {} closed. Reason: {:?}",
+            client_addr, reason
+        );
+        // notify netcode that the io task got disconnected
+        let _ = status_tx
+            .send(ServerIoEvent::ClientDisconnected(client_addr))
+            .await;
+        to_client_channels.lock().unwrap().remove(&client_addr);
+        debug!("Dropping tasks");
+        // the handles being dropped cancels the tasks
+    }
+}
+
+impl Transport for QuicServerSocket {
+    fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    fn split(self) -> (BoxedSender, BoxedReceiver) {
+        (Box::new(self.sender), Box::new(self.receiver))
+    }
+}
+
+struct QuicServerSocketSender {
+    to_client_senders: Arc<Mutex<HashMap<SocketAddr, UnboundedSender<Bytes>>>>,
+}
+
+impl PacketSender for QuicServerSocketSender {
+    fn send(&mut self, payload: &[u8], address: &SocketAddr) -> Result<()> {
+        if let Some(to_client_sender) = self.to_client_senders.lock().unwrap().get(address) {
+            to_client_sender
+                .send(Bytes::copy_from_slice(payload))
+                .map_err(|e| {
+                    std::io::Error::other(format!("unable to send message to client: {}", e)).into()
+                })
+        } else {
+            // consider that if the channel doesn't exist, it's because the connection was closed
+            Ok(())
+        }
+    }
+}
+
+struct QuicServerSocketReceiver {
+    buffer: [u8; MTU],
+    from_client_receiver: UnboundedReceiver<(Bytes, SocketAddr)>,
+}
+
+impl PacketReceiver for QuicServerSocketReceiver {
+    fn recv(&mut self) -> Result<Option<(&mut [u8], SocketAddr)>> {
+        match self.from_client_receiver.try_recv() {
+            Ok((data, addr)) => {
+                self.buffer[..data.len()].copy_from_slice(data.as_ref());
+                Ok(Some((&mut self.buffer[..data.len()], addr)))
+            }
+            Err(e) => {
+                if e == TryRecvError::Empty {
+                    Ok(None)
+                } else {
+                    Err(std::io::Error::other(format!(
+                        "unable to receive message from client: {}",
+                        e
+                    ))
+                    .into())
+                }
+            }
+        }
+    }
+}
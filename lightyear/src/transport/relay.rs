@@ -0,0 +1,697 @@
+//! Relay that forwards packets between clients and a game server.
+//!
+//! Going through a relay lets clients reach a server that is behind a NAT (the server opens the path
+//! to the relay itself), and hides the IP address of the server from the clients.
+//!
+//! 1. A publicly reachable [`RelayServer`] is started, for example in a thin bevy app with the [`RelayPlugin`].
+//! 2. The game server uses [`ServerTransport::Relay`](crate::prelude::server::ServerTransport) with a session id,
+//!    and regularly registers itself to the relay as the host of that session.
+//! 3. Clients use [`ClientTransport::Relay`](crate::prelude::client::ClientTransport) with the same session id.
+//!    Their packets are sent to the relay, which forwards them to the host of the session.
+//!    The address of the server in the connect token should be the address of the relay.
+//!
+//! The session id acts as a routing token, and the relay does not authenticate the hosts: every client of the session
+//! knows the id, and could register itself as the host of the session.
+//! To prevent a client from hijacking a running session, the relay keeps the first host that registered a session
+//! until that host stops registering for 30 seconds; the registrations of the session from other addresses are ignored
+//! in the meantime. This also means that:
+//! - the game server must register to the relay before the session id is given to the clients. Otherwise a client
+//!   could register first, and receive (and drop) the packets of every other client of the session
+//! - if the address of the host changes (for example because its NAT mapping expired), the session is unreachable
+//!   until the previous registration times out
+//! - a session id should be random and only shared with the clients of the session, and should not be reused after
+//!   the session ends
+//!
+//! The packets are still authenticated and encrypted by netcode, so a peer that takes over a session can
+//! drop the packets of the clients, but cannot read them or impersonate the server.
+//!
+//! ```rust,no_run
+//! use bevy::prelude::*;
+//! use lightyear::transport::relay::RelayPlugin;
+//!
+//! App::new()
+//!     .add_plugins(MinimalPlugins)
+//!     .add_plugins(RelayPlugin {
+//!         addr: "0.0.0.0:5000".parse().unwrap(),
+//!     })
+//!     .run();
+//! ```
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::ops::Range;
+
+use bevy::prelude::{App, Plugin, ResMut, Resource, Update};
+use bevy::utils::{Duration, HashMap, Instant};
+use tracing::{debug, error, info, trace};
+
+use crate::client::io::transport::{ClientTransportBuilder, ClientTransportEnum};
+use crate::client::io::{ClientIoEventReceiver, ClientNetworkEventSender};
+use crate::server::io::transport::{ServerTransportBuilder, ServerTransportEnum};
+use crate::server::io::{ServerIoEventReceiver, ServerNetworkEventSender};
+use crate::transport::error::Result;
+use crate::transport::io::IoState;
+use crate::transport::{BoxedReceiver, BoxedSender, PacketReceiver, PacketSender, Transport, MTU};
+
+/// Prefix added to every packet of the relay protocol
+const MAGIC: &[u8; 4] = b"LYRL";
+/// Interval between two registrations of the host to the relay.
+///
+/// The registrations also keep the NAT mapping of the host open.
+const REGISTER_INTERVAL: Duration = Duration::from_secs(1);
+/// Duration after which the relay forgets about a host or a client that did not send any packet
+const PEER_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RelayHeader {
+    /// Sent from the host to the relay to register as the host of a session
+    Register { session_id: u64 },
+    /// Sent from a client to the relay, the payload should be forwarded to the host of the session
+    ToHost { session_id: u64 },
+    /// Sent from the relay to the host, the payload was sent by `client_addr`
+    FromClient { client_addr: SocketAddr },
+    /// Sent from the host to the relay, the payload should be forwarded to `client_addr`
+    ToClient { client_addr: SocketAddr },
+    /// Sent from the relay to a client, the payload was sent by the host
+    FromHost,
+}
+
+impl RelayHeader {
+    /// Write the header followed by the payload in `buf`
+    fn write(&self, buf: &mut Vec<u8>, payload: &[u8]) {
+        buf.clear();
+        buf.extend_from_slice(MAGIC);
+        match self {
+            RelayHeader::Register { session_id } => {
+                buf.push(0);
+                buf.extend_from_slice(&session_id.to_be_bytes());
+            }
+            RelayHeader::ToHost { session_id } => {
+                buf.push(1);
+                buf.extend_from_slice(&session_id.to_be_bytes());
+            }
+            RelayHeader::FromClient { client_addr } => {
+                buf.push(2);
+                write_addr(buf, client_addr);
+            }
+            RelayHeader::ToClient { client_addr } => {
+                buf.push(3);
+                write_addr(buf, client_addr);
+            }
+            RelayHeader::FromHost => buf.push(4),
+        }
+        buf.extend_from_slice(payload);
+    }
+
+    /// Read the header at the start of `buf`, and return it with the length of the header
+    fn read(buf: &[u8]) -> Option<(Self, usize)> {
+        let body = buf.strip_prefix(MAGIC)?;
+        let (kind, body) = body.split_first()?;
+        let (header, rest) = match kind {
+            0 | 1 => {
+                let session_id = u64::from_be_bytes(body.get(..8)?.try_into().ok()?);
+                let header = if *kind == 0 {
+                    RelayHeader::Register { session_id }
+                } else {
+                    RelayHeader::ToHost { session_id }
+                };
+                (header, &body[8..])
+            }
+            2 | 3 => {
+                let (client_addr, rest) = read_addr(body)?;
+                let header = if *kind == 2 {
+                    RelayHeader::FromClient { client_addr }
+                } else {
+                    RelayHeader::ToClient { client_addr }
+                };
+                (header, rest)
+            }
+            4 => (RelayHeader::FromHost, body),
+            _ => return None,
+        };
+        Some((header, buf.len() - rest.len()))
+    }
+}
+
+fn write_addr(buf: &mut Vec<u8>, addr: &SocketAddr) {
+    match addr.ip() {
+        IpAddr::V4(ip) => {
+            buf.push(4);
+            buf.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            buf.push(6);
+            buf.extend_from_slice(&ip.octets());
+        }
+    }
+    buf.extend_from_slice(&addr.port().to_be_bytes());
+}
+
+fn read_addr(buf: &[u8]) -> Option<(SocketAddr, &[u8])> {
+    let (version, buf) = buf.split_first()?;
+    let (ip, buf): (IpAddr, _) = match version {
+        4 => {
+            let octets: [u8; 4] = buf.get(..4)?.try_into().ok()?;
+            (Ipv4Addr::from(octets).into(), &buf[4..])
+        }
+        6 => {
+            let octets: [u8; 16] = buf.get(..16)?.try_into().ok()?;
+            (Ipv6Addr::from(octets).into(), &buf[16..])
+        }
+        _ => return None,
+    };
+    let port = u16::from_be_bytes(buf.get(..2)?.try_into().ok()?);
+    Some((SocketAddr::new(ip, port), &buf[2..]))
+}
+
+struct Peer {
+    session_id: u64,
+    last_seen: Instant,
+}
+
+/// Publicly reachable server that forwards packets between the clients and the host of a session.
+#[derive(Resource)]
+pub struct RelayServer {
+    socket: UdpSocket,
+    /// Host address of each session
+    hosts: HashMap<u64, SocketAddr>,
+    /// Session of each host
+    host_sessions: HashMap<SocketAddr, Peer>,
+    /// Session of each client
+    client_sessions: HashMap<SocketAddr, Peer>,
+    recv_buffer: [u8; MTU],
+    send_buffer: Vec<u8>,
+}
+
+impl RelayServer {
+    /// Bind the relay server to the given address
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        info!("Relay server listening on {}", socket.local_addr()?);
+        Ok(Self {
+            socket,
+            hosts: HashMap::default(),
+            host_sessions: HashMap::default(),
+            client_sessions: HashMap::default(),
+            recv_buffer: [0; MTU],
+            send_buffer: Vec::with_capacity(MTU),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Forward all the packets that were received since the last update.
+    ///
+    /// This does not block, so it should be called regularly (for example in a bevy system).
+    pub fn update(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        self.client_sessions
+            .retain(|_, peer| now.duration_since(peer.last_seen) < PEER_TIMEOUT);
+        let hosts = &mut self.hosts;
+        self.host_sessions.retain(|_, peer| {
+            let alive = now.duration_since(peer.last_seen) < PEER_TIMEOUT;
+            if !alive {
+                hosts.remove(&peer.session_id);
+            }
+            alive
+        });
+        loop {
+            let (len, addr) = match self.socket.recv_from(&mut self.recv_buffer) {
+                Ok(res) => res,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                // on some platforms, an ICMP port unreachable message from a previous send is reported here
+                Err(e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+                Err(e) => return Err(e),
+            };
+            let Some((header, header_len)) = RelayHeader::read(&self.recv_buffer[..len]) else {
+                trace!("Relay server ignored invalid packet from {}", addr);
+                continue;
+            };
+            let payload = &self.recv_buffer[header_len..len];
+            match header {
+                RelayHeader::Register { session_id } => {
+                    // the first host of the session keeps it until it times out, so that the clients
+                    // (who also know the session id) cannot take it over
+                    if let Some(&host_addr) = self.hosts.get(&session_id) {
+                        if host_addr != addr {
+                            debug!(
+                                "Relay server ignored registration of session {} from {}: the session is hosted by {}",
+                                session_id, addr, host_addr
+                            );
+                            continue;
+                        }
+                    } else if let Some(host) = self.host_sessions.get(&addr) {
+                        debug!(
+                            "Relay server ignored registration of session {} from {}: it already hosts session {}",
+                            session_id, addr, host.session_id
+                        );
+                        continue;
+                    } else {
+                        info!("Host {} registered session {}", addr, session_id);
+                        self.hosts.insert(session_id, addr);
+                    }
+                    self.host_sessions.insert(
+                        addr,
+                        Peer {
+                            session_id,
+                            last_seen: now,
+                        },
+                    );
+                }
+                RelayHeader::ToHost { session_id } => {
+                    let Some(&host_addr) = self.hosts.get(&session_id) else {
+                        trace!(
+                            "Relay server ignored packet for unknown session {}",
+                            session_id
+                        );
+                        continue;
+                    };
+                    self.client_sessions.insert(
+                        addr,
+                        Peer {
+                            session_id,
+                            last_seen: now,
+                        },
+                    );
+                    RelayHeader::FromClient { client_addr: addr }
+                        .write(&mut self.send_buffer, payload);
+                    if let Err(e) = self.socket.send_to(&self.send_buffer, host_addr) {
+                        debug!(
+                            "Relay server could not forward packet to host {}: {:?}",
+                            host_addr, e
+                        );
+                    }
+                }
+                RelayHeader::ToClient { client_addr } => {
+                    let Some(host) = self.host_sessions.get_mut(&addr) else {
+                        trace!(
+                            "Relay server ignored packet from unregistered host {}",
+                            addr
+                        );
+                        continue;
+                    };
+                    // hosts can only send packets to the clients of their own session
+                    if self
+                        .client_sessions
+                        .get(&client_addr)
+                        .map_or(true, |client| client.session_id != host.session_id)
+                    {
+                        trace!(
+                            "Relay server ignored packet from {} to unknown client {}",
+                            addr,
+                            client_addr
+                        );
+                        continue;
+                    }
+                    host.last_seen = now;
+                    RelayHeader::FromHost.write(&mut self.send_buffer, payload);
+                    if let Err(e) = self.socket.send_to(&self.send_buffer, client_addr) {
+                        debug!(
+                            "Relay server could not forward packet to client {}: {:?}",
+                            client_addr, e
+                        );
+                    }
+                }
+                RelayHeader::FromClient { .. } | RelayHeader::FromHost => {
+                    trace!("Relay server ignored unexpected packet from {}", addr);
+                }
+            }
+        }
+    }
+}
+
+/// Plugin that runs a [`RelayServer`] bound to `addr`
+pub struct RelayPlugin {
+    pub addr: SocketAddr,
+}
+
+impl Plugin for RelayPlugin {
+    fn build(&self, app: &mut App) {
+        let relay = RelayServer::bind(self.addr).expect("could not bind the relay server");
+        app.insert_resource(relay)
+            .add_systems(Update, update_relay_server);
+    }
+}
+
+fn update_relay_server(mut relay: ResMut<RelayServer>) {
+    if let Err(e) = relay.update() {
+        error!("Relay server error: {:?}", e);
+    }
+}
+
+pub(crate) struct RelaySocketBuilder {
+    pub(crate) local_addr: SocketAddr,
+    pub(crate) relay_addr: SocketAddr,
+    pub(crate) session_id: u64,
+}
+
+impl RelaySocketBuilder {
+    fn bind(&self) -> Result<(UdpSocket, SocketAddr)> {
+        let socket = UdpSocket::bind(self.local_addr)?;
+        socket.set_nonblocking(true)?;
+        let local_addr = socket.local_addr()?;
+        Ok((socket, local_addr))
+    }
+}
+
+impl ClientTransportBuilder for RelaySocketBuilder {
+    fn connect(
+        self,
+    ) -> Result<(
+        ClientTransportEnum,
+        IoState,
+        Option<ClientIoEventReceiver>,
+        Option<ClientNetworkEventSender>,
+    )> {
+        let (socket, local_addr) = self.bind()?;
+        let sender = RelayClientSender {
+            socket: socket.try_clone()?,
+            relay_addr: self.relay_addr,
+            session_id: self.session_id,
+            buffer: Vec::with_capacity(MTU),
+        };
+        let receiver = RelayClientReceiver {
+            socket,
+            relay_addr: self.relay_addr,
+            buffer: [0; MTU],
+        };
+        Ok((
+            ClientTransportEnum::Relay(RelaySocket {
+                local_addr,
+                sender: Box::new(sender),
+                receiver: Box::new(receiver),
+            }),
+            IoState::Connected,
+            None,
+            None,
+        ))
+    }
+}
+
+impl ServerTransportBuilder for RelaySocketBuilder {
+    fn start(
+        self,
+    ) -> Result<(
+        ServerTransportEnum,
+        IoState,
+        Option<ServerIoEventReceiver>,
+        Option<ServerNetworkEventSender>,
+    )> {
+        let (socket, local_addr) = self.bind()?;
+        let sender = RelayHostSender {
+            socket: socket.try_clone()?,
+            relay_addr: self.relay_addr,
+            buffer: Vec::with_capacity(MTU),
+        };
+        let receiver = RelayHostReceiver {
+            socket,
+            relay_addr: self.relay_addr,
+            session_id: self.session_id,
+            last_register_time: None,
+            buffer: [0; MTU],
+            register_buffer: Vec::with_capacity(MTU),
+        };
+        Ok((
+            ServerTransportEnum::Relay(RelaySocket {
+                local_addr,
+                sender: Box::new(sender),
+                receiver: Box::new(receiver),
+            }),
+            IoState::Connected,
+            None,
+            None,
+        ))
+    }
+}
+
+/// UDP socket that sends and receives packets through a [`RelayServer`]
+pub struct RelaySocket {
+    local_addr: SocketAddr,
+    sender: BoxedSender,
+    receiver: BoxedReceiver,
+}
+
+impl Transport for RelaySocket {
+    fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    fn split(self) -> (BoxedSender, BoxedReceiver) {
+        (self.sender, self.receiver)
+    }
+}
+
+/// Receive a packet from the relay, and return the header and the position of the payload in `buffer`
+fn recv_from_relay(
+    socket: &UdpSocket,
+    relay_addr: SocketAddr,
+    buffer: &mut [u8],
+) -> Result<Option<(RelayHeader, Range<usize>)>> {
+    loop {
+        match socket.recv_from(buffer) {
+            Ok((len, addr)) => {
+                if addr != relay_addr {
+                    trace!("Ignoring packet that did not come from the relay: {}", addr);
+                    continue;
+                }
+                let Some((header, header_len)) = RelayHeader::read(&buffer[..len]) else {
+                    trace!("Ignoring invalid packet from the relay");
+                    continue;
+                };
+                return Ok(Some((header, header_len..len)));
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+struct RelayClientSender {
+    socket: UdpSocket,
+    relay_addr: SocketAddr,
+    session_id: u64,
+    buffer: Vec<u8>,
+}
+
+impl PacketSender for RelayClientSender {
+    /// All packets are sent to the host of the session, through the relay
+    fn send(&mut self, payload: &[u8], _: &SocketAddr) -> Result<()> {
+        RelayHeader::ToHost {
+            session_id: self.session_id,
+        }
+        .write(&mut self.buffer, payload);
+        self.socket.send_to(&self.buffer, self.relay_addr)?;
+        Ok(())
+    }
+}
+
+struct RelayClientReceiver {
+    socket: UdpSocket,
+    relay_addr: SocketAddr,
+    buffer: [u8; MTU],
+}
+
+impl PacketReceiver for RelayClientReceiver {
+    /// Packets from the host are reported as coming from the relay address
+    fn recv(&mut self) -> Result<Option<(&mut [u8], SocketAddr)>> {
+        loop {
+            match recv_from_relay(&self.socket, self.relay_addr, &mut self.buffer)? {
+                Some((RelayHeader::FromHost, payload)) => {
+                    return Ok(Some((&mut self.buffer[payload], self.relay_addr)));
+                }
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+struct RelayHostSender {
+    socket: UdpSocket,
+    relay_addr: SocketAddr,
+    buffer: Vec<u8>,
+}
+
+impl PacketSender for RelayHostSender {
+    fn send(&mut self, payload: &[u8], address: &SocketAddr) -> Result<()> {
+        RelayHeader::ToClient {
+            client_addr: *address,
+        }
+        .write(&mut self.buffer, payload);
+        self.socket.send_to(&self.buffer, self.relay_addr)?;
+        Ok(())
+    }
+}
+
+struct RelayHostReceiver {
+    socket: UdpSocket,
+    relay_addr: SocketAddr,
+    session_id: u64,
+    last_register_time: Option<Instant>,
+    buffer: [u8; MTU],
+    register_buffer: Vec<u8>,
+}
+
+impl PacketReceiver for RelayHostReceiver {
+    /// Packets from clients are reported as coming from the address of the client, as seen by the relay
+    fn recv(&mut self) -> Result<Option<(&mut [u8], SocketAddr)>> {
+        // the receiver is polled every frame, so we use it to register regularly to the relay
+        if self
+            .last_register_time
+            .map_or(true, |t| t.elapsed() >= REGISTER_INTERVAL)
+        {
+            RelayHeader::Register {
+                session_id: self.session_id,
+            }
+            .write(&mut self.register_buffer, &[]);
+            self.socket
+                .send_to(&self.register_buffer, self.relay_addr)?;
+            self.last_register_time = Some(Instant::now());
+        }
+        loop {
+            match recv_from_relay(&self.socket, self.relay_addr, &mut self.buffer)? {
+                Some((RelayHeader::FromClient { client_addr }, payload)) => {
+                    return Ok(Some((&mut self.buffer[payload], client_addr)));
+                }
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_serialization() {
+        let headers = [
+            RelayHeader::Register { session_id: 42 },
+            RelayHeader::ToHost { session_id: 42 },
+            RelayHeader::FromClient {
+                client_addr: "1.2.3.4:5000".parse().unwrap(),
+            },
+            RelayHeader::ToClient {
+                client_addr: "[::1]:5000".parse().unwrap(),
+            },
+            RelayHeader::FromHost,
+        ];
+        let mut buf = Vec::new();
+        for header in headers {
+            header.write(&mut buf, b"payload");
+            let (read_header, header_len) = RelayHeader::read(&buf).unwrap();
+            assert_eq!(read_header, header);
+            assert_eq!(&buf[header_len..], b"payload");
+        }
+        assert_eq!(RelayHeader::read(b"hello world"), None);
+    }
+
+    #[test]
+    fn test_relay() {
+        let local_addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let mut relay = RelayServer::bind(local_addr).unwrap();
+        let relay_addr = relay.local_addr().unwrap();
+        let session_id = 42;
+
+        let (server_socket, _, _, _) = RelaySocketBuilder {
+            local_addr,
+            relay_addr,
+            session_id,
+        }
+        .start()
+        .unwrap();
+        let (mut server_sender, mut server_receiver) = server_socket.split();
+        let (client_socket, _, _, _) = RelaySocketBuilder {
+            local_addr,
+            relay_addr,
+            session_id,
+        }
+        .connect()
+        .unwrap();
+        let client_addr = client_socket.local_addr();
+        let (mut client_sender, mut client_receiver) = client_socket.split();
+
+        // the host registers to the relay when it is first polled
+        assert!(server_receiver.recv().unwrap().is_none());
+        std::thread::sleep(Duration::from_millis(50));
+        relay.update().unwrap();
+
+        // client -> relay -> server
+        let msg = b"hello server";
+        client_sender.send(msg, &relay_addr).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        relay.update().unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        let (recv_msg, addr) = server_receiver.recv().unwrap().unwrap();
+        assert_eq!(recv_msg, msg);
+        assert_eq!(addr, client_addr);
+
+        // server -> relay -> client
+        let msg = b"hello client";
+        server_sender.send(msg, &client_addr).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        relay.update().unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        let (recv_msg, addr) = client_receiver.recv().unwrap().unwrap();
+        assert_eq!(recv_msg, msg);
+        assert_eq!(addr, relay_addr);
+    }
+
+    #[test]
+    fn test_relay_register_hijack() {
+        let local_addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let mut relay = RelayServer::bind(local_addr).unwrap();
+        let relay_addr = relay.local_addr().unwrap();
+        let session_id = 42;
+
+        let (server_socket, _, _, _) = RelaySocketBuilder {
+            local_addr,
+            relay_addr,
+            session_id,
+        }
+        .start()
+        .unwrap();
+        let server_addr = server_socket.local_addr();
+        let (_, mut server_receiver) = server_socket.split();
+        let (client_socket, _, _, _) = RelaySocketBuilder {
+            local_addr,
+            relay_addr,
+            session_id,
+        }
+        .connect()
+        .unwrap();
+        let (mut client_sender, _) = client_socket.split();
+
+        // the host registers to the relay when it is first polled
+        assert!(server_receiver.recv().unwrap().is_none());
+        std::thread::sleep(Duration::from_millis(50));
+        relay.update().unwrap();
+
+        // another peer that knows the session id tries to register as the host
+        let attacker = UdpSocket::bind(local_addr).unwrap();
+        attacker.set_nonblocking(true).unwrap();
+        let mut buf = Vec::new();
+        RelayHeader::Register { session_id }.write(&mut buf, &[]);
+        attacker.send_to(&buf, relay_addr).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        relay.update().unwrap();
+        assert_eq!(relay.hosts.get(&session_id), Some(&server_addr));
+        assert!(!relay
+            .host_sessions
+            .contains_key(&attacker.local_addr().unwrap()));
+
+        // the packets of the clients are still forwarded to the first host
+        let msg = b"hello server";
+        client_sender.send(msg, &relay_addr).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        relay.update().unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        let (recv_msg, _) = server_receiver.recv().unwrap().unwrap();
+        assert_eq!(recv_msg, msg);
+        let mut recv_buf = [0; MTU];
+        assert!(attacker.recv_from(&mut recv_buf).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! TCP client implementation.
+use std::net::SocketAddr;
+
+use async_compat::Compat;
+use bevy::tasks::IoTaskPool;
+use tokio::net::TcpSocket;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TryRecvError;
+use tracing::{debug, error, info, trace};
+
+use crate::client::io::transport::{ClientTransportBuilder, ClientTransportEnum};
+use crate::client::io::{ClientIoEvent, ClientIoEventReceiver, ClientNetworkEventSender};
+use crate::transport::error::Result;
+use crate::transport::io::IoState;
+use crate::transport::{BoxedReceiver, BoxedSender, PacketReceiver, PacketSender, Transport, MTU};
+
+use super::{read_packet, write_packet};
+
+pub(crate) struct TcpClientSocketBuilder {
+    pub(crate) client_addr: SocketAddr,
+    pub(crate) server_addr: SocketAddr,
+}
+
+impl ClientTransportBuilder for TcpClientSocketBuilder {
+    fn connect(
+        self,
+    ) -> Result<(
+        ClientTransportEnum,
+        IoState,
+        Option<ClientIoEventReceiver>,
+        Option<ClientNetworkEventSender>,
+    )> {
+        let (to_server_sender, mut to_server_receiver) = mpsc::unbounded_channel::<Vec<u8>>();
+        let (from_server_sender, from_server_receiver) = mpsc::unbounded_channel();
+        // channels used to cancel the task
+        let (close_tx, close_rx) = async_channel::bounded(1);
+        // channels used to check the status of the io task
+        let (event_tx, event_rx) = async_channel::bounded(1);
+
+        let server_addr = self.server_addr;
+        // bind the socket right away so that the actual local address is known
+        // (for example when binding to port 0)
+        let socket = if server_addr.is_ipv4() {
+            TcpSocket::new_v4()
+        } else {
+            TcpSocket::new_v6()
+        }?;
+        socket.bind(self.client_addr)?;
+        let local_addr = socket.local_addr()?;
+        // need to run this with Compat because tokio's TcpStream requires the tokio reactor
+        IoTaskPool::get()
+            .spawn(Compat::new(async move {
+                info!("Connecting to server via tcp at: {}", server_addr);
+                let stream = tokio::select! {
+                    _ = close_rx.recv() => {
+                        info!("Tcp connection closed. Reason: client requested disconnection.");
+                        let _ = event_tx.send(ClientIoEvent::Disconnected(std::io::Error::other("received close signal").into())).await;
+                        return;
+                    }
+                    stream = socket.connect(server_addr) => stream,
+                };
+                let stream = match stream {
+                    Ok(s) => s,
+                    Err(e) => {
+                        error!("Error creating tcp connection: {:?}", e);
+                        let _ = event_tx.send(ClientIoEvent::Disconnected(e.into())).await;
+                        return;
+                    }
+                };
+                // packets are small and latency-sensitive, we don't want them to be batched
+                if let Err(e) = stream.set_nodelay(true) {
+                    error!("Could not disable Nagle's algorithm on the tcp stream: {:?}", e);
+                }
+                // signal that the io is connected
+                event_tx.send(ClientIoEvent::Connected).await.unwrap();
+                info!("Connected.");
+
+                let (mut read, mut write) = stream.into_split();
+                let event_tx_clone = event_tx.clone();
+                let recv_handle = IoTaskPool::get().spawn(Compat::new(async move {
+                    loop {
+                        match read_packet(&mut read).await {
+                            Ok(data) => {
+                                trace!("receive packet from server: {:?}", &data);
+                                if from_server_sender.send(data).is_err() {
+                                    return;
+                                }
+                            }
+                            Err(e) => {
+                                // the stream is either closed or corrupted, we cannot recover
+                                error!("tcp read error: {:?}", e);
+                                let _ = event_tx_clone.send(ClientIoEvent::Disconnected(e.into())).await;
+                                return;
+                            }
+                        }
+                    }
+                }));
+                let send_handle = IoTaskPool::get().spawn(Compat::new(async move {
+                    while let Some(msg) = to_server_receiver.recv().await {
+                        trace!("send packet to server: {:?}", &msg);
+                        if let Err(e) = write_packet(&mut write, &msg).await {
+                            error!("tcp write error: {:?}", e);
+                        }
+                    }
+                }));
+                // wait for a signal that the io should be closed
+                let _ = close_rx.recv().await;
+                info!("Tcp connection closed. Reason: client requested disconnection. Shutting down tcp tasks.");
+                recv_handle.cancel().await;
+                send_handle.cancel().await;
+                debug!("Tcp tasks shut down.");
+            }))
+            .detach();
+
+        let sender = TcpClientPacketSender { to_server_sender };
+        let receiver = TcpClientPacketReceiver {
+            server_addr: self.server_addr,
+            from_server_receiver,
+            buffer: [0; MTU],
+        };
+        Ok((
+            ClientTransportEnum::TcpClient(TcpClientSocket {
+                local_addr,
+                sender,
+                receiver,
+            }),
+            IoState::Connecting,
+            Some(ClientIoEventReceiver(event_rx)),
+            Some(ClientNetworkEventSender(close_tx)),
+        ))
+    }
+}
+
+/// TCP client socket
+pub struct TcpClientSocket {
+    local_addr: SocketAddr,
+    sender: TcpClientPacketSender,
+    receiver: TcpClientPacketReceiver,
+}
+
+impl Transport for TcpClientSocket {
+    fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    fn split(self) -> (BoxedSender, BoxedReceiver) {
+        (Box::new(self.sender), Box::new(self.receiver))
+    }
+}
+
+struct TcpClientPacketSender {
+    to_server_sender: mpsc::UnboundedSender<Vec<u8>>,
+}
+
+impl PacketSender for TcpClientPacketSender {
+    fn send(&mut self, payload: &[u8], _: &SocketAddr) -> Result<()> {
+        self.to_server_sender
+            .send(payload.to_vec())
+            .map_err(|e| std::io::Error::other(format!("tcp send error: {:?}", e)).into())
+    }
+}
+
+struct TcpClientPacketReceiver {
+    server_addr: SocketAddr,
+    from_server_receiver: mpsc::UnboundedReceiver<Vec<u8>>,
+    buffer: [u8; MTU],
+}
+
+impl PacketReceiver for TcpClientPacketReceiver {
+    fn recv(&mut self) -> Result<Option<(&mut [u8], SocketAddr)>> {
+        match self.from_server_receiver.try_recv() {
+            Ok(data) => {
+                self.buffer[..data.len()].copy_from_slice(data.as_ref());
+                Ok(Some((&mut self.buffer[..data.len()], self.server_addr)))
+            }
+            Err(e) => {
+                if e == TryRecvError::Empty {
+                    Ok(None)
+                } else {
+                    Err(std::io::Error::other(format!("tcp receive error: {:?}", e)).into())
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Transport using a TCP stream, where each packet is prefixed with its length.
+//!
+//! This is useful for networks that block UDP entirely (for example some corporate or hotel networks),
+//! but it comes with a cost: TCP already guarantees reliability and ordering, so any reliability
+//! handled by lightyear's channels is done twice, and a single lost packet will delay every packet
+//! sent after it (head-of-line blocking). Prefer UDP-based transports whenever they are available.
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::transport::MTU;
+
+pub(crate) mod client;
+pub(crate) mod server;
+
+/// Write a single packet to the stream, prefixed by its length as a big-endian u16
+pub(crate) async fn write_packet<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    payload: &[u8],
+) -> std::io::Result<()> {
+    if payload.len() > MTU {
+        return Err(std::io::Error::other(format!(
+            "packet of size {} is bigger than the MTU",
+            payload.len()
+        )));
+    }
+    writer.write_u16(payload.len() as u16).await?;
+    writer.write_all(payload).await?;
+    writer.flush().await
+}
+
+/// Read a single length-prefixed packet from the stream
+pub(crate) async fn read_packet<R: AsyncRead + Unpin>(reader: &mut R) -> std::io::Result<Vec<u8>> {
+    let len = reader.read_u16().await? as usize;
+    if len > MTU {
+        return Err(std::io::Error::other(format!(
+            "received packet of size {} is bigger than the MTU",
+            len
+        )));
+    }
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf).await?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::tasks::{IoTaskPool, TaskPoolBuilder};
+    use bevy::utils::Duration;
+
+    use crate::client::io::transport::ClientTransportBuilder;
+    use crate::server::io::transport::ServerTransportBuilder;
+    use crate::transport::{PacketReceiver, PacketSender, Transport};
+
+    use super::client::*;
+    use super::server::*;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_framing() {
+        let (mut client, mut server) = tokio::io::duplex(4 * MTU);
+        write_packet(&mut client, b"hello").await.unwrap();
+        write_packet(&mut client, b"").await.unwrap();
+        write_packet(&mut client, b"world").await.unwrap();
+        assert_eq!(read_packet(&mut server).await.unwrap(), b"hello");
+        assert_eq!(read_packet(&mut server).await.unwrap(), b"");
+        assert_eq!(read_packet(&mut server).await.unwrap(), b"world");
+
+        // packets bigger than the MTU are rejected
+        assert!(write_packet(&mut client, &[0; MTU + 1]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tcp_native() {
+        IoTaskPool::get_or_init(|| TaskPoolBuilder::default().build());
+
+        // keep the io channels alive, otherwise the io tasks stop right away
+        let (server_socket, _, _server_status, _server_close) = TcpServerSocketBuilder {
+            server_addr: "127.0.0.1:0".parse().unwrap(),
+        }
+        .start()
+        .unwrap();
+        let server_addr = server_socket.local_addr();
+        let (mut server_send, mut server_recv) = server_socket.split();
+
+        // wait for the server to start listening
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let (client_socket, _, client_status, _client_close) = TcpClientSocketBuilder {
+            client_addr: "127.0.0.1:0".parse().unwrap(),
+            server_addr,
+        }
+        .connect()
+        .unwrap();
+        let client_addr = client_socket.local_addr();
+        let (mut client_send, mut client_recv) = client_socket.split();
+
+        // wait for the connection to be established
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        match client_status.unwrap().try_recv() {
+            Ok(crate::client::io::ClientIoEvent::Connected) => {}
+            Ok(crate::client::io::ClientIoEvent::Disconnected(e)) => panic!("{e:?}"),
+            Err(e) => panic!("{e:?}"),
+        }
+
+        let msg = b"hello world";
+
+        // client to server
+        client_send.send(msg, &server_addr).unwrap();
+
+        // sleep a little to give time to the message to arrive in the socket
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let Ok(Some((recv_msg, address))) = server_recv.recv() else {
+            panic!("server expected to receive a packet from client");
+        };
+        assert_eq!(address, client_addr);
+        assert_eq!(recv_msg, msg);
+
+        // server to client
+        server_send.send(msg, &client_addr).unwrap();
+
+        // sleep a little to give time to the message to arrive in the socket
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let Ok(Some((recv_msg, address))) = client_recv.recv() else {
+            panic!("client expected to receive a packet from server");
+        };
+        assert_eq!(address, server_addr);
+        assert_eq!(recv_msg, msg);
+    }
+}
@@ -0,0 +1,251 @@
+//! TCP server implementation.
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use async_compat::Compat;
+use bevy::tasks::{futures_lite, IoTaskPool};
+use bevy::utils::HashMap;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tracing::{debug, error, info, trace};
+
+use crate::server::io::transport::{ServerTransportBuilder, ServerTransportEnum};
+use crate::server::io::{ServerIoEvent, ServerIoEventReceiver, ServerNetworkEventSender};
+use crate::transport::error::Result;
+use crate::transport::io::IoState;
+use crate::transport::{BoxedReceiver, BoxedSender, PacketReceiver, PacketSender, Transport, MTU};
+
+use super::{read_packet, write_packet};
+
+type ClientBoundTxMap = Arc<Mutex<HashMap<SocketAddr, UnboundedSender<Vec<u8>>>>>;
+
+pub(crate) struct TcpServerSocketBuilder {
+    pub(crate) server_addr: SocketAddr,
+}
+
+impl ServerTransportBuilder for TcpServerSocketBuilder {
+    fn start(
+        self,
+    ) -> Result<(
+        ServerTransportEnum,
+        IoState,
+        Option<ServerIoEventReceiver>,
+        Option<ServerNetworkEventSender>,
+    )> {
+        let (from_client_sender, from_client_receiver) = mpsc::unbounded_channel();
+        // channels used to cancel the task
+        let (close_tx, close_rx) = async_channel::unbounded();
+        // channels used to check the status of the io task
+        let (status_tx, status_rx) = async_channel::unbounded();
+        let to_client_senders = ClientBoundTxMap::default();
+        let addr_to_task = Arc::new(Mutex::new(HashMap::new()));
+
+        let sender = TcpServerSocketSender {
+            to_client_senders: to_client_senders.clone(),
+        };
+        let receiver = TcpServerSocketReceiver {
+            buffer: [0; MTU],
+            from_client_receiver,
+        };
+
+        // bind the listener right away so that the actual local address is known
+        // (for example when binding to port 0)
+        let std_listener = std::net::TcpListener::bind(self.server_addr)?;
+        std_listener.set_nonblocking(true)?;
+        let local_addr = std_listener.local_addr()?;
+        // need to run this with Compat because tokio's TcpListener requires the tokio reactor
+        IoTaskPool::get()
+            .spawn(Compat::new(async move {
+                let listener = match TcpListener::from_std(std_listener) {
+                    Ok(l) => l,
+                    Err(e) => {
+                        status_tx
+                            .send(ServerIoEvent::ServerDisconnected(e.into()))
+                            .await
+                            .unwrap();
+                        return;
+                    }
+                };
+                info!("Starting server tcp task");
+                status_tx.send(ServerIoEvent::ServerConnected).await.unwrap();
+                loop {
+                    tokio::select! {
+                        // event from netcode
+                        Ok(event) = close_rx.recv() => {
+                            match event {
+                                ServerIoEvent::ServerDisconnected(e) => {
+                                    debug!("Stopping tcp io task. Reason: {:?}", e);
+                                    drop(addr_to_task);
+                                    return;
+                                }
+                                ServerIoEvent::ClientDisconnected(addr) => {
+                                    debug!("Stopping tcp io task associated with address: {:?} because we received a disconnection signal from netcode", addr);
+                                    addr_to_task.lock().unwrap().remove(&addr);
+                                    to_client_senders.lock().unwrap().remove(&addr);
+                                }
+                                _ => {}
+                            }
+                        }
+                        // new client connecting
+                        accepted = listener.accept() => {
+                            let Ok((stream, client_addr)) = accepted.inspect_err(|e| {
+                                error!("failed to accept new client: {:?}", e);
+                            }) else {
+                                continue;
+                            };
+                            let task = IoTaskPool::get().spawn(Compat::new(
+                                TcpServerSocket::handle_client(
+                                    client_addr,
+                                    stream,
+                                    from_client_sender.clone(),
+                                    to_client_senders.clone(),
+                                    status_tx.clone(),
+                                ),
+                            ));
+                            addr_to_task.lock().unwrap().insert(client_addr, task);
+                        }
+                    }
+                }
+            }))
+            .detach();
+
+        Ok((
+            ServerTransportEnum::TcpServer(TcpServerSocket {
+                local_addr,
+                sender,
+                receiver,
+            }),
+            IoState::Connecting,
+            Some(ServerIoEventReceiver(status_rx)),
+            Some(ServerNetworkEventSender(close_tx)),
+        ))
+    }
+}
+
+/// TCP server socket
+pub struct TcpServerSocket {
+    local_addr: SocketAddr,
+    sender: TcpServerSocketSender,
+    receiver: TcpServerSocketReceiver,
+}
+
+impl TcpServerSocket {
+    async fn handle_client(
+        client_addr: SocketAddr,
+        stream: TcpStream,
+        from_client_sender: UnboundedSender<(Vec<u8>, SocketAddr)>,
+        to_client_senders: ClientBoundTxMap,
+        status_tx: async_channel::Sender<ServerIoEvent>,
+    ) {
+        info!("New tcp connection: {}", client_addr);
+        // packets are small and latency-sensitive, we don't want them to be batched
+        if let Err(e) = stream.set_nodelay(true) {
+            error!(
+                "Could not disable Nagle's algorithm on the tcp stream: {:?}",
+                e
+            );
+        }
+
+        // add a new channel for this client
+        let (to_client_sender, mut to_client_receiver) = mpsc::unbounded_channel::<Vec<u8>>();
+        to_client_senders
+            .lock()
+            .unwrap()
+            .insert(client_addr, to_client_sender);
+
+        let (mut read, mut write) = stream.into_split();
+        let from_client_handle = IoTaskPool::get().spawn(Compat::new(async move {
+            loop {
+                match read_packet(&mut read).await {
+                    Ok(data) => {
+                        trace!("received packet from client!: {:?}", &data);
+                        if from_client_sender.send((data, client_addr)).is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) => {
+                        // the stream is either closed or corrupted, we cannot recover
+                        error!("tcp read error: {:?}", e);
+                        return;
+                    }
+                }
+            }
+        }));
+        let to_client_handle = IoTaskPool::get().spawn(Compat::new(async move {
+            while let Some(msg) = to_client_receiver.recv().await {
+                trace!("sending packet to client!: {:?}", &msg);
+                if let Err(e) = write_packet(&mut write, &msg).await {
+                    error!("tcp write error: {:?}", e);
+                    return;
+                }
+            }
+        }));
+
+        // wait for the tcp connection to be closed for any reason
+        let _closed = futures_lite::future::race(from_client_handle, to_client_handle).await;
+
+        info!("Connection with {} closed", client_addr);
+        to_client_senders.lock().unwrap().remove(&client_addr);
+        // notify netcode that the io task got disconnected
+        let _ = status_tx
+            .send(ServerIoEvent::ClientDisconnected(client_addr))
+            .await;
+        // dropping the task handles cancels them
+    }
+}
+
+impl Transport for TcpServerSocket {
+    fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    fn split(self) -> (BoxedSender, BoxedReceiver) {
+        (Box::new(self.sender), Box::new(self.receiver))
+    }
+}
+
+struct TcpServerSocketSender {
+    to_client_senders: ClientBoundTxMap,
+}
+
+impl PacketSender for TcpServerSocketSender {
+    fn send(&mut self, payload: &[u8], address: &SocketAddr) -> Result<()> {
+        if let Some(to_client_sender) = self.to_client_senders.lock().unwrap().get(address) {
+            to_client_sender.send(payload.to_vec()).map_err(|e| {
+                std::io::Error::other(format!("unable to send message to client: {}", e)).into()
+            })
+        } else {
+            // consider that if the channel doesn't exist, it's because the connection was closed
+            Ok(())
+        }
+    }
+}
+
+struct TcpServerSocketReceiver {
+    buffer: [u8; MTU],
+    from_client_receiver: UnboundedReceiver<(Vec<u8>, SocketAddr)>,
+}
+
+impl PacketReceiver for TcpServerSocketReceiver {
+    fn recv(&mut self) -> Result<Option<(&mut [u8], SocketAddr)>> {
+        match self.from_client_receiver.try_recv() {
+            Ok((data, addr)) => {
+                self.buffer[..data.len()].copy_from_slice(data.as_ref());
+                Ok(Some((&mut self.buffer[..data.len()], addr)))
+            }
+            Err(e) => {
+                if e == TryRecvError::Empty {
+                    Ok(None)
+                } else {
+                    Err(std::io::Error::other(format!(
+                        "unable to receive message from client: {}",
+                        e
+                    ))
+                    .into())
+                }
+            }
+        }
+    }
+}
@@ -2,6 +2,10 @@
 use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 
+use bevy::prelude::Reflect;
+use bevy::utils::Duration;
+use socket2::{Domain, Protocol, Socket, Type};
+
 use crate::client::io::transport::{ClientTransportBuilder, ClientTransportEnum};
 use crate::client::io::{ClientIoEventReceiver, ClientNetworkEventSender};
 use crate::server::io::transport::{ServerTransportBuilder, ServerTransportEnum};
@@ -11,26 +15,84 @@ use crate::transport::{BoxedReceiver, BoxedSender, PacketReceiver, PacketSender,
 
 use super::error::Result;
 
+/// Low-level options applied to the UDP socket before it is bound.
+///
+/// The default values keep the OS defaults.
+#[derive(Clone, Debug, Default, PartialEq, Reflect)]
+pub struct UdpSocketConfig {
+    /// Size in bytes of the kernel send buffer (`SO_SNDBUF`).
+    ///
+    /// Servers handling many clients might want to increase it to avoid dropping packets during bursts.
+    pub send_buffer_size: Option<usize>,
+    /// Size in bytes of the kernel receive buffer (`SO_RCVBUF`).
+    pub recv_buffer_size: Option<usize>,
+    /// Value of the IPv4 type-of-service field (`IP_TOS`), which can be used for DSCP marking.
+    ///
+    /// This is ignored for IPv6 sockets.
+    pub tos: Option<u32>,
+    /// Allow binding to an address that is still in the `TIME_WAIT` state (`SO_REUSEADDR`)
+    pub reuse_address: bool,
+    /// How the socket is polled for incoming packets
+    pub poll_strategy: UdpPollStrategy,
+}
+
+/// How the UDP socket is polled for incoming packets
+#[derive(Clone, Copy, Debug, Default, PartialEq, Reflect)]
+pub enum UdpPollStrategy {
+    /// The socket is non-blocking: polling returns immediately if no packet is available
+    #[default]
+    NonBlocking,
+    /// The socket blocks for at most the provided duration while waiting for a packet.
+    ///
+    /// The socket is polled from the Bevy schedule, so the frame is blocked until a packet arrives or
+    /// the timeout expires: every frame ends its receive loop with a poll that finds no packet, which
+    /// adds up to the timeout to the frame time. This can reduce CPU usage for dedicated servers that
+    /// have nothing else to do, but should not be used by clients that render.
+    ///
+    /// The duration is capped at [`MAX_POLL_TIMEOUT`] so that a misconfigured timeout can't stall the app,
+    /// and a zero duration is treated as [`UdpPollStrategy::NonBlocking`].
+    Timeout(Duration),
+}
+
+/// Maximum duration that a [`UdpPollStrategy::Timeout`] socket blocks the frame while waiting for a packet
+pub const MAX_POLL_TIMEOUT: Duration = Duration::from_millis(5);
+
 pub struct UdpSocketBuilder {
     pub(crate) local_addr: SocketAddr,
+    pub(crate) config: UdpSocketConfig,
 }
 
 impl UdpSocketBuilder {
+    fn bind(&self) -> Result<std::net::UdpSocket> {
+        let socket = Socket::new(
+            Domain::for_address(self.local_addr),
+            Type::DGRAM,
+            Some(Protocol::UDP),
+        )?;
+        if let Some(size) = self.config.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.config.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(tos) = self.config.tos {
+            if self.local_addr.is_ipv4() {
+                socket.set_tos(tos)?;
+            }
+        }
+        socket.set_reuse_address(self.config.reuse_address)?;
+        match self.config.poll_strategy {
+            UdpPollStrategy::Timeout(timeout) if !timeout.is_zero() => {
+                socket.set_read_timeout(Some(timeout.min(MAX_POLL_TIMEOUT)))?
+            }
+            _ => socket.set_nonblocking(true)?,
+        }
+        socket.bind(&self.local_addr.into())?;
+        Ok(socket.into())
+    }
+
     fn build(self) -> Result<UdpSocket> {
-        let udp_socket = std::net::UdpSocket::bind(self.local_addr)?;
-        let local_addr = udp_socket.local_addr()?;
-        let socket = Arc::new(Mutex::new(udp_socket));
-        socket.as_ref().lock().unwrap().set_nonblocking(true)?;
-        let sender = UdpSocketBuffer {
-            socket: socket.clone(),
-            buffer: [0; MTU],
-        };
-        let receiver = sender.clone();
-        Ok(UdpSocket {
-            local_addr,
-            sender,
-            receiver,
-        })
+        UdpSocket::new(self.bind()?)
     }
 }
 
@@ -71,6 +133,58 @@ impl ServerTransportBuilder for UdpSocketBuilder {
     }
 }
 
+/// Builds the transport from a [`UdpSocket`](std::net::UdpSocket) that is already bound,
+/// for example the socket returned by [`punch_hole`](crate::transport::nat::punch_hole)
+pub struct BoundUdpSocketBuilder {
+    pub(crate) socket: Arc<std::net::UdpSocket>,
+}
+
+impl BoundUdpSocketBuilder {
+    fn build(self) -> Result<UdpSocket> {
+        // the duplicated handle refers to the same socket, so the local port (and any NAT mapping) is kept
+        let udp_socket = self.socket.try_clone()?;
+        udp_socket.set_nonblocking(true)?;
+        UdpSocket::new(udp_socket)
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl ClientTransportBuilder for BoundUdpSocketBuilder {
+    fn connect(
+        self,
+    ) -> Result<(
+        ClientTransportEnum,
+        IoState,
+        Option<ClientIoEventReceiver>,
+        Option<ClientNetworkEventSender>,
+    )> {
+        Ok((
+            ClientTransportEnum::UdpSocket(self.build()?),
+            IoState::Connected,
+            None,
+            None,
+        ))
+    }
+}
+
+impl ServerTransportBuilder for BoundUdpSocketBuilder {
+    fn start(
+        self,
+    ) -> Result<(
+        ServerTransportEnum,
+        IoState,
+        Option<ServerIoEventReceiver>,
+        Option<ServerNetworkEventSender>,
+    )> {
+        Ok((
+            ServerTransportEnum::UdpSocket(self.build()?),
+            IoState::Connected,
+            None,
+            None,
+        ))
+    }
+}
+
 /// UDP Socket
 pub struct UdpSocket {
     local_addr: SocketAddr,
@@ -78,6 +192,23 @@ pub struct UdpSocket {
     receiver: UdpSocketBuffer,
 }
 
+impl UdpSocket {
+    fn new(udp_socket: std::net::UdpSocket) -> Result<Self> {
+        let local_addr = udp_socket.local_addr()?;
+        let socket = Arc::new(Mutex::new(udp_socket));
+        let sender = UdpSocketBuffer {
+            socket: socket.clone(),
+            buffer: [0; MTU],
+        };
+        let receiver = sender.clone();
+        Ok(UdpSocket {
+            local_addr,
+            sender,
+            receiver,
+        })
+    }
+}
+
 impl Transport for UdpSocket {
     fn local_addr(&self) -> SocketAddr {
         self.local_addr
@@ -118,7 +249,11 @@ impl PacketReceiver for UdpSocketBuffer {
             .recv_from(&mut self.buffer)
         {
             Ok((recv_len, address)) => Ok(Some((&mut self.buffer[..recv_len], address))),
-            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+            // the error kind depends on the platform when the read timeout expires
+            Err(ref e)
+                if e.kind() == std::io::ErrorKind::WouldBlock
+                    || e.kind() == std::io::ErrorKind::TimedOut =>
+            {
                 // Nothing to receive on the socket
                 Ok(None)
             }
@@ -139,26 +274,74 @@ mod tests {
     use bevy::utils::Duration;
 
     use crate::transport::middleware::conditioner::{LinkConditioner, LinkConditionerConfig};
-    use crate::transport::middleware::PacketReceiverWrapper;
-    use crate::transport::udp::UdpSocketBuilder;
+    use crate::transport::middleware::PacketMiddlewares;
+    use crate::transport::udp::{UdpPollStrategy, UdpSocketBuilder, UdpSocketConfig};
     use crate::transport::{PacketReceiver, PacketSender, Transport};
 
     #[test]
     fn test_udp_socket() {
         // let the OS assign a port
         let local_addr = SocketAddr::from_str("127.0.0.1:0").unwrap();
-        let (client_socket, _, _, _) = UdpSocketBuilder { local_addr }
-            .connect()
-            .expect("could not connect to socket");
+        let (client_socket, _, _, _) = UdpSocketBuilder {
+            local_addr,
+            config: UdpSocketConfig::default(),
+        }
+        .connect()
+        .expect("could not connect to socket");
         let client_addr = client_socket.local_addr();
         let (mut client_sender, _) = client_socket.split();
 
-        let (server_socket, _, _, _) = UdpSocketBuilder { local_addr }
+        let (server_socket, _, _, _) = UdpSocketBuilder {
+            local_addr,
+            config: UdpSocketConfig::default(),
+        }
+        .start()
+        .expect("could not connect to socket");
+        let server_addr = server_socket.local_addr();
+        let (_, mut server_receiver) = server_socket.split();
+
+        let msg = b"hello world";
+        client_sender.send(msg, &server_addr).unwrap();
+
+        // sleep a little to give time to the message to arrive in the socket
+        std::thread::sleep(Duration::from_millis(10));
+
+        let Some((recv_msg, address)) = server_receiver.recv().unwrap() else {
+            panic!("expected to receive a packet");
+        };
+        assert_eq!(address, client_addr);
+        assert_eq!(recv_msg, msg);
+    }
+
+    #[test]
+    fn test_udp_socket_with_config() {
+        // let the OS assign a port
+        let local_addr = SocketAddr::from_str("127.0.0.1:0").unwrap();
+        let config = UdpSocketConfig {
+            send_buffer_size: Some(1 << 20),
+            recv_buffer_size: Some(1 << 20),
+            tos: Some(0xb8),
+            reuse_address: true,
+            poll_strategy: UdpPollStrategy::Timeout(Duration::from_millis(5)),
+        };
+        let (client_socket, _, _, _) = UdpSocketBuilder {
+            local_addr,
+            config: config.clone(),
+        }
+        .connect()
+        .expect("could not connect to socket");
+        let client_addr = client_socket.local_addr();
+        let (mut client_sender, _) = client_socket.split();
+
+        let (server_socket, _, _, _) = UdpSocketBuilder { local_addr, config }
             .start()
             .expect("could not connect to socket");
         let server_addr = server_socket.local_addr();
         let (_, mut server_receiver) = server_socket.split();
 
+        // the read timeout expires without any packet
+        assert!(server_receiver.recv().unwrap().is_none());
+
         let msg = b"hello world";
         client_sender.send(msg, &server_addr).unwrap();
 
@@ -172,6 +355,24 @@ mod tests {
         assert_eq!(recv_msg, msg);
     }
 
+    #[test]
+    fn test_udp_socket_poll_timeout_is_capped() {
+        let local_addr = SocketAddr::from_str("127.0.0.1:0").unwrap();
+        let config = UdpSocketConfig {
+            poll_strategy: UdpPollStrategy::Timeout(Duration::from_secs(10)),
+            ..Default::default()
+        };
+        let (server_socket, _, _, _) = UdpSocketBuilder { local_addr, config }
+            .start()
+            .expect("could not connect to socket");
+        let (_, mut server_receiver) = server_socket.split();
+
+        // the poll blocks for at most MAX_POLL_TIMEOUT, not for the configured 10 seconds
+        let start = std::time::Instant::now();
+        assert!(server_receiver.recv().unwrap().is_none());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
     #[test]
     fn test_udp_socket_with_conditioner() {
         use mock_instant::MockClock;
@@ -179,24 +380,34 @@ mod tests {
         // let the OS assign a port
         let local_addr = SocketAddr::from_str("127.0.0.1:0").unwrap();
 
-        let (client_socket, _, _, _) = UdpSocketBuilder { local_addr }
-            .connect()
-            .expect("could not connect to socket");
+        let (client_socket, _, _, _) = UdpSocketBuilder {
+            local_addr,
+            config: UdpSocketConfig::default(),
+        }
+        .connect()
+        .expect("could not connect to socket");
         let client_addr = client_socket.local_addr();
         let (mut client_sender, _) = client_socket.split();
 
-        let (server_socket, _, _, _) = UdpSocketBuilder { local_addr }
-            .start()
-            .expect("could not connect to socket");
+        let (server_socket, _, _, _) = UdpSocketBuilder {
+            local_addr,
+            config: UdpSocketConfig::default(),
+        }
+        .start()
+        .expect("could not connect to socket");
         let server_addr = server_socket.local_addr();
-        let (_, server_receiver) = server_socket.split();
+        let (server_sender, server_receiver) = server_socket.split();
 
-        let mut conditioned_server_receiver = LinkConditioner::new(LinkConditionerConfig {
-            incoming_latency: Duration::from_millis(100),
-            incoming_jitter: Duration::from_millis(0),
-            incoming_loss: 0.0,
-        })
-        .wrap(server_receiver);
+        let mut middlewares = PacketMiddlewares::default();
+        middlewares.push(|| {
+            LinkConditioner::<Vec<u8>>::new(LinkConditionerConfig {
+                incoming_latency: Duration::from_millis(100),
+                incoming_jitter: Duration::from_millis(0),
+                incoming_loss: 0.0,
+                ..Default::default()
+            })
+        });
+        let (_, mut conditioned_server_receiver) = middlewares.wrap(server_sender, server_receiver);
 
         let msg = b"hello world";
         client_sender.send(msg, &server_addr).unwrap();
@@ -0,0 +1,268 @@
+//! The transport is a unix domain datagram socket, for processes running on the same machine
+use std::io;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use bevy::utils::HashMap;
+use tracing::trace;
+
+use crate::client::io::transport::{ClientTransportBuilder, ClientTransportEnum};
+use crate::client::io::{ClientIoEventReceiver, ClientNetworkEventSender};
+use crate::server::io::transport::{ServerTransportBuilder, ServerTransportEnum};
+use crate::server::io::{ServerIoEventReceiver, ServerNetworkEventSender};
+use crate::transport::error::Result;
+use crate::transport::io::IoState;
+use crate::transport::{
+    BoxedReceiver, BoxedSender, PacketReceiver, PacketSender, Transport, LOCAL_SOCKET, MTU,
+};
+
+/// Bind a unix datagram socket to `path`, removing a stale socket file left by a previous run
+fn bind(path: &Path) -> io::Result<UnixDatagram> {
+    if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    let socket = UnixDatagram::bind(path)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+pub(crate) struct UnixClientSocketBuilder {
+    pub(crate) local_path: PathBuf,
+    pub(crate) server_path: PathBuf,
+    pub(crate) server_addr: SocketAddr,
+}
+
+impl ClientTransportBuilder for UnixClientSocketBuilder {
+    fn connect(
+        self,
+    ) -> Result<(
+        ClientTransportEnum,
+        IoState,
+        Option<ClientIoEventReceiver>,
+        Option<ClientNetworkEventSender>,
+    )> {
+        // the client needs to be bound to a path, otherwise the server cannot answer
+        let socket = bind(&self.local_path)?;
+        socket.connect(&self.server_path)?;
+        let sender = UnixClientSocketSender {
+            socket: socket.try_clone()?,
+        };
+        let receiver = UnixClientSocketReceiver {
+            socket,
+            server_addr: self.server_addr,
+            buffer: [0; MTU],
+        };
+        Ok((
+            ClientTransportEnum::UnixSocket(UnixTransport {
+                sender: Box::new(sender),
+                receiver: Box::new(receiver),
+            }),
+            IoState::Connected,
+            None,
+            None,
+        ))
+    }
+}
+
+pub(crate) struct UnixServerSocketBuilder {
+    pub(crate) path: PathBuf,
+}
+
+impl ServerTransportBuilder for UnixServerSocketBuilder {
+    fn start(
+        self,
+    ) -> Result<(
+        ServerTransportEnum,
+        IoState,
+        Option<ServerIoEventReceiver>,
+        Option<ServerNetworkEventSender>,
+    )> {
+        let socket = bind(&self.path)?;
+        let peers = Arc::new(Mutex::new(UnixPeers::default()));
+        let sender = UnixServerSocketSender {
+            socket: socket.try_clone()?,
+            peers: peers.clone(),
+        };
+        let receiver = UnixServerSocketReceiver {
+            socket,
+            peers,
+            buffer: [0; MTU],
+        };
+        Ok((
+            ServerTransportEnum::UnixSocket(UnixTransport {
+                sender: Box::new(sender),
+                receiver: Box::new(receiver),
+            }),
+            IoState::Connected,
+            None,
+            None,
+        ))
+    }
+}
+
+/// Unix domain datagram socket.
+///
+/// Peers are identified by a path instead of a [`SocketAddr`], so the server assigns a
+/// placeholder [`SocketAddr`] to each client path.
+pub struct UnixTransport {
+    sender: BoxedSender,
+    receiver: BoxedReceiver,
+}
+
+impl Transport for UnixTransport {
+    fn local_addr(&self) -> SocketAddr {
+        // unix sockets are not bound to a SocketAddr
+        LOCAL_SOCKET
+    }
+
+    fn split(self) -> (BoxedSender, BoxedReceiver) {
+        (self.sender, self.receiver)
+    }
+}
+
+struct UnixClientSocketSender {
+    socket: UnixDatagram,
+}
+
+impl PacketSender for UnixClientSocketSender {
+    /// The socket is connected to the server path, so the address is ignored
+    fn send(&mut self, payload: &[u8], _: &SocketAddr) -> Result<()> {
+        self.socket.send(payload)?;
+        Ok(())
+    }
+}
+
+struct UnixClientSocketReceiver {
+    socket: UnixDatagram,
+    server_addr: SocketAddr,
+    buffer: [u8; MTU],
+}
+
+impl PacketReceiver for UnixClientSocketReceiver {
+    /// Packets are reported as coming from `server_addr`, since the socket only receives packets from the server
+    fn recv(&mut self) -> Result<Option<(&mut [u8], SocketAddr)>> {
+        match self.socket.recv(&mut self.buffer) {
+            Ok(recv_len) => Ok(Some((&mut self.buffer[..recv_len], self.server_addr))),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Mapping between the paths of the clients and the placeholder addresses used to identify them
+#[derive(Default)]
+struct UnixPeers {
+    addrs: HashMap<PathBuf, SocketAddr>,
+    paths: HashMap<SocketAddr, PathBuf>,
+    next_id: u32,
+}
+
+impl UnixPeers {
+    fn get_or_insert(&mut self, path: &Path) -> SocketAddr {
+        if let Some(addr) = self.addrs.get(path) {
+            return *addr;
+        }
+        // use an address in the unique local range, which cannot be confused with a real peer
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        let ip = Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, (id >> 16) as u16, id as u16);
+        let addr = SocketAddr::new(ip.into(), 0);
+        self.addrs.insert(path.to_path_buf(), addr);
+        self.paths.insert(addr, path.to_path_buf());
+        addr
+    }
+}
+
+struct UnixServerSocketSender {
+    socket: UnixDatagram,
+    peers: Arc<Mutex<UnixPeers>>,
+}
+
+impl PacketSender for UnixServerSocketSender {
+    fn send(&mut self, payload: &[u8], address: &SocketAddr) -> Result<()> {
+        let peers = self.peers.lock().unwrap();
+        let Some(path) = peers.paths.get(address) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no unix socket path for address {}", address),
+            )
+            .into());
+        };
+        self.socket.send_to(payload, path)?;
+        Ok(())
+    }
+}
+
+struct UnixServerSocketReceiver {
+    socket: UnixDatagram,
+    peers: Arc<Mutex<UnixPeers>>,
+    buffer: [u8; MTU],
+}
+
+impl PacketReceiver for UnixServerSocketReceiver {
+    fn recv(&mut self) -> Result<Option<(&mut [u8], SocketAddr)>> {
+        loop {
+            match self.socket.recv_from(&mut self.buffer) {
+                Ok((recv_len, address)) => {
+                    let Some(path) = address.as_pathname() else {
+                        trace!("Ignoring packet from an unnamed unix socket");
+                        continue;
+                    };
+                    let addr = self.peers.lock().unwrap().get_or_insert(path);
+                    return Ok(Some((&mut self.buffer[..recv_len], addr)));
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_unix_socket() {
+        let dir = std::env::temp_dir();
+        let server_path = dir.join(format!("lightyear-test-server-{}.sock", std::process::id()));
+        let client_path = dir.join(format!("lightyear-test-client-{}.sock", std::process::id()));
+        let server_addr = SocketAddr::from(([127, 0, 0, 1], 5000));
+
+        let (server_socket, _, _, _) = UnixServerSocketBuilder {
+            path: server_path.clone(),
+        }
+        .start()
+        .unwrap();
+        let (mut server_sender, mut server_receiver) = server_socket.split();
+        let (client_socket, _, _, _) = UnixClientSocketBuilder {
+            local_path: client_path.clone(),
+            server_path: server_path.clone(),
+            server_addr,
+        }
+        .connect()
+        .unwrap();
+        let (mut client_sender, mut client_receiver) = client_socket.split();
+
+        let msg = b"hello server";
+        client_sender.send(msg, &server_addr).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        let (recv_msg, client_addr) = server_receiver.recv().unwrap().unwrap();
+        assert_eq!(recv_msg, msg);
+
+        let msg = b"hello client";
+        server_sender.send(msg, &client_addr).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        let (recv_msg, addr) = client_receiver.recv().unwrap().unwrap();
+        assert_eq!(recv_msg, msg);
+        assert_eq!(addr, server_addr);
+
+        let _ = std::fs::remove_file(server_path);
+        let _ = std::fs::remove_file(client_path);
+    }
+}
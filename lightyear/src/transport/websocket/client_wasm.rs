@@ -18,7 +18,7 @@ use web_sys::{
 };
 
 use crate::client::io::transport::{ClientTransportBuilder, ClientTransportEnum};
-use crate::client::io::{ClientIoEventReceiver, ClientNetworkEventSender};
+use crate::client::io::{ClientIoEvent, ClientIoEventReceiver, ClientNetworkEventSender};
 use crate::transport::error::{Error, Result};
 use crate::transport::io::IoState;
 use crate::transport::{
@@ -41,6 +41,9 @@ impl ClientTransportBuilder for WebSocketClientSocketBuilder {
         let (serverbound_tx, serverbound_rx) = unbounded_channel::<Vec<u8>>();
         let (clientbound_tx, clientbound_rx) = unbounded_channel::<Vec<u8>>();
         let (close_tx, close_rx) = async_channel::bounded(1);
+        // channels used to check the status of the io task
+        // (unbounded because the websocket callbacks cannot await)
+        let (status_tx, status_rx) = async_channel::unbounded();
 
         let sender = WebSocketClientSocketSender { serverbound_tx };
 
@@ -64,16 +67,29 @@ impl ClientTransportBuilder for WebSocketClientSocketBuilder {
                 .expect("Unable to propagate the read websocket message to the receiver");
         });
 
+        let status_tx_clone = status_tx.clone();
         let on_close_callback = Closure::<dyn FnMut(_)>::new(move |e: CloseEvent| {
             info!(
                 "WebSocket connection closed with code {} and reason {}",
                 e.code(),
                 e.reason()
             );
+            let _ = status_tx_clone.try_send(ClientIoEvent::Disconnected(
+                std::io::Error::other(format!(
+                    "websocket closed with code {} and reason {}",
+                    e.code(),
+                    e.reason()
+                ))
+                .into(),
+            ));
         });
 
+        let status_tx_clone = status_tx.clone();
         let on_error_callback = Closure::<dyn FnMut(_)>::new(move |e: ErrorEvent| {
             error!("WebSocket connection error {}", e.message());
+            let _ = status_tx_clone.try_send(ClientIoEvent::Disconnected(
+                std::io::Error::other(format!("websocket error: {}", e.message())).into(),
+            ));
         });
 
         // need to clone these two because we move two times
@@ -82,6 +98,7 @@ impl ClientTransportBuilder for WebSocketClientSocketBuilder {
 
         let on_open_callback = Closure::<dyn FnOnce()>::once(move || {
             info!("WebSocket handshake has been successfully completed");
+            let _ = status_tx.try_send(ClientIoEvent::Connected);
             let serverbound_rx = serverbound_rx.clone();
             wasm_bindgen_futures::spawn_local(async move {
                 while let Some(msg) = serverbound_rx.lock().await.recv().await {
@@ -116,8 +133,8 @@ impl ClientTransportBuilder for WebSocketClientSocketBuilder {
 
         Ok((
             ClientTransportEnum::WebSocketClient(WebSocketClientSocket { sender, receiver }),
-            IoState::Connected,
-            None,
+            IoState::Connecting,
+            Some(ClientIoEventReceiver(status_rx)),
             Some(ClientNetworkEventSender(close_tx)),
         ))
     }
@@ -78,6 +78,28 @@ mod tests {
         assert_eq!(recv_msg, msg);
         dbg!(recv_msg);
     }
+
+    #[cfg(not(target_family = "wasm"))]
+    #[test]
+    fn test_certificate_digest() {
+        use crate::prelude::server::ServerTransport;
+
+        let certificate = Identity::self_signed(["localhost"]).unwrap();
+        let expected = certificate.certificate_chain().as_slice()[0]
+            .hash()
+            .to_string()
+            .replace(':', "");
+        let transport = ServerTransport::WebTransportServer {
+            server_addr: "127.0.0.1:7001".parse().unwrap(),
+            certificate,
+        };
+        let digest = transport.certificate_digest().unwrap();
+        assert_eq!(digest, expected);
+        // 32 bytes encoded as hex
+        assert_eq!(digest.len(), 64);
+
+        assert!(ServerTransport::default().certificate_digest().is_none());
+    }
 }
 
 #[cfg(target_family = "wasm")]
@@ -0,0 +1,101 @@
+//! Implement lightyear traits for some common bevy_rapier2d types
+//!
+//! The [`RapierReplicationPlugin`] can be added to the protocol to replicate and predict
+//! the physics state of the rigid bodies.
+use bevy::prelude::{App, FixedUpdate, Plugin, Transform};
+use bevy_rapier2d::prelude::{
+    ExternalForce, NoUserData, RapierConfiguration, RapierPhysicsPlugin, TimestepMode, Velocity,
+};
+use tracing::trace;
+
+use crate::client::components::{ComponentSyncMode, LerpFn};
+use crate::client::config::ClientConfig;
+use crate::client::prediction::local_rollback::AppRollbackExt;
+use crate::client::prediction::rollback::Rollback;
+use crate::prelude::{AppComponentExt, ChannelDirection};
+use crate::server::config::ServerConfig;
+use crate::utils::bevy::TransformLinearInterpolation;
+
+pub mod velocity {
+    use super::*;
+
+    pub fn lerp(start: &Velocity, other: &Velocity, t: f32) -> Velocity {
+        let res = Velocity {
+            linvel: start.linvel * (1.0 - t) + other.linvel * t,
+            angvel: start.angvel * (1.0 - t) + other.angvel * t,
+        };
+        trace!(
+            "velocity lerp: start: {:?} end: {:?} t: {} res: {:?}",
+            start,
+            other,
+            t,
+            res
+        );
+        res
+    }
+}
+
+/// Plugin that replicates the physics state of the `bevy_rapier2d` rigid bodies, and predicts it on the client.
+///
+/// - [`Transform`] and [`Velocity`] are registered in the protocol, with full prediction: rapier reads the
+///   position of the rigid bodies from their [`Transform`] and writes it back after each step.
+///   [`Transform`] is also interpolated and visually corrected.
+/// - on the client, [`ExternalForce`] is rolled back: it is not replicated, but rapier applies it at every step
+///   until it is changed, so a re-simulated tick must use the force that was applied at that tick.
+/// - the [`RapierPhysicsPlugin`] runs in the `FixedUpdate` schedule with `TimestepMode::Fixed` and a single
+///   substep of the tick duration (read from the `ClientConfig` or `ServerConfig` when the app is finished), so
+///   that each tick re-simulated during a rollback steps the physics again by exactly one tick.
+/// - rapier steps every rigid body of the world, so the rollbacks can't be restricted to some
+///   [`PredictionGroup`](crate::client::prediction::rollback::PredictionGroup)s: the groups are ignored.
+///
+/// The plugin adds the [`RapierPhysicsPlugin`], so it should not be added separately. The systems that
+/// apply the player's actions to the rigid bodies should run in `FixedUpdate`, before the physics.
+pub struct RapierReplicationPlugin;
+
+impl Plugin for RapierReplicationPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_component::<Transform>(ChannelDirection::Bidirectional)
+            .add_prediction(ComponentSyncMode::Full)
+            .add_interpolation(ComponentSyncMode::Full)
+            .add_interpolation_fn(TransformLinearInterpolation::lerp)
+            .add_correction_fn(TransformLinearInterpolation::lerp);
+        // the velocity is needed to predict the physics, but it is not displayed
+        app.register_component::<Velocity>(ChannelDirection::Bidirectional)
+            .add_prediction(ComponentSyncMode::Full);
+
+        if app.world.get_resource::<ClientConfig>().is_some() {
+            // the force is only known by the peer that applies it, but it changes the result of each step
+            app.add_rollback::<ExternalForce>();
+        }
+
+        // the rollback re-runs the `FixedUpdate` schedule for each re-simulated tick
+        if !app.is_plugin_added::<RapierPhysicsPlugin<NoUserData>>() {
+            app.add_plugins(RapierPhysicsPlugin::<NoUserData>::default().in_schedule(FixedUpdate));
+        }
+    }
+
+    fn finish(&self, app: &mut App) {
+        let tick_duration = app
+            .world
+            .get_resource::<ClientConfig>()
+            .map(|config| config.shared.tick.tick_duration)
+            .or_else(|| {
+                app.world
+                    .get_resource::<ServerConfig>()
+                    .map(|config| config.shared.tick.tick_duration)
+            })
+            .expect("the ClientConfig or ServerConfig must be inserted before the RapierReplicationPlugin");
+        // step the physics exactly once per tick, by the duration of a tick, so that a re-simulated tick
+        // gives the same result as the original one
+        app.world
+            .get_resource_or_insert_with(RapierConfiguration::default)
+            .timestep_mode = TimestepMode::Fixed {
+            dt: tick_duration.as_secs_f32(),
+            substeps: 1,
+        };
+        // the physics step simulates all the rigid bodies, so a rollback must include all of them
+        if let Some(mut rollback) = app.world.get_resource_mut::<Rollback>() {
+            rollback.disable_prediction_groups();
+        }
+    }
+}
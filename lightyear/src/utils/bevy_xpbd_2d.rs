@@ -1,9 +1,90 @@
 //! Implement lightyear traits for some common bevy types
-use crate::shared::replication::delta::Diffable;
+//!
+//! The [`XpbdReplicationPlugin`] can be added to the protocol to replicate and predict
+//! the physics state of the rigid bodies.
+use bevy::prelude::{App, FixedUpdate, Plugin, Time};
 use bevy_xpbd_2d::components::*;
 use bevy_xpbd_2d::math::Scalar;
+use bevy_xpbd_2d::prelude::{Physics, PhysicsPlugins, PhysicsSchedulePlugin};
 use tracing::trace;
 
+use crate::client::components::ComponentSyncMode;
+use crate::client::config::ClientConfig;
+use crate::client::prediction::local_rollback::AppRollbackExt;
+use crate::client::prediction::rollback::Rollback;
+use crate::prelude::{AppComponentExt, ChannelDirection};
+use crate::server::config::ServerConfig;
+use crate::shared::replication::delta::Diffable;
+
+/// Plugin that replicates the physics state of the `bevy_xpbd_2d` rigid bodies, and predicts it on the client.
+///
+/// - [`Position`], [`Rotation`], [`LinearVelocity`] and [`AngularVelocity`] are registered in the protocol,
+///   with full prediction. [`Position`] and [`Rotation`] are also interpolated and visually corrected; xpbd
+///   derives the `Transform` of the rigid bodies from them.
+/// - on the client, [`ExternalForce`] and [`ExternalTorque`] are rolled back: they are not replicated, but a
+///   persistent force keeps being applied by xpbd at every step, so a re-simulated tick must use the force
+///   that was applied at that tick.
+/// - the [`PhysicsPlugins`] run in the `FixedUpdate` schedule, and `Time<Physics>` is set to step once per run
+///   by the tick duration (read from the `ClientConfig` or `ServerConfig` when the app is finished). Each tick
+///   re-simulated during a rollback therefore steps the physics again.
+/// - xpbd steps every rigid body of the world, so the rollbacks can't be restricted to some
+///   [`PredictionGroup`](crate::client::prediction::rollback::PredictionGroup)s: the groups are ignored.
+///
+/// The plugin adds the [`PhysicsPlugins`], so they should not be added separately. The systems that
+/// apply the player's actions to the rigid bodies should run in `FixedUpdate`, before the physics.
+pub struct XpbdReplicationPlugin;
+
+impl Plugin for XpbdReplicationPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_component::<Position>(ChannelDirection::Bidirectional)
+            .add_prediction(ComponentSyncMode::Full)
+            .add_interpolation(ComponentSyncMode::Full)
+            .add_interpolation_fn(position::lerp)
+            .add_correction_fn(position::lerp);
+        app.register_component::<Rotation>(ChannelDirection::Bidirectional)
+            .add_prediction(ComponentSyncMode::Full)
+            .add_interpolation(ComponentSyncMode::Full)
+            .add_interpolation_fn(rotation::lerp)
+            .add_correction_fn(rotation::lerp);
+        // the velocities are needed to predict the physics, but they are not displayed
+        app.register_component::<LinearVelocity>(ChannelDirection::Bidirectional)
+            .add_prediction(ComponentSyncMode::Full);
+        app.register_component::<AngularVelocity>(ChannelDirection::Bidirectional)
+            .add_prediction(ComponentSyncMode::Full);
+
+        if app.world.get_resource::<ClientConfig>().is_some() {
+            // the forces are only known by the peer that applies them, but they change the result of each step
+            app.add_rollback::<ExternalForce>()
+                .add_rollback::<ExternalTorque>();
+        }
+
+        // step the physics exactly once per tick, including the ticks that are re-simulated during rollback
+        if !app.is_plugin_added::<PhysicsSchedulePlugin>() {
+            app.add_plugins(PhysicsPlugins::new(FixedUpdate));
+        }
+    }
+
+    fn finish(&self, app: &mut App) {
+        let tick_duration = app
+            .world
+            .get_resource::<ClientConfig>()
+            .map(|config| config.shared.tick.tick_duration)
+            .or_else(|| {
+                app.world
+                    .get_resource::<ServerConfig>()
+                    .map(|config| config.shared.tick.tick_duration)
+            })
+            .expect("the ClientConfig or ServerConfig must be inserted before the XpbdReplicationPlugin");
+        app.insert_resource(Time::new_with(Physics::fixed_once_hz(
+            1.0 / tick_duration.as_secs_f64(),
+        )));
+        // the physics step simulates all the rigid bodies, so a rollback must include all of them
+        if let Some(mut rollback) = app.world.get_resource_mut::<Rollback>() {
+            rollback.disable_prediction_groups();
+        }
+    }
+}
+
 pub mod position {
     use super::*;
 
@@ -0,0 +1,215 @@
+//! Fixed-point numbers, for deterministic simulations
+//!
+//! [`Fixed32`] has 16 integer bits and 16 fractional bits, [`Fixed64`] has 32 integer bits and 32 fractional bits.
+//! The arithmetic only uses integer operations, so it gives the same results on every platform.
+//! Additions and subtractions wrap around on overflow.
+//!
+//! The values are serialized as their raw integer representation, and they implement [`Linear`]
+//! by interpolating the raw representation, so they can be used in predicted or interpolated components.
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use bevy::reflect::Reflect;
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::component::Linear;
+
+macro_rules! fixed_point {
+    ($name:ident, $raw:ty, $wide:ty, $frac_bits:expr) => {
+        #[derive(
+            Serialize,
+            Deserialize,
+            Clone,
+            Copy,
+            Debug,
+            Default,
+            PartialEq,
+            Eq,
+            PartialOrd,
+            Ord,
+            Hash,
+            Reflect,
+        )]
+        #[serde(transparent)]
+        pub struct $name(pub $raw);
+
+        impl $name {
+            /// Number of fractional bits
+            pub const FRAC_BITS: u32 = $frac_bits;
+            pub const ZERO: Self = Self(0);
+            pub const ONE: Self = Self(1 << $frac_bits);
+            pub const MIN: Self = Self(<$raw>::MIN);
+            pub const MAX: Self = Self(<$raw>::MAX);
+
+            /// Create a number from its raw representation
+            pub const fn from_bits(bits: $raw) -> Self {
+                Self(bits)
+            }
+
+            /// Returns the raw representation of the number
+            pub const fn to_bits(self) -> $raw {
+                self.0
+            }
+
+            /// Create a number from an integer, which wraps around if it doesn't fit in the integer bits
+            pub const fn from_int(value: $raw) -> Self {
+                Self(value.wrapping_shl($frac_bits))
+            }
+
+            /// Returns the integer part of the number, rounded towards negative infinity
+            pub const fn floor_to_int(self) -> $raw {
+                self.0 >> $frac_bits
+            }
+
+            /// Convert a float to the nearest fixed-point number (saturating if it is out of range).
+            ///
+            /// Float conversions are not guaranteed to be deterministic across platforms, so they should
+            /// only be used for inputs or rendering.
+            pub fn from_f64(value: f64) -> Self {
+                Self((value * (1u64 << $frac_bits) as f64).round() as $raw)
+            }
+
+            pub fn to_f64(self) -> f64 {
+                self.0 as f64 / (1u64 << $frac_bits) as f64
+            }
+
+            pub fn from_f32(value: f32) -> Self {
+                Self::from_f64(value as f64)
+            }
+
+            pub fn to_f32(self) -> f32 {
+                self.to_f64() as f32
+            }
+
+            pub const fn abs(self) -> Self {
+                Self(self.0.wrapping_abs())
+            }
+        }
+
+        impl Add for $name {
+            type Output = Self;
+
+            fn add(self, rhs: Self) -> Self::Output {
+                Self(self.0.wrapping_add(rhs.0))
+            }
+        }
+
+        impl Sub for $name {
+            type Output = Self;
+
+            fn sub(self, rhs: Self) -> Self::Output {
+                Self(self.0.wrapping_sub(rhs.0))
+            }
+        }
+
+        impl Mul for $name {
+            type Output = Self;
+
+            fn mul(self, rhs: Self) -> Self::Output {
+                Self(((self.0 as $wide * rhs.0 as $wide) >> $frac_bits) as $raw)
+            }
+        }
+
+        impl Div for $name {
+            type Output = Self;
+
+            /// # Panics
+            ///
+            /// Panics if `rhs` is zero.
+            fn div(self, rhs: Self) -> Self::Output {
+                Self((((self.0 as $wide) << $frac_bits) / rhs.0 as $wide) as $raw)
+            }
+        }
+
+        impl Neg for $name {
+            type Output = Self;
+
+            fn neg(self) -> Self::Output {
+                Self(self.0.wrapping_neg())
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, rhs: Self) {
+                *self = *self + rhs;
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, rhs: Self) {
+                *self = *self - rhs;
+            }
+        }
+
+        impl MulAssign for $name {
+            fn mul_assign(&mut self, rhs: Self) {
+                *self = *self * rhs;
+            }
+        }
+
+        impl DivAssign for $name {
+            fn div_assign(&mut self, rhs: Self) {
+                *self = *self / rhs;
+            }
+        }
+
+        impl Linear for $name {
+            /// Interpolate the raw representation; `t` is converted to a fixed-point number first
+            fn lerp(start: &Self, other: &Self, t: f32) -> Self {
+                let t = (t as f64 * (1u64 << $frac_bits) as f64).round() as $wide;
+                let diff = other.0 as $wide - start.0 as $wide;
+                Self((start.0 as $wide + ((diff * t) >> $frac_bits)) as $raw)
+            }
+        }
+    };
+}
+
+fixed_point!(Fixed32, i32, i64, 16);
+fixed_point!(Fixed64, i64, i128, 32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arithmetic() {
+        let a = Fixed32::from_int(3);
+        let b = Fixed32::from_f32(0.5);
+        assert_eq!((a + b).to_f32(), 3.5);
+        assert_eq!((a - b).to_f32(), 2.5);
+        assert_eq!((a * b).to_f32(), 1.5);
+        assert_eq!((a / b).to_f32(), 6.0);
+        assert_eq!((-a).floor_to_int(), -3);
+        assert_eq!((-b).floor_to_int(), -1);
+
+        let a = Fixed64::from_int(1 << 20);
+        let b = Fixed64::from_f64(0.25);
+        assert_eq!((a * b).floor_to_int(), 1 << 18);
+        assert_eq!((a / b).floor_to_int(), 1 << 22);
+        assert_eq!(Fixed64::MAX + Fixed64::from_bits(1), Fixed64::MIN);
+    }
+
+    #[test]
+    fn test_lerp() {
+        let start = Fixed32::from_int(-2);
+        let end = Fixed32::from_int(6);
+        assert_eq!(Fixed32::lerp(&start, &end, 0.0), start);
+        assert_eq!(Fixed32::lerp(&start, &end, 0.25), Fixed32::ZERO);
+        assert_eq!(Fixed32::lerp(&start, &end, 1.0), end);
+
+        // the interpolation does not overflow for far apart values
+        let start = Fixed64::MIN;
+        let end = Fixed64::MAX;
+        assert_eq!(Fixed64::lerp(&start, &end, 0.5), Fixed64::from_bits(-1));
+    }
+
+    #[test]
+    fn test_serialize() {
+        // small values are serialized as a varint of their raw representation
+        let value = Fixed32::from_f32(0.25);
+        let bytes = bincode::serde::encode_to_vec(value, bincode::config::standard()).unwrap();
+        assert_eq!(bytes.len(), 3);
+        let (decoded, _): (Fixed32, usize) =
+            bincode::serde::decode_from_slice(&bytes, bincode::config::standard()).unwrap();
+        assert_eq!(decoded, value);
+    }
+}
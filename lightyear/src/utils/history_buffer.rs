@@ -0,0 +1,210 @@
+//! Bounded, tick-ordered history of a single value, used to store the history of components and resources.
+//!
+//! Each entity has one history per component (there is no contiguous storage shared by an archetype).
+use std::collections::VecDeque;
+
+use crate::shared::tick_manager::Tick;
+
+/// Default number of ticks of history kept by a [`HistoryBuffer`]
+pub(crate) const DEFAULT_HISTORY_TICKS: u16 = 256;
+
+/// A bounded buffer that contains the history of one value (for example one component of one entity), ordered by tick.
+///
+/// The history only contains the ticks where the value changed, so the value at a given tick is the most recent
+/// value that is older or equal to that tick.
+///
+/// The buffer is bounded: when a value is added, the values that are older than `max_ticks` before the most recent
+/// tick are evicted (except the one that is still valid at that horizon), so the buffer never contains more than
+/// `max_ticks + 1` values and its allocation is reused once it has reached that size.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HistoryBuffer<T> {
+    buffer: VecDeque<(Tick, T)>,
+    max_ticks: u16,
+}
+
+impl<T> Default for HistoryBuffer<T> {
+    fn default() -> Self {
+        Self::with_max_ticks(DEFAULT_HISTORY_TICKS)
+    }
+}
+
+impl<T> HistoryBuffer<T> {
+    pub(crate) fn with_max_ticks(max_ticks: u16) -> Self {
+        Self {
+            buffer: VecDeque::new(),
+            max_ticks,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.buffer.clear();
+    }
+
+    /// The oldest value in the buffer
+    pub(crate) fn front(&self) -> Option<&(Tick, T)> {
+        self.buffer.front()
+    }
+
+    /// The most recent value in the buffer
+    pub(crate) fn back(&self) -> Option<&(Tick, T)> {
+        self.buffer.back()
+    }
+
+    pub(crate) fn pop_front(&mut self) -> Option<(Tick, T)> {
+        self.buffer.pop_front()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &(Tick, T)> {
+        self.buffer.iter()
+    }
+
+    /// Add a value at the end of the buffer, replacing the values for the same or more recent ticks
+    /// (which can happen when ticks are simulated again during a rollback)
+    pub(crate) fn add(&mut self, tick: Tick, value: T) {
+        self.truncate_after(tick - 1);
+        self.buffer.push_back((tick, value));
+        self.evict();
+    }
+
+    /// Insert a value at its position in the buffer, replacing the value for the same tick.
+    ///
+    /// Unlike [`add`](Self::add), the more recent values are kept, so this can be used for values that
+    /// can be received out of order.
+    pub(crate) fn insert(&mut self, tick: Tick, value: T) {
+        let index = self.buffer.partition_point(|(t, _)| *t < tick);
+        match self.buffer.get_mut(index) {
+            Some((t, existing)) if *t == tick => *existing = value,
+            _ => self.buffer.insert(index, (tick, value)),
+        }
+        self.evict();
+    }
+
+    /// Remove the values that are strictly more recent than the specified tick
+    pub(crate) fn truncate_after(&mut self, tick: Tick) {
+        while self.buffer.back().is_some_and(|(t, _)| *t > tick) {
+            self.buffer.pop_back();
+        }
+    }
+
+    /// Clear the history of values strictly older than the specified tick,
+    /// and return the most recent value that is older or equal to the specified tick.
+    /// That value is kept in the buffer, so that we can still get the value for the future ticks.
+    pub(crate) fn pop_until_tick(&mut self, tick: Tick) -> Option<&(Tick, T)> {
+        while self.buffer.get(1).is_some_and(|(t, _)| *t <= tick) {
+            self.buffer.pop_front();
+        }
+        self.buffer.front().filter(|(t, _)| *t <= tick)
+    }
+
+    /// Evict the values that are older than `max_ticks` before the most recent tick,
+    /// keeping the value that is still valid at that horizon
+    fn evict(&mut self) {
+        let Some(&(newest, _)) = self.buffer.back() else {
+            return;
+        };
+        let horizon = newest - self.max_ticks;
+        while self.buffer.get(1).is_some_and(|(t, _)| *t <= horizon) {
+            self.buffer.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_replaces_more_recent_ticks() {
+        let mut history = HistoryBuffer::default();
+        history.add(Tick(1), 1);
+        history.add(Tick(2), 2);
+        history.add(Tick(3), 3);
+        // a rollback re-simulates tick 2
+        history.add(Tick(2), 4);
+        assert_eq!(
+            history.iter().collect::<Vec<_>>(),
+            [&(Tick(1), 1), &(Tick(2), 4)]
+        );
+    }
+
+    #[test]
+    fn test_insert_out_of_order() {
+        let mut history = HistoryBuffer::default();
+        history.insert(Tick(1), 1);
+        history.insert(Tick(4), 4);
+        history.insert(Tick(2), 2);
+        history.insert(Tick(4), 5);
+        assert_eq!(
+            history.iter().collect::<Vec<_>>(),
+            [&(Tick(1), 1), &(Tick(2), 2), &(Tick(4), 5)]
+        );
+    }
+
+    #[test]
+    fn test_pop_until_tick() {
+        let mut history = HistoryBuffer::default();
+        history.add(Tick(2), 2);
+        history.add(Tick(4), 4);
+        assert_eq!(history.pop_until_tick(Tick(1)), None);
+        assert_eq!(history.pop_until_tick(Tick(3)), Some(&(Tick(2), 2)));
+        assert_eq!(history.pop_until_tick(Tick(5)), Some(&(Tick(4), 4)));
+        assert_eq!(history.len(), 1);
+    }
+
+    #[test]
+    fn test_eviction() {
+        let mut history = HistoryBuffer::with_max_ticks(10);
+        for i in 0..30 {
+            history.add(Tick(i), i);
+        }
+        // only the values within 10 ticks of the most recent tick are kept
+        assert_eq!(history.len(), 11);
+        assert_eq!(history.front(), Some(&(Tick(19), 19)));
+        assert_eq!(history.back(), Some(&(Tick(29), 29)));
+
+        // the value that is still valid at the horizon is kept, even if it is older
+        let mut history = HistoryBuffer::with_max_ticks(10);
+        history.add(Tick(0), 0);
+        history.add(Tick(25), 25);
+        assert_eq!(history.len(), 2);
+        history.add(Tick(40), 40);
+        assert_eq!(
+            history.iter().collect::<Vec<_>>(),
+            [&(Tick(25), 25), &(Tick(40), 40)]
+        );
+        assert_eq!(history.pop_until_tick(Tick(32)), Some(&(Tick(25), 25)));
+    }
+
+    #[test]
+    fn test_tick_wraparound() {
+        let mut history = HistoryBuffer::with_max_ticks(10);
+        for i in 0..8 {
+            history.add(Tick((u16::MAX - 3).wrapping_add(i)), i);
+        }
+        // the ticks are still ordered after wrapping around
+        assert_eq!(history.front(), Some(&(Tick(u16::MAX - 3), 0)));
+        assert_eq!(history.back(), Some(&(Tick(3), 7)));
+        assert_eq!(history.pop_until_tick(Tick(1)), Some(&(Tick(1), 5)));
+        assert_eq!(history.len(), 3);
+
+        // a rollback before the wraparound removes the values after the wraparound
+        history.insert(Tick(0), 10);
+        history.add(Tick(u16::MAX), 11);
+        assert_eq!(history.iter().collect::<Vec<_>>(), [&(Tick(u16::MAX), 11)]);
+
+        // eviction also works across the wraparound
+        for i in 0..20 {
+            history.add(Tick(i), i);
+        }
+        assert_eq!(history.len(), 11);
+        assert_eq!(history.front(), Some(&(Tick(9), 9)));
+    }
+}
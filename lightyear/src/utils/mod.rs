@@ -2,6 +2,8 @@
 
 pub(crate) mod free_list;
 
+pub(crate) mod history_buffer;
+
 pub(crate) mod ready_buffer;
 
 pub(crate) mod sequence_buffer;
@@ -12,6 +14,11 @@ pub mod bevy;
 #[cfg(feature = "xpbd_2d")]
 pub mod bevy_xpbd_2d;
 
+#[cfg_attr(docsrs, doc(cfg(feature = "rapier_2d")))]
+#[cfg(feature = "rapier_2d")]
+pub mod bevy_rapier_2d;
+
 pub(crate) mod captures;
+pub mod fixed;
 pub(crate) mod pool;
 pub mod wrapping_id;
@@ -1,6 +1,4 @@
 //! Wrapper around a list where the index is a wrapping key
-use std::marker::PhantomData;
-
 use crate::utils::wrapping_id::WrappedId;
 
 /// Fixed size data structure with
@@ -10,51 +8,97 @@ use crate::utils::wrapping_id::WrappedId;
 ///
 /// The key must be a WrappedId, we update the buffer by using the key modulo the buffer size
 /// More optimized than HashMap
+///
+/// The key is stored alongside each value, so that a value that was overwritten by a more recent key
+/// is not returned for an older key.
 #[derive(Debug)]
-pub struct SequenceBuffer<K: WrappedId, T, const N: usize> {
-    buffer: [Option<T>; N],
-    _marker: PhantomData<K>,
+pub struct SequenceBuffer<K: WrappedId, T> {
+    buffer: Vec<Option<(K, T)>>,
+    len: usize,
 }
 
-impl<K: WrappedId, T, const N: usize> Default for SequenceBuffer<K, T, N> {
-    fn default() -> Self {
-        Self::new()
+impl<K: WrappedId + Copy + PartialEq, T> SequenceBuffer<K, T> {
+    /// Create a buffer that can hold `capacity` consecutive keys.
+    ///
+    /// The capacity is rounded up to a power of two, so that the keys keep mapping to
+    /// consecutive indices when they wrap around.
+    pub fn new(capacity: usize) -> Self {
+        let mut buffer = Vec::new();
+        buffer.resize_with(Self::round_capacity(capacity), || None);
+        Self { buffer, len: 0 }
     }
-}
 
-impl<K: WrappedId, T, const N: usize> SequenceBuffer<K, T, N> {
-    pub fn new() -> Self {
-        Self {
-            buffer: std::array::from_fn(|_| None),
-            _marker: PhantomData,
-        }
+    /// Number of consecutive keys that the buffer can hold
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
     }
 
+    /// Number of values in the buffer
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Insert a value, overwriting the value of any other key that maps to the same index
     pub fn push(&mut self, key: &K, value: T) {
         let index = self.index(key);
-        // TODO: risk that we keep around the previously buffered value
-        //  solution would be to clear values between the last insert and K (if K is more recent)
-        self.buffer[index] = Some(value);
+        if self.buffer[index].replace((*key, value)).is_none() {
+            self.len += 1;
+        }
     }
 
     pub fn get(&self, key: &K) -> Option<&T> {
         let index = self.index(key);
-        self.buffer[index].as_ref()
+        match &self.buffer[index] {
+            Some((k, value)) if k == key => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
     }
 
     pub fn remove(&mut self, key: &K) -> Option<T> {
         let index = self.index(key);
-        self.buffer[index].take()
+        if !matches!(&self.buffer[index], Some((k, _)) if k == key) {
+            return None;
+        }
+        self.len -= 1;
+        self.buffer[index].take().map(|(_, value)| value)
     }
 
     pub fn clear(&mut self) {
-        for i in 0..N {
-            self.buffer[i] = None;
+        self.buffer.iter_mut().for_each(|entry| *entry = None);
+        self.len = 0;
+    }
+
+    /// Grow the buffer so that it can hold at least `capacity` consecutive keys, keeping the existing values
+    pub fn grow(&mut self, capacity: usize) {
+        let capacity = Self::round_capacity(capacity);
+        if capacity <= self.capacity() {
+            return;
+        }
+        let mut buffer = Vec::new();
+        buffer.resize_with(capacity, || None);
+        for (key, value) in std::mem::replace(&mut self.buffer, buffer)
+            .into_iter()
+            .flatten()
+        {
+            let index = self.index(&key);
+            self.buffer[index] = Some((key, value));
         }
     }
 
+    fn round_capacity(capacity: usize) -> usize {
+        capacity.clamp(1, u16::MAX as usize + 1).next_power_of_two()
+    }
+
     fn index(&self, key: &K) -> usize {
-        key.rem(N)
+        key.rem(self.buffer.len())
     }
 }
 
@@ -66,17 +110,44 @@ mod tests {
 
     #[test]
     fn test_sequence_buffer() {
-        let mut buffer = SequenceBuffer::<MessageId, u8, 32>::new();
+        let mut buffer = SequenceBuffer::<MessageId, u8>::new(32);
 
         // check basic behaviour
         buffer.push(&MessageId(0), 0);
         assert_eq!(buffer.get(&MessageId(0)), Some(&0));
 
         assert_eq!(buffer.remove(&MessageId(0)), Some(0));
+        assert!(buffer.is_empty());
 
         // check loop around behaviour
         buffer.push(&MessageId(0), 0);
         buffer.push(&MessageId(32), 1);
-        assert_eq!(buffer.get(&MessageId(0)), Some(&1));
+        assert_eq!(buffer.get(&MessageId(0)), None);
+        assert_eq!(buffer.get(&MessageId(32)), Some(&1));
+        assert_eq!(buffer.remove(&MessageId(0)), None);
+        assert_eq!(buffer.len(), 1);
+    }
+
+    #[test]
+    fn test_sequence_buffer_wrapping() {
+        // the capacity is rounded up to a power of two
+        let mut buffer = SequenceBuffer::<MessageId, u16>::new(20);
+        assert_eq!(buffer.capacity(), 32);
+
+        // consecutive keys around the wrap-around point don't collide
+        for id in (u16::MAX - 15)..=u16::MAX {
+            buffer.push(&MessageId(id), id);
+        }
+        for id in 0..16 {
+            buffer.push(&MessageId(id), id);
+        }
+        assert_eq!(buffer.len(), 32);
+
+        // growing the buffer keeps the values
+        buffer.grow(40);
+        assert_eq!(buffer.capacity(), 64);
+        assert_eq!(buffer.len(), 32);
+        assert_eq!(buffer.get(&MessageId(u16::MAX)), Some(&u16::MAX));
+        assert_eq!(buffer.get(&MessageId(15)), Some(&15));
     }
 }